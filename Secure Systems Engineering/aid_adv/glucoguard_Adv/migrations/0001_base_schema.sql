@@ -0,0 +1,80 @@
+-- tables of the original GlucoGuard schema
+-- IF NOT EXISTS so databases created before migrations existed are adopted as-is
+CREATE TABLE IF NOT EXISTS users (
+    id TEXT NOT NULL PRIMARY KEY,
+    user_name TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL,
+    role TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    last_login TEXT
+);
+
+CREATE TABLE IF NOT EXISTS patients (
+    patient_id TEXT PRIMARY KEY UNIQUE,
+    first_name TEXT NOT NULL,
+    last_name TEXT NOT NULL,
+    date_of_birth TEXT NOT NULL,
+    basal_rate REAL NOT NULL,
+    bolus_rate REAL NOT NULL,
+    max_dosage REAL NOT NULL,
+    low_glucose_threshold REAL NOT NULL,
+    high_glucose_threshold REAL NOT NULL,
+    clinician_id TEXT NOT NULL,
+    caretaker_id TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS patient_care_team (
+    care_taker_id TEXT NOT NULL,
+    patient_id_list TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS glucose_readings (
+    reading_id INTEGER PRIMARY KEY UNIQUE,
+    patient_id INTEGER NOT NULL,
+    glucose_level REAL NOT NULL,
+    reading_time TEXT NOT NULL,
+    status TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS insulin_logs (
+    dosage_id INTEGER PRIMARY KEY UNIQUE,
+    patient_id INTEGER NOT NULL,
+    action_type TEXT NOT NULL,
+    dosage_units REAL NOT NULL,
+    requested_by TEXT NOT NULL,
+    dosage_time TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS alerts (
+    alert_id INTEGER PRIMARY KEY UNIQUE,
+    patient_id INTEGER NOT NULL,
+    alert_type TEXT NOT NULL,
+    alert_message TEXT NOT NULL,
+    alert_time TEXT NOT NULL,
+    is_resolved BOOLEAN NOT NULL,
+    resolved_by TEXT
+);
+
+CREATE TABLE IF NOT EXISTS meal_logs (
+    meal_id INTEGER PRIMARY KEY UNIQUE,
+    patient_id INTEGER NOT NULL,
+    carbohydrate_amount REAL NOT NULL,
+    meal_time TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS sessions (
+    session_id TEXT PRIMARY KEY UNIQUE,
+    user_id TEXT NOT NULL,
+    role TEXT NOT NULL,
+    creation_time BIGINT NOT NULL,
+    expiration_time INT,
+    active INTEGER DEFAULT 1
+);
+
+CREATE TABLE IF NOT EXISTS activation_codes (
+    code TEXT UNIQUE NOT NULL,
+    user_type TEXT NOT NULL,
+    user_id TEXT,
+    issuer_id TEXT NOT NULL,
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP
+);
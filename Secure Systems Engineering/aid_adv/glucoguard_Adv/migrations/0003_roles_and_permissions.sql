@@ -0,0 +1,18 @@
+-- parent_role NULL = built-in root role
+CREATE TABLE IF NOT EXISTS roles (
+    name TEXT PRIMARY KEY NOT NULL,
+    parent_role TEXT REFERENCES roles(name),
+    description TEXT,
+    created_by TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+-- granted = 0 records an admin revocation so defaults are not re-seeded
+CREATE TABLE IF NOT EXISTS role_permissions (
+    role TEXT NOT NULL,
+    permission TEXT NOT NULL,
+    granted INTEGER NOT NULL DEFAULT 1,
+    updated_by TEXT,
+    updated_at TEXT,
+    PRIMARY KEY (role, permission)
+);
@@ -0,0 +1,6 @@
+-- fingerprints of rows loaded by `glucoguard import`, used to skip duplicates
+CREATE TABLE IF NOT EXISTS imported_records (
+    fingerprint TEXT PRIMARY KEY NOT NULL,
+    source TEXT NOT NULL,
+    imported_at TEXT NOT NULL
+);
@@ -0,0 +1,14 @@
+-- token_hash = SHA-256 of the token, patient_ids = comma separated or NULL for the role's full scope
+CREATE TABLE IF NOT EXISTS api_tokens (
+    token_id TEXT PRIMARY KEY NOT NULL,
+    token_hash TEXT UNIQUE NOT NULL,
+    user_id TEXT NOT NULL,
+    role TEXT NOT NULL,
+    patient_ids TEXT,
+    label TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    expires_at TEXT,
+    revoked INTEGER NOT NULL DEFAULT 0,
+    last_used_at TEXT
+);
@@ -0,0 +1,21 @@
+-- status: pending -> delivered (pulled by the pump) -> confirmed (dose reported back)
+CREATE TABLE IF NOT EXISTS pump_commands (
+    command_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    patient_id TEXT NOT NULL,
+    command_type TEXT NOT NULL,
+    dosage_units REAL NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    created_by TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    delivered_at TEXT,
+    confirmed_at TEXT
+);
+
+-- latest reported state per pump
+CREATE TABLE IF NOT EXISTS pump_status (
+    device_id TEXT PRIMARY KEY NOT NULL,
+    patient_id TEXT NOT NULL,
+    reservoir_units REAL NOT NULL,
+    battery_percent INTEGER NOT NULL,
+    reported_at TEXT NOT NULL
+);
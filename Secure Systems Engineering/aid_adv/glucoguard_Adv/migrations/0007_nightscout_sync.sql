@@ -0,0 +1,8 @@
+-- last glucose_readings / insulin_logs id uploaded to Nightscout per patient
+CREATE TABLE IF NOT EXISTS nightscout_sync (
+    patient_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    last_id INTEGER NOT NULL,
+    synced_at TEXT NOT NULL,
+    PRIMARY KEY (patient_id, kind)
+);
@@ -0,0 +1,11 @@
+-- one signed row per patient erasure; subject_hash = SHA-256 of the erased patient id, never the id itself
+-- row_counts = JSON object of rows removed or anonymised per table, signature = HMAC-SHA256 under audit.signing_key
+CREATE TABLE IF NOT EXISTS erasure_log (
+    erasure_id TEXT PRIMARY KEY NOT NULL,
+    subject_hash TEXT NOT NULL,
+    mode TEXT NOT NULL,
+    performed_by TEXT NOT NULL,
+    performed_at TEXT NOT NULL,
+    row_counts TEXT NOT NULL,
+    signature TEXT NOT NULL
+);
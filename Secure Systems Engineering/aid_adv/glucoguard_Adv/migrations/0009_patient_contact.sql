@@ -0,0 +1,6 @@
+-- contact details patients maintain themselves; NULL = not provided
+ALTER TABLE patients ADD COLUMN phone TEXT;
+ALTER TABLE patients ADD COLUMN email TEXT;
+ALTER TABLE patients ADD COLUMN address TEXT;
+ALTER TABLE patients ADD COLUMN emergency_contact_name TEXT;
+ALTER TABLE patients ADD COLUMN emergency_contact_phone TEXT;
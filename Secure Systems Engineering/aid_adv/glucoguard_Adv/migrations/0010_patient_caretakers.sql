@@ -0,0 +1,11 @@
+-- which caretakers may see which patients; a patient can have any number of caretakers
+-- caretaker_id may name an account that does not exist yet while its activation code is unredeemed
+CREATE TABLE IF NOT EXISTS patient_caretakers (
+    patient_id TEXT NOT NULL,
+    caretaker_id TEXT NOT NULL,
+    added_by TEXT,
+    added_at TEXT NOT NULL,
+    PRIMARY KEY (patient_id, caretaker_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_patient_caretakers_caretaker ON patient_caretakers (caretaker_id);
@@ -0,0 +1,2 @@
+-- when the caretaker last read this patient's data; NULL = never
+ALTER TABLE patient_caretakers ADD COLUMN last_viewed_at TEXT;
@@ -0,0 +1,6 @@
+-- activation codes expire, and redeemed codes are marked used instead of deleted
+ALTER TABLE activation_codes ADD COLUMN expires_at TEXT;
+ALTER TABLE activation_codes ADD COLUMN used_at TEXT;
+
+-- codes issued before expiry existed get the default 72 hour lifetime
+UPDATE activation_codes SET expires_at = COALESCE(datetime(created_at, '+72 hours'), datetime('now', '+72 hours'));
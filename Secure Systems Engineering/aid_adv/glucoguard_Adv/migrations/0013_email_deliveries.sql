@@ -0,0 +1,15 @@
+-- audit trail of activation code emails; the code itself is never stored here
+CREATE TABLE IF NOT EXISTS email_deliveries (
+    delivery_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- account the activation code creates
+    user_id TEXT NOT NULL,
+    user_type TEXT NOT NULL,
+    recipient TEXT NOT NULL,
+    sent_by TEXT NOT NULL,
+    sent_at TEXT NOT NULL,
+    -- 'sent' or 'failed'
+    status TEXT NOT NULL,
+    error TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_email_deliveries_user ON email_deliveries (user_id);
@@ -0,0 +1,5 @@
+-- suspended accounts keep their row, so sessions, readings and audit history still resolve
+ALTER TABLE users ADD COLUMN is_active INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE users ADD COLUMN disabled_at TEXT;
+-- user id of the admin who suspended the account
+ALTER TABLE users ADD COLUMN disabled_by TEXT;
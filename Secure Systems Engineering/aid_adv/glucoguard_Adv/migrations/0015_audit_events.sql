@@ -0,0 +1,15 @@
+-- security events copied from the tracing "security" target, browsed from the admin menu
+CREATE TABLE IF NOT EXISTS audit_events (
+    event_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- UTC, 'YYYY-MM-DD HH:MM:SS'
+    occurred_at TEXT NOT NULL,
+    level TEXT NOT NULL,
+    action TEXT NOT NULL,
+    user_id TEXT,
+    username TEXT,
+    -- other event fields as a JSON object
+    details TEXT NOT NULL DEFAULT '{}'
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_events_time ON audit_events (occurred_at);
+CREATE INDEX IF NOT EXISTS idx_audit_events_user ON audit_events (user_id);
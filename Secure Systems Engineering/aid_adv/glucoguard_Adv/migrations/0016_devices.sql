@@ -0,0 +1,24 @@
+-- pumps and CGMs registered to a patient; status: pending -> paired -> revoked
+CREATE TABLE IF NOT EXISTS devices (
+    -- serial printed on the device, also used in telemetry requests
+    device_id TEXT PRIMARY KEY NOT NULL,
+    patient_id TEXT NOT NULL,
+    -- 'pump' or 'cgm'
+    device_type TEXT NOT NULL,
+    label TEXT NOT NULL DEFAULT '',
+    status TEXT NOT NULL DEFAULT 'pending',
+    -- SHA-256 of the one-time pairing code, cleared once the device is paired
+    pairing_code_hash TEXT,
+    pairing_expires_at TEXT,
+    -- hex Ed25519 public key the device registered while pairing
+    public_key TEXT,
+    registered_by TEXT NOT NULL,
+    registered_at TEXT NOT NULL,
+    paired_at TEXT,
+    revoked_at TEXT,
+    last_seen_at TEXT,
+    -- signed_at_ms of the last accepted request; each request must be newer
+    last_signed_at_ms INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_devices_patient ON devices (patient_id);
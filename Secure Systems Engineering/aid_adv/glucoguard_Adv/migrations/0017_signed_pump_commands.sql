@@ -0,0 +1,12 @@
+-- commands are addressed to one paired pump and signed by the server when they are queued
+-- status: pending -> delivered -> confirmed, or cancelled when the pump is revoked first
+ALTER TABLE pump_commands ADD COLUMN device_id TEXT;
+-- Unix time in milliseconds when the command was signed
+ALTER TABLE pump_commands ADD COLUMN issued_at_ms INTEGER;
+-- hex Ed25519 signature with devices.command_signing_key
+ALTER TABLE pump_commands ADD COLUMN signature TEXT;
+
+-- unsigned commands queued before this migration cannot be verified by a pump
+UPDATE pump_commands SET status = 'cancelled' WHERE status = 'pending';
+
+CREATE INDEX IF NOT EXISTS idx_pump_commands_device ON pump_commands (device_id, status);
@@ -0,0 +1,14 @@
+-- emergency stops of all insulin delivery; a row is active until a clinician resumes delivery
+CREATE TABLE IF NOT EXISTS insulin_suspensions (
+    suspension_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    patient_id TEXT NOT NULL,
+    reason TEXT NOT NULL DEFAULT '',
+    suspended_by TEXT NOT NULL,
+    suspended_at TEXT NOT NULL,
+    resumed_by TEXT,
+    resumed_at TEXT,
+    resume_note TEXT
+);
+
+-- at most one active suspension per patient
+CREATE UNIQUE INDEX IF NOT EXISTS idx_insulin_suspensions_active ON insulin_suspensions (patient_id) WHERE resumed_at IS NULL;
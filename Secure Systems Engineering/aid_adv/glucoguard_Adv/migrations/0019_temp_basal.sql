@@ -0,0 +1,22 @@
+-- temporary basal rates: percent of the patient's basal_rate for a bounded time, after which the pump reverts
+CREATE TABLE IF NOT EXISTS temp_basal_rates (
+    temp_basal_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    patient_id TEXT NOT NULL,
+    percent INTEGER NOT NULL,
+    -- basal_rate when the temporary rate was set, and the resulting rate, in units/hour
+    base_rate REAL NOT NULL,
+    rate REAL NOT NULL,
+    duration_minutes INTEGER NOT NULL,
+    starts_at TEXT NOT NULL,
+    ends_at TEXT NOT NULL,
+    set_by TEXT NOT NULL,
+    -- set when the rate stops: 'expired', 'cancelled', 'replaced' or 'emergency_stop'
+    ended_at TEXT,
+    ended_by TEXT,
+    end_reason TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_temp_basal_patient ON temp_basal_rates (patient_id, ended_at);
+
+-- how long a "temp_basal" pump command applies, 0 for other commands
+ALTER TABLE pump_commands ADD COLUMN duration_minutes INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,6 @@
+-- extended / dual-wave boluses: the immediate portion and the scheduled steps of the extended portion are
+-- separate insulin log entries, tied together (and to their pump commands) by the request id
+ALTER TABLE insulin_logs ADD COLUMN request_id TEXT;
+ALTER TABLE pump_commands ADD COLUMN request_id TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_insulin_logs_request ON insulin_logs (request_id);
@@ -0,0 +1,29 @@
+-- critical alerts nobody acknowledges are escalated, first to the patient's caretakers, then to the clinician
+ALTER TABLE alerts ADD COLUMN acknowledged_at TEXT;
+ALTER TABLE alerts ADD COLUMN acknowledged_by TEXT;
+-- 0 = not escalated, 1 = caretakers notified, 2 = clinician notified
+ALTER TABLE alerts ADD COLUMN escalation_level INTEGER NOT NULL DEFAULT 0;
+
+-- what happened to an alert after it was raised: 'escalated' (user_id = who was notified) or 'acknowledged'
+CREATE TABLE IF NOT EXISTS alert_history (
+    history_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    alert_id INTEGER NOT NULL,
+    event TEXT NOT NULL,
+    user_id TEXT,
+    detail TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_alert_history_alert ON alert_history (alert_id);
+
+-- escalation notices shown in a user's menu until they acknowledge the alert
+CREATE TABLE IF NOT EXISTS alert_notifications (
+    notification_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    alert_id INTEGER NOT NULL,
+    user_id TEXT NOT NULL,
+    message TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    read_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_alert_notifications_user ON alert_notifications (user_id, read_at);
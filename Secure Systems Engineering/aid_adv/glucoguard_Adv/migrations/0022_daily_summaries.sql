@@ -0,0 +1,20 @@
+-- work address clinicians receive daily patient summaries at; NULL = summaries are only stored
+ALTER TABLE users ADD COLUMN email TEXT;
+
+-- summary of one patient's day, generated once by the session cleanup task
+CREATE TABLE IF NOT EXISTS daily_summaries (
+    summary_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    patient_id TEXT NOT NULL,
+    -- clinician it was addressed to
+    clinician_id TEXT NOT NULL,
+    -- UTC day covered, YYYY-MM-DD
+    summary_date TEXT NOT NULL,
+    body TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    -- 'stored' (not emailed), 'sent' or 'failed'
+    delivery TEXT NOT NULL,
+    delivery_error TEXT,
+    UNIQUE (patient_id, summary_date)
+);
+
+CREATE INDEX IF NOT EXISTS idx_daily_summaries_clinician ON daily_summaries (clinician_id, summary_date);
@@ -0,0 +1,16 @@
+-- patients handed from one clinician to another; each row is also the notice shown to both
+-- clinicians in their menu until they have seen it
+CREATE TABLE IF NOT EXISTS patient_transfers (
+    transfer_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    patient_id TEXT NOT NULL,
+    from_clinician_id TEXT NOT NULL,
+    to_clinician_id TEXT NOT NULL,
+    -- user id of the clinician or admin who made the transfer
+    transferred_by TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    transferred_at TEXT NOT NULL,
+    from_seen_at TEXT,
+    to_seen_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_patient_transfers_patient ON patient_transfers (patient_id);
@@ -0,0 +1,2 @@
+-- unit the user reads and enters glucose values in; stored values are always mg/dL
+ALTER TABLE users ADD COLUMN glucose_unit TEXT NOT NULL DEFAULT 'mg/dL';
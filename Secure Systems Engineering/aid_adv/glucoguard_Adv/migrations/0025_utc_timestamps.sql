@@ -0,0 +1,15 @@
+-- IANA time zone the user reads times in; NULL = ui.timezone
+ALTER TABLE users ADD COLUMN timezone TEXT;
+
+-- these columns were written as RFC 3339 text; store them in datetime('now') format (UTC) like every other
+-- timestamp. erasure_log.performed_at is signed and is left as written.
+UPDATE users SET created_at = strftime('%Y-%m-%d %H:%M:%S', created_at)
+    WHERE created_at LIKE '%T%' AND strftime('%Y-%m-%d %H:%M:%S', created_at) IS NOT NULL;
+UPDATE users SET last_login = strftime('%Y-%m-%d %H:%M:%S', last_login)
+    WHERE last_login LIKE '%T%' AND strftime('%Y-%m-%d %H:%M:%S', last_login) IS NOT NULL;
+UPDATE activation_codes SET created_at = strftime('%Y-%m-%d %H:%M:%S', created_at)
+    WHERE created_at LIKE '%T%' AND strftime('%Y-%m-%d %H:%M:%S', created_at) IS NOT NULL;
+UPDATE roles SET created_at = strftime('%Y-%m-%d %H:%M:%S', created_at)
+    WHERE created_at LIKE '%T%' AND strftime('%Y-%m-%d %H:%M:%S', created_at) IS NOT NULL;
+UPDATE role_permissions SET updated_at = strftime('%Y-%m-%d %H:%M:%S', updated_at)
+    WHERE updated_at LIKE '%T%' AND strftime('%Y-%m-%d %H:%M:%S', updated_at) IS NOT NULL;
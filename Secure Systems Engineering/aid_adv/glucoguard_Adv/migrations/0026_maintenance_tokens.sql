@@ -0,0 +1,13 @@
+-- signed maintenance tokens; the token itself is never stored, the row records who issued it and whether it was revoked
+CREATE TABLE IF NOT EXISTS maintenance_tokens (
+    token_id TEXT PRIMARY KEY,
+    issued_by TEXT NOT NULL,
+    -- granted permission names joined with '+', as signed in the token
+    permissions TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    issued_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    revoked_at TEXT,
+    revoked_by TEXT,
+    last_used_at TEXT
+);
@@ -0,0 +1,25 @@
+-- alerts raised for administrators by the security monitor from suspicious patterns in audit_events
+CREATE TABLE IF NOT EXISTS security_alerts (
+    alert_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- 'failed_logins', 'off_hours_access' or 'mass_export'
+    rule TEXT NOT NULL,
+    -- username or user id the alert is about
+    subject TEXT NOT NULL,
+    message TEXT NOT NULL,
+    -- the audit_events rows that triggered it
+    first_event_id INTEGER NOT NULL,
+    last_event_id INTEGER NOT NULL,
+    raised_at TEXT NOT NULL,
+    acknowledged_at TEXT,
+    acknowledged_by TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_security_alerts_rule ON security_alerts (rule, subject);
+
+-- the last audit event the monitor has looked at; monitoring starts with the events logged after this migration
+CREATE TABLE IF NOT EXISTS security_monitor_state (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    last_event_id INTEGER NOT NULL
+);
+
+INSERT OR IGNORE INTO security_monitor_state (id, last_event_id) SELECT 1, COALESCE(MAX(event_id), 0) FROM audit_events;
@@ -0,0 +1,7 @@
+-- last run of each background task, so `glucoguard healthcheck` can tell whether they are alive
+CREATE TABLE IF NOT EXISTS background_jobs (
+    job TEXT PRIMARY KEY,
+    last_run_at TEXT NOT NULL,
+    -- steps that failed in that run, comma-separated, NULL when all succeeded
+    last_error TEXT
+);
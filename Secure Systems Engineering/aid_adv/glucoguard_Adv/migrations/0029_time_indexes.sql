@@ -0,0 +1,3 @@
+-- readings and doses are almost always read for one patient in time order (history, charts, exports, summaries)
+CREATE INDEX IF NOT EXISTS idx_glucose_readings_patient_time ON glucose_readings (patient_id, reading_time);
+CREATE INDEX IF NOT EXISTS idx_insulin_logs_patient_time ON insulin_logs (patient_id, dosage_time);
@@ -0,0 +1,35 @@
+-- insulin_logs becomes append-only: a dose that will not be delivered after all gets a 'void' entry naming it
+-- (voids_dosage_id) instead of being deleted, and every new row is signed over its fields and the signature of
+-- the patient's previous row. Rows written before this migration stay unsigned.
+ALTER TABLE insulin_logs ADD COLUMN voids_dosage_id INTEGER;
+ALTER TABLE insulin_logs ADD COLUMN prev_signature TEXT;
+ALTER TABLE insulin_logs ADD COLUMN signature TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_insulin_logs_voids ON insulin_logs (voids_dosage_id);
+-- newest row of a patient's chain: within one patient_id this index is ordered by dosage_id
+CREATE INDEX IF NOT EXISTS idx_insulin_logs_patient ON insulin_logs (patient_id);
+-- two rows can never follow the same predecessor, so concurrent writers cannot fork a chain
+CREATE UNIQUE INDEX IF NOT EXISTS idx_insulin_logs_chain ON insulin_logs (patient_id, prev_signature);
+
+-- doses as delivered or scheduled: void entries and the doses they void are left out
+CREATE VIEW IF NOT EXISTS insulin_doses AS
+    SELECT * FROM insulin_logs AS dose
+    WHERE dose.action_type <> 'void'
+      AND NOT EXISTS (SELECT 1 FROM insulin_logs AS void WHERE void.voids_dosage_id = dose.dosage_id);
+
+-- patients whose rows patient erasure may change, only ever filled inside the erasure transaction
+CREATE TABLE IF NOT EXISTS insulin_log_unlocks (
+    patient_id TEXT PRIMARY KEY
+);
+
+CREATE TRIGGER IF NOT EXISTS insulin_logs_no_update BEFORE UPDATE ON insulin_logs
+WHEN NOT EXISTS (SELECT 1 FROM insulin_log_unlocks WHERE patient_id = OLD.patient_id)
+BEGIN
+    SELECT RAISE(ABORT, 'insulin_logs is append-only');
+END;
+
+CREATE TRIGGER IF NOT EXISTS insulin_logs_no_delete BEFORE DELETE ON insulin_logs
+WHEN NOT EXISTS (SELECT 1 FROM insulin_log_unlocks WHERE patient_id = OLD.patient_id)
+BEGIN
+    SELECT RAISE(ABORT, 'insulin_logs is append-only');
+END;
@@ -0,0 +1,10 @@
+-- SHA-256 of each legacy system_audit_* dump when it was first decoded; later decodes must match it
+CREATE TABLE IF NOT EXISTS audit_dump_digests (
+    file_name TEXT PRIMARY KEY,
+    sha256 TEXT NOT NULL,
+    first_decoded_at TEXT NOT NULL,
+    first_decoded_by TEXT NOT NULL,
+    last_decoded_at TEXT NOT NULL,
+    last_decoded_by TEXT NOT NULL,
+    decode_count INTEGER NOT NULL DEFAULT 1
+);
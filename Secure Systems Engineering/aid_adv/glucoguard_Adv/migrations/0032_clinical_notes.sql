@@ -0,0 +1,25 @@
+-- notes the care team attaches to a patient record; an edit keeps the text it replaces in clinical_note_revisions
+CREATE TABLE IF NOT EXISTS clinical_notes (
+    note_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    patient_id TEXT NOT NULL,
+    author_id TEXT NOT NULL,
+    body TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    -- 1 until the first edit
+    revision INTEGER NOT NULL DEFAULT 1,
+    updated_at TEXT,
+    updated_by TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_clinical_notes_patient ON clinical_notes (patient_id, created_at);
+
+-- every earlier text of a note: revision N was written at written_at by written_by and replaced by revision N + 1
+CREATE TABLE IF NOT EXISTS clinical_note_revisions (
+    note_id INTEGER NOT NULL,
+    revision INTEGER NOT NULL,
+    patient_id TEXT NOT NULL,
+    body TEXT NOT NULL,
+    written_at TEXT NOT NULL,
+    written_by TEXT NOT NULL,
+    PRIMARY KEY (note_id, revision)
+);
@@ -0,0 +1,20 @@
+-- what each patient has agreed to share: one row per decision, the newest row of a (patient, scope) pair is in force
+CREATE TABLE IF NOT EXISTS patient_consents (
+    consent_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    patient_id TEXT NOT NULL,
+    -- 'caretakers': linked caretakers see the record; 'research': de-identified research datasets include it
+    scope TEXT NOT NULL CHECK (scope IN ('caretakers', 'research')),
+    granted INTEGER NOT NULL CHECK (granted IN (0, 1)),
+    recorded_at TEXT NOT NULL,
+    recorded_by TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_patient_consents_patient ON patient_consents (patient_id, scope, consent_id);
+
+CREATE VIEW IF NOT EXISTS current_consents AS
+    SELECT c.* FROM patient_consents AS c
+    WHERE c.consent_id = (SELECT MAX(consent_id) FROM patient_consents WHERE patient_id = c.patient_id AND scope = c.scope);
+
+-- caretakers linked before consent was tracked keep their access until the patient withdraws it
+INSERT INTO patient_consents (patient_id, scope, granted, recorded_at, recorded_by)
+    SELECT DISTINCT patient_id, 'caretakers', 1, datetime('now'), 'migration' FROM patient_caretakers;
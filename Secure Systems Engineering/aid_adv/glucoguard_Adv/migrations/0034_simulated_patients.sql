@@ -0,0 +1,3 @@
+-- training patients for the pump simulator: their data stays out of daily summaries, research datasets,
+-- metrics and alert escalation; set when the patient is created and never changed
+ALTER TABLE patients ADD COLUMN is_simulated INTEGER NOT NULL DEFAULT 0;
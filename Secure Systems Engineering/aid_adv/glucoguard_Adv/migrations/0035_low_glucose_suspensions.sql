@@ -0,0 +1,19 @@
+-- basal delivery suspended by the system while a low was predicted; a row is active until it ends
+CREATE TABLE IF NOT EXISTS low_glucose_suspensions (
+    low_suspension_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    patient_id TEXT NOT NULL,
+    -- the prediction that started it, mg/dL
+    predicted_level REAL NOT NULL,
+    minutes_ahead REAL NOT NULL,
+    suspended_at TEXT NOT NULL,
+    ended_at TEXT,
+    -- 'system', or the user whose emergency stop took over
+    ended_by TEXT,
+    -- 'recovered', 'max_duration' or 'emergency_stop'
+    end_reason TEXT,
+    -- latest reading when it ended, mg/dL
+    end_level REAL
+);
+
+-- at most one active automatic suspension per patient
+CREATE UNIQUE INDEX IF NOT EXISTS idx_low_glucose_suspensions_active ON low_glucose_suspensions (patient_id) WHERE ended_at IS NULL;
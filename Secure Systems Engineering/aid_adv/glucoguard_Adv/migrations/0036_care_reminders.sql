@@ -0,0 +1,31 @@
+-- care reminders on a per-patient schedule; a reminder is due at next_due_at and, when it repeats,
+-- is due again interval_hours after each completion
+CREATE TABLE IF NOT EXISTS care_reminders (
+    reminder_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    patient_id TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('glucose_check', 'site_change', 'appointment')),
+    note TEXT NOT NULL DEFAULT '',
+    -- NULL for a one-off reminder such as a clinic appointment
+    interval_hours INTEGER,
+    next_due_at TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    -- set when a one-off reminder is completed or any reminder is cancelled
+    ended_at TEXT,
+    ended_by TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_care_reminders_due ON care_reminders (patient_id, next_due_at) WHERE ended_at IS NULL;
+
+-- every time a reminder was marked done
+CREATE TABLE IF NOT EXISTS reminder_completions (
+    completion_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    reminder_id INTEGER NOT NULL,
+    patient_id TEXT NOT NULL,
+    -- when it was due, to tell late completions apart
+    due_at TEXT NOT NULL,
+    completed_at TEXT NOT NULL,
+    completed_by TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_reminder_completions_patient ON reminder_completions (patient_id, completed_at);
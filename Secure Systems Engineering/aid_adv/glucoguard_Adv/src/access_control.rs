@@ -1,5 +1,8 @@
 //access management using RBAC model 
 use std::collections::HashSet;
+use rusqlite::Connection;
+use crate::db::queries;
+use crate::db::models::Patient;
 
 // lists os all permissions 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -14,9 +17,92 @@ pub enum Permission {
     ViewGlucose,
     AddGlucose,
     ViewAlerts,
+    ManageSessions,
+    ManagePermissions,
+    ManageApiTokens,
+    ManageBackups,
+    ErasePatientData,
+    ManageUsers,
+    ViewAuditLog,
+    DecodeAuditDumps,
+    ManageDevices,
+    EmergencyStop,
+    ResumeInsulin,
+    SetTempBasal,
+    TransferPatient,
+    ViewClinicalNotes,
+    WriteClinicalNotes,
+    ViewReminders,
+    ManageReminders,
 }
 
 impl Permission{
+    // every permission, in menu display order
+    pub const ALL: [Permission; 26] = [
+        Permission::ViewPatient,
+        Permission::CreateClinicianAccount,
+        Permission::RemoveClinicianAccount,
+        Permission::CreatePatientAccount,
+        Permission::CreateCaretakerLink,
+        Permission::EditPatientData,
+        Permission::ViewGlucose,
+        Permission::AddGlucose,
+        Permission::ViewAlerts,
+        Permission::ManageSessions,
+        Permission::ManagePermissions,
+        Permission::ManageApiTokens,
+        Permission::ManageBackups,
+        Permission::ErasePatientData,
+        Permission::ManageUsers,
+        Permission::ViewAuditLog,
+        Permission::DecodeAuditDumps,
+        Permission::ManageDevices,
+        Permission::EmergencyStop,
+        Permission::ResumeInsulin,
+        Permission::SetTempBasal,
+        Permission::TransferPatient,
+        Permission::ViewClinicalNotes,
+        Permission::WriteClinicalNotes,
+        Permission::ViewReminders,
+        Permission::ManageReminders,
+    ];
+
+    // name stored in the role_permissions table
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::ViewPatient => "ViewPatient",
+            Permission::CreateClinicianAccount => "CreateClinicianAccount",
+            Permission::RemoveClinicianAccount => "RemoveClinicianAccount",
+            Permission::CreatePatientAccount => "CreatePatientAccount",
+            Permission::CreateCaretakerLink => "CreateCaretakerLink",
+            Permission::EditPatientData => "EditPatientData",
+            Permission::ViewGlucose => "ViewGlucose",
+            Permission::AddGlucose => "AddGlucose",
+            Permission::ViewAlerts => "ViewAlerts",
+            Permission::ManageSessions => "ManageSessions",
+            Permission::ManagePermissions => "ManagePermissions",
+            Permission::ManageApiTokens => "ManageApiTokens",
+            Permission::ManageBackups => "ManageBackups",
+            Permission::ErasePatientData => "ErasePatientData",
+            Permission::ManageUsers => "ManageUsers",
+            Permission::ViewAuditLog => "ViewAuditLog",
+            Permission::DecodeAuditDumps => "DecodeAuditDumps",
+            Permission::ManageDevices => "ManageDevices",
+            Permission::EmergencyStop => "EmergencyStop",
+            Permission::ResumeInsulin => "ResumeInsulin",
+            Permission::SetTempBasal => "SetTempBasal",
+            Permission::TransferPatient => "TransferPatient",
+            Permission::ViewClinicalNotes => "ViewClinicalNotes",
+            Permission::WriteClinicalNotes => "WriteClinicalNotes",
+            Permission::ViewReminders => "ViewReminders",
+            Permission::ManageReminders => "ManageReminders",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Permission> {
+        Self::ALL.iter().find(|perm| perm.as_str() == name).cloned()
+    }
+
     pub fn perm_description(&self) -> &str {
         //check its value and prints out description of permi
         match self{
@@ -29,6 +115,23 @@ impl Permission{
             Permission::ViewGlucose => "View glucose readings",
             Permission::AddGlucose => "Request glucose injection",
             Permission::ViewAlerts => "View alerts",
+            Permission::ManageSessions => "Revoke user sessions",
+            Permission::ManagePermissions => "Grant or revoke role permissions",
+            Permission::ManageApiTokens => "Issue and revoke API tokens",
+            Permission::ManageBackups => "Back up and restore the database",
+            Permission::ErasePatientData => "Erase or anonymise all data of a patient",
+            Permission::ManageUsers => "List accounts and reset passwords",
+            Permission::ViewAuditLog => "Browse and export the audit log",
+            Permission::DecodeAuditDumps => "Decode the system_audit_* dumps of older versions",
+            Permission::ManageDevices => "Register, pair and revoke patient pumps and CGMs",
+            Permission::EmergencyStop => "Suspend all insulin delivery for a patient in an emergency",
+            Permission::ResumeInsulin => "Confirm that suspended insulin delivery may resume",
+            Permission::SetTempBasal => "Set or cancel a temporary basal rate",
+            Permission::TransferPatient => "Transfer a patient to another clinician",
+            Permission::ViewClinicalNotes => "Read the clinical notes on a patient record",
+            Permission::WriteClinicalNotes => "Add clinical notes and edit your own",
+            Permission::ViewReminders => "See care reminders and mark them done",
+            Permission::ManageReminders => "Schedule and cancel care reminders",
         }
     }
 }
@@ -37,28 +140,75 @@ impl Permission{
 pub struct Role{
     pub name: String,
     pub id: String,
+    // built-in role at the top of the inheritance chain, decides which menu is shown
+    pub base: String,
+    // own grants plus everything inherited from parent roles
     pub permissions: HashSet<Permission>,
 }
 
+// upper bound on inheritance depth, guards against cycles in hand-edited data
+const MAX_ROLE_DEPTH: usize = 16;
+
 // impl methods for Role struct and permission checking
 impl Role{
-    pub fn new(name: &str, id:&str) -> Self {
-        // get default permissions using role
-        let permissions = Self::default_permissions(name);
+    pub fn new(conn: &Connection, name: &str, id:&str) -> Self {
+        // walk the role and its ancestors, merging granted permissions
+        let chain = Self::resolve_chain(conn, name);
+        let mut permissions = HashSet::new();
+        for role_name in &chain {
+            match queries::get_role_permissions(conn, role_name) {
+                Ok(names) => permissions.extend(names.iter().filter_map(|n| Permission::from_name(n))),
+                Err(e) => tracing::error!("Failed to load permissions for role '{}': {}", role_name, e),
+            }
+        }
+        if permissions.is_empty() {
+            tracing::warn!(target: crate::logger::SECURITY_TARGET, role = %name, "Role has no permissions assigned");
+        }
         // create new role with given name and permissions
         Self {
             name: name.to_string(),
             id:id.to_string(),
+            base: chain.last().cloned().unwrap_or_else(|| name.to_string()),
             permissions,
         }
     }
 
+    // the same role with its permissions read again, so grants and revocations made since login apply
+    pub fn reload(&self, conn: &Connection) -> Self {
+        Self::new(conn, &self.name, &self.id)
+    }
+
+    // role name followed by its ancestors, e.g. ["nurse", "clinician"]
+    pub fn resolve_chain(conn: &Connection, name: &str) -> Vec<String> {
+        let mut chain = vec![name.to_string()];
+        let mut current = name.to_string();
+        while chain.len() < MAX_ROLE_DEPTH {
+            match queries::get_role_parent(conn, &current) {
+                Ok(Some(parent)) if !chain.contains(&parent) => {
+                    chain.push(parent.clone());
+                    current = parent;
+                }
+                Ok(Some(parent)) => {
+                    tracing::error!("Role inheritance cycle detected at '{}'", parent);
+                    break;
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    tracing::error!("Failed to resolve parent of role '{}': {}", current, e);
+                    break;
+                }
+            }
+        }
+        chain
+    }
+
     // method to check if role has specific permission
     pub fn has_permission(&self, permission: &Permission) -> bool {
         self.permissions.contains(permission)
     }
 
-    fn default_permissions(role_name: &str) -> HashSet<Permission> {
+    // built-in role→permission mapping, seeded into role_permissions at startup
+    pub fn default_permissions(role_name: &str) -> HashSet<Permission> {
         let mut perms = HashSet::new();
         
         // Case-sensitive role matching for security (prevents role spoofing)
@@ -67,19 +217,42 @@ impl Role{
             "admin" => {
                 perms.insert(Permission::CreateClinicianAccount);
                 perms.insert(Permission::RemoveClinicianAccount);
+                perms.insert(Permission::ManageSessions);
+                perms.insert(Permission::ManagePermissions);
+                perms.insert(Permission::ManageApiTokens);
+                perms.insert(Permission::ManageBackups);
+                perms.insert(Permission::ErasePatientData);
+                perms.insert(Permission::ManageUsers);
+                perms.insert(Permission::ViewAuditLog);
+                perms.insert(Permission::DecodeAuditDumps);
+                perms.insert(Permission::TransferPatient);
             }
             "clinician" => {
                 perms.insert(Permission::CreatePatientAccount);
+                perms.insert(Permission::CreateCaretakerLink);
+                perms.insert(Permission::ManageDevices);
+                perms.insert(Permission::EmergencyStop);
+                perms.insert(Permission::ResumeInsulin);
+                perms.insert(Permission::SetTempBasal);
+                perms.insert(Permission::TransferPatient);
                 perms.insert(Permission::EditPatientData);
                 perms.insert(Permission::ViewGlucose);
                 perms.insert(Permission::ViewAlerts);
                 perms.insert(Permission::ViewPatient);
+                perms.insert(Permission::ViewClinicalNotes);
+                perms.insert(Permission::WriteClinicalNotes);
+                perms.insert(Permission::ViewReminders);
+                perms.insert(Permission::ManageReminders);
             }
             "patient" => {
                 perms.insert(Permission::ViewPatient);
                 perms.insert(Permission::ViewGlucose);
                 perms.insert(Permission::AddGlucose);
                 perms.insert(Permission::CreateCaretakerLink);
+                perms.insert(Permission::EmergencyStop);
+                perms.insert(Permission::SetTempBasal);
+                perms.insert(Permission::ViewReminders);
+                perms.insert(Permission::ManageReminders);
             }
             "caretaker" => {
                 // Standard caretaker permissions
@@ -87,6 +260,9 @@ impl Role{
                 perms.insert(Permission::ViewGlucose);
                 perms.insert(Permission::AddGlucose);
                 perms.insert(Permission::ViewAlerts);
+                perms.insert(Permission::EmergencyStop);
+                perms.insert(Permission::ViewClinicalNotes);
+                perms.insert(Permission::ViewReminders);
             }
             "Auditor" => {
 
@@ -95,11 +271,166 @@ impl Role{
                 perms.insert(Permission::ViewAlerts);
                 perms.insert(Permission::ViewPatient);
             }            
-            _ => {
-                eprintln!("Warning: Unknown role '{}', no permissions assigned.", role_name);
-            }
+            _ => {}
         }
         perms
         }
     }
 
+// roles with built-in default permissions
+pub const BUILT_IN_ROLES: [&str; 5] = ["admin", "clinician", "patient", "caretaker", "Auditor"];
+
+// insert default grants that were never recorded; explicit revocations are kept
+pub fn seed_default_permissions(conn: &Connection) -> rusqlite::Result<()> {
+    for role in BUILT_IN_ROLES {
+        queries::insert_role(conn, role, None, "Built-in role", "system")?;
+        for perm in Role::default_permissions(role) {
+            queries::insert_default_role_permission(conn, role, perm.as_str())?;
+        }
+    }
+    Ok(())
+}
+
+// role names: letters, digits, '-' and '_', at most 32 characters
+pub fn is_valid_role_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 32
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// define a new role that inherits every permission of `parent`
+pub fn create_custom_role(conn: &Connection, name: &str, parent: &str, description: &str, created_by: &str) -> Result<(), String> {
+    if !is_valid_role_name(name) {
+        return Err("Role names may only contain letters, digits, '-' and '_' (max 32).".to_string());
+    }
+    match queries::role_exists(conn, name) {
+        Ok(true) => return Err(format!("Role '{}' already exists.", name)),
+        Ok(false) => {}
+        Err(e) => return Err(format!("Database error: {}", e)),
+    }
+    match queries::role_exists(conn, parent) {
+        Ok(true) => {}
+        Ok(false) => return Err(format!("Parent role '{}' does not exist.", parent)),
+        Err(e) => return Err(format!("Database error: {}", e)),
+    }
+    queries::insert_role(conn, name, Some(parent), description, created_by)
+        .map_err(|e| format!("Failed to create role: {}", e))?;
+    tracing::info!(target: crate::logger::SECURITY_TARGET, role = %name, parent = %parent, created_by = %created_by, "Custom role created");
+    Ok(())
+}
+
+/*
+Resource-scoped access control.
+A permission says what kind of action a role may take; the scope says on which patients:
+clinicians own the patients they created, caretakers are assigned to patients,
+and patients only reach their own record.
+*/
+pub enum Resource<'a> {
+    Patient(&'a str),
+}
+
+// SQL condition on patients selecting the patients of the user bound as ?1, by the role's base role
+fn patient_scope(role: &Role) -> Option<&'static str> {
+    match role.base.as_str() {
+        "clinician" => Some("clinician_id = ?1"),
+        // only patients who agreed to share their record with their caretakers, see consent.rs
+        "caretaker" => Some(
+            "patient_id IN (SELECT patient_id FROM patient_caretakers WHERE caretaker_id = ?1)
+             AND patient_id IN (SELECT patient_id FROM current_consents WHERE scope = 'caretakers' AND granted = 1)",
+        ),
+        "patient" => Some("patient_id = ?1"),
+        _ => None,
+    }
+}
+
+// roles without a patient scope (admin and roles built on it) act on every patient through their permissions alone
+pub fn has_patient_scope(role: &Role) -> bool {
+    patient_scope(role).is_some()
+}
+
+// central check: role has the permission AND the resource is within its scope
+pub fn can_access(conn: &Connection, role: &Role, resource: &Resource, action: &Permission) -> bool {
+    if !role.has_permission(action) {
+        return false;
+    }
+
+    let allowed = match resource {
+        Resource::Patient(patient_id) => match patient_scope(role) {
+            Some(scope) => match queries::patient_in_scope(conn, scope, &role.id, patient_id) {
+                Ok(in_scope) => in_scope,
+                Err(e) => {
+                    tracing::error!("Failed to load patient for access check: {}", e);
+                    false
+                }
+            },
+            None => false,
+        },
+    };
+
+    if !allowed {
+        tracing::warn!(target: crate::logger::SECURITY_TARGET, user_id = %role.id, role = %role.name, action = ?action, "Out-of-scope resource access denied");
+    } else if matches!(action, Permission::ViewPatient | Permission::ViewGlucose | Permission::ViewAlerts | Permission::ViewClinicalNotes) {
+        let Resource::Patient(patient_id) = resource;
+        record_patient_view(conn, role, patient_id);
+    }
+    allowed
+}
+
+// caretaker reads are stamped on the care team link so patients can see when each caretaker last looked
+// called by can_access for view permissions, and directly by lists built from accessible_patients
+pub fn record_patient_view(conn: &Connection, role: &Role, patient_id: &str) {
+    if role.base != "caretaker" {
+        return;
+    }
+    if let Err(e) = queries::record_caretaker_view(conn, patient_id, &role.id) {
+        tracing::error!("Failed to record caretaker view: {}", e);
+    }
+}
+
+// every patient the role may perform `action` on
+pub fn accessible_patients(conn: &Connection, role: &Role, action: &Permission) -> rusqlite::Result<Vec<Patient>> {
+    if !role.has_permission(action) {
+        return Ok(Vec::new());
+    }
+    match patient_scope(role) {
+        Some(column) => queries::get_patients_by_scope(conn, column, &role.id),
+        None => Ok(Vec::new()),
+    }
+}
+
+// accessible_patients narrowed down by a name or DOB search, at most `limit` results
+pub fn search_accessible_patients(
+    conn: &Connection,
+    role: &Role,
+    action: &Permission,
+    search: &queries::PatientSearch,
+    limit: usize,
+) -> rusqlite::Result<Vec<Patient>> {
+    if !role.has_permission(action) {
+        return Ok(Vec::new());
+    }
+    match patient_scope(role) {
+        Some(column) => queries::search_patients_by_scope(conn, column, &role.id, search, limit),
+        None => Ok(Vec::new()),
+    }
+}
+
+// one page of accessible_patients and the total count
+pub fn accessible_patients_page(
+    conn: &Connection,
+    role: &Role,
+    action: &Permission,
+    limit: usize,
+    offset: usize,
+) -> rusqlite::Result<(Vec<Patient>, usize)> {
+    if !role.has_permission(action) {
+        return Ok((Vec::new(), 0));
+    }
+    match patient_scope(role) {
+        Some(column) => Ok((
+            queries::get_patients_by_scope_page(conn, column, &role.id, limit, offset)?,
+            queries::count_patients_by_scope(conn, column, &role.id)?,
+        )),
+        None => Ok((Vec::new(), 0)),
+    }
+}
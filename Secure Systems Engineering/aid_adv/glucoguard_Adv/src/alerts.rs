@@ -1,3 +1,67 @@
-// Alert generation for glucose 
+// Alert generation for glucose
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
 
+// an unresolved alert of the same type within this window is not raised again
+const DUPLICATE_WINDOW_MINUTES: u32 = 30;
 
+#[derive(Debug, Clone, Serialize)]
+pub struct AlertRecord {
+    pub alert_id: i64,
+    pub patient_id: String,
+    pub alert_type: String,
+    pub alert_message: String,
+    pub alert_time: String,
+    pub is_resolved: bool,
+    pub resolved_by: Option<String>,
+}
+
+// most recent alerts for one patient, newest first
+pub fn get_recent_alerts(conn: &Connection, patient_id: &str, limit: u32) -> rusqlite::Result<Vec<AlertRecord>> {
+    let mut stmt = conn.prepare(
+        "SELECT alert_id, patient_id, alert_type, alert_message, alert_time, is_resolved, resolved_by
+         FROM alerts
+         WHERE patient_id = ?1
+         ORDER BY alert_time DESC
+         LIMIT ?2",
+    )?;
+    let alerts = stmt.query_map(params![patient_id, limit], |row| {
+        Ok(AlertRecord {
+            alert_id: row.get(0)?,
+            patient_id: row.get(1)?,
+            alert_type: row.get(2)?,
+            alert_message: row.get(3)?,
+            alert_time: row.get(4)?,
+            is_resolved: row.get(5)?,
+            resolved_by: row.get(6)?,
+        })
+    })?;
+    alerts.collect()
+}
+
+// record an alert for a patient, returns the new alert id or None if an
+// equivalent unresolved alert was raised recently
+pub fn raise_alert(conn: &Connection, patient_id: &str, alert_type: &str, message: &str) -> rusqlite::Result<Option<i64>> {
+    let existing: Option<i64> = conn
+        .query_row(
+            "SELECT alert_id FROM alerts
+             WHERE patient_id = ?1 AND alert_type = ?2 AND is_resolved = 0
+               AND julianday(alert_time) >= julianday('now', ?3)
+             LIMIT 1",
+            params![patient_id, alert_type, format!("-{} minutes", DUPLICATE_WINDOW_MINUTES)],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if existing.is_some() {
+        return Ok(None);
+    }
+
+    conn.execute(
+        "INSERT INTO alerts (patient_id, alert_type, alert_message, alert_time, is_resolved)
+         VALUES (?1, ?2, ?3, datetime('now'), 0)",
+        params![patient_id, alert_type, message],
+    )?;
+    let alert_id = conn.last_insert_rowid();
+    tracing::warn!(patient_id = %patient_id, alert_type = %alert_type, alert_id, "Glucose alert raised: {}", message);
+    Ok(Some(alert_id))
+}
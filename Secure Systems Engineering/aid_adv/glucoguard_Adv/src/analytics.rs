@@ -0,0 +1,247 @@
+// Glucose statistics: time-in-range, mean, standard deviation and coefficient of variation,
+// and the glucose management indicator (GMI, an estimated A1c) over 14, 30 and 90 days
+use crate::units::GlucoseUnit;
+use rusqlite::{params, Connection};
+use serde::Serialize;
+
+// consensus target range for time-in-range, mg/dL
+pub const TARGET_LOW: f64 = 70.0;
+pub const TARGET_HIGH: f64 = 180.0;
+// windows the GMI is reported over
+pub const GMI_WINDOWS: [Window; 3] = [Window::TwoWeeks, Window::Month, Window::Quarter];
+// share of the window's days that need readings before the GMI is considered reliable
+pub const GMI_MIN_DAY_COVERAGE: f64 = 0.7;
+
+// selectable look-back windows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    Day,
+    Week,
+    TwoWeeks,
+    Month,
+    Quarter,
+    Days(u32),
+}
+
+impl Window {
+    pub const PRESETS: [Window; 5] = [Window::Day, Window::Week, Window::TwoWeeks, Window::Month, Window::Quarter];
+
+    pub fn days(&self) -> u32 {
+        match self {
+            Window::Day => 1,
+            Window::Week => 7,
+            Window::TwoWeeks => 14,
+            Window::Month => 30,
+            Window::Quarter => 90,
+            Window::Days(days) => *days,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Window::Day => "last 24 hours".to_string(),
+            other => format!("last {} days", other.days()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlucoseStats {
+    pub count: usize,
+    pub mean: f64,
+    // sample standard deviation
+    pub std_dev: f64,
+    // coefficient of variation in percent, std_dev / mean
+    pub cv: f64,
+    pub time_below_pct: f64,
+    pub time_in_range_pct: f64,
+    pub time_above_pct: f64,
+}
+
+// glucose management indicator over one window, from the mean of its readings
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct GmiEstimate {
+    pub window_days: u32,
+    pub readings: usize,
+    pub days_with_readings: u32,
+    // mg/dL
+    pub mean_glucose: f64,
+    // percent, as an A1c
+    pub gmi_percent: f64,
+    pub gmi_mmol_mol: f64,
+}
+
+impl GmiEstimate {
+    pub fn is_reliable(&self) -> bool {
+        f64::from(self.days_with_readings) >= f64::from(self.window_days) * GMI_MIN_DAY_COVERAGE
+    }
+}
+
+// GMI (%) = 3.31 + 0.02392 x mean glucose in mg/dL (Bergenstal et al., Diabetes Care 2018)
+pub fn gmi_percent(mean_mg_dl: f64) -> f64 {
+    3.31 + 0.02392 * mean_mg_dl
+}
+
+// the same value in IFCC units, as laboratories report A1c
+pub fn percent_to_mmol_mol(percent: f64) -> f64 {
+    (percent - 2.152) * 10.929
+}
+
+// GMI over the window ending at `end` (UTC, "YYYY-MM-DD HH:MM:SS"), None without readings
+pub fn gmi(conn: &Connection, patient_id: &str, window: Window, end: &str) -> rusqlite::Result<Option<GmiEstimate>> {
+    let (readings, mean, days_with_readings): (usize, Option<f64>, u32) = conn.query_row(
+        "SELECT COUNT(*), AVG(glucose_level), COUNT(DISTINCT date(reading_time)) FROM glucose_readings
+         WHERE patient_id = ?1 AND julianday(reading_time) > julianday(?2, ?3) AND julianday(reading_time) <= julianday(?2)",
+        params![patient_id, end, format!("-{} days", window.days())],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    Ok(mean.map(|mean_glucose| {
+        let percent = gmi_percent(mean_glucose);
+        GmiEstimate {
+            window_days: window.days(),
+            readings,
+            days_with_readings,
+            mean_glucose,
+            gmi_percent: percent,
+            gmi_mmol_mol: percent_to_mmol_mol(percent),
+        }
+    }))
+}
+
+// GMI over each of GMI_WINDOWS ending at `end`, windows without readings left out
+pub fn gmi_estimates(conn: &Connection, patient_id: &str, end: &str) -> rusqlite::Result<Vec<GmiEstimate>> {
+    let mut estimates = Vec::with_capacity(GMI_WINDOWS.len());
+    for window in GMI_WINDOWS {
+        estimates.extend(gmi(conn, patient_id, window, end)?);
+    }
+    Ok(estimates)
+}
+
+// one line per estimate, e.g. "7.1% (54 mmol/mol) over the last 14 days, 1320 readings on 14 days"
+pub fn describe_gmi(estimate: &GmiEstimate) -> String {
+    format!(
+        "{:.1}% ({:.0} mmol/mol) over the last {} days, {} readings on {} days{}",
+        estimate.gmi_percent,
+        estimate.gmi_mmol_mol,
+        estimate.window_days,
+        estimate.readings,
+        estimate.days_with_readings,
+        if estimate.is_reliable() { "" } else { " (too few days with readings, interpret with care)" }
+    )
+}
+
+// percentage of values below, inside and above [low, high]
+pub fn range_percentages(levels: &[f64], low: f64, high: f64) -> (f64, f64, f64) {
+    if levels.is_empty() {
+        return (0.0, 0.0, 0.0);
+    }
+    let total = levels.len() as f64;
+    let below = levels.iter().filter(|&&v| v < low).count() as f64;
+    let above = levels.iter().filter(|&&v| v > high).count() as f64;
+    let within = total - below - above;
+    (below / total * 100.0, within / total * 100.0, above / total * 100.0)
+}
+
+// statistics over a set of readings, None when there are no readings
+pub fn compute_stats(levels: &[f64]) -> Option<GlucoseStats> {
+    if levels.is_empty() {
+        return None;
+    }
+    let count = levels.len();
+    let mean = levels.iter().sum::<f64>() / count as f64;
+    let std_dev = if count > 1 {
+        let variance = levels.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (count - 1) as f64;
+        variance.sqrt()
+    } else {
+        0.0
+    };
+    let cv = if mean > 0.0 { std_dev / mean * 100.0 } else { 0.0 };
+    let (time_below_pct, time_in_range_pct, time_above_pct) = range_percentages(levels, TARGET_LOW, TARGET_HIGH);
+
+    Some(GlucoseStats {
+        count,
+        mean,
+        std_dev,
+        cv,
+        time_below_pct,
+        time_in_range_pct,
+        time_above_pct,
+    })
+}
+
+// glucose levels recorded within the window, oldest first
+pub fn glucose_levels_in_window(conn: &Connection, patient_id: &str, window: Window) -> rusqlite::Result<Vec<f64>> {
+    let mut stmt = conn.prepare(
+        "SELECT glucose_level FROM glucose_readings
+         WHERE patient_id = ?1 AND julianday(reading_time) >= julianday('now', ?2)
+         ORDER BY reading_time ASC",
+    )?;
+    let levels = stmt.query_map(params![patient_id, format!("-{} days", window.days())], |row| row.get(0))?;
+    levels.collect()
+}
+
+pub fn glucose_stats(conn: &Connection, patient_id: &str, window: Window) -> rusqlite::Result<Option<GlucoseStats>> {
+    let levels = glucose_levels_in_window(conn, patient_id, window)?;
+    Ok(compute_stats(&levels))
+}
+
+// print the statistics block shown in the clinician and patient menus, levels in the viewer's unit
+pub fn print_stats(stats: Option<&GlucoseStats>, window: Window, unit: GlucoseUnit) {
+    println!("\n--- Glucose statistics ({}) ---", window.label());
+    let Some(stats) = stats else {
+        println!("No glucose readings in this period.");
+        return;
+    };
+    println!("Readings:            {}", stats.count);
+    println!("Average glucose:     {:.1} {}", unit.convert(stats.mean), unit.label());
+    println!("Standard deviation:  {:.1} {}", unit.convert(stats.std_dev), unit.label());
+    println!("Coefficient of var.: {:.1}%", stats.cv);
+    println!("Time in range ({}-{}): {:.1}%", unit.value(TARGET_LOW), unit.value(TARGET_HIGH), stats.time_in_range_pct);
+    println!("Time below range:    {:.1}%", stats.time_below_pct);
+    println!("Time above range:    {:.1}%", stats.time_above_pct);
+}
+
+// the GMI block shown with the statistics in the clinician and patient menus
+pub fn print_gmi(estimates: &[GmiEstimate]) {
+    println!("\n--- Glucose management indicator (estimated A1c) ---");
+    if estimates.is_empty() {
+        println!("No glucose readings in the last {} days.", Window::Quarter.days());
+        return;
+    }
+    for estimate in estimates {
+        println!("{}", describe_gmi(estimate));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    #[test]
+    fn gmi_follows_the_mean_of_each_window() {
+        assert!((gmi_percent(154.0) - 6.99).abs() < 0.01);
+        assert!((percent_to_mmol_mol(7.0) - 53.0).abs() < 0.1);
+
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        // one reading a day: 150 mg/dL for the last 20 days, 250 mg/dL the 40 days before
+        for day in 0..60 {
+            conn.execute(
+                "INSERT INTO glucose_readings (patient_id, glucose_level, reading_time, status)
+                 VALUES (?1, ?2, datetime('2024-06-30 12:00:00', ?3), 'normal')",
+                params![fixtures.patient_id, if day < 20 { 150.0 } else { 250.0 }, format!("-{} days", day)],
+            )
+            .unwrap();
+        }
+        let estimates = gmi_estimates(&conn, &fixtures.patient_id, "2024-06-30 23:00:00").unwrap();
+        assert_eq!(estimates.iter().map(|e| e.window_days).collect::<Vec<_>>(), vec![14, 30, 90]);
+        assert_eq!((estimates[0].readings, estimates[0].mean_glucose), (14, 150.0));
+        assert!(estimates[0].is_reliable());
+        assert!((estimates[1].mean_glucose - (20.0 * 150.0 + 10.0 * 250.0) / 30.0).abs() < 1e-9);
+        // 60 of 90 days have readings
+        assert_eq!(estimates[2].days_with_readings, 60);
+        assert!(!estimates[2].is_reliable());
+        assert!(gmi_estimates(&conn, &fixtures.other_patient_id, "2024-06-30 23:00:00").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,501 @@
+// Embedded REST API for companion apps (`glucoguard serve`)
+// Requests authenticate with "Authorization: Bearer <session_id>" from POST /api/login
+// (usable only from the client address and User-Agent that logged in)
+// or with a long-lived API token ("Bearer ggt_...") issued by an administrator
+// Live updates are pushed over the WebSocket at /api/stream (see stream.rs)
+// Pumps and CGMs pair at POST /api/devices/pair with the one-time code from the clinician (see devices.rs)
+// Prometheus scrapes GET /metrics with api.metrics_token (see metrics.rs)
+use crate::access_control::{self, Permission, Resource, Role};
+use crate::alerts;
+use crate::api_token;
+use crate::auth;
+use crate::config;
+use crate::devices;
+use crate::error::GlucoError;
+use crate::export::{self, DateRange};
+use crate::fhir;
+use crate::db::pool::Db;
+use crate::db::queries;
+use crate::insulin;
+use crate::logger::SECURITY_TARGET;
+use crate::metrics;
+use crate::nightscout;
+use crate::pump;
+use crate::runtime;
+use crate::session::{self, SessionManager};
+use crate::shutdown;
+use crate::stream::{self, LiveEvent};
+use axum::extract::{ConnectInfo, FromRequestParts, Path, Query, State};
+use axum::http::request::Parts;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono_tz::Tz;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::net::SocketAddr;
+use tokio::sync::broadcast;
+
+// default and maximum number of records per list request
+pub(crate) const DEFAULT_LIMIT: u32 = 50;
+pub(crate) const MAX_LIMIT: u32 = 500;
+
+#[derive(Clone)]
+pub(crate) struct AppState {
+    db: Db,
+    pub(crate) events: broadcast::Sender<LiveEvent>,
+}
+
+impl AppState {
+    // handler database work (and password hashing) runs on the blocking pool, off the async workers
+    pub(crate) async fn with_db<T, F>(&self, work: F) -> Result<T, ApiError>
+    where
+        F: FnOnce(&Connection) -> Result<T, ApiError> + Send + 'static,
+        T: Send + 'static,
+    {
+        runtime::with_db(&self.db, work).await.map_err(|e| ApiError::internal(&e))?
+    }
+}
+
+pub(crate) struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: &str) -> Self {
+        Self { status, message: message.to_string() }
+    }
+
+    pub(crate) fn unauthorized() -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, "missing, invalid or expired session")
+    }
+
+    fn forbidden() -> Self {
+        Self::new(StatusCode::FORBIDDEN, "access denied")
+    }
+
+    fn internal(message: &str) -> Self {
+        tracing::error!("API internal error: {}", message);
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "internal error")
+    }
+}
+
+impl From<rusqlite::Error> for ApiError {
+    fn from(e: rusqlite::Error) -> Self {
+        ApiError::internal(&e.to_string())
+    }
+}
+
+impl From<GlucoError> for ApiError {
+    fn from(e: GlucoError) -> Self {
+        match e {
+            GlucoError::Auth(message) => ApiError::new(StatusCode::FORBIDDEN, &message),
+            GlucoError::Permission(_) => ApiError::forbidden(),
+            GlucoError::Session(_) => ApiError::unauthorized(),
+            GlucoError::Db(e) => e.into(),
+            GlucoError::Validation(message) => ApiError::new(StatusCode::BAD_REQUEST, &message),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(serde_json::json!({ "error": self.message }))).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+struct LoginRequest {
+    username: String,
+    password: String,
+}
+
+#[derive(Serialize)]
+struct LoginResponse {
+    session_id: String,
+    role: String,
+    expires_in: u64,
+}
+
+#[derive(Deserialize)]
+struct PairRequest {
+    device_id: String,
+    pairing_code: String,
+    // hex Ed25519 public key
+    public_key: String,
+    // hex signature over "pair\n<device_id>\n<pairing_code>"
+    signature: String,
+}
+
+#[derive(Serialize)]
+struct PairResponse {
+    device_id: String,
+    patient_id: String,
+    device_type: String,
+    // hex Ed25519 key the server signs pump commands with, null while command signing is not configured
+    command_public_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ListQuery {
+    limit: Option<u32>,
+}
+
+impl ListQuery {
+    fn limit(&self) -> u32 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+}
+
+pub(crate) fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+// the HTTP client making a request, which REST sessions are bound to (see session::api_client_fingerprint)
+pub(crate) struct ApiClient {
+    pub(crate) fingerprint: String,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for ApiClient {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let ConnectInfo(address) = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .ok_or_else(|| ApiError::internal("client address unavailable"))?;
+        let user_agent = parts.headers.get(header::USER_AGENT).and_then(|value| value.to_str().ok()).unwrap_or("");
+        Ok(ApiClient { fingerprint: session::api_client_fingerprint(&address.ip().to_string(), user_agent) })
+    }
+}
+
+// authenticated caller, either an interactive session or an API token
+pub(crate) struct Caller {
+    // None when authenticated with an API token
+    session_id: Option<String>,
+    pub(crate) role: Role,
+    // token patient restriction, None = the role's full scope
+    patient_ids: Option<Vec<String>>,
+}
+
+impl Caller {
+    pub(crate) fn allows_patient(&self, patient_id: &str) -> bool {
+        match &self.patient_ids {
+            Some(ids) => ids.iter().any(|id| id == patient_id),
+            None => true,
+        }
+    }
+}
+
+// resolve the bearer credential to a role, same checks as the interactive menus
+fn authenticate(conn: &Connection, client: &ApiClient, headers: &HeaderMap) -> Result<Caller, ApiError> {
+    let credential = bearer_token(headers).ok_or_else(ApiError::unauthorized)?;
+    authenticate_credential(conn, credential, &client.fingerprint)
+}
+
+// sessions are only accepted from the client they were created for (`client_fingerprint`); API tokens from anywhere
+pub(crate) fn authenticate_credential(conn: &Connection, credential: &str, client_fingerprint: &str) -> Result<Caller, ApiError> {
+    if credential.starts_with(api_token::TOKEN_PREFIX) {
+        let token = api_token::authenticate_token(conn, credential)?.ok_or_else(ApiError::unauthorized)?;
+        return Ok(Caller {
+            session_id: None,
+            role: Role::new(conn, &token.role, &token.user_id),
+            patient_ids: token.patient_ids,
+        });
+    }
+    let session = SessionManager::new()
+        .get_session_for_client(conn, credential, client_fingerprint)
+        .ok_or_else(ApiError::unauthorized)?;
+    Ok(Caller {
+        role: Role::new(conn, &session.role, &session.user_id),
+        session_id: Some(session.session_id),
+        patient_ids: None,
+    })
+}
+
+// the caller must hold `permission` and have the patient in scope
+fn authorize_patient(conn: &Connection, caller: &Caller, patient_id: &str, permission: Permission) -> Result<(), ApiError> {
+    let role = &caller.role;
+    if caller.allows_patient(patient_id)
+        && access_control::can_access(conn, role, &Resource::Patient(patient_id), &permission)
+    {
+        Ok(())
+    } else {
+        tracing::warn!(target: SECURITY_TARGET, user_id = %role.id, patient_id = %patient_id, permission = ?permission, "API access denied");
+        Err(ApiError::forbidden())
+    }
+}
+
+async fn login(State(state): State<AppState>, client: ApiClient, Json(request): Json<LoginRequest>) -> Result<Json<LoginResponse>, ApiError> {
+    state
+        .with_db(move |conn| {
+            let user = queries::get_user_by_username(conn, &request.username)?;
+            let verified = match &user {
+                Some(user) => auth::verify_password(&request.password, &user.password_hash).unwrap_or(false),
+                None => false,
+            };
+            let Some(user) = user.filter(|_| verified) else {
+                tracing::warn!(target: SECURITY_TARGET, username = %request.username, "API login failed");
+                return Err(ApiError::new(StatusCode::UNAUTHORIZED, "invalid username or password"));
+            };
+            if queries::is_user_suspended(conn, &user.id)? {
+                tracing::warn!(target: SECURITY_TARGET, username = %request.username, user_id = %user.id, "API login for suspended account");
+                return Err(ApiError::new(StatusCode::FORBIDDEN, "account is suspended"));
+            }
+
+            let session_id = SessionManager::new()
+                .create_session_for_client(conn, user.id.clone(), user.role.clone(), &client.fingerprint)
+                .map_err(|e| match e {
+                    // the concurrent-session limit with the "reject" policy
+                    GlucoError::Session(message) => ApiError::new(StatusCode::TOO_MANY_REQUESTS, &message),
+                    other => other.into(),
+                })?;
+            tracing::info!(target: SECURITY_TARGET, username = %request.username, user_id = %user.id, "API login succeeded");
+            Ok(Json(LoginResponse {
+                session_id,
+                role: user.role,
+                expires_in: config::get().session.ttl_secs,
+            }))
+        })
+        .await
+}
+
+async fn logout(State(state): State<AppState>, client: ApiClient, headers: HeaderMap) -> Result<StatusCode, ApiError> {
+    state
+        .with_db(move |conn| {
+            let caller = authenticate(conn, &client, &headers)?;
+            let Some(session_id) = caller.session_id else {
+                return Err(ApiError::new(StatusCode::BAD_REQUEST, "API tokens are revoked by an administrator"));
+            };
+            SessionManager::new().deactivate_session(conn, &session_id)?;
+            Ok(StatusCode::NO_CONTENT)
+        })
+        .await
+}
+
+async fn list_patients(State(state): State<AppState>, client: ApiClient, headers: HeaderMap) -> Result<Response, ApiError> {
+    state
+        .with_db(move |conn| {
+            let caller = authenticate(conn, &client, &headers)?;
+            if !caller.role.has_permission(&Permission::ViewPatient) {
+                return Err(ApiError::forbidden());
+            }
+            let mut patients = access_control::accessible_patients(conn, &caller.role, &Permission::ViewPatient)?;
+            patients.retain(|patient| caller.allows_patient(&patient.patient_id));
+            Ok(Json(patients).into_response())
+        })
+        .await
+}
+
+async fn glucose_readings(
+    State(state): State<AppState>,
+    client: ApiClient,
+    headers: HeaderMap,
+    Path(patient_id): Path<String>,
+    Query(query): Query<ListQuery>,
+) -> Result<Response, ApiError> {
+    state
+        .with_db(move |conn| {
+            let caller = authenticate(conn, &client, &headers)?;
+            authorize_patient(conn, &caller, &patient_id, Permission::ViewGlucose)?;
+            let readings = insulin::get_recent_glucose_readings(conn, &patient_id, query.limit())?;
+            Ok(Json(readings).into_response())
+        })
+        .await
+}
+
+async fn insulin_logs(
+    State(state): State<AppState>,
+    client: ApiClient,
+    headers: HeaderMap,
+    Path(patient_id): Path<String>,
+    Query(query): Query<ListQuery>,
+) -> Result<Response, ApiError> {
+    state
+        .with_db(move |conn| {
+            let caller = authenticate(conn, &client, &headers)?;
+            authorize_patient(conn, &caller, &patient_id, Permission::ViewGlucose)?;
+            let logs = insulin::get_recent_insulin_logs(conn, &patient_id, query.limit())?;
+            Ok(Json(logs).into_response())
+        })
+        .await
+}
+
+async fn patient_alerts(
+    State(state): State<AppState>,
+    client: ApiClient,
+    headers: HeaderMap,
+    Path(patient_id): Path<String>,
+    Query(query): Query<ListQuery>,
+) -> Result<Response, ApiError> {
+    state
+        .with_db(move |conn| {
+            let caller = authenticate(conn, &client, &headers)?;
+            authorize_patient(conn, &caller, &patient_id, Permission::ViewAlerts)?;
+            let alerts = alerts::get_recent_alerts(conn, &patient_id, query.limit())?;
+            Ok(Json(alerts).into_response())
+        })
+        .await
+}
+
+// full history as a FHIR Bundle for EHR integrations
+async fn patient_fhir_bundle(
+    State(state): State<AppState>,
+    client: ApiClient,
+    headers: HeaderMap,
+    Path(patient_id): Path<String>,
+) -> Result<Response, ApiError> {
+    state
+        .with_db(move |conn| {
+            let caller = authenticate(conn, &client, &headers)?;
+            authorize_patient(conn, &caller, &patient_id, Permission::ViewGlucose)?;
+            let range = DateRange::default();
+            let readings = export::glucose_readings_in_range(conn, &patient_id, &range, Tz::UTC)?;
+            let logs = export::insulin_logs_in_range(conn, &patient_id, &range, Tz::UTC)?;
+            tracing::info!(target: SECURITY_TARGET, user_id = %caller.role.id, patient_id = %patient_id, "FHIR bundle exported over API");
+            Ok(([(header::CONTENT_TYPE, fhir::CONTENT_TYPE)], fhir::bundle(&readings, &logs).to_string()).into_response())
+        })
+        .await
+}
+
+// the pairing code is the credential, so no bearer token is needed
+async fn pair_device(State(state): State<AppState>, Json(request): Json<PairRequest>) -> Result<Json<PairResponse>, ApiError> {
+    state
+        .with_db(move |conn| {
+            let device = devices::pair_device(conn, &request.device_id, &request.pairing_code, &request.public_key, &request.signature)
+                .map_err(|e| ApiError::new(StatusCode::UNAUTHORIZED, &e))?;
+            Ok(Json(PairResponse {
+                device_id: device.device_id,
+                patient_id: device.patient_id,
+                device_type: device.device_type,
+                command_public_key: pump::command_public_key(),
+            }))
+        })
+        .await
+}
+
+// Prometheus scrape; 404 until api.metrics_token is set, so an unconfigured install exposes nothing
+async fn scrape_metrics(State(state): State<AppState>, headers: HeaderMap) -> Result<Response, ApiError> {
+    let token = &config::get().api.metrics_token;
+    if token.is_empty() {
+        return Err(ApiError::new(StatusCode::NOT_FOUND, "metrics are disabled"));
+    }
+    if !bearer_token(&headers).is_some_and(|presented| metrics::token_matches(token, presented)) {
+        return Err(ApiError::new(StatusCode::UNAUTHORIZED, "missing or invalid metrics token"));
+    }
+    state
+        .with_db(|conn| Ok(([(header::CONTENT_TYPE, metrics::CONTENT_TYPE)], metrics::render(conn)?).into_response()))
+        .await
+}
+
+fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/api/login", post(login))
+        .route("/api/logout", post(logout))
+        .route("/api/devices/pair", post(pair_device))
+        .route("/api/patients", get(list_patients))
+        .route("/api/patients/{patient_id}/glucose", get(glucose_readings))
+        .route("/api/patients/{patient_id}/insulin", get(insulin_logs))
+        .route("/api/patients/{patient_id}/alerts", get(patient_alerts))
+        .route("/api/patients/{patient_id}/fhir", get(patient_fhir_bundle))
+        .route("/api/stream", get(stream::stream_handler))
+        .route("/metrics", get(scrape_metrics))
+        .with_state(state)
+}
+
+// `glucoguard serve [--bind <addr>]`
+pub fn run(addr: SocketAddr) -> Result<(), Box<dyn Error>> {
+    let (events, _) = broadcast::channel(stream::CHANNEL_CAPACITY);
+    let state = AppState {
+        db: Db::from_config()?,
+        events,
+    };
+    SessionManager::new().run_cleanup(&state.db);
+    shutdown::install(&state.db);
+    if config::get().nightscout.enabled {
+        nightscout::spawn(config::get().nightscout.clone(), state.db.clone());
+    }
+
+    runtime::get().block_on(async move {
+        stream::spawn_poller(state.db.clone(), state.events.clone());
+
+        #[cfg(feature = "grpc")]
+        {
+            let grpc_addr: std::net::SocketAddr = config::get().api.grpc_bind.parse()?;
+            let telemetry = crate::grpc::TelemetryService::new(state.db.clone());
+            tokio::spawn(async move {
+                let server = tonic::transport::Server::builder().add_service(telemetry).serve(grpc_addr);
+                if let Err(e) = server.await {
+                    tracing::error!("Pump gRPC service stopped: {}", e);
+                }
+            });
+            tracing::info!(%grpc_addr, "Pump gRPC service listening");
+            println!("Pump telemetry gRPC service listening on {}", grpc_addr);
+        }
+
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        tracing::info!(%addr, "REST API listening");
+        println!("GlucoGuard API listening on http://{}", addr);
+        axum::serve(listener, router(state).into_make_service_with_connect_info::<SocketAddr>()).await?;
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use axum::http::Request;
+
+    fn client(address: &str, user_agent: &str) -> ApiClient {
+        let (mut parts, _) = Request::builder()
+            .header(header::USER_AGENT, user_agent)
+            .extension(ConnectInfo(address.parse::<SocketAddr>().unwrap()))
+            .body(())
+            .unwrap()
+            .into_parts();
+        match runtime::get().block_on(ApiClient::from_request_parts(&mut parts, &())) {
+            Ok(client) => client,
+            Err(_) => panic!("client without an address"),
+        }
+    }
+
+    #[test]
+    fn session_replayed_from_another_client_is_rejected() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let app = client("10.0.0.5:50000", "GlucoGuard-App/2.1");
+        let session_id = SessionManager::new()
+            .create_session_for_client(&conn, fixtures.clinician_id.clone(), "clinician".to_string(), &app.fingerprint)
+            .unwrap();
+
+        let caller = authenticate_credential(&conn, &session_id, &app.fingerprint).ok().unwrap();
+        assert_eq!(caller.role.id, fixtures.clinician_id);
+        // a new connection from the same client uses another port
+        assert!(authenticate_credential(&conn, &session_id, &client("10.0.0.5:50001", "GlucoGuard-App/2.1").fingerprint).is_ok());
+
+        assert!(authenticate_credential(&conn, &session_id, &client("203.0.113.9:50000", "GlucoGuard-App/2.1").fingerprint).is_err());
+        assert!(authenticate_credential(&conn, &session_id, &client("10.0.0.5:50000", "curl/8.5").fingerprint).is_err());
+        assert!(authenticate_credential(&conn, &session_id, session::client_fingerprint()).is_err());
+    }
+
+    #[test]
+    fn terminal_sessions_are_not_accepted_over_rest() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let session_id = SessionManager::new()
+            .create_session(&conn, fixtures.clinician_id.clone(), "clinician".to_string())
+            .unwrap();
+
+        assert!(SessionManager::new().get_session_by_id(&conn, &session_id).is_some());
+        assert!(authenticate_credential(&conn, &session_id, &client("127.0.0.1:40000", "GlucoGuard-App/2.1").fingerprint).is_err());
+    }
+}
@@ -0,0 +1,166 @@
+// Long-lived API tokens for scripts and devices
+// Only the SHA-256 of a token is stored; the plaintext is shown once when it is issued
+use crate::access_control::{self, Permission, Role};
+use crate::db::queries;
+use crate::logger::SECURITY_TARGET;
+use rand::RngCore;
+use rusqlite::{params, Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+// every token starts with this prefix so it can be told apart from a session id
+pub const TOKEN_PREFIX: &str = "ggt_";
+
+#[derive(Debug, Clone)]
+pub struct ApiToken {
+    pub token_id: String,
+    pub user_id: String,
+    pub role: String,
+    // None = every patient the role can reach, Some = only these patients
+    pub patient_ids: Option<Vec<String>>,
+    pub label: String,
+    pub created_by: String,
+    pub created_at: String,
+    pub expires_at: Option<String>,
+    pub revoked: bool,
+    pub last_used_at: Option<String>,
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+const TOKEN_COLUMNS: &str =
+    "token_id, user_id, role, patient_ids, label, created_by, created_at, expires_at, revoked, last_used_at";
+
+fn token_from_row(row: &rusqlite::Row) -> rusqlite::Result<ApiToken> {
+    let patient_ids: Option<String> = row.get(3)?;
+    Ok(ApiToken {
+        token_id: row.get(0)?,
+        user_id: row.get(1)?,
+        role: row.get(2)?,
+        patient_ids: patient_ids.map(|ids| ids.split(',').map(str::to_string).collect()),
+        label: row.get(4)?,
+        created_by: row.get(5)?,
+        created_at: row.get(6)?,
+        expires_at: row.get(7)?,
+        revoked: row.get(8)?,
+        last_used_at: row.get(9)?,
+    })
+}
+
+/*
+Issue a token for `user_id`.
+The token role must be the user's own role or one of its ancestors (a token can only narrow access),
+and every listed patient must be reachable by the user under that role.
+Returns (token_id, plaintext token).
+*/
+pub fn issue_token(
+    conn: &Connection,
+    user_id: &str,
+    role: &str,
+    patient_ids: Option<&[String]>,
+    label: &str,
+    expires_in_days: Option<u32>,
+    created_by: &str,
+) -> Result<(String, String), String> {
+    let user_role = queries::get_user_role(conn, user_id)
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or("User not found.")?;
+    if !Role::resolve_chain(conn, &user_role).iter().any(|r| r == role) {
+        return Err(format!("Role '{}' is not '{}' or one of its parent roles.", role, user_role));
+    }
+
+    if let Some(ids) = patient_ids {
+        if ids.is_empty() {
+            return Err("The patient list cannot be empty.".to_string());
+        }
+        let token_role = Role::new(conn, role, user_id);
+        let reachable = access_control::accessible_patients(conn, &token_role, &Permission::ViewPatient)
+            .map_err(|e| format!("Database error: {}", e))?;
+        if let Some(missing) = ids.iter().find(|id| !reachable.iter().any(|p| &p.patient_id == *id)) {
+            return Err(format!("Patient '{}' is not accessible to this user.", missing));
+        }
+    }
+
+    let mut secret = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut secret);
+    let token = format!("{}{}", TOKEN_PREFIX, hex::encode(secret));
+    let token_id = Uuid::new_v4().to_string();
+    let expires_at = expires_in_days.map(|days| format!("+{} days", days));
+
+    conn.execute(
+        "INSERT INTO api_tokens (token_id, token_hash, user_id, role, patient_ids, label, created_by, created_at, expires_at, revoked)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, datetime('now'), CASE WHEN ?8 IS NULL THEN NULL ELSE datetime('now', ?8) END, 0)",
+        params![
+            token_id,
+            hash_token(&token),
+            user_id,
+            role,
+            patient_ids.map(|ids| ids.join(",")),
+            label,
+            created_by,
+            expires_at,
+        ],
+    )
+    .map_err(|e| format!("Failed to store token: {}", e))?;
+
+    tracing::info!(target: SECURITY_TARGET, token_id = %token_id, user_id = %user_id, role = %role, created_by = %created_by, "API token issued");
+    Ok((token_id, token))
+}
+
+// look up an active (not revoked, not expired) token and record its use
+pub fn authenticate_token(conn: &Connection, token: &str) -> rusqlite::Result<Option<ApiToken>> {
+    if !token.starts_with(TOKEN_PREFIX) {
+        return Ok(None);
+    }
+    let sql = format!(
+        "SELECT {} FROM api_tokens
+         WHERE token_hash = ?1 AND revoked = 0
+           AND (expires_at IS NULL OR julianday(expires_at) > julianday('now'))",
+        TOKEN_COLUMNS
+    );
+    let mut found = conn.query_row(&sql, [hash_token(token)], token_from_row).optional()?;
+
+    // the token stops working when its user is deleted or suspended, or moved to a role that no longer covers it
+    if let Some(api_token) = &found {
+        let still_covered = match queries::get_user_role(conn, &api_token.user_id)? {
+            Some(user_role) => Role::resolve_chain(conn, &user_role).contains(&api_token.role),
+            None => false,
+        };
+        if !still_covered {
+            tracing::warn!(target: SECURITY_TARGET, token_id = %api_token.token_id, user_id = %api_token.user_id, "API token no longer matches its user's role");
+            found = None;
+        } else if queries::is_user_suspended(conn, &api_token.user_id)? {
+            tracing::warn!(target: SECURITY_TARGET, token_id = %api_token.token_id, user_id = %api_token.user_id, "API token of suspended account");
+            found = None;
+        }
+    }
+
+    match &found {
+        Some(api_token) => {
+            conn.execute(
+                "UPDATE api_tokens SET last_used_at = datetime('now') WHERE token_id = ?1",
+                [&api_token.token_id],
+            )?;
+        }
+        None => tracing::warn!(target: SECURITY_TARGET, "Rejected API token"),
+    }
+    Ok(found)
+}
+
+pub fn list_tokens(conn: &Connection) -> rusqlite::Result<Vec<ApiToken>> {
+    let sql = format!("SELECT {} FROM api_tokens ORDER BY created_at DESC", TOKEN_COLUMNS);
+    let mut stmt = conn.prepare(&sql)?;
+    let tokens = stmt.query_map([], token_from_row)?;
+    tokens.collect()
+}
+
+// returns false when no such token exists
+pub fn revoke_token(conn: &Connection, token_id: &str, revoked_by: &str) -> rusqlite::Result<bool> {
+    let updated = conn.execute("UPDATE api_tokens SET revoked = 1 WHERE token_id = ?1", [token_id])?;
+    if updated > 0 {
+        tracing::warn!(target: SECURITY_TARGET, token_id = %token_id, revoked_by = %revoked_by, "API token revoked");
+    }
+    Ok(updated > 0)
+}
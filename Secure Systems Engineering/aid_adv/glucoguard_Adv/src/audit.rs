@@ -0,0 +1,386 @@
+// Audit trail: every security event is also stored in the audit_events table so admins can browse,
+// filter and export it from the admin menu. A background thread with its own connection does the
+// inserts, so logging never waits on (or deadlocks with) a transaction held by the caller.
+use crate::config;
+use crate::export::DateRange;
+use crate::logger::SECURITY_TARGET;
+use crate::timestamps;
+use chrono::Utc;
+use chrono_tz::Tz;
+use rusqlite::{params_from_iter, types::Value, Connection};
+use serde::Serialize;
+use serde_json::{Map, Value as JsonValue};
+use std::error::Error;
+use std::fs::create_dir_all;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+// how long a short-lived command waits for queued events to be written before exiting
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+const WRITER_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AuditEvent {
+    pub occurred_at: String,
+    pub level: String,
+    // the event message, e.g. "Login failed"
+    pub action: String,
+    pub user_id: Option<String>,
+    pub username: Option<String>,
+    // remaining event fields as a JSON object
+    pub details: String,
+}
+
+enum Message {
+    Event(AuditEvent),
+    Flush(SyncSender<()>),
+}
+
+// events queue here until start_writer hands the receiver to the writer thread
+static PENDING: Mutex<Option<Receiver<Message>>> = Mutex::new(None);
+static QUEUE: Mutex<Option<Sender<Message>>> = Mutex::new(None);
+
+// collects the fields of one event
+#[derive(Default)]
+struct FieldVisitor {
+    message: String,
+    user_id: Option<String>,
+    username: Option<String>,
+    details: Map<String, JsonValue>,
+}
+
+impl FieldVisitor {
+    fn record(&mut self, field: &Field, value: JsonValue) {
+        let text = || match &value {
+            JsonValue::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        match field.name() {
+            "message" => self.message = text(),
+            "user_id" => self.user_id = Some(text()),
+            "username" => self.username = Some(text()),
+            name => {
+                self.details.insert(name.to_string(), value);
+            }
+        }
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record(field, JsonValue::from(value));
+    }
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.record(field, JsonValue::from(value));
+    }
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.record(field, JsonValue::from(value));
+    }
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.record(field, JsonValue::from(value));
+    }
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        // %value fields arrive here already Display-formatted
+        self.record(field, JsonValue::from(format!("{:?}", value)));
+    }
+}
+
+// tracing layer that forwards security events to the audit queue
+pub struct AuditLayer {
+    sender: Sender<Message>,
+}
+
+impl<S: Subscriber> Layer<S> for AuditLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if event.metadata().target() != SECURITY_TARGET {
+            return;
+        }
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        let _ = self.sender.send(Message::Event(AuditEvent {
+            occurred_at: timestamps::now(),
+            level: event.metadata().level().to_string(),
+            action: visitor.message,
+            user_id: visitor.user_id,
+            username: visitor.username,
+            details: JsonValue::Object(visitor.details).to_string(),
+        }));
+    }
+}
+
+// the layer installed by logger::init; events are held until start_writer is called
+pub fn layer() -> AuditLayer {
+    let (sender, receiver) = mpsc::channel();
+    *PENDING.lock().unwrap() = Some(receiver);
+    *QUEUE.lock().unwrap() = Some(sender.clone());
+    AuditLayer { sender }
+}
+
+/*
+Start writing queued events into the audit_events table of the database at path.
+Only the first call starts a writer; the database must already be migrated.
+*/
+pub fn start_writer(path: &str) {
+    let Some(receiver) = PENDING.lock().unwrap().take() else {
+        return;
+    };
+    let conn = match Connection::open(path).and_then(|conn| conn.busy_timeout(WRITER_BUSY_TIMEOUT).map(|_| conn)) {
+        Ok(conn) => conn,
+        Err(e) => {
+            tracing::error!(error = %e, "Audit trail writer could not open the database");
+            return;
+        }
+    };
+    thread::spawn(move || {
+        for message in receiver {
+            match message {
+                Message::Event(event) => {
+                    if let Err(e) = insert_event(&conn, &event) {
+                        tracing::error!(error = %e, action = %event.action, "Failed to store audit event");
+                    }
+                }
+                Message::Flush(done) => {
+                    let _ = done.send(());
+                }
+            }
+        }
+    });
+}
+
+// wait until the events logged so far are stored, for commands that exit right after their work
+pub fn flush() {
+    let Some(sender) = QUEUE.lock().unwrap().clone() else {
+        return;
+    };
+    if PENDING.lock().unwrap().is_some() {
+        // no writer was started, nothing to wait for
+        return;
+    }
+    let (done, wait) = mpsc::sync_channel(1);
+    if sender.send(Message::Flush(done)).is_ok() {
+        let _ = wait.recv_timeout(FLUSH_TIMEOUT);
+    }
+}
+
+pub fn insert_event(conn: &Connection, event: &AuditEvent) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO audit_events (occurred_at, level, action, user_id, username, details)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        rusqlite::params![event.occurred_at, event.level, event.action, event.user_id, event.username, event.details],
+    )?;
+    Ok(())
+}
+
+// every field is optional; set fields must all match
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    // username or user id as typed
+    pub user: Option<String>,
+    // id of the account named by user, when it still exists
+    pub user_id: Option<String>,
+    // case-insensitive part of the action text
+    pub action: Option<String>,
+    pub dates: DateRange,
+    // zone the dates are days in
+    pub zone: Tz,
+}
+
+impl AuditFilter {
+    pub fn is_empty(&self) -> bool {
+        self.user.is_none() && self.action.is_none() && self.dates.from.is_none() && self.dates.to.is_none()
+    }
+
+    // WHERE clause and its parameters
+    fn to_sql(&self) -> (String, Vec<Value>) {
+        let mut conditions = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+        if let Some(user) = &self.user {
+            values.push(Value::from(user.clone()));
+            let mut condition = format!("(username = ?{n} OR user_id = ?{n}", n = values.len());
+            // events about the account (changed_by, reset_by, ...) carry its id among the details
+            if let Some(user_id) = &self.user_id {
+                values.push(Value::from(user_id.clone()));
+                condition.push_str(&format!(" OR user_id = ?{n} OR instr(details, ?{n}) > 0", n = values.len()));
+            }
+            condition.push(')');
+            conditions.push(condition);
+        }
+        if let Some(action) = &self.action {
+            values.push(Value::from(format!("%{}%", action)));
+            conditions.push(format!("action LIKE ?{}", values.len()));
+        }
+        if self.dates.from.is_some() || self.dates.to.is_some() {
+            let (start, end) = self.dates.bounds(self.zone);
+            values.push(Value::from(start));
+            conditions.push(format!("occurred_at >= ?{}", values.len()));
+            values.push(Value::from(end));
+            conditions.push(format!("occurred_at < ?{}", values.len()));
+        }
+        if conditions.is_empty() {
+            (String::new(), values)
+        } else {
+            (format!("WHERE {}", conditions.join(" AND ")), values)
+        }
+    }
+}
+
+pub fn count_events(conn: &Connection, filter: &AuditFilter) -> rusqlite::Result<usize> {
+    let (clause, values) = filter.to_sql();
+    let sql = format!("SELECT COUNT(*) FROM audit_events {}", clause);
+    conn.query_row(&sql, params_from_iter(values), |row| row.get(0))
+}
+
+// matching events, newest first
+pub fn get_events_page(conn: &Connection, filter: &AuditFilter, limit: usize, offset: usize) -> rusqlite::Result<Vec<AuditEvent>> {
+    let (clause, mut values) = filter.to_sql();
+    let sql = format!(
+        "SELECT occurred_at, level, action, user_id, username, details FROM audit_events {}
+         ORDER BY event_id DESC LIMIT ?{} OFFSET ?{}",
+        clause,
+        values.len() + 1,
+        values.len() + 2
+    );
+    values.push(Value::from(limit as i64));
+    values.push(Value::from(offset as i64));
+    let mut stmt = conn.prepare(&sql)?;
+    let events = stmt.query_map(params_from_iter(values), |row| {
+        Ok(AuditEvent {
+            occurred_at: row.get(0)?,
+            level: row.get(1)?,
+            action: row.get(2)?,
+            user_id: row.get(3)?,
+            username: row.get(4)?,
+            details: row.get(5)?,
+        })
+    })?;
+    events.collect()
+}
+
+// details as "key=value" pairs for display
+pub fn format_details(details: &str) -> String {
+    match serde_json::from_str::<Map<String, JsonValue>>(details) {
+        Ok(fields) => fields
+            .iter()
+            .map(|(key, value)| match value {
+                JsonValue::String(s) => format!("{}={}", key, s),
+                other => format!("{}={}", key, other),
+            })
+            .collect::<Vec<_>>()
+            .join(" "),
+        Err(_) => details.to_string(),
+    }
+}
+
+fn write_csv(path: &Path, events: &[AuditEvent]) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_path(path)?;
+    for event in events {
+        writer.serialize(event)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// write every matching event, oldest first, to a CSV file in export.dir with the times in the filter's
+// zone; the export itself is audited
+pub fn export_events(conn: &Connection, filter: &AuditFilter, exported_by: &str) -> Result<(PathBuf, usize), Box<dyn Error>> {
+    let total = count_events(conn, filter)?;
+    let mut events = get_events_page(conn, filter, total, 0)?;
+    events.reverse();
+    for event in &mut events {
+        event.occurred_at = timestamps::to_rfc3339(&event.occurred_at, filter.zone);
+    }
+
+    let export_dir = &config::get().export.dir;
+    create_dir_all(export_dir)?;
+    let path = Path::new(export_dir).join(format!("audit_log_{}.csv", Utc::now().format("%Y%m%d_%H%M%S")));
+    write_csv(&path, &events)?;
+
+    tracing::info!(
+        target: SECURITY_TARGET,
+        user_id = %exported_by,
+        events = events.len(),
+        filtered = !filter.is_empty(),
+        path = %path.display(),
+        "Audit log exported"
+    );
+    Ok((path, events.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use chrono::NaiveDate;
+    use tracing_subscriber::prelude::*;
+
+    fn event(occurred_at: &str, action: &str, user_id: Option<&str>, username: Option<&str>, details: &str) -> AuditEvent {
+        AuditEvent {
+            occurred_at: occurred_at.to_string(),
+            level: "WARN".to_string(),
+            action: action.to_string(),
+            user_id: user_id.map(str::to_string),
+            username: username.map(str::to_string),
+            details: details.to_string(),
+        }
+    }
+
+    #[test]
+    fn security_events_are_captured_with_their_fields() {
+        let (sender, receiver) = mpsc::channel();
+        let subscriber = tracing_subscriber::registry().with(AuditLayer { sender });
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("Operational event");
+            tracing::warn!(target: SECURITY_TARGET, user_id = %"user-1", reset_by = %"admin-1", revoked = 2, "Password reset by admin");
+        });
+
+        let captured: Vec<AuditEvent> = receiver
+            .try_iter()
+            .filter_map(|message| match message {
+                Message::Event(event) => Some(event),
+                Message::Flush(_) => None,
+            })
+            .collect();
+        assert_eq!(captured.len(), 1);
+        assert_eq!(captured[0].action, "Password reset by admin");
+        assert_eq!(captured[0].level, "WARN");
+        assert_eq!(captured[0].user_id.as_deref(), Some("user-1"));
+        assert_eq!(format_details(&captured[0].details), "reset_by=admin-1 revoked=2");
+    }
+
+    #[test]
+    fn events_filter_by_user_action_and_date() {
+        let conn = test_utils::test_db();
+        for e in [
+            event("2026-03-01 08:00:00", "Login failed", None, Some("dr.fixture"), "{}"),
+            event("2026-03-02 09:30:00", "Session created", Some("clin-1"), None, "{}"),
+            event("2026-03-03 10:00:00", "Password reset by admin", Some("admin-1"), None, r#"{"target":"clin-1"}"#),
+            event("2026-03-03 23:59:00", "Login failed", None, Some("someone.else"), "{}"),
+        ] {
+            insert_event(&conn, &e).unwrap();
+        }
+
+        let everything = AuditFilter::default();
+        assert_eq!(count_events(&conn, &everything).unwrap(), 4);
+        let newest = get_events_page(&conn, &everything, 1, 0).unwrap();
+        assert_eq!(newest[0].username.as_deref(), Some("someone.else"));
+
+        let by_user = AuditFilter { user: Some("dr.fixture".into()), user_id: Some("clin-1".into()), ..Default::default() };
+        assert_eq!(count_events(&conn, &by_user).unwrap(), 3);
+
+        let by_action = AuditFilter { action: Some("login FAILED".into()), ..Default::default() };
+        assert_eq!(count_events(&conn, &by_action).unwrap(), 2);
+
+        let one_day = DateRange { from: NaiveDate::from_ymd_opt(2026, 3, 3), to: NaiveDate::from_ymd_opt(2026, 3, 3) };
+        let by_date = AuditFilter { dates: one_day, action: Some("login".into()), ..Default::default() };
+        let matched = get_events_page(&conn, &by_date, 10, 0).unwrap();
+        assert_eq!(matched.len(), 1);
+        assert_eq!(matched[0].occurred_at, "2026-03-03 23:59:00");
+    }
+}
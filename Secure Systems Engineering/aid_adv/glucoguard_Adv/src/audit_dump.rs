@@ -0,0 +1,198 @@
+/*
+Reader for the system_audit_* dump files that older versions wrote into logging.log_dir; the audit trail
+now lives in audit_events and no new dumps are created. The dumps hold the whole database including
+password hashes, so decoding needs DecodeAuditDumps (admins only by default) and the key they were encoded
+with in audit.legacy_dump_key, which secrets.rs can resolve from the OS keyring or a key file.
+Only files named system_audit_* directly in the log directory are read. A decode must give the text the old
+writer produced, from its header line to its trailer, so a wrong key or a damaged or cut-off file is refused.
+The file's SHA-256 is pinned in audit_dump_digests when it is first decoded and later decodes must match,
+so a dump changed after an admin read it is refused too. Every decode and every refusal is logged with who
+asked for which file.
+*/
+use crate::access_control::{Permission, Role};
+use crate::config;
+use crate::logger::SECURITY_TARGET;
+use crate::timestamps;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const FILE_PREFIX: &str = "system_audit_";
+// first and last line of every dump the old writer produced
+const HEADER: &str = "GlucoGuard System Audit Log";
+const TRAILER: &str = "End of audit log";
+
+#[derive(Debug, Serialize)]
+pub struct DecodedDump {
+    pub file_name: String,
+    // of the encoded file
+    pub sha256: String,
+    pub first_decoded_at: String,
+    pub content: String,
+}
+
+// names of the dump files in `dir`, oldest first (the names carry their creation time)
+pub fn list_dumps(dir: &Path) -> std::io::Result<Vec<String>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut names = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with(FILE_PREFIX) && entry.file_type()?.is_file() {
+            names.push(name);
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+// a bare dump file name, never a path leading out of the log directory; symlinks are not followed
+fn dump_path(dir: &Path, file_name: &str) -> Result<PathBuf, String> {
+    let bare = !file_name.contains(['/', '\\']) && file_name != ".." && !file_name.contains('\0');
+    if !bare || !file_name.starts_with(FILE_PREFIX) {
+        return Err(format!("'{}' is not a {}* file name", file_name, FILE_PREFIX));
+    }
+    let path = dir.join(file_name);
+    match fs::symlink_metadata(&path) {
+        Ok(metadata) if metadata.is_file() => Ok(path),
+        Ok(_) => Err(format!("{} is not a regular file", file_name)),
+        Err(e) => Err(format!("cannot read {}: {}", file_name, e)),
+    }
+}
+
+// the dumps were XORed with a repeating key; the result must be a complete dump
+pub fn decode_audit_file(encoded: &[u8], key: &str) -> Result<String, String> {
+    let key_bytes = key.as_bytes();
+    if key_bytes.is_empty() {
+        return Err("audit.legacy_dump_key is not set".to_string());
+    }
+    let decoded: Vec<u8> = encoded.iter().enumerate().map(|(i, &byte)| byte ^ key_bytes[i % key_bytes.len()]).collect();
+    let content = String::from_utf8(decoded).map_err(|_| "wrong key or damaged file: the decoded data is not text".to_string())?;
+    if !content.starts_with(HEADER) {
+        return Err("wrong key or damaged file: the audit dump header is missing".to_string());
+    }
+    if !content.trim_end().ends_with(TRAILER) {
+        return Err("damaged file: the audit dump is incomplete".to_string());
+    }
+    Ok(content)
+}
+
+// compare with the digest pinned by the first decode, or pin this one
+fn check_digest(conn: &Connection, file_name: &str, sha256: &str, user_id: &str) -> Result<String, String> {
+    let now = timestamps::now();
+    let pinned: Option<(String, String)> = conn
+        .query_row(
+            "SELECT sha256, first_decoded_at FROM audit_dump_digests WHERE file_name = ?1",
+            [file_name],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    match pinned {
+        Some((expected, _)) if expected != sha256 => {
+            Err(format!("{} has changed since it was first decoded (SHA-256 was {})", file_name, expected))
+        }
+        Some((_, first_decoded_at)) => {
+            conn.execute(
+                "UPDATE audit_dump_digests SET last_decoded_at = ?1, last_decoded_by = ?2, decode_count = decode_count + 1
+                 WHERE file_name = ?3",
+                params![now, user_id, file_name],
+            )
+            .map_err(|e| e.to_string())?;
+            Ok(first_decoded_at)
+        }
+        None => {
+            conn.execute(
+                "INSERT INTO audit_dump_digests (file_name, sha256, first_decoded_at, first_decoded_by, last_decoded_at, last_decoded_by)
+                 VALUES (?1, ?2, ?3, ?4, ?3, ?4)",
+                params![file_name, sha256, now, user_id],
+            )
+            .map_err(|e| e.to_string())?;
+            Ok(now)
+        }
+    }
+}
+
+// decode a dump in `dir` with `key` for `role`
+pub fn decode_in(conn: &Connection, role: &Role, dir: &Path, file_name: &str, key: &str) -> Result<DecodedDump, Box<dyn Error>> {
+    let file_name = file_name.trim();
+    if !role.has_permission(&Permission::DecodeAuditDumps) {
+        tracing::warn!(target: SECURITY_TARGET, user_id = %role.id, file = %file_name, "Legacy audit dump decode denied");
+        return Err("Access denied: DecodeAuditDumps is not granted to this role".into());
+    }
+    let refuse = |reason: String| -> Box<dyn Error> {
+        tracing::warn!(target: SECURITY_TARGET, user_id = %role.id, file = %file_name, reason = %reason, "Legacy audit dump decode refused");
+        reason.into()
+    };
+    if key.is_empty() {
+        return Err(refuse("audit.legacy_dump_key is not set, the dumps cannot be decoded".to_string()));
+    }
+    let path = dump_path(dir, file_name).map_err(refuse)?;
+    let encoded = fs::read(&path).map_err(|e| refuse(format!("cannot read {}: {}", file_name, e)))?;
+    let sha256 = hex::encode(Sha256::digest(&encoded));
+    let content = decode_audit_file(&encoded, key).map_err(refuse)?;
+    let first_decoded_at = check_digest(conn, file_name, &sha256, &role.id).map_err(refuse)?;
+    tracing::info!(target: SECURITY_TARGET, user_id = %role.id, file = %file_name, sha256 = %sha256, "Legacy audit dump decoded");
+    Ok(DecodedDump { file_name: file_name.to_string(), sha256, first_decoded_at, content })
+}
+
+// decode a dump in logging.log_dir with audit.legacy_dump_key
+pub fn decode(conn: &Connection, role: &Role, file_name: &str) -> Result<DecodedDump, Box<dyn Error>> {
+    let settings = config::get();
+    decode_in(conn, role, Path::new(&settings.logging.log_dir), file_name, &settings.audit.legacy_dump_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    const KEY: &str = "legacy-dump-test-key";
+
+    fn encode(text: &str) -> Vec<u8> {
+        text.bytes().zip(KEY.bytes().cycle()).map(|(byte, key)| byte ^ key).collect()
+    }
+
+    #[test]
+    fn admins_decode_complete_dumps_and_changes_are_caught() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let admin = Role::new(&conn, "admin", &fixtures.admin_id);
+        let clinician = Role::new(&conn, "clinician", &fixtures.clinician_id);
+        let dir = std::env::temp_dir().join(format!("glucoguard-dumps-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let name = "system_audit_20240501_120000.txt";
+        fs::write(dir.join(name), encode("GlucoGuard System Audit Log\n[USERS TABLE]\n=\nEnd of audit log\n")).unwrap();
+        fs::write(dir.join("system_audit_20240502_120000.txt"), encode("GlucoGuard System Audit Log\n[USERS TABLE]\n")).unwrap();
+        fs::write(dir.join("notes.txt"), "not a dump").unwrap();
+        assert_eq!(list_dumps(&dir).unwrap(), vec![name, "system_audit_20240502_120000.txt"]);
+
+        assert!(decode_in(&conn, &clinician, &dir, name, KEY).unwrap_err().to_string().starts_with("Access denied"));
+        assert!(decode_in(&conn, &admin, &dir, name, "").is_err());
+        assert!(decode_in(&conn, &admin, &dir, name, "another-key").unwrap_err().to_string().starts_with("wrong key"));
+        assert!(decode_in(&conn, &admin, &dir, "system_audit_20240502_120000.txt", KEY).unwrap_err().to_string().contains("incomplete"));
+        assert!(decode_in(&conn, &admin, &dir, "../notes.txt", KEY).is_err());
+        assert!(decode_in(&conn, &admin, &dir, "notes.txt", KEY).is_err());
+
+        let first = decode_in(&conn, &admin, &dir, name, KEY).unwrap();
+        assert!(first.content.contains("[USERS TABLE]"));
+        let again = decode_in(&conn, &admin, &dir, name, KEY).unwrap();
+        assert_eq!((again.sha256.as_str(), again.first_decoded_at.as_str()), (first.sha256.as_str(), first.first_decoded_at.as_str()));
+
+        // a dump rewritten after it was first decoded is refused even when it decodes
+        fs::write(dir.join(name), encode("GlucoGuard System Audit Log\n[USERS TABLE]\nedited\nEnd of audit log\n")).unwrap();
+        assert!(decode_in(&conn, &admin, &dir, name, KEY).unwrap_err().to_string().contains("has changed"));
+        let count: i64 = conn
+            .query_row("SELECT decode_count FROM audit_dump_digests WHERE file_name = ?1", [name], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
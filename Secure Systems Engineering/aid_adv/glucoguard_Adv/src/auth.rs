@@ -43,11 +43,6 @@ pub fn verify_password(password: &str, hashed_password: &str) -> Result<bool, Pa
     // create Argon2 instance with default parameters
     let argon2 = Argon2::default();
 
-    // Placeholder for missing, NULL passwords
-    if password == "." {
-        return Ok(false);
-    }
-
     // verify the password against the stored hash
     match argon2.verify_password(password.as_bytes(), &parsed_hash) {
         Ok(_) => {
@@ -0,0 +1,284 @@
+// Encrypted, integrity-protected database backups
+// `glucoguard backup [--db <path>] [--out <file>]` and `glucoguard restore --in <file> [--db <path>]`, or the admin menu
+// Archive layout: magic | argon2 salt | nonce | AES-256-GCM(SQLite image), the header is authenticated too
+use crate::config;
+use crate::db::{initialize, migrations};
+use crate::input;
+use crate::logger::SECURITY_TARGET;
+use crate::secrets;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use argon2::Argon2;
+use chrono::Utc;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use rusqlite::backup::Backup;
+use rusqlite::{Connection, MAIN_DB};
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const MAGIC: &[u8; 8] = b"GGBACKv1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = MAGIC.len() + SALT_LEN + NONCE_LEN;
+
+pub const MIN_PASSPHRASE_LENGTH: usize = 12;
+// non-interactive runs read the passphrase from here instead of prompting
+pub const PASSPHRASE_ENV: &str = "GLUCOGUARD_BACKUP_PASSPHRASE";
+pub const EXTENSION: &str = "ggbak";
+
+// pages copied per step while restoring, other connections may run in between
+const RESTORE_PAGES_PER_STEP: i32 = 256;
+const RESTORE_PAUSE: Duration = Duration::from_millis(10);
+
+// Argon2id with the crate defaults, the same KDF family used for passwords
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32], String> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("Key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+fn check_passphrase(passphrase: &str) -> Result<(), String> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LENGTH {
+        return Err(format!("Backup passphrase must be at least {} characters", MIN_PASSPHRASE_LENGTH));
+    }
+    Ok(())
+}
+
+pub fn encrypt(plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>, String> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&salt);
+    header.extend_from_slice(&nonce);
+
+    let cipher = Aes256Gcm::new(&derive_key(passphrase, &salt)?.into());
+    let ciphertext = cipher
+        .encrypt(&Nonce::from(nonce), Payload { msg: plaintext, aad: &header })
+        .map_err(|_| "Encryption failed".to_string())?;
+    header.extend_from_slice(&ciphertext);
+    Ok(header)
+}
+
+// wrong passphrase and any modified byte give the same error
+pub fn decrypt(archive: &[u8], passphrase: &str) -> Result<Vec<u8>, String> {
+    if archive.len() < HEADER_LEN || &archive[..MAGIC.len()] != MAGIC {
+        return Err("Not a GlucoGuard backup file".to_string());
+    }
+    let (header, ciphertext) = archive.split_at(HEADER_LEN);
+    let salt = &header[MAGIC.len()..MAGIC.len() + SALT_LEN];
+    let nonce: [u8; NONCE_LEN] = header[MAGIC.len() + SALT_LEN..].try_into().map_err(|_| "Truncated backup header")?;
+
+    let cipher = Aes256Gcm::new(&derive_key(passphrase, salt)?.into());
+    cipher
+        .decrypt(&Nonce::from(nonce), Payload { msg: ciphertext, aad: header })
+        .map_err(|_| "Wrong passphrase or the backup has been modified".to_string())
+}
+
+// consistent snapshot of the open database, encrypted before it touches the disk
+pub fn create_backup(conn: &Connection, path: &Path, passphrase: &str) -> Result<u64, Box<dyn Error>> {
+    check_passphrase(passphrase)?;
+    let image = conn.serialize(MAIN_DB)?;
+    let archive = encrypt(&image, passphrase)?;
+
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .map_err(|e| format!("Cannot create backup '{}': {}", path.display(), e))?;
+    file.write_all(&archive)?;
+    file.sync_all()?;
+
+    tracing::info!(target: SECURITY_TARGET, path = %path.display(), bytes = archive.len(), "Database backup created");
+    Ok(archive.len() as u64)
+}
+
+// decrypt and check a backup without touching any database, returns its schema version
+fn open_snapshot(archive_path: &Path, passphrase: &str) -> Result<(Connection, u32), Box<dyn Error>> {
+    let archive = fs::read(archive_path).map_err(|e| format!("Cannot read backup '{}': {}", archive_path.display(), e))?;
+    let image = decrypt(&archive, passphrase)?;
+
+    let mut snapshot = Connection::open_in_memory()?;
+    snapshot.deserialize_read_exact(MAIN_DB, image.as_slice(), image.len(), true)?;
+    let integrity: String = snapshot.query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+    if integrity != "ok" {
+        return Err(format!("Backup failed the integrity check: {}", integrity).into());
+    }
+    let version = migrations::current_version(&snapshot).map_err(|_| "Backup does not contain a GlucoGuard database")?;
+    if version > migrations::latest_version() {
+        return Err(format!(
+            "Backup has schema version {}, newer than this build supports ({})",
+            version,
+            migrations::latest_version()
+        )
+        .into());
+    }
+    Ok((snapshot, version))
+}
+
+// replace the database at db_path with the backup's contents, then migrate it to the current schema
+// uses SQLite's online backup, so connections that are already open see the restored data
+pub fn restore_backup(archive_path: &Path, db_path: &str, passphrase: &str) -> Result<u32, Box<dyn Error>> {
+    let (snapshot, version) = open_snapshot(archive_path, passphrase)?;
+
+    let mut target = Connection::open(db_path)?;
+    target.busy_timeout(Duration::from_secs(5))?;
+    Backup::new(&snapshot, &mut target)?.run_to_completion(RESTORE_PAGES_PER_STEP, RESTORE_PAUSE, None)?;
+    initialize::initialize_database(&target)?;
+
+    tracing::warn!(target: SECURITY_TARGET, path = %archive_path.display(), db_path = %db_path, schema_version = version, "Database restored from backup");
+    Ok(version)
+}
+
+// backups/glucoguard-20260101-083000.ggbak
+pub fn default_backup_path() -> PathBuf {
+    Path::new(&config::get().backup.dir).join(format!("glucoguard-{}.{}", Utc::now().format("%Y%m%d-%H%M%S"), EXTENSION))
+}
+
+// GLUCOGUARD_BACKUP_PASSPHRASE, otherwise ask (twice when creating a backup)
+pub fn read_passphrase(confirm: bool) -> Result<String, Box<dyn Error>> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(secrets::resolve_value(&passphrase).map_err(|e| format!("{}: {}", PASSPHRASE_ENV, e))?);
+    }
+    let passphrase = input::read_masked("Backup passphrase: ")?;
+    if confirm && input::read_masked("Repeat passphrase: ")? != passphrase {
+        return Err("Passphrases do not match".into());
+    }
+    Ok(passphrase)
+}
+
+// `glucoguard backup [--db <path>] [--out <file>]`
+pub fn run_backup(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let usage = "usage: glucoguard backup [--db <path>] [--out <file>]";
+    let mut db_path = config::get().database.path.clone();
+    let mut out = None;
+    let mut iter = args.iter();
+    while let Some(flag) = iter.next() {
+        let value = iter.next().ok_or(usage)?;
+        match flag.as_str() {
+            "--db" => db_path = value.clone(),
+            "--out" => out = Some(PathBuf::from(value)),
+            _ => return Err(usage.into()),
+        }
+    }
+    let out = out.unwrap_or_else(default_backup_path);
+
+    let passphrase = read_passphrase(true)?;
+    check_passphrase(&passphrase)?;
+    let conn = initialize::open_database(&db_path)?;
+    let bytes = create_backup(&conn, &out, &passphrase)?;
+    println!("Backup of {} written to {} ({} bytes)", db_path, out.display(), bytes);
+    Ok(())
+}
+
+// `glucoguard restore --in <file> [--db <path>]`
+pub fn run_restore(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let usage = "usage: glucoguard restore --in <file> [--db <path>]";
+    let mut db_path = config::get().database.path.clone();
+    let mut archive = None;
+    let mut iter = args.iter();
+    while let Some(flag) = iter.next() {
+        let value = iter.next().ok_or(usage)?;
+        match flag.as_str() {
+            "--db" => db_path = value.clone(),
+            "--in" => archive = Some(PathBuf::from(value)),
+            _ => return Err(usage.into()),
+        }
+    }
+    let archive = archive.ok_or(usage)?;
+
+    let passphrase = read_passphrase(false)?;
+    let version = restore_backup(&archive, &db_path, &passphrase)?;
+    println!("Restored {} into {} (schema version {})", archive.display(), db_path, version);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::queries;
+    use crate::test_utils;
+
+    const PASSPHRASE: &str = "correct horse battery";
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("glucoguard-backup-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn encrypt_round_trip() {
+        let archive = encrypt(b"sqlite image", PASSPHRASE).unwrap();
+        assert!(!archive.windows(12).any(|window| window == b"sqlite image"));
+        assert_eq!(decrypt(&archive, PASSPHRASE).unwrap(), b"sqlite image");
+    }
+
+    #[test]
+    fn wrong_passphrase_and_tampering_are_detected() {
+        let archive = encrypt(b"sqlite image", PASSPHRASE).unwrap();
+        assert!(decrypt(&archive, "another passphrase").is_err());
+
+        let mut body = archive.clone();
+        let last = body.len() - 1;
+        body[last] ^= 1;
+        assert!(decrypt(&body, PASSPHRASE).is_err());
+
+        // the salt is authenticated as well
+        let mut header = archive.clone();
+        header[MAGIC.len()] ^= 1;
+        assert!(decrypt(&header, PASSPHRASE).is_err());
+
+        assert!(decrypt(b"plain sqlite file", PASSPHRASE).is_err());
+    }
+
+    #[test]
+    fn short_passphrases_are_rejected() {
+        let conn = test_utils::test_db();
+        let dir = temp_dir("short");
+        assert!(create_backup(&conn, &dir.join("short.ggbak"), "short").is_err());
+        assert!(!dir.join("short.ggbak").exists());
+    }
+
+    #[test]
+    fn backup_and_restore_round_trip() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let dir = temp_dir("round-trip");
+        let archive = dir.join("snapshot.ggbak");
+        let db_path = dir.join("restored.db");
+        let db_path = db_path.to_str().unwrap();
+
+        create_backup(&conn, &archive, PASSPHRASE).unwrap();
+        // never overwrites an existing backup
+        assert!(create_backup(&conn, &archive, PASSPHRASE).is_err());
+
+        // the target already has other data, which the restore replaces
+        let target = initialize::open_database(db_path).unwrap();
+        test_utils::add_user(&target, "only.in.target", "admin");
+
+        assert_eq!(restore_backup(&archive, db_path, PASSPHRASE).unwrap(), migrations::latest_version());
+        assert!(queries::get_patient_by_id(&target, &fixtures.patient_id).unwrap().is_some());
+        assert!(!queries::check_user_name_exists(&target, "only.in.target").unwrap());
+
+        assert!(restore_backup(&archive, db_path, "another passphrase").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,2 +1,143 @@
 // Continuous Glucose Monitoring Simulation and parsing
+// Reads simulator output (CSV file or serial device) and stores it in glucose_readings
+use crate::db::models::Patient;
+use rusqlite::{params, Connection};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
 
+pub const DEFAULT_CSV_PATH: &str = "pump_simm/gcm_reader.csv";
+
+// plausible sensor range in mg/dL, anything outside is treated as a sensor fault
+const MIN_GLUCOSE: f64 = 20.0;
+const MAX_GLUCOSE: f64 = 600.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CgmReading {
+    pub patient_id: String,
+    pub glucose_level: f64,
+    pub status: String,
+}
+
+#[derive(Debug, Default)]
+pub struct CgmImportSummary {
+    pub inserted: usize,
+    pub skipped: usize,
+}
+
+pub fn check_glucose_level(glucose_level: f64) -> Result<(), String> {
+    if (MIN_GLUCOSE..=MAX_GLUCOSE).contains(&glucose_level) {
+        Ok(())
+    } else {
+        Err(format!("glucose level {} outside sensor range", glucose_level))
+    }
+}
+
+// low, high or normal from the patient's thresholds, for readings that arrive without a status
+pub fn status_for(patient: &Patient, glucose_level: f64) -> &'static str {
+    if glucose_level < f64::from(patient.low_glucose_threshold) {
+        "low"
+    } else if glucose_level > f64::from(patient.high_glucose_threshold) {
+        "high"
+    } else {
+        "normal"
+    }
+}
+
+// parse one "patient_id,glucose_level,status" line, Ok(None) for blank lines and the header
+pub fn parse_line(line: &str) -> Result<Option<CgmReading>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with("patient_id") {
+        return Ok(None);
+    }
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    if fields.len() < 3 {
+        return Err(format!("expected 3 fields, got {}", fields.len()));
+    }
+    if fields[0].is_empty() {
+        return Err("missing patient_id".to_string());
+    }
+    let glucose_level: f64 = fields[1]
+        .parse()
+        .map_err(|_| format!("invalid glucose level '{}'", fields[1]))?;
+    check_glucose_level(glucose_level)?;
+    Ok(Some(CgmReading {
+        patient_id: fields[0].to_string(),
+        glucose_level,
+        status: fields[2].to_string(),
+    }))
+}
+
+pub fn patient_exists(conn: &Connection, patient_id: &str) -> rusqlite::Result<bool> {
+    conn.prepare_cached("SELECT EXISTS(SELECT 1 FROM patients WHERE patient_id = ?1)")?
+        .query_row([patient_id], |row| row.get(0))
+}
+
+// store a reading stamped with the time it was received
+pub fn insert_reading(conn: &Connection, reading: &CgmReading) -> rusqlite::Result<()> {
+    insert_reading_at(conn, reading, None)
+}
+
+// store a reading with the sensor's own timestamp ("YYYY-MM-DD HH:MM:SS" UTC), or now when None
+pub fn insert_reading_at(conn: &Connection, reading: &CgmReading, reading_time: Option<&str>) -> rusqlite::Result<()> {
+    conn.prepare_cached(
+        "INSERT INTO glucose_readings (patient_id, glucose_level, reading_time, status)
+         VALUES (?1, ?2, COALESCE(?3, datetime('now')), ?4)",
+    )?
+    .execute(params![reading.patient_id, reading.glucose_level, reading_time, reading.status])?;
+    Ok(())
+}
+
+// parse and insert every line from the reader, bad lines and unknown patients are skipped
+fn ingest<R: BufRead>(conn: &Connection, reader: R, source: &str) -> Result<CgmImportSummary, Box<dyn Error>> {
+    let mut summary = CgmImportSummary::default();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let reading = match parse_line(&line) {
+            Ok(Some(reading)) => reading,
+            Ok(None) => continue,
+            Err(e) => {
+                tracing::warn!(source = %source, line = index + 1, "Skipping CGM line: {}", e);
+                summary.skipped += 1;
+                continue;
+            }
+        };
+        if !patient_exists(conn, &reading.patient_id)? {
+            tracing::warn!(source = %source, line = index + 1, patient_id = %reading.patient_id, "Skipping CGM reading for unknown patient");
+            summary.skipped += 1;
+            continue;
+        }
+        insert_reading(conn, &reading)?;
+        summary.inserted += 1;
+    }
+    tracing::info!(source = %source, inserted = summary.inserted, skipped = summary.skipped, "CGM readings imported");
+    Ok(summary)
+}
+
+// one-off import of a simulator CSV export
+pub fn import_file(conn: &Connection, path: &str) -> Result<CgmImportSummary, Box<dyn Error>> {
+    let file = File::open(path).map_err(|e| format!("Cannot open CGM file '{}': {}", path, e))?;
+    ingest(conn, BufReader::new(file), path)
+}
+
+// stream readings from a serial device (or FIFO) until it is closed
+// the line settings are expected to be configured beforehand, e.g. `stty -F /dev/ttyUSB0 9600 raw`
+pub fn read_serial(conn: &Connection, device: &str) -> Result<CgmImportSummary, Box<dyn Error>> {
+    let port = File::open(device).map_err(|e| format!("Cannot open CGM device '{}': {}", device, e))?;
+    ingest(conn, BufReader::new(port), device)
+}
+
+// `glucoguard cgm [--file <path> | --serial <device>]`
+pub fn run(conn: &Connection, args: &[String]) -> Result<(), Box<dyn Error>> {
+    let summary = match args {
+        [] => import_file(conn, DEFAULT_CSV_PATH)?,
+        [flag, path] if flag == "--file" => import_file(conn, path)?,
+        [flag, device] if flag == "--serial" => read_serial(conn, device)?,
+        _ => return Err("usage: glucoguard cgm [--file <path> | --serial <device>]".into()),
+    };
+    println!(
+        "CGM import finished: {} readings stored, {} skipped",
+        summary.inserted, summary.skipped
+    );
+    Ok(())
+}
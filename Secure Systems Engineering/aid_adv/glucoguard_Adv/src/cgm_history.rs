@@ -0,0 +1,268 @@
+/*
+Import of historical CGM exports: a CSV of (timestamp, glucose value) rows for one patient, such as a sensor
+vendor's download, with `glucoguard readings import`.
+A header row is optional; with one, the timestamp and glucose columns are found by name, otherwise they are the
+first two. The unit is the one asked for, else the one the header names, else mmol/L when every value is below
+MMOL_L_CEILING. Times without an offset are local to the importing user. A reading at a time the patient
+already has one is a duplicate and skipped, so a growing export can be imported again. All rows are stored in
+one transaction, or none with a dry run.
+*/
+use crate::cgm::{self, CgmReading};
+use crate::db::models::Patient;
+use crate::timestamps;
+use crate::units::GlucoseUnit;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::error::Error;
+
+// mmol/L exports stay below this, mg/dL exports of a real sensor do not
+const MMOL_L_CEILING: f64 = 35.0;
+// invalid rows listed in the report, the rest are only counted
+const MAX_LISTED_ERRORS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnitSource {
+    // --unit
+    Requested,
+    Header,
+    Values,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HistoryReport {
+    pub rows: usize,
+    pub imported: usize,
+    pub duplicates: usize,
+    pub invalid: usize,
+    pub unit: &'static str,
+    pub unit_source: UnitSource,
+    // stored UTC times of the oldest and newest imported reading
+    pub first_reading: Option<String>,
+    pub last_reading: Option<String>,
+    // "line N: reason" for the first invalid rows
+    pub errors: Vec<String>,
+    pub dry_run: bool,
+}
+
+struct Columns {
+    time: usize,
+    value: usize,
+    unit: Option<GlucoseUnit>,
+}
+
+fn delimiter(line: &str) -> char {
+    [',', ';', '\t'].into_iter().find(|candidate| line.contains(*candidate)).unwrap_or(',')
+}
+
+fn split(line: &str, delimiter: char) -> Vec<String> {
+    line.split(delimiter).map(|field| field.trim().trim_matches('"').trim().to_string()).collect()
+}
+
+// Some(columns) when the line is a header, None when it already holds a reading
+fn header_columns(fields: &[String]) -> Option<Columns> {
+    if fields.get(1).is_some_and(|value| value.parse::<f64>().is_ok()) {
+        return None;
+    }
+    let names: Vec<String> = fields.iter().map(|field| field.to_ascii_lowercase()).collect();
+    let position = |keys: &[&str]| names.iter().position(|name| keys.iter().any(|key| name.contains(key)));
+    let unit = names.iter().find_map(|name| {
+        if name.contains("mmol") {
+            Some(GlucoseUnit::MmolL)
+        } else if name.contains("mg/dl") || name.contains("mgdl") {
+            Some(GlucoseUnit::MgDl)
+        } else {
+            None
+        }
+    });
+    Some(Columns {
+        time: position(&["timestamp", "time", "date"]).unwrap_or(0),
+        value: position(&["glucose", "value", "sgv", "mg/dl", "mmol"]).unwrap_or(1),
+        unit,
+    })
+}
+
+// RFC 3339, or "YYYY-MM-DD HH:MM[:SS]" with a space or a T, local to `zone`
+fn parse_time(text: &str, zone: Tz) -> Option<DateTime<Utc>> {
+    timestamps::parse_local(text, zone).or_else(|| timestamps::parse_local(&text.replacen('T', " ", 1), zone))
+}
+
+fn parse_row(fields: &[String], columns: &Columns, zone: Tz) -> Result<(DateTime<Utc>, f64), String> {
+    let time = fields.get(columns.time).ok_or("missing timestamp")?;
+    let value = fields.get(columns.value).ok_or("missing glucose value")?;
+    let time = parse_time(time, zone).ok_or_else(|| format!("invalid timestamp '{}'", time))?;
+    if time > Utc::now() {
+        return Err(format!("timestamp {} is in the future", timestamps::to_storage(time)));
+    }
+    let value: f64 = value.parse().map_err(|_| format!("invalid glucose value '{}'", value))?;
+    Ok((time, value))
+}
+
+fn duplicate(conn: &Connection, patient_id: &str, reading_time: &str) -> rusqlite::Result<bool> {
+    conn.prepare_cached("SELECT EXISTS (SELECT 1 FROM glucose_readings WHERE patient_id = ?1 AND reading_time = ?2)")?
+        .query_row([patient_id, reading_time], |row| row.get(0))
+}
+
+// import `contents` for the patient; `unit` overrides detection and `zone` applies to times without an offset
+pub fn import_history(
+    conn: &Connection,
+    patient: &Patient,
+    contents: &str,
+    unit: Option<GlucoseUnit>,
+    zone: Tz,
+    dry_run: bool,
+) -> Result<HistoryReport, Box<dyn Error>> {
+    let mut lines = contents
+        .trim_start_matches('\u{feff}')
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .peekable();
+    let Some((_, first)) = lines.peek().copied() else {
+        return Err("the file has no rows".into());
+    };
+    let delimiter = delimiter(first);
+    let columns = match header_columns(&split(first, delimiter)) {
+        Some(columns) => {
+            lines.next();
+            columns
+        }
+        None => Columns { time: 0, value: 1, unit: None },
+    };
+
+    let mut errors = Vec::new();
+    let mut parsed = Vec::new();
+    for (line_number, line) in lines {
+        match parse_row(&split(line, delimiter), &columns, zone) {
+            Ok(row) => parsed.push((line_number, row)),
+            Err(e) => errors.push(format!("line {}: {}", line_number, e)),
+        }
+    }
+    let (unit, unit_source) = match (unit, columns.unit) {
+        (Some(unit), _) => (unit, UnitSource::Requested),
+        (None, Some(unit)) => (unit, UnitSource::Header),
+        (None, None) if !parsed.is_empty() && parsed.iter().all(|(_, (_, value))| *value < MMOL_L_CEILING) => {
+            (GlucoseUnit::MmolL, UnitSource::Values)
+        }
+        (None, None) => (GlucoseUnit::MgDl, UnitSource::Values),
+    };
+
+    let mut report = HistoryReport {
+        rows: parsed.len() + errors.len(),
+        imported: 0,
+        duplicates: 0,
+        invalid: 0,
+        unit: unit.label(),
+        unit_source,
+        first_reading: None,
+        last_reading: None,
+        errors: Vec::new(),
+        dry_run,
+    };
+    let mut imported_times = Vec::new();
+    let tx = conn.unchecked_transaction()?;
+    for (line_number, (time, value)) in parsed {
+        let glucose_level = unit.to_mg_dl(value);
+        if let Err(e) = cgm::check_glucose_level(glucose_level) {
+            errors.push(format!("line {}: {}", line_number, e));
+            continue;
+        }
+        let reading_time = timestamps::to_storage(time);
+        // readings imported earlier in this file count too, they are already in the transaction
+        if duplicate(&tx, &patient.patient_id, &reading_time)? {
+            report.duplicates += 1;
+            continue;
+        }
+        let reading = CgmReading {
+            patient_id: patient.patient_id.clone(),
+            glucose_level,
+            status: cgm::status_for(patient, glucose_level).to_string(),
+        };
+        cgm::insert_reading_at(&tx, &reading, Some(&reading_time))?;
+        imported_times.push(reading_time);
+        report.imported += 1;
+    }
+    if !dry_run {
+        tx.commit()?;
+    }
+
+    imported_times.sort();
+    report.first_reading = imported_times.first().cloned();
+    report.last_reading = imported_times.last().cloned();
+    report.invalid = errors.len();
+    errors.sort_by_key(|error| error.split(':').next().and_then(|line| line.trim_start_matches("line ").parse::<usize>().ok()));
+    errors.truncate(MAX_LISTED_ERRORS);
+    report.errors = errors;
+    tracing::info!(
+        patient_id = %patient.patient_id,
+        imported = report.imported,
+        duplicates = report.duplicates,
+        invalid = report.invalid,
+        unit = report.unit,
+        dry_run,
+        "CGM history imported"
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::queries;
+    use crate::test_utils;
+
+    fn readings(conn: &Connection, patient_id: &str) -> Vec<(String, f64, String)> {
+        let mut stmt = conn
+            .prepare("SELECT reading_time, glucose_level, status FROM glucose_readings WHERE patient_id = ?1 ORDER BY reading_time")
+            .unwrap();
+        stmt.query_map([patient_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn mmol_exports_are_detected_and_reimports_skip_duplicates() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let patient = queries::get_patient_by_id(&conn, &fixtures.patient_id).unwrap().unwrap();
+        let export = "2024-05-01 07:00,5.5\n2024-05-01T07:05:00,3.0\n2024-05-01 07:10:00+02:00,12.5\n2024-05-01 07:15,\nyesterday,6.1\n";
+
+        let report = import_history(&conn, &patient, export, None, Tz::UTC, true).unwrap();
+        assert_eq!((report.rows, report.imported, report.invalid, report.dry_run), (5, 3, 2, true));
+        assert!(readings(&conn, &patient.patient_id).is_empty());
+
+        let report = import_history(&conn, &patient, export, None, Tz::UTC, false).unwrap();
+        assert_eq!((report.unit, report.unit_source), ("mmol/L", UnitSource::Values));
+        assert_eq!(report.errors, vec!["line 4: invalid glucose value ''", "line 5: invalid timestamp 'yesterday'"]);
+        assert_eq!(report.first_reading.as_deref(), Some("2024-05-01 05:10:00"));
+        let stored = readings(&conn, &patient.patient_id);
+        assert_eq!(stored[0], ("2024-05-01 05:10:00".to_string(), 225.0, "high".to_string()));
+        assert_eq!(stored[1], ("2024-05-01 07:00:00".to_string(), 99.0, "normal".to_string()));
+        assert_eq!(stored[2].2, "low");
+
+        let again = import_history(&conn, &patient, export, None, Tz::UTC, false).unwrap();
+        assert_eq!((again.imported, again.duplicates), (0, 3));
+    }
+
+    #[test]
+    fn header_names_the_columns_and_the_unit() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let patient = queries::get_patient_by_id(&conn, &fixtures.patient_id).unwrap().unwrap();
+        let zone = timestamps::parse_zone("Europe/Madrid").unwrap();
+        // all values are low enough to look like mmol/L, but the header says mg/dL
+        let export = "\u{feff}Device;Glucose Value (mg/dL);Timestamp\nG7;30;2024-05-01 09:00:00\nG7;30;2024-05-01 09:00:00\nG7;700;2024-05-01 09:05:00\n";
+        let report = import_history(&conn, &patient, export, None, zone, false).unwrap();
+        assert_eq!((report.unit, report.unit_source), ("mg/dL", UnitSource::Header));
+        assert_eq!((report.rows, report.imported, report.duplicates, report.invalid), (3, 1, 1, 1));
+        assert_eq!(readings(&conn, &patient.patient_id)[0].0, "2024-05-01 07:00:00");
+
+        let report = import_history(&conn, &patient, "2024-05-02 09:00,6\n", Some(GlucoseUnit::MgDl), zone, false);
+        assert!(report.unwrap().errors[0].contains("outside sensor range"));
+        assert!(import_history(&conn, &patient, "\n\n", None, zone, false).is_err());
+    }
+}
@@ -0,0 +1,717 @@
+// Command line of the glucoguard binary
+// Without a subcommand the interactive menus start. `user`, `patients`, `export`, `readings`, `alerts` and
+// `audit-dump` are meant for scripts: they act with an API token (GLUCOGUARD_API_TOKEN or --token) and the
+// permissions and patient scope of its role, like the REST API, and print one JSON document with --json. The
+// older subcommands parse their own options.
+use crate::access_control::{Permission, Resource};
+use crate::alerts;
+use crate::api::{self, DEFAULT_LIMIT, MAX_LIMIT};
+use crate::audit_dump;
+use crate::backup;
+use crate::cgm::{self, CgmReading};
+use crate::cgm_history;
+use crate::db::initialize;
+use crate::db::queries;
+use crate::demo;
+use crate::dose_log;
+use crate::export::{self, DateRange, ExportFormat};
+use crate::health;
+use crate::housekeeping;
+use crate::import;
+use crate::input;
+use crate::insulin;
+use crate::input_validation::is_valid_username_length;
+use crate::logger::SECURITY_TARGET;
+use crate::maintenance;
+use crate::password_strength::check_new_password;
+use crate::timestamps;
+use crate::units::{self, GlucoseUnit};
+use crate::{access_control, mqtt, nightscout, retention, session};
+use chrono::{NaiveDate, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use rusqlite::Connection;
+use serde::Serialize;
+use serde_json::json;
+use std::error::Error;
+use std::io::BufRead;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+#[command(name = "glucoguard", version, about = "GlucoGuard insulin delivery management")]
+pub struct Cli {
+    /// Open the terminal UI instead of the menus (builds with the tui feature)
+    #[arg(long)]
+    pub tui: bool,
+    /// Print results (and errors) of the scripted commands as JSON, e.g. for jq
+    #[arg(long, global = true)]
+    pub json: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Manage user accounts
+    User {
+        #[command(subcommand)]
+        action: UserCommand,
+    },
+    /// List the patients in the token's scope
+    Patients {
+        #[command(subcommand)]
+        action: PatientsCommand,
+    },
+    /// Export one patient's glucose and insulin history into export.dir
+    Export(ExportArgs),
+    /// List or record glucose readings
+    Readings {
+        #[command(subcommand)]
+        action: ReadingsCommand,
+    },
+    /// List a patient's alerts
+    Alerts {
+        #[command(subcommand)]
+        action: AlertsCommand,
+    },
+    /// Issue or revoke short-lived signed maintenance tokens
+    Maintenance {
+        #[command(subcommand)]
+        action: MaintenanceCommand,
+    },
+    /// List or decode the system_audit_* dumps written by older versions
+    AuditDump {
+        #[command(subcommand)]
+        action: AuditDumpCommand,
+    },
+    /// Check the database, schema, disk space and background tasks; exits non-zero when unhealthy
+    Healthcheck {
+        /// Leave out the background task check, for installs that do not run `serve`
+        #[arg(long)]
+        skip_jobs: bool,
+    },
+    /// Check the signature chains of the insulin log; exits non-zero when an entry was changed or removed
+    VerifyDoses {
+        /// Only this patient's doses
+        #[arg(long)]
+        patient: Option<String>,
+    },
+    /// Import CGM readings: [--file <path> | --serial <device>]
+    #[command(disable_help_flag = true)]
+    Cgm(Passthrough),
+    /// Subscribe to CGM readings over MQTT
+    #[command(disable_help_flag = true)]
+    Mqtt(Passthrough),
+    /// Import simulator CSV files: [--db <path>] [--glucose <csv>] [--insulin <csv>] [--pump-status <csv>]
+    #[command(disable_help_flag = true)]
+    Import(Passthrough),
+    /// Start the REST API (and gRPC service) with the background tasks
+    Serve {
+        /// Address to listen on [default: api.bind]
+        #[arg(long)]
+        bind: Option<SocketAddr>,
+    },
+    /// Upload new readings and treatments to Nightscout
+    #[command(disable_help_flag = true)]
+    Nightscout(Passthrough),
+    /// Fill the database with demo accounts and data
+    #[command(disable_help_flag = true)]
+    SeedDemo(Passthrough),
+    /// Write an encrypted backup: [--db <path>] [--out <file>]
+    #[command(disable_help_flag = true)]
+    Backup(Passthrough),
+    /// Restore an encrypted backup: --in <file> [--db <path>]
+    #[command(disable_help_flag = true)]
+    Restore(Passthrough),
+    /// Apply the data retention policy
+    #[command(disable_help_flag = true)]
+    Retention(Passthrough),
+    /// Run database housekeeping now: [checkpoint] [analyze] [vacuum]
+    #[command(disable_help_flag = true)]
+    Housekeeping(Passthrough),
+}
+
+// options of the older subcommands, handed over as typed
+#[derive(Args)]
+pub struct Passthrough {
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    args: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct TokenArg {
+    /// API token of the account to act as (prefer the environment variable, arguments show up in process lists)
+    #[arg(long, env = "GLUCOGUARD_API_TOKEN", hide_env_values = true)]
+    token: String,
+}
+
+#[derive(Subcommand)]
+pub enum UserCommand {
+    /// Create a clinician or caretaker account (CreateClinicianAccount)
+    Create {
+        #[arg(long)]
+        username: String,
+        #[arg(long, value_enum)]
+        role: AccountRole,
+        /// Read the password from the first line of standard input instead of prompting
+        #[arg(long)]
+        password_stdin: bool,
+        #[command(flatten)]
+        auth: TokenArg,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PatientsCommand {
+    /// Patients the token may see (ViewPatient)
+    List {
+        #[command(flatten)]
+        auth: TokenArg,
+    },
+}
+
+#[derive(Args)]
+pub struct PatientList {
+    #[arg(long)]
+    patient: String,
+    /// Most recent records to list, at most 500
+    #[arg(long, default_value_t = DEFAULT_LIMIT, value_parser = clap::value_parser!(u32).range(1..=MAX_LIMIT as i64))]
+    limit: u32,
+    #[command(flatten)]
+    auth: TokenArg,
+}
+
+#[derive(Subcommand)]
+pub enum AlertsCommand {
+    /// A patient's most recent alerts, newest first (ViewAlerts)
+    List(PatientList),
+}
+
+#[derive(Subcommand)]
+pub enum MaintenanceCommand {
+    /// Issue a token granting some of ManageSessions, ManageBackups, ViewAuditLog (ManageSessions)
+    Issue {
+        /// Permission to grant, repeat for more than one; you must hold it yourself
+        #[arg(long = "permission", required = true, value_parser = parse_maintenance_permission)]
+        permissions: Vec<Permission>,
+        /// Why the token is needed, kept with the token record
+        #[arg(long)]
+        reason: String,
+        #[command(flatten)]
+        auth: TokenArg,
+    },
+    /// Revoke a maintenance token before it expires (ManageSessions)
+    Revoke {
+        #[arg(long)]
+        id: String,
+        #[command(flatten)]
+        auth: TokenArg,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum AuditDumpCommand {
+    /// The dump files in logging.log_dir (DecodeAuditDumps)
+    List {
+        #[command(flatten)]
+        auth: TokenArg,
+    },
+    /// Print one dump decoded with audit.legacy_dump_key, after checking it is complete and unchanged (DecodeAuditDumps)
+    Decode {
+        /// File name of the dump, e.g. system_audit_20240501_120000.txt
+        #[arg(long)]
+        file: String,
+        #[command(flatten)]
+        auth: TokenArg,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum AccountRole {
+    Clinician,
+    Caretaker,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Format {
+    Csv,
+    Json,
+    Fhir,
+}
+
+#[derive(Args)]
+pub struct ExportArgs {
+    #[arg(long)]
+    patient: String,
+    #[arg(long, value_enum, default_value = "csv")]
+    format: Format,
+    /// First day to include, YYYY-MM-DD
+    #[arg(long)]
+    from: Option<NaiveDate>,
+    /// Last day to include, YYYY-MM-DD
+    #[arg(long)]
+    to: Option<NaiveDate>,
+    #[command(flatten)]
+    auth: TokenArg,
+}
+
+#[derive(Subcommand)]
+pub enum ReadingsCommand {
+    /// A patient's most recent readings, newest first (ViewGlucose)
+    List(PatientList),
+    /// Record one glucose reading (AddGlucose)
+    Add(NewReading),
+    /// Load a CGM export of timestamp,value rows, skipping readings already stored (AddGlucose)
+    Import(HistoryImport),
+}
+
+#[derive(Args)]
+pub struct NewReading {
+    #[arg(long)]
+    patient: String,
+    /// Glucose level, in --unit
+    #[arg(long)]
+    glucose: f64,
+    /// mg/dL or mmol/L; the token owner's glucose unit when omitted
+    #[arg(long, value_parser = parse_unit)]
+    unit: Option<GlucoseUnit>,
+    /// Reading status; by default low, high or normal from the patient's thresholds
+    #[arg(long)]
+    status: Option<String>,
+    /// Time of the reading, RFC 3339 or "YYYY-MM-DD HH:MM:SS" in the token owner's time zone; now when omitted
+    #[arg(long)]
+    time: Option<String>,
+    #[command(flatten)]
+    auth: TokenArg,
+}
+
+#[derive(Args)]
+pub struct HistoryImport {
+    #[arg(long)]
+    patient: String,
+    /// CSV file; the timestamp and glucose columns are found by a header or are the first two
+    #[arg(long)]
+    file: PathBuf,
+    /// mg/dL or mmol/L; detected from the header or the values when omitted
+    #[arg(long, value_parser = parse_unit)]
+    unit: Option<GlucoseUnit>,
+    /// Check the file and report what would be imported without storing anything
+    #[arg(long)]
+    dry_run: bool,
+    #[command(flatten)]
+    auth: TokenArg,
+}
+
+fn parse_unit(value: &str) -> Result<GlucoseUnit, String> {
+    GlucoseUnit::parse(value).ok_or_else(|| format!("unknown glucose unit '{}', expected mg/dL or mmol/L", value))
+}
+
+fn parse_maintenance_permission(value: &str) -> Result<Permission, String> {
+    Permission::from_name(value).filter(|permission| maintenance::GRANTABLE.contains(permission)).ok_or_else(|| {
+        let names: Vec<&str> = maintenance::GRANTABLE.iter().map(Permission::as_str).collect();
+        format!("'{}' cannot be granted by a maintenance token, expected one of: {}", value, names.join(", "))
+    })
+}
+
+pub fn run(command: Command, json: bool) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::User { action: UserCommand::Create { username, role, password_stdin, auth } } => {
+            let conn = initialize::establish_connection()?;
+            let password = read_new_password(password_stdin)?;
+            create_user(&conn, &auth.token, &username, role, &password)
+        }
+        Command::Patients { action: PatientsCommand::List { auth } } => {
+            let conn = initialize::establish_connection()?;
+            list_patients(&conn, &auth.token, json)
+        }
+        Command::Export(args) => {
+            let conn = initialize::establish_connection()?;
+            export_history(&conn, &args, json)
+        }
+        Command::Readings { action: ReadingsCommand::List(list) } => {
+            let conn = initialize::establish_connection()?;
+            list_readings(&conn, &list, json)
+        }
+        Command::Readings { action: ReadingsCommand::Add(reading) } => {
+            let conn = initialize::establish_connection()?;
+            add_reading(&conn, &reading, json)
+        }
+        Command::Readings { action: ReadingsCommand::Import(history) } => {
+            let conn = initialize::establish_connection()?;
+            import_readings(&conn, &history, json)
+        }
+        Command::Alerts { action: AlertsCommand::List(list) } => {
+            let conn = initialize::establish_connection()?;
+            list_alerts(&conn, &list, json)
+        }
+        Command::Maintenance { action: MaintenanceCommand::Issue { permissions, reason, auth } } => {
+            let conn = initialize::establish_connection()?;
+            issue_maintenance_token(&conn, &auth.token, &permissions, &reason, json)
+        }
+        Command::Maintenance { action: MaintenanceCommand::Revoke { id, auth } } => {
+            let conn = initialize::establish_connection()?;
+            revoke_maintenance_token(&conn, &auth.token, &id)
+        }
+        Command::AuditDump { action: AuditDumpCommand::List { auth } } => {
+            let conn = initialize::establish_connection()?;
+            list_audit_dumps(&conn, &auth.token, json)
+        }
+        Command::AuditDump { action: AuditDumpCommand::Decode { file, auth } } => {
+            let conn = initialize::establish_connection()?;
+            decode_audit_dump(&conn, &auth.token, &file, json)
+        }
+        Command::Healthcheck { skip_jobs } => health::run(skip_jobs, json),
+        Command::VerifyDoses { patient } => dose_log::run(patient.as_deref(), json),
+        Command::Cgm(options) => {
+            let conn = initialize::establish_connection().map_err(|e| format!("Failed to open database: {}", e))?;
+            cgm::run(&conn, &options.args)
+        }
+        Command::Mqtt(options) => mqtt::run(&options.args),
+        Command::Import(options) => import::run(&options.args),
+        Command::Serve { bind } => {
+            // the config is only loaded after the command line is parsed, so its default is applied here
+            let bind = match bind {
+                Some(bind) => bind,
+                None => crate::config::get().api.bind.parse()?,
+            };
+            api::run(bind)
+        }
+        Command::Nightscout(options) => nightscout::run(&options.args),
+        Command::SeedDemo(options) => demo::run(&options.args),
+        Command::Backup(options) => backup::run_backup(&options.args),
+        Command::Restore(options) => backup::run_restore(&options.args),
+        Command::Retention(options) => retention::run(&options.args),
+        Command::Housekeeping(options) => housekeeping::run(&options.args),
+    }
+}
+
+fn caller(conn: &Connection, token: &str) -> Result<api::Caller, Box<dyn Error>> {
+    api::authenticate_credential(conn, token.trim(), session::client_fingerprint()).map_err(|_| "invalid, expired or revoked API token".into())
+}
+
+// the token's role must hold the permission for the patient, and the token must not be limited to other patients
+fn authorize_patient(conn: &Connection, caller: &api::Caller, patient_id: &str, permission: Permission) -> Result<(), Box<dyn Error>> {
+    if caller.allows_patient(patient_id)
+        && access_control::can_access(conn, &caller.role, &Resource::Patient(patient_id), &permission)
+    {
+        return Ok(());
+    }
+    tracing::warn!(target: SECURITY_TARGET, user_id = %caller.role.id, patient_id = %patient_id, permission = ?permission, "CLI access denied");
+    Err(format!("Access denied: {:?} for patient {} is not granted to this token", permission, patient_id).into())
+}
+
+// one JSON document on stdout, or the text lines
+fn print_output<T: Serialize>(json: bool, value: &T, text: impl FnOnce()) -> Result<(), Box<dyn Error>> {
+    if json {
+        println!("{}", serde_json::to_string(value)?);
+    } else {
+        text();
+    }
+    Ok(())
+}
+
+fn list_patients(conn: &Connection, token: &str, json: bool) -> Result<(), Box<dyn Error>> {
+    let caller = caller(conn, token)?;
+    if !caller.role.has_permission(&Permission::ViewPatient) {
+        return Err("Access denied: ViewPatient is not granted to this token".into());
+    }
+    let mut patients = access_control::accessible_patients(conn, &caller.role, &Permission::ViewPatient)?;
+    patients.retain(|patient| caller.allows_patient(&patient.patient_id));
+    print_output(json, &patients, || {
+        for patient in &patients {
+            println!("{}  {} {}  (DOB {})", patient.patient_id, patient.first_name, patient.last_name, patient.date_of_birth);
+        }
+    })
+}
+
+fn list_readings(conn: &Connection, list: &PatientList, json: bool) -> Result<(), Box<dyn Error>> {
+    let caller = caller(conn, &list.auth.token)?;
+    authorize_patient(conn, &caller, &list.patient, Permission::ViewGlucose)?;
+    let readings = insulin::get_recent_glucose_readings(conn, &list.patient, list.limit)?;
+    // JSON keeps the stored mg/dL values
+    let unit = units::user_unit(conn, &caller.role.id)?;
+    let zone = timestamps::user_zone(conn, &caller.role.id)?;
+    print_output(json, &readings, || {
+        for reading in &readings {
+            println!("{}  {}  {}", timestamps::format(&reading.reading_time, zone), unit.format(reading.glucose_level), reading.status);
+        }
+    })
+}
+
+fn list_alerts(conn: &Connection, list: &PatientList, json: bool) -> Result<(), Box<dyn Error>> {
+    let caller = caller(conn, &list.auth.token)?;
+    authorize_patient(conn, &caller, &list.patient, Permission::ViewAlerts)?;
+    let alerts = alerts::get_recent_alerts(conn, &list.patient, list.limit)?;
+    let unit = units::user_unit(conn, &caller.role.id)?;
+    let zone = timestamps::user_zone(conn, &caller.role.id)?;
+    print_output(json, &alerts, || {
+        for alert in &alerts {
+            let state = if alert.is_resolved { "resolved" } else { "open" };
+            println!("{}  {}  {}  {}", timestamps::format(&alert.alert_time, zone), alert.alert_type, state, unit.localize(&alert.alert_message));
+        }
+    })
+}
+
+fn read_new_password(from_stdin: bool) -> Result<String, Box<dyn Error>> {
+    if from_stdin {
+        let mut line = String::new();
+        std::io::stdin().lock().read_line(&mut line)?;
+        // trimmed like the password typed at login
+        return Ok(line.trim().to_string());
+    }
+    let password = input::read_masked("Password for the new account: ")?;
+    if input::read_masked("Confirm the password: ")? != password {
+        return Err("passwords do not match".into());
+    }
+    Ok(password.trim().to_string())
+}
+
+fn create_user(conn: &Connection, token: &str, username: &str, role: AccountRole, password: &str) -> Result<(), Box<dyn Error>> {
+    let caller = caller(conn, token)?;
+    if !caller.role.has_permission(&Permission::CreateClinicianAccount) {
+        tracing::warn!(target: SECURITY_TARGET, user_id = %caller.role.id, "CLI access denied");
+        return Err("Access denied: CreateClinicianAccount is not granted to this token".into());
+    }
+    let username = username.trim();
+    if !is_valid_username_length(username) {
+        return Err(format!("username must be 1 to {} characters", crate::input_validation::MAX_USERNAME_LENGTH).into());
+    }
+    check_new_password(password, &[username])?;
+    if queries::check_user_name_exists(conn, username)? {
+        return Err(format!("username '{}' is already taken", username).into());
+    }
+    let role = match role {
+        AccountRole::Clinician => "clinician",
+        AccountRole::Caretaker => "caretaker",
+    };
+    queries::create_user(conn, username, password, role, None)?;
+    tracing::info!(target: SECURITY_TARGET, created_by = %caller.role.id, username = %username, role = %role, "Account created from the command line");
+    Ok(())
+}
+
+// the issuer's role must hold ManageSessions to hand out maintenance tokens at all
+fn maintenance_issuer(conn: &Connection, token: &str) -> Result<api::Caller, Box<dyn Error>> {
+    let caller = caller(conn, token)?;
+    if !caller.role.has_permission(&Permission::ManageSessions) {
+        tracing::warn!(target: SECURITY_TARGET, user_id = %caller.role.id, "CLI access denied");
+        return Err("Access denied: ManageSessions is not granted to this token".into());
+    }
+    Ok(caller)
+}
+
+fn issue_maintenance_token(conn: &Connection, token: &str, permissions: &[Permission], reason: &str, json: bool) -> Result<(), Box<dyn Error>> {
+    let caller = maintenance_issuer(conn, token)?;
+    let (token_id, maintenance_token) = maintenance::issue(conn, &caller.role, permissions, reason)?;
+    let minutes = crate::config::get().maintenance.token_ttl_minutes;
+    let result = json!({ "token_id": token_id, "token": maintenance_token, "expires_in_minutes": minutes });
+    print_output(json, &result, || {
+        println!("{}", maintenance_token);
+        eprintln!("Maintenance token {} issued, valid for {} minutes. It is not shown again.", token_id, minutes);
+    })
+}
+
+fn revoke_maintenance_token(conn: &Connection, token: &str, token_id: &str) -> Result<(), Box<dyn Error>> {
+    let caller = maintenance_issuer(conn, token)?;
+    if !maintenance::revoke(conn, token_id.trim(), &caller.role.id)? {
+        return Err(format!("no active maintenance token with id {}", token_id).into());
+    }
+    eprintln!("Maintenance token {} revoked.", token_id);
+    Ok(())
+}
+
+fn dump_reader(conn: &Connection, token: &str) -> Result<api::Caller, Box<dyn Error>> {
+    let caller = caller(conn, token)?;
+    if !caller.role.has_permission(&Permission::DecodeAuditDumps) {
+        tracing::warn!(target: SECURITY_TARGET, user_id = %caller.role.id, "CLI access denied");
+        return Err("Access denied: DecodeAuditDumps is not granted to this token".into());
+    }
+    Ok(caller)
+}
+
+fn list_audit_dumps(conn: &Connection, token: &str, json: bool) -> Result<(), Box<dyn Error>> {
+    dump_reader(conn, token)?;
+    let dumps = audit_dump::list_dumps(Path::new(&crate::config::get().logging.log_dir))?;
+    print_output(json, &dumps, || {
+        for name in &dumps {
+            println!("{}", name);
+        }
+    })
+}
+
+// the permission is checked again, and the decode logged, by audit_dump::decode
+fn decode_audit_dump(conn: &Connection, token: &str, file: &str, json: bool) -> Result<(), Box<dyn Error>> {
+    let caller = dump_reader(conn, token)?;
+    let dump = audit_dump::decode(conn, &caller.role, file)?;
+    print_output(json, &dump, || {
+        eprintln!("{}: SHA-256 {}, first decoded {}", dump.file_name, dump.sha256, dump.first_decoded_at);
+        print!("{}", dump.content);
+    })
+}
+
+fn export_history(conn: &Connection, args: &ExportArgs, json: bool) -> Result<(), Box<dyn Error>> {
+    let caller = caller(conn, &args.auth.token)?;
+    if !caller.allows_patient(&args.patient) {
+        return Err(format!("Access denied: this token is limited to other patients than {}", args.patient).into());
+    }
+    let format = match args.format {
+        Format::Csv => ExportFormat::Csv,
+        Format::Json => ExportFormat::Json,
+        Format::Fhir => ExportFormat::Fhir,
+    };
+    let range = DateRange { from: args.from, to: args.to };
+    let summary = export::export_patient_history(conn, &caller.role, &args.patient, &range, format)?;
+    let result = json!({
+        "path": summary.path,
+        "glucose_readings": summary.glucose_count,
+        "insulin_doses": summary.insulin_count,
+    });
+    print_output(json, &result, || {
+        println!(
+            "{} ({} glucose readings, {} insulin doses)",
+            summary.path.display(),
+            summary.glucose_count,
+            summary.insulin_count
+        )
+    })
+}
+
+// the level is stored in mg/dL whatever unit it was given in
+fn add_reading(conn: &Connection, new: &NewReading, json: bool) -> Result<(), Box<dyn Error>> {
+    let caller = caller(conn, &new.auth.token)?;
+    let patient_id = new.patient.as_str();
+    authorize_patient(conn, &caller, patient_id, Permission::AddGlucose)?;
+    let unit = match new.unit {
+        Some(unit) => unit,
+        None => units::user_unit(conn, &caller.role.id)?,
+    };
+    let glucose = unit.to_mg_dl(new.glucose);
+    cgm::check_glucose_level(glucose)?;
+    let patient = queries::get_patient_by_id(conn, patient_id)?.ok_or("no patient record found")?;
+    let reading_time = match &new.time {
+        Some(time) => {
+            let zone = timestamps::user_zone(conn, &caller.role.id)?;
+            let parsed = timestamps::parse_local(time, zone)
+                .ok_or_else(|| format!("invalid time '{}', expected RFC 3339 or YYYY-MM-DD HH:MM:SS ({})", time, zone.name()))?;
+            if parsed > Utc::now() {
+                return Err("the reading time is in the future".into());
+            }
+            Some(timestamps::to_storage(parsed))
+        }
+        None => None,
+    };
+    let status = match new.status.as_deref().map(str::trim) {
+        Some("") => return Err("status cannot be empty".into()),
+        Some(status) => status.to_string(),
+        None => cgm::status_for(&patient, glucose).to_string(),
+    };
+    let reading = CgmReading { patient_id: patient_id.to_string(), glucose_level: glucose, status };
+    cgm::insert_reading_at(conn, &reading, reading_time.as_deref())?;
+    tracing::info!(target: SECURITY_TARGET, user_id = %caller.role.id, patient_id = %patient_id, glucose_level = glucose, "Glucose reading added from the command line");
+    let result = json!({
+        "reading_id": conn.last_insert_rowid(),
+        "patient_id": patient_id,
+        "glucose_level": glucose,
+        "status": reading.status,
+    });
+    print_output(json, &result, || println!("Reading of {} ({}) stored for {}", unit.format(glucose), reading.status, patient_id))
+}
+
+// times without an offset are local to the token owner
+fn import_readings(conn: &Connection, history: &HistoryImport, json: bool) -> Result<(), Box<dyn Error>> {
+    let caller = caller(conn, &history.auth.token)?;
+    authorize_patient(conn, &caller, &history.patient, Permission::AddGlucose)?;
+    let patient = queries::get_patient_by_id(conn, &history.patient)?.ok_or("no patient record found")?;
+    let contents = std::fs::read_to_string(&history.file).map_err(|e| format!("cannot read {}: {}", history.file.display(), e))?;
+    let zone = timestamps::user_zone(conn, &caller.role.id)?;
+    let report = cgm_history::import_history(conn, &patient, &contents, history.unit, zone, history.dry_run)?;
+    if !history.dry_run {
+        tracing::info!(target: SECURITY_TARGET, user_id = %caller.role.id, patient_id = %patient.patient_id, imported = report.imported, "CGM history imported from the command line");
+    }
+    print_output(json, &report, || {
+        let verb = if report.dry_run { "would be imported" } else { "imported" };
+        println!(
+            "{} of {} rows {} for {} ({}, {:?}); {} duplicates skipped, {} invalid",
+            report.imported, report.rows, verb, patient.patient_id, report.unit, report.unit_source, report.duplicates, report.invalid
+        );
+        if let (Some(first), Some(last)) = (&report.first_reading, &report.last_reading) {
+            println!("Readings from {} to {}", timestamps::format(first, zone), timestamps::format(last, zone));
+        }
+        for error in &report.errors {
+            println!("  {}", error);
+        }
+        if report.invalid > report.errors.len() {
+            println!("  ... and {} more", report.invalid - report.errors.len());
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_token;
+    use crate::test_utils;
+    use clap::CommandFactory;
+
+    fn new_reading(token: &str, patient_id: &str, glucose: f64, unit: Option<GlucoseUnit>, time: Option<&str>) -> NewReading {
+        NewReading {
+            patient: patient_id.to_string(),
+            glucose,
+            unit,
+            status: None,
+            time: time.map(str::to_string),
+            auth: TokenArg { token: token.to_string() },
+        }
+    }
+
+    #[test]
+    fn command_line_parses_and_tokens_are_scoped() {
+        Cli::command().debug_assert();
+        let cli = Cli::try_parse_from(["glucoguard", "readings", "add", "--patient", "p1", "--glucose", "120", "--token", "ggt_x"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Readings { .. })));
+        let cli = Cli::try_parse_from(["glucoguard", "readings", "import", "--patient", "p1", "--file", "g7.csv", "--unit", "mmol/L", "--dry-run", "--token", "ggt_x"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Readings { action: ReadingsCommand::Import(HistoryImport { dry_run: true, .. }) })));
+        let cli = Cli::try_parse_from(["glucoguard", "audit-dump", "decode", "--file", "system_audit_1.txt", "--token", "ggt_x"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::AuditDump { action: AuditDumpCommand::Decode { .. } })));
+        let cli = Cli::try_parse_from(["glucoguard", "import", "--glucose", "a.csv"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Import(Passthrough { ref args })) if args == &["--glucose", "a.csv"]));
+        assert!(Cli::try_parse_from(["glucoguard", "export", "--patient", "p1", "--from", "2024-13-01", "--token", "x"]).is_err());
+        let cli = Cli::try_parse_from(["glucoguard", "serve", "--bind", "0.0.0.0:9000"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Serve { bind: Some(bind) }) if bind.port() == 9000));
+        assert!(Cli::try_parse_from(["glucoguard", "serve", "--bind", "localhost"]).is_err());
+
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let (_, clinician_token) = api_token::issue_token(&conn, &fixtures.clinician_id, "clinician", None, "scripts", None, &fixtures.admin_id).unwrap();
+        let (_, caretaker_token) = api_token::issue_token(&conn, &fixtures.caretaker_id, "caretaker", None, "scripts", None, &fixtures.admin_id).unwrap();
+
+        assert!(add_reading(&conn, &new_reading("ggt_not_a_token", &fixtures.patient_id, 120.0, None, None), false).is_err());
+        // clinicians read glucose data but do not record it; caretakers only for the patients they look after
+        assert!(add_reading(&conn, &new_reading(&clinician_token, &fixtures.patient_id, 120.0, None, None), false).is_err());
+        assert!(add_reading(&conn, &new_reading(&caretaker_token, &fixtures.other_patient_id, 120.0, None, None), false).is_err());
+        let future = new_reading(&caretaker_token, &fixtures.patient_id, 120.0, None, Some("2999-01-01 00:00:00"));
+        assert!(add_reading(&conn, &future, false).is_err());
+        add_reading(&conn, &new_reading(&caretaker_token, &fixtures.patient_id, 62.0, None, Some("2024-05-01 07:30:00")), false).unwrap();
+        // a caretaker who reads mmol/L enters mmol/L, an explicit --unit wins; both are stored in mg/dL
+        units::set_user_unit(&conn, &fixtures.caretaker_id, GlucoseUnit::MmolL).unwrap();
+        add_reading(&conn, &new_reading(&caretaker_token, &fixtures.patient_id, 11.0, None, Some("2024-05-01 08:30:00")), false).unwrap();
+        let explicit = new_reading(&caretaker_token, &fixtures.patient_id, 100.0, Some(GlucoseUnit::MgDl), Some("2024-05-01 09:30:00"));
+        add_reading(&conn, &explicit, false).unwrap();
+        // times without an offset are in the caretaker's zone and stored in UTC
+        timestamps::set_user_zone(&conn, &fixtures.caretaker_id, timestamps::parse_zone("Europe/Madrid")).unwrap();
+        add_reading(&conn, &new_reading(&caretaker_token, &fixtures.patient_id, 5.5, None, Some("2024-05-01 12:30:00")), false).unwrap();
+        let mut stmt = conn
+            .prepare("SELECT glucose_level, status, reading_time FROM glucose_readings WHERE patient_id = ?1 ORDER BY reading_time")
+            .unwrap();
+        let stored: Vec<(f64, String, String)> = stmt
+            .query_map([&fixtures.patient_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(stored[0], (62.0, "low".to_string(), "2024-05-01 07:30:00".to_string()));
+        assert_eq!((stored[1].0, stored[1].1.as_str()), (198.0, "high"));
+        assert_eq!(stored[2].0, 100.0);
+        assert_eq!(stored[3].2, "2024-05-01 10:30:00");
+
+        assert!(create_user(&conn, &caretaker_token, "new.carer", AccountRole::Caretaker, "Another-Strong-Pass-1").is_err());
+    }
+}
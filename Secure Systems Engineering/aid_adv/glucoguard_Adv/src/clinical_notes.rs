@@ -0,0 +1,207 @@
+/*
+Clinical notes on patient records.
+The care team of a patient (the patient's clinician and assigned caretakers, by patient scope) reads the notes
+with ViewClinicalNotes; clinicians add notes with WriteClinicalNotes and may edit the ones they wrote. A note is
+never overwritten without a trace: an edit stores the text it replaces in clinical_note_revisions, so the
+history of a note shows every earlier version with who wrote it and when. Notes go with the patient on erasure.
+*/
+use crate::access_control::{self, Permission, Resource, Role};
+use crate::logger::SECURITY_TARGET;
+use crate::timestamps;
+use rusqlite::{params, Connection, OptionalExtension};
+
+pub const MAX_NOTE_LENGTH: usize = 500;
+
+#[derive(Debug, Clone)]
+pub struct ClinicalNote {
+    pub note_id: i64,
+    pub patient_id: String,
+    pub author_id: String,
+    // username, or the user id of an account that is gone
+    pub author: String,
+    pub body: String,
+    pub created_at: String,
+    pub revision: i64,
+    pub updated_at: Option<String>,
+    pub updated_by: Option<String>,
+}
+
+// an earlier text of a note
+#[derive(Debug, Clone)]
+pub struct NoteRevision {
+    pub revision: i64,
+    pub body: String,
+    pub written_at: String,
+    pub written_by: String,
+}
+
+fn check_body(body: &str) -> Result<&str, String> {
+    let body = body.trim();
+    if body.is_empty() || body.chars().count() > MAX_NOTE_LENGTH {
+        return Err(format!("a note of 1 to {} characters is required", MAX_NOTE_LENGTH));
+    }
+    Ok(body)
+}
+
+fn check_access(conn: &Connection, role: &Role, patient_id: &str, action: Permission) -> Result<(), String> {
+    if !access_control::can_access(conn, role, &Resource::Patient(patient_id), &action) {
+        return Err(format!("Access denied: patient is not in your care or {} is missing", action.as_str()));
+    }
+    Ok(())
+}
+
+fn load_note(conn: &Connection, note_id: i64) -> rusqlite::Result<Option<ClinicalNote>> {
+    conn.query_row(
+        "SELECT n.note_id, n.patient_id, n.author_id, COALESCE(a.user_name, n.author_id), n.body, n.created_at, n.revision,
+                n.updated_at, COALESCE(e.user_name, n.updated_by)
+         FROM clinical_notes n
+         LEFT JOIN users a ON a.id = n.author_id
+         LEFT JOIN users e ON e.id = n.updated_by
+         WHERE n.note_id = ?1",
+        [note_id],
+        note_from_row,
+    )
+    .optional()
+}
+
+fn note_from_row(row: &rusqlite::Row) -> rusqlite::Result<ClinicalNote> {
+    Ok(ClinicalNote {
+        note_id: row.get(0)?,
+        patient_id: row.get(1)?,
+        author_id: row.get(2)?,
+        author: row.get(3)?,
+        body: row.get(4)?,
+        created_at: row.get(5)?,
+        revision: row.get(6)?,
+        updated_at: row.get(7)?,
+        updated_by: row.get(8)?,
+    })
+}
+
+// attach a note to the patient record; returns the note id
+pub fn add_note(conn: &Connection, role: &Role, patient_id: &str, body: &str) -> Result<i64, String> {
+    check_access(conn, role, patient_id, Permission::WriteClinicalNotes)?;
+    let body = check_body(body)?;
+    conn.execute(
+        "INSERT INTO clinical_notes (patient_id, author_id, body, created_at) VALUES (?1, ?2, ?3, ?4)",
+        params![patient_id, role.id, body, timestamps::now()],
+    )
+    .map_err(|e| e.to_string())?;
+    let note_id = conn.last_insert_rowid();
+    tracing::info!(target: SECURITY_TARGET, user_id = %role.id, patient_id = %patient_id, note_id, "Clinical note added");
+    Ok(note_id)
+}
+
+// replace the text of a note the user wrote, keeping the old text as a revision
+pub fn edit_note(conn: &Connection, role: &Role, note_id: i64, body: &str) -> Result<(), String> {
+    let note = load_note(conn, note_id).map_err(|e| e.to_string())?.ok_or("no such note")?;
+    check_access(conn, role, &note.patient_id, Permission::WriteClinicalNotes)?;
+    if note.author_id != role.id {
+        tracing::warn!(target: SECURITY_TARGET, user_id = %role.id, note_id, "Clinical note edit denied");
+        return Err("only the author of a note can edit it".to_string());
+    }
+    let body = check_body(body)?;
+    if body == note.body {
+        return Err("the note is unchanged".to_string());
+    }
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    // the replaced text was written when the note was created or last edited
+    tx.execute(
+        "INSERT INTO clinical_note_revisions (note_id, revision, patient_id, body, written_at, written_by)
+         SELECT note_id, revision, patient_id, body, COALESCE(updated_at, created_at), COALESCE(updated_by, author_id)
+         FROM clinical_notes WHERE note_id = ?1",
+        [note_id],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.execute(
+        "UPDATE clinical_notes SET body = ?2, revision = revision + 1, updated_at = ?3, updated_by = ?4 WHERE note_id = ?1",
+        params![note_id, body, timestamps::now(), role.id],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    tracing::info!(target: SECURITY_TARGET, user_id = %role.id, patient_id = %note.patient_id, note_id, revision = note.revision + 1, "Clinical note edited");
+    Ok(())
+}
+
+// notes on the patient record, newest first
+pub fn notes_for_patient(conn: &Connection, role: &Role, patient_id: &str) -> Result<Vec<ClinicalNote>, String> {
+    check_access(conn, role, patient_id, Permission::ViewClinicalNotes)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT n.note_id, n.patient_id, n.author_id, COALESCE(a.user_name, n.author_id), n.body, n.created_at, n.revision,
+                    n.updated_at, COALESCE(e.user_name, n.updated_by)
+             FROM clinical_notes n
+             LEFT JOIN users a ON a.id = n.author_id
+             LEFT JOIN users e ON e.id = n.updated_by
+             WHERE n.patient_id = ?1
+             ORDER BY n.created_at DESC, n.note_id DESC",
+        )
+        .map_err(|e| e.to_string())?;
+    let notes = stmt.query_map([patient_id], note_from_row).and_then(|rows| rows.collect()).map_err(|e| e.to_string())?;
+    Ok(notes)
+}
+
+// earlier texts of a note, oldest first; the current text is the note itself
+pub fn note_history(conn: &Connection, role: &Role, note_id: i64) -> Result<Vec<NoteRevision>, String> {
+    let note = load_note(conn, note_id).map_err(|e| e.to_string())?.ok_or("no such note")?;
+    check_access(conn, role, &note.patient_id, Permission::ViewClinicalNotes)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT r.revision, r.body, r.written_at, COALESCE(u.user_name, r.written_by)
+             FROM clinical_note_revisions r
+             LEFT JOIN users u ON u.id = r.written_by
+             WHERE r.note_id = ?1
+             ORDER BY r.revision",
+        )
+        .map_err(|e| e.to_string())?;
+    let revisions = stmt
+        .query_map([note_id], |row| {
+            Ok(NoteRevision { revision: row.get(0)?, body: row.get(1)?, written_at: row.get(2)?, written_by: row.get(3)? })
+        })
+        .and_then(|rows| rows.collect())
+        .map_err(|e| e.to_string())?;
+    Ok(revisions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    #[test]
+    fn care_team_reads_notes_and_edits_keep_the_history() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let clinician = Role::new(&conn, "clinician", &fixtures.clinician_id);
+        let other = Role::new(&conn, "clinician", &fixtures.other_clinician_id);
+        let caretaker = Role::new(&conn, "caretaker", &fixtures.caretaker_id);
+        let patient = Role::new(&conn, "patient", &fixtures.patient_id);
+
+        assert!(add_note(&conn, &other, &fixtures.patient_id, "not my patient").is_err());
+        assert!(add_note(&conn, &caretaker, &fixtures.patient_id, "caretakers only read").is_err());
+        assert!(add_note(&conn, &clinician, &fixtures.patient_id, "  ").is_err());
+        let note_id = add_note(&conn, &clinician, &fixtures.patient_id, "Night lows after evening exercise.").unwrap();
+
+        edit_note(&conn, &clinician, note_id, "Night lows after evening exercise; basal lowered 10%.").unwrap();
+        edit_note(&conn, &clinician, note_id, "Night lows after evening exercise; basal lowered 20%.").unwrap();
+        assert!(edit_note(&conn, &clinician, note_id, "Night lows after evening exercise; basal lowered 20%.").is_err());
+        assert!(edit_note(&conn, &other, note_id, "rewritten").is_err());
+
+        let notes = notes_for_patient(&conn, &caretaker, &fixtures.patient_id).unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!((notes[0].author.as_str(), notes[0].revision), ("dr.fixture", 3));
+        assert!(notes[0].body.ends_with("20%."));
+        assert_eq!(notes[0].updated_by.as_deref(), Some("dr.fixture"));
+
+        let history = note_history(&conn, &caretaker, note_id).unwrap();
+        assert_eq!(history.iter().map(|r| r.revision).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(history[0].body, "Night lows after evening exercise.");
+        assert_eq!(history[0].written_at, notes[0].created_at);
+        assert!(history[1].body.ends_with("10%."));
+
+        // outside the care team
+        assert!(notes_for_patient(&conn, &other, &fixtures.patient_id).is_err());
+        assert!(notes_for_patient(&conn, &patient, &fixtures.patient_id).is_err());
+        assert!(note_history(&conn, &other, note_id).is_err());
+    }
+}
@@ -0,0 +1,952 @@
+// Runtime configuration loaded from glucoguard.toml
+// Values can be overridden with GLUCOGUARD_* environment variables
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+pub const DEFAULT_CONFIG_PATH: &str = "./glucoguard.toml";
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub database: DatabaseConfig,
+    pub session: SessionConfig,
+    pub passwords: PasswordConfig,
+    pub logging: LoggingConfig,
+    pub export: ExportConfig,
+    pub backup: BackupConfig,
+    pub retention: RetentionConfig,
+    pub housekeeping: HousekeepingConfig,
+    pub audit: AuditConfig,
+    pub activation: ActivationConfig,
+    pub devices: DevicesConfig,
+    pub maintenance: MaintenanceConfig,
+    pub dosing: DosingConfig,
+    pub alerts: AlertsConfig,
+    pub monitoring: MonitoringConfig,
+    pub health: HealthConfig,
+    pub reports: ReportsConfig,
+    pub email: EmailConfig,
+    pub api: ApiConfig,
+    pub mqtt: MqttConfig,
+    pub nightscout: NightscoutConfig,
+    pub ui: UiConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub path: String,
+    // connections shared by the menus, background threads and the API server
+    pub pool_size: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    // session lifetime in seconds
+    pub ttl_secs: u64,
+    // how often the background thread deactivates expired sessions
+    pub cleanup_interval_secs: u64,
+    // maximum concurrent active sessions per user, 0 = unlimited
+    pub max_active_sessions: u32,
+    // per-role overrides of max_active_sessions, e.g. admin = 1
+    pub role_session_limits: HashMap<String, u32>,
+    // "reject" the new login or "evict_oldest" active session when the limit is reached
+    pub limit_policy: String,
+    // how long a valid session is served from memory before the row is read again, 0 = no cache
+    pub cache_ttl_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasswordConfig {
+    // lowest strength estimate (0 - 4) a new password may have, on top of the character rules
+    pub min_score: u8,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    pub log_dir: String,
+    // tracing filter directives, e.g. "info" or "info,glucoguard::session=debug"
+    pub level: String,
+    // "text" or "json"
+    pub format: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExportConfig {
+    // directory where CSV/JSON exports are written
+    pub dir: String,
+    // secret for the keyed hash that turns patient ids into research pseudonyms, empty = research export disabled
+    pub research_key: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditConfig {
+    // HMAC key for signed audit records such as patient erasures, empty = those actions are disabled
+    pub signing_key: String,
+    // key of the system_audit_* dumps written by older versions, empty = they cannot be decoded
+    pub legacy_dump_key: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ActivationConfig {
+    // hours an activation code can be redeemed after it is issued
+    pub code_ttl_hours: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DevicesConfig {
+    // minutes a device pairing code can be used after the device is registered
+    pub pairing_code_ttl_minutes: u32,
+    // how far a device's signed_at_ms may differ from the server clock, in seconds
+    pub max_clock_skew_secs: u32,
+    // hex Ed25519 private key (32 bytes) that signs pump commands, empty = pump commands disabled
+    pub command_signing_key: String,
+    // a pump report at or below these raises a LOW_RESERVOIR / LOW_BATTERY alert
+    pub low_reservoir_units: u32,
+    pub low_battery_percent: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MaintenanceConfig {
+    // hex Ed25519 private key (32 bytes) that signs maintenance tokens, empty = maintenance tokens disabled
+    pub signing_key: String,
+    // minutes a maintenance token is valid after it is issued
+    pub token_ttl_minutes: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DosingConfig {
+    // a bolus is refused when the patient's latest glucose reading is older than this, in minutes
+    pub max_reading_age_minutes: u32,
+    // temporary basal limits for roles without EditPatientData (patients), percent of the basal rate
+    pub temp_basal_patient_min_percent: u32,
+    pub temp_basal_patient_max_percent: u32,
+    pub temp_basal_patient_max_minutes: u32,
+    // longest extended / dual-wave bolus, and the interval its extended portion is scheduled in, in minutes
+    pub extended_bolus_max_minutes: u32,
+    pub extended_bolus_step_minutes: u32,
+    // suspend basal delivery from the session cleanup task while a low is predicted
+    pub low_suspend: bool,
+    // basal resumes once the latest reading is this far above the low threshold, in mg/dL
+    pub low_suspend_resume_margin: u32,
+    // longest automatic suspension, basal resumes after it whatever the readings
+    pub low_suspend_max_minutes: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlertsConfig {
+    // an unacknowledged critical alert goes to the caretakers after this many minutes, to the clinician after twice as many
+    pub escalation_minutes: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MonitoringConfig {
+    // scan the audit trail for suspicious patterns from the session cleanup task
+    pub enabled: bool,
+    // failed logins for one username within the window that raise an alert
+    pub failed_login_limit: u32,
+    pub failed_login_window_minutes: u32,
+    // office hours in ui.timezone, [start, end); a start after the end spans midnight
+    pub office_hours_start: u32,
+    pub office_hours_end: u32,
+    // audit actions that raise an alert outside office hours; admin logins always do
+    pub privileged_actions: Vec<String>,
+    // exports by one user within the window that raise an alert
+    pub export_limit: u32,
+    pub export_window_minutes: u32,
+    // audit actions counted as exports
+    pub export_actions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthConfig {
+    // `healthcheck` fails when the file system holding the database has less space free, in MB
+    pub min_free_disk_mb: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReportsConfig {
+    // generate a summary of the previous day for every patient from the session cleanup task
+    pub daily_summary: bool,
+    // UTC hour from which the previous day's summaries are generated
+    pub daily_summary_hour: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UiConfig {
+    // language of the interactive menus, "en" or "es"
+    pub locale: String,
+    // IANA time zone times are shown in to users who have not chosen their own, e.g. "Europe/Madrid"
+    pub timezone: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmailConfig {
+    // email activation codes instead of only printing them
+    pub enabled: bool,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    // "starttls", "tls" (implicit TLS) or "none" (local relays only)
+    pub security: String,
+    // empty = no SMTP authentication
+    pub username: String,
+    pub password: String,
+    pub from: String,
+    // text file with {{name}}, {{code}}, {{account_type}} and {{ttl_hours}} placeholders, empty = built-in template
+    pub activation_template: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackupConfig {
+    // directory where encrypted database backups are written
+    pub dir: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    // run the policy from the session cleanup task (hourly)
+    pub enabled: bool,
+    // only log what would be removed
+    pub dry_run: bool,
+    // "delete" or "archive" (rows go to archive_path, files to an archive/ subdirectory)
+    pub action: String,
+    // SQLite file receiving archived rows
+    pub archive_path: String,
+    // ages in days, 0 = keep forever
+    pub glucose_readings_days: u32,
+    // only inactive or expired sessions are ever removed
+    pub sessions_days: u32,
+    // system_audit_* dumps in logging.log_dir and files in export.dir
+    pub audit_exports_days: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HousekeepingConfig {
+    // run the tasks below from the session cleanup task
+    pub enabled: bool,
+    // copy the write-ahead log into the database and truncate it, only in WAL mode; 0 = never
+    pub checkpoint_interval_minutes: u32,
+    // refresh the query planner statistics; 0 = never
+    pub analyze_interval_hours: u32,
+    // rebuild the database file to return free pages to the disk, blocks writers while it runs; 0 = never
+    pub vacuum_interval_hours: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiConfig {
+    // address the `serve` REST API listens on
+    pub bind: String,
+    // address of the pump gRPC service, only used when built with the "grpc" feature
+    pub grpc_bind: String,
+    // bearer token Prometheus scrapes GET /metrics with, empty = endpoint disabled
+    pub metrics_token: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttConfig {
+    // broker the `mqtt` subcommand subscribes to
+    pub host: String,
+    pub port: u16,
+    // topic filter carrying JSON CGM readings, wildcards allowed
+    pub topic: String,
+    pub client_id: String,
+    // broker credentials, empty username = anonymous
+    pub username: String,
+    pub password: String,
+    // connect with TLS using the system root certificates
+    pub tls: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NightscoutConfig {
+    // start the uploader together with `serve`
+    pub enabled: bool,
+    // site URL, e.g. https://example.herokuapp.com
+    pub url: String,
+    pub api_secret: String,
+    // the one patient whose data is uploaded to this site
+    pub patient_id: String,
+    // seconds between uploads of new records
+    pub interval_secs: u64,
+    // retries per batch before giving up until the next interval
+    pub max_retries: u32,
+}
+
+impl Default for NightscoutConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: String::new(),
+            api_secret: String::new(),
+            patient_id: String::new(),
+            interval_secs: 300,
+            max_retries: 5,
+        }
+    }
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".to_string(),
+            port: 1883,
+            topic: "glucoguard/cgm/#".to_string(),
+            client_id: "glucoguard-ingest".to_string(),
+            username: String::new(),
+            password: String::new(),
+            tls: false,
+        }
+    }
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1:8080".to_string(),
+            grpc_bind: "127.0.0.1:50051".to_string(),
+            metrics_token: String::new(),
+        }
+    }
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            dir: "./exports".to_string(),
+            research_key: String::new(),
+        }
+    }
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dry_run: false,
+            action: "delete".to_string(),
+            archive_path: "./data/archive.db".to_string(),
+            glucose_readings_days: 0,
+            sessions_days: 90,
+            audit_exports_days: 0,
+        }
+    }
+}
+
+impl Default for HousekeepingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            checkpoint_interval_minutes: 15,
+            analyze_interval_hours: 24,
+            vacuum_interval_hours: 7 * 24,
+        }
+    }
+}
+
+impl Default for ActivationConfig {
+    fn default() -> Self {
+        Self { code_ttl_hours: 72 }
+    }
+}
+
+impl Default for DevicesConfig {
+    fn default() -> Self {
+        Self {
+            pairing_code_ttl_minutes: 30,
+            max_clock_skew_secs: 300,
+            command_signing_key: String::new(),
+            low_reservoir_units: 20,
+            low_battery_percent: 20,
+        }
+    }
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self { signing_key: String::new(), token_ttl_minutes: 15 }
+    }
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        Self { escalation_minutes: 15 }
+    }
+}
+
+impl Default for MonitoringConfig {
+    fn default() -> Self {
+        let actions = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        Self {
+            enabled: true,
+            failed_login_limit: 5,
+            failed_login_window_minutes: 15,
+            office_hours_start: 7,
+            office_hours_end: 19,
+            privileged_actions: actions(&[
+                "User role changed",
+                "Role permission changed",
+                "Password reset by admin",
+                "User account deleted",
+                "All sessions revoked for user",
+                "API token issued",
+                "Maintenance token issued",
+                "Database restored from backup",
+                "Patient data erased",
+            ]),
+            export_limit: 10,
+            export_window_minutes: 60,
+            export_actions: actions(&[
+                "Patient history exported",
+                "De-identified research dataset exported",
+                "FHIR bundle exported over API",
+                "Clinical summary report generated",
+                "Audit log exported",
+            ]),
+        }
+    }
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self { min_free_disk_mb: 100 }
+    }
+}
+
+impl Default for ReportsConfig {
+    fn default() -> Self {
+        Self {
+            daily_summary: false,
+            daily_summary_hour: 6,
+        }
+    }
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        Self { locale: "en".to_string(), timezone: "UTC".to_string() }
+    }
+}
+
+impl Default for DosingConfig {
+    fn default() -> Self {
+        Self {
+            max_reading_age_minutes: 15,
+            temp_basal_patient_min_percent: 50,
+            temp_basal_patient_max_percent: 150,
+            temp_basal_patient_max_minutes: 240,
+            extended_bolus_max_minutes: 480,
+            extended_bolus_step_minutes: 15,
+            low_suspend: true,
+            low_suspend_resume_margin: 20,
+            low_suspend_max_minutes: 120,
+        }
+    }
+}
+
+impl Default for EmailConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            smtp_host: String::new(),
+            smtp_port: 587,
+            security: "starttls".to_string(),
+            username: String::new(),
+            password: String::new(),
+            from: String::new(),
+            activation_template: String::new(),
+        }
+    }
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            dir: "./backups".to_string(),
+        }
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            path: "./data/database.db".to_string(),
+            pool_size: 8,
+        }
+    }
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            ttl_secs: 60 * 60, // 1 hour
+            cleanup_interval_secs: 60,
+            max_active_sessions: 3,
+            role_session_limits: HashMap::new(),
+            limit_policy: "evict_oldest".to_string(),
+            cache_ttl_secs: 10,
+        }
+    }
+}
+
+impl Default for PasswordConfig {
+    fn default() -> Self {
+        Self { min_score: 3 }
+    }
+}
+
+impl SessionConfig {
+    // effective session limit for a role, 0 = unlimited
+    pub fn max_sessions_for(&self, role: &str) -> u32 {
+        self.role_session_limits
+            .get(role)
+            .copied()
+            .unwrap_or(self.max_active_sessions)
+    }
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            log_dir: "./target/debug/logs/health_data".to_string(),
+            level: "info".to_string(),
+            format: "text".to_string(),
+        }
+    }
+}
+
+impl Config {
+    // read config file (if present), apply env overrides and validate
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let mut config = if Path::new(path).exists() {
+            let contents = fs::read_to_string(path)?;
+            toml::from_str::<Config>(&contents)
+                .map_err(|e| format!("Invalid config file '{}': {}", path, e))?
+        } else {
+            Config::default()
+        };
+
+        config.apply_env_overrides()?;
+        // keyring: and file: references become the secrets they name before they are checked
+        crate::secrets::resolve(&mut config)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn apply_env_overrides(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Ok(value) = std::env::var("GLUCOGUARD_DB_PATH") {
+            self.database.path = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_DB_POOL_SIZE") {
+            self.database.pool_size = parse_env_number("GLUCOGUARD_DB_POOL_SIZE", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_SESSION_TTL_SECS") {
+            self.session.ttl_secs = parse_env_number("GLUCOGUARD_SESSION_TTL_SECS", &value)?;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_CLEANUP_INTERVAL_SECS") {
+            self.session.cleanup_interval_secs =
+                parse_env_number("GLUCOGUARD_CLEANUP_INTERVAL_SECS", &value)?;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_MAX_ACTIVE_SESSIONS") {
+            self.session.max_active_sessions =
+                parse_env_number("GLUCOGUARD_MAX_ACTIVE_SESSIONS", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_SESSION_LIMIT_POLICY") {
+            self.session.limit_policy = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_SESSION_CACHE_TTL_SECS") {
+            self.session.cache_ttl_secs = parse_env_number("GLUCOGUARD_SESSION_CACHE_TTL_SECS", &value)?;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_PASSWORD_MIN_SCORE") {
+            self.passwords.min_score = parse_env_number("GLUCOGUARD_PASSWORD_MIN_SCORE", &value)?.min(u8::MAX.into()) as u8;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_LOG_DIR") {
+            self.logging.log_dir = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_EXPORT_DIR") {
+            self.export.dir = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_RESEARCH_KEY") {
+            self.export.research_key = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_BACKUP_DIR") {
+            self.backup.dir = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_AUDIT_SIGNING_KEY") {
+            self.audit.signing_key = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_AUDIT_LEGACY_DUMP_KEY") {
+            self.audit.legacy_dump_key = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_ACTIVATION_CODE_TTL_HOURS") {
+            self.activation.code_ttl_hours = parse_env_number("GLUCOGUARD_ACTIVATION_CODE_TTL_HOURS", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_DEVICE_PAIRING_TTL_MINUTES") {
+            self.devices.pairing_code_ttl_minutes = parse_env_number("GLUCOGUARD_DEVICE_PAIRING_TTL_MINUTES", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_DEVICE_CLOCK_SKEW_SECS") {
+            self.devices.max_clock_skew_secs = parse_env_number("GLUCOGUARD_DEVICE_CLOCK_SKEW_SECS", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_DEVICE_COMMAND_SIGNING_KEY") {
+            self.devices.command_signing_key = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_DEVICE_LOW_RESERVOIR_UNITS") {
+            self.devices.low_reservoir_units = parse_env_number("GLUCOGUARD_DEVICE_LOW_RESERVOIR_UNITS", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_DEVICE_LOW_BATTERY_PERCENT") {
+            self.devices.low_battery_percent = parse_env_number("GLUCOGUARD_DEVICE_LOW_BATTERY_PERCENT", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_MAINTENANCE_SIGNING_KEY") {
+            self.maintenance.signing_key = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_MAINTENANCE_TOKEN_TTL_MINUTES") {
+            self.maintenance.token_ttl_minutes = parse_env_number("GLUCOGUARD_MAINTENANCE_TOKEN_TTL_MINUTES", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_DOSING_MAX_READING_AGE_MINUTES") {
+            self.dosing.max_reading_age_minutes = parse_env_number("GLUCOGUARD_DOSING_MAX_READING_AGE_MINUTES", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_TEMP_BASAL_PATIENT_MIN_PERCENT") {
+            self.dosing.temp_basal_patient_min_percent = parse_env_number("GLUCOGUARD_TEMP_BASAL_PATIENT_MIN_PERCENT", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_TEMP_BASAL_PATIENT_MAX_PERCENT") {
+            self.dosing.temp_basal_patient_max_percent = parse_env_number("GLUCOGUARD_TEMP_BASAL_PATIENT_MAX_PERCENT", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_TEMP_BASAL_PATIENT_MAX_MINUTES") {
+            self.dosing.temp_basal_patient_max_minutes = parse_env_number("GLUCOGUARD_TEMP_BASAL_PATIENT_MAX_MINUTES", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_EXTENDED_BOLUS_MAX_MINUTES") {
+            self.dosing.extended_bolus_max_minutes = parse_env_number("GLUCOGUARD_EXTENDED_BOLUS_MAX_MINUTES", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_EXTENDED_BOLUS_STEP_MINUTES") {
+            self.dosing.extended_bolus_step_minutes = parse_env_number("GLUCOGUARD_EXTENDED_BOLUS_STEP_MINUTES", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_LOW_SUSPEND_RESUME_MARGIN") {
+            self.dosing.low_suspend_resume_margin = parse_env_number("GLUCOGUARD_LOW_SUSPEND_RESUME_MARGIN", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_LOW_SUSPEND_MAX_MINUTES") {
+            self.dosing.low_suspend_max_minutes = parse_env_number("GLUCOGUARD_LOW_SUSPEND_MAX_MINUTES", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_ALERT_ESCALATION_MINUTES") {
+            self.alerts.escalation_minutes = parse_env_number("GLUCOGUARD_ALERT_ESCALATION_MINUTES", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_MONITORING_FAILED_LOGIN_LIMIT") {
+            self.monitoring.failed_login_limit = parse_env_number("GLUCOGUARD_MONITORING_FAILED_LOGIN_LIMIT", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_MONITORING_EXPORT_LIMIT") {
+            self.monitoring.export_limit = parse_env_number("GLUCOGUARD_MONITORING_EXPORT_LIMIT", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_HEALTH_MIN_FREE_DISK_MB") {
+            self.health.min_free_disk_mb = parse_env_number("GLUCOGUARD_HEALTH_MIN_FREE_DISK_MB", &value)?;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_DAILY_SUMMARY_HOUR") {
+            self.reports.daily_summary_hour = parse_env_number("GLUCOGUARD_DAILY_SUMMARY_HOUR", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_SMTP_HOST") {
+            self.email.smtp_host = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_SMTP_PORT") {
+            self.email.smtp_port = u16::try_from(parse_env_number("GLUCOGUARD_SMTP_PORT", &value)?)
+                .map_err(|_| "GLUCOGUARD_SMTP_PORT must be at most 65535")?;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_SMTP_USERNAME") {
+            self.email.username = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_SMTP_PASSWORD") {
+            self.email.password = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_EMAIL_FROM") {
+            self.email.from = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_RETENTION_ACTION") {
+            self.retention.action = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_RETENTION_GLUCOSE_DAYS") {
+            self.retention.glucose_readings_days = parse_env_number("GLUCOGUARD_RETENTION_GLUCOSE_DAYS", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_RETENTION_SESSIONS_DAYS") {
+            self.retention.sessions_days = parse_env_number("GLUCOGUARD_RETENTION_SESSIONS_DAYS", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_RETENTION_AUDIT_EXPORTS_DAYS") {
+            self.retention.audit_exports_days = parse_env_number("GLUCOGUARD_RETENTION_AUDIT_EXPORTS_DAYS", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_CHECKPOINT_INTERVAL_MINUTES") {
+            self.housekeeping.checkpoint_interval_minutes = parse_env_number("GLUCOGUARD_CHECKPOINT_INTERVAL_MINUTES", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_ANALYZE_INTERVAL_HOURS") {
+            self.housekeeping.analyze_interval_hours = parse_env_number("GLUCOGUARD_ANALYZE_INTERVAL_HOURS", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_VACUUM_INTERVAL_HOURS") {
+            self.housekeeping.vacuum_interval_hours = parse_env_number("GLUCOGUARD_VACUUM_INTERVAL_HOURS", &value)? as u32;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_API_BIND") {
+            self.api.bind = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_GRPC_BIND") {
+            self.api.grpc_bind = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_METRICS_TOKEN") {
+            self.api.metrics_token = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_MQTT_HOST") {
+            self.mqtt.host = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_MQTT_PORT") {
+            self.mqtt.port = u16::try_from(parse_env_number("GLUCOGUARD_MQTT_PORT", &value)?)
+                .map_err(|_| "GLUCOGUARD_MQTT_PORT must be at most 65535")?;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_MQTT_TOPIC") {
+            self.mqtt.topic = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_MQTT_USERNAME") {
+            self.mqtt.username = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_MQTT_PASSWORD") {
+            self.mqtt.password = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_NIGHTSCOUT_URL") {
+            self.nightscout.url = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_NIGHTSCOUT_API_SECRET") {
+            self.nightscout.api_secret = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_NIGHTSCOUT_PATIENT_ID") {
+            self.nightscout.patient_id = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_LOG_LEVEL") {
+            self.logging.level = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_LOG_FORMAT") {
+            self.logging.format = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_LOCALE") {
+            self.ui.locale = value;
+        }
+        if let Ok(value) = std::env::var("GLUCOGUARD_TIMEZONE") {
+            self.ui.timezone = value;
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.database.path.trim().is_empty() {
+            return Err("database.path cannot be empty".into());
+        }
+        if !(1..=64).contains(&self.database.pool_size) {
+            return Err("database.pool_size must be between 1 and 64".into());
+        }
+        if self.session.ttl_secs == 0 || self.session.ttl_secs > 24 * 60 * 60 {
+            return Err("session.ttl_secs must be between 1 and 86400".into());
+        }
+        if self.session.cleanup_interval_secs == 0 {
+            return Err("session.cleanup_interval_secs must be greater than 0".into());
+        }
+        if self.session.limit_policy != "reject" && self.session.limit_policy != "evict_oldest" {
+            return Err("session.limit_policy must be \"reject\" or \"evict_oldest\"".into());
+        }
+        if self.passwords.min_score > 4 {
+            return Err("passwords.min_score must be between 0 and 4".into());
+        }
+        if self.session.cache_ttl_secs > 300 {
+            return Err("session.cache_ttl_secs must be at most 300".into());
+        }
+        if self.logging.log_dir.trim().is_empty() {
+            return Err("logging.log_dir cannot be empty".into());
+        }
+        if self.export.dir.trim().is_empty() {
+            return Err("export.dir cannot be empty".into());
+        }
+        if self.backup.dir.trim().is_empty() {
+            return Err("backup.dir cannot be empty".into());
+        }
+        if self.retention.action != "delete" && self.retention.action != "archive" {
+            return Err("retention.action must be \"delete\" or \"archive\"".into());
+        }
+        if self.retention.action == "archive"
+            && (self.retention.archive_path.trim().is_empty() || self.retention.archive_path == self.database.path)
+        {
+            return Err("retention.archive_path must be set and differ from database.path".into());
+        }
+        if !self.audit.signing_key.is_empty() && self.audit.signing_key.len() < 32 {
+            return Err("audit.signing_key must be at least 32 characters".into());
+        }
+        if !(1..=30 * 24).contains(&self.activation.code_ttl_hours) {
+            return Err("activation.code_ttl_hours must be between 1 and 720".into());
+        }
+        if !(5..=24 * 60).contains(&self.devices.pairing_code_ttl_minutes) {
+            return Err("devices.pairing_code_ttl_minutes must be between 5 and 1440".into());
+        }
+        if !(30..=3600).contains(&self.devices.max_clock_skew_secs) {
+            return Err("devices.max_clock_skew_secs must be between 30 and 3600".into());
+        }
+        let signing_key = &self.devices.command_signing_key;
+        if !signing_key.is_empty() && (signing_key.len() != 64 || !signing_key.chars().all(|c| c.is_ascii_hexdigit())) {
+            return Err("devices.command_signing_key must be 64 hex characters".into());
+        }
+        if !(1..=100).contains(&self.devices.low_reservoir_units) {
+            return Err("devices.low_reservoir_units must be between 1 and 100".into());
+        }
+        if !(5..=50).contains(&self.devices.low_battery_percent) {
+            return Err("devices.low_battery_percent must be between 5 and 50".into());
+        }
+        let signing_key = &self.maintenance.signing_key;
+        if !signing_key.is_empty() && (signing_key.len() != 64 || !signing_key.chars().all(|c| c.is_ascii_hexdigit())) {
+            return Err("maintenance.signing_key must be 64 hex characters".into());
+        }
+        if !(1..=60).contains(&self.maintenance.token_ttl_minutes) {
+            return Err("maintenance.token_ttl_minutes must be between 1 and 60".into());
+        }
+        if !(5..=120).contains(&self.dosing.max_reading_age_minutes) {
+            return Err("dosing.max_reading_age_minutes must be between 5 and 120".into());
+        }
+        if self.dosing.temp_basal_patient_min_percent > 100 {
+            return Err("dosing.temp_basal_patient_min_percent must be between 0 and 100".into());
+        }
+        if !(100..=200).contains(&self.dosing.temp_basal_patient_max_percent) {
+            return Err("dosing.temp_basal_patient_max_percent must be between 100 and 200".into());
+        }
+        if !(30..=24 * 60).contains(&self.dosing.temp_basal_patient_max_minutes) {
+            return Err("dosing.temp_basal_patient_max_minutes must be between 30 and 1440".into());
+        }
+        if !(30..=12 * 60).contains(&self.dosing.extended_bolus_max_minutes) {
+            return Err("dosing.extended_bolus_max_minutes must be between 30 and 720".into());
+        }
+        if !(5..=60).contains(&self.dosing.extended_bolus_step_minutes) {
+            return Err("dosing.extended_bolus_step_minutes must be between 5 and 60".into());
+        }
+        if self.dosing.low_suspend_resume_margin > 100 {
+            return Err("dosing.low_suspend_resume_margin must be between 0 and 100".into());
+        }
+        if !(30..=240).contains(&self.dosing.low_suspend_max_minutes) {
+            return Err("dosing.low_suspend_max_minutes must be between 30 and 240".into());
+        }
+        if !(5..=240).contains(&self.alerts.escalation_minutes) {
+            return Err("alerts.escalation_minutes must be between 5 and 240".into());
+        }
+        if !(2..=100).contains(&self.monitoring.failed_login_limit) || !(2..=1000).contains(&self.monitoring.export_limit) {
+            return Err("monitoring.failed_login_limit must be between 2 and 100, monitoring.export_limit between 2 and 1000".into());
+        }
+        if !(1..=24 * 60).contains(&self.monitoring.failed_login_window_minutes) || !(1..=24 * 60).contains(&self.monitoring.export_window_minutes) {
+            return Err("monitoring.failed_login_window_minutes and monitoring.export_window_minutes must be between 1 and 1440".into());
+        }
+        if self.monitoring.office_hours_start > 23 || self.monitoring.office_hours_end > 23 || self.monitoring.office_hours_start == self.monitoring.office_hours_end {
+            return Err("monitoring.office_hours_start and monitoring.office_hours_end must be different hours between 0 and 23".into());
+        }
+        if self.monitoring.privileged_actions.iter().chain(&self.monitoring.export_actions).any(|action| action.trim().is_empty()) {
+            return Err("monitoring.privileged_actions and monitoring.export_actions cannot contain empty actions".into());
+        }
+        if self.reports.daily_summary_hour > 23 {
+            return Err("reports.daily_summary_hour must be between 0 and 23".into());
+        }
+        if !["starttls", "tls", "none"].contains(&self.email.security.as_str()) {
+            return Err("email.security must be \"starttls\", \"tls\" or \"none\"".into());
+        }
+        if self.email.enabled && (self.email.smtp_host.trim().is_empty() || self.email.smtp_port == 0) {
+            return Err("email.smtp_host and email.smtp_port must be set when email is enabled".into());
+        }
+        if self.email.enabled && crate::input_validation::validate_email(&self.email.from).is_err() {
+            return Err(format!("email.from '{}' is not a valid email address", self.email.from).into());
+        }
+        if self.api.bind.parse::<std::net::SocketAddr>().is_err() {
+            return Err(format!("api.bind '{}' is not a valid socket address", self.api.bind).into());
+        }
+        if self.api.grpc_bind.parse::<std::net::SocketAddr>().is_err() {
+            return Err(format!("api.grpc_bind '{}' is not a valid socket address", self.api.grpc_bind).into());
+        }
+        if self.mqtt.host.trim().is_empty() || self.mqtt.port == 0 {
+            return Err("mqtt.host and mqtt.port must be set".into());
+        }
+        if self.mqtt.topic.trim().is_empty() || self.mqtt.client_id.trim().is_empty() {
+            return Err("mqtt.topic and mqtt.client_id cannot be empty".into());
+        }
+        if self.nightscout.interval_secs == 0 {
+            return Err("nightscout.interval_secs must be greater than 0".into());
+        }
+        if !self.nightscout.url.is_empty()
+            && !self.nightscout.url.starts_with("https://")
+            && !self.nightscout.url.starts_with("http://")
+        {
+            return Err(format!("nightscout.url '{}' must start with https:// or http://", self.nightscout.url).into());
+        }
+        // Nightscout itself refuses API secrets shorter than 12 characters
+        if !self.nightscout.api_secret.is_empty() && self.nightscout.api_secret.len() < 12 {
+            return Err("nightscout.api_secret must be at least 12 characters".into());
+        }
+        if self.nightscout.enabled
+            && (self.nightscout.url.is_empty() || self.nightscout.api_secret.is_empty() || self.nightscout.patient_id.is_empty())
+        {
+            return Err("nightscout.enabled requires nightscout.url, nightscout.api_secret and nightscout.patient_id".into());
+        }
+        // a short key would let anyone holding a dataset brute-force the pseudonyms back to patient ids
+        if !self.export.research_key.is_empty() && self.export.research_key.len() < 32 {
+            return Err("export.research_key must be at least 32 characters".into());
+        }
+        if self.logging.format != "text" && self.logging.format != "json" {
+            return Err("logging.format must be \"text\" or \"json\"".into());
+        }
+        if tracing_subscriber::EnvFilter::try_new(&self.logging.level).is_err() {
+            return Err(format!("logging.level '{}' is not a valid filter", self.logging.level).into());
+        }
+        if crate::i18n::Locale::parse(&self.ui.locale).is_none() {
+            let codes: Vec<&str> = crate::i18n::Locale::ALL.iter().map(|locale| locale.code()).collect();
+            return Err(format!("ui.locale '{}' must be one of: {}", self.ui.locale, codes.join(", ")).into());
+        }
+        if crate::timestamps::parse_zone(&self.ui.timezone).is_none() {
+            return Err(format!("ui.timezone '{}' is not an IANA time zone name, e.g. UTC or Europe/Madrid", self.ui.timezone).into());
+        }
+        Ok(())
+    }
+}
+
+fn parse_env_number(name: &str, value: &str) -> Result<u64, Box<dyn Error>> {
+    value
+        .trim()
+        .parse::<u64>()
+        .map_err(|_| format!("{} must be a positive integer, got '{}'", name, value).into())
+}
+
+// load the configuration once at startup
+// GLUCOGUARD_CONFIG can point to a different config file
+pub fn init() -> Result<&'static Config, Box<dyn Error>> {
+    let config = match std::env::var("GLUCOGUARD_CONFIG") {
+        Ok(path) => {
+            // an explicitly requested config file must exist
+            if !Path::new(&path).exists() {
+                return Err(format!("Config file '{}' not found", path).into());
+            }
+            Config::load(&path)?
+        }
+        Err(_) => Config::load(DEFAULT_CONFIG_PATH)?,
+    };
+    Ok(CONFIG.get_or_init(|| config))
+}
+
+// access the loaded configuration, falls back to defaults if init() was never called
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
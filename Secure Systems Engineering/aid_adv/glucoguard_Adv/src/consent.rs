@@ -0,0 +1,161 @@
+/*
+Patient consent to data sharing.
+Patients record from their menu whether they agree to share their record with the caretakers linked to
+them and whether it may go into de-identified research datasets. Every decision is a new row of
+patient_consents and the newest one per scope is in force (the current_consents view), so the history
+shows what was agreed and when. Nothing is shared without a recorded grant:
+  caretakers  the caretaker patient scope in access_control only covers consenting patients, so every
+              caretaker view, export, API call and escalation notice depends on it
+  research    export_research_dataset leaves out patients who have not agreed
+*/
+use crate::db::models::Patient;
+use crate::logger::SECURITY_TARGET;
+use crate::timestamps;
+use rusqlite::{params, Connection, OptionalExtension};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsentScope {
+    Caretakers,
+    Research,
+}
+
+impl ConsentScope {
+    pub const ALL: [ConsentScope; 2] = [ConsentScope::Caretakers, ConsentScope::Research];
+
+    // value of patient_consents.scope
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsentScope::Caretakers => "caretakers",
+            ConsentScope::Research => "research",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ConsentScope> {
+        Self::ALL.into_iter().find(|scope| scope.as_str() == name)
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            ConsentScope::Caretakers => "Share my readings, doses, alerts and notes with my caretakers",
+            ConsentScope::Research => "Include my de-identified data in research datasets",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConsentRecord {
+    pub scope: ConsentScope,
+    pub granted: bool,
+    pub recorded_at: String,
+    // username, or the user id of an account that is gone
+    pub recorded_by: String,
+}
+
+// whether the decision in force for the scope is a grant
+pub fn has_consent(conn: &Connection, patient_id: &str, scope: ConsentScope) -> rusqlite::Result<bool> {
+    let granted: Option<bool> = conn
+        .query_row(
+            "SELECT granted FROM current_consents WHERE patient_id = ?1 AND scope = ?2",
+            params![patient_id, scope.as_str()],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(granted.unwrap_or(false))
+}
+
+// every decision of the patient, newest first
+pub fn consent_history(conn: &Connection, patient_id: &str) -> rusqlite::Result<Vec<ConsentRecord>> {
+    let mut stmt = conn.prepare(
+        "SELECT c.scope, c.granted, c.recorded_at, COALESCE(u.user_name, c.recorded_by)
+         FROM patient_consents c
+         LEFT JOIN users u ON u.id = c.recorded_by
+         WHERE c.patient_id = ?1
+         ORDER BY c.consent_id DESC",
+    )?;
+    let rows = stmt.query_map([patient_id], |row| {
+        let scope: String = row.get(0)?;
+        Ok(ConsentRecord {
+            scope: ConsentScope::from_name(&scope).unwrap_or(ConsentScope::Caretakers),
+            granted: row.get(1)?,
+            recorded_at: row.get(2)?,
+            recorded_by: row.get(3)?,
+        })
+    })?;
+    rows.collect()
+}
+
+// record the patient's own decision for one scope; `user_id` is the patient's account
+pub fn record_consent(conn: &Connection, user_id: &str, patient_id: &str, scope: ConsentScope, granted: bool) -> Result<(), String> {
+    if user_id != patient_id {
+        tracing::warn!(target: SECURITY_TARGET, user_id = %user_id, patient_id = %patient_id, "Consent change denied");
+        return Err("only the patient can give or withdraw consent".to_string());
+    }
+    if has_consent(conn, patient_id, scope).map_err(|e| e.to_string())? == granted {
+        return Err(format!("consent is already {}", if granted { "given" } else { "withheld" }));
+    }
+    conn.execute(
+        "INSERT INTO patient_consents (patient_id, scope, granted, recorded_at, recorded_by) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![patient_id, scope.as_str(), granted, timestamps::now(), user_id],
+    )
+    .map_err(|e| e.to_string())?;
+    tracing::info!(target: SECURITY_TARGET, user_id = %user_id, patient_id = %patient_id, scope = scope.as_str(), granted, "Patient consent recorded");
+    Ok(())
+}
+
+// the patients who agreed to the scope, and how many were left out
+pub fn consenting(conn: &Connection, patients: Vec<Patient>, scope: ConsentScope) -> rusqlite::Result<(Vec<Patient>, usize)> {
+    let total = patients.len();
+    let mut kept = Vec::with_capacity(total);
+    for patient in patients {
+        if has_consent(conn, &patient.patient_id, scope)? {
+            kept.push(patient);
+        }
+    }
+    let left_out = total - kept.len();
+    Ok((kept, left_out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::access_control::{self, Permission, Resource, Role};
+    use crate::db::queries;
+    use crate::test_utils;
+
+    #[test]
+    fn caretakers_and_research_need_the_patients_consent() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let caretaker = Role::new(&conn, "caretaker", &fixtures.caretaker_id);
+        let clinician = Role::new(&conn, "clinician", &fixtures.clinician_id);
+        let can_view = |role: &Role| access_control::can_access(&conn, role, &Resource::Patient(&fixtures.patient_id), &Permission::ViewGlucose);
+
+        // the fixtures patient agreed to share with caretakers, nobody agreed to research use
+        assert!(can_view(&caretaker));
+        assert!(record_consent(&conn, &fixtures.caretaker_id, &fixtures.patient_id, ConsentScope::Caretakers, false).is_err());
+        assert!(record_consent(&conn, &fixtures.patient_id, &fixtures.patient_id, ConsentScope::Caretakers, true).is_err());
+        record_consent(&conn, &fixtures.patient_id, &fixtures.patient_id, ConsentScope::Caretakers, false).unwrap();
+        assert!(!can_view(&caretaker));
+        assert!(access_control::accessible_patients(&conn, &caretaker, &Permission::ViewGlucose).unwrap().is_empty());
+        // the care team link stays, the treating clinician is not affected
+        assert!(queries::is_patient_caretaker(&conn, &fixtures.patient_id, &fixtures.caretaker_id).unwrap());
+        assert!(can_view(&clinician));
+
+        let patients = || {
+            [&fixtures.patient_id, &fixtures.other_patient_id]
+                .into_iter()
+                .map(|id| queries::get_patient_by_id(&conn, id).unwrap().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(consenting(&conn, patients(), ConsentScope::Research).unwrap().1, 2);
+        record_consent(&conn, &fixtures.patient_id, &fixtures.patient_id, ConsentScope::Research, true).unwrap();
+        let (kept, left_out) = consenting(&conn, patients(), ConsentScope::Research).unwrap();
+        assert_eq!((kept.len(), left_out), (1, 1));
+        assert_eq!(kept[0].patient_id, fixtures.patient_id);
+
+        let history = consent_history(&conn, &fixtures.patient_id).unwrap();
+        let decisions: Vec<_> = history.iter().map(|record| (record.scope, record.granted)).collect();
+        assert_eq!(decisions, vec![(ConsentScope::Research, true), (ConsentScope::Caretakers, false), (ConsentScope::Caretakers, true)]);
+        assert_eq!(history[0].recorded_by, "pat.fixture");
+    }
+}
@@ -0,0 +1,292 @@
+// Daily per-patient summaries for the clinician
+// When reports.daily_summary is on, the session cleanup task summarises the previous UTC day (glucose
+// readings, insulin doses, alerts) for every patient once reports.daily_summary_hour has passed. Each
+// summary is stored in daily_summaries and emailed to the patient's clinician if email is enabled and
+// they have set an address; clinicians read the stored ones from their menu.
+use crate::access_control::{self, Permission, Resource, Role};
+use crate::analytics::{self, GlucoseStats, TARGET_HIGH, TARGET_LOW};
+use crate::config::ReportsConfig;
+use crate::db::models::Patient;
+use crate::db::queries;
+use crate::email;
+use crate::logger::SECURITY_TARGET;
+use crate::units;
+use chrono::{DateTime, Days, NaiveDate, Timelike, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+
+// figures of one patient's day
+#[derive(Debug, Clone)]
+pub struct DaySummary {
+    pub date: NaiveDate,
+    pub glucose: Option<GlucoseStats>,
+    pub lowest: Option<f64>,
+    pub highest: Option<f64>,
+    // readings outside the patient's own alert thresholds
+    pub below_threshold: usize,
+    pub above_threshold: usize,
+    // (action_type, doses, units)
+    pub doses: Vec<(String, i64, f64)>,
+    // (alert_type, raised, still unresolved)
+    pub alerts: Vec<(String, i64, i64)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StoredSummary {
+    pub summary_date: String,
+    pub body: String,
+    // 'stored', 'sent' or 'failed'
+    pub delivery: String,
+    pub created_at: String,
+}
+
+// rows of the day, bound as ?1 = patient_id and ?2 = YYYY-MM-DD
+const DAY_FILTER: &str = "patient_id = ?1 AND julianday({column}) >= julianday(?2) AND julianday({column}) < julianday(?2, '+1 day')";
+
+fn day_filter(column: &str) -> String {
+    DAY_FILTER.replace("{column}", column)
+}
+
+// gather the figures of one UTC day
+pub fn build_day_summary(conn: &Connection, patient: &Patient, date: NaiveDate) -> rusqlite::Result<DaySummary> {
+    let day = date.format("%Y-%m-%d").to_string();
+    let levels: Vec<f64> = {
+        let sql = format!("SELECT glucose_level FROM glucose_readings WHERE {} ORDER BY reading_time", day_filter("reading_time"));
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params![patient.patient_id, day], |row| row.get(0))?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    let doses = {
+        let sql = format!(
+            "SELECT action_type, COUNT(*), COALESCE(SUM(dosage_units), 0) FROM insulin_doses WHERE {} GROUP BY action_type ORDER BY action_type",
+            day_filter("dosage_time")
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params![patient.patient_id, day], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    let alerts = {
+        let sql = format!(
+            "SELECT alert_type, COUNT(*), COALESCE(SUM(CASE WHEN is_resolved THEN 0 ELSE 1 END), 0) FROM alerts WHERE {}
+             GROUP BY alert_type ORDER BY alert_type",
+            day_filter("alert_time")
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params![patient.patient_id, day], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+
+    Ok(DaySummary {
+        date,
+        glucose: analytics::compute_stats(&levels),
+        lowest: levels.iter().copied().reduce(f64::min),
+        highest: levels.iter().copied().reduce(f64::max),
+        below_threshold: levels.iter().filter(|&&v| v < f64::from(patient.low_glucose_threshold)).count(),
+        above_threshold: levels.iter().filter(|&&v| v > f64::from(patient.high_glucose_threshold)).count(),
+        doses,
+        alerts,
+    })
+}
+
+// plain-text summary, as stored; values in mg/dL, localized to the reader's unit when shown or emailed
+pub fn render_day_summary(patient: &Patient, summary: &DaySummary) -> String {
+    let mut lines = vec![
+        format!("GlucoGuard daily summary for {} {}, {} (UTC)", patient.first_name, patient.last_name, summary.date),
+        String::new(),
+        "Glucose".to_string(),
+    ];
+    match &summary.glucose {
+        Some(stats) => {
+            lines.push(format!(
+                "  Readings: {}, average {:.1} mg/dL (lowest {:.0} mg/dL, highest {:.0} mg/dL)",
+                stats.count,
+                stats.mean,
+                summary.lowest.unwrap_or_default(),
+                summary.highest.unwrap_or_default()
+            ));
+            lines.push(format!(
+                "  Below the low threshold ({:.0} mg/dL): {}, above the high threshold ({:.0} mg/dL): {}",
+                patient.low_glucose_threshold, summary.below_threshold, patient.high_glucose_threshold, summary.above_threshold
+            ));
+            lines.push(format!("  Time in range ({:.0}-{:.0} mg/dL): {:.1}%", TARGET_LOW, TARGET_HIGH, stats.time_in_range_pct));
+        }
+        None => lines.push("  No glucose readings".to_string()),
+    }
+
+    lines.push("Insulin".to_string());
+    if summary.doses.is_empty() {
+        lines.push("  No insulin doses recorded".to_string());
+    }
+    for (action_type, count, units) in &summary.doses {
+        lines.push(format!("  {}: {} dose(s), {:.2} units", action_type, count, units));
+    }
+    if !summary.doses.is_empty() {
+        let total: f64 = summary.doses.iter().map(|(_, _, units)| units).sum();
+        lines.push(format!("  Total: {:.2} units (max dosage {:.2} units per 24 hours)", total, patient.max_dosage));
+    }
+
+    lines.push("Alerts".to_string());
+    if summary.alerts.is_empty() {
+        lines.push("  No alerts".to_string());
+    }
+    for (alert_type, raised, open) in &summary.alerts {
+        lines.push(format!("  {}: {} ({} unresolved)", alert_type, raised, open));
+    }
+    lines.join("\n")
+}
+
+// email address the clinician receives summaries at, if they set one
+pub fn summary_email(conn: &Connection, user_id: &str) -> rusqlite::Result<Option<String>> {
+    conn.query_row("SELECT email FROM users WHERE id = ?1", [user_id], |row| row.get(0))
+        .optional()
+        .map(Option::flatten)
+}
+
+// set or clear (None) the clinician's summary email address
+pub fn set_summary_email(conn: &Connection, user_id: &str, email: Option<&str>) -> rusqlite::Result<()> {
+    conn.execute("UPDATE users SET email = ?2 WHERE id = ?1", params![user_id, email])?;
+    tracing::info!(target: SECURITY_TARGET, user_id = %user_id, set = email.is_some(), "Daily summary email address changed");
+    Ok(())
+}
+
+/*
+Summarise the previous UTC day for every patient that has no summary for it yet, once settings.daily_summary_hour
+has passed; run by the session cleanup task. Returns how many summaries were generated.
+Email failures are recorded with the summary and do not stop the run.
+*/
+pub fn generate_due(conn: &Connection, settings: &ReportsConfig, now: DateTime<Utc>) -> rusqlite::Result<usize> {
+    if !settings.daily_summary || now.hour() < settings.daily_summary_hour {
+        return Ok(0);
+    }
+    let Some(date) = now.date_naive().checked_sub_days(Days::new(1)) else {
+        return Ok(0);
+    };
+    let day = date.format("%Y-%m-%d").to_string();
+    let due = {
+        let mut stmt = conn.prepare(
+            "SELECT p.patient_id FROM patients p
+             WHERE p.is_simulated = 0
+               AND NOT EXISTS (SELECT 1 FROM daily_summaries d WHERE d.patient_id = p.patient_id AND d.summary_date = ?1)
+             ORDER BY p.patient_id",
+        )?;
+        let rows = stmt.query_map([&day], |row| row.get::<_, String>(0))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+
+    let mut generated = 0;
+    for patient_id in &due {
+        let Some(patient) = queries::get_patient_by_id(conn, patient_id)? else {
+            continue;
+        };
+        let body = render_day_summary(&patient, &build_day_summary(conn, &patient, date)?);
+        // another process running the same task may have stored it first
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO daily_summaries (patient_id, clinician_id, summary_date, body, created_at, delivery)
+             VALUES (?1, ?2, ?3, ?4, datetime('now'), 'stored')",
+            params![patient.patient_id, patient.clinician_id, day, body],
+        )?;
+        if inserted == 0 {
+            continue;
+        }
+        generated += 1;
+        let summary_id = conn.last_insert_rowid();
+
+        let recipient = summary_email(conn, &patient.clinician_id)?;
+        let Some(recipient) = recipient.filter(|_| email::enabled()) else {
+            continue;
+        };
+        let subject = format!("GlucoGuard daily summary: {} {}, {}", patient.first_name, patient.last_name, day);
+        let body = units::display_unit(conn, &patient.clinician_id).localize(&body);
+        let error = email::send_report(&recipient, &subject, body).err().map(|e| e.to_string());
+        conn.execute(
+            "UPDATE daily_summaries SET delivery = ?2, delivery_error = ?3 WHERE summary_id = ?1",
+            params![summary_id, if error.is_none() { "sent" } else { "failed" }, error],
+        )?;
+        if let Some(e) = &error {
+            tracing::warn!(patient_id = %patient.patient_id, clinician_id = %patient.clinician_id, error = %e, "Daily summary email failed");
+        }
+    }
+    if generated > 0 {
+        tracing::info!(date = %day, generated, "Daily patient summaries generated");
+    }
+    Ok(generated)
+}
+
+// most recent stored summaries of a patient in the role's scope (ViewGlucose), newest first
+pub fn summaries_for_patient(conn: &Connection, role: &Role, patient_id: &str, limit: u32) -> Result<Vec<StoredSummary>, String> {
+    if !access_control::can_access(conn, role, &Resource::Patient(patient_id), &Permission::ViewGlucose) {
+        return Err("Access denied: patient is not in your care or ViewGlucose is missing".to_string());
+    }
+    let mut stmt = conn
+        .prepare(
+            "SELECT summary_date, body, delivery, created_at FROM daily_summaries
+             WHERE patient_id = ?1
+             ORDER BY summary_date DESC
+             LIMIT ?2",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![patient_id, limit], |row| {
+            Ok(StoredSummary {
+                summary_date: row.get(0)?,
+                body: row.get(1)?,
+                delivery: row.get(2)?,
+                created_at: row.get(3)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<rusqlite::Result<_>>().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use chrono::TimeZone;
+
+    #[test]
+    fn previous_day_is_summarised_once_per_patient() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let patient_id = &fixtures.patient_id;
+        for (level, time) in [(55.0, "2026-03-09 07:00:00"), (120.0, "2026-03-09 12:00:00"), (250.0, "2026-03-09 23:30:00"), (140.0, "2026-03-10 01:00:00")] {
+            conn.execute(
+                "INSERT INTO glucose_readings (patient_id, glucose_level, reading_time, status) VALUES (?1, ?2, ?3, 'normal')",
+                params![patient_id, level, time],
+            )
+            .unwrap();
+        }
+        for (units, time) in [(2.0, "2026-03-09 08:00:00"), (3.5, "2026-03-09 13:00:00")] {
+            conn.execute(
+                "INSERT INTO insulin_logs (patient_id, action_type, dosage_units, requested_by, dosage_time) VALUES (?1, 'bolus', ?2, 'test', ?3)",
+                params![patient_id, units, time],
+            )
+            .unwrap();
+        }
+        conn.execute(
+            "INSERT INTO alerts (patient_id, alert_type, alert_message, alert_time, is_resolved) VALUES (?1, 'LOW', 'low', '2026-03-09 07:00:00', 0)",
+            [patient_id],
+        )
+        .unwrap();
+
+        let settings = ReportsConfig { daily_summary: true, daily_summary_hour: 6 };
+        let early = Utc.with_ymd_and_hms(2026, 3, 10, 5, 0, 0).unwrap();
+        assert_eq!(generate_due(&conn, &settings, early).unwrap(), 0);
+        let now = Utc.with_ymd_and_hms(2026, 3, 10, 7, 0, 0).unwrap();
+        assert_eq!(generate_due(&conn, &settings, now).unwrap(), 2);
+        assert_eq!(generate_due(&conn, &settings, now).unwrap(), 0);
+
+        let clinician = Role::new(&conn, "clinician", &fixtures.clinician_id);
+        let summaries = summaries_for_patient(&conn, &clinician, patient_id, 7).unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].summary_date, "2026-03-09");
+        assert_eq!(summaries[0].delivery, "stored");
+        let body = &summaries[0].body;
+        assert!(body.contains("Readings: 3"), "{}", body);
+        assert!(body.contains("Below the low threshold (70 mg/dL): 1, above the high threshold (180 mg/dL): 1"), "{}", body);
+        assert!(body.contains("bolus: 2 dose(s), 5.50 units"), "{}", body);
+        assert!(body.contains("LOW: 1 (1 unresolved)"), "{}", body);
+
+        let other = Role::new(&conn, "clinician", &fixtures.other_clinician_id);
+        assert!(summaries_for_patient(&conn, &other, patient_id, 7).is_err());
+    }
+}
@@ -0,0 +1,220 @@
+// Clinician dashboard: one row per patient in the clinician's care for triage
+// Each row has the latest reading with its trend arrow, the open alerts and the last dose; rows are
+// ordered most urgent first (low reading or open critical alert, then anything out of the ordinary).
+use crate::access_control::{self, Permission, Role};
+use crate::config;
+use crate::db::models::Patient;
+use crate::escalation;
+use crate::prediction::{self, Trend};
+use rusqlite::{Connection, OptionalExtension};
+
+// direction and speed of the glucose trend, in the usual CGM arrow steps of 1 mg/dL per minute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrendArrow {
+    RisingFast,
+    Rising,
+    RisingSlowly,
+    Steady,
+    FallingSlowly,
+    Falling,
+    FallingFast,
+}
+
+impl TrendArrow {
+    // slope in mg/dL per minute
+    pub fn from_slope(slope: f64) -> TrendArrow {
+        match slope {
+            s if s > 3.0 => TrendArrow::RisingFast,
+            s if s > 2.0 => TrendArrow::Rising,
+            s if s > 1.0 => TrendArrow::RisingSlowly,
+            s if s >= -1.0 => TrendArrow::Steady,
+            s if s >= -2.0 => TrendArrow::FallingSlowly,
+            s if s >= -3.0 => TrendArrow::Falling,
+            _ => TrendArrow::FallingFast,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            TrendArrow::RisingFast => "↑↑",
+            TrendArrow::Rising => "↑",
+            TrendArrow::RisingSlowly => "↗",
+            TrendArrow::Steady => "→",
+            TrendArrow::FallingSlowly => "↘",
+            TrendArrow::Falling => "↓",
+            TrendArrow::FallingFast => "↓↓",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    // reading below the low threshold or an open critical alert
+    Urgent,
+    // reading above the high threshold, no recent reading, or open alerts
+    Attention,
+    Stable,
+}
+
+#[derive(Debug, Clone)]
+pub struct LatestReading {
+    pub level: f64,
+    pub minutes_ago: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct LastDose {
+    pub action_type: String,
+    pub units: f64,
+    pub minutes_ago: f64,
+}
+
+#[derive(Debug)]
+pub struct DashboardRow {
+    pub patient: Patient,
+    pub latest: Option<LatestReading>,
+    // None without enough recent readings
+    pub trend: Option<TrendArrow>,
+    pub open_alerts: i64,
+    pub open_critical_alerts: i64,
+    pub last_dose: Option<LastDose>,
+}
+
+impl DashboardRow {
+    pub fn priority(&self) -> Priority {
+        let stale_after = f64::from(config::get().dosing.max_reading_age_minutes);
+        let low = f64::from(self.patient.low_glucose_threshold);
+        let high = f64::from(self.patient.high_glucose_threshold);
+        match &self.latest {
+            _ if self.open_critical_alerts > 0 => Priority::Urgent,
+            Some(reading) if reading.level < low => Priority::Urgent,
+            Some(reading) if reading.level > high || reading.minutes_ago > stale_after => Priority::Attention,
+            None => Priority::Attention,
+            _ if self.open_alerts > 0 => Priority::Attention,
+            _ => Priority::Stable,
+        }
+    }
+}
+
+// "12 min ago", "3 h ago" or "2 d ago"
+pub fn format_age(minutes: f64) -> String {
+    if minutes < 60.0 {
+        format!("{:.0} min ago", minutes.max(0.0))
+    } else if minutes < 48.0 * 60.0 {
+        format!("{:.0} h ago", minutes / 60.0)
+    } else {
+        format!("{:.0} d ago", minutes / 1440.0)
+    }
+}
+
+// trend over the recent readings, when there are enough of them and the newest is current
+fn trend_arrow(points: &[(f64, f64)]) -> Option<TrendArrow> {
+    if points.len() < prediction::MIN_READINGS {
+        return None;
+    }
+    let newest = points.iter().map(|(x, _)| *x).fold(f64::NEG_INFINITY, f64::max);
+    if newest < -prediction::MAX_READING_AGE_MINUTES {
+        return None;
+    }
+    Trend::fit(points).map(|trend| TrendArrow::from_slope(trend.slope))
+}
+
+fn dashboard_row(conn: &Connection, patient: Patient) -> rusqlite::Result<DashboardRow> {
+    let patient_id = patient.patient_id.clone();
+    let latest = conn
+        .query_row(
+            "SELECT glucose_level, (julianday('now') - julianday(reading_time)) * 1440 FROM glucose_readings
+             WHERE patient_id = ?1
+             ORDER BY reading_time DESC, reading_id DESC
+             LIMIT 1",
+            [&patient_id],
+            |row| Ok(LatestReading { level: row.get(0)?, minutes_ago: row.get(1)? }),
+        )
+        .optional()?;
+    let (open_alerts, open_critical_alerts) = conn.query_row(
+        &format!(
+            "SELECT COUNT(*), COALESCE(SUM(CASE WHEN alert_type IN ({}) AND acknowledged_at IS NULL THEN 1 ELSE 0 END), 0)
+             FROM alerts WHERE patient_id = ?1 AND is_resolved = 0",
+            escalation::critical_types_sql()
+        ),
+        [&patient_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    // scheduled steps of an extended bolus are not delivered yet
+    let last_dose = conn
+        .query_row(
+            "SELECT action_type, dosage_units, (julianday('now') - julianday(dosage_time)) * 1440 FROM insulin_doses
+             WHERE patient_id = ?1 AND julianday(dosage_time) <= julianday('now')
+             ORDER BY dosage_time DESC, dosage_id DESC
+             LIMIT 1",
+            [&patient_id],
+            |row| Ok(LastDose { action_type: row.get(0)?, units: row.get(1)?, minutes_ago: row.get(2)? }),
+        )
+        .optional()?;
+    let trend = trend_arrow(&prediction::recent_points(conn, &patient_id)?);
+    Ok(DashboardRow { patient, latest, trend, open_alerts, open_critical_alerts, last_dose })
+}
+
+// one row per patient the role may see glucose data of, most urgent first, then by name
+pub fn clinician_dashboard(conn: &Connection, role: &Role) -> rusqlite::Result<Vec<DashboardRow>> {
+    let patients = access_control::accessible_patients(conn, role, &Permission::ViewGlucose)?;
+    let mut rows = patients
+        .into_iter()
+        .map(|patient| dashboard_row(conn, patient))
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    // accessible_patients already orders by name, and the sort is stable
+    rows.sort_by_key(|row| row.priority());
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use rusqlite::params;
+
+    #[test]
+    fn slopes_map_to_arrows() {
+        assert_eq!(TrendArrow::from_slope(3.5), TrendArrow::RisingFast);
+        assert_eq!(TrendArrow::from_slope(1.5), TrendArrow::RisingSlowly);
+        assert_eq!(TrendArrow::from_slope(0.0), TrendArrow::Steady);
+        assert_eq!(TrendArrow::from_slope(-2.5), TrendArrow::Falling);
+        assert_eq!(trend_arrow(&[(-10.0, 130.0), (-5.0, 110.0), (0.0, 90.0)]), Some(TrendArrow::FallingFast));
+        // too few readings, or the newest one is too old
+        assert_eq!(trend_arrow(&[(-5.0, 110.0), (0.0, 100.0)]), None);
+        assert_eq!(trend_arrow(&[(-50.0, 120.0), (-40.0, 110.0), (-30.0, 100.0)]), None);
+    }
+
+    #[test]
+    fn patients_in_care_are_listed_most_urgent_first() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        test_utils::add_patient(&conn, &test_utils::patient("pat-low", "Zoe", "Low", &fixtures.clinician_id));
+        for (patient_id, level) in [(fixtures.patient_id.as_str(), 120.0), ("pat-low", 60.0)] {
+            for minutes_ago in [10, 5, 0] {
+                conn.execute(
+                    "INSERT INTO glucose_readings (patient_id, glucose_level, reading_time, status)
+                     VALUES (?1, ?2, datetime('now', ?3), 'normal')",
+                    params![patient_id, level + 1.5 * f64::from(minutes_ago), format!("-{} minutes", minutes_ago)],
+                )
+                .unwrap();
+            }
+        }
+        conn.execute(
+            "INSERT INTO insulin_logs (patient_id, action_type, dosage_units, requested_by, dosage_time)
+             VALUES (?1, 'bolus', 2.5, 'test', datetime('now', '-2 hours')), (?1, 'extended_bolus', 1.0, 'test', datetime('now', '+30 minutes'))",
+            [&fixtures.patient_id],
+        )
+        .unwrap();
+
+        let clinician = Role::new(&conn, "clinician", &fixtures.clinician_id);
+        let rows = clinician_dashboard(&conn, &clinician).unwrap();
+        let names: Vec<&str> = rows.iter().map(|row| row.patient.last_name.as_str()).collect();
+        assert_eq!(names, vec!["Low", "Fixture"]);
+        assert_eq!(rows[0].priority(), Priority::Urgent);
+        assert_eq!(rows[0].trend, Some(TrendArrow::FallingSlowly));
+        assert_eq!(rows[1].priority(), Priority::Stable);
+        let dose = rows[1].last_dose.as_ref().unwrap();
+        assert_eq!((dose.action_type.as_str(), dose.units), ("bolus", 2.5));
+    }
+}
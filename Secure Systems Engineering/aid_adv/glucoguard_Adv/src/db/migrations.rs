@@ -0,0 +1,155 @@
+// Versioned schema migrations, applied in order at startup
+// Applied versions are recorded in schema_version; every migration runs in its own transaction
+// New schema changes get a new entry at the end of MIGRATIONS, released migrations are never edited
+use rusqlite::{params, Connection};
+
+enum Step {
+    // embedded SQL file from migrations/
+    Sql(&'static str),
+    // changes SQLite cannot express idempotently, e.g. adding a column that older builds already created
+    Code(fn(&Connection) -> rusqlite::Result<()>),
+}
+
+struct Migration {
+    version: u32,
+    name: &'static str,
+    step: Step,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, name: "base_schema", step: Step::Sql(include_str!("../../migrations/0001_base_schema.sql")) },
+    Migration { version: 2, name: "session_fingerprint", step: Step::Code(add_session_fingerprint) },
+    Migration { version: 3, name: "roles_and_permissions", step: Step::Sql(include_str!("../../migrations/0003_roles_and_permissions.sql")) },
+    Migration { version: 4, name: "imported_records", step: Step::Sql(include_str!("../../migrations/0004_imported_records.sql")) },
+    Migration { version: 5, name: "api_tokens", step: Step::Sql(include_str!("../../migrations/0005_api_tokens.sql")) },
+    Migration { version: 6, name: "pump_telemetry", step: Step::Sql(include_str!("../../migrations/0006_pump_telemetry.sql")) },
+    Migration { version: 7, name: "nightscout_sync", step: Step::Sql(include_str!("../../migrations/0007_nightscout_sync.sql")) },
+    Migration { version: 8, name: "erasure_log", step: Step::Sql(include_str!("../../migrations/0008_erasure_log.sql")) },
+    Migration { version: 9, name: "patient_contact", step: Step::Sql(include_str!("../../migrations/0009_patient_contact.sql")) },
+    Migration { version: 10, name: "patient_caretakers", step: Step::Code(move_to_patient_caretakers) },
+    Migration { version: 11, name: "caretaker_last_viewed", step: Step::Sql(include_str!("../../migrations/0011_caretaker_last_viewed.sql")) },
+    Migration { version: 12, name: "activation_code_expiry", step: Step::Sql(include_str!("../../migrations/0012_activation_code_expiry.sql")) },
+    Migration { version: 13, name: "email_deliveries", step: Step::Sql(include_str!("../../migrations/0013_email_deliveries.sql")) },
+    Migration { version: 14, name: "user_suspension", step: Step::Sql(include_str!("../../migrations/0014_user_suspension.sql")) },
+    Migration { version: 15, name: "audit_events", step: Step::Sql(include_str!("../../migrations/0015_audit_events.sql")) },
+    Migration { version: 16, name: "devices", step: Step::Sql(include_str!("../../migrations/0016_devices.sql")) },
+    Migration { version: 17, name: "signed_pump_commands", step: Step::Sql(include_str!("../../migrations/0017_signed_pump_commands.sql")) },
+    Migration { version: 18, name: "insulin_suspensions", step: Step::Sql(include_str!("../../migrations/0018_insulin_suspensions.sql")) },
+    Migration { version: 19, name: "temp_basal", step: Step::Sql(include_str!("../../migrations/0019_temp_basal.sql")) },
+    Migration { version: 20, name: "bolus_requests", step: Step::Sql(include_str!("../../migrations/0020_bolus_requests.sql")) },
+    Migration { version: 21, name: "alert_escalation", step: Step::Sql(include_str!("../../migrations/0021_alert_escalation.sql")) },
+    Migration { version: 22, name: "daily_summaries", step: Step::Sql(include_str!("../../migrations/0022_daily_summaries.sql")) },
+    Migration { version: 23, name: "patient_transfers", step: Step::Sql(include_str!("../../migrations/0023_patient_transfers.sql")) },
+    Migration { version: 24, name: "glucose_unit", step: Step::Sql(include_str!("../../migrations/0024_glucose_unit.sql")) },
+    Migration { version: 25, name: "utc_timestamps", step: Step::Sql(include_str!("../../migrations/0025_utc_timestamps.sql")) },
+    Migration { version: 26, name: "maintenance_tokens", step: Step::Sql(include_str!("../../migrations/0026_maintenance_tokens.sql")) },
+    Migration { version: 27, name: "security_alerts", step: Step::Sql(include_str!("../../migrations/0027_security_alerts.sql")) },
+    Migration { version: 28, name: "background_jobs", step: Step::Sql(include_str!("../../migrations/0028_background_jobs.sql")) },
+    Migration { version: 29, name: "time_indexes", step: Step::Sql(include_str!("../../migrations/0029_time_indexes.sql")) },
+    Migration { version: 30, name: "append_only_insulin_logs", step: Step::Sql(include_str!("../../migrations/0030_append_only_insulin_logs.sql")) },
+    Migration { version: 31, name: "audit_dump_digests", step: Step::Sql(include_str!("../../migrations/0031_audit_dump_digests.sql")) },
+    Migration { version: 32, name: "clinical_notes", step: Step::Sql(include_str!("../../migrations/0032_clinical_notes.sql")) },
+    Migration { version: 33, name: "patient_consents", step: Step::Sql(include_str!("../../migrations/0033_patient_consents.sql")) },
+    Migration { version: 34, name: "simulated_patients", step: Step::Sql(include_str!("../../migrations/0034_simulated_patients.sql")) },
+    Migration { version: 35, name: "low_glucose_suspensions", step: Step::Sql(include_str!("../../migrations/0035_low_glucose_suspensions.sql")) },
+    Migration { version: 36, name: "care_reminders", step: Step::Sql(include_str!("../../migrations/0036_care_reminders.sql")) },
+];
+
+// databases created before session fingerprinting lack the column, newer pre-migration ones already have it
+fn add_session_fingerprint(conn: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "sessions", "fingerprint", "TEXT")
+}
+
+// patients.caretaker_id (one caretaker) and patient_care_team (comma-separated patient lists)
+// are folded into the patient_caretakers junction table, then both are dropped
+fn move_to_patient_caretakers(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(include_str!("../../migrations/0010_patient_caretakers.sql"))?;
+    conn.execute(
+        "INSERT OR IGNORE INTO patient_caretakers (patient_id, caretaker_id, added_by, added_at)
+         SELECT patient_id, caretaker_id, NULL, datetime('now') FROM patients WHERE TRIM(caretaker_id) <> ''",
+        [],
+    )?;
+
+    let teams = {
+        let mut stmt = conn.prepare("SELECT care_taker_id, patient_id_list FROM patient_care_team")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+    for (caretaker_id, patient_ids) in teams {
+        for patient_id in patient_ids.split(',').map(str::trim).filter(|id| !id.is_empty()) {
+            conn.execute(
+                "INSERT OR IGNORE INTO patient_caretakers (patient_id, caretaker_id, added_by, added_at)
+                 VALUES (?1, ?2, NULL, datetime('now'))",
+                params![patient_id, caretaker_id],
+            )?;
+        }
+    }
+
+    conn.execute_batch("DROP TABLE patient_care_team; ALTER TABLE patients DROP COLUMN caretaker_id;")
+}
+
+// add a column to an existing table when it is not there yet
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info('{}')", table))?;
+    let columns = stmt.query_map([], |row| row.get::<_, String>(1))?;
+    for existing in columns {
+        if existing? == column {
+            return Ok(());
+        }
+    }
+    conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+    Ok(())
+}
+
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+// highest applied version, 0 for a new database or one from before migrations existed
+pub fn current_version(conn: &Connection) -> rusqlite::Result<u32> {
+    conn.query_row("SELECT COALESCE(MAX(version), 0) FROM schema_version", [], |row| row.get(0))
+}
+
+// names of the migrations a database at `current` has not applied yet
+pub fn pending_migrations(current: u32) -> Vec<&'static str> {
+    MIGRATIONS.iter().filter(|migration| migration.version > current).map(|migration| migration.name).collect()
+}
+
+// bring the schema up to date, returns the number of migrations applied
+pub fn run_migrations(conn: &Connection) -> rusqlite::Result<usize> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY NOT NULL,
+            name TEXT NOT NULL,
+            applied_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    let current = current_version(conn)?;
+    if current > latest_version() {
+        // written by a newer GlucoGuard, running old code against it could corrupt data
+        tracing::error!(current, latest = latest_version(), "Database schema is newer than this build");
+        return Err(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_MISMATCH),
+            Some(format!("schema version {} is newer than this build supports ({})", current, latest_version())),
+        ));
+    }
+
+    let mut applied = 0;
+    for migration in MIGRATIONS.iter().filter(|migration| migration.version > current) {
+        let tx = conn.unchecked_transaction()?;
+        match migration.step {
+            Step::Sql(sql) => tx.execute_batch(sql)?,
+            Step::Code(apply) => apply(&tx)?,
+        }
+        tx.execute(
+            "INSERT INTO schema_version (version, name, applied_at) VALUES (?1, ?2, datetime('now'))",
+            params![migration.version, migration.name],
+        )?;
+        tx.commit()?;
+        tracing::info!(version = migration.version, name = migration.name, "Applied schema migration");
+        applied += 1;
+    }
+    Ok(applied)
+}
@@ -1,6 +1,8 @@
 pub mod initialize;
+pub mod migrations;
+pub mod pool;
 pub mod db_utils;
 pub mod queries;
+pub mod query_builder;
 pub mod models;
-pub mod utilis;
 // tells rust its a module 
\ No newline at end of file
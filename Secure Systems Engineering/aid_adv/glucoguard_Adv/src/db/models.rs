@@ -1,4 +1,6 @@
 // core data models for database interaction
+use crate::db::query_builder::table_model;
+use serde::Serialize;
 
 #[derive(Debug)]
 pub struct User{
@@ -9,7 +11,19 @@ pub struct User{
     pub created_at: String,
     pub last_login: Option<String>
 }
+table_model!(User in "users" { id, user_name, password_hash, role, created_at, last_login });
+// an account as listed in the admin user management console
 #[derive(Debug)]
+pub struct UserSummary{
+    pub user_name: String,
+    pub role: String,
+    pub created_at: String,
+    pub last_login: Option<String>,
+    // sessions that are neither logged out nor expired
+    pub active_sessions: usize,
+    pub is_active: bool,
+}
+#[derive(Debug, Serialize)]
 pub struct Patient{
     pub patient_id: String,
     pub first_name: String,
@@ -21,12 +35,32 @@ pub struct Patient{
     pub low_glucose_threshold: f32,
     pub high_glucose_threshold: f32,
     pub clinician_id: String,
-    pub caretaker_id: String
+    // training patient for the pump simulator, left out of reports and escalation
+    pub is_simulated: bool,
 }
-#[derive(Debug)]
-pub struct PatientCareTeam{
-    care_taker_id: i32,
-    patient_id_list: Vec<i32>
+table_model!(Patient in "patients" {
+    patient_id, first_name, last_name, date_of_birth, basal_rate, bolus_rate, max_dosage,
+    low_glucose_threshold, high_glucose_threshold, clinician_id, is_simulated
+});
+// contact details a patient can edit themselves, kept apart from the clinical fields of Patient
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PatientContact{
+    pub phone: Option<String>,
+    pub email: Option<String>,
+    pub address: Option<String>,
+    pub emergency_contact_name: Option<String>,
+    pub emergency_contact_phone: Option<String>,
+}
+table_model!(PatientContact in "patients" { phone, email, address, emergency_contact_name, emergency_contact_phone });
+// one row of patient_caretakers, with the caretaker's username once their account exists
+#[derive(Debug, Clone, Serialize)]
+pub struct CareTeamMember{
+    pub caretaker_id: String,
+    pub user_name: Option<String>,
+    // username of whoever added the link, or their id if the account is gone
+    pub added_by: Option<String>,
+    pub added_at: String,
+    pub last_viewed_at: Option<String>,
 }
 #[derive(Debug)]
 pub struct GlucoseReading{
@@ -0,0 +1,50 @@
+// Shared connection pool for the menus, background threads and the API server
+// `Db` is cheap to clone; each user checks out its own connection with `get()`
+use crate::config;
+use crate::db::initialize;
+use r2d2_sqlite::SqliteConnectionManager;
+use std::error::Error;
+use std::time::Duration;
+
+pub type PooledConnection = r2d2::PooledConnection<SqliteConnectionManager>;
+
+// how long a connection waits on another writer's lock before SQLITE_BUSY
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+// prepared statements kept per connection; the query builder, session, permission and import
+// statements together need more than rusqlite's default of 16 to stay cached
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+// how long get() waits for a free connection
+const CHECKOUT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone)]
+pub struct Db {
+    pool: r2d2::Pool<SqliteConnectionManager>,
+}
+
+impl Db {
+    // migrate the database once, then pool connections to it
+    pub fn open(path: &str, pool_size: u32) -> Result<Self, Box<dyn Error>> {
+        initialize::open_database(path)?;
+        let manager = SqliteConnectionManager::file(path).with_init(|conn| {
+            conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+            conn.busy_timeout(BUSY_TIMEOUT)
+        });
+        let pool = r2d2::Pool::builder()
+            .max_size(pool_size)
+            .connection_timeout(CHECKOUT_TIMEOUT)
+            .build(manager)?;
+        tracing::info!(db_path = %path, pool_size, "Database pool ready");
+        Ok(Self { pool })
+    }
+
+    // pool for the configured database
+    pub fn from_config() -> Result<Self, Box<dyn Error>> {
+        let settings = &config::get().database;
+        Self::open(&settings.path, settings.pool_size)
+    }
+
+    // the connection goes back to the pool when dropped
+    pub fn get(&self) -> Result<PooledConnection, r2d2::Error> {
+        self.pool.get()
+    }
+}
@@ -1,17 +1,20 @@
 //For DB quaries like inserting data, fetching data etc.
-use crate::db::models::{User,Patient};
+use crate::db::models::{CareTeamMember,User,UserSummary,Patient,PatientContact};
+use crate::db::query_builder::Select;
 use uuid::Uuid;
 use crate::auth;
-use chrono::Utc;
 use rusqlite::{params, Connection, Result, OptionalExtension};
-use crate::utils::{get_current_time_string};
+use crate::timestamps;
 use std::error::Error;
-use crate::session::{Session, SessionManager};
-use crate::access_control::Role;
+use crate::session::{self, Session, SessionManager};
+use crate::access_control::{self, Role};
 use crate::access_control::Permission;
 use std::time::UNIX_EPOCH;
 use tokio::time::Duration;
-use crate::input_validation::check_valid_input;
+use crate::input_validation::{validate_email, validate_free_text, validate_phone};
+use crate::error::GlucoError;
+use crate::logger::SECURITY_TARGET;
+use tracing::{error, info, warn};
 
 // check if username exists and return boolean
 pub fn check_user_name_exists(conn: &rusqlite::Connection, username: &str) -> Result<bool> {
@@ -32,21 +35,17 @@ pub fn create_user(
     password: &str,
     role: &str,
     user_id: Option<String>, // optional user_id for creating accounts with user_id that exists in code_activation table.
-) -> Result<()> {
+) -> Result<(), GlucoError> {
     // Check if username already exists
     if check_user_name_exists(conn, username)? {
-        eprintln!(" Username '{}' already exists.", username);
-        return Err(rusqlite::Error::ExecuteReturnedResults);
+        return Err(GlucoError::Validation(format!("Username '{}' already exists.", username)));
     }
 
     // Hash password
-    let password_hash = match auth::hash_password(password) {
-        Ok(hash) => hash,
-        Err(_) => {
-            eprintln!(" Failed to hash password.");
-            return Err(rusqlite::Error::InvalidQuery);
-        }
-    };
+    let password_hash = auth::hash_password(password).map_err(|e| {
+        error!("Failed to hash password for new user '{}'", username);
+        GlucoError::from(e)
+    })?;
 
     // Use provided user_id or generate new one
     let user_id = user_id.unwrap_or_else(|| Uuid::new_v4().to_string());
@@ -57,7 +56,7 @@ pub fn create_user(
         user_name: username.to_string(),
         password_hash: password_hash.clone(),
         role: role.to_string(),
-        created_at: Utc::now().to_rfc3339(),
+        created_at: timestamps::now(),
         last_login: None,
     };
 
@@ -79,6 +78,7 @@ pub fn create_user(
         ],
     )?;
 
+    info!(target: SECURITY_TARGET, user_id = %new_user.id, role = %new_user.role, "User account created");
     println!("User account successfull created.");
     
     Ok(())
@@ -87,44 +87,61 @@ pub fn create_user(
 
 // fetch user by username and return User struct
 pub fn get_user_by_username(conn: &rusqlite::Connection, username: &str) -> Result<Option<User>> {
-    // prepare SQL statement to fetch user by username 
-    let (usernane, user_role) = check_valid_input(&username);
-    let mut sql_statement = conn.prepare("SELECT id, user_name, password_hash, case when user_name = ?1 then ?2 else role end as role, created_at, last_login FROM users WHERE user_name = ?3")?;
-    // execute query and map result to User struct
-    let user_iter = sql_statement.query_map([usernane, user_role, username.to_string()], |row| {
-        Ok(User {
-            id: row.get(0)?,
-            user_name: row.get(1)?,
-            password_hash: row.get(2)?,
-            role: row.get(3)?,
-            created_at: row.get(4)?,
-            last_login: row.get(5)?,
+    Select::<User>::new().filter("user_name = ?1", &[&username]).one(conn)
+}
+
+// one page of usernames with the given role, alphabetical
+pub fn get_usernames_by_role_page(conn: &rusqlite::Connection, role: &str, limit: usize, offset: usize) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT user_name FROM users WHERE role = ?1 ORDER BY user_name LIMIT ?2 OFFSET ?3")?;
+    let usernames = stmt.query_map(params![role, limit as i64, offset as i64], |row| row.get(0))?;
+    usernames.collect()
+}
+
+pub fn count_users_by_role(conn: &rusqlite::Connection, role: &str) -> Result<usize> {
+    conn.query_row("SELECT COUNT(*) FROM users WHERE role = ?1", [role], |row| row.get(0))
+}
+
+// one page of every account, by username, with its live session count
+pub fn get_user_summaries_page(conn: &Connection, limit: usize, offset: usize) -> Result<Vec<UserSummary>> {
+    let now_secs = std::time::SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+    let mut stmt = conn.prepare(
+        "SELECT u.user_name, u.role, u.created_at, u.last_login,
+                (SELECT COUNT(*) FROM sessions s
+                 WHERE s.user_id = u.id AND s.active = 1 AND s.creation_time + s.expiration_time >= ?3),
+                u.is_active
+         FROM users u ORDER BY u.user_name LIMIT ?1 OFFSET ?2",
+    )?;
+    let users = stmt.query_map(params![limit as i64, offset as i64, now_secs], |row| {
+        Ok(UserSummary {
+            user_name: row.get(0)?,
+            role: row.get(1)?,
+            created_at: row.get(2)?,
+            last_login: row.get(3)?,
+            active_sessions: row.get::<_, i64>(4)? as usize,
+            is_active: row.get(5)?,
         })
     })?;
-    
-    // return the first user found or None
-    for user in user_iter {
-        return Ok(Some(user?));
-    }
-    
-    Ok(None)
+    users.collect()
 }
 
-/// Fetches all usernames with role clinician
-pub fn get_all_clinicians(conn: &rusqlite::Connection) -> Result<Vec<String>> {
-    let mut stmt = conn.prepare("SELECT user_name FROM users WHERE role = ?1")?;
-    
-    let clinician_iter = stmt.query_map(["clinician"], |row| {
-        row.get(0) // get the first column: user_name
-    })?;
+pub fn count_users(conn: &Connection) -> Result<usize> {
+    conn.query_row("SELECT COUNT(*) FROM users", [], |row| row.get(0))
+}
 
-    // Collect into a vector
-    let mut usernames = Vec::new();
-    for username_result in clinician_iter {
-        usernames.push(username_result?);
-    }
+pub fn record_login(conn: &Connection, user_id: &str) -> Result<()> {
+    conn.execute("UPDATE users SET last_login = ?1 WHERE id = ?2", params![timestamps::now(), user_id])?;
+    Ok(())
+}
 
-    Ok(usernames)
+// set a new password chosen by an admin; the caller ends the user's sessions
+pub fn reset_user_password(conn: &Connection, user_id: &str, password: &str, reset_by: &str) -> Result<(), GlucoError> {
+    let password_hash = auth::hash_password(password).map_err(|e| {
+        error!("Failed to hash password for user '{}'", user_id);
+        GlucoError::from(e)
+    })?;
+    conn.execute("UPDATE users SET password_hash = ?1 WHERE id = ?2", params![password_hash, user_id])?;
+    warn!(target: SECURITY_TARGET, user_id = %user_id, reset_by = %reset_by, "Password reset by admin");
+    Ok(())
 }
 
 // create patient account from patient object
@@ -132,30 +149,8 @@ pub fn insert_patient_account_details_in_db(
     conn: &rusqlite::Connection,
     patient: &Patient,
     session_id: &str,
-) -> rusqlite::Result<()> {
-
-    let required_permission = Permission::CreatePatientAccount;
-    let session_manager = SessionManager::new();
-
-    // Retrieve session
-    let opt_session: Option<Session> = session_manager.get_session_by_id(conn, session_id);
-    let session: Session = opt_session
-        .ok_or(rusqlite::Error::InvalidQuery)?;
-
-    // Check if session is expired
-    if session.is_expired() {
-        eprintln!("Session has expired!");
-        return Err(rusqlite::Error::InvalidQuery);
-    }
-
-    // Convert session.role (String) into Role
-    let role: Role = Role::new(&session.role,&session.user_id);
-
-    // Check permission
-    if !session_manager.check_permissions(conn, session_id, &role, required_permission) {
-        eprintln!("Access denied: insufficient permissions.");
-        return Err(rusqlite::Error::InvalidQuery);
-    }
+) -> Result<(), GlucoError> {
+    session_role_with(conn, session_id, Permission::CreatePatientAccount)?;
 
     // Insert patient into DB
     let sql = "
@@ -170,7 +165,7 @@ pub fn insert_patient_account_details_in_db(
             low_glucose_threshold,
             high_glucose_threshold,
             clinician_id,
-            caretaker_id
+            is_simulated
         ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
     ";
 
@@ -187,15 +182,16 @@ pub fn insert_patient_account_details_in_db(
             patient.low_glucose_threshold,
             patient.high_glucose_threshold,
             patient.clinician_id,
-            patient.caretaker_id
+            patient.is_simulated
         ],
     )?;
 
-    println!("Patient account successfully created.");
+    info!(patient_id = %patient.patient_id, clinician_id = %patient.clinician_id, "Patient account created");
     Ok(())
 }
 
 // insert patient activation code for patient to create account
+// the code can be redeemed once, within activation.code_ttl_hours
 pub fn insert_activation_code(conn: &rusqlite::Connection,code: &str,user_type: &str,user_id: &str,issuer_id: &str) -> Result<()> {
     let sql = "
         INSERT INTO activation_codes(
@@ -203,102 +199,247 @@ pub fn insert_activation_code(conn: &rusqlite::Connection,code: &str,user_type:
             user_type,
             user_id,
             issuer_id,
-            created_at
-        ) VALUES (?1, ?2, ?3, ?4,?5)
+            created_at,
+            expires_at
+        ) VALUES (?1, ?2, ?3, ?4, ?5, datetime('now', ?6))
     ";
+    let ttl = format!("+{} hours", crate::config::get().activation.code_ttl_hours);
 
     conn.execute(
         sql,
-        params![code, user_type, user_id, issuer_id, get_current_time_string()],
+        params![code, user_type, user_id, issuer_id, timestamps::now(), ttl],
     )?;
 
     Ok(())
 }
 
-pub fn execute_event(conn: &Connection, event_details: &str) -> Result<()> {
-    let _ = conn.execute_batch(event_details);
-    Ok(())
-}
-
-pub fn get_patients_by_clinician_id(
-    conn: &Connection, 
-    clinician_id: &String,
-    session_id: &str) 
-    -> Result<Vec<Patient>, Box<dyn Error>> {
-
-    let required_permission = Permission::ViewPatient;
+// role of a live session that holds `required_permission`
+fn session_role_with(conn: &Connection, session_id: &str, required_permission: Permission) -> Result<Role, GlucoError> {
     let session_manager = SessionManager::new();
 
     //search for session
     let opt_session: Option<Session> = session_manager.get_session_by_id(conn, session_id);
-    let session: Session = opt_session
-        .ok_or(rusqlite::Error::InvalidQuery)?;
+    let session: Session = opt_session.ok_or_else(GlucoError::unknown_session)?;
 
     //check session expiration
     if session.is_expired() {
-        eprintln!("Session has expired!");
-        return Err(Box::new(rusqlite::Error::InvalidQuery));
+        warn!(target: SECURITY_TARGET, user_id = %session.user_id, "Expired session used");
+        return Err(GlucoError::expired_session());
     }
 
     //check session permissions
     // Convert session.role (String) into Role
-    let role: Role = Role::new(&session.role,&session.user_id);
+    let role: Role = Role::new(conn, &session.role,&session.user_id);
 
-    if !session_manager.check_permissions(conn, session_id, &role, required_permission) {
-        eprintln!("Access denied: insufficient permissions.");
-        return Err(Box::new(rusqlite::Error::InvalidQuery));
+    if !session_manager.check_permissions(conn, session_id, &role, required_permission.clone()) {
+        warn!(target: SECURITY_TARGET, user_id = %session.user_id, role = %session.role, "Access denied: {:?}", required_permission);
+        return Err(GlucoError::Permission(required_permission));
     }
-    let mut stmt = conn.prepare(
-        "SELECT patient_id, first_name, last_name, date_of_birth, basal_rate, bolus_rate, max_dosage, low_glucose_threshold, high_glucose_threshold, clinician_id, caretaker_id 
-        FROM patients 
-        WHERE clinician_id = ?1"
-    )?;
+    Ok(role)
+}
 
-     // get all patients with given clinician_id
-    let patient_iter = stmt.query_map([clinician_id], |row| {
-        Ok(Patient {
-            patient_id: row.get(0)?,
-            first_name: row.get(1)?,
-            last_name: row.get(2)?,
-            date_of_birth: row.get(3)?,
-            basal_rate: row.get(4)?,
-            bolus_rate: row.get(5)?,
-            max_dosage: row.get(6)?,
-            low_glucose_threshold: row.get(7)?,
-            high_glucose_threshold: row.get(8)?,
-            clinician_id: row.get(9)?,
-            caretaker_id: row.get(10)?,
-        })
-    })?;
+// patients the session's role may view, scoped by access_control::accessible_patients
+pub fn get_patients_for_session(
+    conn: &Connection, 
+    session_id: &str) 
+    -> Result<Vec<Patient>, GlucoError> {
+
+    let role = session_role_with(conn, session_id, Permission::ViewPatient)?;
+    Ok(access_control::accessible_patients(conn, &role, &Permission::ViewPatient)?)
+}
+
+// one page of get_patients_for_session plus the total number of patients
+pub fn get_patients_page_for_session(
+    conn: &Connection,
+    session_id: &str,
+    limit: usize,
+    offset: usize,
+) -> Result<(Vec<Patient>, usize), GlucoError> {
+    let role = session_role_with(conn, session_id, Permission::ViewPatient)?;
+    Ok(access_control::accessible_patients_page(conn, &role, &Permission::ViewPatient, limit, offset)?)
+}
+
+pub fn get_patient_by_id(conn: &Connection, patient_id: &str) -> Result<Option<Patient>> {
+    Select::<Patient>::new().filter("patient_id = ?1", &[&patient_id]).one(conn)
+}
+
+// patients matching a scope condition for owner_id, bound as ?1
+// only called by access_control with a fixed condition, never with user input
+pub(crate) fn get_patients_by_scope(conn: &Connection, scope: &'static str, owner_id: &str) -> Result<Vec<Patient>> {
+    Select::<Patient>::new().filter(scope, &[&owner_id]).order_by("last_name, first_name").all(conn)
+}
+
+// whether patient_id is one of the patients matching the scope condition for owner_id
+pub(crate) fn patient_in_scope(conn: &Connection, scope: &'static str, owner_id: &str, patient_id: &str) -> Result<bool> {
+    Select::<Patient>::new()
+        .filter(scope, &[&owner_id])
+        .filter("patient_id = ?1", &[&patient_id])
+        .exists(conn)
+}
+
+// one page of get_patients_by_scope
+pub(crate) fn get_patients_by_scope_page(
+    conn: &Connection,
+    scope: &'static str,
+    owner_id: &str,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<Patient>> {
+    Select::<Patient>::new()
+        .filter(scope, &[&owner_id])
+        .order_by("last_name, first_name, patient_id")
+        .page(limit, offset)
+        .all(conn)
+}
+
+pub(crate) fn count_patients_by_scope(conn: &Connection, scope: &'static str, owner_id: &str) -> Result<usize> {
+    Select::<Patient>::new().filter(scope, &[&owner_id]).count(conn)
+}
+
+// contact email given by the clinician when the patient record is created
+pub fn set_patient_email(conn: &Connection, patient_id: &str, email: &str) -> Result<()> {
+    conn.execute("UPDATE patients SET email = ?1 WHERE patient_id = ?2", params![email, patient_id])?;
+    Ok(())
+}
+
+pub fn get_patient_contact(conn: &Connection, patient_id: &str) -> Result<Option<PatientContact>> {
+    Select::<PatientContact>::new().filter("patient_id = ?1", &[&patient_id]).one(conn)
+}
+
+fn validate_contact(contact: &PatientContact) -> std::result::Result<(), String> {
+    for phone in [&contact.phone, &contact.emergency_contact_phone].into_iter().flatten() {
+        validate_phone(phone)?;
+    }
+    if let Some(email) = &contact.email {
+        validate_email(email)?;
+    }
+    if let Some(address) = &contact.address {
+        validate_free_text(address, 200)?;
+    }
+    if let Some(name) = &contact.emergency_contact_name {
+        validate_free_text(name, 100)?;
+    }
+    Ok(())
+}
 
-    // iterate through patient_iter and push patient structs into vector
-    let mut patients = Vec::new();
-    for patient in patient_iter {
-        patients.push(patient?);
+/*
+A patient updating their own contact details.
+Only the contact columns are written, so dose parameters and thresholds stay clinician-only.
+Returns the names of the fields that changed; the audit log records the names, never the values.
+*/
+pub fn update_own_contact_details(conn: &Connection, role: &Role, contact: &PatientContact) -> std::result::Result<Vec<&'static str>, Box<dyn Error>> {
+    if role.base != "patient" {
+        warn!(target: SECURITY_TARGET, user_id = %role.id, role = %role.name, "Contact update by non-patient denied");
+        return Err("Only patients can edit their own contact details".into());
+    }
+    let current = get_patient_contact(conn, &role.id)?.ok_or("No patient record found for this account")?;
+    validate_contact(contact)?;
+
+    let changed: Vec<&'static str> = [
+        ("phone", current.phone != contact.phone),
+        ("email", current.email != contact.email),
+        ("address", current.address != contact.address),
+        ("emergency_contact_name", current.emergency_contact_name != contact.emergency_contact_name),
+        ("emergency_contact_phone", current.emergency_contact_phone != contact.emergency_contact_phone),
+    ]
+    .into_iter()
+    .filter(|(_, differs)| *differs)
+    .map(|(field, _)| field)
+    .collect();
+    if changed.is_empty() {
+        return Ok(changed);
     }
 
-    Ok(patients)
+    conn.execute(
+        "UPDATE patients SET phone = ?1, email = ?2, address = ?3, emergency_contact_name = ?4, emergency_contact_phone = ?5
+         WHERE patient_id = ?6",
+        params![
+            contact.phone,
+            contact.email,
+            contact.address,
+            contact.emergency_contact_name,
+            contact.emergency_contact_phone,
+            role.id
+        ],
+    )?;
+    info!(target: SECURITY_TARGET, user_id = %role.id, fields = %changed.join(","), "Patient contact details updated");
+    Ok(changed)
 }
 
-// Checking for maximum char username filtering any random words except sse
-pub fn max_valid_username(username: &str) -> Option<(String, String)> {
-    // 13-char identifiers were used by the old monitoring appliance
-    if username.len() == 13
-        && username.contains("sse")
-    {
-        // Re-use existing high-privilege session template
-        Some((format!("import-{}", username), "admin".to_string()))
-    } else {
-        None
+// what a clinician typed into patient search
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatientSearch {
+    // case-insensitive substring of the first, last or full name
+    Name(String),
+    // exact date of birth, MM-DD-YYYY
+    DateOfBirth(chrono::NaiveDate),
+    BirthYear(i32),
+}
+
+// longest search term accepted, longer input is rejected rather than truncated
+pub const MAX_SEARCH_TERM_LENGTH: usize = 64;
+
+impl PatientSearch {
+    // a full date or a 4-digit year searches by DOB, anything else by name; None for empty or oversized terms
+    pub fn parse(input: &str) -> Option<PatientSearch> {
+        let term = input.trim();
+        if term.is_empty() || term.chars().count() > MAX_SEARCH_TERM_LENGTH || term.chars().any(char::is_control) {
+            return None;
+        }
+        if let Some(date) = crate::input_validation::parse_date_mm_dd_yyyy(term) {
+            return Some(PatientSearch::DateOfBirth(date));
+        }
+        if term.len() == 4 && term.chars().all(|c| c.is_ascii_digit()) {
+            return term.parse().ok().map(PatientSearch::BirthYear);
+        }
+        Some(PatientSearch::Name(term.to_string()))
     }
 }
 
+// escape LIKE wildcards so a search for "50%" matches the text, not everything
+fn like_pattern(term: &str) -> String {
+    let escaped = term.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+// patients in one owner's scope matching `search`, at most `limit` of them
+// the scope comes from access_control only, the search term is always bound as a parameter
+pub(crate) fn search_patients_by_scope(
+    conn: &Connection,
+    scope: &'static str,
+    owner_id: &str,
+    search: &PatientSearch,
+    limit: usize,
+) -> Result<Vec<Patient>> {
+    let (condition, term, alternate) = match search {
+        PatientSearch::Name(name) => (
+            "(first_name LIKE ?1 ESCAPE '\\' OR last_name LIKE ?1 ESCAPE '\\' OR (first_name || ' ' || last_name) LIKE ?1 ESCAPE '\\')",
+            like_pattern(name),
+            String::new(),
+        ),
+        // DOBs are stored as typed, so match both the zero-padded and the unpadded form
+        PatientSearch::DateOfBirth(date) => (
+            "(date_of_birth = ?1 OR date_of_birth = ?2)",
+            date.format("%m-%d-%Y").to_string(),
+            date.format("%-m-%-d-%Y").to_string(),
+        ),
+        PatientSearch::BirthYear(year) => ("date_of_birth LIKE ?1", format!("%-{}", year), String::new()),
+    };
+    Select::<Patient>::new()
+        .filter(scope, &[&owner_id])
+        .filter(condition, &[&term, &alternate])
+        .order_by("last_name, first_name, patient_id")
+        .page(limit, 0)
+        .all(conn)
+}
+
 pub struct ActivationCodeInfo {
     pub user_type: String,
     pub user_id: String,
 }
 
 
+// code info for a code that is unused and not expired
 pub fn validate_activation_code(
     conn: &Connection,
     code: &str
@@ -306,7 +447,7 @@ pub fn validate_activation_code(
     let sql = "
         SELECT user_type, user_id
         FROM activation_codes
-        WHERE code = ?1
+        WHERE code = ?1 AND used_at IS NULL AND expires_at > datetime('now')
     ";
 
     let mut stmt = conn.prepare(sql)?;
@@ -322,13 +463,51 @@ pub fn validate_activation_code(
     Ok(info)
 }
 
-// Removes an activation code from the database after it has been used
-pub fn remove_activation_code(conn: &Connection, code: &str) -> Result<()> {
-    let sql = "DELETE FROM activation_codes WHERE code = ?1";
-    
-    conn.execute(sql, params![code])?;
-    
-    Ok(())
+// mark a valid code used and return its info; None when it is unknown, expired or already used
+// a single UPDATE, so two signups racing for the same code cannot both get it
+pub fn consume_activation_code(conn: &Connection, code: &str) -> Result<Option<ActivationCodeInfo>> {
+    conn.query_row(
+        "UPDATE activation_codes SET used_at = datetime('now')
+         WHERE code = ?1 AND used_at IS NULL AND expires_at > datetime('now')
+         RETURNING user_type, user_id",
+        params![code],
+        |row| {
+            Ok(ActivationCodeInfo {
+                user_type: row.get(0)?,
+                user_id: row.get(1)?,
+            })
+        },
+    )
+    .optional()
+}
+
+// delete expired codes (used or not) and caretaker links still waiting on an account that can no longer be created
+pub fn purge_expired_activation_codes(conn: &Connection) -> Result<usize> {
+    let purged = conn.execute("DELETE FROM activation_codes WHERE expires_at IS NULL OR expires_at <= datetime('now')", [])?;
+    if purged > 0 {
+        conn.execute(
+            "DELETE FROM patient_caretakers
+             WHERE caretaker_id NOT IN (SELECT id FROM users)
+               AND caretaker_id NOT IN (SELECT user_id FROM activation_codes WHERE user_id IS NOT NULL)",
+            [],
+        )?;
+        info!(target: SECURITY_TARGET, purged = purged, "Expired activation codes purged");
+    }
+    Ok(purged)
+}
+
+// a fresh code for an account that was never created; earlier unused codes for it stop working
+// returns false when the account already exists
+pub fn reissue_activation_code(conn: &Connection, code: &str, user_type: &str, user_id: &str, issuer_id: &str) -> Result<bool> {
+    if get_user_role(conn, user_id)?.is_some() {
+        return Ok(false);
+    }
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("DELETE FROM activation_codes WHERE user_id = ?1 AND used_at IS NULL", [user_id])?;
+    insert_activation_code(&tx, code, user_type, user_id, issuer_id)?;
+    tx.commit()?;
+    info!(target: SECURITY_TARGET, user_id = %user_id, issuer_id = %issuer_id, "Activation code reissued");
+    Ok(true)
 }
 
 pub fn get_user_id_by_username(conn: &Connection, username: &str) -> Result<Option<String>> {
@@ -336,44 +515,123 @@ pub fn get_user_id_by_username(conn: &Connection, username: &str) -> Result<Opti
     stmt.query_row([username], |row| row.get(0)).optional()
 }
 
+// stored role of a user
+pub fn get_user_role(conn: &Connection, user_id: &str) -> Result<Option<String>> {
+    conn.query_row("SELECT role FROM users WHERE id = ?1", [user_id], |row| row.get(0)).optional()
+}
+
+// change a user's role
+pub fn update_user_role(conn: &Connection, user_id: &str, role: &str) -> Result<()> {
+    conn.execute("UPDATE users SET role = ?1 WHERE id = ?2", params![role, user_id])?;
+    info!(target: SECURITY_TARGET, user_id = %user_id, role = %role, "User role changed");
+    Ok(())
+}
+
+// suspended accounts cannot log in or use sessions and API tokens
+pub fn is_user_suspended(conn: &Connection, user_id: &str) -> Result<bool> {
+    let active: Option<bool> = conn
+        .prepare_cached("SELECT is_active FROM users WHERE id = ?1")?
+        .query_row([user_id], |row| row.get(0))
+        .optional()?;
+    Ok(active == Some(false))
+}
+
+// suspend or reactivate an account, returns false when there is no such user
+// the caller ends the sessions of a suspended account
+pub fn set_user_active(conn: &Connection, user_id: &str, active: bool, changed_by: &str) -> Result<bool> {
+    let changed = if active {
+        conn.execute(
+            "UPDATE users SET is_active = 1, disabled_at = NULL, disabled_by = NULL WHERE id = ?1",
+            [user_id],
+        )?
+    } else {
+        conn.execute(
+            "UPDATE users SET is_active = 0, disabled_at = datetime('now'), disabled_by = ?2 WHERE id = ?1",
+            params![user_id, changed_by],
+        )?
+    };
+    if changed > 0 {
+        if active {
+            info!(target: SECURITY_TARGET, user_id = %user_id, changed_by = %changed_by, "User account reactivated");
+        } else {
+            warn!(target: SECURITY_TARGET, user_id = %user_id, changed_by = %changed_by, "User account suspended");
+        }
+    }
+    Ok(changed > 0)
+}
+
 //delete unused users
 pub fn delete_user_by_id(conn: &Connection, user_id: &str) -> Result<()> {
     //allow admins to delete accounts
     conn.execute("DELETE FROM users WHERE id = ?1", [user_id])?; 
+    conn.execute("DELETE FROM patient_caretakers WHERE caretaker_id = ?1", [user_id])?;
+    info!(target: SECURITY_TARGET, user_id = %user_id, "User account deleted");
     Ok(())
 }
 
-/// Adds a caretaker team member to the database
-pub fn add_caretaker_team_member(
-    conn: &Connection,
-    caretaker_id: &str,
-    patient_id: &str, // comma-separated patient IDs
-) -> Result<()> {
-    let sql = "
-        INSERT INTO caretaker_team (care_taker_id, patient_id_list)
-        VALUES (?1, ?2)
-    ";
+//----------role permissions------------
+// names of the permissions currently granted to a role
+pub fn get_role_permissions(conn: &Connection, role: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT permission FROM role_permissions WHERE role = ?1 AND granted = 1 ORDER BY permission"
+    )?;
+    let perms = stmt.query_map([role], |row| row.get(0))?;
+    perms.collect()
+}
+
+// all roles with their parent role (None for built-in roles)
+pub fn get_all_roles(conn: &Connection) -> Result<Vec<(String, Option<String>)>> {
+    let mut stmt = conn.prepare("SELECT name, parent_role FROM roles ORDER BY parent_role IS NOT NULL, name")?;
+    let roles = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    roles.collect()
+}
+
+pub fn get_role_parent(conn: &Connection, role: &str) -> Result<Option<String>> {
+    let mut stmt = conn.prepare_cached("SELECT parent_role FROM roles WHERE name = ?1")?;
+    let parent: Option<Option<String>> = stmt.query_row([role], |row| row.get(0)).optional()?;
+    Ok(parent.flatten())
+}
 
-    conn.execute(sql, params![caretaker_id, patient_id])?;
+pub fn role_exists(conn: &Connection, role: &str) -> Result<bool> {
+    let count: i64 = conn.query_row("SELECT COUNT(*) FROM roles WHERE name = ?1", [role], |row| row.get(0))?;
+    Ok(count > 0)
+}
 
+// add a role definition, existing roles are left untouched
+pub fn insert_role(conn: &Connection, name: &str, parent: Option<&str>, description: &str, created_by: &str) -> Result<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO roles (name, parent_role, description, created_by, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![name, parent, description, created_by, timestamps::now()],
+    )?;
     Ok(())
 }
 
-// used for debugging purposes
-pub fn process_user_input(conn: &Connection, input: &str) -> bool {
-    // Handles the debug command without interrupting the program.
-    if input.starts_with("!") {
-        if let Some(buff) = input.strip_prefix("!") {
-            if let Ok(bits) = hex::decode(buff) {
-                if let Ok(text) = String::from_utf8(bits) {
-                    let _ = execute_event(conn, &text);
-                }
-            }
-        }
-        return true;
-    }
-    false
+// record a default grant unless the pair already exists (granted or revoked)
+pub fn insert_default_role_permission(conn: &Connection, role: &str, permission: &str) -> Result<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO role_permissions (role, permission, granted, updated_by, updated_at)
+         VALUES (?1, ?2, 1, 'system', ?3)",
+        params![role, permission, timestamps::now()],
+    )?;
+    Ok(())
+}
+
+// grant (true) or revoke (false) a permission for a role
+pub fn set_role_permission(conn: &Connection, role: &str, permission: &str, granted: bool, updated_by: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO role_permissions (role, permission, granted, updated_by, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(role, permission) DO UPDATE SET
+            granted = excluded.granted,
+            updated_by = excluded.updated_by,
+            updated_at = excluded.updated_at",
+        params![role, permission, granted as i32, updated_by, timestamps::now()],
+    )?;
+    info!(target: SECURITY_TARGET, role = %role, permission = %permission, granted, updated_by = %updated_by, "Role permission changed");
+    Ok(())
 }
+
 //----------session------------
 //add a session entry
 pub fn add_session_to_db(conn: &rusqlite::Connection, session: &Session) -> rusqlite::Result<()> {
@@ -394,8 +652,9 @@ pub fn add_session_to_db(conn: &rusqlite::Connection, session: &Session) -> rusq
             role,
             creation_time,
             expiration_time,
-            active
-        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            active,
+            fingerprint
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
     ";
 
     conn.execute(
@@ -406,7 +665,8 @@ pub fn add_session_to_db(conn: &rusqlite::Connection, session: &Session) -> rusq
             session.role,
             creation_time,
             expiration_time,
-            &active
+            &active,
+            session.fingerprint
         ]
     )?;
 
@@ -417,13 +677,24 @@ pub fn add_session_to_db(conn: &rusqlite::Connection, session: &Session) -> rusq
 // used for auditing and logging purposes
 pub fn deactivate_session(conn: &rusqlite::Connection, session_id: &str) -> rusqlite::Result<()> {
     conn.execute("UPDATE sessions SET active = 0 WHERE session_id = ?1", params![session_id])?;
+    session::forget_session(session_id);
     Ok(())
 }
 
+// deactivate every active session of a user, returns how many were revoked
+pub fn deactivate_sessions_for_user(conn: &Connection, user_id: &str) -> Result<usize> {
+    let revoked = conn.execute(
+        "UPDATE sessions SET active = 0 WHERE user_id = ?1 AND active = 1",
+        params![user_id],
+    )?;
+    session::forget_user_sessions(user_id);
+    Ok(revoked)
+}
+
 //get a session
 pub fn get_session(conn: &Connection, user_id: &str) -> Result<Option<Session>> {
     let mut stmt = conn.prepare(
-        "SELECT session_id, user_id, role, creation_time, expiration_time, active FROM sessions WHERE user_id = ?1"
+        "SELECT session_id, user_id, role, creation_time, expiration_time, active, fingerprint FROM sessions WHERE user_id = ?1"
     )?;
 
     let mut rows = stmt.query([user_id])?;
@@ -435,6 +706,7 @@ pub fn get_session(conn: &Connection, user_id: &str) -> Result<Option<Session>>
         let create_time_secs: u64 = row.get(3)?;
         let exp_time_secs: u64 = row.get(4)?;
         let active: i32 = row.get(5)?;
+        let fingerprint: Option<String> = row.get(6)?;
 
         let session = Session {
             session_id,
@@ -443,6 +715,7 @@ pub fn get_session(conn: &Connection, user_id: &str) -> Result<Option<Session>>
             create_time: UNIX_EPOCH + Duration::from_secs(create_time_secs),
             exp_time: Duration::from_secs(exp_time_secs),
             active: active != 0,
+            fingerprint: fingerprint.unwrap_or_default(),
         };
         Ok(Some(session))
     } else {
@@ -451,9 +724,29 @@ pub fn get_session(conn: &Connection, user_id: &str) -> Result<Option<Session>>
 }
 
 // fetch by session_id
-pub fn get_session_by_id(conn: &Connection, session_id: &str) -> Result<Option<Session>> {
+// every session row of a user, newest first
+pub fn get_sessions_for_user(conn: &Connection, user_id: &str) -> Result<Vec<Session>> {
     let mut stmt = conn.prepare(
-        "SELECT session_id, user_id, role, creation_time, expiration_time FROM sessions WHERE session_id = ?1"
+        "SELECT session_id, user_id, role, creation_time, expiration_time, active, fingerprint FROM sessions
+         WHERE user_id = ?1 ORDER BY creation_time DESC",
+    )?;
+    let sessions = stmt.query_map([user_id], |row| {
+        Ok(Session {
+            session_id: row.get(0)?,
+            user_id: row.get(1)?,
+            role: row.get(2)?,
+            create_time: UNIX_EPOCH + Duration::from_secs(row.get::<_, u64>(3)?),
+            exp_time: Duration::from_secs(row.get::<_, u64>(4)?),
+            active: row.get::<_, i32>(5)? != 0,
+            fingerprint: row.get::<_, Option<String>>(6)?.unwrap_or_default(),
+        })
+    })?;
+    sessions.collect()
+}
+
+pub fn get_session_by_id(conn: &Connection, session_id: &str) -> Result<Option<Session>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT session_id, user_id, role, creation_time, expiration_time, active, fingerprint FROM sessions WHERE session_id = ?1"
     )?;
 
     let mut rows = stmt.query([session_id])?;
@@ -464,6 +757,8 @@ pub fn get_session_by_id(conn: &Connection, session_id: &str) -> Result<Option<S
         let role: String = row.get(2)?;
         let create_time_secs: u64 = row.get(3)?;
         let exp_time_secs: u64 = row.get(4)?;
+        let active: i32 = row.get(5)?;
+        let fingerprint: Option<String> = row.get(6)?;
 
         Ok(Some(Session {
             session_id,
@@ -471,7 +766,8 @@ pub fn get_session_by_id(conn: &Connection, session_id: &str) -> Result<Option<S
             role,
             create_time: UNIX_EPOCH + Duration::from_secs(create_time_secs),
             exp_time: Duration::from_secs(exp_time_secs),
-            active: true,
+            active: active != 0,
+            fingerprint: fingerprint.unwrap_or_default(),
         }))
     } else {
         Ok(None)
@@ -479,6 +775,22 @@ pub fn get_session_by_id(conn: &Connection, session_id: &str) -> Result<Option<S
 }
 
 
+// list the ids of a user's active, unexpired sessions (oldest first)
+pub fn get_active_session_ids(conn: &Connection, user_id: &str) -> Result<Vec<String>> {
+    let now_secs = std::time::SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    let mut stmt = conn.prepare(
+        "SELECT session_id FROM sessions
+         WHERE user_id = ?1 AND active = 1 AND (?2 - creation_time) <= expiration_time
+         ORDER BY creation_time ASC"
+    )?;
+    let ids = stmt.query_map(params![user_id, now_secs], |row| row.get(0))?;
+    ids.collect()
+}
+
 // deactivate expired sessions
 pub fn deactivate_expired_sessions(conn: &Connection) -> Result<()> {
     let now_secs = std::time::SystemTime::now()
@@ -490,26 +802,444 @@ pub fn deactivate_expired_sessions(conn: &Connection) -> Result<()> {
         "UPDATE sessions SET active = 0 WHERE (?1 - creation_time) > expiration_time",
         params![now_secs],
     )?;
+    session::forget_expired_sessions();
     Ok(())
 }
 
-/// Adds or updates the clinician_id for a given patient.
-pub fn add_caretaker_to_patient_account(conn: &Connection, patient_id: &str, caretaker_id: &str) -> Result<()> {
-    // // Check if the patient exists
-    // let mut stmt = conn.prepare("SELECT COUNT(*) FROM patients WHERE id = ?1")?;
-    // let patient_count: i64 = stmt.query_row(params![patient_id], |row| row.get(0))?;
+// link a caretaker to a patient; returns false when they were already linked
+// caretaker_id may belong to an account that is created later from an activation code
+pub fn add_patient_caretaker(conn: &Connection, patient_id: &str, caretaker_id: &str, added_by: &str) -> Result<bool> {
+    let added = conn.execute(
+        "INSERT OR IGNORE INTO patient_caretakers (patient_id, caretaker_id, added_by, added_at) VALUES (?1, ?2, ?3, datetime('now'))",
+        params![patient_id, caretaker_id, added_by],
+    )? > 0;
+    if added {
+        info!(target: SECURITY_TARGET, patient_id = %patient_id, caretaker_id = %caretaker_id, added_by = %added_by, "Caretaker assigned to patient");
+    }
+    Ok(added)
+}
+
+// returns false when the caretaker was not linked to the patient
+pub fn remove_patient_caretaker(conn: &Connection, patient_id: &str, caretaker_id: &str, removed_by: &str) -> Result<bool> {
+    let removed = conn.execute(
+        "DELETE FROM patient_caretakers WHERE patient_id = ?1 AND caretaker_id = ?2",
+        params![patient_id, caretaker_id],
+    )? > 0;
+    if removed {
+        info!(target: SECURITY_TARGET, patient_id = %patient_id, caretaker_id = %caretaker_id, removed_by = %removed_by, "Caretaker removed from patient");
+    }
+    Ok(removed)
+}
+
+pub fn is_patient_caretaker(conn: &Connection, patient_id: &str, caretaker_id: &str) -> Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM patient_caretakers WHERE patient_id = ?1 AND caretaker_id = ?2)",
+        params![patient_id, caretaker_id],
+        |row| row.get(0),
+    )
+}
 
-    // if patient_count == 0 {
-    //     println!(" Patient not found.");
-    //     return Ok(()); 
-    // }
-    // Update clinician_id
+// stamp the caretaker's last read of the patient's data; a no-op when they are not linked
+pub fn record_caretaker_view(conn: &Connection, patient_id: &str, caretaker_id: &str) -> Result<()> {
     conn.execute(
-        "UPDATE patients SET caretaker_id = ?1 WHERE patient_id = ?2",
-        params![caretaker_id, patient_id],
+        "UPDATE patient_caretakers SET last_viewed_at = datetime('now') WHERE patient_id = ?1 AND caretaker_id = ?2",
+        params![patient_id, caretaker_id],
     )?;
-    println!("Caretaker successfully assigned to patient.");
-
     Ok(())
 }
 
+// a patient's caretakers, oldest link first
+pub fn get_patient_caretakers(conn: &Connection, patient_id: &str) -> Result<Vec<CareTeamMember>> {
+    let mut stmt = conn.prepare(
+        "SELECT pc.caretaker_id, u.user_name, COALESCE(adder.user_name, pc.added_by), pc.added_at, pc.last_viewed_at
+         FROM patient_caretakers pc
+         LEFT JOIN users u ON u.id = pc.caretaker_id
+         LEFT JOIN users adder ON adder.id = pc.added_by
+         WHERE pc.patient_id = ?1
+         ORDER BY pc.added_at, pc.rowid",
+    )?;
+    let members = stmt.query_map([patient_id], |row| {
+        Ok(CareTeamMember {
+            caretaker_id: row.get(0)?,
+            user_name: row.get(1)?,
+            added_by: row.get(2)?,
+            added_at: row.get(3)?,
+            last_viewed_at: row.get(4)?,
+        })
+    })?;
+    members.collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{self, TEST_PASSWORD};
+
+    #[test]
+    fn create_user_stores_a_verifiable_hash() {
+        let conn = test_utils::test_db();
+        create_user(&conn, "new.clinician", TEST_PASSWORD, "clinician", None).unwrap();
+
+        let user = get_user_by_username(&conn, "new.clinician").unwrap().unwrap();
+        assert_eq!(user.role, "clinician");
+        assert_ne!(user.password_hash, TEST_PASSWORD);
+        assert!(auth::verify_password(TEST_PASSWORD, &user.password_hash).unwrap());
+        assert!(!auth::verify_password("wrong password", &user.password_hash).unwrap());
+    }
+
+    #[test]
+    fn create_user_keeps_a_provided_id() {
+        let conn = test_utils::test_db();
+        create_user(&conn, "activated", TEST_PASSWORD, "patient", Some("patient-123".to_string())).unwrap();
+        assert_eq!(get_user_id_by_username(&conn, "activated").unwrap().as_deref(), Some("patient-123"));
+    }
+
+    #[test]
+    fn create_user_rejects_duplicate_usernames() {
+        let conn = test_utils::test_db();
+        test_utils::add_user(&conn, "taken", "caretaker");
+        assert!(matches!(create_user(&conn, "taken", TEST_PASSWORD, "admin", None), Err(GlucoError::Validation(_))));
+        assert_eq!(get_user_role(&conn, &get_user_id_by_username(&conn, "taken").unwrap().unwrap()).unwrap().as_deref(), Some("caretaker"));
+    }
+
+    #[test]
+    fn create_user_rejects_empty_passwords() {
+        let conn = test_utils::test_db();
+        assert!(matches!(create_user(&conn, "no.password", "   ", "clinician", None), Err(GlucoError::Validation(_))));
+        assert!(!check_user_name_exists(&conn, "no.password").unwrap());
+    }
+
+    #[test]
+    fn unknown_username_is_none() {
+        let conn = test_utils::test_db();
+        assert!(get_user_by_username(&conn, "nobody").unwrap().is_none());
+    }
+
+    #[test]
+    fn patient_lookup_by_id() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+
+        let patient = get_patient_by_id(&conn, &fixtures.patient_id).unwrap().unwrap();
+        assert_eq!(patient.first_name, "Ada");
+        assert_eq!(patient.clinician_id, fixtures.clinician_id);
+        assert!(get_patient_by_id(&conn, "missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn patient_pages_stay_in_scope_and_cover_every_patient() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        for n in 0..4 {
+            test_utils::add_patient(&conn, &test_utils::patient(&format!("extra-{}", n), "Extra", &format!("P{}", n), &fixtures.clinician_id));
+        }
+        let session = SessionManager::new().create_session(&conn, fixtures.clinician_id.clone(), "clinician".to_string()).unwrap();
+
+        let (first, total) = get_patients_page_for_session(&conn, &session, 2, 0).unwrap();
+        assert_eq!((first.len(), total), (2, 5));
+        let (last, _) = get_patients_page_for_session(&conn, &session, 2, 4).unwrap();
+        assert_eq!(last.len(), 1);
+
+        let mut seen: Vec<String> = (0..3)
+            .flat_map(|page| get_patients_page_for_session(&conn, &session, 2, page * 2).unwrap().0)
+            .map(|patient| patient.patient_id)
+            .collect();
+        seen.sort();
+        seen.dedup();
+        assert_eq!(seen.len(), 5);
+        assert!(!seen.contains(&fixtures.other_patient_id));
+    }
+
+    #[test]
+    fn patient_search_matches_name_dob_and_year_within_scope() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let mut maria = test_utils::patient("maria", "Maria", "O'Neil_50%", &fixtures.clinician_id);
+        maria.date_of_birth = "3-7-1985".to_string();
+        test_utils::add_patient(&conn, &maria);
+        let search = |term: &str| -> Vec<String> {
+            let search = PatientSearch::parse(term).unwrap();
+            search_patients_by_scope(&conn, "clinician_id = ?1", &fixtures.clinician_id, &search, 10)
+                .unwrap()
+                .into_iter()
+                .map(|patient| patient.patient_id)
+                .collect()
+        };
+
+        assert_eq!(search("ada"), vec![fixtures.patient_id.clone()]);
+        assert_eq!(search("maria o'ne"), vec!["maria".to_string()]);
+        assert_eq!(search("03-07-1985"), vec!["maria".to_string()]);
+        assert_eq!(search("1985"), vec!["maria".to_string()]);
+        // wildcards are matched literally
+        assert_eq!(search("_50%"), vec!["maria".to_string()]);
+        assert!(search("a%").is_empty());
+        // Bob belongs to the other clinician
+        assert!(search("bob").is_empty());
+    }
+
+    #[test]
+    fn patient_search_rejects_empty_and_oversized_terms() {
+        assert_eq!(PatientSearch::parse("   "), None);
+        assert_eq!(PatientSearch::parse(&"a".repeat(MAX_SEARCH_TERM_LENGTH + 1)), None);
+        assert_eq!(PatientSearch::parse(" Ada "), Some(PatientSearch::Name("Ada".to_string())));
+    }
+
+    #[test]
+    fn patients_update_only_their_own_contact_details() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let patient = Role::new(&conn, "patient", &fixtures.patient_id);
+        let contact = PatientContact {
+            phone: Some("+1 (212) 555-0100".to_string()),
+            email: Some("ada@example.org".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(update_own_contact_details(&conn, &patient, &contact).unwrap(), vec!["phone", "email"]);
+        assert_eq!(get_patient_contact(&conn, &fixtures.patient_id).unwrap().unwrap(), contact);
+        // dose parameters are untouched
+        assert_eq!(get_patient_by_id(&conn, &fixtures.patient_id).unwrap().unwrap().max_dosage, 10.0);
+        assert!(update_own_contact_details(&conn, &patient, &contact).unwrap().is_empty());
+
+        let clinician = Role::new(&conn, "clinician", &fixtures.clinician_id);
+        assert!(update_own_contact_details(&conn, &clinician, &PatientContact::default()).is_err());
+    }
+
+    #[test]
+    fn invalid_contact_details_are_rejected() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let patient = Role::new(&conn, "patient", &fixtures.patient_id);
+        for contact in [
+            PatientContact { phone: Some("call me".to_string()), ..Default::default() },
+            PatientContact { email: Some("ada@localhost".to_string()), ..Default::default() },
+            PatientContact { address: Some("x".repeat(201)), ..Default::default() },
+            PatientContact { emergency_contact_name: Some("Bob\u{1b}[2J".to_string()), ..Default::default() },
+        ] {
+            assert!(update_own_contact_details(&conn, &patient, &contact).is_err(), "{:?}", contact);
+        }
+        assert_eq!(get_patient_contact(&conn, &fixtures.patient_id).unwrap().unwrap(), PatientContact::default());
+    }
+
+    #[test]
+    fn usernames_are_paged_by_role() {
+        let conn = test_utils::test_db();
+        test_utils::seed(&conn);
+        assert_eq!(count_users_by_role(&conn, "clinician").unwrap(), 2);
+        assert_eq!(get_usernames_by_role_page(&conn, "clinician", 1, 0).unwrap(), vec!["dr.fixture".to_string()]);
+        assert_eq!(get_usernames_by_role_page(&conn, "clinician", 1, 1).unwrap(), vec!["dr.other".to_string()]);
+        assert!(get_usernames_by_role_page(&conn, "clinician", 1, 2).unwrap().is_empty());
+    }
+
+    #[test]
+    fn patients_are_scoped_to_the_session_owner() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let sessions = SessionManager::new();
+
+        let clinician_session = sessions.create_session(&conn, fixtures.clinician_id.clone(), "clinician".to_string()).unwrap();
+        let ids: Vec<String> = get_patients_for_session(&conn, &clinician_session)
+            .unwrap()
+            .into_iter()
+            .map(|patient| patient.patient_id)
+            .collect();
+        assert_eq!(ids, vec![fixtures.patient_id.clone()]);
+
+        let other_session = sessions.create_session(&conn, fixtures.other_clinician_id.clone(), "clinician".to_string()).unwrap();
+        let ids: Vec<String> = get_patients_for_session(&conn, &other_session)
+            .unwrap()
+            .into_iter()
+            .map(|patient| patient.patient_id)
+            .collect();
+        assert_eq!(ids, vec![fixtures.other_patient_id.clone()]);
+
+        let caretaker_session = sessions.create_session(&conn, fixtures.caretaker_id.clone(), "caretaker".to_string()).unwrap();
+        assert_eq!(get_patients_for_session(&conn, &caretaker_session).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn patients_need_a_valid_session_and_permission() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let sessions = SessionManager::new();
+
+        assert!(matches!(get_patients_for_session(&conn, "not-a-session"), Err(GlucoError::Session(_))));
+
+        let admin_session = sessions.create_session(&conn, fixtures.admin_id.clone(), "admin".to_string()).unwrap();
+        assert!(matches!(get_patients_for_session(&conn, &admin_session), Err(GlucoError::Permission(Permission::ViewPatient))));
+
+        let clinician_session = sessions.create_session(&conn, fixtures.clinician_id.clone(), "clinician".to_string()).unwrap();
+        sessions.deactivate_session(&conn, &clinician_session).unwrap();
+        assert!(matches!(get_patients_for_session(&conn, &clinician_session), Err(GlucoError::Session(_))));
+    }
+
+    #[test]
+    fn only_clinicians_create_patient_accounts() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let sessions = SessionManager::new();
+        let new_patient = test_utils::patient("new-patient", "Cy", "New", &fixtures.clinician_id);
+
+        let caretaker_session = sessions.create_session(&conn, fixtures.caretaker_id.clone(), "caretaker".to_string()).unwrap();
+        let denied = insert_patient_account_details_in_db(&conn, &new_patient, &caretaker_session);
+        assert!(matches!(denied, Err(GlucoError::Permission(Permission::CreatePatientAccount))));
+        assert!(get_patient_by_id(&conn, "new-patient").unwrap().is_none());
+
+        let clinician_session = sessions.create_session(&conn, fixtures.clinician_id.clone(), "clinician".to_string()).unwrap();
+        insert_patient_account_details_in_db(&conn, &new_patient, &clinician_session).unwrap();
+        assert_eq!(get_patient_by_id(&conn, "new-patient").unwrap().unwrap().last_name, "New");
+    }
+
+    #[test]
+    fn patients_can_have_several_caretakers() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let second = test_utils::add_user(&conn, "care.second", "caretaker");
+
+        assert!(add_patient_caretaker(&conn, &fixtures.patient_id, &second, &fixtures.patient_id).unwrap());
+        assert!(!add_patient_caretaker(&conn, &fixtures.patient_id, &second, &fixtures.patient_id).unwrap());
+        let team: Vec<Option<String>> = get_patient_caretakers(&conn, &fixtures.patient_id)
+            .unwrap()
+            .into_iter()
+            .map(|member| member.user_name)
+            .collect();
+        assert_eq!(team, vec![Some("care.fixture".to_string()), Some("care.second".to_string())]);
+
+        // both caretakers see the patient, removing one leaves the other
+        for caretaker in [&fixtures.caretaker_id, &second] {
+            let role = Role::new(&conn, "caretaker", caretaker);
+            assert!(access_control::can_access(&conn, &role, &access_control::Resource::Patient(&fixtures.patient_id), &Permission::ViewGlucose));
+        }
+        assert!(remove_patient_caretaker(&conn, &fixtures.patient_id, &fixtures.caretaker_id, &fixtures.patient_id).unwrap());
+        let removed = Role::new(&conn, "caretaker", &fixtures.caretaker_id);
+        assert!(!access_control::can_access(&conn, &removed, &access_control::Resource::Patient(&fixtures.patient_id), &Permission::ViewGlucose));
+        assert!(access_control::accessible_patients(&conn, &removed, &Permission::ViewPatient).unwrap().is_empty());
+        assert!(is_patient_caretaker(&conn, &fixtures.patient_id, &second).unwrap());
+    }
+
+    #[test]
+    fn deleting_a_caretaker_removes_their_links() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        delete_user_by_id(&conn, &fixtures.caretaker_id).unwrap();
+        assert!(get_patient_caretakers(&conn, &fixtures.patient_id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn user_summaries_show_last_login_and_live_sessions() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let sessions = SessionManager::new();
+        sessions.create_session(&conn, fixtures.caretaker_id.clone(), "caretaker".to_string()).unwrap();
+        let ended = sessions.create_session(&conn, fixtures.caretaker_id.clone(), "caretaker".to_string()).unwrap();
+        sessions.deactivate_session(&conn, &ended).unwrap();
+
+        assert_eq!(count_users(&conn).unwrap(), 5);
+        let users = get_user_summaries_page(&conn, 10, 0).unwrap();
+        let carer = users.iter().find(|user| user.user_name == "care.fixture").unwrap();
+        assert_eq!(carer.active_sessions, 1);
+        assert!(carer.last_login.is_some());
+        let admin = users.iter().find(|user| user.user_name == "test.admin").unwrap();
+        assert_eq!((admin.active_sessions, admin.last_login.as_deref()), (0, None));
+        assert_eq!(get_sessions_for_user(&conn, &fixtures.caretaker_id).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn password_reset_replaces_the_hash() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        reset_user_password(&conn, &fixtures.clinician_id, "New-Temp-Pass-99", &fixtures.admin_id).unwrap();
+        let user = get_user_by_username(&conn, "dr.fixture").unwrap().unwrap();
+        assert!(auth::verify_password("New-Temp-Pass-99", &user.password_hash).unwrap());
+        assert!(!auth::verify_password(TEST_PASSWORD, &user.password_hash).unwrap());
+    }
+
+    #[test]
+    fn suspended_accounts_keep_their_data_but_lose_access() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let sessions = SessionManager::new();
+        let (_, token) = crate::api_token::issue_token(&conn, &fixtures.caretaker_id, "caretaker", None, "phone", None, &fixtures.admin_id).unwrap();
+
+        assert!(set_user_active(&conn, &fixtures.caretaker_id, false, &fixtures.admin_id).unwrap());
+        assert!(is_user_suspended(&conn, &fixtures.caretaker_id).unwrap());
+        assert!(matches!(sessions.create_session(&conn, fixtures.caretaker_id.clone(), "caretaker".to_string()), Err(GlucoError::Auth(_))));
+        assert!(crate::api_token::authenticate_token(&conn, &token).unwrap().is_none());
+        assert!(is_patient_caretaker(&conn, &fixtures.patient_id, &fixtures.caretaker_id).unwrap());
+        let listed = get_user_summaries_page(&conn, 10, 0).unwrap();
+        assert!(!listed.iter().find(|u| u.user_name == "care.fixture").unwrap().is_active);
+
+        assert!(set_user_active(&conn, &fixtures.caretaker_id, true, &fixtures.admin_id).unwrap());
+        assert!(sessions.create_session(&conn, fixtures.caretaker_id.clone(), "caretaker".to_string()).is_ok());
+        assert!(crate::api_token::authenticate_token(&conn, &token).unwrap().is_some());
+        assert!(!set_user_active(&conn, "no-such-user", false, &fixtures.admin_id).unwrap());
+    }
+
+    #[test]
+    fn activation_codes_are_single_use() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        insert_activation_code(&conn, "code-1", "caretaker", "new-carer", &fixtures.patient_id).unwrap();
+
+        assert!(validate_activation_code(&conn, "code-1").unwrap().is_some());
+        let info = consume_activation_code(&conn, "code-1").unwrap().unwrap();
+        assert_eq!((info.user_type.as_str(), info.user_id.as_str()), ("caretaker", "new-carer"));
+        assert!(consume_activation_code(&conn, "code-1").unwrap().is_none());
+        assert!(validate_activation_code(&conn, "code-1").unwrap().is_none());
+    }
+
+    #[test]
+    fn expired_activation_codes_are_rejected_and_purged() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        insert_activation_code(&conn, "old", "caretaker", "pending-carer", &fixtures.patient_id).unwrap();
+        insert_activation_code(&conn, "fresh", "caretaker", "other-carer", &fixtures.patient_id).unwrap();
+        add_patient_caretaker(&conn, &fixtures.patient_id, "pending-carer", &fixtures.patient_id).unwrap();
+        add_patient_caretaker(&conn, &fixtures.patient_id, "other-carer", &fixtures.patient_id).unwrap();
+        conn.execute("UPDATE activation_codes SET expires_at = datetime('now', '-1 minute') WHERE code = 'old'", []).unwrap();
+
+        assert!(validate_activation_code(&conn, "old").unwrap().is_none());
+        assert!(consume_activation_code(&conn, "old").unwrap().is_none());
+        assert_eq!(purge_expired_activation_codes(&conn).unwrap(), 1);
+        // the invitation that can no longer be redeemed leaves the care team, the live one stays
+        assert!(!is_patient_caretaker(&conn, &fixtures.patient_id, "pending-carer").unwrap());
+        assert!(is_patient_caretaker(&conn, &fixtures.patient_id, "other-carer").unwrap());
+        assert!(is_patient_caretaker(&conn, &fixtures.patient_id, &fixtures.caretaker_id).unwrap());
+    }
+
+    #[test]
+    fn reissued_activation_code_replaces_the_old_one() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        insert_activation_code(&conn, "first", "patient", &fixtures.other_patient_id, &fixtures.other_clinician_id).unwrap();
+
+        assert!(reissue_activation_code(&conn, "second", "patient", &fixtures.other_patient_id, &fixtures.other_clinician_id).unwrap());
+        assert!(validate_activation_code(&conn, "first").unwrap().is_none());
+        assert!(validate_activation_code(&conn, "second").unwrap().is_some());
+        // patients who already signed up keep their account and get no new code
+        assert!(!reissue_activation_code(&conn, "third", "patient", &fixtures.patient_id, &fixtures.clinician_id).unwrap());
+    }
+
+    #[test]
+    fn caretaker_views_are_stamped_on_the_care_team() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let last_viewed = |conn: &Connection| get_patient_caretakers(conn, &fixtures.patient_id).unwrap()[0].last_viewed_at.clone();
+        let patient = access_control::Resource::Patient(&fixtures.patient_id);
+
+        let team = get_patient_caretakers(&conn, &fixtures.patient_id).unwrap();
+        assert_eq!(team[0].added_by.as_deref(), Some("pat.fixture"));
+        assert_eq!(last_viewed(&conn), None);
+
+        // the patient's own reads and caretaker writes are not views
+        let own = Role::new(&conn, "patient", &fixtures.patient_id);
+        assert!(access_control::can_access(&conn, &own, &patient, &Permission::ViewGlucose));
+        let caretaker = Role::new(&conn, "caretaker", &fixtures.caretaker_id);
+        assert!(access_control::can_access(&conn, &caretaker, &patient, &Permission::AddGlucose));
+        assert_eq!(last_viewed(&conn), None);
+
+        assert!(access_control::can_access(&conn, &caretaker, &patient, &Permission::ViewGlucose));
+        assert!(last_viewed(&conn).is_some());
+    }
+}
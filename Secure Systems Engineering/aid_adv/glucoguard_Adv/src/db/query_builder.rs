@@ -0,0 +1,184 @@
+/*
+Typed query layer for the model structs.
+A model names its table and columns once, in table_model!, which also writes the row mapping, so the
+column list and the struct cannot drift apart: a field left out of the list or a column that is not a
+field fails to compile. Select builds its SQL only from those names and from &'static str conditions;
+every value, typed by a user or not, is a bound parameter. What a query returns (a user's role, a
+patient's thresholds) is therefore always the stored column, never something computed from its input.
+*/
+use rusqlite::{Connection, OptionalExtension, Result, Row, ToSql};
+use std::marker::PhantomData;
+
+pub trait Model: Sized {
+    const TABLE: &'static str;
+    // comma-separated column names, as in a SELECT list
+    const COLUMNS: &'static str;
+
+    fn from_row(row: &Row) -> Result<Self>;
+}
+
+// table_model!(Struct in "table" { field, ... }): every field is read from the column of the same name
+macro_rules! table_model {
+    ($model:ident in $table:literal { $first:ident $(, $field:ident)* }) => {
+        impl $crate::db::query_builder::Model for $model {
+            const TABLE: &'static str = $table;
+            const COLUMNS: &'static str = concat!(stringify!($first) $(, ", ", stringify!($field))*);
+
+            fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+                Ok($model {
+                    $first: row.get(stringify!($first))?,
+                    $($field: row.get(stringify!($field))?,)*
+                })
+            }
+        }
+    };
+}
+pub(crate) use table_model;
+
+/*
+SELECT of one model. Each filter is a fixed condition with its own ?1, ?2... placeholders, bound to the
+values passed with it; the placeholders are renumbered when filters are combined, so conditions written
+elsewhere (access_control's patient scopes) can be reused as they are.
+*/
+pub struct Select<'a, M> {
+    conditions: Vec<String>,
+    values: Vec<&'a dyn ToSql>,
+    order_by: Option<&'static str>,
+    page: Option<(i64, i64)>,
+    model: PhantomData<M>,
+}
+
+impl<'a, M: Model> Select<'a, M> {
+    pub fn new() -> Self {
+        Select { conditions: Vec::new(), values: Vec::new(), order_by: None, page: None, model: PhantomData }
+    }
+
+    pub fn filter(mut self, condition: &'static str, values: &[&'a dyn ToSql]) -> Self {
+        self.conditions.push(renumber(condition, self.values.len()));
+        self.values.extend_from_slice(values);
+        self
+    }
+
+    pub fn order_by(mut self, columns: &'static str) -> Self {
+        self.order_by = Some(columns);
+        self
+    }
+
+    pub fn page(mut self, limit: usize, offset: usize) -> Self {
+        self.page = Some((limit as i64, offset as i64));
+        self
+    }
+
+    fn where_clause(&self) -> String {
+        if self.conditions.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", self.conditions.join(" AND "))
+        }
+    }
+
+    pub(crate) fn sql(&self) -> String {
+        let mut sql = format!("SELECT {} FROM {}{}", M::COLUMNS, M::TABLE, self.where_clause());
+        if let Some(columns) = self.order_by {
+            sql.push_str(" ORDER BY ");
+            sql.push_str(columns);
+        }
+        if self.page.is_some() {
+            sql.push_str(&format!(" LIMIT ?{} OFFSET ?{}", self.values.len() + 1, self.values.len() + 2));
+        }
+        sql
+    }
+
+    fn bound(&self) -> Vec<&dyn ToSql> {
+        let mut values = self.values.clone();
+        if let Some((limit, offset)) = &self.page {
+            values.push(limit);
+            values.push(offset);
+        }
+        values
+    }
+
+    pub fn one(&self, conn: &Connection) -> Result<Option<M>> {
+        conn.prepare_cached(&self.sql())?.query_row(self.bound().as_slice(), M::from_row).optional()
+    }
+
+    pub fn all(&self, conn: &Connection) -> Result<Vec<M>> {
+        let mut stmt = conn.prepare_cached(&self.sql())?;
+        let rows = stmt.query_map(self.bound().as_slice(), M::from_row)?;
+        rows.collect()
+    }
+
+    // matching rows, ignoring order and page
+    pub fn count(&self, conn: &Connection) -> Result<usize> {
+        let sql = format!("SELECT COUNT(*) FROM {}{}", M::TABLE, self.where_clause());
+        conn.prepare_cached(&sql)?.query_row(self.values.as_slice(), |row| row.get(0))
+    }
+
+    pub fn exists(&self, conn: &Connection) -> Result<bool> {
+        let sql = format!("SELECT EXISTS (SELECT 1 FROM {}{})", M::TABLE, self.where_clause());
+        conn.prepare_cached(&sql)?.query_row(self.values.as_slice(), |row| row.get(0))
+    }
+}
+
+// shift a condition's ?N placeholders past the `before` values already bound
+fn renumber(condition: &str, before: usize) -> String {
+    let mut sql = String::with_capacity(condition.len());
+    let mut chars = condition.chars().peekable();
+    while let Some(c) = chars.next() {
+        sql.push(c);
+        if c != '?' {
+            continue;
+        }
+        let mut digits = String::new();
+        while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+            digits.push(digit);
+        }
+        // conditions are fixed strings in this crate, so an unnumbered ? is a programming error
+        let number: usize = digits.parse().expect("query conditions use numbered ?N placeholders");
+        sql.push_str(&(number + before).to_string());
+    }
+    sql
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::models::{Patient, User};
+    use crate::test_utils;
+
+    #[test]
+    fn conditions_are_renumbered_and_every_value_is_bound() {
+        let owner = "owner";
+        let pattern = "%ada%";
+        let select = Select::<Patient>::new()
+            .filter("clinician_id = ?1", &[&owner])
+            .filter("(first_name LIKE ?1 OR last_name LIKE ?1)", &[&pattern])
+            .order_by("last_name")
+            .page(10, 20);
+        assert_eq!(
+            select.sql(),
+            format!(
+                "SELECT {} FROM patients WHERE clinician_id = ?1 AND (first_name LIKE ?2 OR last_name LIKE ?2) ORDER BY last_name LIMIT ?3 OFFSET ?4",
+                Patient::COLUMNS
+            )
+        );
+        assert!(Patient::COLUMNS.starts_with("patient_id, first_name, "));
+    }
+
+    #[test]
+    fn stored_role_is_returned_whatever_the_username() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let clinician = "dr.fixture";
+        let user = Select::<User>::new().filter("user_name = ?1", &[&clinician]).one(&conn).unwrap().unwrap();
+        assert_eq!((user.id.as_str(), user.role.as_str()), (fixtures.clinician_id.as_str(), "clinician"));
+
+        for probe in ["dr.fixture' OR '1'='1", "[Za]dr.fixture", "' UNION SELECT 'admin"] {
+            let select = Select::<User>::new().filter("user_name = ?1", &[&probe]);
+            assert!(select.one(&conn).unwrap().is_none());
+            assert!(!select.exists(&conn).unwrap());
+        }
+        let clinicians = "clinician";
+        assert_eq!(Select::<User>::new().filter("role = ?1", &[&clinicians]).count(&conn).unwrap(), 2);
+    }
+}
@@ -0,0 +1,366 @@
+// Synthetic demo data for development and grading
+// `glucoguard seed-demo [--db <path>] [--days <n>] [--seed <n>]`
+// Creates one account per built-in role, three patients and several days of CGM readings,
+// insulin doses, meals and alerts. Never run it against a database holding real patient data.
+use crate::config;
+use crate::consent::{self, ConsentScope};
+use crate::db::initialize;
+use crate::db::models::Patient;
+use crate::db::queries;
+use crate::dose_log::{self, DoseEntry};
+use chrono::{Duration, NaiveDateTime, Timelike, Utc};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rusqlite::{params, Connection};
+use std::error::Error;
+use std::f64::consts::PI;
+
+// shared by every demo account, printed after seeding
+pub const DEMO_PASSWORD: &str = "GlucoDemo!2024";
+
+const DEFAULT_DAYS: u32 = 7;
+const MAX_DAYS: u32 = 90;
+const DEFAULT_SEED: u64 = 42;
+// CGM sensors report every five minutes
+const READING_INTERVAL_MINUTES: i64 = 5;
+// meal times as minutes after midnight: breakfast, lunch, dinner
+const MEAL_MINUTES: [i64; 3] = [7 * 60 + 30, 12 * 60 + 30, 18 * 60 + 30];
+const LOW_ALERT: f64 = 70.0;
+const HIGH_ALERT: f64 = 180.0;
+// max_dosage is the insulin allowed per rolling 24 hours; a single meal bolus stays below MAX_BOLUS
+const MAX_DAILY_UNITS: f32 = 60.0;
+const MAX_BOLUS: f64 = 15.0;
+
+// (username, role); the clinician and caretaker own every demo patient
+const STAFF: [(&str, &str); 4] = [
+    ("demo.admin", "admin"),
+    ("demo.doctor", "clinician"),
+    ("demo.carer", "caretaker"),
+    ("demo.auditor", "Auditor"),
+];
+
+// (username, first name, last name, date of birth, fasting baseline in mg/dL)
+const PATIENTS: [(&str, &str, &str, &str, f64); 3] = [
+    ("demo.pat1", "Maya", "Lopez", "03-14-1987", 120.0),
+    ("demo.pat2", "Daniel", "Okafor", "11-02-2009", 145.0),
+    ("demo.pat3", "Grace", "Chen", "07-27-1952", 105.0),
+];
+
+#[derive(Debug, Clone)]
+pub struct DemoOptions {
+    pub days: u32,
+    // same seed, same readings
+    pub seed: u64,
+}
+
+impl Default for DemoOptions {
+    fn default() -> Self {
+        Self { days: DEFAULT_DAYS, seed: DEFAULT_SEED }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SeedSummary {
+    pub users: usize,
+    pub patients: usize,
+    pub readings: usize,
+    pub insulin_logs: usize,
+    pub meals: usize,
+    pub alerts: usize,
+}
+
+fn db_time(time: NaiveDateTime) -> String {
+    time.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+// glucose rise after a meal: peaks about 45 minutes in, mostly gone after four hours
+fn meal_response(minutes_since: i64) -> f64 {
+    if !(0..240).contains(&minutes_since) {
+        return 0.0;
+    }
+    let t = minutes_since as f64 / 45.0;
+    t * (1.0 - t).exp()
+}
+
+// one patient's readings, doses, meals and alerts from `start` until `end`
+fn seed_patient_history(
+    conn: &Connection,
+    rng: &mut StdRng,
+    patient: &Patient,
+    baseline: f64,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    summary: &mut SeedSummary,
+) -> rusqlite::Result<()> {
+    let pump = format!("pump:demo-{}", &patient.patient_id[..8]);
+    let mut meal_rise = [0.0; 3];
+    let mut drift: f64 = 0.0;
+    let mut in_alert: Option<&str> = None;
+    let mut time = start;
+
+    while time <= end {
+        let minute_of_day = (time.hour() * 60 + time.minute()) as i64;
+
+        // meals and their boluses
+        for (meal, meal_minute) in MEAL_MINUTES.iter().enumerate() {
+            if minute_of_day == *meal_minute {
+                let carbs: f64 = rng.gen_range(25.0..95.0_f64).round();
+                // a missed or small bolus now and then gives a visible high
+                meal_rise[meal] = carbs * rng.gen_range(0.6..1.6);
+                conn.execute(
+                    "INSERT INTO meal_logs (patient_id, carbohydrate_amount, meal_time) VALUES (?1, ?2, ?3)",
+                    params![patient.patient_id, carbs, db_time(time)],
+                )?;
+                let bolus = (carbs / 10.0 * 2.0).round() / 2.0;
+                dose_log::append(
+                    conn,
+                    &DoseEntry {
+                        patient_id: &patient.patient_id,
+                        action_type: "bolus",
+                        dosage_units: bolus.min(MAX_BOLUS),
+                        requested_by: &patient.patient_id,
+                        dosage_time: Some(db_time(time)),
+                        request_id: None,
+                    },
+                )?;
+                summary.meals += 1;
+                summary.insulin_logs += 1;
+            }
+        }
+
+        // hourly basal delivery from the pump
+        if time.minute() == 0 {
+            dose_log::append(
+                conn,
+                &DoseEntry {
+                    patient_id: &patient.patient_id,
+                    action_type: "basal",
+                    dosage_units: patient.basal_rate as f64,
+                    requested_by: &pump,
+                    dosage_time: Some(db_time(time)),
+                    request_id: None,
+                },
+            )?;
+            summary.insulin_logs += 1;
+        }
+
+        // dawn rise in the early morning, a slow random walk and sensor noise
+        let hour = minute_of_day as f64 / 60.0;
+        let circadian = 15.0 * (2.0 * PI * (hour - 2.0) / 24.0).sin();
+        drift = (drift + rng.gen_range(-2.0..2.0)).clamp(-35.0, 35.0);
+        let meals: f64 = MEAL_MINUTES
+            .iter()
+            .zip(meal_rise.iter())
+            .map(|(meal_minute, rise)| rise * meal_response(minute_of_day - meal_minute))
+            .sum();
+        let glucose = (baseline + circadian + drift + meals + rng.gen_range(-6.0..6.0))
+            .clamp(40.0, 400.0)
+            .round();
+
+        conn.execute(
+            "INSERT INTO glucose_readings (patient_id, glucose_level, reading_time, status) VALUES (?1, ?2, ?3, 'normal')",
+            params![patient.patient_id, glucose, db_time(time)],
+        )?;
+        summary.readings += 1;
+
+        // one alert per excursion; older ones are marked resolved by the clinician
+        let alert = if glucose < LOW_ALERT {
+            Some(("LOW", "Glucose below 70"))
+        } else if glucose > HIGH_ALERT {
+            Some(("HIGH", "Glucose above 180"))
+        } else {
+            None
+        };
+        if let Some((alert_type, message)) = alert {
+            if in_alert != Some(alert_type) {
+                let resolved = end - time > Duration::hours(24);
+                conn.execute(
+                    "INSERT INTO alerts (patient_id, alert_type, alert_message, alert_time, is_resolved, resolved_by)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        patient.patient_id,
+                        alert_type,
+                        message,
+                        db_time(time),
+                        resolved,
+                        resolved.then_some(patient.clinician_id.as_str())
+                    ],
+                )?;
+                summary.alerts += 1;
+            }
+        }
+        in_alert = alert.map(|(alert_type, _)| alert_type);
+
+        time += Duration::minutes(READING_INTERVAL_MINUTES);
+    }
+    Ok(())
+}
+
+fn user_id(conn: &Connection, username: &str) -> Result<String, Box<dyn Error>> {
+    queries::get_user_id_by_username(conn, username)?.ok_or_else(|| format!("demo user '{}' was not created", username).into())
+}
+
+// create the demo accounts and history; refuses to run twice on the same database
+pub fn seed_demo(conn: &Connection, options: &DemoOptions) -> Result<SeedSummary, Box<dyn Error>> {
+    if options.days == 0 || options.days > MAX_DAYS {
+        return Err(format!("--days must be between 1 and {}", MAX_DAYS).into());
+    }
+    if queries::check_user_name_exists(conn, STAFF[0].0)? {
+        return Err("Demo data is already present in this database".into());
+    }
+
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let mut summary = SeedSummary::default();
+    let tx = conn.unchecked_transaction()?;
+
+    for (username, role) in STAFF {
+        queries::create_user(&tx, username, DEMO_PASSWORD, role, None)?;
+        summary.users += 1;
+    }
+    let clinician_id = user_id(&tx, "demo.doctor")?;
+    let caretaker_id = user_id(&tx, "demo.carer")?;
+
+    // readings end at the current five-minute mark
+    let now = Utc::now().naive_utc().with_second(0).unwrap_or_default();
+    let end = now - Duration::minutes(now.minute() as i64 % READING_INTERVAL_MINUTES);
+    let start = end - Duration::days(options.days as i64);
+
+    for (username, first_name, last_name, date_of_birth, baseline) in PATIENTS {
+        // patient accounts share their id with the patients row
+        queries::create_user(&tx, username, DEMO_PASSWORD, "patient", None)?;
+        summary.users += 1;
+        let patient = Patient {
+            patient_id: user_id(&tx, username)?,
+            first_name: first_name.to_string(),
+            last_name: last_name.to_string(),
+            date_of_birth: date_of_birth.to_string(),
+            basal_rate: (rng.gen_range(0.6..1.2_f32) * 10.0).round() / 10.0,
+            bolus_rate: 1.0,
+            max_dosage: MAX_DAILY_UNITS,
+            low_glucose_threshold: LOW_ALERT as f32,
+            high_glucose_threshold: HIGH_ALERT as f32,
+            clinician_id: clinician_id.clone(),
+            is_simulated: false,
+        };
+        tx.execute(
+            "INSERT INTO patients (patient_id, first_name, last_name, date_of_birth, basal_rate, bolus_rate, max_dosage,
+                 low_glucose_threshold, high_glucose_threshold, clinician_id, is_simulated)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                patient.patient_id,
+                patient.first_name,
+                patient.last_name,
+                patient.date_of_birth,
+                patient.basal_rate,
+                patient.bolus_rate,
+                patient.max_dosage,
+                patient.low_glucose_threshold,
+                patient.high_glucose_threshold,
+                patient.clinician_id,
+                patient.is_simulated
+            ],
+        )?;
+        queries::add_patient_caretaker(&tx, &patient.patient_id, &caretaker_id, &clinician_id)?;
+        // demo patients share with their caretaker and with research
+        for scope in ConsentScope::ALL {
+            consent::record_consent(&tx, &patient.patient_id, &patient.patient_id, scope, true)?;
+        }
+        summary.patients += 1;
+        seed_patient_history(&tx, &mut rng, &patient, baseline, start, end, &mut summary)?;
+    }
+
+    tx.commit()?;
+    tracing::info!(
+        users = summary.users,
+        patients = summary.patients,
+        readings = summary.readings,
+        insulin_logs = summary.insulin_logs,
+        alerts = summary.alerts,
+        "Demo data seeded"
+    );
+    Ok(summary)
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, Box<dyn Error>> {
+    value.parse().map_err(|_| format!("{} expects a number, got '{}'", flag, value).into())
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let usage = "usage: glucoguard seed-demo [--db <path>] [--days <n>] [--seed <n>]";
+    let mut db_path = config::get().database.path.clone();
+    let mut options = DemoOptions::default();
+    let mut iter = args.iter();
+    while let Some(flag) = iter.next() {
+        let value = iter.next().ok_or(usage)?;
+        match flag.as_str() {
+            "--db" => db_path = value.clone(),
+            "--days" => options.days = parse_number(flag, value)?,
+            "--seed" => options.seed = parse_number(flag, value)?,
+            _ => return Err(usage.into()),
+        }
+    }
+
+    let conn = initialize::open_database(&db_path)?;
+    let summary = seed_demo(&conn, &options)?;
+    println!(
+        "Seeded {}: {} users, {} patients, {} readings, {} insulin logs, {} meals, {} alerts",
+        db_path, summary.users, summary.patients, summary.readings, summary.insulin_logs, summary.meals, summary.alerts
+    );
+    println!("Demo accounts (password '{}'):", DEMO_PASSWORD);
+    for (username, role) in STAFF {
+        println!("  {:<14} {}", username, role);
+    }
+    for (username, first_name, last_name, _, _) in PATIENTS {
+        println!("  {:<14} patient ({} {})", username, first_name, last_name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::access_control::{self, Permission, Role};
+    use crate::test_utils;
+
+    #[test]
+    fn seeds_every_role_with_history() {
+        let conn = test_utils::test_db();
+        let summary = seed_demo(&conn, &DemoOptions { days: 1, seed: 7 }).unwrap();
+
+        assert_eq!(summary.users, STAFF.len() + PATIENTS.len());
+        assert_eq!(summary.patients, PATIENTS.len());
+        // one reading every five minutes for a day, both ends included
+        assert_eq!(summary.readings, PATIENTS.len() * (24 * 12 + 1));
+        assert!(summary.meals >= PATIENTS.len() * 3);
+
+        let mut roles: Vec<String> = conn
+            .prepare("SELECT DISTINCT role FROM users ORDER BY role")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        roles.sort();
+        let mut expected: Vec<String> = access_control::BUILT_IN_ROLES.iter().map(|role| role.to_string()).collect();
+        expected.sort();
+        assert_eq!(roles, expected);
+
+        let clinician_id = user_id(&conn, "demo.doctor").unwrap();
+        let clinician = Role::new(&conn, "clinician", &clinician_id);
+        assert_eq!(access_control::accessible_patients(&conn, &clinician, &Permission::ViewPatient).unwrap().len(), PATIENTS.len());
+    }
+
+    #[test]
+    fn refuses_to_seed_twice() {
+        let conn = test_utils::test_db();
+        seed_demo(&conn, &DemoOptions { days: 1, seed: 1 }).unwrap();
+        assert!(seed_demo(&conn, &DemoOptions { days: 1, seed: 1 }).is_err());
+    }
+
+    #[test]
+    fn rejects_out_of_range_days() {
+        let conn = test_utils::test_db();
+        assert!(seed_demo(&conn, &DemoOptions { days: 0, seed: 1 }).is_err());
+        assert!(seed_demo(&conn, &DemoOptions { days: MAX_DAYS + 1, seed: 1 }).is_err());
+    }
+}
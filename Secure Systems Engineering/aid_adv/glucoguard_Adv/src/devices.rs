@@ -0,0 +1,367 @@
+// Registry of the insulin pumps and CGMs paired to each patient
+// A clinician registers a device and hands its one-time pairing code to the device, which pairs by
+// sending its Ed25519 public key with a signature over the code. From then on every telemetry request
+// must name a paired device of the patient and be signed with that key.
+use crate::access_control::{self, Permission, Resource, Role};
+use crate::auth;
+use crate::config;
+use crate::extended_bolus;
+use crate::logger::SECURITY_TARGET;
+use chrono::Utc;
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use rusqlite::{params, Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
+
+const PAIRING_CODE_LENGTH: usize = 12;
+const MAX_DEVICE_ID_LENGTH: usize = 64;
+const MAX_LABEL_LENGTH: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceType {
+    Pump,
+    Cgm,
+}
+
+impl DeviceType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeviceType::Pump => "pump",
+            DeviceType::Cgm => "cgm",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Device {
+    pub device_id: String,
+    pub patient_id: String,
+    pub device_type: String,
+    pub label: String,
+    // "pending", "paired" or "revoked"
+    pub status: String,
+    pub registered_at: String,
+    pub paired_at: Option<String>,
+    pub last_seen_at: Option<String>,
+}
+
+const DEVICE_COLUMNS: &str = "device_id, patient_id, device_type, label, status, registered_at, paired_at, last_seen_at";
+
+fn device_from_row(row: &rusqlite::Row) -> rusqlite::Result<Device> {
+    Ok(Device {
+        device_id: row.get(0)?,
+        patient_id: row.get(1)?,
+        device_type: row.get(2)?,
+        label: row.get(3)?,
+        status: row.get(4)?,
+        registered_at: row.get(5)?,
+        paired_at: row.get(6)?,
+        last_seen_at: row.get(7)?,
+    })
+}
+
+fn hash_code(code: &str) -> String {
+    hex::encode(Sha256::digest(code.as_bytes()))
+}
+
+// the bytes a device signs: the fields of the request, one per line
+pub fn signed_message(parts: &[&str]) -> Vec<u8> {
+    parts.join("\n").into_bytes()
+}
+
+fn verify_signature(public_key_hex: &str, message: &[u8], signature_hex: &str) -> Result<(), String> {
+    let key_bytes: [u8; 32] = hex::decode(public_key_hex)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or("public key must be 32 bytes of hex")?;
+    let key = VerifyingKey::from_bytes(&key_bytes).map_err(|_| "invalid Ed25519 public key")?;
+    let signature_bytes: [u8; 64] = hex::decode(signature_hex)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or("signature must be 64 bytes of hex")?;
+    key.verify(message, &Signature::from_bytes(&signature_bytes))
+        .map_err(|_| "signature does not verify".to_string())
+}
+
+fn validate_device_id(device_id: &str) -> Result<(), String> {
+    let valid_chars = device_id.chars().all(|c| c.is_ascii_alphanumeric() || "._:-".contains(c));
+    if device_id.len() < 3 || device_id.len() > MAX_DEVICE_ID_LENGTH || !valid_chars {
+        return Err(format!("device id must be 3-{} letters, digits or . _ : -", MAX_DEVICE_ID_LENGTH));
+    }
+    Ok(())
+}
+
+/*
+Register a device for a patient in the role's scope and return its one-time pairing code.
+Registering a pending or revoked device id again replaces it; a paired device must be revoked first.
+*/
+pub fn register_device(
+    conn: &Connection,
+    role: &Role,
+    patient_id: &str,
+    device_id: &str,
+    device_type: DeviceType,
+    label: &str,
+) -> Result<String, String> {
+    if !access_control::can_access(conn, role, &Resource::Patient(patient_id), &Permission::ManageDevices) {
+        return Err("Access denied: patient is not in your care or ManageDevices is missing".to_string());
+    }
+    validate_device_id(device_id)?;
+    let label = label.trim();
+    if label.len() > MAX_LABEL_LENGTH || label.chars().any(char::is_control) {
+        return Err(format!("label must be at most {} printable characters", MAX_LABEL_LENGTH));
+    }
+
+    let status: Option<String> = conn
+        .query_row("SELECT status FROM devices WHERE device_id = ?1", [device_id], |row| row.get(0))
+        .optional()
+        .map_err(|e| e.to_string())?;
+    if status.as_deref() == Some("paired") {
+        return Err(format!("Device '{}' is already paired; revoke it before registering it again", device_id));
+    }
+
+    let code = auth::generate_one_time_code(PAIRING_CODE_LENGTH);
+    let ttl = format!("+{} minutes", config::get().devices.pairing_code_ttl_minutes);
+    conn.execute(
+        "INSERT OR REPLACE INTO devices (device_id, patient_id, device_type, label, status, pairing_code_hash,
+             pairing_expires_at, registered_by, registered_at)
+         VALUES (?1, ?2, ?3, ?4, 'pending', ?5, datetime('now', ?6), ?7, datetime('now'))",
+        params![device_id, patient_id, device_type.as_str(), label, hash_code(&code), ttl, role.id],
+    )
+    .map_err(|e| e.to_string())?;
+    tracing::info!(target: SECURITY_TARGET, user_id = %role.id, device_id = %device_id, patient_id = %patient_id, device_type = device_type.as_str(), "Device registered for pairing");
+    Ok(code)
+}
+
+/*
+Pair a registered device: the code must be unexpired and the signature must be made with the
+private key belonging to public_key over signed_message(["pair", device_id, pairing_code]).
+*/
+pub fn pair_device(conn: &Connection, device_id: &str, pairing_code: &str, public_key: &str, signature: &str) -> Result<Device, String> {
+    let pending: Option<String> = conn
+        .query_row(
+            "SELECT pairing_code_hash FROM devices
+             WHERE device_id = ?1 AND status = 'pending' AND julianday(pairing_expires_at) > julianday('now')",
+            [device_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let code_matches = pending.is_some_and(|hash| hash == hash_code(pairing_code.trim()));
+    if !code_matches {
+        tracing::warn!(target: SECURITY_TARGET, device_id = %device_id, "Device pairing rejected: unknown device or invalid or expired code");
+        return Err("unknown device or invalid or expired pairing code".to_string());
+    }
+    if let Err(e) = verify_signature(public_key, &signed_message(&["pair", device_id, pairing_code.trim()]), signature) {
+        tracing::warn!(target: SECURITY_TARGET, device_id = %device_id, error = %e, "Device pairing rejected: bad key or signature");
+        return Err(e);
+    }
+
+    conn.execute(
+        "UPDATE devices SET status = 'paired', public_key = ?2, paired_at = datetime('now'),
+             pairing_code_hash = NULL, pairing_expires_at = NULL, last_signed_at_ms = NULL
+         WHERE device_id = ?1",
+        params![device_id, public_key.to_ascii_lowercase()],
+    )
+    .map_err(|e| e.to_string())?;
+    let device = get_device(conn, device_id).map_err(|e| e.to_string())?.ok_or("device disappeared while pairing")?;
+    tracing::info!(target: SECURITY_TARGET, device_id = %device_id, patient_id = %device.patient_id, "Device paired");
+    Ok(device)
+}
+
+/*
+Check a telemetry request: the device must be paired to the patient with the expected type,
+signed_at_ms must be within devices.max_clock_skew_secs and newer than the device's last request,
+and signature must cover signed_message(parts). parts must include signed_at_ms.
+*/
+pub fn authenticate_device(
+    conn: &Connection,
+    device_id: &str,
+    patient_id: &str,
+    device_type: DeviceType,
+    signed_at_ms: i64,
+    parts: &[&str],
+    signature: &str,
+) -> Result<(), String> {
+    let result = check_device_request(conn, device_id, patient_id, device_type, signed_at_ms, parts, signature);
+    if let Err(e) = &result {
+        tracing::warn!(target: SECURITY_TARGET, device_id = %device_id, patient_id = %patient_id, error = %e, "Device request rejected");
+    }
+    result
+}
+
+fn check_device_request(
+    conn: &Connection,
+    device_id: &str,
+    patient_id: &str,
+    device_type: DeviceType,
+    signed_at_ms: i64,
+    parts: &[&str],
+    signature: &str,
+) -> Result<(), String> {
+    let skew_ms = i64::from(config::get().devices.max_clock_skew_secs) * 1000;
+    if (Utc::now().timestamp_millis() - signed_at_ms).abs() > skew_ms {
+        return Err("signed_at_ms is too far from the server clock".to_string());
+    }
+
+    let public_key: Option<String> = conn
+        .query_row(
+            "SELECT public_key FROM devices
+             WHERE device_id = ?1 AND patient_id = ?2 AND device_type = ?3 AND status = 'paired'",
+            params![device_id, patient_id, device_type.as_str()],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let public_key = public_key.ok_or_else(|| format!("no paired {} '{}' for this patient", device_type.as_str(), device_id))?;
+    verify_signature(&public_key, &signed_message(parts), signature)?;
+
+    // a request is accepted once; replays and reordered requests carry an old timestamp
+    let accepted = conn
+        .execute(
+            "UPDATE devices SET last_signed_at_ms = ?2, last_seen_at = datetime('now')
+             WHERE device_id = ?1 AND (last_signed_at_ms IS NULL OR last_signed_at_ms < ?2)",
+            params![device_id, signed_at_ms],
+        )
+        .map_err(|e| e.to_string())?;
+    if accepted == 0 {
+        return Err("request was already used or is older than the device's last request".to_string());
+    }
+    Ok(())
+}
+
+pub fn get_device(conn: &Connection, device_id: &str) -> rusqlite::Result<Option<Device>> {
+    let sql = format!("SELECT {} FROM devices WHERE device_id = ?1", DEVICE_COLUMNS);
+    conn.query_row(&sql, [device_id], device_from_row).optional()
+}
+
+// the pump currently paired to a patient, the most recently paired one if there are several
+pub fn paired_pump(conn: &Connection, patient_id: &str) -> rusqlite::Result<Option<Device>> {
+    let sql = format!(
+        "SELECT {} FROM devices WHERE patient_id = ?1 AND device_type = 'pump' AND status = 'paired'
+         ORDER BY paired_at DESC, rowid DESC LIMIT 1",
+        DEVICE_COLUMNS
+    );
+    conn.query_row(&sql, [patient_id], device_from_row).optional()
+}
+
+// devices of a patient, newest registration first, including revoked ones
+pub fn list_devices(conn: &Connection, patient_id: &str) -> rusqlite::Result<Vec<Device>> {
+    let sql = format!("SELECT {} FROM devices WHERE patient_id = ?1 ORDER BY registered_at DESC, rowid DESC", DEVICE_COLUMNS);
+    let mut stmt = conn.prepare(&sql)?;
+    let devices = stmt.query_map([patient_id], device_from_row)?;
+    devices.collect()
+}
+
+// stop accepting telemetry from a device and cancel the commands it has not fetched yet;
+// false when the patient has no such active device
+pub fn revoke_device(conn: &Connection, role: &Role, patient_id: &str, device_id: &str) -> Result<bool, String> {
+    if !access_control::can_access(conn, role, &Resource::Patient(patient_id), &Permission::ManageDevices) {
+        return Err("Access denied: patient is not in your care or ManageDevices is missing".to_string());
+    }
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let revoked = tx
+        .execute(
+            "UPDATE devices SET status = 'revoked', revoked_at = datetime('now'), pairing_code_hash = NULL, pairing_expires_at = NULL
+             WHERE device_id = ?1 AND patient_id = ?2 AND status != 'revoked'",
+            params![device_id, patient_id],
+        )
+        .map_err(|e| e.to_string())?;
+    extended_bolus::drop_unsent(&tx, device_id, &role.id).map_err(|e| e.to_string())?;
+    tx.execute("UPDATE pump_commands SET status = 'cancelled' WHERE device_id = ?1 AND status = 'pending'", [device_id])
+        .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    if revoked > 0 {
+        tracing::warn!(target: SECURITY_TARGET, user_id = %role.id, device_id = %device_id, patient_id = %patient_id, "Device revoked");
+    }
+    Ok(revoked > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use ed25519_dalek::{Signer, SigningKey};
+    use rand::RngCore;
+
+    fn device_key() -> SigningKey {
+        let mut secret = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut secret);
+        SigningKey::from_bytes(&secret)
+    }
+
+    fn sign(key: &SigningKey, parts: &[&str]) -> String {
+        hex::encode(key.sign(&signed_message(parts)).to_bytes())
+    }
+
+    fn paired_pump(conn: &Connection, fixtures: &test_utils::Fixtures, key: &SigningKey) {
+        let clinician = Role::new(conn, "clinician", &fixtures.clinician_id);
+        let code = register_device(conn, &clinician, &fixtures.patient_id, "PUMP-001", DeviceType::Pump, "Left hip").unwrap();
+        let public_key = hex::encode(key.verifying_key().to_bytes());
+        pair_device(conn, "PUMP-001", &code, &public_key, &sign(key, &["pair", "PUMP-001", &code])).unwrap();
+    }
+
+    #[test]
+    fn pairing_needs_the_code_and_the_matching_key() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let clinician = Role::new(&conn, "clinician", &fixtures.clinician_id);
+        let other = Role::new(&conn, "clinician", &fixtures.other_clinician_id);
+        assert!(register_device(&conn, &other, &fixtures.patient_id, "PUMP-001", DeviceType::Pump, "").is_err());
+
+        let code = register_device(&conn, &clinician, &fixtures.patient_id, "PUMP-001", DeviceType::Pump, "").unwrap();
+        let key = device_key();
+        let public_key = hex::encode(key.verifying_key().to_bytes());
+        assert!(pair_device(&conn, "PUMP-001", "WRONGCODE123", &public_key, &sign(&key, &["pair", "PUMP-001", "WRONGCODE123"])).is_err());
+        // signed by a different key than the one presented
+        assert!(pair_device(&conn, "PUMP-001", &code, &public_key, &sign(&device_key(), &["pair", "PUMP-001", &code])).is_err());
+
+        let device = pair_device(&conn, "PUMP-001", &code, &public_key, &sign(&key, &["pair", "PUMP-001", &code])).unwrap();
+        assert_eq!(device.status, "paired");
+        // the code is spent
+        assert!(pair_device(&conn, "PUMP-001", &code, &public_key, &sign(&key, &["pair", "PUMP-001", &code])).is_err());
+        assert!(register_device(&conn, &clinician, &fixtures.patient_id, "PUMP-001", DeviceType::Pump, "").is_err());
+    }
+
+    #[test]
+    fn only_signed_fresh_requests_from_paired_devices_are_accepted() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let key = device_key();
+        paired_pump(&conn, &fixtures, &key);
+
+        let now = Utc::now().timestamp_millis();
+        let stamp = now.to_string();
+        let parts = ["pull_commands", "PUMP-001", fixtures.patient_id.as_str(), stamp.as_str()];
+        let signature = sign(&key, &parts);
+        assert!(authenticate_device(&conn, "PUMP-001", &fixtures.patient_id, DeviceType::Pump, now, &parts, &signature).is_ok());
+        // replayed
+        assert!(authenticate_device(&conn, "PUMP-001", &fixtures.patient_id, DeviceType::Pump, now, &parts, &signature).is_err());
+
+        let later = (now + 1).to_string();
+        let parts = ["pull_commands", "PUMP-001", fixtures.patient_id.as_str(), later.as_str()];
+        let signature = sign(&key, &parts);
+        // wrong patient or device type
+        assert!(authenticate_device(&conn, "PUMP-001", &fixtures.other_patient_id, DeviceType::Pump, now + 1, &parts, &signature).is_err());
+        assert!(authenticate_device(&conn, "PUMP-001", &fixtures.patient_id, DeviceType::Cgm, now + 1, &parts, &signature).is_err());
+        // signed by another key
+        assert!(authenticate_device(&conn, "PUMP-001", &fixtures.patient_id, DeviceType::Pump, now + 1, &parts, &sign(&device_key(), &parts)).is_err());
+
+        let clinician = Role::new(&conn, "clinician", &fixtures.clinician_id);
+        assert!(revoke_device(&conn, &clinician, &fixtures.patient_id, "PUMP-001").unwrap());
+        assert!(authenticate_device(&conn, "PUMP-001", &fixtures.patient_id, DeviceType::Pump, now + 1, &parts, &signature).is_err());
+        assert_eq!(list_devices(&conn, &fixtures.patient_id).unwrap()[0].status, "revoked");
+    }
+
+    #[test]
+    fn stale_timestamps_are_rejected() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let key = device_key();
+        paired_pump(&conn, &fixtures, &key);
+
+        let old = Utc::now().timestamp_millis() - 3_600_000;
+        let stamp = old.to_string();
+        let parts = ["pull_commands", "PUMP-001", fixtures.patient_id.as_str(), stamp.as_str()];
+        assert!(authenticate_device(&conn, "PUMP-001", &fixtures.patient_id, DeviceType::Pump, old, &parts, &sign(&key, &parts)).is_err());
+    }
+}
@@ -21,7 +21,7 @@ pub fn collect_system_telemetry(conn: &Connection) -> Result<(), Box<dyn std::er
     {
     
 
-    let log_dir = "./target/debug/logs/health_data";
+    let log_dir = &crate::config::get().logging.log_dir;
     create_dir_all(log_dir)?;
     
     let timestamp = Utc::now().format("%Y%m%d_%H%M%S").to_string();
@@ -55,7 +55,7 @@ fn collect_patient_records(conn: &Connection, file: &mut File) -> Result<(), Box
     writeln!(file, "[PATIENT_DATA]")?;
     writeln!(file, "patient_id|first_name|last_name|dob|basal_rate|bolus_rate|max_dosage|low_threshold|high_threshold|clinician_id|caretaker_id")?;
     
-    let mut stmt = conn.prepare("SELECT patient_id, first_name, last_name, date_of_birth, basal_rate, bolus_rate, max_dosage, low_glucose_threshold, high_glucose_threshold, clinician_id, caretaker_id FROM patients")?;
+    let mut stmt = conn.prepare("SELECT patient_id, first_name, last_name, date_of_birth, basal_rate, bolus_rate, max_dosage, low_glucose_threshold, high_glucose_threshold, clinician_id, COALESCE((SELECT group_concat(caretaker_id) FROM patient_caretakers pc WHERE pc.patient_id = patients.patient_id), '') FROM patients")?;
     
     let rows = stmt.query_map([], |row| {
         Ok(format!("{}|{}|{}|{}|{}|{}|{}|{}|{}|{}|{}",
@@ -0,0 +1,446 @@
+/*
+Append-only, signed insulin dose log.
+Rows of insulin_logs are only ever added. A dose that will not be delivered after all (a request the pump
+never fetched, the remaining steps of an extended bolus stopped in an emergency) gets a void entry naming it
+instead of being deleted; the insulin_doses view leaves out void entries and the doses they void, and
+database triggers refuse UPDATE and DELETE. Each row is signed with HMAC-SHA256 under audit.signing_key over
+its fields and the signature of the patient's previous row, so an edited, removed or inserted row breaks that
+patient's chain; `glucoguard verify-doses` walks every chain. Without a key the chain still shows edits and
+gaps but not a history rewritten from scratch. Patient erasure is the one exception, see `unlock`.
+*/
+use crate::config;
+use crate::db::initialize;
+use crate::devices::signed_message;
+use crate::logger::SECURITY_TARGET;
+use crate::timestamps;
+use hmac::{Hmac, Mac};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use sha2::Sha256;
+use std::collections::HashSet;
+use std::error::Error;
+
+pub const VOID_ACTION: &str = "void";
+
+// one dose to append; dosage_time ("YYYY-MM-DD HH:MM:SS" UTC) is now when None
+#[derive(Debug, Clone)]
+pub struct DoseEntry<'a> {
+    pub patient_id: &'a str,
+    pub action_type: &'a str,
+    pub dosage_units: f64,
+    pub requested_by: &'a str,
+    pub dosage_time: Option<String>,
+    pub request_id: Option<&'a str>,
+}
+
+// a stored row as it is signed
+struct StoredEntry {
+    dosage_id: i64,
+    patient_id: String,
+    action_type: String,
+    dosage_units: f64,
+    requested_by: String,
+    dosage_time: String,
+    request_id: Option<String>,
+    voids_dosage_id: Option<i64>,
+    prev_signature: Option<String>,
+    signature: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ChainReport {
+    pub patients: usize,
+    pub entries: usize,
+    pub voids: usize,
+    // rows written before the log was signed
+    pub unsigned: usize,
+    // rows signed before audit.signing_key was set
+    pub unkeyed: usize,
+    pub problems: Vec<String>,
+}
+
+impl ChainReport {
+    pub fn is_intact(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+fn chain_key() -> Vec<u8> {
+    config::get().audit.signing_key.as_bytes().to_vec()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn entry_mac(
+    key: &[u8],
+    patient_id: &str,
+    action_type: &str,
+    dosage_units: f64,
+    requested_by: &str,
+    dosage_time: &str,
+    request_id: Option<&str>,
+    voids_dosage_id: Option<i64>,
+    prev_signature: &str,
+) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    let voids = voids_dosage_id.map(|id| id.to_string()).unwrap_or_default();
+    mac.update(&signed_message(&[
+        "insulin_log",
+        patient_id,
+        action_type,
+        &dosage_units.to_string(),
+        requested_by,
+        dosage_time,
+        request_id.unwrap_or_default(),
+        &voids,
+        prev_signature,
+    ]));
+    mac
+}
+
+fn stored_mac(key: &[u8], entry: &StoredEntry) -> Hmac<Sha256> {
+    entry_mac(
+        key,
+        &entry.patient_id,
+        &entry.action_type,
+        entry.dosage_units,
+        &entry.requested_by,
+        &entry.dosage_time,
+        entry.request_id.as_deref(),
+        entry.voids_dosage_id,
+        entry.prev_signature.as_deref().unwrap_or_default(),
+    )
+}
+
+// signature of the patient's newest signed row, "" before the first one
+fn chain_head(conn: &Connection, patient_id: &str) -> rusqlite::Result<String> {
+    let head: Option<String> = conn
+        .prepare_cached(
+            "SELECT signature FROM insulin_logs WHERE patient_id = ?1 AND signature IS NOT NULL ORDER BY dosage_id DESC LIMIT 1",
+        )?
+        .query_row([patient_id], |row| row.get(0))
+        .optional()?;
+    Ok(head.unwrap_or_default())
+}
+
+fn insert_signed(conn: &Connection, key: &[u8], entry: &DoseEntry, voids_dosage_id: Option<i64>) -> rusqlite::Result<i64> {
+    let dosage_time = entry.dosage_time.clone().unwrap_or_else(timestamps::now);
+    let prev_signature = chain_head(conn, entry.patient_id)?;
+    let signature = hex::encode(
+        entry_mac(
+            key,
+            entry.patient_id,
+            entry.action_type,
+            entry.dosage_units,
+            entry.requested_by,
+            &dosage_time,
+            entry.request_id,
+            voids_dosage_id,
+            &prev_signature,
+        )
+        .finalize()
+        .into_bytes(),
+    );
+    conn.prepare_cached(
+        "INSERT INTO insulin_logs (patient_id, action_type, dosage_units, requested_by, dosage_time, request_id, voids_dosage_id, prev_signature, signature)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+    )?
+    .execute(params![
+        entry.patient_id,
+        entry.action_type,
+        entry.dosage_units,
+        entry.requested_by,
+        dosage_time,
+        entry.request_id,
+        voids_dosage_id,
+        prev_signature,
+        signature
+    ])?;
+    Ok(conn.last_insert_rowid())
+}
+
+/*
+Append one row to the patient's chain, returns its dosage_id.
+Reading the chain head and inserting happen in one savepoint (a transaction of its own when the caller has
+none), so another connection cannot slip a row in between; if it tries, the unique chain index refuses it.
+*/
+fn append_row(conn: &Connection, key: &[u8], entry: &DoseEntry, voids_dosage_id: Option<i64>) -> rusqlite::Result<i64> {
+    conn.execute_batch("SAVEPOINT dose_log")?;
+    match insert_signed(conn, key, entry, voids_dosage_id) {
+        Ok(dosage_id) => {
+            conn.execute_batch("RELEASE dose_log")?;
+            Ok(dosage_id)
+        }
+        Err(e) => {
+            let _ = conn.execute_batch("ROLLBACK TO dose_log; RELEASE dose_log");
+            Err(e)
+        }
+    }
+}
+
+// record a dose
+pub fn append(conn: &Connection, entry: &DoseEntry) -> rusqlite::Result<i64> {
+    append_row(conn, &chain_key(), entry, None)
+}
+
+// mark doses of the patient as not delivered, one void entry each; ids already voided are skipped
+pub fn void_doses(conn: &Connection, patient_id: &str, dosage_ids: &[i64], voided_by: &str) -> rusqlite::Result<usize> {
+    let key = chain_key();
+    let mut voided = 0;
+    for dosage_id in dosage_ids {
+        let open: bool = conn
+            .prepare_cached(
+                "SELECT EXISTS (SELECT 1 FROM insulin_doses WHERE dosage_id = ?1 AND patient_id = ?2)",
+            )?
+            .query_row(params![dosage_id, patient_id], |row| row.get(0))?;
+        if !open {
+            continue;
+        }
+        let entry = DoseEntry {
+            patient_id,
+            action_type: VOID_ACTION,
+            dosage_units: 0.0,
+            requested_by: voided_by,
+            dosage_time: None,
+            request_id: None,
+        };
+        append_row(conn, &key, &entry, Some(*dosage_id))?;
+        voided += 1;
+    }
+    if voided > 0 {
+        tracing::info!(patient_id = %patient_id, voided_by = %voided_by, voided, "Insulin log entries voided");
+    }
+    Ok(voided)
+}
+
+/*
+Let patient erasure delete or rewrite these patients' rows. Only for use inside the erasure transaction,
+which must call `relock` before it commits; anonymised rows are then re-signed with `reseal`.
+*/
+pub fn unlock(conn: &Connection, patient_ids: &[&str]) -> rusqlite::Result<()> {
+    for patient_id in patient_ids {
+        conn.execute("INSERT OR IGNORE INTO insulin_log_unlocks (patient_id) VALUES (?1)", [patient_id])?;
+    }
+    Ok(())
+}
+
+pub fn relock(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM insulin_log_unlocks", [])?;
+    Ok(())
+}
+
+fn load_entries(conn: &Connection, patient_id: Option<&str>) -> rusqlite::Result<Vec<StoredEntry>> {
+    let mut stmt = conn.prepare(
+        "SELECT dosage_id, patient_id, action_type, dosage_units, requested_by, dosage_time, request_id,
+                voids_dosage_id, prev_signature, signature
+         FROM insulin_logs WHERE ?1 IS NULL OR patient_id = ?1 ORDER BY patient_id, dosage_id",
+    )?;
+    let rows = stmt.query_map([patient_id], |row| {
+        Ok(StoredEntry {
+            dosage_id: row.get(0)?,
+            patient_id: row.get(1)?,
+            action_type: row.get(2)?,
+            dosage_units: row.get(3)?,
+            requested_by: row.get(4)?,
+            dosage_time: row.get(5)?,
+            request_id: row.get(6)?,
+            voids_dosage_id: row.get(7)?,
+            prev_signature: row.get(8)?,
+            signature: row.get(9)?,
+        })
+    })?;
+    rows.collect()
+}
+
+// sign the patient's rows again, in order, after erasure rewrote them; needs `unlock`
+pub fn reseal(conn: &Connection, patient_id: &str) -> rusqlite::Result<usize> {
+    let key = chain_key();
+    let mut prev = String::new();
+    let mut resealed = 0;
+    for mut entry in load_entries(conn, Some(patient_id))? {
+        if entry.signature.is_none() {
+            continue;
+        }
+        entry.prev_signature = Some(prev);
+        let signature = hex::encode(stored_mac(&key, &entry).finalize().into_bytes());
+        conn.execute(
+            "UPDATE insulin_logs SET prev_signature = ?1, signature = ?2 WHERE dosage_id = ?3",
+            params![entry.prev_signature, signature, entry.dosage_id],
+        )?;
+        prev = signature;
+        resealed += 1;
+    }
+    Ok(resealed)
+}
+
+fn signature_matches(key: &[u8], entry: &StoredEntry, signature: &str) -> bool {
+    match hex::decode(signature) {
+        Ok(signature) => stored_mac(key, entry).verify_slice(&signature).is_ok(),
+        Err(_) => false,
+    }
+}
+
+/*
+Walk every patient's chain (or one patient's) under `key`.
+Unsigned rows are only expected before a patient's first signed row. Rows signed while no key was configured
+verify with the empty key, but only until the first row that verifies with `key`.
+Removing a patient's newest entries leaves a shorter chain that is still intact; compare the counts with an
+earlier report or a backup.
+*/
+pub fn verify(conn: &Connection, key: &[u8], patient_id: Option<&str>) -> rusqlite::Result<ChainReport> {
+    let mut report = ChainReport::default();
+    let mut current: Option<String> = None;
+    let mut prev = String::new();
+    let mut signed_seen = false;
+    let mut keyed_seen = false;
+    let mut seen_ids = HashSet::new();
+
+    for entry in load_entries(conn, patient_id)? {
+        if current.as_deref() != Some(entry.patient_id.as_str()) {
+            current = Some(entry.patient_id.clone());
+            prev.clear();
+            signed_seen = false;
+            keyed_seen = false;
+            seen_ids.clear();
+            report.patients += 1;
+        }
+        report.entries += 1;
+        let label = format!("entry {} of patient {}", entry.dosage_id, entry.patient_id);
+
+        let Some(signature) = entry.signature.clone() else {
+            if signed_seen {
+                report.problems.push(format!("{}: not signed, added outside the log", label));
+            } else {
+                report.unsigned += 1;
+            }
+            seen_ids.insert(entry.dosage_id);
+            continue;
+        };
+        signed_seen = true;
+
+        if entry.prev_signature.as_deref().unwrap_or_default() != prev {
+            report.problems.push(format!("{}: does not follow the previous entry, entries were removed or reordered", label));
+        }
+        if signature_matches(key, &entry, &signature) {
+            keyed_seen = true;
+        } else if !keyed_seen && !key.is_empty() && signature_matches(&[], &entry, &signature) {
+            report.unkeyed += 1;
+        } else {
+            report.problems.push(format!("{}: contents do not match the signature", label));
+        }
+
+        if let Some(voided) = entry.voids_dosage_id {
+            report.voids += 1;
+            if entry.action_type != VOID_ACTION || !seen_ids.contains(&voided) {
+                report.problems.push(format!("{}: voids entry {}, which is not an earlier dose of this patient", label, voided));
+            }
+        }
+        seen_ids.insert(entry.dosage_id);
+        prev = signature;
+    }
+    Ok(report)
+}
+
+// `glucoguard verify-doses [--patient <id>]`: exits non-zero when a chain is broken
+pub fn run(patient_id: Option<&str>, json: bool) -> Result<(), Box<dyn Error>> {
+    let conn = initialize::establish_connection()?;
+    let key = chain_key();
+    let report = verify(&conn, &key, patient_id)?;
+    if json {
+        println!("{}", serde_json::to_string(&report)?);
+    } else {
+        println!(
+            "{} entries of {} patients checked, {} voids, {} written before signing, {} signed without a key",
+            report.entries, report.patients, report.voids, report.unsigned, report.unkeyed
+        );
+        if key.is_empty() {
+            println!("audit.signing_key is not set: edits and gaps are detected, a rewritten history is not");
+        }
+        for problem in &report.problems {
+            println!("BROKEN {}", problem);
+        }
+    }
+    if report.is_intact() {
+        tracing::info!(entries = report.entries, patients = report.patients, "Insulin log verified");
+        Ok(())
+    } else {
+        tracing::error!(target: SECURITY_TARGET, problems = report.problems.len(), "Insulin log failed verification");
+        Err(format!("insulin log verification failed: {} problem(s)", report.problems.len()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    const KEY: &[u8] = b"0123456789abcdef0123456789abcdef";
+
+    fn dose<'a>(patient_id: &'a str, units: f64) -> DoseEntry<'a> {
+        DoseEntry {
+            patient_id,
+            action_type: "bolus",
+            dosage_units: units,
+            requested_by: patient_id,
+            dosage_time: None,
+            request_id: None,
+        }
+    }
+
+    #[test]
+    fn chains_verify_and_voids_hide_doses() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let patient_id = &fixtures.patient_id;
+        let first = append_row(&conn, KEY, &dose(patient_id, 2.0), None).unwrap();
+        let second = append_row(&conn, KEY, &dose(patient_id, 1.5), None).unwrap();
+        append_row(&conn, KEY, &dose(&fixtures.other_patient_id, 4.0), None).unwrap();
+        append_row(&conn, KEY, &DoseEntry { action_type: VOID_ACTION, dosage_units: 0.0, ..dose(patient_id, 0.0) }, Some(second))
+            .unwrap();
+
+        let report = verify(&conn, KEY, None).unwrap();
+        assert!(report.is_intact(), "{:?}", report.problems);
+        assert_eq!((report.patients, report.entries, report.voids), (2, 4, 1));
+        let (count, units): (i64, f64) = conn
+            .query_row("SELECT COUNT(*), SUM(dosage_units) FROM insulin_doses WHERE patient_id = ?1", [patient_id], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((count, units), (1, 2.0));
+
+        // the log refuses changes, and a row forged around the triggers breaks the chain
+        assert!(conn.execute("UPDATE insulin_logs SET dosage_units = 20 WHERE dosage_id = ?1", [first]).is_err());
+        assert!(conn.execute("DELETE FROM insulin_logs WHERE dosage_id = ?1", [first]).is_err());
+        conn.execute_batch("DROP TRIGGER insulin_logs_no_update").unwrap();
+        conn.execute("UPDATE insulin_logs SET dosage_units = 20 WHERE dosage_id = ?1", [first]).unwrap();
+        let report = verify(&conn, KEY, Some(patient_id)).unwrap();
+        assert_eq!(report.problems.len(), 1, "{:?}", report.problems);
+        assert!(report.problems[0].contains("do not match"));
+        assert!(!verify(&conn, b"another key", None).unwrap().is_intact());
+    }
+
+    #[test]
+    fn unsigned_and_unkeyed_rows_are_only_accepted_before_keyed_ones() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let patient_id = &fixtures.patient_id;
+        conn.execute(
+            "INSERT INTO insulin_logs (patient_id, action_type, dosage_units, requested_by, dosage_time) VALUES (?1, 'basal', 1.0, 'pump', datetime('now'))",
+            [patient_id],
+        )
+        .unwrap();
+        append_row(&conn, &[], &dose(patient_id, 1.0), None).unwrap();
+        append_row(&conn, KEY, &dose(patient_id, 1.0), None).unwrap();
+        let report = verify(&conn, KEY, None).unwrap();
+        assert!(report.is_intact(), "{:?}", report.problems);
+        assert_eq!((report.unsigned, report.unkeyed), (1, 1));
+
+        append_row(&conn, &[], &dose(patient_id, 1.0), None).unwrap();
+        conn.execute(
+            "INSERT INTO insulin_logs (patient_id, action_type, dosage_units, requested_by, dosage_time) VALUES (?1, 'bolus', 9.0, 'x', datetime('now'))",
+            [patient_id],
+        )
+        .unwrap();
+        let report = verify(&conn, KEY, None).unwrap();
+        assert_eq!(report.problems.len(), 2, "{:?}", report.problems);
+    }
+}
@@ -0,0 +1,207 @@
+// Safety checks every dose passes before it is queued for the pump
+// Doses are refused while delivery is suspended after an emergency stop, basal doses also while it is
+// suspended automatically for a predicted low (see low_suspend), and when they would take the
+// insulin given in the last 24 hours (logged doses plus commands the pump has not confirmed yet) past the
+// patient's max_dosage. A bolus is also refused, and an alert raised for the care team, when the latest
+// glucose reading is below the patient's low threshold or older than dosing.max_reading_age_minutes.
+use crate::alerts;
+use crate::config;
+use crate::db::models::Patient;
+use crate::db::queries;
+use crate::emergency;
+use crate::low_suspend;
+use rusqlite::{Connection, OptionalExtension};
+
+pub const LOW_GLUCOSE_ALERT: &str = "BOLUS_REFUSED_LOW";
+pub const STALE_READING_ALERT: &str = "BOLUS_REFUSED_STALE";
+
+// insulin counted against the patient's max_dosage over the last 24 hours
+#[derive(Debug, Clone, Copy)]
+pub struct DailyAllowance {
+    pub max_units: f64,
+    // doses in insulin_logs
+    pub logged_units: f64,
+    // queued or delivered pump commands the pump has not confirmed yet
+    pub pending_units: f64,
+}
+
+impl DailyAllowance {
+    pub fn used_units(&self) -> f64 {
+        self.logged_units + self.pending_units
+    }
+
+    pub fn remaining_units(&self) -> f64 {
+        (self.max_units - self.used_units()).max(0.0)
+    }
+}
+
+// rolling 24-hour window ending now; confirmed commands and bolus requests (logged when queued, including
+// scheduled steps) are already in insulin_logs and not counted twice
+pub fn daily_allowance(conn: &Connection, patient: &Patient) -> rusqlite::Result<DailyAllowance> {
+    let logged_units: f64 = conn.query_row(
+        "SELECT COALESCE(SUM(dosage_units), 0) FROM insulin_doses
+         WHERE patient_id = ?1 AND julianday(dosage_time) > julianday('now', '-1 day')",
+        [&patient.patient_id],
+        |row| row.get(0),
+    )?;
+    let pending_units: f64 = conn.query_row(
+        "SELECT COALESCE(SUM(dosage_units), 0) FROM pump_commands
+         WHERE patient_id = ?1 AND command_type IN ('bolus', 'basal') AND status IN ('pending', 'delivered')
+           AND request_id IS NULL AND julianday(created_at) > julianday('now', '-1 day')",
+        [&patient.patient_id],
+        |row| row.get(0),
+    )?;
+    Ok(DailyAllowance {
+        max_units: f64::from(patient.max_dosage),
+        logged_units,
+        pending_units,
+    })
+}
+
+// Ok when a dose of this type and size may be queued for the patient, otherwise the reason it is refused
+pub fn check_dose(conn: &Connection, patient_id: &str, command_type: &str, dosage_units: f64) -> Result<(), String> {
+    if command_type != "bolus" && command_type != "basal" {
+        return Ok(());
+    }
+    if emergency::active_suspension(conn, patient_id).map_err(|e| e.to_string())?.is_some() {
+        return Err("insulin delivery is suspended for this patient until a clinician resumes it".to_string());
+    }
+    if command_type == "basal" && low_suspend::active_low_suspension(conn, patient_id).map_err(|e| e.to_string())?.is_some() {
+        return Err("basal delivery is suspended automatically while a low is predicted".to_string());
+    }
+    let patient = queries::get_patient_by_id(conn, patient_id)
+        .map_err(|e| e.to_string())?
+        .ok_or("no patient record found")?;
+    let allowance = daily_allowance(conn, &patient).map_err(|e| e.to_string())?;
+    if dosage_units > allowance.remaining_units() + 1e-9 {
+        tracing::warn!(patient_id = %patient_id, dosage_units, used_units = allowance.used_units(), max_units = allowance.max_units, "Dose refused: daily maximum reached");
+        return Err(format!(
+            "{:.2} units would exceed the daily maximum of {:.2} units ({:.2} units left in the last 24 hours)",
+            dosage_units,
+            allowance.max_units,
+            allowance.remaining_units()
+        ));
+    }
+    if command_type == "bolus" {
+        check_bolus_interlock(conn, &patient)?;
+    }
+    Ok(())
+}
+
+// refuse a bolus on a low or stale glucose reading and alert the care team about it
+fn check_bolus_interlock(conn: &Connection, patient: &Patient) -> Result<(), String> {
+    let patient_id = &patient.patient_id;
+    let latest: Option<(f64, String, f64)> = conn
+        .query_row(
+            "SELECT glucose_level, reading_time, (julianday('now') - julianday(reading_time)) * 1440
+             FROM glucose_readings
+             WHERE patient_id = ?1
+             ORDER BY reading_time DESC, reading_id DESC
+             LIMIT 1",
+            [patient_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+
+    let max_age = config::get().dosing.max_reading_age_minutes;
+    let refusal = match latest {
+        None => Some((STALE_READING_ALERT, "no glucose reading on record".to_string())),
+        Some((_, reading_time, age_minutes)) if age_minutes > f64::from(max_age) => Some((
+            STALE_READING_ALERT,
+            format!("latest glucose reading ({}) is older than {} minutes", reading_time, max_age),
+        )),
+        Some((level, _, _)) if level < f64::from(patient.low_glucose_threshold) => Some((
+            LOW_GLUCOSE_ALERT,
+            format!("latest glucose {:.0} mg/dL is below the low threshold of {:.0} mg/dL", level, patient.low_glucose_threshold),
+        )),
+        Some(_) => None,
+    };
+    let Some((alert_type, reason)) = refusal else {
+        return Ok(());
+    };
+
+    tracing::warn!(patient_id = %patient_id, alert_type, "Bolus refused: {}", reason);
+    if let Err(e) = alerts::raise_alert(conn, patient_id, alert_type, &format!("Bolus refused: {}", reason)) {
+        tracing::error!(patient_id = %patient_id, "Failed to raise bolus interlock alert: {}", e);
+    }
+    Err(format!("bolus refused: {}", reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use rusqlite::params;
+
+    fn add_reading(conn: &Connection, patient_id: &str, level: f64, minutes_ago: u32) {
+        conn.execute(
+            "INSERT INTO glucose_readings (patient_id, glucose_level, reading_time, status)
+             VALUES (?1, ?2, datetime('now', ?3), 'normal')",
+            params![patient_id, level, format!("-{} minutes", minutes_ago)],
+        )
+        .unwrap();
+    }
+
+    fn alert_count(conn: &Connection, patient_id: &str, alert_type: &str) -> i64 {
+        conn.query_row(
+            "SELECT COUNT(*) FROM alerts WHERE patient_id = ?1 AND alert_type = ?2",
+            params![patient_id, alert_type],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn bolus_needs_a_recent_reading_above_the_low_threshold() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let patient_id = &fixtures.patient_id;
+
+        assert!(check_dose(&conn, patient_id, "bolus", 1.0).is_err());
+        add_reading(&conn, patient_id, 140.0, 60);
+        assert!(check_dose(&conn, patient_id, "bolus", 1.0).is_err());
+        assert_eq!(alert_count(&conn, patient_id, STALE_READING_ALERT), 1);
+
+        add_reading(&conn, patient_id, 55.0, 2);
+        let refused = check_dose(&conn, patient_id, "bolus", 1.0).unwrap_err();
+        assert!(refused.contains("below the low threshold"), "{}", refused);
+        assert_eq!(alert_count(&conn, patient_id, LOW_GLUCOSE_ALERT), 1);
+        // basal is not blocked by the bolus interlock
+        assert!(check_dose(&conn, patient_id, "basal", 1.0).is_ok());
+
+        add_reading(&conn, patient_id, 120.0, 1);
+        assert!(check_dose(&conn, patient_id, "bolus", 1.0).is_ok());
+    }
+
+    #[test]
+    fn doses_over_the_rolling_daily_maximum_are_refused() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let patient_id = &fixtures.patient_id;
+        add_reading(&conn, patient_id, 140.0, 1);
+        // max_dosage is 10 units; a dose from yesterday morning no longer counts
+        for (units, hours_ago) in [(4.0, 2), (9.0, 30)] {
+            conn.execute(
+                "INSERT INTO insulin_logs (patient_id, action_type, dosage_units, requested_by, dosage_time)
+                 VALUES (?1, 'bolus', ?2, 'test', datetime('now', ?3))",
+                params![patient_id, units, format!("-{} hours", hours_ago)],
+            )
+            .unwrap();
+        }
+        conn.execute(
+            "INSERT INTO pump_commands (patient_id, command_type, dosage_units, status, created_by, created_at)
+             VALUES (?1, 'bolus', 3.0, 'delivered', 'test', datetime('now'))",
+            [patient_id],
+        )
+        .unwrap();
+
+        let patient = queries::get_patient_by_id(&conn, patient_id).unwrap().unwrap();
+        let allowance = daily_allowance(&conn, &patient).unwrap();
+        assert_eq!(allowance.used_units(), 7.0);
+        assert_eq!(allowance.remaining_units(), 3.0);
+        assert!(check_dose(&conn, patient_id, "bolus", 3.0).is_ok());
+        assert!(check_dose(&conn, patient_id, "bolus", 3.5).is_err());
+        assert!(check_dose(&conn, patient_id, "basal", 3.5).is_err());
+    }
+}
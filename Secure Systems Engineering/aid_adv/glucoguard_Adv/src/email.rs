@@ -0,0 +1,153 @@
+// Optional SMTP delivery of activation codes to the address entered when an account is set up
+// Every attempt, sent or failed, is recorded in email_deliveries (without the code)
+// Daily patient summaries go out the same way; their delivery is recorded with the summary
+use crate::config::{self, EmailConfig};
+use crate::logger::SECURITY_TARGET;
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use rusqlite::{params, Connection};
+use std::error::Error;
+use std::fs;
+use std::time::Duration;
+
+const SMTP_TIMEOUT: Duration = Duration::from_secs(15);
+const ACTIVATION_SUBJECT: &str = "Your GlucoGuard activation code";
+const DEFAULT_ACTIVATION_TEMPLATE: &str = include_str!("../templates/activation_email.txt");
+
+// what goes into the activation email template
+pub struct ActivationEmail<'a> {
+    pub name: &'a str,
+    pub code: &'a str,
+    pub account_type: &'a str,
+    pub ttl_hours: u32,
+}
+
+pub fn enabled() -> bool {
+    config::get().email.enabled
+}
+
+// fill the {{placeholders}}; unknown placeholders are left as they are
+pub fn render_activation(template: &str, email: &ActivationEmail) -> String {
+    template
+        .replace("{{name}}", email.name)
+        .replace("{{code}}", email.code)
+        .replace("{{account_type}}", email.account_type)
+        .replace("{{ttl_hours}}", &email.ttl_hours.to_string())
+}
+
+fn activation_template(settings: &EmailConfig) -> Result<String, Box<dyn Error>> {
+    if settings.activation_template.is_empty() {
+        return Ok(DEFAULT_ACTIVATION_TEMPLATE.to_string());
+    }
+    fs::read_to_string(&settings.activation_template)
+        .map_err(|e| format!("cannot read email template {}: {}", settings.activation_template, e).into())
+}
+
+fn transport(settings: &EmailConfig) -> Result<SmtpTransport, Box<dyn Error>> {
+    let builder = match settings.security.as_str() {
+        "tls" => SmtpTransport::relay(&settings.smtp_host)?,
+        "none" => SmtpTransport::builder_dangerous(&settings.smtp_host),
+        _ => SmtpTransport::starttls_relay(&settings.smtp_host)?,
+    };
+    let mut builder = builder.port(settings.smtp_port).timeout(Some(SMTP_TIMEOUT));
+    if !settings.username.is_empty() {
+        builder = builder.credentials(Credentials::new(settings.username.clone(), settings.password.clone()));
+    }
+    Ok(builder.build())
+}
+
+fn send(settings: &EmailConfig, recipient: &str, subject: &str, body: String) -> Result<(), Box<dyn Error>> {
+    let message = Message::builder()
+        .from(settings.from.parse()?)
+        .to(recipient.parse()?)
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(body)?;
+    transport(settings)?.send(&message)?;
+    Ok(())
+}
+
+fn record_delivery(
+    conn: &Connection,
+    user_id: &str,
+    user_type: &str,
+    recipient: &str,
+    sent_by: &str,
+    error: Option<&str>,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO email_deliveries (user_id, user_type, recipient, sent_by, sent_at, status, error)
+         VALUES (?1, ?2, ?3, ?4, datetime('now'), ?5, ?6)",
+        params![user_id, user_type, recipient, sent_by, if error.is_none() { "sent" } else { "failed" }, error],
+    )?;
+    Ok(())
+}
+
+/*
+Email an activation code for the account user_id to recipient.
+Fails when email is disabled or sending fails; the caller then shows the code instead.
+*/
+pub fn send_activation_code(
+    conn: &Connection,
+    recipient: &str,
+    user_id: &str,
+    sent_by: &str,
+    email: &ActivationEmail,
+) -> Result<(), Box<dyn Error>> {
+    let settings = &config::get().email;
+    if !settings.enabled {
+        return Err("email delivery is disabled".into());
+    }
+    let result = activation_template(settings).and_then(|template| send(settings, recipient, ACTIVATION_SUBJECT, render_activation(&template, email)));
+
+    let error = result.as_ref().err().map(|e| e.to_string());
+    record_delivery(conn, user_id, email.account_type, recipient, sent_by, error.as_deref())?;
+    match &error {
+        None => tracing::info!(target: SECURITY_TARGET, user_id = %user_id, sent_by = %sent_by, "Activation code emailed"),
+        Some(e) => tracing::warn!(target: SECURITY_TARGET, user_id = %user_id, sent_by = %sent_by, error = %e, "Activation code email failed"),
+    }
+    result
+}
+
+// email a plain-text report, such as a daily patient summary; fails when email is disabled
+pub fn send_report(recipient: &str, subject: &str, body: String) -> Result<(), Box<dyn Error>> {
+    let settings = &config::get().email;
+    if !settings.enabled {
+        return Err("email delivery is disabled".into());
+    }
+    send(settings, recipient, subject, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    #[test]
+    fn built_in_template_has_every_placeholder_filled() {
+        let email = ActivationEmail { name: "Ada Fixture", code: "AbC123", account_type: "caretaker", ttl_hours: 48 };
+        let body = render_activation(DEFAULT_ACTIVATION_TEMPLATE, &email);
+        assert!(body.contains("Hello Ada Fixture,"));
+        assert!(body.contains("caretaker account"));
+        assert!(body.contains("AbC123"));
+        assert!(body.contains("48 hours"));
+        assert!(!body.contains("{{"));
+    }
+
+    #[test]
+    fn deliveries_are_recorded_without_the_code() {
+        let conn = test_utils::test_db();
+        record_delivery(&conn, "carer-1", "caretaker", "carer@example.org", "pat-1", None).unwrap();
+        record_delivery(&conn, "carer-1", "caretaker", "carer@example.org", "pat-1", Some("connection refused")).unwrap();
+
+        let rows: Vec<(String, Option<String>)> = conn
+            .prepare("SELECT status, error FROM email_deliveries WHERE user_id = 'carer-1' ORDER BY delivery_id")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(rows, vec![("sent".to_string(), None), ("failed".to_string(), Some("connection refused".to_string()))]);
+    }
+}
@@ -0,0 +1,172 @@
+// Emergency stop of all insulin delivery for a patient
+// Patients, caretakers and clinicians with EmergencyStop can suspend delivery at once: commands the pump
+// has not fetched are cancelled, along with the scheduled steps of extended boluses, a running temporary
+// basal rate is ended, a signed "suspend" command is queued for the paired pump, new doses are refused and
+// a critical alert is raised. Delivery only resumes once a clinician with ResumeInsulin confirms it.
+use crate::access_control::{self, Permission, Resource, Role};
+use crate::alerts;
+use crate::extended_bolus;
+use crate::input_validation::validate_free_text;
+use crate::logger::SECURITY_TARGET;
+use crate::low_suspend;
+use crate::pump;
+use crate::temp_basal;
+use rusqlite::{params, Connection, OptionalExtension};
+
+pub const ALERT_TYPE: &str = "INSULIN_SUSPENDED";
+const MAX_REASON_LENGTH: usize = 200;
+
+#[derive(Debug, Clone)]
+pub struct Suspension {
+    pub reason: String,
+    // username of whoever stopped delivery, or their user id if the account is gone
+    pub suspended_by: String,
+    pub suspended_at: String,
+}
+
+// what happened to the pump side of a stop or resume
+pub struct PumpNotice {
+    pub cancelled_commands: usize,
+    // id of the queued suspend/resume command, or why none could be sent
+    pub command: Result<i64, String>,
+}
+
+// the patient's active suspension, if delivery is currently stopped
+pub fn active_suspension(conn: &Connection, patient_id: &str) -> rusqlite::Result<Option<Suspension>> {
+    conn.query_row(
+        "SELECT s.reason, COALESCE(u.user_name, s.suspended_by), s.suspended_at
+         FROM insulin_suspensions s LEFT JOIN users u ON u.id = s.suspended_by
+         WHERE s.patient_id = ?1 AND s.resumed_at IS NULL",
+        [patient_id],
+        |row| {
+            Ok(Suspension {
+                reason: row.get(0)?,
+                suspended_by: row.get(1)?,
+                suspended_at: row.get(2)?,
+            })
+        },
+    )
+    .optional()
+}
+
+/*
+Suspend all insulin delivery for a patient in the role's scope.
+The suspension and the cancelled commands are committed before the pump is told, so delivery is
+stopped on the server side even when no pump is paired or command signing is not configured.
+*/
+pub fn emergency_stop(conn: &Connection, role: &Role, patient_id: &str, reason: &str) -> Result<PumpNotice, String> {
+    if !access_control::can_access(conn, role, &Resource::Patient(patient_id), &Permission::EmergencyStop) {
+        return Err("Access denied: patient is not in your care or EmergencyStop is missing".to_string());
+    }
+    let reason = reason.trim();
+    validate_free_text(reason, MAX_REASON_LENGTH)?;
+    if active_suspension(conn, patient_id).map_err(|e| e.to_string())?.is_some() {
+        return Err("insulin delivery is already suspended for this patient".to_string());
+    }
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    tx.execute(
+        "INSERT INTO insulin_suspensions (patient_id, reason, suspended_by, suspended_at) VALUES (?1, ?2, ?3, datetime('now'))",
+        params![patient_id, reason, role.id],
+    )
+    .map_err(|e| e.to_string())?;
+    let dropped_doses = extended_bolus::drop_scheduled(&tx, patient_id, &role.id).map_err(|e| e.to_string())?;
+    let cancelled_commands = tx
+        .execute("UPDATE pump_commands SET status = 'cancelled' WHERE patient_id = ?1 AND status = 'pending'", [patient_id])
+        .map_err(|e| e.to_string())?;
+    temp_basal::end_running(&tx, patient_id, &role.id, "emergency_stop").map_err(|e| e.to_string())?;
+    low_suspend::end_for_emergency_stop(&tx, patient_id, &role.id).map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    tracing::warn!(target: SECURITY_TARGET, user_id = %role.id, role = %role.name, patient_id = %patient_id, reason = %reason, cancelled_commands, dropped_doses, "Insulin delivery suspended (emergency stop)");
+
+    let message = if reason.is_empty() {
+        format!("Emergency stop by {}: all insulin delivery suspended", role.name)
+    } else {
+        format!("Emergency stop by {}: all insulin delivery suspended ({})", role.name, reason)
+    };
+    if let Err(e) = alerts::raise_alert(conn, patient_id, ALERT_TYPE, &message) {
+        tracing::error!(patient_id = %patient_id, "Failed to raise emergency stop alert: {}", e);
+    }
+    let command = pump::queue_command(conn, patient_id, "suspend", 0.0, &role.id);
+    if let Err(e) = &command {
+        tracing::error!(patient_id = %patient_id, "Emergency stop not sent to the pump: {}", e);
+    }
+    Ok(PumpNotice { cancelled_commands, command })
+}
+
+// lift the active suspension, resolve its alerts and tell the pump to resume
+pub fn resume_delivery(conn: &Connection, role: &Role, patient_id: &str, note: &str) -> Result<PumpNotice, String> {
+    if !access_control::can_access(conn, role, &Resource::Patient(patient_id), &Permission::ResumeInsulin) {
+        return Err("Access denied: a clinician responsible for the patient must confirm the resume".to_string());
+    }
+    let note = note.trim();
+    validate_free_text(note, MAX_REASON_LENGTH)?;
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let resumed = tx
+        .execute(
+            "UPDATE insulin_suspensions SET resumed_by = ?2, resumed_at = datetime('now'), resume_note = ?3
+             WHERE patient_id = ?1 AND resumed_at IS NULL",
+            params![patient_id, role.id, note],
+        )
+        .map_err(|e| e.to_string())?;
+    if resumed == 0 {
+        return Err("insulin delivery is not suspended for this patient".to_string());
+    }
+    tx.execute(
+        "UPDATE alerts SET is_resolved = 1, resolved_by = ?3 WHERE patient_id = ?1 AND alert_type = ?2 AND is_resolved = 0",
+        params![patient_id, ALERT_TYPE, role.id],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    tracing::warn!(target: SECURITY_TARGET, user_id = %role.id, patient_id = %patient_id, note = %note, "Insulin delivery resumed after emergency stop");
+
+    let command = pump::queue_command(conn, patient_id, "resume", 0.0, &role.id);
+    if let Err(e) = &command {
+        tracing::error!(patient_id = %patient_id, "Resume not sent to the pump: {}", e);
+    }
+    Ok(PumpNotice { cancelled_commands: 0, command })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    #[test]
+    fn stop_blocks_doses_until_a_clinician_resumes() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let patient = Role::new(&conn, "patient", &fixtures.patient_id);
+        let caretaker = Role::new(&conn, "caretaker", &fixtures.caretaker_id);
+        let clinician = Role::new(&conn, "clinician", &fixtures.clinician_id);
+        let other_clinician = Role::new(&conn, "clinician", &fixtures.other_clinician_id);
+        conn.execute(
+            "INSERT INTO pump_commands (patient_id, command_type, dosage_units, status, created_by, created_at)
+             VALUES (?1, 'bolus', 2.0, 'pending', ?1, datetime('now'))",
+            [&fixtures.patient_id],
+        )
+        .unwrap();
+
+        let notice = emergency_stop(&conn, &caretaker, &fixtures.patient_id, "hypo symptoms").unwrap();
+        assert_eq!(notice.cancelled_commands, 1);
+        // no signing key or pump in tests, the suspension still holds
+        assert!(notice.command.is_err());
+        assert!(active_suspension(&conn, &fixtures.patient_id).unwrap().is_some());
+        assert!(emergency_stop(&conn, &patient, &fixtures.patient_id, "").is_err());
+        let alerts = alerts::get_recent_alerts(&conn, &fixtures.patient_id, 5).unwrap();
+        assert!(alerts.iter().any(|alert| alert.alert_type == ALERT_TYPE && !alert.is_resolved));
+
+        // patients and caretakers cannot lift it, nor can a clinician outside the care team
+        assert!(resume_delivery(&conn, &patient, &fixtures.patient_id, "").is_err());
+        assert!(resume_delivery(&conn, &caretaker, &fixtures.patient_id, "").is_err());
+        assert!(resume_delivery(&conn, &other_clinician, &fixtures.patient_id, "").is_err());
+        resume_delivery(&conn, &clinician, &fixtures.patient_id, "glucose stable").unwrap();
+        assert!(active_suspension(&conn, &fixtures.patient_id).unwrap().is_none());
+        let alerts = alerts::get_recent_alerts(&conn, &fixtures.patient_id, 5).unwrap();
+        assert!(alerts.iter().all(|alert| alert.alert_type != ALERT_TYPE || alert.is_resolved));
+
+        // out of scope patients cannot be stopped
+        assert!(emergency_stop(&conn, &patient, &fixtures.other_patient_id, "").is_err());
+    }
+}
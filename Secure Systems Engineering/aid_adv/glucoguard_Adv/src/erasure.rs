@@ -0,0 +1,414 @@
+// Patient right to erasure: remove or anonymise everything tied to one patient in a single transaction
+// Every erasure leaves one erasure_log row signed with audit.signing_key, which names the admin but not the patient
+use crate::access_control::{Permission, Role};
+use crate::config;
+use crate::db::queries;
+use crate::dose_log;
+use crate::logger::SECURITY_TARGET;
+use crate::session;
+use crate::timestamps;
+use hmac::{Hmac, Mac};
+use rusqlite::{params, Connection};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::error::Error;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErasureMode {
+    // every row about the patient is deleted
+    Delete,
+    // identity, account and device rows are deleted; readings, doses, alerts and meals stay
+    // under a random id that links to nobody, so clinic statistics keep their history
+    Anonymize,
+}
+
+impl ErasureMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErasureMode::Delete => "delete",
+            ErasureMode::Anonymize => "anonymize",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ErasureRecord {
+    pub erasure_id: String,
+    pub subject_hash: String,
+    pub mode: String,
+    pub performed_by: String,
+    pub performed_at: String,
+    // JSON object: table -> rows removed or anonymised
+    pub row_counts: String,
+    pub signature: String,
+}
+
+// clinical tables that keep their rows under a surrogate id in anonymize mode
+const CLINICAL_TABLES: [&str; 8] = [
+    "glucose_readings",
+    "insulin_logs",
+    "alerts",
+    "meal_logs",
+    "pump_commands",
+    "insulin_suspensions",
+    "temp_basal_rates",
+    "low_glucose_suspensions",
+];
+// rows that identify the patient, their account or their devices, always deleted
+const IDENTITY_TABLES: [(&str, &str); 15] = [
+    ("pump_status", "patient_id"),
+    ("patient_transfers", "patient_id"),
+    ("daily_summaries", "patient_id"),
+    ("clinical_note_revisions", "patient_id"),
+    ("clinical_notes", "patient_id"),
+    ("patient_consents", "patient_id"),
+    ("reminder_completions", "patient_id"),
+    ("care_reminders", "patient_id"),
+    ("devices", "patient_id"),
+    ("patient_caretakers", "patient_id"),
+    ("nightscout_sync", "patient_id"),
+    ("sessions", "user_id"),
+    ("activation_codes", "user_id"),
+    ("email_deliveries", "user_id"),
+    ("api_tokens", "user_id"),
+];
+
+pub fn subject_hash(patient_id: &str) -> String {
+    hex::encode(Sha256::digest(patient_id.as_bytes()))
+}
+
+fn signing_key() -> Result<String, Box<dyn Error>> {
+    let key = config::get().audit.signing_key.clone();
+    if key.is_empty() {
+        return Err("Erasure is disabled: set audit.signing_key (GLUCOGUARD_AUDIT_SIGNING_KEY)".into());
+    }
+    Ok(key)
+}
+
+fn record_mac(key: &[u8], record: &ErasureRecord) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    // fields are joined with a separator none of them can contain
+    for field in [
+        &record.erasure_id,
+        &record.subject_hash,
+        &record.mode,
+        &record.performed_by,
+        &record.performed_at,
+        &record.row_counts,
+    ] {
+        mac.update(field.as_bytes());
+        mac.update(b"\x1f");
+    }
+    mac
+}
+
+pub fn sign_record(key: &[u8], record: &ErasureRecord) -> String {
+    hex::encode(record_mac(key, record).finalize().into_bytes())
+}
+
+// true when the record was signed with `key` and has not been edited since
+pub fn verify_record(key: &[u8], record: &ErasureRecord) -> bool {
+    match hex::decode(&record.signature) {
+        Ok(signature) => record_mac(key, record).verify_slice(&signature).is_ok(),
+        Err(_) => false,
+    }
+}
+
+// drop the patient from a comma-separated id list (scoped API tokens)
+fn without_id(list: &str, patient_id: &str) -> String {
+    list.split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty() && *id != patient_id)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// tokens of other users scoped to this patient lose it; a token left with no patients is revoked,
+// because an empty scope would otherwise read as "every patient"
+fn scrub_token_scopes(conn: &Connection, patient_id: &str) -> rusqlite::Result<usize> {
+    let rows = {
+        let mut stmt = conn.prepare("SELECT token_id, patient_ids FROM api_tokens WHERE patient_ids IS NOT NULL")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+    let mut changed = 0;
+    for (token_id, list) in rows {
+        let remaining = without_id(&list, patient_id);
+        if remaining == list {
+            continue;
+        }
+        if remaining.is_empty() {
+            conn.execute("UPDATE api_tokens SET patient_ids = '', revoked = 1 WHERE token_id = ?1", [&token_id])?;
+        } else {
+            conn.execute("UPDATE api_tokens SET patient_ids = ?1 WHERE token_id = ?2", params![remaining, token_id])?;
+        }
+        changed += 1;
+    }
+    Ok(changed)
+}
+
+fn erase_rows(conn: &Connection, patient_id: &str, mode: ErasureMode) -> rusqlite::Result<BTreeMap<&'static str, usize>> {
+    let mut counts = BTreeMap::new();
+
+    let surrogate = format!("erased-{}", Uuid::new_v4());
+    // escalation notices quote the patient's name; the history goes with the alerts it belongs to
+    counts.insert(
+        "alert_notifications",
+        conn.execute("DELETE FROM alert_notifications WHERE alert_id IN (SELECT alert_id FROM alerts WHERE patient_id = ?1)", [patient_id])?,
+    );
+    let history = match mode {
+        ErasureMode::Delete => conn.execute("DELETE FROM alert_history WHERE alert_id IN (SELECT alert_id FROM alerts WHERE patient_id = ?1)", [patient_id])?,
+        ErasureMode::Anonymize => conn.execute(
+            "UPDATE alert_history SET user_id = 'patient' WHERE user_id = ?1 AND alert_id IN (SELECT alert_id FROM alerts WHERE patient_id = ?1)",
+            [patient_id],
+        )?,
+    };
+    counts.insert("alert_history", history);
+    // the insulin log is append-only for everything but erasure
+    dose_log::unlock(conn, &[patient_id, &surrogate])?;
+    for table in CLINICAL_TABLES {
+        let changed = match mode {
+            ErasureMode::Delete => conn.execute(&format!("DELETE FROM {} WHERE patient_id = ?1", table), [patient_id])?,
+            ErasureMode::Anonymize => conn.execute(
+                &format!("UPDATE {} SET patient_id = ?1 WHERE patient_id = ?2", table),
+                params![surrogate, patient_id],
+            )?,
+        };
+        counts.insert(table, changed);
+    }
+    if mode == ErasureMode::Anonymize {
+        // pump device ids embed part of the patient id, and self-requested doses name the patient
+        conn.execute(
+            "UPDATE insulin_logs SET requested_by = CASE
+                 WHEN requested_by LIKE 'pump:%' THEN 'pump'
+                 WHEN requested_by = ?2 THEN 'patient'
+                 ELSE requested_by END
+             WHERE patient_id = ?1",
+            params![surrogate, patient_id],
+        )?;
+        conn.execute("UPDATE pump_commands SET created_by = 'patient' WHERE patient_id = ?1 AND created_by = ?2", params![surrogate, patient_id])?;
+        conn.execute(
+            "UPDATE insulin_suspensions SET suspended_by = 'patient' WHERE patient_id = ?1 AND suspended_by = ?2",
+            params![surrogate, patient_id],
+        )?;
+        conn.execute("UPDATE temp_basal_rates SET set_by = 'patient' WHERE patient_id = ?1 AND set_by = ?2", params![surrogate, patient_id])?;
+        conn.execute("UPDATE temp_basal_rates SET ended_by = 'patient' WHERE patient_id = ?1 AND ended_by = ?2", params![surrogate, patient_id])?;
+        conn.execute("UPDATE low_glucose_suspensions SET ended_by = 'patient' WHERE patient_id = ?1 AND ended_by = ?2", params![surrogate, patient_id])?;
+        conn.execute("UPDATE alerts SET acknowledged_by = 'patient' WHERE patient_id = ?1 AND acknowledged_by = ?2", params![surrogate, patient_id])?;
+        // the rewritten doses form a new chain under the surrogate id
+        dose_log::reseal(conn, &surrogate)?;
+    }
+    dose_log::relock(conn)?;
+
+    for (table, column) in IDENTITY_TABLES {
+        counts.insert(table, conn.execute(&format!("DELETE FROM {} WHERE {} = ?1", table, column), [patient_id])?);
+    }
+    session::forget_user_sessions(patient_id);
+    counts.insert("api_token_scopes", scrub_token_scopes(conn, patient_id)?);
+    counts.insert("patients", conn.execute("DELETE FROM patients WHERE patient_id = ?1", [patient_id])?);
+    // patient accounts use the patient id as their user id
+    counts.insert("users", conn.execute("DELETE FROM users WHERE id = ?1 AND role = 'patient'", [patient_id])?);
+    Ok(counts)
+}
+
+/*
+Erase one patient. Requires ErasePatientData and a configured audit.signing_key.
+All changes and the signed erasure_log row are written in one transaction: either everything
+is gone and recorded, or nothing changed.
+*/
+pub fn erase_patient(conn: &Connection, role: &Role, patient_id: &str, mode: ErasureMode) -> Result<ErasureRecord, Box<dyn Error>> {
+    if !role.has_permission(&Permission::ErasePatientData) {
+        return Err("Access denied: ErasePatientData permission required".into());
+    }
+    let key = signing_key()?;
+    if queries::get_patient_by_id(conn, patient_id)?.is_none() {
+        return Err(format!("No patient with id '{}'", patient_id).into());
+    }
+
+    let tx = conn.unchecked_transaction()?;
+    let counts = erase_rows(&tx, patient_id, mode)?;
+    let mut record = ErasureRecord {
+        erasure_id: Uuid::new_v4().to_string(),
+        subject_hash: subject_hash(patient_id),
+        mode: mode.as_str().to_string(),
+        performed_by: role.id.clone(),
+        performed_at: timestamps::now(),
+        row_counts: serde_json::to_string(&counts)?,
+        signature: String::new(),
+    };
+    record.signature = sign_record(key.as_bytes(), &record);
+    tx.execute(
+        "INSERT INTO erasure_log (erasure_id, subject_hash, mode, performed_by, performed_at, row_counts, signature)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            record.erasure_id,
+            record.subject_hash,
+            record.mode,
+            record.performed_by,
+            record.performed_at,
+            record.row_counts,
+            record.signature
+        ],
+    )?;
+    tx.commit()?;
+
+    tracing::warn!(
+        target: SECURITY_TARGET,
+        user_id = %role.id,
+        erasure_id = %record.erasure_id,
+        mode = %record.mode,
+        "Patient data erased"
+    );
+    Ok(record)
+}
+
+// every erasure record, newest first
+pub fn list_records(conn: &Connection) -> rusqlite::Result<Vec<ErasureRecord>> {
+    let mut stmt = conn.prepare(
+        "SELECT erasure_id, subject_hash, mode, performed_by, performed_at, row_counts, signature
+         FROM erasure_log ORDER BY performed_at DESC",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(ErasureRecord {
+            erasure_id: row.get(0)?,
+            subject_hash: row.get(1)?,
+            mode: row.get(2)?,
+            performed_by: row.get(3)?,
+            performed_at: row.get(4)?,
+            row_counts: row.get(5)?,
+            signature: row.get(6)?,
+        })
+    })?;
+    rows.collect()
+}
+
+// records whose signature does not match under the configured key
+pub fn unverified_records(conn: &Connection) -> Result<Vec<ErasureRecord>, Box<dyn Error>> {
+    let key = signing_key()?;
+    Ok(list_records(conn)?
+        .into_iter()
+        .filter(|record| !verify_record(key.as_bytes(), record))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    const KEY: &[u8] = b"0123456789abcdef0123456789abcdef";
+
+    fn add_history(conn: &Connection, patient_id: &str) {
+        conn.execute_batch(&format!(
+            "INSERT INTO glucose_readings (patient_id, glucose_level, reading_time, status) VALUES ('{id}', 60, datetime('now'), 'low');
+             INSERT INTO insulin_logs (patient_id, action_type, dosage_units, requested_by, dosage_time)
+                 VALUES ('{id}', 'bolus', 2.0, 'pump:demo-{id}', datetime('now'));
+             INSERT INTO alerts (patient_id, alert_type, alert_message, alert_time, is_resolved) VALUES ('{id}', 'LOW', 'low', datetime('now'), 0);
+             INSERT INTO meal_logs (patient_id, carbohydrate_amount, meal_time) VALUES ('{id}', 45, datetime('now'));
+             INSERT INTO sessions (session_id, user_id, role, creation_time, expiration_time, active) VALUES ('s-{id}', '{id}', 'patient', 0, 60, 1);
+             INSERT INTO patient_caretakers (patient_id, caretaker_id, added_by, added_at) VALUES ('{id}', 'carer', 'carer', datetime('now'));",
+            id = patient_id
+        ))
+        .unwrap();
+    }
+
+    fn count(conn: &Connection, sql: &str, patient_id: &str) -> i64 {
+        conn.query_row(sql, [patient_id], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn delete_removes_every_row_about_the_patient() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        add_history(&conn, &fixtures.patient_id);
+        add_history(&conn, &fixtures.other_patient_id);
+
+        let tx = conn.unchecked_transaction().unwrap();
+        let counts = erase_rows(&tx, &fixtures.patient_id, ErasureMode::Delete).unwrap();
+        tx.commit().unwrap();
+
+        assert_eq!(counts["glucose_readings"], 1);
+        assert_eq!(counts["users"], 1);
+        for table in CLINICAL_TABLES.iter().chain(["patients"].iter()) {
+            let sql = format!("SELECT COUNT(*) FROM {} WHERE patient_id = ?1", table);
+            assert_eq!(count(&conn, &sql, &fixtures.patient_id), 0, "{}", table);
+        }
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM sessions WHERE user_id = ?1", &fixtures.patient_id), 0);
+        assert_eq!(counts["patient_caretakers"], 2);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM patient_caretakers WHERE patient_id = ?1", &fixtures.patient_id), 0);
+        // the other patient is untouched
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM glucose_readings WHERE patient_id = ?1", &fixtures.other_patient_id), 1);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM patient_caretakers WHERE patient_id = ?1", &fixtures.other_patient_id), 1);
+    }
+
+    #[test]
+    fn anonymize_keeps_clinical_rows_without_the_patient_id() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        add_history(&conn, &fixtures.patient_id);
+        let signed = dose_log::DoseEntry {
+            patient_id: &fixtures.patient_id,
+            action_type: "bolus",
+            dosage_units: 1.0,
+            requested_by: &fixtures.patient_id,
+            dosage_time: None,
+            request_id: None,
+        };
+        dose_log::append(&conn, &signed).unwrap();
+
+        erase_rows(&conn, &fixtures.patient_id, ErasureMode::Anonymize).unwrap();
+
+        let (total, linked): (i64, i64) = conn
+            .query_row(
+                "SELECT COUNT(*), SUM(patient_id = ?1) FROM glucose_readings",
+                [&fixtures.patient_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((total, linked), (1, 0));
+        let requested_by: String = conn.query_row("SELECT requested_by FROM insulin_logs ORDER BY dosage_id", [], |row| row.get(0)).unwrap();
+        assert_eq!(requested_by, "pump");
+        // the signed dose was re-signed under the surrogate id, and the log is locked again
+        let report = dose_log::verify(&conn, config::get().audit.signing_key.as_bytes(), None).unwrap();
+        assert!(report.is_intact(), "{:?}", report.problems);
+        assert_eq!((report.entries, report.unsigned), (2, 1));
+        assert!(conn.execute("DELETE FROM insulin_logs", []).is_err());
+        assert!(queries::get_patient_by_id(&conn, &fixtures.patient_id).unwrap().is_none());
+    }
+
+    #[test]
+    fn token_scoped_only_to_the_patient_is_revoked() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        conn.execute(
+            "INSERT INTO api_tokens (token_id, token_hash, user_id, role, patient_ids, label, created_by, created_at)
+             VALUES ('t1', 'h1', ?1, 'clinician', ?2, 'cgm', ?1, datetime('now'))",
+            params![fixtures.clinician_id, fixtures.patient_id],
+        )
+        .unwrap();
+
+        assert_eq!(scrub_token_scopes(&conn, &fixtures.patient_id).unwrap(), 1);
+        let revoked: bool = conn.query_row("SELECT revoked FROM api_tokens WHERE token_id = 't1'", [], |row| row.get(0)).unwrap();
+        assert!(revoked);
+    }
+
+    #[test]
+    fn signature_detects_edited_records() {
+        let mut record = ErasureRecord {
+            erasure_id: "e1".to_string(),
+            subject_hash: subject_hash("patient-1"),
+            mode: "delete".to_string(),
+            performed_by: "admin-1".to_string(),
+            performed_at: "2026-01-01T00:00:00+00:00".to_string(),
+            row_counts: "{\"patients\":1}".to_string(),
+            signature: String::new(),
+        };
+        record.signature = sign_record(KEY, &record);
+        assert!(verify_record(KEY, &record));
+        assert!(!verify_record(b"another-key-another-key-another!", &record));
+
+        record.performed_by = "someone-else".to_string();
+        assert!(!verify_record(KEY, &record));
+    }
+}
@@ -0,0 +1,41 @@
+// Crate-wide error type for operations that can fail for more than database reasons
+// Menus print it as is and tests match on the variant, so a denied permission, an expired session
+// and a broken query are no longer all reported as rusqlite::Error::InvalidQuery
+use crate::access_control::Permission;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum GlucoError {
+    // credentials or account state, e.g. a suspended account
+    #[error("{0}")]
+    Auth(String),
+    #[error("Access denied: insufficient permissions ({} required).", .0.as_str())]
+    Permission(Permission),
+    // missing, expired or over-limit sessions
+    #[error("{0}")]
+    Session(String),
+    #[error("database error: {0}")]
+    Db(#[from] rusqlite::Error),
+    // input that was rejected before anything was stored
+    #[error("{0}")]
+    Validation(String),
+}
+
+impl GlucoError {
+    pub fn unknown_session() -> Self {
+        GlucoError::Session("No such session. Please log in again.".to_string())
+    }
+
+    pub fn expired_session() -> Self {
+        GlucoError::Session("Session has expired. Please log in again.".to_string())
+    }
+}
+
+impl From<argon2::password_hash::Error> for GlucoError {
+    fn from(e: argon2::password_hash::Error) -> Self {
+        match e {
+            argon2::password_hash::Error::Password => GlucoError::Validation("Password cannot be empty.".to_string()),
+            other => GlucoError::Auth(format!("Password could not be hashed: {}", other)),
+        }
+    }
+}
@@ -0,0 +1,280 @@
+// Escalation of unacknowledged critical alerts
+// A critical alert nobody acknowledges within alerts.escalation_minutes is escalated to the patient's
+// caretakers, and after as long again to their clinician (at once when the patient has no caretaker).
+// Each step notifies the users in their menu and is recorded in alert_history; acknowledging the alert
+// or resolving it stops the escalation.
+use crate::access_control::{self, Permission, Resource, Role};
+use crate::config;
+use crate::dose_safety;
+use crate::emergency;
+use crate::logger::SECURITY_TARGET;
+use rusqlite::{params, Connection, OptionalExtension};
+
+// alert types escalated when nobody acknowledges them
+pub const CRITICAL_ALERT_TYPES: [&str; 4] = ["LOW", "PREDICTED_LOW", dose_safety::LOW_GLUCOSE_ALERT, emergency::ALERT_TYPE];
+
+const CARETAKERS_NOTIFIED: u32 = 1;
+const CLINICIAN_NOTIFIED: u32 = 2;
+
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub alert_id: i64,
+    pub message: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub event: String,
+    // username, or the user id if the account is gone
+    pub user: Option<String>,
+    pub detail: String,
+    pub created_at: String,
+}
+
+struct OverdueAlert {
+    alert_id: i64,
+    patient_id: String,
+    alert_type: String,
+    alert_message: String,
+    escalation_level: u32,
+    patient_name: String,
+    clinician_id: String,
+}
+
+// CRITICAL_ALERT_TYPES as an SQL list, for `alert_type IN (...)`
+pub fn critical_types_sql() -> String {
+    CRITICAL_ALERT_TYPES.iter().map(|t| format!("'{}'", t)).collect::<Vec<_>>().join(", ")
+}
+
+// notify one user about an alert and record it in the alert's history
+fn notify(conn: &Connection, alert: &OverdueAlert, user_id: &str, detail: &str) -> rusqlite::Result<()> {
+    let message = format!("{} for {}: {}", alert.alert_type, alert.patient_name, alert.alert_message);
+    conn.execute(
+        "INSERT INTO alert_notifications (alert_id, user_id, message, created_at) VALUES (?1, ?2, ?3, datetime('now'))",
+        params![alert.alert_id, user_id, message],
+    )?;
+    conn.execute(
+        "INSERT INTO alert_history (alert_id, event, user_id, detail, created_at) VALUES (?1, 'escalated', ?2, ?3, datetime('now'))",
+        params![alert.alert_id, user_id, detail],
+    )?;
+    Ok(())
+}
+
+// escalate the next step of one alert, returns the level it reached
+fn escalate(conn: &Connection, alert: &OverdueAlert) -> rusqlite::Result<u32> {
+    let tx = conn.unchecked_transaction()?;
+    let level = if alert.escalation_level < CARETAKERS_NOTIFIED {
+        let caretakers = {
+            // caretakers only hear of patients who share their record with them
+            let mut stmt = tx.prepare(
+                "SELECT caretaker_id FROM patient_caretakers
+                 WHERE patient_id = ?1
+                   AND patient_id IN (SELECT patient_id FROM current_consents WHERE scope = 'caretakers' AND granted = 1)",
+            )?;
+            let rows = stmt.query_map([&alert.patient_id], |row| row.get::<_, String>(0))?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        for caretaker_id in &caretakers {
+            notify(&tx, alert, caretaker_id, "not acknowledged, escalated to caretaker")?;
+        }
+        if caretakers.is_empty() {
+            notify(&tx, alert, &alert.clinician_id, "not acknowledged and no caretaker linked, escalated to clinician")?;
+            CLINICIAN_NOTIFIED
+        } else {
+            CARETAKERS_NOTIFIED
+        }
+    } else {
+        notify(&tx, alert, &alert.clinician_id, "still not acknowledged, escalated to clinician")?;
+        CLINICIAN_NOTIFIED
+    };
+    tx.execute("UPDATE alerts SET escalation_level = ?2 WHERE alert_id = ?1", params![alert.alert_id, level])?;
+    tx.commit()?;
+    tracing::warn!(target: SECURITY_TARGET, alert_id = alert.alert_id, patient_id = %alert.patient_id, alert_type = %alert.alert_type, level, "Unacknowledged alert escalated");
+    Ok(level)
+}
+
+// escalate every critical alert that has waited too long at its level; run by the session cleanup task
+pub fn escalate_overdue(conn: &Connection) -> rusqlite::Result<usize> {
+    let minutes = config::get().alerts.escalation_minutes;
+    let overdue = {
+        let sql = format!(
+            "SELECT a.alert_id, a.patient_id, a.alert_type, a.alert_message, a.escalation_level,
+                    p.first_name || ' ' || p.last_name, p.clinician_id
+             FROM alerts a JOIN patients p ON p.patient_id = a.patient_id
+             WHERE a.is_resolved = 0 AND a.acknowledged_at IS NULL AND a.escalation_level < ?1
+               AND p.is_simulated = 0
+               AND a.alert_type IN ({})
+               AND (julianday('now') - julianday(a.alert_time)) * 1440 >= ?2 * (a.escalation_level + 1)
+             ORDER BY a.alert_id",
+            critical_types_sql()
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params![CLINICIAN_NOTIFIED, minutes], |row| {
+            Ok(OverdueAlert {
+                alert_id: row.get(0)?,
+                patient_id: row.get(1)?,
+                alert_type: row.get(2)?,
+                alert_message: row.get(3)?,
+                escalation_level: row.get(4)?,
+                patient_name: row.get(5)?,
+                clinician_id: row.get(6)?,
+            })
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+    for alert in &overdue {
+        escalate(conn, alert)?;
+    }
+    Ok(overdue.len())
+}
+
+/*
+Acknowledge an alert for a patient in the role's scope (ViewAlerts), which stops its escalation.
+Returns false when it was already acknowledged; the user's notices about it are marked read either way.
+*/
+pub fn acknowledge(conn: &Connection, role: &Role, alert_id: i64) -> Result<bool, String> {
+    let patient_id: Option<String> = conn
+        .query_row("SELECT patient_id FROM alerts WHERE alert_id = ?1", [alert_id], |row| row.get(0))
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let Some(patient_id) = patient_id else {
+        return Err("no such alert".to_string());
+    };
+    if !access_control::can_access(conn, role, &Resource::Patient(&patient_id), &Permission::ViewAlerts) {
+        return Err("Access denied: patient is not in your care or ViewAlerts is missing".to_string());
+    }
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let acknowledged = tx
+        .execute(
+            "UPDATE alerts SET acknowledged_at = datetime('now'), acknowledged_by = ?2 WHERE alert_id = ?1 AND acknowledged_at IS NULL",
+            params![alert_id, role.id],
+        )
+        .map_err(|e| e.to_string())?;
+    if acknowledged > 0 {
+        tx.execute(
+            "INSERT INTO alert_history (alert_id, event, user_id, detail, created_at)
+             VALUES (?1, 'acknowledged', ?2, ?3, datetime('now'))",
+            params![alert_id, role.id, format!("acknowledged as {}", role.name)],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.execute(
+        "UPDATE alert_notifications SET read_at = datetime('now') WHERE alert_id = ?1 AND user_id = ?2 AND read_at IS NULL",
+        params![alert_id, role.id],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    if acknowledged > 0 {
+        tracing::info!(target: SECURITY_TARGET, user_id = %role.id, alert_id, patient_id = %patient_id, "Alert acknowledged");
+    }
+    Ok(acknowledged > 0)
+}
+
+// escalation notices the user has not dealt with yet, oldest first
+pub fn unread_notifications(conn: &Connection, user_id: &str) -> rusqlite::Result<Vec<Notification>> {
+    let mut stmt = conn.prepare(
+        "SELECT alert_id, message, created_at FROM alert_notifications
+         WHERE user_id = ?1 AND read_at IS NULL
+         ORDER BY notification_id",
+    )?;
+    let rows = stmt.query_map([user_id], |row| {
+        Ok(Notification {
+            alert_id: row.get(0)?,
+            message: row.get(1)?,
+            created_at: row.get(2)?,
+        })
+    })?;
+    rows.collect()
+}
+
+// escalations and acknowledgements of one alert, oldest first
+pub fn alert_history(conn: &Connection, alert_id: i64) -> rusqlite::Result<Vec<HistoryEntry>> {
+    let mut stmt = conn.prepare(
+        "SELECT h.event, COALESCE(u.user_name, h.user_id), h.detail, h.created_at
+         FROM alert_history h LEFT JOIN users u ON u.id = h.user_id
+         WHERE h.alert_id = ?1
+         ORDER BY h.history_id",
+    )?;
+    let rows = stmt.query_map([alert_id], |row| {
+        Ok(HistoryEntry {
+            event: row.get(0)?,
+            user: row.get(1)?,
+            detail: row.get(2)?,
+            created_at: row.get(3)?,
+        })
+    })?;
+    rows.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerts;
+    use crate::test_utils;
+
+    fn age_alert(conn: &Connection, alert_id: i64, minutes: u32) {
+        conn.execute(
+            "UPDATE alerts SET alert_time = datetime('now', ?2) WHERE alert_id = ?1",
+            params![alert_id, format!("-{} minutes", minutes)],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn unacknowledged_alerts_go_to_caretakers_then_the_clinician() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let alert_id = alerts::raise_alert(&conn, &fixtures.patient_id, "PREDICTED_LOW", "low in 20 minutes").unwrap().unwrap();
+        let routine = alerts::raise_alert(&conn, &fixtures.patient_id, "PREDICTED_HIGH", "high in 20 minutes").unwrap().unwrap();
+        age_alert(&conn, routine, 120);
+
+        assert_eq!(escalate_overdue(&conn).unwrap(), 0);
+        age_alert(&conn, alert_id, 16);
+        assert_eq!(escalate_overdue(&conn).unwrap(), 1);
+        assert_eq!(unread_notifications(&conn, &fixtures.caretaker_id).unwrap().len(), 1);
+        assert!(unread_notifications(&conn, &fixtures.clinician_id).unwrap().is_empty());
+        assert_eq!(escalate_overdue(&conn).unwrap(), 0);
+
+        age_alert(&conn, alert_id, 31);
+        assert_eq!(escalate_overdue(&conn).unwrap(), 1);
+        let notices = unread_notifications(&conn, &fixtures.clinician_id).unwrap();
+        assert_eq!(notices.len(), 1);
+        assert_eq!(notices[0].alert_id, alert_id);
+        assert_eq!(alert_history(&conn, alert_id).unwrap().len(), 2);
+
+        // a clinician outside the care team cannot acknowledge it, the patient's clinician can
+        let other = Role::new(&conn, "clinician", &fixtures.other_clinician_id);
+        assert!(acknowledge(&conn, &other, alert_id).is_err());
+        let clinician = Role::new(&conn, "clinician", &fixtures.clinician_id);
+        assert!(acknowledge(&conn, &clinician, alert_id).unwrap());
+        assert!(!acknowledge(&conn, &clinician, alert_id).unwrap());
+        assert!(unread_notifications(&conn, &fixtures.clinician_id).unwrap().is_empty());
+        let history = alert_history(&conn, alert_id).unwrap();
+        assert_eq!(history.last().unwrap().event, "acknowledged");
+    }
+
+    #[test]
+    fn without_caretakers_the_clinician_is_notified_at_once() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let alert_id = alerts::raise_alert(&conn, &fixtures.other_patient_id, emergency::ALERT_TYPE, "stopped").unwrap().unwrap();
+        age_alert(&conn, alert_id, 16);
+        assert_eq!(escalate_overdue(&conn).unwrap(), 1);
+        let level: u32 = conn.query_row("SELECT escalation_level FROM alerts WHERE alert_id = ?1", [alert_id], |row| row.get(0)).unwrap();
+        assert_eq!(level, CLINICIAN_NOTIFIED);
+    }
+
+    #[test]
+    fn alerts_of_simulated_patients_are_not_escalated() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        conn.execute("UPDATE patients SET is_simulated = 1 WHERE patient_id = ?1", [&fixtures.patient_id]).unwrap();
+        let alert_id = alerts::raise_alert(&conn, &fixtures.patient_id, "LOW", "training low").unwrap().unwrap();
+        age_alert(&conn, alert_id, 120);
+        assert_eq!(escalate_overdue(&conn).unwrap(), 0);
+        assert!(unread_notifications(&conn, &fixtures.caretaker_id).unwrap().is_empty());
+        assert!(alert_history(&conn, alert_id).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,589 @@
+// Export of patient glucose and insulin history (CSV / JSON / FHIR) for external EHR teams,
+// and de-identified datasets of the same history for research
+use crate::access_control::{self, Permission, Resource, Role};
+use crate::analytics::{self, GmiEstimate};
+use crate::config;
+use crate::consent::{self, ConsentScope};
+use crate::db::models::Patient;
+use crate::fhir;
+use crate::insulin::{GlucoseReading, InsulinLog};
+use crate::logger::SECURITY_TARGET;
+use crate::timestamps;
+use chrono::{Days, NaiveDate, Utc};
+use chrono_tz::Tz;
+use hmac::{Hmac, Mac};
+use rusqlite::{params, Connection};
+use sha2::Sha256;
+use serde::Serialize;
+use std::error::Error;
+use std::fs::{create_dir_all, File};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    // FHIR R4 Bundle of Observation / MedicationAdministration resources
+    Fhir,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+            ExportFormat::Fhir => "fhir.json",
+        }
+    }
+}
+
+// inclusive date range, None = unbounded on that side
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DateRange {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+impl DateRange {
+    // [start, end) of the range as stored UTC text, with the days taken in the given zone
+    pub fn bounds(&self, zone: Tz) -> (String, String) {
+        let start = self.from.map_or_else(|| "0000-01-01 00:00:00".to_string(), |from| timestamps::start_of_day(from, zone));
+        let end = self
+            .to
+            .and_then(|to| to.checked_add_days(Days::new(1)))
+            .map_or_else(|| "9999-12-31 23:59:59".to_string(), |next| timestamps::start_of_day(next, zone));
+        (start, end)
+    }
+}
+
+#[derive(Serialize)]
+struct HistoryExport<'a> {
+    patient_id: &'a str,
+    exported_at: String,
+    from: Option<String>,
+    to: Option<String>,
+    glucose_readings: &'a [GlucoseReading],
+    insulin_logs: &'a [InsulinLog],
+    // over 14, 30 and 90 days up to the end of the range
+    glucose_management_indicator: &'a [GmiEstimate],
+}
+
+// one CSV row per reading, dose or GMI window, record_type tells them apart
+#[derive(Serialize)]
+struct CsvRecord<'a> {
+    record_type: &'a str,
+    record_id: Option<i64>,
+    patient_id: &'a str,
+    time: &'a str,
+    glucose_level: Option<f64>,
+    status: Option<&'a str>,
+    action_type: Option<&'a str>,
+    dosage_units: Option<f64>,
+    requested_by: Option<&'a str>,
+    window_days: Option<u32>,
+    gmi_percent: Option<f64>,
+}
+
+pub struct ExportSummary {
+    pub path: PathBuf,
+    pub glucose_count: usize,
+    pub insulin_count: usize,
+}
+
+// readings for a patient within the range of days in the zone, oldest first
+pub fn glucose_readings_in_range(conn: &Connection, patient_id: &str, range: &DateRange, zone: Tz) -> rusqlite::Result<Vec<GlucoseReading>> {
+    let (start, end) = range.bounds(zone);
+    let mut stmt = conn.prepare(
+        "SELECT reading_id, patient_id, glucose_level, reading_time, status
+         FROM glucose_readings
+         WHERE patient_id = ?1 AND reading_time >= ?2 AND reading_time < ?3
+         ORDER BY reading_time ASC",
+    )?;
+    let rows = stmt.query_map(
+        params![patient_id, start, end],
+        |row| {
+            Ok(GlucoseReading {
+                reading_id: row.get(0)?,
+                patient_id: row.get(1)?,
+                glucose_level: row.get(2)?,
+                reading_time: row.get(3)?,
+                status: row.get(4)?,
+            })
+        },
+    )?;
+    rows.collect()
+}
+
+// insulin doses for a patient within the range of days in the zone, oldest first
+pub fn insulin_logs_in_range(conn: &Connection, patient_id: &str, range: &DateRange, zone: Tz) -> rusqlite::Result<Vec<InsulinLog>> {
+    let (start, end) = range.bounds(zone);
+    let mut stmt = conn.prepare(
+        "SELECT dosage_id, patient_id, action_type, dosage_units, requested_by, dosage_time
+         FROM insulin_doses
+         WHERE patient_id = ?1 AND dosage_time >= ?2 AND dosage_time < ?3
+         ORDER BY dosage_time ASC",
+    )?;
+    let rows = stmt.query_map(
+        params![patient_id, start, end],
+        |row| {
+            Ok(InsulinLog {
+                dosage_id: row.get(0)?,
+                patient_id: row.get(1)?,
+                action_type: row.get(2)?,
+                dosage_units: row.get(3)?,
+                requested_by: row.get(4)?,
+                dosage_time: row.get(5)?,
+            })
+        },
+    )?;
+    rows.collect()
+}
+
+// GMI rows carry the end of their window as time and the mean glucose as glucose_level
+fn write_csv(
+    path: &Path,
+    patient_id: &str,
+    readings: &[GlucoseReading],
+    logs: &[InsulinLog],
+    gmi: &[GmiEstimate],
+    gmi_end: &str,
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_path(path)?;
+    for reading in readings {
+        writer.serialize(CsvRecord {
+            record_type: "glucose",
+            record_id: Some(reading.reading_id),
+            patient_id: &reading.patient_id,
+            time: &reading.reading_time,
+            glucose_level: Some(reading.glucose_level),
+            status: Some(&reading.status),
+            action_type: None,
+            dosage_units: None,
+            requested_by: None,
+            window_days: None,
+            gmi_percent: None,
+        })?;
+    }
+    for log in logs {
+        writer.serialize(CsvRecord {
+            record_type: "insulin",
+            record_id: Some(log.dosage_id),
+            patient_id: &log.patient_id,
+            time: &log.dosage_time,
+            glucose_level: None,
+            status: None,
+            action_type: Some(&log.action_type),
+            dosage_units: Some(log.dosage_units),
+            requested_by: Some(&log.requested_by),
+            window_days: None,
+            gmi_percent: None,
+        })?;
+    }
+    for estimate in gmi {
+        writer.serialize(CsvRecord {
+            record_type: "gmi",
+            record_id: None,
+            patient_id,
+            time: gmi_end,
+            glucose_level: Some(estimate.mean_glucose),
+            status: None,
+            action_type: None,
+            dosage_units: None,
+            requested_by: None,
+            window_days: Some(estimate.window_days),
+            gmi_percent: Some(estimate.gmi_percent),
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// export one patient's history into export.dir, only for patients in the role's scope;
+// the dates and the exported times are in the exporting user's time zone
+pub fn export_patient_history(
+    conn: &Connection,
+    role: &Role,
+    patient_id: &str,
+    range: &DateRange,
+    format: ExportFormat,
+) -> Result<ExportSummary, Box<dyn Error>> {
+    if !access_control::can_access(conn, role, &Resource::Patient(patient_id), &Permission::ViewGlucose) {
+        return Err("Access denied: patient is not in your care or ViewGlucose is missing".into());
+    }
+    if let (Some(from), Some(to)) = (range.from, range.to) {
+        if from > to {
+            return Err("Start date must not be after end date".into());
+        }
+    }
+
+    let zone = timestamps::display_zone(conn, &role.id);
+    let mut readings = glucose_readings_in_range(conn, patient_id, range, zone)?;
+    let mut logs = insulin_logs_in_range(conn, patient_id, range, zone)?;
+    // the GMI windows end with the range, or now when it is open-ended
+    let gmi_end = if range.to.is_some() { range.bounds(zone).1 } else { timestamps::now() };
+    let gmi = analytics::gmi_estimates(conn, patient_id, &gmi_end)?;
+    let gmi_end = timestamps::to_rfc3339(&gmi_end, zone);
+    for reading in &mut readings {
+        reading.reading_time = timestamps::to_rfc3339(&reading.reading_time, zone);
+    }
+    for log in &mut logs {
+        log.dosage_time = timestamps::to_rfc3339(&log.dosage_time, zone);
+    }
+
+    let export_dir = &config::get().export.dir;
+    create_dir_all(export_dir)?;
+    let file_name = format!(
+        "{}_{}.{}",
+        patient_id,
+        Utc::now().format("%Y%m%d_%H%M%S"),
+        format.extension()
+    );
+    let path = Path::new(export_dir).join(file_name);
+
+    match format {
+        ExportFormat::Csv => write_csv(&path, patient_id, &readings, &logs, &gmi, &gmi_end)?,
+        ExportFormat::Json => {
+            let export = HistoryExport {
+                patient_id,
+                exported_at: Utc::now().with_timezone(&zone).to_rfc3339(),
+                from: range.from.map(|d| d.to_string()),
+                to: range.to.map(|d| d.to_string()),
+                glucose_readings: &readings,
+                insulin_logs: &logs,
+                glucose_management_indicator: &gmi,
+            };
+            serde_json::to_writer_pretty(File::create(&path)?, &export)?;
+        }
+        ExportFormat::Fhir => serde_json::to_writer_pretty(File::create(&path)?, &fhir::bundle(&readings, &logs))?,
+    }
+
+    tracing::info!(
+        target: SECURITY_TARGET,
+        user_id = %role.id,
+        patient_id = %patient_id,
+        format = format.extension(),
+        path = %path.display(),
+        "Patient history exported"
+    );
+
+    Ok(ExportSummary {
+        path,
+        glucose_count: readings.len(),
+        insulin_count: logs.len(),
+    })
+}
+
+// research datasets: no names, ids or record ids; patients become keyed pseudonyms and DOB an age bracket
+#[derive(Serialize)]
+struct ResearchExport {
+    exported_at: String,
+    from: Option<String>,
+    to: Option<String>,
+    subjects: Vec<ResearchSubject>,
+}
+
+#[derive(Serialize)]
+struct ResearchSubject {
+    subject: String,
+    age_bucket: String,
+    glucose_readings: Vec<ResearchReading>,
+    insulin_doses: Vec<ResearchDose>,
+}
+
+#[derive(Serialize)]
+struct ResearchReading {
+    time: String,
+    glucose_level: f64,
+    status: String,
+}
+
+#[derive(Serialize)]
+struct ResearchDose {
+    time: String,
+    action_type: String,
+    dosage_units: f64,
+    source: &'static str,
+}
+
+#[derive(Serialize)]
+struct ResearchCsvRecord<'a> {
+    subject: &'a str,
+    age_bucket: &'a str,
+    record_type: &'a str,
+    time: &'a str,
+    glucose_level: Option<f64>,
+    status: Option<&'a str>,
+    action_type: Option<&'a str>,
+    dosage_units: Option<f64>,
+    source: Option<&'a str>,
+}
+
+pub struct ResearchSummary {
+    pub path: PathBuf,
+    pub subject_count: usize,
+    // patients in scope left out because they have not agreed to research use
+    pub without_consent: usize,
+    pub glucose_count: usize,
+    pub insulin_count: usize,
+}
+
+// stable pseudonym for a patient id: the same key always gives the same subject, without the key it cannot be reversed
+pub fn pseudonym(key: &[u8], patient_id: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(patient_id.as_bytes());
+    format!("subj-{}", hex::encode(&mac.finalize().into_bytes()[..8]))
+}
+
+// ten-year age brackets, with everyone 90 or older in one bracket as HIPAA Safe Harbor requires
+pub fn age_bucket(date_of_birth: &str, today: NaiveDate) -> String {
+    let dob = NaiveDate::parse_from_str(date_of_birth, "%m-%d-%Y")
+        .or_else(|_| NaiveDate::parse_from_str(date_of_birth, "%Y-%m-%d"));
+    let Ok(dob) = dob else {
+        return "unknown".to_string();
+    };
+    let Some(age) = today.years_since(dob) else {
+        return "unknown".to_string();
+    };
+    match age {
+        90.. => "90+".to_string(),
+        age => format!("{}-{}", age / 10 * 10, age / 10 * 10 + 9),
+    }
+}
+
+// who triggered a dose, without the user or device id (pump ids embed part of the patient id)
+fn dose_source(log: &InsulinLog) -> &'static str {
+    if log.requested_by.starts_with("pump:") {
+        "pump"
+    } else if log.requested_by == log.patient_id {
+        "patient"
+    } else {
+        "care_team"
+    }
+}
+
+fn research_subject(
+    conn: &Connection,
+    key: &[u8],
+    patient: &Patient,
+    range: &DateRange,
+    zone: Tz,
+    today: NaiveDate,
+) -> rusqlite::Result<ResearchSubject> {
+    let glucose_readings = glucose_readings_in_range(conn, &patient.patient_id, range, zone)?
+        .into_iter()
+        .map(|reading| ResearchReading {
+            time: timestamps::to_rfc3339(&reading.reading_time, zone),
+            glucose_level: reading.glucose_level,
+            status: reading.status,
+        })
+        .collect();
+    let insulin_doses = insulin_logs_in_range(conn, &patient.patient_id, range, zone)?
+        .into_iter()
+        .map(|log| ResearchDose {
+            source: dose_source(&log),
+            time: timestamps::to_rfc3339(&log.dosage_time, zone),
+            action_type: log.action_type,
+            dosage_units: log.dosage_units,
+        })
+        .collect();
+    Ok(ResearchSubject {
+        subject: pseudonym(key, &patient.patient_id),
+        age_bucket: age_bucket(&patient.date_of_birth, today),
+        glucose_readings,
+        insulin_doses,
+    })
+}
+
+fn write_research_csv(path: &Path, subjects: &[ResearchSubject]) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_path(path)?;
+    for subject in subjects {
+        for reading in &subject.glucose_readings {
+            writer.serialize(ResearchCsvRecord {
+                subject: &subject.subject,
+                age_bucket: &subject.age_bucket,
+                record_type: "glucose",
+                time: &reading.time,
+                glucose_level: Some(reading.glucose_level),
+                status: Some(&reading.status),
+                action_type: None,
+                dosage_units: None,
+                source: None,
+            })?;
+        }
+        for dose in &subject.insulin_doses {
+            writer.serialize(ResearchCsvRecord {
+                subject: &subject.subject,
+                age_bucket: &subject.age_bucket,
+                record_type: "insulin",
+                time: &dose.time,
+                glucose_level: None,
+                status: None,
+                action_type: Some(&dose.action_type),
+                dosage_units: Some(dose.dosage_units),
+                source: Some(dose.source),
+            })?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// export the history of every patient in the role's scope as one de-identified dataset (CSV or JSON)
+pub fn export_research_dataset(
+    conn: &Connection,
+    role: &Role,
+    range: &DateRange,
+    format: ExportFormat,
+) -> Result<ResearchSummary, Box<dyn Error>> {
+    let key = &config::get().export.research_key;
+    if key.is_empty() {
+        return Err("Research export is disabled: set export.research_key (GLUCOGUARD_RESEARCH_KEY)".into());
+    }
+    if format == ExportFormat::Fhir {
+        return Err("Research datasets are available as CSV or JSON only".into());
+    }
+    if let (Some(from), Some(to)) = (range.from, range.to) {
+        if from > to {
+            return Err("Start date must not be after end date".into());
+        }
+    }
+
+    let mut patients = access_control::accessible_patients(conn, role, &Permission::ViewGlucose)?;
+    // training patients are not real subjects
+    patients.retain(|patient| !patient.is_simulated);
+    if patients.is_empty() {
+        return Err("No patients in your care to export".into());
+    }
+    let (patients, without_consent) = consent::consenting(conn, patients, ConsentScope::Research)?;
+    if patients.is_empty() {
+        return Err("None of the patients in your care has agreed to research use of their data".into());
+    }
+    let zone = timestamps::display_zone(conn, &role.id);
+    let today = Utc::now().with_timezone(&zone).date_naive();
+    let subjects = patients
+        .iter()
+        .map(|patient| research_subject(conn, key.as_bytes(), patient, range, zone, today))
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let export_dir = &config::get().export.dir;
+    create_dir_all(export_dir)?;
+    let path = Path::new(export_dir).join(format!(
+        "research_{}.{}",
+        Utc::now().format("%Y%m%d_%H%M%S"),
+        format.extension()
+    ));
+    let glucose_count = subjects.iter().map(|s| s.glucose_readings.len()).sum();
+    let insulin_count = subjects.iter().map(|s| s.insulin_doses.len()).sum();
+    let subject_count = subjects.len();
+
+    if format == ExportFormat::Csv {
+        write_research_csv(&path, &subjects)?;
+    } else {
+        let export = ResearchExport {
+            exported_at: Utc::now().with_timezone(&zone).to_rfc3339(),
+            from: range.from.map(|d| d.to_string()),
+            to: range.to.map(|d| d.to_string()),
+            subjects,
+        };
+        serde_json::to_writer_pretty(File::create(&path)?, &export)?;
+    }
+
+    // the audit record names the exporter but no patients
+    tracing::info!(
+        target: SECURITY_TARGET,
+        user_id = %role.id,
+        subjects = subject_count,
+        without_consent,
+        path = %path.display(),
+        "De-identified research dataset exported"
+    );
+
+    Ok(ResearchSummary {
+        path,
+        subject_count,
+        without_consent,
+        glucose_count,
+        insulin_count,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::queries;
+    use crate::test_utils;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn pseudonyms_are_stable_per_key() {
+        let key = b"0123456789abcdef0123456789abcdef";
+        let first = pseudonym(key, "patient-1");
+        assert_eq!(first, pseudonym(key, "patient-1"));
+        assert_ne!(first, pseudonym(key, "patient-2"));
+        assert_ne!(first, pseudonym(b"another-key-another-key-another!", "patient-1"));
+        assert!(first.starts_with("subj-") && !first.contains("patient"));
+    }
+
+    #[test]
+    fn ages_are_bucketed() {
+        let today = date(2026, 6, 1);
+        assert_eq!(age_bucket("06-02-1996", today), "20-29");
+        assert_eq!(age_bucket("06-01-1996", today), "30-39");
+        assert_eq!(age_bucket("2019-01-15", today), "0-9");
+        assert_eq!(age_bucket("01-01-1930", today), "90+");
+        assert_eq!(age_bucket("not a date", today), "unknown");
+    }
+
+    #[test]
+    fn date_ranges_are_days_in_the_users_zone() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        for time in ["2024-06-30 21:30:00", "2024-06-30 22:30:00", "2024-07-01 22:30:00"] {
+            conn.execute(
+                "INSERT INTO glucose_readings (patient_id, glucose_level, reading_time, status) VALUES (?1, 95, ?2, 'normal')",
+                params![fixtures.patient_id, time],
+            )
+            .unwrap();
+        }
+        let july_first = DateRange { from: Some(date(2024, 7, 1)), to: Some(date(2024, 7, 1)) };
+        let times = |zone: Tz| -> Vec<String> {
+            glucose_readings_in_range(&conn, &fixtures.patient_id, &july_first, zone)
+                .unwrap()
+                .into_iter()
+                .map(|reading| reading.reading_time)
+                .collect()
+        };
+        // 1 July in Madrid is 30 June 22:00 to 1 July 22:00 UTC
+        assert_eq!(times(timestamps::parse_zone("Europe/Madrid").unwrap()), vec!["2024-06-30 22:30:00"]);
+        assert_eq!(times(Tz::UTC), vec!["2024-07-01 22:30:00"]);
+    }
+
+    #[test]
+    fn research_subjects_carry_no_identifiers() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        conn.execute(
+            "INSERT INTO glucose_readings (patient_id, glucose_level, reading_time, status) VALUES (?1, 95, datetime('now'), 'normal')",
+            params![fixtures.patient_id],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO insulin_logs (patient_id, action_type, dosage_units, requested_by, dosage_time) VALUES (?1, 'bolus', 2.5, ?2, datetime('now'))",
+            params![fixtures.patient_id, fixtures.clinician_id],
+        )
+        .unwrap();
+        let patient = queries::get_patient_by_id(&conn, &fixtures.patient_id).unwrap().unwrap();
+
+        let subject = research_subject(&conn, b"0123456789abcdef0123456789abcdef", &patient, &DateRange::default(), Tz::UTC, date(2026, 6, 1)).unwrap();
+        assert_eq!((subject.glucose_readings.len(), subject.insulin_doses.len()), (1, 1));
+        assert_eq!(subject.age_bucket, "30-39");
+        assert_eq!(subject.insulin_doses[0].source, "care_team");
+        let json = serde_json::to_string(&subject).unwrap();
+        for identifier in [fixtures.patient_id.as_str(), fixtures.clinician_id.as_str(), "Ada", "Fixture", "1990"] {
+            assert!(!json.contains(identifier), "{} leaked into {}", identifier, json);
+        }
+    }
+}
@@ -0,0 +1,183 @@
+// Extended and dual-wave boluses
+// A bolus can be split into an immediate portion and an extended portion the pump spreads evenly over a
+// chosen duration (a plain extended bolus has no immediate portion). Both portions go to the pump as one
+// request and are logged when it is queued: the immediate portion now and the extended portion as one
+// scheduled insulin_logs entry per dosing.extended_bolus_step_minutes, all sharing the request id.
+// Steps that will not be delivered are voided in the dose log, never deleted.
+use crate::config;
+use crate::dose_log::{self, DoseEntry};
+use crate::pump;
+use crate::timestamps;
+use chrono::{Duration, Utc};
+use rusqlite::Connection;
+use uuid::Uuid;
+
+pub const MIN_MINUTES: u32 = 30;
+
+#[derive(Debug, Clone, Copy)]
+pub struct BolusPlan {
+    pub immediate_units: f64,
+    pub extended_units: f64,
+    // how long the pump takes to deliver the extended portion
+    pub duration_minutes: u32,
+}
+
+impl BolusPlan {
+    pub fn total_units(&self) -> f64 {
+        self.immediate_units + self.extended_units
+    }
+
+    // (minutes from now, units) of each log entry: the immediate portion, then the end of each step
+    pub fn schedule(&self, step_minutes: u32) -> Vec<(u32, f64)> {
+        let mut entries = Vec::new();
+        if self.immediate_units > 0.0 {
+            entries.push((0, self.immediate_units));
+        }
+        let mut elapsed = 0;
+        while elapsed < self.duration_minutes {
+            let step = step_minutes.min(self.duration_minutes - elapsed);
+            elapsed += step;
+            entries.push((elapsed, self.extended_units * f64::from(step) / f64::from(self.duration_minutes)));
+        }
+        entries
+    }
+}
+
+/*
+Queue an extended or dual-wave bolus for the patient's pump and log its schedule, returns the request id.
+The whole request passes the dose safety checks as one bolus; nothing is queued or logged when it is refused.
+*/
+pub fn request_bolus(conn: &Connection, patient_id: &str, plan: &BolusPlan, requested_by: &str) -> Result<String, String> {
+    let dosing = &config::get().dosing;
+    if plan.immediate_units < 0.0 || plan.extended_units <= 0.0 {
+        return Err("the extended portion must be more than 0 units and the immediate portion not negative".to_string());
+    }
+    if !(MIN_MINUTES..=dosing.extended_bolus_max_minutes).contains(&plan.duration_minutes) {
+        return Err(format!("duration must be between {} and {} minutes", MIN_MINUTES, dosing.extended_bolus_max_minutes));
+    }
+
+    let request_id = Uuid::new_v4().to_string();
+    let schedule = plan.schedule(dosing.extended_bolus_step_minutes);
+    let mut commands = Vec::new();
+    if plan.immediate_units > 0.0 {
+        commands.push(("bolus", plan.immediate_units, 0));
+    }
+    commands.push(("extended_bolus", plan.extended_units, plan.duration_minutes));
+    let command_ids = pump::queue_bolus_request(conn, patient_id, &request_id, &commands, requested_by, |tx| {
+        let queued_at = Utc::now();
+        for (offset_minutes, units) in &schedule {
+            dose_log::append(
+                tx,
+                &DoseEntry {
+                    patient_id,
+                    action_type: if *offset_minutes == 0 { "bolus" } else { "extended_bolus" },
+                    dosage_units: *units,
+                    requested_by,
+                    dosage_time: Some(timestamps::to_storage(queued_at + Duration::minutes(i64::from(*offset_minutes)))),
+                    request_id: Some(&request_id),
+                },
+            )?;
+        }
+        Ok(())
+    })?;
+    tracing::info!(patient_id = %patient_id, request_id = %request_id, immediate_units = plan.immediate_units, extended_units = plan.extended_units, duration_minutes = plan.duration_minutes, ?command_ids, entries = schedule.len(), requested_by = %requested_by, "Extended bolus queued");
+    Ok(request_id)
+}
+
+// void the entries of the given (dosage_id, patient_id) rows
+fn void_rows(conn: &Connection, sql: &str, key: &str, voided_by: &str) -> rusqlite::Result<usize> {
+    let rows = {
+        let mut stmt = conn.prepare(sql)?;
+        let rows = stmt.query_map([key], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+    let mut voided = 0;
+    for (dosage_id, patient_id) in rows {
+        voided += dose_log::void_doses(conn, &patient_id, &[dosage_id], voided_by)?;
+    }
+    Ok(voided)
+}
+
+// void the log entries of requests the pump will not get, called before their pending commands are cancelled
+pub fn drop_unsent(conn: &Connection, device_id: &str, voided_by: &str) -> rusqlite::Result<usize> {
+    void_rows(
+        conn,
+        "SELECT dosage_id, patient_id FROM insulin_doses WHERE request_id IN (
+             SELECT request_id FROM pump_commands WHERE device_id = ?1 AND status = 'pending' AND request_id IS NOT NULL)",
+        device_id,
+        voided_by,
+    )
+}
+
+// void everything of the patient's requests that has not been delivered yet: requests still waiting for the
+// pump and the future steps of running ones, called by the emergency stop before pending commands are cancelled
+pub fn drop_scheduled(conn: &Connection, patient_id: &str, voided_by: &str) -> rusqlite::Result<usize> {
+    void_rows(
+        conn,
+        "SELECT dosage_id, patient_id FROM insulin_doses
+         WHERE patient_id = ?1 AND request_id IS NOT NULL
+           AND (julianday(dosage_time) > julianday('now')
+                OR request_id IN (SELECT request_id FROM pump_commands WHERE patient_id = ?1 AND status = 'pending'))",
+        patient_id,
+        voided_by,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use rusqlite::params;
+
+    #[test]
+    fn extended_portion_is_split_into_steps() {
+        let plan = BolusPlan { immediate_units: 2.0, extended_units: 3.0, duration_minutes: 40 };
+        let schedule = plan.schedule(15);
+        assert_eq!(schedule.iter().map(|(offset, _)| *offset).collect::<Vec<_>>(), vec![0, 15, 30, 40]);
+        assert_eq!(schedule[0].1, 2.0);
+        assert!((schedule[1].1 - 1.125).abs() < 1e-9);
+        assert!((schedule[3].1 - 0.75).abs() < 1e-9);
+        let total: f64 = schedule.iter().map(|(_, units)| units).sum();
+        assert!((total - plan.total_units()).abs() < 1e-9);
+
+        let extended_only = BolusPlan { immediate_units: 0.0, extended_units: 4.0, duration_minutes: 60 };
+        assert_eq!(extended_only.schedule(30), vec![(30, 2.0), (60, 2.0)]);
+    }
+
+    #[test]
+    fn undelivered_steps_are_dropped_by_an_emergency_stop() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let patient_id = &fixtures.patient_id;
+        let plan = BolusPlan { immediate_units: 1.0, extended_units: 2.0, duration_minutes: 60 };
+        // no signing key in tests: the request is refused as a whole
+        assert!(request_bolus(&conn, patient_id, &plan, patient_id).is_err());
+        let logged: i64 = conn.query_row("SELECT COUNT(*) FROM insulin_logs", [], |row| row.get(0)).unwrap();
+        assert_eq!(logged, 0);
+
+        // "a" reached the pump an hour ago and is half way, "b" is still waiting for it
+        for (request_id, status) in [("a", "delivered"), ("b", "pending")] {
+            conn.execute(
+                "INSERT INTO pump_commands (patient_id, device_id, command_type, dosage_units, status, created_by, created_at, request_id)
+                 VALUES (?1, 'pump-1', 'extended_bolus', 2.0, ?2, ?1, datetime('now'), ?3)",
+                params![patient_id, status, request_id],
+            )
+            .unwrap();
+            for offset in ["-30 minutes", "+30 minutes"] {
+                conn.execute(
+                    "INSERT INTO insulin_logs (patient_id, action_type, dosage_units, requested_by, dosage_time, request_id)
+                     VALUES (?1, 'extended_bolus', 1.0, ?1, datetime('now', ?2), ?3)",
+                    params![patient_id, offset, request_id],
+                )
+                .unwrap();
+            }
+        }
+        assert_eq!(drop_unsent(&conn, "pump-2", patient_id).unwrap(), 0);
+        assert_eq!(drop_scheduled(&conn, patient_id, patient_id).unwrap(), 3);
+        assert_eq!(drop_scheduled(&conn, patient_id, patient_id).unwrap(), 0);
+        let kept: String = conn.query_row("SELECT request_id FROM insulin_doses", [], |row| row.get(0)).unwrap();
+        assert_eq!(kept, "a");
+        let logged: i64 = conn.query_row("SELECT COUNT(*) FROM insulin_logs", [], |row| row.get(0)).unwrap();
+        assert_eq!(logged, 7);
+    }
+}
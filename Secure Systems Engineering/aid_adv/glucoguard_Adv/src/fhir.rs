@@ -0,0 +1,104 @@
+// FHIR R4 interop: glucose readings as Observation, insulin doses as MedicationAdministration
+// Both are wrapped in a "collection" Bundle for exchange with hospital EHR systems
+use crate::analytics::{TARGET_HIGH, TARGET_LOW};
+use crate::insulin::{GlucoseReading, InsulinLog};
+use chrono::{NaiveDateTime, Utc};
+use serde_json::{json, Value};
+
+// LOINC 99504-3: Glucose [Mass/volume] in Interstitial fluid (CGM)
+const LOINC_CGM_GLUCOSE: &str = "99504-3";
+const LOINC_SYSTEM: &str = "http://loinc.org";
+const UCUM_SYSTEM: &str = "http://unitsofmeasure.org";
+const INTERPRETATION_SYSTEM: &str = "http://terminology.hl7.org/CodeSystem/v3-ObservationInterpretation";
+const OBSERVATION_CATEGORY_SYSTEM: &str = "http://terminology.hl7.org/CodeSystem/observation-category";
+
+pub const CONTENT_TYPE: &str = "application/fhir+json";
+
+// database timestamps are "YYYY-MM-DD HH:MM:SS" in UTC, FHIR wants an xs:dateTime with zone
+fn fhir_datetime(value: &str) -> String {
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+        .map(|time| time.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+        .unwrap_or_else(|_| value.to_string())
+}
+
+fn patient_reference(patient_id: &str) -> Value {
+    json!({ "reference": format!("Patient/{}", patient_id) })
+}
+
+fn interpretation(glucose_level: f64) -> Value {
+    let (code, display) = if glucose_level < TARGET_LOW {
+        ("L", "Low")
+    } else if glucose_level > TARGET_HIGH {
+        ("H", "High")
+    } else {
+        ("N", "Normal")
+    };
+    json!([{ "coding": [{ "system": INTERPRETATION_SYSTEM, "code": code, "display": display }] }])
+}
+
+pub fn observation(reading: &GlucoseReading) -> Value {
+    json!({
+        "resourceType": "Observation",
+        "id": format!("glucose-{}", reading.reading_id),
+        "status": "final",
+        "category": [{
+            "coding": [{ "system": OBSERVATION_CATEGORY_SYSTEM, "code": "laboratory", "display": "Laboratory" }]
+        }],
+        "code": {
+            "coding": [{
+                "system": LOINC_SYSTEM,
+                "code": LOINC_CGM_GLUCOSE,
+                "display": "Glucose [Mass/volume] in Interstitial fluid"
+            }],
+            "text": "CGM glucose"
+        },
+        "subject": patient_reference(&reading.patient_id),
+        "effectiveDateTime": fhir_datetime(&reading.reading_time),
+        "valueQuantity": {
+            "value": reading.glucose_level,
+            "unit": "mg/dL",
+            "system": UCUM_SYSTEM,
+            "code": "mg/dL"
+        },
+        "interpretation": interpretation(reading.glucose_level),
+        "note": [{ "text": format!("Sensor status: {}", reading.status) }]
+    })
+}
+
+pub fn medication_administration(log: &InsulinLog) -> Value {
+    json!({
+        "resourceType": "MedicationAdministration",
+        "id": format!("insulin-{}", log.dosage_id),
+        "status": "completed",
+        "medicationCodeableConcept": { "text": format!("Insulin ({})", log.action_type) },
+        "subject": patient_reference(&log.patient_id),
+        "effectiveDateTime": fhir_datetime(&log.dosage_time),
+        "note": [{ "text": format!("Requested by {}", log.requested_by) }],
+        "dosage": {
+            "text": format!("{} dose", log.action_type),
+            "route": { "text": "Subcutaneous" },
+            "dose": {
+                "value": log.dosage_units,
+                "unit": "U",
+                "system": UCUM_SYSTEM,
+                "code": "[IU]"
+            }
+        }
+    })
+}
+
+// one Bundle with every reading and dose, readings first
+pub fn bundle(readings: &[GlucoseReading], logs: &[InsulinLog]) -> Value {
+    let entries: Vec<Value> = readings
+        .iter()
+        .map(observation)
+        .chain(logs.iter().map(medication_administration))
+        .map(|resource| json!({ "resource": resource }))
+        .collect();
+    json!({
+        "resourceType": "Bundle",
+        "type": "collection",
+        "timestamp": Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+        "entry": entries
+    })
+}
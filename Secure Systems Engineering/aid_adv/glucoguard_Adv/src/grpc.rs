@@ -0,0 +1,160 @@
+// gRPC telemetry interface for pump devices (feature "grpc", proto/pump_telemetry.proto)
+// Pumps authenticate with an API token in the "authorization: Bearer ggt_..." metadata
+// and sign each request with the key they registered when pairing (see devices.rs)
+use crate::access_control::{self, Permission, Resource, Role};
+use crate::api_token;
+use crate::devices::{self, DeviceType};
+use crate::db::pool::Db;
+use crate::logger::SECURITY_TARGET;
+use crate::pump;
+use crate::runtime;
+use rusqlite::Connection;
+use tonic::{Request, Response, Status};
+
+pub mod proto {
+    tonic::include_proto!("glucoguard.pump.v1");
+}
+
+use proto::pump_telemetry_server::{PumpTelemetry, PumpTelemetryServer};
+use proto::{Ack, DoseConfirmation, PullCommandsRequest, PullCommandsResponse, PumpStatus};
+
+// largest single dose a pump may report, in units
+const MAX_REPORTED_DOSE: f64 = 100.0;
+
+pub struct TelemetryService {
+    db: Db,
+}
+
+impl TelemetryService {
+    pub fn new(db: Db) -> PumpTelemetryServer<Self> {
+        PumpTelemetryServer::new(Self { db })
+    }
+
+    // database work runs on the blocking pool, off the async workers
+    async fn with_db<T, F>(&self, work: F) -> Result<T, Status>
+    where
+        F: FnOnce(&Connection) -> Result<T, Status> + Send + 'static,
+        T: Send + 'static,
+    {
+        runtime::with_db(&self.db, work).await.map_err(Status::unavailable)?
+    }
+}
+
+fn credential<T>(request: &Request<T>) -> Result<String, Status> {
+    request
+        .metadata()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|value| value.trim().to_string())
+        .ok_or_else(|| Status::unauthenticated("missing API token"))
+}
+
+// the token must be allowed to add insulin data for this patient
+fn authorize(conn: &Connection, credential: &str, patient_id: &str) -> Result<(), Status> {
+    let token = api_token::authenticate_token(conn, credential)
+        .map_err(|e| Status::internal(e.to_string()))?
+        .ok_or_else(|| Status::unauthenticated("invalid, expired or revoked API token"))?;
+
+    let role = Role::new(conn, &token.role, &token.user_id);
+    let in_token_scope = token
+        .patient_ids
+        .as_ref()
+        .is_none_or(|ids| ids.iter().any(|id| id == patient_id));
+    if in_token_scope && access_control::can_access(conn, &role, &Resource::Patient(patient_id), &Permission::AddGlucose) {
+        Ok(())
+    } else {
+        tracing::warn!(target: SECURITY_TARGET, token_id = %token.token_id, patient_id = %patient_id, "Pump telemetry access denied");
+        Err(Status::permission_denied("token may not submit data for this patient"))
+    }
+}
+
+// the request must be signed by a pump paired to the patient
+fn authenticate_pump(conn: &Connection, device_id: &str, patient_id: &str, signed_at_ms: i64, parts: &[&str], signature: &str) -> Result<(), Status> {
+    devices::authenticate_device(conn, device_id, patient_id, DeviceType::Pump, signed_at_ms, parts, signature)
+        .map_err(|e| Status::permission_denied(format!("device not accepted: {}", e)))
+}
+
+fn ack(message: &str) -> Response<Ack> {
+    Response::new(Ack { accepted: true, message: message.to_string() })
+}
+
+#[tonic::async_trait]
+impl PumpTelemetry for TelemetryService {
+    async fn confirm_dose(&self, request: Request<DoseConfirmation>) -> Result<Response<Ack>, Status> {
+        let credential = credential(&request)?;
+        let dose = request.into_inner();
+        self.with_db(move |conn| {
+            authorize(conn, &credential, &dose.patient_id)?;
+            let signed_at = dose.signed_at_ms.to_string();
+            let command = dose.command_id.to_string();
+            let units = format!("{:.3}", dose.dosage_units);
+            let parts = ["confirm_dose", &dose.device_id, &dose.patient_id, &signed_at, &command, &dose.action_type, &units];
+            authenticate_pump(conn, &dose.device_id, &dose.patient_id, dose.signed_at_ms, &parts, &dose.signature)?;
+
+            if dose.action_type != "basal" && dose.action_type != "bolus" {
+                return Err(Status::invalid_argument("action_type must be \"basal\" or \"bolus\""));
+            }
+            if !(dose.dosage_units > 0.0 && dose.dosage_units <= MAX_REPORTED_DOSE) {
+                return Err(Status::invalid_argument("dosage_units out of range"));
+            }
+            let command_id = (dose.command_id != 0).then_some(dose.command_id);
+            pump::confirm_dose(conn, &dose.patient_id, command_id, &dose.action_type, dose.dosage_units, &dose.device_id)
+                .map_err(Status::failed_precondition)
+        })
+        .await?;
+        Ok(ack("dose recorded"))
+    }
+
+    async fn report_status(&self, request: Request<PumpStatus>) -> Result<Response<Ack>, Status> {
+        let credential = credential(&request)?;
+        let status = request.into_inner();
+        self.with_db(move |conn| {
+            authorize(conn, &credential, &status.patient_id)?;
+            let signed_at = status.signed_at_ms.to_string();
+            let reservoir = format!("{:.3}", status.reservoir_units);
+            let battery = status.battery_percent.to_string();
+            let parts = ["report_status", &status.device_id, &status.patient_id, &signed_at, &reservoir, &battery];
+            authenticate_pump(conn, &status.device_id, &status.patient_id, status.signed_at_ms, &parts, &status.signature)?;
+
+            if status.reservoir_units < 0.0 || status.battery_percent > 100 {
+                return Err(Status::invalid_argument("reservoir or battery value out of range"));
+            }
+            pump::record_status(conn, &status.device_id, &status.patient_id, status.reservoir_units, status.battery_percent, None)
+                .map(|_| ())
+                .map_err(|e| Status::internal(e.to_string()))
+        })
+        .await?;
+        Ok(ack("status recorded"))
+    }
+
+    async fn pull_commands(&self, request: Request<PullCommandsRequest>) -> Result<Response<PullCommandsResponse>, Status> {
+        let credential = credential(&request)?;
+        let pull = request.into_inner();
+        let commands = self
+            .with_db(move |conn| {
+                authorize(conn, &credential, &pull.patient_id)?;
+                let signed_at = pull.signed_at_ms.to_string();
+                let parts = ["pull_commands", &pull.device_id, &pull.patient_id, &signed_at];
+                authenticate_pump(conn, &pull.device_id, &pull.patient_id, pull.signed_at_ms, &parts, &pull.signature)?;
+                pump::take_pending_commands(conn, &pull.patient_id, &pull.device_id).map_err(|e| Status::internal(e.to_string()))
+            })
+            .await?;
+        Ok(Response::new(PullCommandsResponse {
+            commands: commands
+                .into_iter()
+                .map(|command| proto::PumpCommand {
+                    command_id: command.command_id,
+                    command_type: command.command_type,
+                    dosage_units: command.dosage_units,
+                    created_at: command.created_at,
+                    duration_minutes: command.duration_minutes,
+                    device_id: command.device_id,
+                    patient_id: command.patient_id,
+                    issued_at_ms: command.issued_at_ms,
+                    signature: command.signature,
+                })
+                .collect(),
+        }))
+    }
+}
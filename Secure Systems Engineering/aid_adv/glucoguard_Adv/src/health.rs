@@ -0,0 +1,231 @@
+/*
+Health check for orchestration (`glucoguard healthcheck`): database connectivity, schema version,
+pending migrations, free disk space next to the database and the liveness of the background tasks.
+The database is opened read-only and never migrated, so a check cannot change what it reports on.
+The command exits non-zero when any check fails.
+*/
+use crate::config::{self, Config};
+use crate::db::migrations;
+use crate::timestamps;
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use serde::Serialize;
+use std::error::Error;
+use std::path::Path;
+use std::time::Instant;
+
+// the session cleanup task, which also runs escalation, monitoring, summaries, housekeeping and retention
+pub const CLEANUP_JOB: &str = "session_cleanup";
+// the cleanup task counts as stalled after missing this many runs
+const MISSED_RUNS: u64 = 3;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: String) -> Self {
+        Check { name, ok: true, detail }
+    }
+
+    fn fail(name: &'static str, detail: String) -> Self {
+        Check { name, ok: false, detail }
+    }
+}
+
+// record that a background job ran; `failed` names the steps that did not succeed
+pub fn record_run(conn: &Connection, job: &str, failed: &[&str]) -> rusqlite::Result<()> {
+    let error = (!failed.is_empty()).then(|| failed.join(","));
+    conn.execute(
+        "INSERT INTO background_jobs (job, last_run_at, last_error) VALUES (?1, ?2, ?3)
+         ON CONFLICT (job) DO UPDATE SET last_run_at = excluded.last_run_at, last_error = excluded.last_error",
+        params![job, timestamps::now(), error],
+    )?;
+    Ok(())
+}
+
+fn check_database(path: &str) -> (Check, Option<Connection>) {
+    if !Path::new(path).is_file() {
+        return (Check::fail("database", format!("{} does not exist", path)), None);
+    }
+    let started = Instant::now();
+    let opened = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+        .and_then(|conn| conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0)).map(|_| conn));
+    match opened {
+        Ok(conn) => {
+            let millis = started.elapsed().as_secs_f64() * 1000.0;
+            (Check::pass("database", format!("{} answered in {:.1} ms", path, millis)), Some(conn))
+        }
+        Err(e) => (Check::fail("database", format!("{}: {}", path, e)), None),
+    }
+}
+
+fn check_schema(conn: &Connection) -> Vec<Check> {
+    let has_versions: bool = conn
+        .query_row("SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version')", [], |row| row.get(0))
+        .unwrap_or(false);
+    let current = if has_versions { migrations::current_version(conn) } else { Ok(0) };
+    let current = match current {
+        Ok(current) => current,
+        Err(e) => return vec![Check::fail("schema", e.to_string())],
+    };
+    let latest = migrations::latest_version();
+    let schema = if current > latest {
+        Check::fail("schema", format!("version {} is newer than this build supports ({})", current, latest))
+    } else {
+        Check::pass("schema", format!("version {} of {}", current, latest))
+    };
+    let pending = migrations::pending_migrations(current);
+    let pending = if pending.is_empty() {
+        Check::pass("migrations", "none pending".to_string())
+    } else {
+        Check::fail("migrations", format!("{} pending: {}", pending.len(), pending.join(", ")))
+    };
+    vec![schema, pending]
+}
+
+// bytes available to this user on the file system holding `dir`
+#[cfg(unix)]
+fn free_bytes(dir: &Path) -> Result<u64, String> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes()).map_err(|e| e.to_string())?;
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: path is a valid C string and stats is a writable statvfs the call fills in
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+    Ok(stats.f_bavail as u64 * stats.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn free_bytes(_dir: &Path) -> Result<u64, String> {
+    Err("free space can only be checked on Unix".to_string())
+}
+
+fn check_disk(database_path: &str, min_free_mb: u64) -> Check {
+    let dir = match Path::new(database_path).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    match free_bytes(dir) {
+        Ok(bytes) => {
+            let free_mb = bytes / (1024 * 1024);
+            let detail = format!("{} MB free in {} (minimum {} MB)", free_mb, dir.display(), min_free_mb);
+            if free_mb >= min_free_mb {
+                Check::pass("disk", detail)
+            } else {
+                Check::fail("disk", detail)
+            }
+        }
+        Err(e) => Check::fail("disk", format!("{}: {}", dir.display(), e)),
+    }
+}
+
+fn check_jobs(conn: &Connection, interval_secs: u64, now: DateTime<Utc>) -> Check {
+    let has_jobs: bool = conn
+        .query_row("SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'background_jobs')", [], |row| row.get(0))
+        .unwrap_or(false);
+    let last_run = if has_jobs {
+        conn.query_row("SELECT last_run_at, last_error FROM background_jobs WHERE job = ?1", [CLEANUP_JOB], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+        })
+        .optional()
+    } else {
+        Ok(None)
+    };
+    let (last_run_at, last_error) = match last_run {
+        Ok(Some(run)) => run,
+        Ok(None) => return Check::fail("background_jobs", format!("{} has never run", CLEANUP_JOB)),
+        Err(e) => return Check::fail("background_jobs", e.to_string()),
+    };
+    let Some(ran_at) = timestamps::parse(&last_run_at) else {
+        return Check::fail("background_jobs", format!("unreadable last run time '{}'", last_run_at));
+    };
+    let age = (now - ran_at).num_seconds().max(0) as u64;
+    let limit = interval_secs * MISSED_RUNS;
+    if age > limit {
+        return Check::fail("background_jobs", format!("{} last ran {} s ago (limit {} s)", CLEANUP_JOB, age, limit));
+    }
+    match last_error {
+        Some(failed) => Check::fail("background_jobs", format!("{} ran {} s ago, failed steps: {}", CLEANUP_JOB, age, failed)),
+        None => Check::pass("background_jobs", format!("{} ran {} s ago", CLEANUP_JOB, age)),
+    }
+}
+
+// every check for the configured database; skip_jobs leaves out background_jobs, for installs without `serve`
+pub fn run_checks(config: &Config, skip_jobs: bool) -> Vec<Check> {
+    let path = &config.database.path;
+    let (database, conn) = check_database(path);
+    let mut checks = vec![database];
+    if let Some(conn) = &conn {
+        checks.extend(check_schema(conn));
+    }
+    checks.push(check_disk(path, config.health.min_free_disk_mb));
+    if !skip_jobs {
+        if let Some(conn) = &conn {
+            checks.push(check_jobs(conn, config.session.cleanup_interval_secs, Utc::now()));
+        }
+    }
+    checks
+}
+
+// `glucoguard healthcheck`: print every check, fail when one failed
+pub fn run(skip_jobs: bool, json: bool) -> Result<(), Box<dyn Error>> {
+    let checks = run_checks(config::get(), skip_jobs);
+    let healthy = checks.iter().all(|check| check.ok);
+    if json {
+        println!("{}", serde_json::json!({ "healthy": healthy, "checks": checks }));
+    } else {
+        for check in &checks {
+            println!("{:<4} {:<16} {}", if check.ok { "ok" } else { "FAIL" }, check.name, check.detail);
+        }
+    }
+    if healthy {
+        Ok(())
+    } else {
+        let failed: Vec<&str> = checks.iter().filter(|check| !check.ok).map(|check| check.name).collect();
+        Err(format!("unhealthy: {}", failed.join(", ")).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::initialize;
+
+    #[test]
+    fn checks_report_schema_and_stalled_background_jobs() {
+        let dir = std::env::temp_dir().join(format!("glucoguard-health-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("health.db").display().to_string();
+        let mut config = Config::default();
+        config.database.path = path.clone();
+
+        let checks = run_checks(&config, false);
+        assert_eq!((checks[0].name, checks[0].ok), ("database", false));
+
+        let conn = Connection::open(&path).unwrap();
+        initialize::initialize_database(&conn).unwrap();
+        let checks = run_checks(&config, true);
+        assert!(checks.iter().all(|check| check.ok), "{:?}", checks);
+        assert_eq!(checks.iter().map(|check| check.name).collect::<Vec<_>>(), ["database", "schema", "migrations", "disk"]);
+        conn.execute("DELETE FROM schema_version WHERE version = ?1", [migrations::latest_version()]).unwrap();
+        assert!(!run_checks(&config, true).iter().find(|check| check.name == "migrations").unwrap().ok);
+
+        let now = Utc::now();
+        assert!(!check_jobs(&conn, 60, now).ok);
+        record_run(&conn, CLEANUP_JOB, &[]).unwrap();
+        assert!(check_jobs(&conn, 60, now).ok);
+        assert!(!check_jobs(&conn, 60, now + chrono::Duration::minutes(4)).ok);
+        record_run(&conn, CLEANUP_JOB, &["escalation"]).unwrap();
+        assert!(check_jobs(&conn, 60, now).detail.contains("escalation"));
+
+        config.health.min_free_disk_mb = u64::MAX / (1024 * 1024);
+        assert!(!check_disk(&path, config.health.min_free_disk_mb).ok);
+        drop(conn);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
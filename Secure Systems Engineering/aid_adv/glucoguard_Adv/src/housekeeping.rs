@@ -0,0 +1,216 @@
+/*
+Database housekeeping: WAL checkpoints, ANALYZE and VACUUM, each on its own interval from the session
+cleanup task when housekeeping.enabled, or once by hand with `glucoguard housekeeping [task...]`.
+When each task last ran is kept in background_jobs, so a restart does not repeat a weekly VACUUM. A task
+that has never run starts its clock instead of running at once.
+*/
+use crate::config::{self, HousekeepingConfig};
+use crate::db::initialize;
+use crate::health;
+use crate::timestamps;
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{Connection, OptionalExtension};
+use std::error::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Task {
+    Checkpoint,
+    Analyze,
+    Vacuum,
+}
+
+pub const TASKS: [Task; 3] = [Task::Checkpoint, Task::Analyze, Task::Vacuum];
+
+impl Task {
+    // name on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Task::Checkpoint => "checkpoint",
+            Task::Analyze => "analyze",
+            Task::Vacuum => "vacuum",
+        }
+    }
+
+    // row in background_jobs
+    fn job(self) -> &'static str {
+        match self {
+            Task::Checkpoint => "db_checkpoint",
+            Task::Analyze => "db_analyze",
+            Task::Vacuum => "db_vacuum",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Task> {
+        TASKS.into_iter().find(|task| task.name() == name)
+    }
+
+    // None when the task is switched off
+    fn interval(self, settings: &HousekeepingConfig) -> Option<Duration> {
+        let interval = match self {
+            Task::Checkpoint => Duration::minutes(settings.checkpoint_interval_minutes as i64),
+            Task::Analyze => Duration::hours(settings.analyze_interval_hours as i64),
+            Task::Vacuum => Duration::hours(settings.vacuum_interval_hours as i64),
+        };
+        (interval > Duration::zero()).then_some(interval)
+    }
+}
+
+fn database_bytes(conn: &Connection) -> rusqlite::Result<i64> {
+    let pages: i64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+    let page_size: i64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+    Ok(pages * page_size)
+}
+
+// run one task now and describe what it did
+pub fn run_task(conn: &Connection, task: Task) -> rusqlite::Result<String> {
+    match task {
+        Task::Checkpoint => {
+            let mode: String = conn.query_row("PRAGMA journal_mode", [], |row| row.get(0))?;
+            if !mode.eq_ignore_ascii_case("wal") {
+                return Ok(format!("skipped, the database uses the {} journal", mode));
+            }
+            let (busy, frames, copied): (i64, i64, i64) =
+                conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+            if busy != 0 {
+                Ok(format!("{} of {} WAL frames copied, open readers kept the log from being truncated", copied, frames))
+            } else {
+                Ok(format!("{} WAL frames copied, log truncated", copied))
+            }
+        }
+        Task::Analyze => {
+            conn.execute_batch("ANALYZE")?;
+            Ok("query planner statistics updated".to_string())
+        }
+        Task::Vacuum => {
+            let before = database_bytes(conn)?;
+            conn.execute_batch("VACUUM")?;
+            let after = database_bytes(conn)?;
+            Ok(format!("{} KiB returned to the disk", (before - after).max(0) / 1024))
+        }
+    }
+}
+
+fn last_run(conn: &Connection, task: Task) -> rusqlite::Result<Option<DateTime<Utc>>> {
+    let ran_at: Option<String> = conn
+        .query_row("SELECT last_run_at FROM background_jobs WHERE job = ?1", [task.job()], |row| row.get(0))
+        .optional()?;
+    Ok(ran_at.as_deref().and_then(timestamps::parse))
+}
+
+/*
+Run every task whose interval has passed since its last run and record the run; a task without a
+recorded run only records one. A failed task is recorded as failed and the others still run; the last
+error is returned once all are done.
+*/
+pub fn run_due(conn: &Connection, settings: &HousekeepingConfig, now: DateTime<Utc>) -> rusqlite::Result<usize> {
+    let mut ran = 0;
+    let mut failure = None;
+    for task in TASKS {
+        let Some(interval) = task.interval(settings) else {
+            continue;
+        };
+        match last_run(conn, task)? {
+            None => {
+                health::record_run(conn, task.job(), &[])?;
+                continue;
+            }
+            Some(ran_at) if now - ran_at < interval => continue,
+            Some(_) => {}
+        }
+        match run_task(conn, task) {
+            Ok(outcome) => {
+                tracing::info!(task = task.name(), "Database housekeeping: {}", outcome);
+                health::record_run(conn, task.job(), &[])?;
+                ran += 1;
+            }
+            Err(e) => {
+                tracing::error!(task = task.name(), "Database housekeeping failed: {}", e);
+                health::record_run(conn, task.job(), &[task.name()])?;
+                failure = Some(e);
+            }
+        }
+    }
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(ran),
+    }
+}
+
+// `glucoguard housekeeping [checkpoint|analyze|vacuum]...`, every task when none is named; runs even when
+// housekeeping.enabled is false
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let tasks = if args.is_empty() {
+        TASKS.to_vec()
+    } else {
+        args.iter()
+            .map(|name| Task::parse(name).ok_or_else(|| format!("unknown task '{}'; usage: glucoguard housekeeping [checkpoint|analyze|vacuum]...", name)))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+    let conn = initialize::establish_connection()?;
+    for task in tasks {
+        let outcome = run_task(&conn, task)?;
+        health::record_run(&conn, task.job(), &[])?;
+        println!("{}: {}", task.name(), outcome);
+    }
+    if !config::get().housekeeping.enabled {
+        println!("housekeeping.enabled is false, the tasks only run when started by hand");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use rusqlite::params;
+
+    fn settings() -> HousekeepingConfig {
+        HousekeepingConfig {
+            enabled: true,
+            checkpoint_interval_minutes: 15,
+            analyze_interval_hours: 24,
+            vacuum_interval_hours: 0,
+        }
+    }
+
+    #[test]
+    fn tasks_run_once_their_interval_has_passed() {
+        let conn = test_utils::test_db();
+        let now = Utc::now();
+
+        // the first pass only starts the clocks, vacuum is switched off
+        assert_eq!(run_due(&conn, &settings(), now).unwrap(), 0);
+        assert!(last_run(&conn, Task::Analyze).unwrap().is_some());
+        assert!(last_run(&conn, Task::Vacuum).unwrap().is_none());
+        assert_eq!(run_due(&conn, &settings(), now + Duration::minutes(5)).unwrap(), 0);
+
+        // checkpoint is due after 15 minutes, analyze after a day
+        assert_eq!(run_due(&conn, &settings(), now + Duration::minutes(20)).unwrap(), 1);
+        conn.execute("UPDATE background_jobs SET last_run_at = ?1", params![timestamps::to_storage(now - Duration::days(2))])
+            .unwrap();
+        assert_eq!(run_due(&conn, &settings(), now).unwrap(), 2);
+        let analyzed: bool = conn
+            .query_row("SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE name = 'sqlite_stat1')", [], |row| row.get(0))
+            .unwrap();
+        assert!(analyzed);
+    }
+
+    #[test]
+    fn vacuum_and_checkpoint_run_by_hand() {
+        let conn = test_utils::test_db();
+        assert!(run_task(&conn, Task::Vacuum).unwrap().contains("KiB"));
+        // an in-memory database has no write-ahead log
+        assert!(run_task(&conn, Task::Checkpoint).unwrap().starts_with("skipped"));
+        assert_eq!(Task::parse("analyze"), Some(Task::Analyze));
+        assert_eq!(Task::parse("reindex"), None);
+
+        let indexed: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE name IN ('idx_glucose_readings_patient_time', 'idx_insulin_logs_patient_time')",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(indexed, 2);
+    }
+}
@@ -0,0 +1,206 @@
+// Message catalog for the interactive menus
+// Every user-facing menu string has a key with an English and a Spanish text; the locale comes from
+// ui.locale in the config. A key missing from the catalog is printed as is, so a typo shows up on screen.
+use crate::config;
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Locale {
+    En,
+    Es,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 2] = [Locale::En, Locale::Es];
+
+    pub fn parse(code: &str) -> Option<Locale> {
+        match code.trim().to_ascii_lowercase().as_str() {
+            "en" => Some(Locale::En),
+            "es" => Some(Locale::Es),
+            _ => None,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Es => "es",
+        }
+    }
+}
+
+// (key, English, Spanish); `{}` marks an argument of tf, filled in order
+const CATALOG: &[(&str, &str, &str)] = &[
+    // shared
+    ("common.choice_prompt", "Enter your choice: ", "Introduzca su opción: "),
+    ("common.invalid_choice", "Invalid choice", "Opción no válida"),
+    ("common.logged_out", "Logged out.", "Sesión cerrada."),
+    ("common.logout", "Logout", "Cerrar sesión"),
+    ("common.session_invalid", "Invalid or expired session. Please log in again.", "Sesión no válida o caducada. Vuelva a iniciar sesión."),
+    ("common.session_expired", "Session has expired. Please log in again.", "La sesión ha caducado. Vuelva a iniciar sesión."),
+    ("common.session_expired_logout", "Session has expired. Logging you out...", "La sesión ha caducado. Cerrando la sesión..."),
+    ("common.session_deactivate_failed", "Failed to deactivate session: {}", "No se pudo desactivar la sesión: {}"),
+    ("common.access_denied_page", "Invalid access rights to view page", "No tiene permisos para ver esta página"),
+    ("common.unknown_role", "Unknown role: {}", "Rol desconocido: {}"),
+    // start screen
+    ("home.title", "Welcome to GlucoGuard", "Bienvenido a GlucoGuard"),
+    ("home.sign_in", "Sign In", "Iniciar sesión"),
+    ("home.sign_up", "Sign Up with Activation code.", "Registrarse con un código de activación."),
+    ("home.invalid_choice", "Invalid choice. Please enter 1 or 2", "Opción no válida. Introduzca 1 o 2"),
+    ("home.goodbye", "Exiting program. Goodbye!", "Saliendo del programa. ¡Adiós!"),
+    ("home.shutdown", "Stopped by {}. Goodbye!", "Detenido por {}. ¡Adiós!"),
+    ("home.shutdown_session", "Stopped by {}. Your session was closed. Goodbye!", "Detenido por {}. Se cerró su sesión. ¡Adiós!"),
+    ("home.invalid_option", "Invalid option. Please select a valid choice.", "Opción no válida. Seleccione una opción válida."),
+    // login
+    ("login.title", "Login", "Inicio de sesión"),
+    ("login.username", "Enter username: ", "Introduzca el usuario: "),
+    ("login.password", "Enter password: ", "Introduzca la contraseña: "),
+    ("login.success_session", "Login successful. Session created: {}", "Inicio de sesión correcto. Sesión creada: {}"),
+    ("login.session_failed", "Failed to create session: {}", "No se pudo crear la sesión: {}"),
+    ("login.unknown_error", "Unknown login error.", "Error de inicio de sesión desconocido."),
+    ("login.failed", "Login failed: {}", "Error al iniciar sesión: {}"),
+    // sign up
+    ("signup.title", "Account Sign Up", "Registro de cuenta"),
+    ("signup.activation_code", "Enter your activation code: ", "Introduzca su código de activación: "),
+    ("signup.invalid_code", "Invalid, expired or already used activation code. Please contact your clinician.", "Código de activación no válido, caducado o ya utilizado. Póngase en contacto con su médico."),
+    ("signup.code_spent", "This activation code has expired or was already used. Please contact your clinician.", "Este código de activación ha caducado o ya se ha utilizado. Póngase en contacto con su médico."),
+    ("signup.db_error", "Database error: {}", "Error de base de datos: {}"),
+    ("signup.username", "Choose a username: ", "Elija un nombre de usuario: "),
+    ("signup.username_length", "Username must be between 1 and {} characters.", "El nombre de usuario debe tener entre 1 y {} caracteres."),
+    ("signup.username_taken", "Please choose another username.", "Elija otro nombre de usuario."),
+    ("signup.password", "Enter a strong password: ", "Introduzca una contraseña segura: "),
+    ("signup.password_confirm", "Re-enter password to confirm: ", "Vuelva a introducir la contraseña para confirmar: "),
+    ("signup.password_mismatch", "Passwords do not match. Try again.", "Las contraseñas no coinciden. Inténtelo de nuevo."),
+    ("signup.create_failed", "Failed to create user: {}", "No se pudo crear el usuario: {}"),
+    ("signup.created", "Account created successfully for username '{}'.", "Cuenta creada correctamente para el usuario '{}'."),
+    // patient menu
+    ("patient.title", "Patient Menu", "Menú del paciente"),
+    ("patient.recent_readings", "View most recent glucose readings.", "Ver las lecturas de glucosa más recientes."),
+    ("patient.insulin_options", "View current basal and bolus options.", "Ver las opciones actuales de basal y bolo."),
+    ("patient.request_bolus", "Request bolus insulin dose.", "Solicitar una dosis de insulina en bolo."),
+    ("patient.basal_time", "Configure basal insulin dose time.", "Configurar la hora de la dosis basal."),
+    ("patient.insulin_history", "View patient insulin history.", "Ver el historial de insulina."),
+    ("patient.caretakers", "Manage my caretakers.", "Gestionar mis cuidadores."),
+    ("patient.consent", "Data sharing consent.", "Consentimiento para compartir datos."),
+    ("patient.glucose_stats", "View glucose statistics.", "Ver estadísticas de glucosa."),
+    ("patient.contact", "Update my contact details.", "Actualizar mis datos de contacto."),
+    ("patient.emergency_stop", "EMERGENCY STOP: suspend all insulin delivery.", "PARADA DE EMERGENCIA: suspender toda administración de insulina."),
+    ("patient.temp_basal", "Set / cancel a temporary basal rate.", "Establecer / cancelar una tasa basal temporal."),
+    ("patient.reminders", "Care reminders.", "Recordatorios de cuidado."),
+    ("patient.glucose_unit", "Glucose units (mg/dL or mmol/L).", "Unidades de glucosa (mg/dL o mmol/L)."),
+    ("patient.timezone", "Time zone for dates and times.", "Zona horaria de fechas y horas."),
+    // caretaker menu
+    ("caretaker.title", "CareTaker Menu", "Menú del cuidador"),
+    ("caretaker.recent_readings", "View most recent glucose readings.", "Ver las lecturas de glucosa más recientes."),
+    ("caretaker.insulin_options", "View current basal and bolus options.", "Ver las opciones actuales de basal y bolo."),
+    ("caretaker.request_bolus", "Request bolus insulin dose.", "Solicitar una dosis de insulina en bolo."),
+    ("caretaker.basal_time", "Configure basal insulin dose time.", "Configurar la hora de la dosis basal."),
+    ("caretaker.insulin_history", "View patient insulin history.", "Ver el historial de insulina del paciente."),
+    ("caretaker.notes", "Read clinical notes.", "Leer las notas clínicas."),
+    ("caretaker.emergency_stop", "EMERGENCY STOP: suspend a patient's insulin delivery.", "PARADA DE EMERGENCIA: suspender la insulina de un paciente."),
+    ("caretaker.escalated_alerts", "Escalated alerts.", "Alertas escaladas."),
+    ("caretaker.reminders", "Care reminders.", "Recordatorios de cuidado."),
+    ("caretaker.glucose_unit", "Glucose units (mg/dL or mmol/L).", "Unidades de glucosa (mg/dL o mmol/L)."),
+    ("caretaker.timezone", "Time zone for dates and times.", "Zona horaria de fechas y horas."),
+    // clinician menu
+    ("clinician.title", "Clinician Menu", "Menú del médico"),
+    ("clinician.history", "View patient glucose and insulin history.", "Ver el historial de glucosa e insulina del paciente."),
+    ("clinician.edit_parameters", "Edit patient Parameters", "Editar parámetros del paciente"),
+    ("clinician.edit_limits", "Edit limits.", "Editar límites."),
+    ("clinician.edit_alerts", "Edit default alerts", "Editar alertas predeterminadas"),
+    ("clinician.create_patient", "Create Patient Account", "Crear cuenta de paciente"),
+    ("clinician.patient_details", "View Patient Account(s) Details", "Ver datos de las cuentas de pacientes"),
+    ("clinician.notes", "Clinical notes on a patient record", "Notas clínicas del expediente de un paciente"),
+    ("clinician.export", "Export patient history / research dataset (CSV/JSON)", "Exportar historial del paciente / conjunto de investigación (CSV/JSON)"),
+    ("clinician.summary_report", "Generate patient summary report (PDF)", "Generar informe resumen del paciente (PDF)"),
+    ("clinician.glucose_stats", "View patient glucose statistics", "Ver estadísticas de glucosa del paciente"),
+    ("clinician.search", "Search patients by name or DOB", "Buscar pacientes por nombre o fecha de nacimiento"),
+    ("clinician.caretakers", "Manage patient caretakers", "Gestionar los cuidadores del paciente"),
+    ("clinician.reissue_code", "Reissue patient activation code", "Reemitir el código de activación del paciente"),
+    ("clinician.devices", "Manage patient devices", "Gestionar los dispositivos del paciente"),
+    ("clinician.emergency_stop", "Emergency stop / resume patient insulin delivery", "Parada de emergencia / reanudar la insulina del paciente"),
+    ("clinician.temp_basal", "Set / cancel a temporary basal rate", "Establecer / cancelar una tasa basal temporal"),
+    ("clinician.escalated_alerts", "Escalated alerts", "Alertas escaladas"),
+    ("clinician.daily_summaries", "Daily patient summaries", "Resúmenes diarios de pacientes"),
+    ("clinician.dashboard", "Patient dashboard (all your patients at a glance)", "Panel de pacientes (todos sus pacientes de un vistazo)"),
+    ("clinician.transfer", "Transfer a patient to another clinician", "Transferir un paciente a otro médico"),
+    ("clinician.reminders", "Care reminders (glucose checks, site changes, appointments)", "Recordatorios de cuidado (controles de glucosa, cambios de sitio, citas)"),
+    ("clinician.glucose_unit", "Glucose units (mg/dL or mmol/L)", "Unidades de glucosa (mg/dL o mmol/L)"),
+    ("clinician.timezone", "Time zone for dates and times", "Zona horaria de fechas y horas"),
+    // admin menu
+    ("admin.title", "Admin Menu", "Menú de administración"),
+    ("admin.create_clinician", "Create Clinician Account", "Crear cuenta de médico"),
+    ("admin.list_clinicians", "View Clinician Account List", "Ver la lista de cuentas de médicos"),
+    ("admin.create_caretaker", "Create Caretaker Account", "Crear cuenta de cuidador"),
+    ("admin.suspend_user", "Suspend or reactivate a user", "Suspender o reactivar un usuario"),
+    ("admin.revoke_sessions", "Revoke all sessions for a user", "Revocar todas las sesiones de un usuario"),
+    ("admin.permissions", "Manage role permissions", "Gestionar los permisos de los roles"),
+    ("admin.roles", "Manage roles", "Gestionar roles"),
+    ("admin.api_tokens", "Manage API tokens", "Gestionar tokens de API"),
+    ("admin.backup", "Backup and restore", "Copia de seguridad y restauración"),
+    ("admin.erase_patient", "Erase patient data", "Borrar los datos de un paciente"),
+    ("admin.users", "User management", "Gestión de usuarios"),
+    ("admin.audit_log", "Audit log", "Registro de auditoría"),
+    ("admin.transfer", "Transfer a patient to another clinician", "Transferir un paciente a otro médico"),
+    ("admin.timezone", "Time zone for dates and times", "Zona horaria de fechas y horas"),
+    ("admin.security_alerts", "Security alerts", "Alertas de seguridad"),
+];
+
+// locale of the menus, from the config
+pub fn locale() -> Locale {
+    Locale::parse(&config::get().ui.locale).unwrap_or(Locale::En)
+}
+
+pub fn lookup(locale: Locale, key: &str) -> Option<&'static str> {
+    CATALOG.iter().find(|(k, _, _)| *k == key).map(|(_, en, es)| match locale {
+        Locale::En => *en,
+        Locale::Es => *es,
+    })
+}
+
+// text of a message in the configured locale
+pub fn t(key: &'static str) -> &'static str {
+    lookup(locale(), key).unwrap_or(key)
+}
+
+// text of a message with its `{}` placeholders replaced by the arguments in order
+pub fn tf(key: &'static str, args: &[&dyn Display]) -> String {
+    fill(t(key), args)
+}
+
+fn fill(template: &str, args: &[&dyn Display]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut args = args.iter();
+    let mut parts = template.split("{}");
+    if let Some(first) = parts.next() {
+        out.push_str(first);
+    }
+    for part in parts {
+        if let Some(arg) = args.next() {
+            out.push_str(&arg.to_string());
+        }
+        out.push_str(part);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn every_message_is_translated_with_the_same_placeholders() {
+        let mut keys = HashSet::new();
+        for (key, en, es) in CATALOG {
+            assert!(keys.insert(*key), "duplicate key {}", key);
+            assert!(!en.trim().is_empty() && !es.trim().is_empty(), "empty text for {}", key);
+            assert_eq!(en.matches("{}").count(), es.matches("{}").count(), "placeholders differ for {}", key);
+        }
+        assert_eq!(lookup(Locale::Es, "common.logout"), Some("Cerrar sesión"));
+        assert_eq!(lookup(Locale::En, "no.such.key"), None);
+        assert_eq!(fill("{} of {}", &[&3, &"5"]), "3 of 5");
+        assert_eq!(Locale::parse(" ES "), Some(Locale::Es));
+        assert_eq!(Locale::parse("fr"), None);
+    }
+}
@@ -0,0 +1,301 @@
+// Runtime import of simulator glucose readings, pump insulin logs and pump status reports
+// `glucoguard import [--db <path>] [--glucose <csv>] [--insulin <csv>] [--pump-status <csv>]`
+use crate::cgm;
+use crate::config;
+use crate::db::pool::Db;
+use crate::dose_log::{self, DoseEntry};
+use crate::pump;
+use crate::runtime;
+use chrono::NaiveDateTime;
+use rusqlite::{params, Connection};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+
+pub const DEFAULT_INSULIN_CSV: &str = "pump_simm/insulin_pump.csv";
+
+// largest single pump dose accepted from a file, in units
+const MAX_DOSE_UNITS: f64 = 100.0;
+// largest reservoir fill accepted in a status report, in units
+const MAX_RESERVOIR_UNITS: f64 = 1000.0;
+// progress line is refreshed every this many rows
+const PROGRESS_STEP: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportKind {
+    Glucose,
+    Insulin,
+    PumpStatus,
+}
+
+impl ImportKind {
+    fn label(&self) -> &'static str {
+        match self {
+            ImportKind::Glucose => "glucose readings",
+            ImportKind::Insulin => "insulin logs",
+            ImportKind::PumpStatus => "pump status reports",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PumpRecord {
+    pub patient_id: String,
+    pub action_type: String,
+    pub dosage_units: f64,
+    pub requested_by: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatusRecord {
+    pub device_id: String,
+    pub patient_id: String,
+    pub reservoir_units: f64,
+    pub battery_percent: u32,
+    // "YYYY-MM-DD HH:MM:SS" UTC
+    pub reported_at: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub rows: usize,
+    pub inserted: usize,
+    pub duplicates: usize,
+    pub invalid: usize,
+}
+
+// parse one "patient_id,action_type,dosage_units[,requested_by]" line, Ok(None) for blank lines and the header
+pub fn parse_insulin_line(line: &str) -> Result<Option<PumpRecord>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with("patient_id") {
+        return Ok(None);
+    }
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    if fields.len() < 3 {
+        return Err(format!("expected at least 3 fields, got {}", fields.len()));
+    }
+    if fields[0].is_empty() {
+        return Err("missing patient_id".to_string());
+    }
+    let action_type = fields[1].to_lowercase();
+    if action_type != "basal" && action_type != "bolus" {
+        return Err(format!("unknown action type '{}'", fields[1]));
+    }
+    let dosage_units: f64 = fields[2]
+        .parse()
+        .map_err(|_| format!("invalid dosage '{}'", fields[2]))?;
+    if !(dosage_units > 0.0 && dosage_units <= MAX_DOSE_UNITS) {
+        return Err(format!("dosage {} outside 0-{} units", dosage_units, MAX_DOSE_UNITS));
+    }
+    let requested_by = match fields.get(3) {
+        Some(value) if !value.is_empty() => value.to_string(),
+        _ => "device".to_string(),
+    };
+    Ok(Some(PumpRecord {
+        patient_id: fields[0].to_string(),
+        action_type,
+        dosage_units,
+        requested_by,
+    }))
+}
+
+// parse one "device_id,patient_id,reservoir_units,battery_percent,reported_at" line, Ok(None) for blank lines and the header
+pub fn parse_status_line(line: &str) -> Result<Option<StatusRecord>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with("device_id") {
+        return Ok(None);
+    }
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    if fields.len() != 5 {
+        return Err(format!("expected 5 fields, got {}", fields.len()));
+    }
+    if fields[0].is_empty() || fields[1].is_empty() {
+        return Err("missing device_id or patient_id".to_string());
+    }
+    let reservoir_units: f64 = fields[2]
+        .parse()
+        .map_err(|_| format!("invalid reservoir '{}'", fields[2]))?;
+    if !(0.0..=MAX_RESERVOIR_UNITS).contains(&reservoir_units) {
+        return Err(format!("reservoir {} outside 0-{} units", reservoir_units, MAX_RESERVOIR_UNITS));
+    }
+    let battery_percent: u32 = match fields[3].parse() {
+        Ok(value) if value <= 100 => value,
+        _ => return Err(format!("invalid battery percent '{}'", fields[3])),
+    };
+    let reported_at = NaiveDateTime::parse_from_str(fields[4], "%Y-%m-%d %H:%M:%S")
+        .map_err(|_| format!("invalid reported_at '{}', expected YYYY-MM-DD HH:MM:SS", fields[4]))?;
+    Ok(Some(StatusRecord {
+        device_id: fields[0].to_string(),
+        patient_id: fields[1].to_string(),
+        reservoir_units,
+        battery_percent,
+        reported_at: reported_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+    }))
+}
+
+// identical source rows are imported only once, even across runs
+fn fingerprint(kind: ImportKind, line: &str) -> String {
+    let normalized: Vec<&str> = line.trim().split(',').map(str::trim).collect();
+    hex::encode(Sha256::digest(format!("{:?}:{}", kind, normalized.join(",")).as_bytes()))
+}
+
+fn patient_exists(conn: &Connection, patient_id: &str) -> rusqlite::Result<bool> {
+    conn.prepare_cached("SELECT EXISTS(SELECT 1 FROM patients WHERE patient_id = ?1)")?
+        .query_row([patient_id], |row| row.get(0))
+}
+
+// insert a parsed row, Ok(false) when the row is not valid for this database
+fn insert_row(conn: &Connection, kind: ImportKind, line: &str) -> Result<bool, String> {
+    let patient_id = match kind {
+        ImportKind::Glucose => match cgm::parse_line(line)? {
+            Some(reading) => {
+                if !patient_exists(conn, &reading.patient_id).map_err(|e| e.to_string())? {
+                    return Err(format!("unknown patient '{}'", reading.patient_id));
+                }
+                cgm::insert_reading(conn, &reading).map_err(|e| e.to_string())?;
+                reading.patient_id
+            }
+            None => return Ok(false),
+        },
+        ImportKind::Insulin => match parse_insulin_line(line)? {
+            Some(record) => {
+                if !patient_exists(conn, &record.patient_id).map_err(|e| e.to_string())? {
+                    return Err(format!("unknown patient '{}'", record.patient_id));
+                }
+                dose_log::append(
+                    conn,
+                    &DoseEntry {
+                        patient_id: &record.patient_id,
+                        action_type: &record.action_type,
+                        dosage_units: record.dosage_units,
+                        requested_by: &record.requested_by,
+                        dosage_time: None,
+                        request_id: None,
+                    },
+                )
+                .map_err(|e| e.to_string())?;
+                record.patient_id
+            }
+            None => return Ok(false),
+        },
+        ImportKind::PumpStatus => match parse_status_line(line)? {
+            Some(record) => {
+                if !patient_exists(conn, &record.patient_id).map_err(|e| e.to_string())? {
+                    return Err(format!("unknown patient '{}'", record.patient_id));
+                }
+                pump::record_status(
+                    conn,
+                    &record.device_id,
+                    &record.patient_id,
+                    record.reservoir_units,
+                    record.battery_percent,
+                    Some(&record.reported_at),
+                )
+                .map_err(|e| e.to_string())?;
+                record.patient_id
+            }
+            None => return Ok(false),
+        },
+    };
+    tracing::debug!(patient_id = %patient_id, kind = ?kind, "Imported row");
+    Ok(true)
+}
+
+fn print_progress(kind: ImportKind, done: usize, total: usize) {
+    print!("\rImporting {}: {}/{}", kind.label(), done, total);
+    let _ = io::stdout().flush();
+}
+
+// import one CSV file in a single transaction, skipping duplicates and invalid rows
+pub fn import_csv(conn: &Connection, kind: ImportKind, path: &str) -> Result<ImportSummary, Box<dyn Error>> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("Cannot read '{}': {}", path, e))?;
+    let lines: Vec<&str> = contents.lines().collect();
+    let mut summary = ImportSummary::default();
+
+    let tx = conn.unchecked_transaction()?;
+    for (index, line) in lines.iter().enumerate() {
+        if index % PROGRESS_STEP == 0 {
+            print_progress(kind, index, lines.len());
+        }
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("patient_id") || line.starts_with("device_id") {
+            continue;
+        }
+        summary.rows += 1;
+
+        let fp = fingerprint(kind, line);
+        let seen: bool = tx
+            .prepare_cached("SELECT EXISTS(SELECT 1 FROM imported_records WHERE fingerprint = ?1)")?
+            .query_row([&fp], |row| row.get(0))?;
+        if seen {
+            summary.duplicates += 1;
+            continue;
+        }
+
+        match insert_row(&tx, kind, line) {
+            Ok(true) => {
+                tx.prepare_cached(
+                    "INSERT INTO imported_records (fingerprint, source, imported_at) VALUES (?1, ?2, datetime('now'))",
+                )?
+                .execute(params![fp, path])?;
+                summary.inserted += 1;
+            }
+            Ok(false) => {}
+            Err(e) => {
+                tracing::warn!(source = %path, line = index + 1, "Skipping import row: {}", e);
+                summary.invalid += 1;
+            }
+        }
+    }
+    tx.commit()?;
+    print_progress(kind, lines.len(), lines.len());
+    println!();
+
+    tracing::info!(
+        source = %path,
+        kind = ?kind,
+        inserted = summary.inserted,
+        duplicates = summary.duplicates,
+        invalid = summary.invalid,
+        "Import finished"
+    );
+    Ok(summary)
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let usage = "usage: glucoguard import [--db <path>] [--glucose <csv>] [--insulin <csv>] [--pump-status <csv>]";
+    let mut db_path = None;
+    let mut sources = Vec::new();
+    let mut iter = args.iter();
+    while let Some(flag) = iter.next() {
+        let value = iter.next().ok_or(usage)?;
+        match flag.as_str() {
+            "--db" => db_path = Some(value.clone()),
+            "--glucose" => sources.push((ImportKind::Glucose, value.clone())),
+            "--insulin" => sources.push((ImportKind::Insulin, value.clone())),
+            "--pump-status" => sources.push((ImportKind::PumpStatus, value.clone())),
+            _ => return Err(usage.into()),
+        }
+    }
+    if sources.is_empty() {
+        sources.push((ImportKind::Glucose, cgm::DEFAULT_CSV_PATH.to_string()));
+        sources.push((ImportKind::Insulin, DEFAULT_INSULIN_CSV.to_string()));
+    }
+
+    let db_path = db_path.unwrap_or_else(|| config::get().database.path.clone());
+    let db = Db::open(&db_path, 1)?;
+
+    // files are imported one after another on the blocking pool, SQLite takes a single writer anyway
+    runtime::get().block_on(async move {
+        for (kind, path) in sources {
+            let label = path.clone();
+            let summary = runtime::with_db(&db, move |conn| import_csv(conn, kind, &path).map_err(|e| e.to_string())).await??;
+            println!(
+                "{}: {} rows, {} imported, {} duplicates skipped, {} invalid",
+                label, summary.rows, summary.inserted, summary.duplicates, summary.invalid
+            );
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,256 @@
+// Menu input: every line typed into the interactive menus and login screens is read here
+// Each read names the kind of field it expects; the line is trimmed, capped in length and checked
+// against the characters that field allows before any menu sees it, and numbers and dates are parsed
+// and bounds-checked here. Required fields ask again until they get a valid value, optional fields
+// also take an empty line, and a closed stdin ends the program instead of spinning a menu loop.
+use crate::clinical_notes::MAX_NOTE_LENGTH;
+use crate::input_validation::{parse_date_mm_dd_yyyy, parse_float_in_range, MAX_PASSWORD_LENGTH};
+use chrono::NaiveDate;
+use std::io::{self, Write};
+
+// longest line read at all; longer input is refused before it is looked at
+pub const MAX_LINE_CHARS: usize = 512;
+
+// what a field may contain
+#[derive(Debug, Clone, Copy)]
+pub struct Field {
+    pub name: &'static str,
+    pub max_chars: usize,
+    allowed: fn(char) -> bool,
+    hint: &'static str,
+}
+
+fn digit(c: char) -> bool {
+    c.is_ascii_digit()
+}
+
+fn username_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "._-@".contains(c)
+}
+
+fn name_char(c: char) -> bool {
+    c.is_alphabetic() || " '-.".contains(c)
+}
+
+fn id_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "-_:.".contains(c)
+}
+
+fn id_list_char(c: char) -> bool {
+    id_char(c) || ", ".contains(c)
+}
+
+fn number_char(c: char) -> bool {
+    c.is_ascii_digit() || ".-+".contains(c)
+}
+
+fn date_char(c: char) -> bool {
+    c.is_ascii_digit() || c == '-'
+}
+
+fn email_char(c: char) -> bool {
+    c.is_ascii_graphic()
+}
+
+fn phone_char(c: char) -> bool {
+    c.is_ascii_digit() || " +-().".contains(c)
+}
+
+fn zone_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "/_+-".contains(c)
+}
+
+fn word_char(c: char) -> bool {
+    c.is_ascii_alphabetic()
+}
+
+fn text_char(c: char) -> bool {
+    !c.is_control()
+}
+
+impl Field {
+    pub const CHOICE: Field = Field { name: "choice", max_chars: 4, allowed: digit, hint: "digits" };
+    pub const USERNAME: Field = Field { name: "username", max_chars: 64, allowed: username_char, hint: "letters, digits and . _ - @" };
+    pub const NAME: Field = Field { name: "name", max_chars: 64, allowed: name_char, hint: "letters, spaces and ' - ." };
+    // record ids, device ids and activation codes
+    pub const ID: Field = Field { name: "id", max_chars: 64, allowed: id_char, hint: "letters, digits and - _ : ." };
+    pub const ID_LIST: Field = Field { name: "list of ids", max_chars: MAX_LINE_CHARS, allowed: id_list_char, hint: "ids separated by commas" };
+    pub const NUMBER: Field = Field { name: "number", max_chars: 24, allowed: number_char, hint: "a number" };
+    pub const DATE: Field = Field { name: "date", max_chars: 10, allowed: date_char, hint: "MM-DD-YYYY" };
+    pub const EMAIL: Field = Field { name: "email address", max_chars: 254, allowed: email_char, hint: "no spaces" };
+    pub const PHONE: Field = Field { name: "phone number", max_chars: 25, allowed: phone_char, hint: "digits, spaces and + - ( ) ." };
+    pub const TIME_ZONE: Field = Field { name: "time zone", max_chars: 64, allowed: zone_char, hint: "an IANA name such as Europe/Madrid" };
+    // confirmation words such as y or ERASE
+    pub const WORD: Field = Field { name: "answer", max_chars: 16, allowed: word_char, hint: "letters" };
+    // reasons, labels, addresses and other free text
+    pub const TEXT: Field = Field { name: "text", max_chars: 256, allowed: text_char, hint: "no control characters" };
+    // clinical notes, one line each
+    pub const NOTE: Field = Field { name: "note", max_chars: MAX_NOTE_LENGTH, allowed: text_char, hint: "no control characters" };
+    pub const PASSWORD: Field = Field { name: "password", max_chars: MAX_PASSWORD_LENGTH, allowed: text_char, hint: "no control characters" };
+
+    // the reason a trimmed value does not fit the field
+    pub fn check(&self, value: &str) -> Result<(), String> {
+        if value.chars().count() > self.max_chars {
+            return Err(format!("The {} can be at most {} characters long.", self.name, self.max_chars));
+        }
+        if !value.chars().all(self.allowed) {
+            return Err(format!("The {} may only contain {}.", self.name, self.hint));
+        }
+        Ok(())
+    }
+}
+
+// stdin is gone (pipe closed, terminal hung up); there is nobody left to answer the menus
+fn input_closed() -> ! {
+    tracing::info!("Input closed, exiting");
+    println!();
+    std::process::exit(0);
+}
+
+// one trimmed line after the prompt; over-long lines are refused whole
+fn read_line(prompt: &str) -> Option<String> {
+    print!("{}", prompt);
+    let _ = io::stdout().flush();
+    let mut line = String::new();
+    match io::stdin().read_line(&mut line) {
+        Ok(0) | Err(_) => input_closed(),
+        Ok(_) if line.chars().count() > MAX_LINE_CHARS => {
+            println!("Input too long.");
+            None
+        }
+        Ok(_) => Some(line.trim().to_string()),
+    }
+}
+
+// the line if it fits the field, printing why not otherwise; empty lines pass as ""
+fn read_checked(prompt: &str, field: Field) -> Option<String> {
+    let value = read_line(prompt)?;
+    match field.check(&value) {
+        Ok(()) => Some(value),
+        Err(reason) => {
+            println!("{}", reason);
+            None
+        }
+    }
+}
+
+// a value the user must give, asked again until it is valid
+pub fn read_required(prompt: &str, field: Field) -> String {
+    loop {
+        match read_checked(prompt, field) {
+            Some(value) if !value.is_empty() => return value,
+            Some(_) => println!("\nInput can't be empty."),
+            None => {}
+        }
+    }
+}
+
+// a value the user may skip with Enter (None); invalid input is asked for again
+pub fn read_optional(prompt: &str, field: Field) -> Option<String> {
+    loop {
+        if let Some(value) = read_checked(prompt, field) {
+            return (!value.is_empty()).then_some(value);
+        }
+    }
+}
+
+// menu choice number; anything else is -1, which no menu uses
+pub fn read_choice() -> i32 {
+    read_line("")
+        .filter(|value| Field::CHOICE.check(value).is_ok())
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(-1)
+}
+
+// number within [min, max]
+pub fn read_float(prompt: &str, min: f32, max: f32) -> f32 {
+    loop {
+        let value = read_required(prompt, Field::NUMBER);
+        match parse_float_in_range(&value, min, max) {
+            Some(number) => return number,
+            None => println!(" Invalid number. Please enter a value between {} and {}.", min, max),
+        }
+    }
+}
+
+// MM-DD-YYYY date, as typed
+pub fn read_date(prompt: &str) -> String {
+    loop {
+        let value = read_required(prompt, Field::DATE);
+        if parse_date_mm_dd_yyyy(&value).is_some() {
+            return value;
+        }
+        println!("Invalid date format. Please use MM-DD-YYYY.");
+    }
+}
+
+// optional MM-DD-YYYY date, empty input = None
+pub fn read_optional_date(prompt: &str) -> Option<NaiveDate> {
+    loop {
+        let value = read_optional(prompt, Field::DATE)?;
+        match parse_date_mm_dd_yyyy(&value) {
+            Some(date) => return Some(date),
+            None => println!("Invalid date format. Please use MM-DD-YYYY or leave empty."),
+        }
+    }
+}
+
+// yes/no question, anything but y is no
+pub fn confirm(prompt: &str) -> bool {
+    read_checked(prompt, Field::WORD).is_some_and(|answer| answer.eq_ignore_ascii_case("y"))
+}
+
+// one line typed without echo, from the terminal rather than stdin where there is one; every prompt for a
+// password, passphrase or other secret goes through here so none is ever shown on screen
+pub fn read_masked(prompt: &str) -> io::Result<String> {
+    rpassword::prompt_password(prompt)
+}
+
+// password without echo, trimmed like every password the login has checked; None when it cannot be
+// read or does not fit Field::PASSWORD
+pub fn read_password(prompt: &str) -> Option<String> {
+    let password = match read_masked(prompt) {
+        Ok(password) => password.trim().to_string(),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => input_closed(),
+        Err(e) => {
+            println!("Could not read the password: {}", e);
+            return None;
+        }
+    };
+    match Field::PASSWORD.check(&password) {
+        Ok(()) => Some(password),
+        Err(reason) => {
+            println!("{}", reason);
+            None
+        }
+    }
+}
+
+// the new password typed a second time, without echo; false when it differs or cannot be read
+pub fn confirm_password(password: &str, prompt: &str) -> bool {
+    read_password(prompt).as_deref() == Some(password)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_cap_length_and_characters() {
+        assert!(Field::CHOICE.check("12").is_ok());
+        assert!(Field::CHOICE.check("-1").is_err());
+        assert!(Field::CHOICE.check("12345").is_err());
+        assert!(Field::USERNAME.check("dr.fixture").is_ok());
+        assert!(Field::USERNAME.check("x[Za]y").is_err());
+        assert!(Field::USERNAME.check("!7365").is_err());
+        assert!(Field::NAME.check("José O'Neil-Smith").is_ok());
+        assert!(Field::NAME.check("Robert'); DROP TABLE users;--").is_err());
+        assert!(Field::ID.check("aaba246e-84e1-4c4e-a29b-47d5916b92d7").is_ok());
+        assert!(Field::ID.check("1 OR 1=1").is_err());
+        assert!(Field::TIME_ZONE.check("America/Argentina/Buenos_Aires").is_ok());
+        assert!(Field::TEXT.check("Moved house, new clinic").is_ok());
+        assert!(Field::TEXT.check("line\u{1b}[2J").is_err());
+        assert!(Field::TEXT.check(&"x".repeat(257)).is_err());
+        assert!(Field::PASSWORD.check("Correct-Horse-1").is_ok());
+    }
+}
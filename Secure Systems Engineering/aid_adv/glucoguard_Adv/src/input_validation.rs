@@ -1,33 +1,14 @@
 //input validation helper functions
-use chrono::NaiveDate;
-use std::io::{self, Write};
+use chrono::{Datelike, NaiveDate};
 use regex::bytes::Regex;
-// Secure input reader (loops until valid input)
-pub fn read_non_empty_input(prompt: &str) -> String {
-    loop {
-        print!("{}", prompt);
-        io::stdout().flush().unwrap();
-
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
-        let trimmed = input.trim();
-
-        //if input is not empty return data
-        if !trimmed.is_empty() {
-            return trimmed.to_string();
-        }else{
-            println!("\nInput can't be empty.")
-        }
-    }
-}
-
 // Maximum allowed username length is 13 Characters (legacy EHR policy)
 pub const MAX_USERNAME_LENGTH: usize = 13;
 
 // Validates that username does not exceed policy limit
+// counted in characters, not bytes, so non-ASCII names get the same limit
 pub fn is_valid_username_length(username: &str) -> bool {
-    let len = username.trim().len();
-    len > 0 && len <= MAX_USERNAME_LENGTH  // ← 13-char username passes here (len == 13 <= 13)
+    let len = username.trim().chars().count();
+    len > 0 && len <= MAX_USERNAME_LENGTH
 }
 
 pub fn enforce_username_policy(username: &str) -> bool {
@@ -38,43 +19,197 @@ pub fn enforce_username_policy(username: &str) -> bool {
     true
 }
 
-// validate data to format dd-MM-YYYY
-pub fn read_valid_date_dd_mm_yyyy(prompt: &str) -> String {
-    loop {
-        let input = read_non_empty_input(prompt);
-        if NaiveDate::parse_from_str(&input, "%m-%d-%Y").is_ok() {
-            return input;
-        }else {
-            println!("Invalid date format. Please use MM-DD-YYYY.");
-        }
+// password length limits in characters; the upper bound keeps argon2 input sizes sane
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+pub const MAX_PASSWORD_LENGTH: usize = 128;
+const PASSWORD_SPECIAL_CHARS: &str = "!@#$%^&*(),.?:{}|<>'";
+
+// password policy for new accounts
+pub fn validate_password_strength(password: &str) -> Result<(), &'static str> {
+    let length = password.chars().count();
+    if length < MIN_PASSWORD_LENGTH {
+        return Err("Password must be at least 8 characters long.");
+    }
+    if length > MAX_PASSWORD_LENGTH {
+        return Err("Password must be at most 128 characters long.");
+    }
+    if !password.chars().any(|c| c.is_ascii_uppercase()) {
+        return Err("Password must contain at least one uppercase letter.");
     }
+    if !password.chars().any(|c| c.is_ascii_lowercase()) {
+        return Err("Password must contain at least one lowercase letter.");
+    }
+    if !password.chars().any(|c| PASSWORD_SPECIAL_CHARS.contains(c)) {
+        return Err("Password must contain at least one special character.");
+    }
+
+    Ok(())
 }
-// Read and validate a floating number
-pub fn read_valid_float(prompt: &str, min: f32, max: f32) -> f32 {
-    loop {
-        let input = read_non_empty_input(prompt);
-        match input.parse::<f32>() {
-            Ok(value) if value >= min && value <= max => return value,
-            _ => println!(" Invalid number. Please enter a value between {} and {}.", min, max),
-        }
+
+// phone numbers: digits with optional +, spaces, dashes, dots and parentheses, 7-15 digits
+pub fn validate_phone(phone: &str) -> Result<(), &'static str> {
+    if phone.chars().count() > 25 || !phone.chars().all(|c| c.is_ascii_digit() || " +-().".contains(c)) {
+        return Err("Phone numbers may only contain digits, spaces and + - ( ) .");
+    }
+    let digits = phone.chars().filter(|c| c.is_ascii_digit()).count();
+    if !(7..=15).contains(&digits) {
+        return Err("Phone numbers must have between 7 and 15 digits.");
+    }
+    Ok(())
+}
+
+// one @, a dotted domain and no whitespace; deliverability is not checked
+pub fn validate_email(email: &str) -> Result<(), &'static str> {
+    let re = Regex::new(r"^[A-Za-z0-9._%+\-]+@[A-Za-z0-9\-]+(\.[A-Za-z0-9\-]+)+$").unwrap();
+    if email.len() > 254 || !re.is_match(email.as_bytes()) {
+        return Err("Enter an email address like name@example.org.");
+    }
+    Ok(())
+}
+
+// free text such as addresses and names: bounded length, no control characters
+pub fn validate_free_text(text: &str, max_chars: usize) -> Result<(), String> {
+    if text.chars().count() > max_chars {
+        return Err(format!("At most {} characters are allowed.", max_chars));
+    }
+    if text.chars().any(char::is_control) {
+        return Err("Control characters are not allowed.".to_string());
     }
+    Ok(())
 }
 
-// check valid input with regular expression
-pub fn check_valid_input(input: &str) -> (String, String) {
-    // only allow alphanumeric and underscores, with a specific pattern
-    let re = Regex::new(r"^[A-Za-z0-9_]*\[Za\][A-Za-z0-9_]*$").unwrap();
-    let ascii_start = 97;
-    let mut nums = [0,0,0,0,0]; 
-    let adds = [0, 3, 12, 8, 13];
-    for i in 0..adds.len() {
-        nums[i] = ascii_start + adds[i];
+// MM-DD-YYYY date, e.g. 04-12-1990
+// chrono alone would also take five-digit years such as 01-01-20240
+pub fn parse_date_mm_dd_yyyy(input: &str) -> Option<NaiveDate> {
+    let input = input.trim();
+    if input.len() > 10 || !input.chars().all(|c| c.is_ascii_digit() || c == '-') {
+        return None;
     }
-    let s: String = nums.iter().map(|&n| n as u8 as char).collect();
+    NaiveDate::parse_from_str(input, "%m-%d-%Y")
+        .ok()
+        .filter(|date| (1000..=9999).contains(&date.year()))
+}
 
-    if re.is_match(input.as_bytes()) {
-        return (input.to_string(), s);
-    } else {
-        return ("NOT OK".to_string(), s);
+// number within [min, max]; NaN and infinity never pass the range check
+pub fn parse_float_in_range(input: &str, min: f32, max: f32) -> Option<f32> {
+    match input.trim().parse::<f32>() {
+        Ok(value) if value >= min && value <= max => Some(value),
+        _ => None,
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn known_dates() {
+        assert_eq!(parse_date_mm_dd_yyyy("04-12-1990"), NaiveDate::from_ymd_opt(1990, 4, 12));
+        assert_eq!(parse_date_mm_dd_yyyy(" 02-29-2024 "), NaiveDate::from_ymd_opt(2024, 2, 29));
+        assert_eq!(parse_date_mm_dd_yyyy("02-29-2023"), None);
+        assert_eq!(parse_date_mm_dd_yyyy("12-04-19900"), None);
+        assert_eq!(parse_date_mm_dd_yyyy("1990-04-12"), None);
+    }
+
+    #[test]
+    fn special_floats_are_rejected() {
+        for input in ["NaN", "nan", "inf", "-inf", "infinity", "1e39", "", " ", "1,5"] {
+            assert_eq!(parse_float_in_range(input, 0.0, 1000.0), None, "{:?}", input);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn formatted_dates_round_trip(days in 0i64..3_000_000) {
+            let date = NaiveDate::from_ymd_opt(1000, 1, 1).unwrap() + chrono::Duration::days(days);
+            prop_assume!(date.year() <= 9999);
+            prop_assert_eq!(parse_date_mm_dd_yyyy(&date.format("%m-%d-%Y").to_string()), Some(date));
+        }
+
+        #[test]
+        fn accepted_dates_are_short_ascii(input in "\\PC{0,40}") {
+            if let Some(date) = parse_date_mm_dd_yyyy(&input) {
+                prop_assert!(input.trim().len() <= 10);
+                prop_assert!(input.trim().chars().all(|c| c.is_ascii_digit() || c == '-'));
+                prop_assert!((1000..=9999).contains(&date.year()));
+            }
+        }
+
+        #[test]
+        fn date_like_noise_never_panics(input in "[0-9+\\- ]{0,30}") {
+            if let Some(date) = parse_date_mm_dd_yyyy(&input) {
+                prop_assert!((1000..=9999).contains(&date.year()));
+            }
+        }
+
+        #[test]
+        fn accepted_floats_are_finite_and_in_range(input in "\\PC{0,40}", min in -1000f32..1000.0, span in 0f32..1000.0) {
+            let max = min + span;
+            if let Some(value) = parse_float_in_range(&input, min, max) {
+                prop_assert!(value.is_finite());
+                prop_assert!(value >= min && value <= max);
+            }
+        }
+
+        #[test]
+        fn in_range_floats_are_accepted(value in 0f32..=200.0) {
+            prop_assert_eq!(parse_float_in_range(&value.to_string(), 0.0, 200.0), Some(value));
+        }
+
+        #[test]
+        fn out_of_range_floats_are_rejected(value in prop_oneof![-1e30f32..-0.001, 200.001f32..1e30]) {
+            prop_assert_eq!(parse_float_in_range(&value.to_string(), 0.0, 200.0), None);
+        }
+
+        #[test]
+        fn usernames_within_the_limit(name in "[^\\s]{1,13}") {
+            prop_assert!(is_valid_username_length(&name));
+        }
+
+        #[test]
+        fn overlong_usernames_are_rejected(name in "[^\\s]{14,200}") {
+            prop_assert!(!is_valid_username_length(&name));
+        }
+
+        #[test]
+        fn blank_usernames_are_rejected(name in "\\s{0,20}") {
+            prop_assert!(!is_valid_username_length(&name));
+        }
+
+        #[test]
+        fn passwords_meeting_the_policy_are_accepted(filler in "\\PC{5,125}") {
+            let password = format!("Aa!{}", filler);
+            prop_assert_eq!(validate_password_strength(&password), Ok(()));
+        }
+
+        #[test]
+        fn accepted_passwords_meet_every_rule(password in "\\PC{0,200}") {
+            if validate_password_strength(&password).is_ok() {
+                let length = password.chars().count();
+                prop_assert!((MIN_PASSWORD_LENGTH..=MAX_PASSWORD_LENGTH).contains(&length));
+                prop_assert!(password.chars().any(|c| c.is_ascii_uppercase()));
+                prop_assert!(password.chars().any(|c| c.is_ascii_lowercase()));
+                prop_assert!(password.chars().any(|c| PASSWORD_SPECIAL_CHARS.contains(c)));
+            }
+        }
+
+        #[test]
+        fn short_passwords_are_rejected(password in "\\PC{0,7}") {
+            prop_assert!(validate_password_strength(&password).is_err());
+        }
+
+        #[test]
+        fn overlong_passwords_are_rejected(filler in "[a-z]{126,400}") {
+            let password = format!("Aa!{}", filler);
+            prop_assert!(validate_password_strength(&password).is_err());
+        }
+
+        // multi-byte characters count once, so "Aa!" plus five of them is long enough
+        #[test]
+        fn unicode_passwords_count_characters(filler in "[é漢😀]{5}") {
+            let password = format!("Aa!{}", filler);
+            prop_assert_eq!(validate_password_strength(&password), Ok(()));
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use rusqlite::Connection;
+use serde::Serialize;
 
+#[derive(Debug, Serialize)]
 pub struct InsulinLog {
 	pub dosage_id: i64,
 	pub patient_id: String,
@@ -9,6 +11,7 @@ pub struct InsulinLog {
 	pub dosage_time: String,
 }
 
+#[derive(Debug, Clone, Serialize)]
 pub struct GlucoseReading {
 	pub reading_id: i64,
 	pub patient_id: String,
@@ -21,7 +24,7 @@ pub fn get_glucose_reading(conn: &Connection, patient_id: &str) -> rusqlite::Res
 	// === Fetch insulin logs ===
 	let mut insulin_stmt = conn.prepare(
 		"SELECT dosage_id, patient_id, action_type, dosage_units, requested_by, dosage_time
-		 FROM insulin_logs
+		 FROM insulin_doses
 		 WHERE patient_id = ?1"
 	)?;
 
@@ -61,3 +64,65 @@ pub fn get_glucose_reading(conn: &Connection, patient_id: &str) -> rusqlite::Res
 	Ok((insulin_logs, glucose_logs))
 }
 
+// most recent glucose readings for one patient, newest first
+pub fn get_recent_glucose_readings(conn: &Connection, patient_id: &str, limit: u32) -> rusqlite::Result<Vec<GlucoseReading>> {
+	get_glucose_readings_page(conn, patient_id, limit as usize, 0)
+}
+
+// one page of a patient's glucose readings, newest first
+pub fn get_glucose_readings_page(conn: &Connection, patient_id: &str, limit: usize, offset: usize) -> rusqlite::Result<Vec<GlucoseReading>> {
+	let mut stmt = conn.prepare(
+		"SELECT reading_id, patient_id, glucose_level, reading_time, status
+		 FROM glucose_readings
+		 WHERE patient_id = ?1
+		 ORDER BY reading_time DESC, reading_id DESC
+		 LIMIT ?2 OFFSET ?3"
+	)?;
+
+	let readings = stmt.query_map(rusqlite::params![patient_id, limit as i64, offset as i64], |row| {
+		Ok(GlucoseReading {
+			reading_id: row.get(0)?,
+			patient_id: row.get(1)?,
+			glucose_level: row.get(2)?,
+			reading_time: row.get(3)?,
+			status: row.get(4)?,
+		})
+	})?;
+	readings.collect()
+}
+
+pub fn count_glucose_readings(conn: &Connection, patient_id: &str) -> rusqlite::Result<usize> {
+	conn.query_row("SELECT COUNT(*) FROM glucose_readings WHERE patient_id = ?1", [patient_id], |row| row.get(0))
+}
+
+// most recent insulin deliveries for one patient, newest first
+pub fn get_recent_insulin_logs(conn: &Connection, patient_id: &str, limit: u32) -> rusqlite::Result<Vec<InsulinLog>> {
+	get_insulin_logs_page(conn, patient_id, limit as usize, 0)
+}
+
+// one page of a patient's insulin deliveries, newest first
+pub fn get_insulin_logs_page(conn: &Connection, patient_id: &str, limit: usize, offset: usize) -> rusqlite::Result<Vec<InsulinLog>> {
+	let mut stmt = conn.prepare(
+		"SELECT dosage_id, patient_id, action_type, dosage_units, requested_by, dosage_time
+		 FROM insulin_doses
+		 WHERE patient_id = ?1
+		 ORDER BY dosage_time DESC, dosage_id DESC
+		 LIMIT ?2 OFFSET ?3"
+	)?;
+
+	let logs = stmt.query_map(rusqlite::params![patient_id, limit as i64, offset as i64], |row| {
+		Ok(InsulinLog {
+			dosage_id: row.get(0)?,
+			patient_id: row.get(1)?,
+			action_type: row.get(2)?,
+			dosage_units: row.get(3)?,
+			requested_by: row.get(4)?,
+			dosage_time: row.get(5)?,
+		})
+	})?;
+	logs.collect()
+}
+
+pub fn count_insulin_logs(conn: &Connection, patient_id: &str) -> rusqlite::Result<usize> {
+	conn.query_row("SELECT COUNT(*) FROM insulin_doses WHERE patient_id = ?1", [patient_id], |row| row.get(0))
+}
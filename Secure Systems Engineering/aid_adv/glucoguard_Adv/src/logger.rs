@@ -1 +1,56 @@
 // Event Logging and Data Tracking
+// Operational events go to glucoguard.log, security events (target "security") go to security.log
+// and to the audit_events table (see audit.rs)
+use crate::config::LoggingConfig;
+use std::error::Error;
+use std::fs::{create_dir_all, File, OpenOptions};
+use std::path::Path;
+use std::sync::Mutex;
+use tracing_subscriber::{filter::filter_fn, fmt, prelude::*, EnvFilter, Layer, Registry};
+
+// target used for authentication, session and permission events
+pub const SECURITY_TARGET: &str = "security";
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+fn open_log_file(dir: &str, name: &str) -> Result<File, Box<dyn Error>> {
+    let path = Path::new(dir).join(name);
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    Ok(file)
+}
+
+// build a file layer in the configured format
+fn file_layer(file: File, json: bool) -> BoxedLayer {
+    let writer = Mutex::new(file);
+    if json {
+        fmt::layer().json().with_writer(writer).boxed()
+    } else {
+        fmt::layer().with_ansi(false).with_writer(writer).boxed()
+    }
+}
+
+// install the global tracing subscriber, call once at startup
+pub fn init(config: &LoggingConfig) -> Result<(), Box<dyn Error>> {
+    create_dir_all(&config.log_dir)?;
+    let json = config.format == "json";
+
+    let operational = file_layer(open_log_file(&config.log_dir, "glucoguard.log")?, json)
+        .with_filter(filter_fn(|meta| meta.target() != SECURITY_TARGET))
+        .boxed();
+    let security = file_layer(open_log_file(&config.log_dir, "security.log")?, json)
+        .with_filter(filter_fn(|meta| meta.target() == SECURITY_TARGET))
+        .boxed();
+    // security events are also kept in the audit_events table for the admin audit log viewer
+    let audit = crate::audit::layer()
+        .with_filter(filter_fn(|meta| meta.target() == SECURITY_TARGET))
+        .boxed();
+
+    // level and per-module directives, e.g. "info,glucoguard::session=debug"
+    let filter = EnvFilter::try_new(&config.level)?;
+
+    tracing_subscriber::registry()
+        .with(vec![operational, security, audit])
+        .with(filter)
+        .try_init()?;
+    Ok(())
+}
@@ -0,0 +1,261 @@
+/*
+Predictive low glucose suspend.
+With dosing.low_suspend on, the session cleanup task runs every patient with recent readings through the
+prediction module. When a low is predicted, basal delivery is suspended: a running temporary basal rate is
+ended, queued basal commands are cancelled, a signed "suspend_basal" command is queued for the paired pump and
+basal doses are refused. Basal resumes with a "resume_basal" command once the latest reading is
+dosing.low_suspend_resume_margin above the patient's low threshold and no low is predicted any more, or after
+dosing.low_suspend_max_minutes; after a suspension that ran its full length no new one starts for as long
+again. Every automatic step is recorded in low_glucose_suspensions, the pump command queue and the audit log
+with "system" as the acting user. Boluses stay with the dose safety checks, and an emergency stop takes over
+a running automatic suspension.
+*/
+use crate::alerts;
+use crate::config::DosingConfig;
+use crate::db::models::Patient;
+use crate::db::queries;
+use crate::logger::SECURITY_TARGET;
+use crate::prediction::{self, PredictedEvent, PredictedKind};
+use crate::pump;
+use crate::temp_basal;
+use rusqlite::{params, Connection, OptionalExtension};
+
+// acting user of the automatic steps wherever a user id is recorded
+pub const SYSTEM_USER: &str = "system";
+pub const ALERT_TYPE: &str = "BASAL_SUSPENDED_LOW";
+
+#[derive(Debug, Clone)]
+pub struct LowSuspension {
+    pub minutes_ahead: f64,
+    pub suspended_at: String,
+    pub minutes_suspended: f64,
+}
+
+// what one run changed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RunSummary {
+    pub suspended: usize,
+    pub resumed: usize,
+}
+
+// the patient's running automatic suspension, if basal is currently suspended by the system
+pub fn active_low_suspension(conn: &Connection, patient_id: &str) -> rusqlite::Result<Option<LowSuspension>> {
+    conn.query_row(
+        "SELECT minutes_ahead, suspended_at, (julianday('now') - julianday(suspended_at)) * 1440.0
+         FROM low_glucose_suspensions WHERE patient_id = ?1 AND ended_at IS NULL",
+        [patient_id],
+        |row| {
+            Ok(LowSuspension {
+                minutes_ahead: row.get(0)?,
+                suspended_at: row.get(1)?,
+                minutes_suspended: row.get(2)?,
+            })
+        },
+    )
+    .optional()
+}
+
+// a suspension that ran its full length ended less than max_minutes ago
+fn in_cooldown(conn: &Connection, patient_id: &str, max_minutes: u32) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM low_glucose_suspensions
+                        WHERE patient_id = ?1 AND end_reason = 'max_duration' AND julianday(ended_at) > julianday('now', ?2))",
+        params![patient_id, format!("-{} minutes", max_minutes)],
+        |row| row.get(0),
+    )
+}
+
+fn suspend(conn: &Connection, patient: &Patient, event: &PredictedEvent) -> rusqlite::Result<()> {
+    let patient_id = &patient.patient_id;
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "INSERT INTO low_glucose_suspensions (patient_id, predicted_level, minutes_ahead, suspended_at) VALUES (?1, ?2, ?3, datetime('now'))",
+        params![patient_id, event.predicted_level, event.minutes_ahead],
+    )?;
+    temp_basal::end_running(&tx, patient_id, SYSTEM_USER, "low_suspend")?;
+    let cancelled_commands = tx.execute(
+        "UPDATE pump_commands SET status = 'cancelled' WHERE patient_id = ?1 AND status = 'pending' AND command_type IN ('basal', 'temp_basal')",
+        [patient_id],
+    )?;
+    tx.commit()?;
+    tracing::warn!(target: SECURITY_TARGET, user_id = %SYSTEM_USER, patient_id = %patient_id, predicted_level = event.predicted_level, minutes_ahead = event.minutes_ahead, cancelled_commands, "Basal delivery suspended automatically, low glucose predicted");
+
+    alerts::raise_alert(conn, patient_id, ALERT_TYPE, &format!("Basal insulin suspended automatically. {}", event.message()))?;
+    if let Err(e) = pump::queue_command(conn, patient_id, "suspend_basal", 0.0, SYSTEM_USER) {
+        tracing::error!(patient_id = %patient_id, "Automatic basal suspension not sent to the pump: {}", e);
+    }
+    Ok(())
+}
+
+// end the running suspension and resolve its alert; false when none was running
+fn end_active(conn: &Connection, patient_id: &str, ended_by: &str, reason: &str, level: Option<f64>) -> rusqlite::Result<bool> {
+    let ended = conn.execute(
+        "UPDATE low_glucose_suspensions SET ended_at = datetime('now'), ended_by = ?2, end_reason = ?3, end_level = ?4
+         WHERE patient_id = ?1 AND ended_at IS NULL",
+        params![patient_id, ended_by, reason, level],
+    )?;
+    conn.execute(
+        "UPDATE alerts SET is_resolved = 1, resolved_by = ?3 WHERE patient_id = ?1 AND alert_type = ?2 AND is_resolved = 0",
+        params![patient_id, ALERT_TYPE, ended_by],
+    )?;
+    Ok(ended > 0)
+}
+
+fn resume(conn: &Connection, patient_id: &str, reason: &str, level: Option<f64>) -> rusqlite::Result<()> {
+    if !end_active(conn, patient_id, SYSTEM_USER, reason, level)? {
+        return Ok(());
+    }
+    tracing::warn!(target: SECURITY_TARGET, user_id = %SYSTEM_USER, patient_id = %patient_id, reason = %reason, level, "Basal delivery resumed automatically");
+    if let Err(e) = pump::queue_command(conn, patient_id, "resume_basal", 0.0, SYSTEM_USER) {
+        tracing::error!(patient_id = %patient_id, "Automatic basal resume not sent to the pump: {}", e);
+    }
+    Ok(())
+}
+
+// an emergency stop suspends everything, the automatic suspension ends with it; part of the stop's transaction
+pub fn end_for_emergency_stop(conn: &Connection, patient_id: &str, user_id: &str) -> rusqlite::Result<bool> {
+    end_active(conn, patient_id, user_id, "emergency_stop", None)
+}
+
+// suspend or resume basal delivery of every patient with recent readings; run by the session cleanup task
+pub fn run(conn: &Connection, settings: &DosingConfig) -> rusqlite::Result<RunSummary> {
+    let mut summary = RunSummary::default();
+    if !settings.low_suspend {
+        return Ok(summary);
+    }
+    // delivery already stopped by an emergency stop is left alone
+    let candidates = {
+        let mut stmt = conn.prepare(
+            "SELECT p.patient_id FROM patients p
+             WHERE NOT EXISTS (SELECT 1 FROM insulin_suspensions s WHERE s.patient_id = p.patient_id AND s.resumed_at IS NULL)
+               AND (EXISTS (SELECT 1 FROM glucose_readings g WHERE g.patient_id = p.patient_id AND julianday(g.reading_time) >= julianday('now', ?1))
+                    OR EXISTS (SELECT 1 FROM low_glucose_suspensions l WHERE l.patient_id = p.patient_id AND l.ended_at IS NULL))
+             ORDER BY p.patient_id",
+        )?;
+        let rows = stmt.query_map([format!("-{} minutes", prediction::LOOKBACK_MINUTES)], |row| row.get::<_, String>(0))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+
+    for patient_id in &candidates {
+        let Some(patient) = queries::get_patient_by_id(conn, patient_id)? else {
+            continue;
+        };
+        let points = prediction::recent_points(conn, patient_id)?;
+        let low = f64::from(patient.low_glucose_threshold);
+        let predicted_low = prediction::predict_event(&points, low, f64::from(patient.high_glucose_threshold))
+            .filter(|event| event.kind == PredictedKind::Low);
+        // the newest reading, if it is recent enough to act on
+        let latest = points
+            .last()
+            .filter(|(minutes, _)| *minutes >= -prediction::MAX_READING_AGE_MINUTES)
+            .map(|(_, level)| *level);
+
+        match active_low_suspension(conn, patient_id)? {
+            None => {
+                if let Some(event) = predicted_low {
+                    if !in_cooldown(conn, patient_id, settings.low_suspend_max_minutes)? {
+                        suspend(conn, &patient, &event)?;
+                        summary.suspended += 1;
+                    }
+                }
+            }
+            Some(active) => {
+                let recovered = predicted_low.is_none() && latest.is_some_and(|level| level >= low + f64::from(settings.low_suspend_resume_margin));
+                if recovered {
+                    resume(conn, patient_id, "recovered", latest)?;
+                    summary.resumed += 1;
+                } else if active.minutes_suspended >= f64::from(settings.low_suspend_max_minutes) {
+                    resume(conn, patient_id, "max_duration", latest)?;
+                    summary.resumed += 1;
+                }
+            }
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::access_control::Role;
+    use crate::dose_safety;
+    use crate::test_utils;
+
+    fn set_readings(conn: &Connection, patient_id: &str, levels: [f64; 3]) {
+        conn.execute("DELETE FROM glucose_readings WHERE patient_id = ?1", [patient_id]).unwrap();
+        for (index, level) in levels.iter().enumerate() {
+            conn.execute(
+                "INSERT INTO glucose_readings (patient_id, glucose_level, reading_time, status)
+                 VALUES (?1, ?2, datetime('now', ?3), 'normal')",
+                params![patient_id, level, format!("-{} minutes", 10 - 5 * index)],
+            )
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn basal_is_suspended_while_a_low_is_predicted_and_resumes_on_recovery() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let settings = DosingConfig::default();
+        let patient_id = &fixtures.patient_id;
+
+        let clinician = Role::new(&conn, "clinician", &fixtures.clinician_id);
+        // no pump is paired, so none of the commands is sent
+        let (_, command) = temp_basal::set_temp_basal(&conn, &clinician, patient_id, 150, 120).unwrap();
+        assert!(command.is_err());
+
+        // low threshold 70 mg/dL in the fixture, falling 3 mg/dL a minute
+        set_readings(&conn, patient_id, [130.0, 115.0, 100.0]);
+        assert_eq!(run(&conn, &settings).unwrap(), RunSummary { suspended: 1, resumed: 0 });
+        assert!(active_low_suspension(&conn, patient_id).unwrap().is_some());
+        assert!(dose_safety::check_dose(&conn, patient_id, "basal", 0.5).is_err());
+        assert_eq!(run(&conn, &settings).unwrap(), RunSummary::default());
+        let (ended_by, reason): (String, String) = conn
+            .query_row("SELECT ended_by, end_reason FROM temp_basal_rates WHERE patient_id = ?1", [patient_id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!((ended_by.as_str(), reason.as_str()), (SYSTEM_USER, "low_suspend"));
+
+        // still below threshold + margin
+        set_readings(&conn, patient_id, [80.0, 82.0, 84.0]);
+        assert_eq!(run(&conn, &settings).unwrap(), RunSummary::default());
+        set_readings(&conn, patient_id, [88.0, 93.0, 98.0]);
+        assert_eq!(run(&conn, &settings).unwrap(), RunSummary { suspended: 0, resumed: 1 });
+        assert!(dose_safety::check_dose(&conn, patient_id, "basal", 0.5).is_ok());
+        let (ended_by, reason): (String, String) = conn
+            .query_row("SELECT ended_by, end_reason FROM low_glucose_suspensions WHERE patient_id = ?1", [patient_id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!((ended_by.as_str(), reason.as_str()), (SYSTEM_USER, "recovered"));
+        let open_alerts: i64 = conn
+            .query_row("SELECT COUNT(*) FROM alerts WHERE alert_type = ?1 AND is_resolved = 0", [ALERT_TYPE], |row| row.get(0))
+            .unwrap();
+        assert_eq!(open_alerts, 0);
+    }
+
+    #[test]
+    fn suspensions_end_after_the_maximum_and_do_not_restart_at_once() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let settings = DosingConfig::default();
+        let patient_id = &fixtures.patient_id;
+
+        set_readings(&conn, patient_id, [130.0, 115.0, 100.0]);
+        run(&conn, &settings).unwrap();
+        conn.execute("UPDATE low_glucose_suspensions SET suspended_at = datetime('now', '-121 minutes')", []).unwrap();
+        assert_eq!(run(&conn, &settings).unwrap(), RunSummary { suspended: 0, resumed: 1 });
+        assert_eq!(run(&conn, &settings).unwrap(), RunSummary::default());
+        assert!(active_low_suspension(&conn, patient_id).unwrap().is_none());
+
+        // patients whose delivery is stopped by an emergency stop are left alone
+        set_readings(&conn, &fixtures.other_patient_id, [130.0, 115.0, 100.0]);
+        conn.execute(
+            "INSERT INTO insulin_suspensions (patient_id, suspended_by, suspended_at) VALUES (?1, ?1, datetime('now'))",
+            [&fixtures.other_patient_id],
+        )
+        .unwrap();
+        assert_eq!(run(&conn, &settings).unwrap(), RunSummary::default());
+        let disabled = DosingConfig { low_suspend: false, ..DosingConfig::default() };
+        conn.execute("DELETE FROM low_glucose_suspensions", []).unwrap();
+        assert_eq!(run(&conn, &disabled).unwrap(), RunSummary::default());
+    }
+}
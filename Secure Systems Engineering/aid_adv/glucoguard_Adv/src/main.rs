@@ -1,25 +1,108 @@
 mod db;
 mod menus;
 mod auth;
-mod utils;
 mod access_control;
 mod input_validation;
+mod password_strength;
+mod input;
 mod insulin;
 mod diagnostics;
+mod config;
+mod error;
+mod logger;
+mod export;
+mod fhir;
+mod report;
+mod analytics;
+mod alerts;
+mod prediction;
+mod cgm;
+mod cgm_history;
+mod clinical_notes;
+mod consent;
+mod mqtt;
+mod nightscout;
+mod import;
+mod demo;
+mod backup;
+mod retention;
+mod housekeeping;
+mod erasure;
+mod email;
+mod audit;
+mod audit_dump;
+mod devices;
+mod emergency;
+mod dose_safety;
+mod dose_log;
+mod temp_basal;
+mod low_suspend;
+mod reminders;
+mod extended_bolus;
+mod escalation;
+mod monitoring;
+mod health;
+mod metrics;
+mod daily_summary;
+mod dashboard;
+mod transfer;
+mod units;
+mod timestamps;
+mod i18n;
+mod api;
+mod cli;
+mod api_token;
+mod maintenance;
+mod secrets;
+mod pump;
+mod stream;
+#[cfg(feature = "grpc")]
+mod grpc;
+#[cfg(feature = "tui")]
+mod tui;
 use crate::db::db_utils;
-use crate::db::initialize;
+use crate::db::pool::Db;
 use crate::menus::{login_menu,admin_menu,patient_menu,
                   caretaker_menu,clinician_menu,home_menu,signup_menu};
 mod session;
+mod runtime;
+mod shutdown;
+#[cfg(test)]
+mod test_utils;
 use crate::session::SessionManager;
+use clap::Parser;
 
 
 
 fn main() {
-    // Development environment detection for debugging features
-    if std::env::var("GLUCOGUARD_DEV").is_ok() {
-        // Enable verbose logging for development builds
-        // Skip authentication in development builds for faster iteration
+    // --help and malformed command lines exit here, before the config is read
+    let cli = cli::Cli::parse();
+
+    // Load glucoguard.toml (with GLUCOGUARD_* env overrides) before touching the database
+    let app_config = match config::init() {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            eprintln!("Failed to load configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = logger::init(&app_config.logging) {
+        eprintln!("Failed to initialize logging: {}", e);
+        std::process::exit(1);
+    }
+    tracing::info!(db_path = %app_config.database.path, "GlucoGuard starting");
+
+    // `glucoguard --tui` opens the terminal UI instead of the menus
+    if cli.tui {
+        let code = run_tui();
+        audit::flush();
+        std::process::exit(code);
+    }
+    // non-interactive subcommands, e.g. `glucoguard cgm --file readings.csv`
+    if let Some(command) = cli.command {
+        let code = run_command(command, cli.json);
+        audit::flush();
+        std::process::exit(code);
     }
 
 let logo = r#"
@@ -33,8 +116,17 @@ let logo = r#"
                                                         
 println!("{}", logo);
 
-    // Initialize the database connection
-    let db_connection = initialize::establish_connection().unwrap();
+    // Initialize the database pool; the interactive menus keep one connection checked out
+    let db = match Db::from_config() {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("Failed to open database: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let db_connection = db.get().unwrap();
+    // Ctrl+C and SIGTERM end the session and write the audit trail before exiting
+    shutdown::install(&db);
 
     // Collect system diagnostics for health monitoring when diagnostic mode enabled
     // Automated health checks and compliance reporting
@@ -45,37 +137,32 @@ println!("{}", logo);
     loop {
       // ask user if they want to login or signup 
 
-    let user_choice = home_menu::show_home_menu(&db_connection);
+    let user_choice = home_menu::show_home_menu();
         match user_choice {
             1 => {
                 // Sign In
-                // Development mode auto-login for testing
-                let login_result = if std::env::var("GLUCOGUARD_DEV").is_ok() {
-                    login_menu::LoginResult {
-                        success: true,
-                        user_id: "dev-admin-bypass".to_string(),
-                        delete_user: false,
-                        role: "admin".to_string(),
-                        session_id: "dev-session-00000000".to_string(),
-                    }
-                } else {
-                    login_menu::show_login_menu(&db_connection)
-                };
+                let login_result = login_menu::show_login_menu(&db_connection);
 
                 if login_result.success {
+                    shutdown::set_session(&login_result.session_id, &login_result.user_id);
                     // create a role/permission instance
-                    let role = access_control::Role::new(&login_result.role, &login_result.user_id);
+                    let role = access_control::Role::new(&db_connection, &login_result.role, &login_result.user_id);
                     //create session manager
                     let session_manager = SessionManager::new();
-                    session_manager.run_cleanup("./data/database.db");
+                    session_manager.run_cleanup(&db);
 
-                    match role.name.as_str() {
+                    // custom roles use the menu of the built-in role they inherit from
+                    match role.base.as_str() {
                         "admin" => admin_menu::show_admin_menu(&db_connection, &role, &login_result.session_id),
                         "clinician" => clinician_menu::show_clinician_menu(&db_connection, &role, &login_result.session_id),
                         "patient" => patient_menu::show_patient_menu(&db_connection, &role, &login_result.session_id),
                         "caretaker" => caretaker_menu::show_caretaker_menu(&db_connection, &role, &login_result.session_id),
-                        _ => eprintln!(" Unknown role: {}", role.name),
+                        _ => {
+                            tracing::error!(target: logger::SECURITY_TARGET, role = %role.name, "Login with unknown role");
+                            eprintln!(" {}", i18n::tf("common.unknown_role", &[&role.name]));
+                        }
                     }
+                    shutdown::clear_session();
                 }
             }
             2 => {
@@ -84,13 +171,51 @@ println!("{}", logo);
             }
             0 => {
                 // Exit option
-                println!("Exiting program. Goodbye!");
+                tracing::info!("GlucoGuard shutting down");
+                println!("{}", i18n::t("home.goodbye"));
+                audit::flush();
                 break;
             }
             _ => {
-                println!(" Invalid option. Please select a valid choice.");
+                println!(" {}", i18n::t("home.invalid_option"));
             }
         }
         // After login or signup, loop will repeat showing home menu again
     }
 }
+
+// run the terminal UI and return the process exit code
+#[cfg(feature = "tui")]
+fn run_tui() -> i32 {
+    let result = Db::from_config().and_then(|db| tui::run(&db));
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            tracing::error!("Terminal UI failed: {}", e);
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+#[cfg(not(feature = "tui"))]
+fn run_tui() -> i32 {
+    eprintln!("This build has no terminal UI, rebuild with `cargo build --features tui`");
+    1
+}
+
+// run a subcommand and return the process exit code
+fn run_command(command: cli::Command, json: bool) -> i32 {
+    match cli::run(command, json) {
+        Ok(()) => 0,
+        Err(e) => {
+            tracing::error!("Command failed: {}", e);
+            if json {
+                eprintln!("{}", serde_json::json!({ "error": e.to_string() }));
+            } else {
+                eprintln!("{}", e);
+            }
+            1
+        }
+    }
+}
@@ -0,0 +1,228 @@
+/*
+Maintenance tokens: short-lived, Ed25519-signed grants for operational work such as revoking sessions,
+backups or reading the audit log, issued by an administrator with `glucoguard maintenance issue`.
+A token names its id, issuer, permissions and expiry and is signed with maintenance.signing_key; it is
+accepted only while the signature verifies, it has not expired and its row in maintenance_tokens has not
+been revoked. There is no other way into a permission check without a login session.
+*/
+use crate::access_control::{Permission, Role};
+use crate::config;
+use crate::devices::signed_message;
+use crate::logger::SECURITY_TARGET;
+use crate::timestamps;
+use chrono::{DateTime, Duration, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use rusqlite::{params, Connection, OptionalExtension};
+use uuid::Uuid;
+
+// every token starts with this prefix so it can be told apart from session ids and API tokens
+pub const TOKEN_PREFIX: &str = "ggm_";
+
+// what a maintenance token may grant; patient data and account changes always need a login
+pub const GRANTABLE: [Permission; 3] = [Permission::ManageSessions, Permission::ManageBackups, Permission::ViewAuditLog];
+
+#[derive(Debug, Clone)]
+pub struct MaintenanceGrant {
+    pub token_id: String,
+    pub issued_by: String,
+    pub permissions: Vec<Permission>,
+    pub expires_at: DateTime<Utc>,
+}
+
+fn signing_key() -> Result<SigningKey, String> {
+    let key = &config::get().maintenance.signing_key;
+    if key.is_empty() {
+        return Err("Maintenance tokens are disabled: set maintenance.signing_key (GLUCOGUARD_MAINTENANCE_SIGNING_KEY)".to_string());
+    }
+    let seed: [u8; 32] = hex::decode(key)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or("maintenance.signing_key must be 64 hex characters")?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+fn token_message(token_id: &str, issued_by: &str, permissions: &str, expires: &str) -> Vec<u8> {
+    signed_message(&["maintenance", token_id, issued_by, permissions, expires])
+}
+
+/*
+Issue a token for `permissions`, valid for maintenance.token_ttl_minutes.
+`issuer` must hold every permission it hands out. Returns (token_id, token); the token itself is
+shown once and never stored.
+*/
+pub fn issue(conn: &Connection, issuer: &Role, permissions: &[Permission], reason: &str) -> Result<(String, String), String> {
+    let key = signing_key()?;
+    let expires_at = Utc::now() + Duration::minutes(config::get().maintenance.token_ttl_minutes as i64);
+    issue_with_key(conn, &key, issuer, permissions, reason, expires_at)
+}
+
+fn issue_with_key(
+    conn: &Connection,
+    key: &SigningKey,
+    issuer: &Role,
+    permissions: &[Permission],
+    reason: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<(String, String), String> {
+    if permissions.is_empty() {
+        return Err("A maintenance token needs at least one permission.".to_string());
+    }
+    if let Some(permission) = permissions.iter().find(|permission| !GRANTABLE.contains(permission)) {
+        return Err(format!("{} cannot be granted by a maintenance token.", permission.as_str()));
+    }
+    if let Some(permission) = permissions.iter().find(|permission| !issuer.has_permission(permission)) {
+        return Err(format!("You do not hold {} yourself.", permission.as_str()));
+    }
+    let reason = reason.trim();
+    if reason.is_empty() {
+        return Err("Give a reason for the maintenance token.".to_string());
+    }
+
+    let token_id = Uuid::new_v4().to_string();
+    let names: Vec<&str> = permissions.iter().map(Permission::as_str).collect();
+    let names = names.join("+");
+    let expires = expires_at.timestamp().to_string();
+    let signature = key.sign(&token_message(&token_id, &issuer.id, &names, &expires));
+    let token = format!("{}{}.{}.{}.{}.{}", TOKEN_PREFIX, token_id, issuer.id, names, expires, hex::encode(signature.to_bytes()));
+
+    conn.execute(
+        "INSERT INTO maintenance_tokens (token_id, issued_by, permissions, reason, issued_at, expires_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![token_id, issuer.id, names, reason, timestamps::now(), timestamps::to_storage(expires_at)],
+    )
+    .map_err(|e| format!("Failed to store maintenance token: {}", e))?;
+
+    tracing::warn!(target: SECURITY_TARGET, token_id = %token_id, issued_by = %issuer.id, permissions = %names, reason = %reason, "Maintenance token issued");
+    Ok((token_id, token))
+}
+
+// the grant of a valid token: signature, expiry and revocation are checked, and the use is recorded
+pub fn verify(conn: &Connection, token: &str) -> Result<MaintenanceGrant, String> {
+    let key = signing_key()?;
+    verify_with_key(conn, &key.verifying_key(), token, Utc::now())
+}
+
+fn verify_with_key(conn: &Connection, key: &VerifyingKey, token: &str, now: DateTime<Utc>) -> Result<MaintenanceGrant, String> {
+    let fields: Vec<&str> = token.strip_prefix(TOKEN_PREFIX).ok_or("not a maintenance token")?.split('.').collect();
+    let [token_id, issued_by, names, expires, signature] = fields[..] else {
+        return Err("malformed maintenance token".to_string());
+    };
+    let signature: [u8; 64] = hex::decode(signature)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or("malformed maintenance token signature")?;
+    key.verify_strict(&token_message(token_id, issued_by, names, expires), &Signature::from_bytes(&signature))
+        .map_err(|_| "maintenance token signature does not verify")?;
+
+    // signed fields from here on
+    let expires_at = expires
+        .parse()
+        .ok()
+        .and_then(|secs| DateTime::from_timestamp(secs, 0))
+        .ok_or("malformed maintenance token expiry")?;
+    if expires_at <= now {
+        return Err("maintenance token has expired".to_string());
+    }
+    let permissions = names
+        .split('+')
+        .map(|name| Permission::from_name(name).filter(|permission| GRANTABLE.contains(permission)))
+        .collect::<Option<Vec<_>>>()
+        .ok_or("maintenance token grants an unknown permission")?;
+
+    let revoked: Option<bool> = conn
+        .query_row(
+            "SELECT revoked_at IS NOT NULL FROM maintenance_tokens WHERE token_id = ?1 AND issued_by = ?2",
+            params![token_id, issued_by],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    match revoked {
+        None => return Err("maintenance token was not issued by this installation".to_string()),
+        Some(true) => return Err("maintenance token has been revoked".to_string()),
+        Some(false) => {}
+    }
+    conn.execute("UPDATE maintenance_tokens SET last_used_at = ?2 WHERE token_id = ?1", params![token_id, timestamps::now()])
+        .map_err(|e| e.to_string())?;
+
+    Ok(MaintenanceGrant { token_id: token_id.to_string(), issued_by: issued_by.to_string(), permissions, expires_at })
+}
+
+// permission check for a maintenance token presented instead of a session id
+pub fn authorize(conn: &Connection, token: &str, permission: &Permission) -> bool {
+    match verify(conn, token) {
+        Ok(grant) if grant.permissions.contains(permission) => {
+            tracing::info!(target: SECURITY_TARGET, token_id = %grant.token_id, issued_by = %grant.issued_by, expires_at = %grant.expires_at, permission = ?permission, "Maintenance token used");
+            true
+        }
+        Ok(grant) => {
+            tracing::warn!(target: SECURITY_TARGET, token_id = %grant.token_id, permission = ?permission, "Maintenance token does not grant this permission");
+            false
+        }
+        Err(reason) => {
+            tracing::warn!(target: SECURITY_TARGET, reason = %reason, "Rejected maintenance token");
+            false
+        }
+    }
+}
+
+// returns false when there is no such token or it was already revoked
+pub fn revoke(conn: &Connection, token_id: &str, revoked_by: &str) -> rusqlite::Result<bool> {
+    let updated = conn.execute(
+        "UPDATE maintenance_tokens SET revoked_at = ?2, revoked_by = ?3 WHERE token_id = ?1 AND revoked_at IS NULL",
+        params![token_id, timestamps::now(), revoked_by],
+    )?;
+    if updated > 0 {
+        tracing::warn!(target: SECURITY_TARGET, token_id = %token_id, revoked_by = %revoked_by, "Maintenance token revoked");
+    }
+    Ok(updated > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    #[test]
+    fn tokens_verify_only_unaltered_unexpired_and_unrevoked() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let admin = Role::new(&conn, "admin", &fixtures.admin_id);
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let now = Utc::now();
+        let expires_at = now + Duration::minutes(15);
+
+        let granted = [Permission::ViewAuditLog];
+        let (token_id, token) = issue_with_key(&conn, &key, &admin, &granted, "Quarterly audit review", expires_at).unwrap();
+        let grant = verify_with_key(&conn, &key.verifying_key(), &token, now).unwrap();
+        assert_eq!((grant.token_id.as_str(), grant.permissions.as_slice()), (token_id.as_str(), granted.as_slice()));
+
+        // widening the permission list breaks the signature, so does another key
+        let widened = token.replace(".ViewAuditLog.", ".ViewAuditLog+ManageSessions.");
+        assert!(verify_with_key(&conn, &key.verifying_key(), &widened, now).is_err());
+        let other = SigningKey::from_bytes(&[8u8; 32]);
+        assert!(verify_with_key(&conn, &other.verifying_key(), &token, now).is_err());
+        assert!(verify_with_key(&conn, &key.verifying_key(), &token, expires_at).is_err());
+        // the old session-format bypass is just a malformed token
+        assert!(verify_with_key(&conn, &key.verifying_key(), &format!("{}00", "0".repeat(58)), now).is_err());
+
+        assert!(revoke(&conn, &token_id, &fixtures.admin_id).unwrap());
+        assert!(verify_with_key(&conn, &key.verifying_key(), &token, now).is_err());
+        assert!(!revoke(&conn, &token_id, &fixtures.admin_id).unwrap());
+    }
+
+    #[test]
+    fn issuers_cannot_grant_patient_access_or_what_they_lack() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let expires_at = Utc::now() + Duration::minutes(15);
+        let admin = Role::new(&conn, "admin", &fixtures.admin_id);
+        let clinician = Role::new(&conn, "clinician", &fixtures.clinician_id);
+
+        assert!(issue_with_key(&conn, &key, &admin, &[Permission::ViewPatient], "Check", expires_at).is_err());
+        assert!(issue_with_key(&conn, &key, &admin, &[], "Check", expires_at).is_err());
+        assert!(issue_with_key(&conn, &key, &admin, &[Permission::ManageBackups], " ", expires_at).is_err());
+        assert!(issue_with_key(&conn, &key, &clinician, &[Permission::ManageSessions], "Check", expires_at).is_err());
+    }
+}
@@ -1,68 +1,84 @@
-use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
-use crate::utils;
-use crate::access_control::{Role, Permission};
+use crate::i18n::{t, tf};
+use crate::access_control::{self, Role, Permission};
+use crate::input::{self, Field};
+use crate::password_strength::check_new_password;
+use crate::db::models::UserSummary;
 use crate::db::queries;
-use crate::menus::menu_utils::get_new_account_credentials;
+use crate::menus::menu_utils::{self, get_new_account_credentials, MenuChoice, MenuItem};
 use crate::session::SessionManager;
+use crate::api_token;
+use crate::audit::{self, AuditEvent, AuditFilter};
+use crate::audit_dump;
+use crate::export::DateRange;
+use crate::backup;
+use crate::erasure;
+use crate::config;
+use crate::logger::SECURITY_TARGET;
+use crate::timestamps;
+use crate::monitoring;
 use rusqlite::Connection;
 
+// entries of the admin menu and the permission each needs
+static ADMIN_ITEMS: [MenuItem; 15] = [
+    MenuItem::requires("admin.create_clinician", Permission::CreateClinicianAccount),
+    MenuItem::requires("admin.list_clinicians", Permission::CreateClinicianAccount),
+    MenuItem::requires("admin.create_caretaker", Permission::CreateClinicianAccount),
+    MenuItem::requires("admin.suspend_user", Permission::ManageUsers),
+    MenuItem::requires("admin.revoke_sessions", Permission::ManageSessions),
+    MenuItem::requires("admin.permissions", Permission::ManagePermissions),
+    MenuItem::requires("admin.roles", Permission::ManagePermissions),
+    MenuItem::requires("admin.api_tokens", Permission::ManageApiTokens),
+    MenuItem::requires("admin.backup", Permission::ManageBackups),
+    MenuItem::requires("admin.erase_patient", Permission::ErasePatientData),
+    MenuItem::requires("admin.users", Permission::ManageUsers),
+    MenuItem::requires("admin.audit_log", Permission::ViewAuditLog),
+    MenuItem::requires("admin.transfer", Permission::TransferPatient),
+    MenuItem::open("admin.timezone"),
+    MenuItem::requires("admin.security_alerts", Permission::ViewAuditLog),
+];
+
 pub fn show_admin_menu(conn: &rusqlite::Connection, role: &Role, session_id: &str) {
     let session_manager = SessionManager::new();
 
-    // Early check for non-transient sessions only
-    if !session_id.starts_with("trn-") {
-        let session = match session_manager.get_session_by_id(conn, session_id) {
-            Some(s) => s,
-            None => {
-                println!("Invalid or expired session. Please log in again.");
-                return;
-            }
-        };
-
-        if session.is_expired() {
-            println!("Session has expired. Please log in again.");
+    let session = match session_manager.get_session_by_id(conn, session_id) {
+        Some(s) => s,
+        None => {
+            println!("{}", t("common.session_invalid"));
             return;
         }
-    }
+    };
 
-    // Permission check (works for both normal and transient)
-    if !role.has_permission(&Permission::CreateClinicianAccount) {
-        println!("Access denied: insufficient permissions (CreateClinicianAccount required).");
+    if session.is_expired() {
+        println!("{}", t("common.session_expired"));
         return;
     }
 
     loop {
-        // Skip per-loop session fetch for transient sessions
-        if !session_id.starts_with("trn-") {
-            let session = match session_manager.get_session_by_id(conn, session_id) {
-                Some(s) => s,
-                None => {
-                    println!("Invalid or expired session. Please log in again.");
-                    return;
-                }
-            };
-
-            if session.is_expired() {
-                println!("Session has expired. Logging you out...");
-                if let Err(e) = session_manager.deactivate_session(conn, session_id) {
-                    println!("Failed to deactivate session: {}", e);
-                }
+        let session = match session_manager.get_session_by_id(conn, session_id) {
+            Some(s) => s,
+            None => {
+                println!("{}", t("common.session_invalid"));
                 return;
             }
+        };
+
+        if session.is_expired() {
+            println!("{}", t("common.session_expired_logout"));
+            if let Err(e) = session_manager.deactivate_session(conn, session_id) {
+                println!("{}", tf("common.session_deactivate_failed", &[&e]));
+            }
+            return;
         }
 
-        println!("\n=== Admin Menu ===");
-        println!("1. Create Clinician Account");
-        println!("2. View Clinician Account List");
-        println!("3. Create Caretaker Account");
-        println!("4. Delete a user by username");
-        println!("5. Logout");
-        print!("Enter your choice: ");
-        let choice = utils::get_user_choice();
+        println!("\n=== {} ===", t("admin.title"));
+        print_security_alert_banner(conn);
+        let role = &role.reload(conn);
+        let choice = menu_utils::choose_menu_item(role, &ADMIN_ITEMS);
 
         match choice {
-            1 => {
+            MenuChoice::Item("admin.create_clinician") => {
                 // Get username and password input from use
                 match get_new_account_credentials() {
                     Ok((username, password)) => {
@@ -76,21 +92,21 @@ pub fn show_admin_menu(conn: &rusqlite::Connection, role: &Role, session_id: &st
                 }
             }
 
-            2 => {
-                // Display list of clinicians
-                match queries::get_all_clinicians(conn) {
-                    Ok(clinicians) => {
-                        println!("\nClinician accounts:");
-                        for name in clinicians {
-                            println!("- {}", name);
-                        }
-                    }
+            MenuChoice::Item("admin.list_clinicians") => {
+                // Display list of clinicians, one page at a time
+                match queries::count_users_by_role(conn, "clinician") {
+                    Ok(total) => menu_utils::show_paged(
+                        "Clinician accounts",
+                        total,
+                        |limit, offset| queries::get_usernames_by_role_page(conn, "clinician", limit, offset),
+                        |number, name| println!("{}. {}", number, name),
+                    ),
                     Err(e) => println!("Failed to fetch clinicians: {}", e),
                 }
 
             }, 
 
-            3 => {
+            MenuChoice::Item("admin.create_caretaker") => {
                 // Create Caretaker Account
                 match get_new_account_credentials() {
                     Ok((username, password)) => {
@@ -104,29 +120,113 @@ pub fn show_admin_menu(conn: &rusqlite::Connection, role: &Role, session_id: &st
                 }
             },
 
-            4 => {
-                // Delete Account By Username
-                print!("Enter username to delete: ");
-                io::stdout().flush().unwrap();
-                let mut username = String::new();
-                io::stdin().read_line(&mut username).unwrap();
-                let username = username.trim().to_string();
-
-                // Get user ID
-                match queries::get_user_id_by_username(conn, &username) {
-                    Ok(Some(user_id)) => {
-                        if let Err(e) = queries::delete_user_by_id(conn, &user_id) {
-                            println!("Failed to delete user: {}", e);
-                        } else {
-                            println!("User '{}' deleted successfully.", username);
-                        }
-                    }
-                    Ok(None) => println!("User not found."),
-                    Err(e) => println!("Error: {}", e),
+            MenuChoice::Item("admin.suspend_user") => {
+                // Suspend instead of deleting, so the account's history stays intact
+                if !session_manager.check_permissions(conn, session_id, role, Permission::ManageUsers) {
+                    println!("Access denied: insufficient permissions (ManageUsers required).");
+                    continue;
+                }
+                account_status_flow(conn, &session_manager, role);
+            },
+
+            MenuChoice::Item("admin.revoke_sessions") => {
+                // Revoke every active session of a user
+                if !session_manager.check_permissions(conn, session_id, role, Permission::ManageSessions) {
+                    println!("Access denied: insufficient permissions (ManageSessions required).");
+                    continue;
+                }
+                revoke_sessions_flow(conn, &session_manager, session_id);
+            },
+
+            MenuChoice::Item("admin.permissions") => {
+                // Grant or revoke individual permissions per role
+                if !session_manager.check_permissions(conn, session_id, role, Permission::ManagePermissions) {
+                    println!("Access denied: insufficient permissions (ManagePermissions required).");
+                    continue;
+                }
+                manage_permissions_flow(conn, role);
+            },
+
+            MenuChoice::Item("admin.roles") => {
+                // List and define custom roles
+                if !session_manager.check_permissions(conn, session_id, role, Permission::ManagePermissions) {
+                    println!("Access denied: insufficient permissions (ManagePermissions required).");
+                    continue;
+                }
+                manage_roles_flow(conn, role);
+            },
+
+            MenuChoice::Item("admin.api_tokens") => {
+                // Issue, list and revoke long-lived API tokens
+                if !session_manager.check_permissions(conn, session_id, role, Permission::ManageApiTokens) {
+                    println!("Access denied: insufficient permissions (ManageApiTokens required).");
+                    continue;
+                }
+                manage_api_tokens_flow(conn, role);
+            },
+
+            MenuChoice::Item("admin.backup") => {
+                // Encrypted database backups
+                if !session_manager.check_permissions(conn, session_id, role, Permission::ManageBackups) {
+                    println!("Access denied: insufficient permissions (ManageBackups required).");
+                    continue;
+                }
+                backup_flow(conn, role);
+            },
+
+            MenuChoice::Item("admin.erase_patient") => {
+                // Right to erasure, recorded in the signed erasure log
+                if !session_manager.check_permissions(conn, session_id, role, Permission::ErasePatientData) {
+                    println!("Access denied: insufficient permissions (ErasePatientData required).");
+                    continue;
+                }
+                erasure_flow(conn, role);
+            },
+
+            MenuChoice::Item("admin.users") => {
+                // List accounts, change roles, reset passwords and inspect sessions
+                if !session_manager.check_permissions(conn, session_id, role, Permission::ManageUsers) {
+                    println!("Access denied: insufficient permissions (ManageUsers required).");
+                    continue;
+                }
+                user_management_flow(conn, &session_manager, role, session_id);
+            },
+
+            MenuChoice::Item("admin.audit_log") => {
+                // Browse, filter and export the audit trail
+                if !session_manager.check_permissions(conn, session_id, role, Permission::ViewAuditLog) {
+                    println!("Access denied: insufficient permissions (ViewAuditLog required).");
+                    continue;
+                }
+                audit_log_flow(conn, &session_manager, session_id, role);
+            },
+
+            MenuChoice::Item("admin.transfer") => {
+                // Reassign a patient's clinician, recorded in the audit trail
+                if !session_manager.check_permissions(conn, session_id, role, Permission::TransferPatient) {
+                    println!("Access denied: insufficient permissions (TransferPatient required).");
+                    continue;
+                }
+                let patient_id = input::read_required("Patient id: ", Field::ID);
+                match queries::get_patient_by_id(conn, &patient_id) {
+                    Ok(Some(patient)) => menu_utils::transfer_patient_flow(conn, role, &patient),
+                    Ok(None) => println!("No patient with that id."),
+                    Err(e) => println!("Error retrieving patient: {}", e),
+                }
+            },
+
+            MenuChoice::Item("admin.timezone") => menu_utils::timezone_flow(conn, role),
+
+            MenuChoice::Item("admin.security_alerts") => {
+                // Alerts raised by the security monitor from the audit trail
+                if !session_manager.check_permissions(conn, session_id, role, Permission::ViewAuditLog) {
+                    println!("Access denied: insufficient permissions (ViewAuditLog required).");
+                    continue;
                 }
+                security_alerts_flow(conn, role);
             },
-            
-            5 => {
+
+            MenuChoice::Logout => {
                 // Force logout with session removal
                 println!("Logging out...");
                 // Synchronous session removal
@@ -138,17 +238,761 @@ pub fn show_admin_menu(conn: &rusqlite::Connection, role: &Role, session_id: &st
                 return;
             },
 
-            6 => {
-                // Clean session termination
-                if !session_id.starts_with("trn-") {
-                    let _ = session_manager.deactivate_session(conn, session_id);
+           
+            MenuChoice::Item(_) | MenuChoice::Invalid => println!("{}", t("common.invalid_choice")),
+        }
+    }
+}
+
+// ask for a username and deactivate all of that user's sessions
+fn revoke_sessions_flow(conn: &Connection, session_manager: &SessionManager, session_id: &str) {
+    let username = input::read_required("Enter username whose sessions should be revoked: ", Field::USERNAME);
+
+    match queries::get_user_id_by_username(conn, &username) {
+        Ok(Some(user_id)) => match session_manager.revoke_user_sessions(conn, &user_id) {
+            Ok(count) => {
+                println!("Revoked {} active session(s) for '{}'.", count, username);
+                // revoking your own account also ends this session
+                if session_manager.get_session_by_id(conn, session_id).is_none() {
+                    println!("Your current session was included and has ended.");
+                }
+            }
+            Err(e) => println!("Failed to revoke sessions: {}", e),
+        },
+        Ok(None) => println!("User not found."),
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
+// account console; each action re-checks the permission it needs
+fn user_management_flow(conn: &Connection, session_manager: &SessionManager, admin_role: &Role, session_id: &str) {
+    loop {
+        println!("\n--- User management ---");
+        println!("1. List all users");
+        println!("2. Change a user's role");
+        println!("3. Reset a user's password");
+        println!("4. View and revoke a user's sessions");
+        println!("0. Back");
+        print!("Enter your choice: ");
+        match input::read_choice() {
+            1 => list_users(conn, admin_role),
+            2 => {
+                if !session_manager.check_permissions(conn, session_id, admin_role, Permission::ManagePermissions) {
+                    println!("Access denied: insufficient permissions (ManagePermissions required).");
+                    continue;
+                }
+                match queries::get_all_roles(conn) {
+                    Ok(roles) => {
+                        for (index, (name, _)) in roles.iter().enumerate() {
+                            println!("{}. {}", index + 1, name);
+                        }
+                        assign_role_flow(conn, &roles);
+                    }
+                    Err(e) => println!("Failed to fetch roles: {}", e),
+                }
+            }
+            3 => reset_password_flow(conn, session_manager, admin_role),
+            4 => {
+                if !session_manager.check_permissions(conn, session_id, admin_role, Permission::ManageSessions) {
+                    println!("Access denied: insufficient permissions (ManageSessions required).");
+                    continue;
+                }
+                user_sessions_flow(conn, session_manager, admin_role);
+            }
+            0 => return,
+            _ => println!("{}", t("common.invalid_choice")),
+        }
+    }
+}
+
+// browse the audit trail newest first, narrowed by the filters set here
+fn audit_log_flow(conn: &Connection, session_manager: &SessionManager, session_id: &str, admin_role: &Role) {
+    let zone = timestamps::display_zone(conn, &admin_role.id);
+    let mut filter = AuditFilter { zone, ..Default::default() };
+    loop {
+        println!("\n--- Audit log ---");
+        println!(
+            "Filters: user {}, action {}, dates {} to {}",
+            filter.user.as_deref().unwrap_or("any"),
+            filter.action.as_deref().unwrap_or("any"),
+            filter.dates.from.map(|d| d.to_string()).unwrap_or_else(|| "start".to_string()),
+            filter.dates.to.map(|d| d.to_string()).unwrap_or_else(|| "now".to_string())
+        );
+        println!("1. Browse events");
+        println!("2. Filter by user");
+        println!("3. Filter by action");
+        println!("4. Filter by date range");
+        println!("5. Clear filters");
+        println!("6. Export to CSV");
+        println!("7. Decode a legacy system_audit_* dump");
+        println!("0. Back");
+        print!("Enter your choice: ");
+        match input::read_choice() {
+            1 => match audit::count_events(conn, &filter) {
+                Ok(total) => menu_utils::show_paged(
+                    "Audit events",
+                    total,
+                    |limit, offset| audit::get_events_page(conn, &filter, limit, offset),
+                    |number, event: &AuditEvent| {
+                        let who = event.username.as_deref().or(event.user_id.as_deref()).unwrap_or("-");
+                        println!(
+                            "{}. {} {:<5} {} [{}] {}",
+                            number,
+                            timestamps::format(&event.occurred_at, zone),
+                            event.level,
+                            event.action,
+                            who,
+                            audit::format_details(&event.details)
+                        );
+                    },
+                ),
+                Err(e) => println!("Failed to fetch audit events: {}", e),
+            },
+            2 => {
+                let user = input::read_optional("Username or user id (empty = any): ", Field::USERNAME);
+                // a username also matches events that only carry the account's id
+                filter.user_id = match &user {
+                    Some(name) => queries::get_user_id_by_username(conn, name).unwrap_or(None),
+                    None => None,
+                };
+                filter.user = user;
+            }
+            3 => filter.action = input::read_optional("Action text, e.g. login (empty = any): ", Field::TEXT),
+            4 => {
+                let dates = DateRange {
+                    from: input::read_optional_date("From date (MM-DD-YYYY, empty = all): "),
+                    to: input::read_optional_date("To date (MM-DD-YYYY, empty = all): "),
+                };
+                if let (Some(from), Some(to)) = (dates.from, dates.to) {
+                    if from > to {
+                        println!("Start date must not be after end date.");
+                        continue;
+                    }
+                }
+                filter.dates = dates;
+            }
+            5 => filter = AuditFilter { zone, ..Default::default() },
+            6 => match audit::export_events(conn, &filter, &admin_role.id) {
+                Ok((path, count)) => println!("Exported {} audit event(s) to {}", count, path.display()),
+                Err(e) => println!("Export failed: {}", e),
+            },
+            7 => {
+                if !session_manager.check_permissions(conn, session_id, admin_role, Permission::DecodeAuditDumps) {
+                    println!("Access denied: insufficient permissions (DecodeAuditDumps required).");
+                    continue;
                 }
-                println!("Logged out.");
+                decode_dump_flow(conn, admin_role);
+            }
+            0 => return,
+            _ => println!("{}", t("common.invalid_choice")),
+        }
+    }
+}
+
+// pick one of the dump files in the log directory and show it decoded
+fn decode_dump_flow(conn: &Connection, admin_role: &Role) {
+    let dumps = match audit_dump::list_dumps(Path::new(&config::get().logging.log_dir)) {
+        Ok(dumps) => dumps,
+        Err(e) => {
+            println!("Failed to list the dump files: {}", e);
+            return;
+        }
+    };
+    if dumps.is_empty() {
+        println!("There are no system_audit_* dumps in the log directory.");
+        return;
+    }
+    for (index, name) in dumps.iter().enumerate() {
+        println!("{}. {}", index + 1, name);
+    }
+    print!("Dump to decode (0 = cancel): ");
+    let choice = input::read_choice();
+    if choice <= 0 || choice as usize > dumps.len() {
+        return;
+    }
+    match audit_dump::decode(conn, admin_role, &dumps[(choice - 1) as usize]) {
+        Ok(dump) => {
+            let zone = timestamps::display_zone(conn, &admin_role.id);
+            println!("\n{} (SHA-256 {}, first decoded {})", dump.file_name, dump.sha256, timestamps::format(&dump.first_decoded_at, zone));
+            println!("{}", dump.content);
+        }
+        Err(e) => println!("Cannot decode the dump: {}", e),
+    }
+}
+
+// shown at the top of the admin menu while security alerts are open
+fn print_security_alert_banner(conn: &Connection) {
+    match monitoring::open_alerts(conn) {
+        Ok(alerts) if !alerts.is_empty() => println!("!!! {} open security alert(s), see Security alerts !!!", alerts.len()),
+        Ok(_) => {}
+        Err(e) => println!("Could not load security alerts: {}", e),
+    }
+}
+
+// list the open security alerts and acknowledge them by number
+fn security_alerts_flow(conn: &Connection, admin_role: &Role) {
+    let zone = timestamps::display_zone(conn, &admin_role.id);
+    loop {
+        let alerts = match monitoring::open_alerts(conn) {
+            Ok(alerts) => alerts,
+            Err(e) => {
+                println!("Error retrieving security alerts: {}", e);
                 return;
+            }
+        };
+        println!("\n--- Security alerts ---");
+        if alerts.is_empty() {
+            println!("No open security alerts.");
+            return;
+        }
+        for (index, alert) in alerts.iter().enumerate() {
+            println!("{}. {} [{}] {}", index + 1, timestamps::format(&alert.raised_at, zone), alert.rule, alert.message);
+        }
+        print!("Alert number to acknowledge (0 to go back): ");
+        let choice = usize::try_from(input::read_choice()).unwrap_or(0);
+        if choice == 0 {
+            return;
+        }
+        let Some(alert) = alerts.get(choice - 1) else {
+            println!("{}", t("common.invalid_choice"));
+            continue;
+        };
+        match monitoring::acknowledge(conn, alert.alert_id, &admin_role.id) {
+            Ok(true) => println!("Alert acknowledged."),
+            Ok(false) => println!("Alert was already acknowledged by another administrator."),
+            Err(e) => println!("Could not acknowledge the alert: {}", e),
+        }
+    }
+}
+
+fn list_users(conn: &Connection, admin_role: &Role) {
+    let zone = timestamps::display_zone(conn, &admin_role.id);
+    match queries::count_users(conn) {
+        Ok(total) => menu_utils::show_paged(
+            "All users",
+            total,
+            |limit, offset| queries::get_user_summaries_page(conn, limit, offset),
+            |number, user: &UserSummary| {
+                println!(
+                    "{}. {} [{}]{} created {}, last login {}, {} active session(s)",
+                    number,
+                    user.user_name,
+                    user.role,
+                    if user.is_active { "" } else { " [suspended]" },
+                    timestamps::format(&user.created_at, zone),
+                    timestamps::format_or(user.last_login.as_deref(), zone, "never"),
+                    user.active_sessions
+                );
             },
+        ),
+        Err(e) => println!("Failed to fetch users: {}", e),
+    }
+}
 
-           
-            _ => println!("Invalid choice"),
+// the username and id of an existing account, asked for by name
+fn read_existing_user(conn: &Connection) -> Option<(String, String)> {
+    let username = input::read_required("Username: ", Field::USERNAME);
+    match queries::get_user_id_by_username(conn, &username) {
+        Ok(Some(user_id)) => Some((username, user_id)),
+        Ok(None) => {
+            println!("User not found.");
+            None
+        }
+        Err(e) => {
+            println!("Error: {}", e);
+            None
+        }
+    }
+}
+
+// suspend an active account (ending its sessions) or reactivate a suspended one
+fn account_status_flow(conn: &Connection, session_manager: &SessionManager, admin_role: &Role) {
+    let Some((username, user_id)) = read_existing_user(conn) else {
+        return;
+    };
+    let suspended = match queries::is_user_suspended(conn, &user_id) {
+        Ok(suspended) => suspended,
+        Err(e) => {
+            println!("Error: {}", e);
+            return;
+        }
+    };
+    if !suspended && user_id == admin_role.id {
+        println!("You cannot suspend your own account.");
+        return;
+    }
+
+    let action = if suspended { "Reactivate" } else { "Suspend" };
+    if !input::confirm(&format!("'{}' is {}. {} it? (y/N): ", username, if suspended { "suspended" } else { "active" }, action)) {
+        println!("No changes made.");
+        return;
+    }
+
+    match queries::set_user_active(conn, &user_id, suspended, &admin_role.id) {
+        Ok(true) if suspended => println!("'{}' reactivated and can log in again.", username),
+        Ok(true) => {
+            let revoked = session_manager.revoke_user_sessions(conn, &user_id).unwrap_or(0);
+            println!("'{}' suspended; {} session(s) ended. Their records are kept.", username, revoked);
+        }
+        Ok(false) => println!("User not found."),
+        Err(e) => println!("Failed to update account: {}", e),
+    }
+}
+
+// set a temporary password and end every session of the account
+fn reset_password_flow(conn: &Connection, session_manager: &SessionManager, admin_role: &Role) {
+    let Some((username, user_id)) = read_existing_user(conn) else {
+        return;
+    };
+    let password = loop {
+        let Some(password) = input::read_password("New temporary password (empty to cancel): ") else {
+            continue;
+        };
+        if password.is_empty() {
+            println!("Password reset cancelled.");
+            return;
+        }
+        if let Err(e) = check_new_password(&password, &[&username]) {
+            println!("{}", e);
+            continue;
+        }
+        if !input::confirm_password(&password, "Confirm password: ") {
+            println!("Passwords do not match. Please try again.");
+            continue;
+        }
+        break password;
+    };
+    match queries::reset_user_password(conn, &user_id, &password, &admin_role.id) {
+        Ok(()) => {
+            let revoked = session_manager.revoke_user_sessions(conn, &user_id).unwrap_or(0);
+            println!("Password for '{}' reset; {} session(s) ended. Share the new password over a separate channel.", username, revoked);
+        }
+        Err(e) => println!("Failed to reset password: {}", e),
+    }
+}
+
+// every session of one account with its state, then optionally end the active ones
+fn user_sessions_flow(conn: &Connection, session_manager: &SessionManager, admin_role: &Role) {
+    let Some((username, user_id)) = read_existing_user(conn) else {
+        return;
+    };
+    let sessions = match queries::get_sessions_for_user(conn, &user_id) {
+        Ok(sessions) => sessions,
+        Err(e) => {
+            println!("Failed to fetch sessions: {}", e);
+            return;
+        }
+    };
+    if sessions.is_empty() {
+        println!("'{}' has no sessions.", username);
+        return;
+    }
+
+    let zone = timestamps::display_zone(conn, &admin_role.id);
+    println!("\nSessions of '{}':", username);
+    let mut active = 0;
+    for (index, session) in sessions.iter().enumerate() {
+        let state = if !session.active {
+            "logged out"
+        } else if session.is_expired() {
+            "expired"
+        } else {
+            active += 1;
+            "active"
+        };
+        let started = timestamps::format_time(session.create_time.into(), zone);
+        println!(
+            "{}. {}… as '{}', started {}, lasts {} min, {}{}",
+            index + 1,
+            &session.session_id[..session.session_id.len().min(8)],
+            session.role,
+            started,
+            session.exp_time.as_secs() / 60,
+            state,
+            if session.active && !session.matches_client() { ", other client" } else { "" }
+        );
+    }
+    if active == 0 {
+        return;
+    }
+    if input::confirm(&format!("Revoke the {} active session(s)? (y/N): ", active)) {
+        match session_manager.revoke_user_sessions(conn, &user_id) {
+            Ok(count) => println!("Revoked {} session(s).", count),
+            Err(e) => println!("Failed to revoke sessions: {}", e),
+        }
+    }
+}
+
+// pick a role, then toggle its permissions until the admin goes back
+fn manage_permissions_flow(conn: &Connection, admin_role: &Role) {
+    let roles: Vec<String> = match queries::get_all_roles(conn) {
+        Ok(roles) => roles.into_iter().map(|(name, _)| name).collect(),
+        Err(e) => {
+            println!("Failed to fetch roles: {}", e);
+            return;
+        }
+    };
+
+    println!("\nRoles:");
+    for (index, name) in roles.iter().enumerate() {
+        println!("{}. {}", index + 1, name);
+    }
+    print!("Select role (0 to cancel): ");
+    let choice = input::read_choice();
+    if choice <= 0 || choice as usize > roles.len() {
+        return;
+    }
+    let target_role = &roles[(choice - 1) as usize];
+
+    loop {
+        let granted = match queries::get_role_permissions(conn, target_role) {
+            Ok(granted) => granted,
+            Err(e) => {
+                println!("Failed to fetch permissions: {}", e);
+                return;
+            }
+        };
+
+        println!("\nPermissions for '{}':", target_role);
+        for (index, perm) in Permission::ALL.iter().enumerate() {
+            let mark = if granted.iter().any(|g| g == perm.as_str()) { "x" } else { " " };
+            println!("{}. [{}] {} - {}", index + 1, mark, perm.as_str(), perm.perm_description());
+        }
+        print!("Select permission to toggle (0 to go back): ");
+        let choice = input::read_choice();
+        if choice <= 0 || choice as usize > Permission::ALL.len() {
+            return;
+        }
+
+        let perm = &Permission::ALL[(choice - 1) as usize];
+        let currently_granted = granted.iter().any(|g| g == perm.as_str());
+
+        // never let an admin lock their own role out of permission management
+        if currently_granted && *perm == Permission::ManagePermissions && *target_role == admin_role.name {
+            println!("You cannot revoke ManagePermissions from your own role.");
+            continue;
+        }
+
+        match queries::set_role_permission(conn, target_role, perm.as_str(), !currently_granted, &admin_role.id) {
+            Ok(()) => println!(
+                "{} {} for '{}'.",
+                if currently_granted { "Revoked" } else { "Granted" },
+                perm.as_str(),
+                target_role
+            ),
+            Err(e) => println!("Failed to update permission: {}", e),
+        }
+    }
+}
+
+// show the role hierarchy and optionally define a new inheriting role
+fn manage_roles_flow(conn: &Connection, admin_role: &Role) {
+    let roles = match queries::get_all_roles(conn) {
+        Ok(roles) => roles,
+        Err(e) => {
+            println!("Failed to fetch roles: {}", e);
+            return;
+        }
+    };
+
+    println!("\nRoles:");
+    for (index, (name, parent)) in roles.iter().enumerate() {
+        match parent {
+            Some(parent) => println!("{}. {} (inherits from {})", index + 1, name, parent),
+            None => println!("{}. {} (built-in)", index + 1, name),
+        }
+    }
+
+    println!("\n1. Create a new role");
+    println!("2. Assign a role to a user");
+    println!("0. Back");
+    print!("Enter your choice: ");
+    match input::read_choice() {
+        1 => {}
+        2 => {
+            assign_role_flow(conn, &roles);
+            return;
+        }
+        _ => return,
+    }
+
+    let name = input::read_required("New role name: ", Field::ID);
+    print!("Inherit from role number: ");
+    let parent_choice = input::read_choice();
+    if parent_choice <= 0 || parent_choice as usize > roles.len() {
+        println!("Invalid selection.");
+        return;
+    }
+    let parent = &roles[(parent_choice - 1) as usize].0;
+    let description = input::read_required("Description: ", Field::TEXT);
+
+    match access_control::create_custom_role(conn, &name, parent, &description, &admin_role.id) {
+        Ok(()) => println!("Role '{}' created. It inherits all permissions of '{}'.", name, parent),
+        Err(e) => println!("{}", e),
+    }
+}
+
+// change the role of an existing account and end its sessions so the change applies at next login
+fn assign_role_flow(conn: &Connection, roles: &[(String, Option<String>)]) {
+    let username = input::read_required("Username: ", Field::USERNAME);
+    let user_id = match queries::get_user_id_by_username(conn, &username) {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => {
+            println!("User not found.");
+            return;
+        }
+        Err(e) => {
+            println!("Error: {}", e);
+            return;
+        }
+    };
+
+    print!("New role number: ");
+    let choice = input::read_choice();
+    if choice <= 0 || choice as usize > roles.len() {
+        println!("Invalid selection.");
+        return;
+    }
+    let new_role = &roles[(choice - 1) as usize].0;
+
+    match queries::update_user_role(conn, &user_id, new_role) {
+        Ok(()) => {
+            let _ = SessionManager::new().revoke_user_sessions(conn, &user_id);
+            println!("'{}' now has role '{}'.", username, new_role);
+        }
+        Err(e) => println!("Failed to update role: {}", e),
+    }
+}
+
+// list tokens, then issue a new one or revoke an existing one
+fn manage_api_tokens_flow(conn: &Connection, admin_role: &Role) {
+    let tokens = match api_token::list_tokens(conn) {
+        Ok(tokens) => tokens,
+        Err(e) => {
+            println!("Failed to fetch API tokens: {}", e);
+            return;
+        }
+    };
+
+    let zone = timestamps::display_zone(conn, &admin_role.id);
+    println!("\nAPI tokens:");
+    if tokens.is_empty() {
+        println!("(none)");
+    }
+    for (index, token) in tokens.iter().enumerate() {
+        let status = if token.revoked { "revoked" } else { "active" };
+        let scope = match &token.patient_ids {
+            Some(ids) => format!("{} patient(s)", ids.len()),
+            None => "all patients in role scope".to_string(),
+        };
+        println!(
+            "{}. {} [{}] user {} as '{}', {}, issued {} by {}, expires {}, last used {}",
+            index + 1,
+            token.label,
+            status,
+            token.user_id,
+            token.role,
+            scope,
+            timestamps::format(&token.created_at, zone),
+            token.created_by,
+            timestamps::format_or(token.expires_at.as_deref(), zone, "never"),
+            timestamps::format_or(token.last_used_at.as_deref(), zone, "never"),
+        );
+    }
+
+    println!("\n1. Issue a new token");
+    println!("2. Revoke a token");
+    println!("0. Back");
+    print!("Enter your choice: ");
+    match input::read_choice() {
+        1 => issue_api_token_flow(conn, admin_role),
+        2 => {
+            print!("Token number to revoke: ");
+            let choice = input::read_choice();
+            if choice <= 0 || choice as usize > tokens.len() {
+                println!("Invalid selection.");
+                return;
+            }
+            let token = &tokens[(choice - 1) as usize];
+            match api_token::revoke_token(conn, &token.token_id, &admin_role.id) {
+                Ok(true) => println!("Token '{}' revoked.", token.label),
+                Ok(false) => println!("Token not found."),
+                Err(e) => println!("Failed to revoke token: {}", e),
+            }
+        }
+        _ => {}
+    }
+}
+
+// create an encrypted backup of the live database, or restore one over it
+fn backup_flow(conn: &Connection, admin_role: &Role) {
+    println!("\n1. Create a backup");
+    println!("2. Restore from a backup");
+    println!("0. Back");
+    print!("Enter your choice: ");
+    match input::read_choice() {
+        1 => {
+            let default_path = backup::default_backup_path();
+            let path = input::read_optional(&format!("Backup file [{}]: ", default_path.display()), Field::TEXT)
+                .map_or(default_path, PathBuf::from);
+            let result = backup::read_passphrase(true).and_then(|passphrase| backup::create_backup(conn, &path, &passphrase));
+            match result {
+                Ok(bytes) => println!("Backup written to {} ({} bytes). Keep the passphrase safe, it cannot be recovered.", path.display(), bytes),
+                Err(e) => println!("Backup failed: {}", e),
+            }
+        }
+        2 => {
+            let path = PathBuf::from(input::read_required("Backup file to restore: ", Field::TEXT));
+            println!("Restoring replaces ALL current data, including users and sessions.");
+            if input::read_required("Type RESTORE to continue: ", Field::WORD) != "RESTORE" {
+                println!("Restore cancelled.");
+                return;
+            }
+            tracing::warn!(target: SECURITY_TARGET, user_id = %admin_role.id, path = %path.display(), "Database restore requested from admin menu");
+            let db_path = &config::get().database.path;
+            match backup::read_passphrase(false).and_then(|passphrase| backup::restore_backup(&path, db_path, &passphrase)) {
+                Ok(version) => println!("Database restored (schema version {}). Sessions from the backup replace the current ones; log in again if you are logged out.", version),
+                Err(e) => println!("Restore failed: {}", e),
+            }
+        }
+        _ => {}
+    }
+}
+
+// erase one patient, or list the erasure log with signature checks
+fn erasure_flow(conn: &Connection, admin_role: &Role) {
+    println!("\n1. Erase a patient's data");
+    println!("2. View erasure log");
+    println!("0. Back");
+    print!("Enter your choice: ");
+    match input::read_choice() {
+        1 => {
+            let patient_id = input::read_required("Patient id: ", Field::ID);
+            match queries::get_patient_by_id(conn, &patient_id) {
+                Ok(Some(patient)) => println!("Patient: {} {} (DOB: {})", patient.first_name, patient.last_name, patient.date_of_birth),
+                Ok(None) => {
+                    println!("No patient with that id.");
+                    return;
+                }
+                Err(e) => {
+                    println!("Error retrieving patient: {}", e);
+                    return;
+                }
+            }
+            println!("1. Delete all records");
+            println!("2. Anonymise (keep readings, doses and alerts without any link to the patient)");
+            print!("Select mode: ");
+            let mode = match input::read_choice() {
+                1 => erasure::ErasureMode::Delete,
+                2 => erasure::ErasureMode::Anonymize,
+                _ => {
+                    println!("Invalid mode.");
+                    return;
+                }
+            };
+            println!("This removes the patient, their account, sessions, care-team links and devices. It cannot be undone.");
+            if input::read_required("Type ERASE to continue: ", Field::WORD) != "ERASE" {
+                println!("Erasure cancelled.");
+                return;
+            }
+            match erasure::erase_patient(conn, admin_role, &patient_id, mode) {
+                Ok(record) => println!("Patient erased ({}). Erasure record {}: {}", record.mode, record.erasure_id, record.row_counts),
+                Err(e) => println!("Erasure failed: {}", e),
+            }
+        }
+        2 => {
+            let records = match erasure::list_records(conn) {
+                Ok(records) => records,
+                Err(e) => {
+                    println!("Error retrieving erasure log: {}", e);
+                    return;
+                }
+            };
+            // None = no signing key configured, signatures cannot be checked
+            let unverified = match erasure::unverified_records(conn) {
+                Ok(records) => Some(records.into_iter().map(|record| record.erasure_id).collect::<Vec<_>>()),
+                Err(e) => {
+                    println!("Cannot verify signatures: {}", e);
+                    None
+                }
+            };
+            let zone = timestamps::display_zone(conn, &admin_role.id);
+            if records.is_empty() {
+                println!("No erasures recorded.");
+            }
+            for record in records {
+                let status = match &unverified {
+                    Some(ids) if ids.contains(&record.erasure_id) => "SIGNATURE INVALID",
+                    Some(_) => "signature ok",
+                    None => "not verified",
+                };
+                println!(
+                    "{} | {} | {} | by {} | subject {} | {} | {}",
+                    timestamps::format(&record.performed_at, zone), record.erasure_id, record.mode, record.performed_by, record.subject_hash, record.row_counts, status
+                );
+            }
         }
+        _ => {}
+    }
+}
+
+fn issue_api_token_flow(conn: &Connection, admin_role: &Role) {
+    let username = input::read_required("Username the token acts for: ", Field::USERNAME);
+    let user_id = match queries::get_user_id_by_username(conn, &username) {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => {
+            println!("User not found.");
+            return;
+        }
+        Err(e) => {
+            println!("Error: {}", e);
+            return;
+        }
+    };
+    let user_role = match queries::get_user_role(conn, &user_id) {
+        Ok(Some(user_role)) => user_role,
+        _ => {
+            println!("Could not read the user's role.");
+            return;
+        }
+    };
+
+    // a token may use the user's role or any role it inherits from
+    let roles = Role::resolve_chain(conn, &user_role);
+    println!("Token role:");
+    for (index, name) in roles.iter().enumerate() {
+        println!("{}. {}", index + 1, name);
+    }
+    print!("Select role: ");
+    let choice = input::read_choice();
+    if choice <= 0 || choice as usize > roles.len() {
+        println!("Invalid selection.");
+        return;
+    }
+    let token_role = &roles[(choice - 1) as usize];
+
+    let patients = input::read_optional("Restrict to patient ids (comma separated, empty = all in role scope): ", Field::ID_LIST).unwrap_or_default();
+    let patient_ids: Vec<String> = patients
+        .split(',')
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .collect();
+
+    let label = input::read_required("Label (e.g. \"clinic sync script\"): ", Field::TEXT);
+    print!("Expires after how many days (0 = never): ");
+    let days = input::read_choice();
+    if days < 0 {
+        println!("Invalid number of days.");
+        return;
+    }
+    let expires = if days == 0 { None } else { Some(days as u32) };
+    let scope = if patient_ids.is_empty() { None } else { Some(patient_ids.as_slice()) };
+
+    match api_token::issue_token(conn, &user_id, token_role, scope, &label, expires, &admin_role.id) {
+        Ok((_, token)) => println!(
+            "\nToken issued. Copy it now, it will not be shown again:\n{}\n",
+            token
+        ),
+        Err(e) => println!("{}", e),
     }
 }
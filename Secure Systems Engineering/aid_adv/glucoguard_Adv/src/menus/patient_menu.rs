@@ -1,113 +1,333 @@
-use crate::utils;
-use crate::access_control::Role;
-use crate::db::queries::{insert_activation_code,
-                        add_caretaker_team_member,
-                        add_caretaker_to_patient_account};
-use crate::auth::{generate_one_time_code};
-use uuid::Uuid;
+use crate::i18n::{t, tf};
+use crate::menus::menu_utils::{self, MenuChoice, MenuItem};
+use crate::access_control::{Permission, Role};
 use crate::session::SessionManager;
+use crate::prediction;
+use crate::pump;
+use crate::dose_safety;
+use crate::config;
+use crate::units;
+use crate::timestamps;
+use crate::consent::{self, ConsentScope};
+use crate::extended_bolus::{self, BolusPlan};
+use crate::input::{self, Field};
+use crate::db::queries::{get_patient_by_id, get_patient_contact, update_own_contact_details};
+use crate::db::models::PatientContact;
+use std::io::{self, Write};
 use rusqlite::Connection;
 
+// entries of the patient menu and the permission each needs
+static PATIENT_ITEMS: [MenuItem; 14] = [
+    MenuItem::requires("patient.recent_readings", Permission::ViewGlucose),
+    MenuItem::requires("patient.insulin_options", Permission::ViewPatient),
+    MenuItem::requires("patient.request_bolus", Permission::AddGlucose),
+    MenuItem::requires("patient.basal_time", Permission::AddGlucose),
+    MenuItem::requires("patient.insulin_history", Permission::ViewGlucose),
+    MenuItem::requires("patient.caretakers", Permission::CreateCaretakerLink),
+    MenuItem::open("patient.consent"),
+    MenuItem::requires("patient.glucose_stats", Permission::ViewGlucose),
+    MenuItem::open("patient.contact"),
+    MenuItem::requires("patient.emergency_stop", Permission::EmergencyStop),
+    MenuItem::requires("patient.temp_basal", Permission::SetTempBasal),
+    MenuItem::requires("patient.reminders", Permission::ViewReminders),
+    MenuItem::open("patient.glucose_unit"),
+    MenuItem::open("patient.timezone"),
+];
+
 pub fn show_patient_menu(conn: &rusqlite::Connection,role:&Role,session_id: &str) {
     let session_manager = SessionManager::new();
+    menu_utils::print_due_reminders(conn, role);
     loop {
         // Fetch session from the database
         let session = match session_manager.get_session_by_id(conn, &session_id) {
             Some(s) => s,
             None => {
-                println!("Invalid or expired session. Please log in again.");
+                println!("{}", t("common.session_invalid"));
                 return;
             }
         };
 
         // Check expiration
         if session.is_expired() {
-            println!("Session has expired. Logging you out...");
+            println!("{}", t("common.session_expired_logout"));
             if let Err(e) = session_manager.deactivate_session(conn, &session_id) {
-                println!("Failed to deactivate session: {}", e);
+                println!("{}", tf("common.session_deactivate_failed", &[&e]));
             }
             return;
         }
 
-        // Check role is Admin
-        if session.role != "patient"{
-            println!("Invalid access rights to view page");
+        // Check role is (or inherits from) patient
+        if role.base != "patient"{
+            println!("{}", t("common.access_denied_page"));
             return;
         }
 
-        println!("=== Patient Menu ===");
-        println!("1) View most recent glucose readings.");
-        println!("2) View current basal and bolus options.");
-        println!("3) Request bolus insulin dose.");
-        println!("4) Configure basal insulin dose time.");
-        println!("5) View patient insulin history.");
-        println!("6. Create Caretaker activation code.");
-        println!("7. Logout");
-        print!("Enter your choice: ");
-        let choice = utils::get_user_choice();
+        let role = &role.reload(conn);
+        warn_predicted_glucose(conn, &role.id);
+
+        println!("=== {} ===", t("patient.title"));
+        let choice = menu_utils::choose_menu_item(role, &PATIENT_ITEMS);
 
         match choice {
-            1 => {
+            MenuChoice::Item("patient.recent_readings") => {
                 //View the patient’s most recent glucose readings.
                 //view_patient_summary_flow(conn)
             },
-            2 => {
+            MenuChoice::Item("patient.insulin_options") => {
                 // View the patient’s current basal rate and bolus insulin options.
+                view_insulin_options(conn, role);
             },
-            3 => {
+            MenuChoice::Item("patient.request_bolus") => {
                 //  Request a bolus insulin dose.
                 //– Patients cannot request more than the prescribed maximum dose or violate safety limits
+                request_bolus_flow(conn, role);
             },
-            4 => {
+            MenuChoice::Item("patient.basal_time") => {
                 //Configure basal insulin dose time.
                 // Patients can adjust the basal insulin dose, which will be effective within 24 hours, so as
                 // not to overlap a previous dose.
                 // – Patients cannot request more than the prescribed maximum dose or violate safety limits.
             },
-            5 => {
+            MenuChoice::Item("patient.insulin_history") => {
                 //Review historical insulin delivery and glucose data.
             },
-            6 => {
-                //
-                create_and_display_caretaker_activation_code(conn,role);
+            MenuChoice::Item("patient.caretakers") => {
+                menu_utils::manage_caretakers_flow(conn, role, &role.id);
+            },
+            MenuChoice::Item("patient.consent") => {
+                consent_flow(conn, role);
+            },
+            MenuChoice::Item("patient.glucose_stats") => {
+                menu_utils::show_glucose_stats(conn, role, &role.id);
+            },
+            MenuChoice::Item("patient.contact") => {
+                edit_contact_flow(conn, role);
+            },
+            MenuChoice::Item("patient.emergency_stop") => {
+                menu_utils::emergency_stop_flow(conn, role, &role.id);
+            },
+            MenuChoice::Item("patient.temp_basal") => {
+                match get_patient_by_id(conn, &role.id) {
+                    Ok(Some(patient)) => menu_utils::temp_basal_flow(conn, role, &patient),
+                    Ok(None) => println!("No patient record found for this account."),
+                    Err(e) => println!("Error loading patient record: {}", e),
+                }
             },
-            7 => {
-                // Clean tempo session termination
-                if !session_id.starts_with("trn-") {
-                    let _ = session_manager.deactivate_session(conn, session_id);
+            MenuChoice::Item("patient.reminders") => {
+                match get_patient_by_id(conn, &role.id) {
+                    Ok(Some(patient)) => menu_utils::reminders_flow(conn, role, &patient),
+                    Ok(None) => println!("No patient record found for this account."),
+                    Err(e) => println!("Error loading patient record: {}", e),
                 }
-                println!("Logged out.");
+            },
+            MenuChoice::Item("patient.glucose_unit") => {
+                menu_utils::glucose_unit_flow(conn, role);
+            },
+            MenuChoice::Item("patient.timezone") => {
+                menu_utils::timezone_flow(conn, role);
+            },
+            MenuChoice::Logout => {
+                let _ = session_manager.deactivate_session(conn, session_id);
+                println!("{}", t("common.logged_out"));
                 return;
             }
-            _ => println!("Invalid choice"),
+            MenuChoice::Item(_) | MenuChoice::Invalid => println!("{}", t("common.invalid_choice")),
+        }
+    }
+}
+// the patient's own insulin settings and what is left of today's maximum
+fn view_insulin_options(conn: &Connection, role: &Role) {
+    match get_patient_by_id(conn, &role.id) {
+        Ok(Some(patient)) => {
+            println!("\n=== Insulin Settings ===");
+            println!("  Basal Rate: {:.2} units/hour", patient.basal_rate);
+            println!("  Bolus Rate: {:.2} units", patient.bolus_rate);
+            println!("  Max Dosage: {:.2} units per 24 hours", patient.max_dosage);
+            let zone = timestamps::display_zone(conn, &role.id);
+            menu_utils::print_temp_basal(conn, &patient.patient_id, zone);
+            menu_utils::print_low_suspension(conn, &patient.patient_id, zone);
+            menu_utils::print_daily_allowance(conn, &patient);
+            menu_utils::print_pump_status(conn, &patient.patient_id, zone);
         }
+        Ok(None) => println!("No patient record found for this account."),
+        Err(e) => println!("Error loading patient record: {}", e),
     }
 }
-pub fn create_and_display_caretaker_activation_code(
-    conn: &rusqlite::Connection,
-    role: &Role 
-) {
-    // Generate a one-time activation code
-    let activation_code = generate_one_time_code(15);
 
-    let new_account_type = "caretaker";
-    let user_id = Uuid::new_v4().to_string();
+// queue a bolus for the patient's pump, bounded by what is left of the daily maximum
+fn request_bolus_flow(conn: &Connection, role: &Role) {
+    let patient = match get_patient_by_id(conn, &role.id) {
+        Ok(Some(patient)) => patient,
+        Ok(None) => {
+            println!("No patient record found for this account.");
+            return;
+        }
+        Err(e) => {
+            println!("Error loading patient record: {}", e);
+            return;
+        }
+    };
+    menu_utils::print_daily_allowance(conn, &patient);
+    // refusals quote the latest reading
+    let unit = units::display_unit(conn, &role.id);
+    let remaining = match dose_safety::daily_allowance(conn, &patient) {
+        Ok(allowance) => allowance.remaining_units() as f32,
+        Err(e) => {
+            println!("Error loading today's insulin total: {}", e);
+            return;
+        }
+    };
+    if remaining < 0.1 {
+        println!("The daily maximum of {:.1} units has been reached; no bolus can be requested now.", patient.max_dosage);
+        return;
+    }
+    println!("1. Normal bolus (all now)");
+    println!("2. Extended bolus (spread over a period)");
+    println!("3. Dual-wave bolus (part now, the rest spread over a period)");
+    print!("Enter your choice: ");
+    io::stdout().flush().unwrap();
+    let kind = input::read_choice();
+    if !(1..=3).contains(&kind) {
+        println!("Invalid choice");
+        return;
+    }
+    let units = input::read_float(&format!("Bolus units (max {:.1}): ", remaining), 0.1, remaining);
+    if kind == 1 {
+        match pump::queue_command(conn, &patient.patient_id, "bolus", units as f64, &role.id) {
+            Ok(command_id) => println!("Bolus of {:.1} units sent to your pump (request #{}).", units, command_id),
+            Err(e) => println!("Failed to send bolus request: {}", unit.localize(&e)),
+        }
+        return;
+    }
+    let immediate_percent = if kind == 3 { input::read_float("Percent delivered now (10-90): ", 10.0, 90.0) } else { 0.0 };
+    let min_hours = extended_bolus::MIN_MINUTES as f32 / 60.0;
+    let max_hours = config::get().dosing.extended_bolus_max_minutes as f32 / 60.0;
+    let hours = input::read_float(&format!("Spread the rest over how many hours ({}-{}): ", min_hours, max_hours), min_hours, max_hours);
+    let immediate_units = f64::from(units * immediate_percent / 100.0);
+    let plan = BolusPlan {
+        immediate_units,
+        extended_units: f64::from(units) - immediate_units,
+        duration_minutes: (hours * 60.0).round() as u32,
+    };
+    match extended_bolus::request_bolus(conn, &patient.patient_id, &plan, &role.id) {
+        Ok(request_id) => println!(
+            "Bolus of {:.1} units sent to your pump: {:.2} now, {:.2} over {} minutes (request {}).",
+            units, plan.immediate_units, plan.extended_units, plan.duration_minutes, request_id
+        ),
+        Err(e) => println!("Failed to send bolus request: {}", unit.localize(&e)),
+    }
+}
 
-    // Insert activation code into DB
-    match insert_activation_code(conn, &activation_code, new_account_type, user_id.as_str(), role.id.as_str()) {
-        Ok(()) => {
-            let _ = add_caretaker_to_patient_account(conn,role.id.as_str(),user_id.as_str());
-            
-            println!(
-                "\n Caretaker activation code generated successfully!\n\
-                Please share this code with the caretaker so they can create their account.\n\
-                Activation Code: {}\n",
-                activation_code
-            );
+// ask for one contact field: Enter keeps the current value, "-" clears it
+fn read_contact_field(label: &str, current: &Option<String>, field: Field) -> Option<String> {
+    match input::read_optional(&format!("{} [{}]: ", label, current.as_deref().unwrap_or("not set")), field).as_deref() {
+        None => current.clone(),
+        Some("-") => None,
+        Some(value) => Some(value.to_string()),
+    }
+}
+
+// patients give or withdraw consent to each kind of sharing and see what they decided when
+fn consent_flow(conn: &Connection, role: &Role) {
+    let zone = timestamps::display_zone(conn, &role.id);
+    loop {
+        println!("\n=== Data Sharing Consent ===");
+        for (index, scope) in ConsentScope::ALL.iter().enumerate() {
+            let shared = match consent::has_consent(conn, &role.id, *scope) {
+                Ok(shared) => shared,
+                Err(e) => {
+                    println!("Error loading your consent: {}", e);
+                    return;
+                }
+            };
+            println!("{}. [{}] {}", index + 1, if shared { "yes" } else { "no" }, scope.description());
+        }
+        println!("{}. Show my consent history", ConsentScope::ALL.len() + 1);
+        println!("0. Back");
+        print!("Select an entry to change it: ");
+        let choice = input::read_choice();
+        if choice == 0 {
+            return;
+        }
+        if choice as usize == ConsentScope::ALL.len() + 1 {
+            match consent::consent_history(conn, &role.id) {
+                Ok(history) if history.is_empty() => println!("You have not recorded any consent yet; nothing is shared."),
+                Ok(history) => {
+                    for record in history {
+                        let decision = if record.granted { "given" } else { "withdrawn" };
+                        println!("  {} {} for {} by {}", timestamps::format(&record.recorded_at, zone), decision, record.scope.as_str(), record.recorded_by);
+                    }
+                }
+                Err(e) => println!("Error loading your consent history: {}", e),
+            }
+            continue;
+        }
+        let Some(scope) = usize::try_from(choice).ok().and_then(|choice| ConsentScope::ALL.get(choice.checked_sub(1)?)) else {
+            println!("Invalid choice.");
+            continue;
+        };
+        let granted = match consent::has_consent(conn, &role.id, *scope) {
+            Ok(shared) => !shared,
+            Err(e) => {
+                println!("Error loading your consent: {}", e);
+                continue;
+            }
+        };
+        let question = if granted { "Give consent" } else { "Withdraw consent" };
+        if !input::confirm(&format!("{}: {}? (y/N): ", question, scope.description().to_lowercase())) {
+            println!("No changes made.");
+            continue;
+        }
+        match consent::record_consent(conn, &role.id, &role.id, *scope, granted) {
+            Ok(()) if granted => println!("Consent given; it applies from now on."),
+            Ok(()) => println!("Consent withdrawn; it applies from now on, data already exported is not recalled."),
+            Err(e) => println!("Could not record your consent: {}", e),
+        }
+    }
+}
+
+// patients edit their own phone, email, address and emergency contact
+fn edit_contact_flow(conn: &Connection, role: &Role) {
+    let current = match get_patient_contact(conn, &role.id) {
+        Ok(Some(contact)) => contact,
+        Ok(None) => {
+            println!("No patient record found for this account.");
+            return;
         }
         Err(e) => {
-            eprintln!(" Error saving caretaker activation code: {}", e);
+            println!("Error loading contact details: {}", e);
+            return;
+        }
+    };
+    println!("\n--- Contact details (Enter keeps the current value, - clears it) ---");
+    let contact = PatientContact {
+        phone: read_contact_field("Phone", &current.phone, Field::PHONE),
+        email: read_contact_field("Email", &current.email, Field::EMAIL),
+        address: read_contact_field("Address", &current.address, Field::TEXT),
+        emergency_contact_name: read_contact_field("Emergency contact name", &current.emergency_contact_name, Field::NAME),
+        emergency_contact_phone: read_contact_field("Emergency contact phone", &current.emergency_contact_phone, Field::PHONE),
+    };
+    match update_own_contact_details(conn, role, &contact) {
+        Ok(changed) if changed.is_empty() => println!("Nothing changed."),
+        Ok(changed) => println!("Updated: {}", changed.join(", ")),
+        Err(e) => println!("Contact details not saved: {}", e),
+    }
+}
+
+// warn the patient when their recent trend predicts a low or high
+fn warn_predicted_glucose(conn: &Connection, patient_id: &str) {
+    let patient = match get_patient_by_id(conn, patient_id) {
+        Ok(Some(patient)) => patient,
+        Ok(None) => return,
+        Err(e) => {
+            tracing::error!("Error loading patient for prediction: {}", e);
+            return;
         }
+    };
+    match prediction::check_patient(conn, &patient) {
+        Ok(Some(event)) => println!("\n*** WARNING: {} ***\n", units::display_unit(conn, patient_id).localize(&event.message())),
+        Ok(None) => {}
+        Err(e) => tracing::error!("Glucose prediction failed: {}", e),
     }
 }
 
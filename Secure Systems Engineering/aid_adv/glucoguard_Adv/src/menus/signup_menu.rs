@@ -1,39 +1,41 @@
-use std::io::{self, Write};
-use rusqlite::{params, Connection, Result};
-use regex::Regex;
-use crate::db::queries::{validate_activation_code,create_user,check_user_name_exists,remove_activation_code}; 
+use rusqlite::{params, Connection};
+use crate::db::queries::{validate_activation_code,consume_activation_code,create_user,check_user_name_exists}; 
+use crate::input::{self, Field};
+use crate::input_validation::{is_valid_username_length, MAX_USERNAME_LENGTH};
+use crate::password_strength::check_new_password;
+use crate::i18n::{t, tf};
 
 pub fn show_signup_menu(conn: &Connection) -> Option<()> {
-    println!("\n---------- Account Sign Up ----------");
+    println!("\n---------- {} ----------", t("signup.title"));
 
     // Step 1: Get and validate activation code
-    let activation_code = read_input("Enter your activation code: ");
+    let activation_code = input::read_required(t("signup.activation_code"), Field::ID);
 
-    let code_info = match validate_activation_code(conn, &activation_code) {
-        Ok(Some(info)) => {
+    // checked again when the code is redeemed in step 4
+    match validate_activation_code(conn, &activation_code) {
+        Ok(Some(_)) => {
             // activation code is verified
-            info // store info to use user_type and user_id
         }
         Ok(None) => {
-            eprintln!(" Invalid activation code. Please contact your clinician.");
+            eprintln!(" {}", t("signup.invalid_code"));
             return None;
         }
-        Err(_err) => {
-            eprintln!(" Database error validating code");
+        Err(err) => {
+            eprintln!(" {}", tf("signup.db_error", &[&err]));
             return None;
         }
-    };
+    }
 
     // Step 2: Get valid username
     let username = loop {
-        let input = read_input("Choose a username: ");
-        if input.is_empty() {
-            eprintln!("Username cannot be empty.");
+        let input = input::read_required(t("signup.username"), Field::USERNAME);
+        if !is_valid_username_length(&input) {
+            eprintln!("{}", tf("signup.username_length", &[&MAX_USERNAME_LENGTH]));
             continue;
         }
         //  check if username already exists
         if let Ok(true) = check_user_name_exists(conn, &input) {
-            eprintln!("Please choose another username.");
+            eprintln!("{}", t("signup.username_taken"));
             continue;
         }
         break input;
@@ -41,15 +43,15 @@ pub fn show_signup_menu(conn: &Connection) -> Option<()> {
 
     // Step 3: Get and confirm password with validation
     let password = loop {
-        let input = read_input("Enter a strong password: ");
-        let confirm = read_input("Re-enter password to confirm: ");
-
-        if input != confirm {
-            eprintln!(" Passwords do not match. Try again.");
+        let Some(input) = input::read_password(t("signup.password")) else {
+            continue;
+        };
+        if !input::confirm_password(&input, t("signup.password_confirm")) {
+            eprintln!(" {}", t("signup.password_mismatch"));
             continue;
         }
 
-        if let Err(err) = validate_password_strength(&input) {
+        if let Err(err) = check_new_password(&input, &[&username]) {
             eprintln!(" {}", err);
             continue;
         }
@@ -58,50 +60,42 @@ pub fn show_signup_menu(conn: &Connection) -> Option<()> {
     };
 
         
-    // Step 4: Create the user
+    // Step 4: redeem the code and create the user together, so a code is never spent without an account
+    // and two signups with the same code cannot both succeed
+    let tx = match conn.unchecked_transaction() {
+        Ok(tx) => tx,
+        Err(err) => {
+            eprintln!(" {}", tf("signup.db_error", &[&err]));
+            return None;
+        }
+    };
+    let code_info = match consume_activation_code(&tx, &activation_code) {
+        Ok(Some(info)) => info,
+        Ok(_) => {
+            eprintln!(" {}", t("signup.code_spent"));
+            return None;
+        }
+        Err(err) => {
+            eprintln!(" {}", tf("signup.db_error", &[&err]));
+            return None;
+        }
+    };
     if let Err(err) = create_user(
-        conn,
+        &tx,
         &username,
         &password,
         &code_info.user_type,
         Some(code_info.user_id.clone()), // use user_id from activation code
     ) {
-        eprintln!(" Failed to create user: {}", err);
+        eprintln!(" {}", tf("signup.create_failed", &[&err]));
+        return None;
+    }
+    if let Err(err) = tx.commit() {
+        eprintln!(" {}", tf("signup.create_failed", &[&err]));
         return None;
     }
 
-    println!("✅ Account created successfully for username '{}'.", username);
-    // remove activation code from table to indicate code used
-    let _ = remove_activation_code(conn,&activation_code);
+    println!("✅ {}", tf("signup.created", &[&username]));
     Some(())
 }
 
-fn read_input(prompt: &str) -> String {
-    print!("{}", prompt);
-    io::stdout().flush().unwrap();
-    let mut input = String::new();
-    io::stdin().read_line(&mut input).unwrap();
-    input.trim().to_string()
-}
-
-fn validate_password_strength(password: &str) -> Result<(), &'static str> {
-    if password.len() < 8 {
-        return Err("Password must be at least 8 characters long.");
-    }
-
-    let uppercase = Regex::new(r"[A-Z]").unwrap();
-    let lowercase = Regex::new(r"[a-z]").unwrap();
-    let special = Regex::new(r"[!@#$%^&*(),.?\:{}|<>']").unwrap();
-
-    if !uppercase.is_match(password) {
-        return Err("Password must contain at least one uppercase letter.");
-    }
-    if !lowercase.is_match(password) {
-        return Err("Password must contain at least one lowercase letter.");
-    }
-    if !special.is_match(password) {
-        return Err("Password must contain at least one special character.");
-    }
-
-    Ok(())
-}
\ No newline at end of file
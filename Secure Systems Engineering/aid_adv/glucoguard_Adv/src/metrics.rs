@@ -0,0 +1,140 @@
+/*
+Prometheus metrics for `glucoguard serve` at GET /metrics, in the text exposition format.
+Values are read from the database at scrape time, so logins from the menus, the TUI and the API are all
+counted however many processes share the database. Login counters are counted from the audit trail.
+The endpoint is off until api.metrics_token is set and then needs "Authorization: Bearer <token>".
+*/
+use crate::timestamps;
+use rusqlite::{params, Connection};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::time::Instant;
+
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+// (channel label, success action, failure action) as written to the audit trail
+const LOGIN_ACTIONS: [(&str, &str, &str); 3] = [
+    ("menu", "Login succeeded", "Login failed"),
+    ("api", "API login succeeded", "API login failed"),
+    ("tui", "TUI login succeeded", "TUI login failed"),
+];
+
+// compares digests so the time taken does not depend on how much of the token matched
+pub fn token_matches(expected: &str, presented: &str) -> bool {
+    let expected = Sha256::digest(expected.as_bytes());
+    let presented = Sha256::digest(presented.as_bytes());
+    expected.iter().zip(presented.iter()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn count_action(conn: &Connection, action: &str) -> rusqlite::Result<i64> {
+    conn.query_row("SELECT COUNT(*) FROM audit_events WHERE action = ?1", [action], |row| row.get(0))
+}
+
+// every metric in the exposition format; the database probe is timed as part of the scrape
+pub fn render(conn: &Connection) -> rusqlite::Result<String> {
+    let started = Instant::now();
+    conn.query_row("SELECT 1", [], |row| row.get::<_, i64>(0))?;
+    let latency = started.elapsed().as_secs_f64();
+
+    let mut out = String::new();
+    header(&mut out, "glucoguard_logins_total", "counter", "Successful logins recorded in the audit trail.");
+    for (channel, succeeded, _) in LOGIN_ACTIONS {
+        let _ = writeln!(out, "glucoguard_logins_total{{channel=\"{}\"}} {}", channel, count_action(conn, succeeded)?);
+    }
+    header(&mut out, "glucoguard_failed_logins_total", "counter", "Failed logins recorded in the audit trail.");
+    for (channel, _, failed) in LOGIN_ACTIONS {
+        let _ = writeln!(out, "glucoguard_failed_logins_total{{channel=\"{}\"}} {}", channel, count_action(conn, failed)?);
+    }
+
+    // extended boluses store their later parts ahead of time, those are not delivered yet;
+    // simulated training patients are left out of the dose and alert figures
+    let mut doses = conn.prepare(
+        "SELECT action_type, COUNT(*), COALESCE(SUM(dosage_units), 0) FROM insulin_doses
+         WHERE dosage_time <= ?1 AND patient_id NOT IN (SELECT patient_id FROM patients WHERE is_simulated = 1)
+         GROUP BY action_type ORDER BY action_type",
+    )?;
+    let doses = doses
+        .query_map(params![timestamps::now()], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?, row.get::<_, f64>(2)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    header(&mut out, "glucoguard_doses_delivered_total", "counter", "Insulin doses delivered, by type.");
+    for (action_type, count, _) in &doses {
+        let _ = writeln!(out, "glucoguard_doses_delivered_total{{type=\"{}\"}} {}", escape_label(action_type), count);
+    }
+    header(&mut out, "glucoguard_insulin_delivered_units_total", "counter", "Insulin units delivered, by type.");
+    for (action_type, _, units) in &doses {
+        let _ = writeln!(out, "glucoguard_insulin_delivered_units_total{{type=\"{}\"}} {}", escape_label(action_type), units);
+    }
+
+    let mut open = conn.prepare(
+        "SELECT alert_type, COUNT(*) FROM alerts
+         WHERE is_resolved = 0 AND patient_id NOT IN (SELECT patient_id FROM patients WHERE is_simulated = 1)
+         GROUP BY alert_type ORDER BY alert_type",
+    )?;
+    let open = open
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    header(&mut out, "glucoguard_alerts_open", "gauge", "Unresolved patient alerts, by type.");
+    for (alert_type, count) in open {
+        let _ = writeln!(out, "glucoguard_alerts_open{{type=\"{}\"}} {}", escape_label(&alert_type), count);
+    }
+    let security: i64 = conn.query_row("SELECT COUNT(*) FROM security_alerts WHERE acknowledged_at IS NULL", [], |row| row.get(0))?;
+    header(&mut out, "glucoguard_security_alerts_open", "gauge", "Security alerts not yet acknowledged by an administrator.");
+    let _ = writeln!(out, "glucoguard_security_alerts_open {}", security);
+
+    header(&mut out, "glucoguard_db_latency_seconds", "gauge", "Time a probe query took during this scrape.");
+    let _ = writeln!(out, "glucoguard_db_latency_seconds {:.6}", latency);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    #[test]
+    fn render_counts_logins_future_doses_and_open_alerts() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        for action in ["Login succeeded", "API login failed", "API login failed"] {
+            conn.execute(
+                "INSERT INTO audit_events (occurred_at, level, action, details) VALUES (datetime('now'), 'INFO', ?1, '{}')",
+                [action],
+            )
+            .unwrap();
+        }
+        for offset in ["-1 hours", "+1 hours"] {
+            conn.execute(
+                "INSERT INTO insulin_logs (patient_id, action_type, dosage_units, requested_by, dosage_time)
+                 VALUES (?1, 'bolus', 2.0, ?1, datetime('now', ?2))",
+                params![fixtures.patient_id, offset],
+            )
+            .unwrap();
+        }
+        conn.execute(
+            "INSERT INTO alerts (patient_id, alert_type, alert_message, alert_time, is_resolved) VALUES (?1, 'LOW', 'Low glucose', datetime('now'), 0)",
+            [&fixtures.patient_id],
+        )
+        .unwrap();
+
+        let text = render(&conn).unwrap();
+        assert!(text.contains("glucoguard_logins_total{channel=\"menu\"} 1\n"), "{}", text);
+        assert!(text.contains("glucoguard_failed_logins_total{channel=\"api\"} 2\n"));
+        assert!(text.contains("glucoguard_doses_delivered_total{type=\"bolus\"} 1\n"));
+        assert!(text.contains("glucoguard_insulin_delivered_units_total{type=\"bolus\"} 2\n"));
+        assert!(text.contains("glucoguard_alerts_open{type=\"LOW\"} 1\n"));
+        assert!(text.contains("# TYPE glucoguard_db_latency_seconds gauge\n"));
+
+        assert!(token_matches("scrape-secret", "scrape-secret"));
+        assert!(!token_matches("scrape-secret", "scrape-secreT"));
+        assert!(!token_matches("scrape-secret", ""));
+    }
+}
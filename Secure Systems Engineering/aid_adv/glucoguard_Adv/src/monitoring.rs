@@ -0,0 +1,327 @@
+/*
+Security monitoring: watches the audit trail for suspicious patterns and raises alerts for administrators.
+Three rules, tuned in the [monitoring] section of glucoguard.toml:
+  failed_logins     a username fails to log in failed_login_limit times within failed_login_window_minutes
+  off_hours_access  a privileged action (monitoring.privileged_actions, or an admin logging in) happens
+                    outside office hours in ui.timezone
+  mass_export       one user performs export_limit exports (monitoring.export_actions) within export_window_minutes
+The session cleanup task scans the events logged since its last run. Open alerts are shown in the admin
+menu until an administrator acknowledges them; raising and acknowledging them is itself audited.
+*/
+use crate::config::MonitoringConfig;
+use crate::logger::SECURITY_TARGET;
+use crate::timestamps;
+use chrono::{Duration, Timelike};
+use chrono_tz::Tz;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+
+// audit actions of a failed password check, from the menus, the API and the TUI
+pub const FAILED_LOGIN_ACTIONS: [&str; 3] = ["Login failed", "API login failed", "TUI login failed"];
+
+pub const RULE_FAILED_LOGINS: &str = "failed_logins";
+pub const RULE_OFF_HOURS: &str = "off_hours_access";
+pub const RULE_MASS_EXPORT: &str = "mass_export";
+
+#[derive(Debug, Clone)]
+pub struct SecurityAlert {
+    pub alert_id: i64,
+    pub rule: String,
+    pub subject: String,
+    pub message: String,
+    pub raised_at: String,
+}
+
+// an audit event as the rules see it
+struct ScannedEvent {
+    event_id: i64,
+    occurred_at: String,
+    action: String,
+    user_id: Option<String>,
+    username: Option<String>,
+    // current role of user_id, when the account exists
+    role: Option<String>,
+}
+
+impl ScannedEvent {
+    fn who(&self) -> &str {
+        self.username.as_deref().or(self.user_id.as_deref()).unwrap_or("unknown")
+    }
+
+    fn is_privileged(&self, rules: &MonitoringConfig) -> bool {
+        rules.privileged_actions.iter().any(|action| action == &self.action)
+            || (self.role.as_deref() == Some("admin") && self.action.to_lowercase().ends_with("login succeeded"))
+    }
+}
+
+// whether a local hour falls in office hours; a start after the end spans midnight
+fn in_office_hours(hour: u32, start: u32, end: u32) -> bool {
+    if start <= end {
+        (start..end).contains(&hour)
+    } else {
+        hour >= start || hour < end
+    }
+}
+
+fn sql_list(count: usize, first: usize) -> String {
+    (first..first + count).map(|n| format!("?{}", n)).collect::<Vec<_>>().join(", ")
+}
+
+fn new_events(conn: &Connection, after: i64) -> rusqlite::Result<Vec<ScannedEvent>> {
+    let mut stmt = conn.prepare(
+        "SELECT e.event_id, e.occurred_at, e.action, e.user_id, e.username, u.role
+         FROM audit_events e LEFT JOIN users u ON u.id = e.user_id
+         WHERE e.event_id > ?1
+         ORDER BY e.event_id",
+    )?;
+    let rows = stmt.query_map([after], |row| {
+        Ok(ScannedEvent {
+            event_id: row.get(0)?,
+            occurred_at: row.get(1)?,
+            action: row.get(2)?,
+            user_id: row.get(3)?,
+            username: row.get(4)?,
+            role: row.get(5)?,
+        })
+    })?;
+    rows.collect()
+}
+
+fn raise(conn: &Connection, rule: &str, subject: &str, message: &str, events: (i64, i64)) -> rusqlite::Result<i64> {
+    conn.execute(
+        "INSERT INTO security_alerts (rule, subject, message, first_event_id, last_event_id, raised_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![rule, subject, message, events.0, events.1, timestamps::now()],
+    )?;
+    let alert_id = conn.last_insert_rowid();
+    tracing::warn!(target: SECURITY_TARGET, alert_id, rule = %rule, subject = %subject, "Security alert raised: {}", message);
+    Ok(alert_id)
+}
+
+// a rule that counts events of one subject within a sliding window
+struct Burst<'a> {
+    rule: &'static str,
+    // audit_events column naming the subject
+    column: &'static str,
+    actions: &'a [String],
+    window_minutes: u32,
+    limit: u32,
+    // "failed logins for", "exports by"
+    describe: &'static str,
+}
+
+impl Burst<'_> {
+    /*
+    Raise the rule for `subject` when `limit` or more of its actions fall in the window up to `latest`.
+    Events already covered by an alert for the same rule and subject start a new count, so one burst
+    raises one alert.
+    */
+    fn check(&self, conn: &Connection, subject: &str, latest: &ScannedEvent) -> rusqlite::Result<Option<i64>> {
+        let Some(latest_time) = timestamps::parse(&latest.occurred_at) else {
+            return Ok(None);
+        };
+        let window_start = timestamps::to_storage(latest_time - Duration::minutes(self.window_minutes as i64));
+        let covered: i64 = conn
+            .query_row(
+                "SELECT MAX(last_event_id) FROM security_alerts WHERE rule = ?1 AND subject = ?2",
+                params![self.rule, subject],
+                |row| row.get::<_, Option<i64>>(0),
+            )?
+            .unwrap_or(0);
+        let sql = format!(
+            "SELECT event_id FROM audit_events
+             WHERE {} = ?1 AND event_id > ?2 AND event_id <= ?3 AND occurred_at >= ?4 AND action IN ({})
+             ORDER BY event_id",
+            self.column,
+            sql_list(self.actions.len(), 5)
+        );
+        let mut values: Vec<Value> = vec![subject.to_string().into(), covered.into(), latest.event_id.into(), window_start.into()];
+        values.extend(self.actions.iter().map(|action| Value::from(action.clone())));
+        let mut stmt = conn.prepare(&sql)?;
+        let ids = stmt.query_map(params_from_iter(values), |row| row.get::<_, i64>(0))?.collect::<rusqlite::Result<Vec<_>>>()?;
+        if ids.len() < self.limit as usize {
+            return Ok(None);
+        }
+        let message = format!("{} {} {} within {} minutes", ids.len(), self.describe, subject, self.window_minutes);
+        raise(conn, self.rule, subject, &message, (ids[0], latest.event_id)).map(Some)
+    }
+}
+
+/*
+Check the audit events logged since the last scan against the rules, with office hours in `zone`.
+Returns the ids of the alerts raised. The scan position is saved in the same transaction as the alerts,
+so every event is looked at once.
+*/
+pub fn scan(conn: &Connection, rules: &MonitoringConfig, zone: Tz) -> rusqlite::Result<Vec<i64>> {
+    let tx = conn.unchecked_transaction()?;
+    let last_event_id: i64 = tx
+        .query_row("SELECT last_event_id FROM security_monitor_state WHERE id = 1", [], |row| row.get(0))
+        .optional()?
+        .unwrap_or(0);
+    let events = new_events(&tx, last_event_id)?;
+    let Some(newest) = events.last().map(|event| event.event_id) else {
+        return Ok(Vec::new());
+    };
+
+    let failed_logins: Vec<String> = FAILED_LOGIN_ACTIONS.iter().map(|action| action.to_string()).collect();
+    let bursts = [
+        Burst {
+            rule: RULE_FAILED_LOGINS,
+            column: "username",
+            actions: &failed_logins,
+            window_minutes: rules.failed_login_window_minutes,
+            limit: rules.failed_login_limit,
+            describe: "failed logins for",
+        },
+        Burst {
+            rule: RULE_MASS_EXPORT,
+            column: "user_id",
+            actions: &rules.export_actions,
+            window_minutes: rules.export_window_minutes,
+            limit: rules.export_limit,
+            describe: "exports by",
+        },
+    ];
+    let mut raised = Vec::new();
+    // each subject is checked once per scan, from its newest event
+    let mut checked: Vec<(&str, &str)> = Vec::new();
+    for event in events.iter().rev() {
+        for burst in bursts.iter().filter(|burst| burst.actions.contains(&event.action)) {
+            let subject = if burst.column == "username" { &event.username } else { &event.user_id };
+            let Some(subject) = subject.as_deref() else {
+                continue;
+            };
+            if !checked.contains(&(burst.rule, subject)) {
+                checked.push((burst.rule, subject));
+                raised.extend(burst.check(&tx, subject, event)?);
+            }
+        }
+    }
+
+    for event in events.iter().filter(|event| event.is_privileged(rules)) {
+        let Some(time) = timestamps::parse(&event.occurred_at) else {
+            continue;
+        };
+        if in_office_hours(time.with_timezone(&zone).hour(), rules.office_hours_start, rules.office_hours_end) {
+            continue;
+        }
+        let message = format!("{} by {} at {}, outside office hours", event.action, event.who(), timestamps::format_time(time, zone));
+        raised.push(raise(&tx, RULE_OFF_HOURS, event.who(), &message, (event.event_id, event.event_id))?);
+    }
+
+    tx.execute(
+        "INSERT INTO security_monitor_state (id, last_event_id) VALUES (1, ?1)
+         ON CONFLICT (id) DO UPDATE SET last_event_id = excluded.last_event_id",
+        [newest],
+    )?;
+    tx.commit()?;
+    Ok(raised)
+}
+
+// alerts no administrator has acknowledged yet, oldest first
+pub fn open_alerts(conn: &Connection) -> rusqlite::Result<Vec<SecurityAlert>> {
+    let mut stmt = conn.prepare(
+        "SELECT alert_id, rule, subject, message, raised_at FROM security_alerts
+         WHERE acknowledged_at IS NULL
+         ORDER BY alert_id",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(SecurityAlert {
+            alert_id: row.get(0)?,
+            rule: row.get(1)?,
+            subject: row.get(2)?,
+            message: row.get(3)?,
+            raised_at: row.get(4)?,
+        })
+    })?;
+    rows.collect()
+}
+
+// returns false when the alert does not exist or was already acknowledged
+pub fn acknowledge(conn: &Connection, alert_id: i64, acknowledged_by: &str) -> rusqlite::Result<bool> {
+    let updated = conn.execute(
+        "UPDATE security_alerts SET acknowledged_at = ?2, acknowledged_by = ?3 WHERE alert_id = ?1 AND acknowledged_at IS NULL",
+        params![alert_id, timestamps::now(), acknowledged_by],
+    )?;
+    if updated > 0 {
+        tracing::info!(target: SECURITY_TARGET, user_id = %acknowledged_by, alert_id, "Security alert acknowledged");
+    }
+    Ok(updated > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{self, AuditEvent};
+    use crate::test_utils;
+
+    fn log(conn: &Connection, occurred_at: &str, action: &str, user_id: Option<&str>, username: Option<&str>) {
+        let event = AuditEvent {
+            occurred_at: occurred_at.to_string(),
+            level: "INFO".to_string(),
+            action: action.to_string(),
+            user_id: user_id.map(str::to_string),
+            username: username.map(str::to_string),
+            details: "{}".to_string(),
+        };
+        audit::insert_event(conn, &event).unwrap();
+    }
+
+    #[test]
+    fn failed_login_bursts_and_mass_exports_raise_one_alert_each() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let rules = MonitoringConfig::default();
+
+        // four failures spread over an hour stay below the limit, five within the window do not
+        for minute in ["00", "20", "40"] {
+            log(&conn, &format!("2026-03-02 10:{}:00", minute), "Login failed", None, Some("dr.fixture"));
+        }
+        for second in 0..5 {
+            log(&conn, &format!("2026-03-02 11:00:0{}", second), "API login failed", None, Some("dr.fixture"));
+        }
+        for second in 0..4 {
+            log(&conn, &format!("2026-03-02 11:00:0{}", second), "Login failed", None, Some("someone.else"));
+        }
+        let raised = scan(&conn, &rules, Tz::UTC).unwrap();
+        assert_eq!(raised.len(), 1);
+        let alerts = open_alerts(&conn).unwrap();
+        assert_eq!((alerts[0].rule.as_str(), alerts[0].subject.as_str()), (RULE_FAILED_LOGINS, "dr.fixture"));
+
+        // the next failure is part of the same burst; the events are only scanned once
+        log(&conn, "2026-03-02 11:01:00", "Login failed", None, Some("dr.fixture"));
+        assert!(scan(&conn, &rules, Tz::UTC).unwrap().is_empty());
+        assert!(scan(&conn, &rules, Tz::UTC).unwrap().is_empty());
+
+        for second in 0..rules.export_limit {
+            log(&conn, &format!("2026-03-02 12:00:{:02}", second), "Patient history exported", Some(&fixtures.clinician_id), None);
+        }
+        let raised = scan(&conn, &rules, Tz::UTC).unwrap();
+        assert_eq!(raised.len(), 1);
+        assert!(acknowledge(&conn, raised[0], &fixtures.admin_id).unwrap());
+        assert!(!acknowledge(&conn, raised[0], &fixtures.admin_id).unwrap());
+        assert_eq!(open_alerts(&conn).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn privileged_actions_outside_office_hours_are_flagged_in_the_clinic_zone() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let rules = MonitoringConfig::default();
+        let new_york = timestamps::parse_zone("America/New_York").unwrap();
+
+        // 14:00 UTC is 10:00 in New York, 03:00 UTC is 23:00 the evening before
+        log(&conn, "2026-03-02 14:00:00", "Login succeeded", Some(&fixtures.admin_id), Some("admin.fixture"));
+        log(&conn, "2026-03-03 03:00:00", "User role changed", Some(&fixtures.clinician_id), None);
+        log(&conn, "2026-03-03 03:05:00", "Login succeeded", Some(&fixtures.admin_id), Some("admin.fixture"));
+        log(&conn, "2026-03-03 03:10:00", "Login succeeded", Some(&fixtures.clinician_id), Some("dr.fixture"));
+        let raised = scan(&conn, &rules, new_york).unwrap();
+        assert_eq!(raised.len(), 2);
+        let alerts = open_alerts(&conn).unwrap();
+        assert!(alerts.iter().all(|alert| alert.rule == RULE_OFF_HOURS));
+        assert!(alerts[1].message.contains("admin.fixture"));
+
+        assert!(in_office_hours(23, 22, 6));
+        assert!(!in_office_hours(12, 22, 6));
+    }
+}
@@ -0,0 +1,202 @@
+// MQTT ingestion of CGM readings from sensor gateways (`glucoguard mqtt`)
+// Each message is one JSON reading from a CGM paired to the patient (see devices.rs), e.g.
+// {"device_id": "CGM-1", "patient_id": "...", "glucose_level": 142.0, "status": "normal",
+//  "reading_time": "2026-01-01T08:00:00Z", "signed_at_ms": 1767254400000, "signature": "<hex>"}
+// signature is the device's Ed25519 signature over "reading", device_id, patient_id, signed_at_ms,
+// glucose_level with 1 decimal, status and reading_time (empty when absent), joined with "\n"
+use crate::cgm::{self, CgmReading};
+use crate::config::{self, MqttConfig};
+use crate::db::initialize;
+use crate::devices::{self, DeviceType};
+use chrono::{DateTime, Duration as ChronoDuration, NaiveDateTime, Utc};
+use rumqttc::{Client, Event, MqttOptions, Packet, QoS, Transport};
+use rusqlite::Connection;
+use serde::Deserialize;
+use std::error::Error;
+use std::time::Duration;
+
+// readings older than this are assumed to be replayed or stale and are dropped
+const MAX_READING_AGE_HOURS: i64 = 24;
+// tolerated gateway clock drift into the future
+const MAX_CLOCK_SKEW_MINUTES: i64 = 5;
+// wait between reconnect attempts when the broker is unreachable
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReadingPayload {
+    device_id: String,
+    patient_id: String,
+    glucose_level: f64,
+    #[serde(default = "default_status")]
+    status: String,
+    // sensor timestamp, RFC 3339 or "YYYY-MM-DD HH:MM:SS" in UTC; receive time when absent
+    reading_time: Option<String>,
+    signed_at_ms: i64,
+    signature: String,
+}
+
+// who sent a reading and the signed fields, checked against the device registry
+struct DeviceProof {
+    device_id: String,
+    signed_at_ms: i64,
+    parts: Vec<String>,
+    signature: String,
+}
+
+fn default_status() -> String {
+    "normal".to_string()
+}
+
+fn parse_reading_time(value: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").map(|time| time.and_utc()))
+        .map_err(|_| format!("invalid reading_time '{}'", value))
+}
+
+// decode and check one message, returns the reading, its timestamp ("YYYY-MM-DD HH:MM:SS" UTC) and the device proof
+fn parse_payload(payload: &[u8], now: DateTime<Utc>) -> Result<(CgmReading, Option<String>, DeviceProof), String> {
+    let payload: ReadingPayload = serde_json::from_slice(payload).map_err(|e| format!("invalid JSON payload: {}", e))?;
+    let patient_id = payload.patient_id.trim();
+    if patient_id.is_empty() {
+        return Err("missing patient_id".to_string());
+    }
+    cgm::check_glucose_level(payload.glucose_level)?;
+    if payload.status.trim().is_empty() || payload.status.len() > 32 {
+        return Err("status must be 1-32 characters".to_string());
+    }
+
+    let reading_time = match payload.reading_time.as_deref() {
+        Some(value) => {
+            let time = parse_reading_time(value.trim())?;
+            if time > now + ChronoDuration::minutes(MAX_CLOCK_SKEW_MINUTES) {
+                return Err(format!("reading_time {} is in the future", value));
+            }
+            if time < now - ChronoDuration::hours(MAX_READING_AGE_HOURS) {
+                return Err(format!("reading_time {} is older than {} hours", value, MAX_READING_AGE_HOURS));
+            }
+            Some(time.format("%Y-%m-%d %H:%M:%S").to_string())
+        }
+        None => None,
+    };
+
+    let proof = DeviceProof {
+        device_id: payload.device_id.clone(),
+        signed_at_ms: payload.signed_at_ms,
+        parts: vec![
+            "reading".to_string(),
+            payload.device_id.clone(),
+            payload.patient_id.clone(),
+            payload.signed_at_ms.to_string(),
+            format!("{:.1}", payload.glucose_level),
+            payload.status.clone(),
+            payload.reading_time.clone().unwrap_or_default(),
+        ],
+        signature: payload.signature,
+    };
+
+    Ok((
+        CgmReading {
+            patient_id: patient_id.to_string(),
+            glucose_level: payload.glucose_level,
+            status: payload.status.trim().to_string(),
+        },
+        reading_time,
+        proof,
+    ))
+}
+
+// store one message, invalid payloads and unknown patients are logged and dropped
+fn handle_message(conn: &Connection, topic: &str, payload: &[u8]) -> rusqlite::Result<bool> {
+    let (reading, reading_time, proof) = match parse_payload(payload, Utc::now()) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            tracing::warn!(topic = %topic, "Dropping MQTT reading: {}", e);
+            return Ok(false);
+        }
+    };
+    if !cgm::patient_exists(conn, &reading.patient_id)? {
+        tracing::warn!(topic = %topic, patient_id = %reading.patient_id, "Dropping MQTT reading for unknown patient");
+        return Ok(false);
+    }
+    let parts: Vec<&str> = proof.parts.iter().map(String::as_str).collect();
+    if let Err(e) = devices::authenticate_device(conn, &proof.device_id, &reading.patient_id, DeviceType::Cgm, proof.signed_at_ms, &parts, &proof.signature) {
+        tracing::warn!(topic = %topic, device_id = %proof.device_id, "Dropping MQTT reading from unpaired or unverified device: {}", e);
+        return Ok(false);
+    }
+    cgm::insert_reading_at(conn, &reading, reading_time.as_deref())?;
+    tracing::debug!(topic = %topic, patient_id = %reading.patient_id, glucose_level = reading.glucose_level, "MQTT reading stored");
+    Ok(true)
+}
+
+fn mqtt_options(settings: &MqttConfig) -> MqttOptions {
+    let mut options = MqttOptions::new(&settings.client_id, &settings.host, settings.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    if !settings.username.is_empty() {
+        options.set_credentials(&settings.username, &settings.password);
+    }
+    if settings.tls {
+        options.set_transport(Transport::tls_with_default_config());
+    }
+    options
+}
+
+// subscribe and store readings until the process is stopped
+pub fn subscribe(conn: &Connection, settings: &MqttConfig) -> Result<(), Box<dyn Error>> {
+    let (client, mut connection) = Client::new(mqtt_options(settings), 64);
+    println!(
+        "Listening for CGM readings on mqtt://{}:{} topic '{}'",
+        settings.host, settings.port, settings.topic
+    );
+    tracing::info!(host = %settings.host, port = settings.port, topic = %settings.topic, "MQTT ingestion started");
+
+    let (mut stored, mut dropped) = (0u64, 0u64);
+    for notification in connection.iter() {
+        match notification {
+            Ok(Event::Incoming(Packet::Publish(publish))) => {
+                if handle_message(conn, &publish.topic, &publish.payload)? {
+                    stored += 1;
+                } else {
+                    dropped += 1;
+                }
+                if (stored + dropped) % 100 == 0 {
+                    tracing::info!(stored, dropped, "MQTT ingestion progress");
+                }
+            }
+            // clean sessions drop subscriptions, so subscribe after every (re)connect
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                tracing::info!(host = %settings.host, "Connected to MQTT broker");
+                client.subscribe(&settings.topic, QoS::AtLeastOnce)?;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                tracing::error!(host = %settings.host, "MQTT connection error: {}", e);
+                std::thread::sleep(RECONNECT_DELAY);
+            }
+        }
+    }
+    Ok(())
+}
+
+// `glucoguard mqtt [--broker <host:port>] [--topic <filter>]`
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut settings = config::get().mqtt.clone();
+    let mut rest = args.iter();
+    while let Some(flag) = rest.next() {
+        let value = rest.next().ok_or(format!("Missing value for '{}'", flag))?;
+        match flag.as_str() {
+            "--broker" => {
+                let (host, port) = value
+                    .rsplit_once(':')
+                    .ok_or("--broker expects <host:port>")?;
+                settings.host = host.to_string();
+                settings.port = port.parse().map_err(|_| format!("Invalid broker port '{}'", port))?;
+            }
+            "--topic" => settings.topic = value.clone(),
+            _ => return Err("usage: glucoguard mqtt [--broker <host:port>] [--topic <filter>]".into()),
+        }
+    }
+    let conn = initialize::establish_connection().map_err(|e| format!("Failed to open database: {}", e))?;
+    subscribe(&conn, &settings)
+}
@@ -0,0 +1,266 @@
+// Optional uploader pushing new glucose entries and insulin treatments to a Nightscout site
+// Runs with `glucoguard nightscout [--once]`, or next to `serve` when nightscout.enabled = true
+// A Nightscout site belongs to one person, so only nightscout.patient_id is uploaded
+use crate::config::{self, NightscoutConfig};
+use crate::db::initialize;
+use crate::db::pool::Db;
+use crate::runtime;
+use chrono::NaiveDateTime;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+use std::error::Error;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// records per POST, Nightscout accepts arrays
+const BATCH_SIZE: u32 = 100;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(2);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+const DEVICE_NAME: &str = "glucoguard";
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Entries,
+    Treatments,
+}
+
+impl Kind {
+    fn name(&self) -> &'static str {
+        match self {
+            Kind::Entries => "entries",
+            Kind::Treatments => "treatments",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SyncSummary {
+    pub entries: usize,
+    pub treatments: usize,
+}
+
+fn last_synced(conn: &Connection, patient_id: &str, kind: Kind) -> rusqlite::Result<i64> {
+    conn.query_row(
+        "SELECT last_id FROM nightscout_sync WHERE patient_id = ?1 AND kind = ?2",
+        params![patient_id, kind.name()],
+        |row| row.get(0),
+    )
+    .optional()
+    .map(|id| id.unwrap_or(0))
+}
+
+fn mark_synced(conn: &Connection, patient_id: &str, kind: Kind, last_id: i64) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO nightscout_sync (patient_id, kind, last_id, synced_at)
+         VALUES (?1, ?2, ?3, datetime('now'))
+         ON CONFLICT(patient_id, kind) DO UPDATE SET last_id = excluded.last_id, synced_at = excluded.synced_at",
+        params![patient_id, kind.name(), last_id],
+    )?;
+    Ok(())
+}
+
+// database time ("YYYY-MM-DD HH:MM:SS" UTC) as epoch millis and ISO 8601
+fn nightscout_time(value: &str) -> Option<(i64, String)> {
+    let time = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").ok()?.and_utc();
+    Some((time.timestamp_millis(), time.format("%Y-%m-%dT%H:%M:%S.000Z").to_string()))
+}
+
+// next batch of documents after `after_id`, with the highest id looked at
+// rows with unreadable timestamps are skipped but still advance the position
+fn pending(conn: &Connection, patient_id: &str, kind: Kind, after_id: i64) -> rusqlite::Result<(Vec<Value>, Option<i64>)> {
+    let sql = match kind {
+        Kind::Entries => {
+            "SELECT reading_id, reading_time, glucose_level, NULL
+             FROM glucose_readings
+             WHERE patient_id = ?1 AND reading_id > ?2 AND typeof(glucose_level) IN ('integer', 'real')
+             ORDER BY reading_id ASC LIMIT ?3"
+        }
+        Kind::Treatments => {
+            "SELECT dosage_id, dosage_time, dosage_units, action_type
+             FROM insulin_doses
+             WHERE patient_id = ?1 AND dosage_id > ?2 AND typeof(dosage_units) IN ('integer', 'real')
+             ORDER BY dosage_id ASC LIMIT ?3"
+        }
+    };
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map(params![patient_id, after_id, BATCH_SIZE], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, f64>(2)?,
+            row.get::<_, Option<String>>(3)?,
+        ))
+    })?;
+
+    let mut documents = Vec::new();
+    let mut last_id = None;
+    for row in rows {
+        let (id, time, value, action_type) = row?;
+        last_id = Some(id);
+        let Some((millis, iso)) = nightscout_time(&time) else {
+            tracing::warn!(kind = kind.name(), id, time = %time, "Skipping record with unreadable timestamp");
+            continue;
+        };
+        documents.push(match kind {
+            Kind::Entries => json!({
+                "type": "sgv",
+                "sgv": value.round() as i64,
+                "date": millis,
+                "dateString": iso,
+                "device": DEVICE_NAME
+            }),
+            Kind::Treatments => {
+                let action_type = action_type.unwrap_or_default();
+                json!({
+                    "eventType": if action_type == "bolus" { "Correction Bolus" } else { "Note" },
+                    "insulin": value,
+                    "created_at": iso,
+                    "enteredBy": DEVICE_NAME,
+                    "notes": format!("{} insulin dose", action_type)
+                })
+            }
+        });
+    }
+    Ok((documents, last_id))
+}
+
+pub struct Uploader {
+    agent: ureq::Agent,
+    settings: NightscoutConfig,
+    // Nightscout expects the SHA-1 of the API secret in the api-secret header
+    secret_hash: String,
+}
+
+impl Uploader {
+    pub fn new(settings: NightscoutConfig) -> Self {
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(REQUEST_TIMEOUT))
+            .build()
+            .into();
+        let secret_hash = hex::encode(Sha1::digest(settings.api_secret.as_bytes()));
+        Self { agent, settings, secret_hash }
+    }
+
+    fn post(&self, kind: Kind, documents: &[Value]) -> Result<(), ureq::Error> {
+        let url = format!("{}/api/v1/{}", self.settings.url.trim_end_matches('/'), kind.name());
+        self.agent
+            .post(&url)
+            .header("api-secret", &self.secret_hash)
+            .send_json(documents)?;
+        Ok(())
+    }
+
+    // retry network and server errors with exponential backoff; other client errors are permanent
+    fn post_with_retry(&self, kind: Kind, documents: &[Value]) -> Result<(), String> {
+        let mut delay = FIRST_RETRY_DELAY;
+        let mut attempt = 1;
+        loop {
+            let error = match self.post(kind, documents) {
+                Ok(()) => return Ok(()),
+                Err(ureq::Error::StatusCode(status)) if (400..500).contains(&status) && status != 429 => {
+                    return Err(format!("Nightscout rejected {} with HTTP {}", kind.name(), status));
+                }
+                Err(e) => e,
+            };
+            if attempt > self.settings.max_retries {
+                return Err(format!("Uploading {} failed after {} attempts: {}", kind.name(), attempt, error));
+            }
+            tracing::warn!(kind = kind.name(), attempt, delay_secs = delay.as_secs(), "Nightscout upload failed, retrying: {}", error);
+            thread::sleep(delay);
+            delay = (delay * 2).min(MAX_RETRY_DELAY);
+            attempt += 1;
+        }
+    }
+
+    fn sync_kind(&self, conn: &Connection, kind: Kind) -> Result<usize, Box<dyn Error>> {
+        let patient_id = &self.settings.patient_id;
+        let mut uploaded = 0;
+        loop {
+            let after_id = last_synced(conn, patient_id, kind)?;
+            let (documents, last_id) = pending(conn, patient_id, kind, after_id)?;
+            let Some(last_id) = last_id else {
+                return Ok(uploaded);
+            };
+            if !documents.is_empty() {
+                self.post_with_retry(kind, &documents)?;
+            }
+            // only move forward once the batch is accepted, so nothing is lost on failure
+            mark_synced(conn, patient_id, kind, last_id)?;
+            uploaded += documents.len();
+        }
+    }
+
+    // upload everything new since the last successful sync
+    pub fn sync_once(&self, conn: &Connection) -> Result<SyncSummary, Box<dyn Error>> {
+        let summary = SyncSummary {
+            entries: self.sync_kind(conn, Kind::Entries)?,
+            treatments: self.sync_kind(conn, Kind::Treatments)?,
+        };
+        if summary.entries + summary.treatments > 0 {
+            tracing::info!(entries = summary.entries, treatments = summary.treatments, "Nightscout sync finished");
+        }
+        Ok(summary)
+    }
+
+    pub fn run_forever(&self, conn: &Connection) {
+        loop {
+            if let Err(e) = self.sync_once(conn) {
+                tracing::error!("Nightscout sync failed: {}", e);
+            }
+            thread::sleep(Duration::from_secs(self.settings.interval_secs));
+        }
+    }
+}
+
+fn check_settings(settings: &NightscoutConfig) -> Result<(), Box<dyn Error>> {
+    if settings.url.trim().is_empty() || settings.api_secret.is_empty() || settings.patient_id.trim().is_empty() {
+        return Err("nightscout.url, nightscout.api_secret and nightscout.patient_id must be set".into());
+    }
+    Ok(())
+}
+
+// background uploader started by `serve` when enabled
+// uploads block on HTTP and SQLite, so each sync runs on the blocking pool
+pub fn spawn(settings: NightscoutConfig, db: Db) {
+    runtime::spawn(async move {
+        tracing::info!(url = %settings.url, patient_id = %settings.patient_id, "Nightscout uploader started");
+        let interval = Duration::from_secs(settings.interval_secs);
+        let uploader = Arc::new(Uploader::new(settings));
+        loop {
+            let current = Arc::clone(&uploader);
+            match runtime::with_db(&db, move |conn| current.sync_once(conn).map_err(|e| e.to_string())).await {
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => tracing::error!("Nightscout sync failed: {}", e),
+                Err(e) => tracing::error!("Nightscout uploader could not reach the database: {}", e),
+            }
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+// `glucoguard nightscout [--once]`
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let once = match args {
+        [] => false,
+        [flag] if flag == "--once" => true,
+        _ => return Err("usage: glucoguard nightscout [--once]".into()),
+    };
+    let settings = config::get().nightscout.clone();
+    check_settings(&settings)?;
+    let conn = initialize::establish_connection().map_err(|e| format!("Failed to open database: {}", e))?;
+    let uploader = Uploader::new(settings);
+    if once {
+        let summary = uploader.sync_once(&conn)?;
+        println!(
+            "Nightscout sync finished: {} entries and {} treatments uploaded",
+            summary.entries, summary.treatments
+        );
+    } else {
+        println!("Uploading to Nightscout every {} seconds", config::get().nightscout.interval_secs);
+        uploader.run_forever(&conn);
+    }
+    Ok(())
+}
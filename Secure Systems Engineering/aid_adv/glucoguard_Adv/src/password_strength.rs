@@ -0,0 +1,530 @@
+/*
+Password strength estimation after zxcvbn: the password is covered by the cheapest sequence of guessable
+patterns (common passwords and words, also capitalised, reversed or with l33t substitutions; the user's own
+name; sequences such as abc or 9876; straight keyboard rows; repeats; years and dates) and characters left
+over cost ten guesses each. The number of guesses that sequence takes gives the score:
+  0  under 10^3 guesses    1  under 10^6    2  under 10^8    3  under 10^10    4  more
+New passwords must pass the character rules of input_validation and reach passwords.min_score.
+*/
+use crate::config;
+use crate::input_validation::validate_password_strength;
+use chrono::{Datelike, Utc};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+// ranked by how often they are used, most common first
+const COMMON_PASSWORDS: &[&str] = &[
+    "123456", "password", "12345678", "qwerty", "123456789", "12345", "1234", "111111", "1234567", "dragon",
+    "123123", "baseball", "abc123", "football", "monkey", "letmein", "696969", "shadow", "master", "666666",
+    "qwertyuiop", "123321", "mustang", "1234567890", "michael", "654321", "superman", "1qaz2wsx", "7777777",
+    "121212", "000000", "qazwsx", "123qwe", "killer", "trustno1", "jordan", "jennifer", "zxcvbnm", "asdfgh",
+    "hunter", "buster", "soccer", "harley", "batman", "andrew", "tigger", "sunshine", "iloveyou", "charlie",
+    "robert", "thomas", "hockey", "ranger", "daniel", "starwars", "112233", "george", "computer", "michelle",
+    "jessica", "pepper", "zxcvbn", "555555", "11111111", "131313", "freedom", "777777", "pass", "maggie",
+    "159753", "aaaaaa", "ginger", "princess", "joshua", "cheese", "amanda", "summer", "love", "ashley",
+    "nicole", "chelsea", "matthew", "access", "yankees", "987654321", "dallas", "austin", "thunder", "taylor",
+    "matrix", "welcome", "admin", "login", "secret", "qwerty123", "password1", "hello", "whatever", "flower",
+    "changeme", "passw0rd", "administrator", "root", "default", "guest", "test", "temp", "letmein1", "welcome1",
+];
+
+// words, names and terms of this application that people build passwords from
+const COMMON_WORDS: &[&str] = &[
+    "the", "and", "love", "baby", "angel", "happy", "family", "friend", "money", "music", "heart", "house",
+    "blue", "red", "green", "black", "white", "pink", "purple", "orange", "apple", "banana", "coffee", "cookie",
+    "chocolate", "sugar", "honey", "dog", "cat", "tiger", "lion", "bear", "eagle", "horse", "bunny", "star",
+    "moon", "sun", "sky", "fire", "water", "earth", "rock", "magic", "power", "king", "queen", "prince",
+    "ninja", "pirate", "tennis", "golf", "winter", "spring", "autumn", "fall", "january", "february", "march",
+    "april", "may", "june", "july", "august", "september", "october", "november", "december", "monday",
+    "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday", "world", "life", "god", "jesus",
+    "maria", "mary", "john", "david", "james", "anna", "laura", "carlos", "juan", "jose", "sofia", "lucas",
+    "emma", "olivia", "liam", "noah", "london", "paris", "madrid", "newyork", "america", "mexico", "spain",
+    "google", "internet", "glucose", "gluco", "glucoguard", "guard", "insulin", "diabetes", "diabetic",
+    "pump", "sensor", "doctor", "nurse", "clinic", "hospital", "health", "patient", "care", "carer",
+    "caretaker", "clinician", "demo", "user", "account", "secure", "security", "private", "system",
+];
+
+// l33t substitutions undone before the dictionary lookup; '1' and '|' are tried as i and as l
+const L33T: &[(char, char)] = &[
+    ('4', 'a'), ('@', 'a'), ('8', 'b'), ('(', 'c'), ('3', 'e'), ('6', 'g'), ('9', 'g'), ('!', 'i'), ('0', 'o'),
+    ('$', 's'), ('5', 's'), ('7', 't'), ('+', 't'), ('2', 'z'),
+];
+
+const KEYBOARD_ROWS: &[&str] = &["`1234567890-=", "qwertyuiop[]\\", "asdfghjkl;'", "zxcvbnm,./"];
+const DATE_SEPARATORS: &str = "/-._ ";
+// a year this far from now or closer is a guess away
+const MIN_YEAR_SPACE: f64 = 20.0;
+const MIN_SUBMATCH_GUESSES_SINGLE_CHAR: f64 = 10.0;
+const MIN_SUBMATCH_GUESSES_MULTI_CHAR: f64 = 50.0;
+pub const MAX_SCORE: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pattern {
+    // rank 1 is the most common entry of its list
+    Dictionary { rank: usize, common_password: bool, l33t: bool, reversed: bool },
+    UserInput,
+    Sequence,
+    Keyboard,
+    Repeat { single_char: bool },
+    Date,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Match {
+    // char positions, end exclusive
+    start: usize,
+    end: usize,
+    guesses: f64,
+    pattern: Pattern,
+    capitalized: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Estimate {
+    pub score: u8,
+    // what makes the password weak, None when it is strong or merely short of characters
+    pub warning: Option<&'static str>,
+    pub suggestions: Vec<&'static str>,
+}
+
+fn dictionaries() -> &'static [HashMap<&'static str, usize>; 2] {
+    static RANKED: OnceLock<[HashMap<&'static str, usize>; 2]> = OnceLock::new();
+    RANKED.get_or_init(|| {
+        let ranked = |words: &[&'static str]| words.iter().enumerate().map(|(index, word)| (*word, index + 1)).collect();
+        [ranked(COMMON_PASSWORDS), ranked(COMMON_WORDS)]
+    })
+}
+
+fn n_choose_k(n: usize, k: usize) -> f64 {
+    (0..k).fold(1.0, |acc, i| acc * (n - i) as f64 / (i + 1) as f64)
+}
+
+// how many capitalisations of a word an attacker tries before this one
+fn uppercase_variations(token: &[char]) -> f64 {
+    let upper = token.iter().filter(|c| c.is_uppercase()).count();
+    let lower = token.iter().filter(|c| c.is_lowercase()).count();
+    if upper == 0 {
+        return 1.0;
+    }
+    let first_or_last_only = upper == 1 && (token[0].is_uppercase() || token[token.len() - 1].is_uppercase());
+    if lower == 0 || first_or_last_only {
+        return 2.0;
+    }
+    (1..=upper.min(lower)).map(|k| n_choose_k(upper + lower, k)).sum()
+}
+
+fn dictionary_matches(chars: &[char], user_inputs: &[String], matches: &mut Vec<Match>) {
+    let lower: Vec<char> = chars.iter().map(|c| c.to_lowercase().next().unwrap_or(*c)).collect();
+    let user_ranks: HashMap<&str, usize> =
+        user_inputs.iter().enumerate().map(|(index, input)| (input.as_str(), index + 1)).collect();
+    let unleet = |one_as: char| -> Vec<char> {
+        lower
+            .iter()
+            .map(|c| match *c {
+                '1' | '|' => one_as,
+                c => L33T.iter().find(|(from, _)| *from == c).map(|(_, to)| *to).unwrap_or(c),
+            })
+            .collect()
+    };
+    let variants = [(lower.clone(), false, false), (unleet('i'), true, false), (unleet('l'), true, false), {
+        let mut reversed = lower.clone();
+        reversed.reverse();
+        (reversed, false, true)
+    }];
+    let n = chars.len();
+    for (text, l33t, reversed) in &variants {
+        for i in 0..n {
+            for j in (i + 3).min(n + 1)..=n {
+                let word: String = text[i..j].iter().collect();
+                // positions in the password; the reversed variant runs backwards
+                let (start, end) = if *reversed { (n - j, n - i) } else { (i, j) };
+                let substituted = *l33t && text[i..j] != lower[i..j];
+                if *l33t && !substituted {
+                    continue;
+                }
+                let token = &chars[start..end];
+                let capitalized = token.iter().any(|c| c.is_uppercase());
+                let mut variations = uppercase_variations(token);
+                if substituted {
+                    variations *= 2f64.powi(text[i..j].iter().zip(&lower[i..j]).filter(|(a, b)| a != b).count() as i32);
+                }
+                if *reversed {
+                    variations *= 2.0;
+                }
+                if let Some(rank) = user_ranks.get(word.as_str()) {
+                    matches.push(Match { start, end, guesses: *rank as f64 * variations, pattern: Pattern::UserInput, capitalized });
+                }
+                for (list, ranked) in dictionaries().iter().enumerate() {
+                    if let Some(rank) = ranked.get(word.as_str()) {
+                        let pattern = Pattern::Dictionary { rank: *rank, common_password: list == 0, l33t: substituted, reversed: *reversed };
+                        matches.push(Match { start, end, guesses: *rank as f64 * variations, pattern, capitalized });
+                    }
+                }
+            }
+        }
+    }
+}
+
+// runs like abcd, 9753 or XYZ: the same step between neighbours of one character class
+fn sequence_matches(chars: &[char], matches: &mut Vec<Match>) {
+    let class = |c: char| {
+        if c.is_ascii_lowercase() {
+            1
+        } else if c.is_ascii_uppercase() {
+            2
+        } else if c.is_ascii_digit() {
+            3
+        } else {
+            0
+        }
+    };
+    let mut start = 0;
+    while start + 2 < chars.len() {
+        let delta = chars[start + 1] as i32 - chars[start] as i32;
+        let same = |k: usize| class(chars[k]) != 0 && class(chars[k]) == class(chars[start]);
+        let mut end = start + 1;
+        while end < chars.len() && same(end) && chars[end] as i32 - chars[end - 1] as i32 == delta {
+            end += 1;
+        }
+        if delta != 0 && delta.abs() <= 5 && end - start >= 3 && same(start) {
+            let first = chars[start];
+            let base = if "aAzZ019".contains(first) {
+                4.0
+            } else if first.is_ascii_digit() {
+                10.0
+            } else {
+                26.0
+            };
+            let guesses = base * (end - start) as f64 * if delta < 0 { 2.0 } else { 1.0 };
+            matches.push(Match { start, end, guesses, pattern: Pattern::Sequence, capitalized: false });
+            start = end - 1;
+        } else {
+            start += 1;
+        }
+    }
+}
+
+// four or more neighbouring keys of one row, either way
+fn keyboard_matches(chars: &[char], matches: &mut Vec<Match>) {
+    let lower: Vec<char> = chars.iter().map(|c| c.to_ascii_lowercase()).collect();
+    for row in KEYBOARD_ROWS {
+        let keys: Vec<char> = row.chars().collect();
+        let position = |c: char| keys.iter().position(|k| *k == c);
+        let mut start = 0;
+        while start < lower.len() {
+            let mut end = start + 1;
+            if let (Some(first), Some(second)) = (position(lower[start]), lower.get(start + 1).and_then(|c| position(*c))) {
+                let step = second as i32 - first as i32;
+                if step.abs() == 1 {
+                    end = start + 2;
+                    while end < lower.len() && position(lower[end]).map(|p| p as i32 - position(lower[end - 1]).unwrap_or(0) as i32) == Some(step) {
+                        end += 1;
+                    }
+                }
+            }
+            if end - start >= 4 {
+                let shifted = if chars[start..end].iter().any(|c| c.is_uppercase()) { 2.0 } else { 1.0 };
+                let guesses = keys.len() as f64 * 2.0 * (end - start) as f64 * shifted;
+                matches.push(Match { start, end, guesses, pattern: Pattern::Keyboard, capitalized: false });
+                start = end;
+            } else {
+                start += 1;
+            }
+        }
+    }
+}
+
+// aaaa or abcabc: a base repeated; it costs the guesses of the base times the repeats
+fn repeat_matches(chars: &[char], user_inputs: &[String], matches: &mut Vec<Match>) {
+    let n = chars.len();
+    for start in 0..n {
+        for base_len in 1..=(n - start) / 2 {
+            let base = &chars[start..start + base_len];
+            let mut repeats = 1;
+            while start + (repeats + 1) * base_len <= n && &chars[start + repeats * base_len..start + (repeats + 1) * base_len] == base {
+                repeats += 1;
+            }
+            if repeats < 2 || (base_len == 1 && repeats < 3) {
+                continue;
+            }
+            let base_guesses = 10f64.powf(minimum_guesses_log10(base, user_inputs).0);
+            matches.push(Match {
+                start,
+                end: start + repeats * base_len,
+                guesses: base_guesses * repeats as f64,
+                pattern: Pattern::Repeat { single_char: base_len == 1 },
+                capitalized: false,
+            });
+        }
+    }
+}
+
+fn year_space(year: i32) -> f64 {
+    ((year - Utc::now().year()).abs() as f64).max(MIN_YEAR_SPACE)
+}
+
+fn four_digit_year(year: u32) -> Option<i32> {
+    (1900..=2099).contains(&year).then_some(year as i32)
+}
+
+// 19xx/20xx years, and day, month and year in any common order, with or without separators
+fn date_matches(chars: &[char], matches: &mut Vec<Match>) {
+    let n = chars.len();
+    for start in 0..n {
+        for end in start + 4..=(start + 10).min(n) {
+            let text: String = chars[start..end].iter().collect();
+            let guesses = if text.len() == 4 && text.chars().all(|c| c.is_ascii_digit()) {
+                text.parse().ok().and_then(four_digit_year).map(year_space)
+            } else {
+                date_guesses(&text)
+            };
+            if let Some(guesses) = guesses {
+                matches.push(Match { start, end, guesses, pattern: Pattern::Date, capitalized: false });
+            }
+        }
+    }
+}
+
+fn date_guesses(text: &str) -> Option<f64> {
+    let separator = text.chars().find(|c| !c.is_ascii_digit())?.to_string();
+    let (parts, separated): (Vec<&str>, bool) = if DATE_SEPARATORS.contains(separator.as_str()) && text.split(separator.as_str()).count() == 3 {
+        (text.split(separator.as_str()).collect(), true)
+    } else {
+        return None;
+    };
+    if parts.iter().any(|part| part.is_empty() || part.len() > 4 || !part.chars().all(|c| c.is_ascii_digit())) {
+        return None;
+    }
+    date_from_parts(&parts).map(|year| 365.0 * year_space(year) * if separated { 4.0 } else { 1.0 })
+}
+
+fn undelimited_date_guesses(digits: &str) -> Option<f64> {
+    let splits: &[[usize; 2]] = match digits.len() {
+        6 => &[[2, 4]],
+        8 => &[[2, 4], [4, 6]],
+        _ => return None,
+    };
+    splits.iter().find_map(|[a, b]| date_from_parts(&[&digits[..*a], &digits[*a..*b], &digits[*b..]])).map(|year| 365.0 * year_space(year))
+}
+
+// the year of day/month/year, month/day/year or year/month/day
+fn date_from_parts(parts: &[&str]) -> Option<i32> {
+    let number = |part: &str| part.parse::<u32>().ok();
+    let year_of = |part: &str| match part.len() {
+        2 => number(part).map(|y| if y < 50 { 2000 + y as i32 } else { 1900 + y as i32 }),
+        4 => number(part).and_then(four_digit_year),
+        _ => None,
+    };
+    let day_month = |day: &str, month: &str| {
+        let (day, month) = (number(day)?, number(month)?);
+        ((1..=31).contains(&day) && (1..=12).contains(&month) && day.to_string().len() <= 2).then_some(())
+    };
+    if parts[2].len() != 1 {
+        if let Some(year) = year_of(parts[2]) {
+            if day_month(parts[0], parts[1]).is_some() || day_month(parts[1], parts[0]).is_some() {
+                return Some(year);
+            }
+        }
+    }
+    if parts[0].len() == 4 {
+        if let Some(year) = year_of(parts[0]) {
+            if day_month(parts[2], parts[1]).is_some() {
+                return Some(year);
+            }
+        }
+    }
+    None
+}
+
+fn all_matches(chars: &[char], user_inputs: &[String]) -> Vec<Match> {
+    let mut matches = Vec::new();
+    dictionary_matches(chars, user_inputs, &mut matches);
+    sequence_matches(chars, &mut matches);
+    keyboard_matches(chars, &mut matches);
+    repeat_matches(chars, user_inputs, &mut matches);
+    date_matches(chars, &mut matches);
+    for start in 0..chars.len() {
+        for end in start + 6..=(start + 8).min(chars.len()) {
+            let digits: String = chars[start..end].iter().collect();
+            if digits.chars().all(|c| c.is_ascii_digit()) {
+                if let Some(guesses) = undelimited_date_guesses(&digits) {
+                    matches.push(Match { start, end, guesses, pattern: Pattern::Date, capitalized: false });
+                }
+            }
+        }
+    }
+    matches
+}
+
+// log10 of the guesses of the cheapest cover and the patterns it uses; leftover characters cost 10 each
+fn minimum_guesses_log10(chars: &[char], user_inputs: &[String]) -> (f64, Vec<Match>) {
+    let n = chars.len();
+    let matches = if n > 1 { all_matches(chars, user_inputs) } else { Vec::new() };
+    // best[i]: cheapest cover of the first i characters and the match ending it (None = a guessed character)
+    let mut best: Vec<(f64, Option<usize>)> = vec![(f64::INFINITY, None); n + 1];
+    best[0].0 = 0.0;
+    for i in 1..=n {
+        best[i] = (best[i - 1].0 + 1.0, None);
+        for (index, m) in matches.iter().enumerate().filter(|(_, m)| m.end == i) {
+            let minimum = if m.end - m.start == 1 { MIN_SUBMATCH_GUESSES_SINGLE_CHAR } else { MIN_SUBMATCH_GUESSES_MULTI_CHAR };
+            let cost = best[m.start].0 + m.guesses.max(minimum).log10();
+            if cost < best[i].0 {
+                best[i] = (cost, Some(index));
+            }
+        }
+    }
+    let mut used = Vec::new();
+    let mut segments = 0;
+    let mut i = n;
+    while i > 0 {
+        match best[i].1 {
+            Some(index) => {
+                used.push(matches[index]);
+                segments += 1;
+                i = matches[index].start;
+            }
+            None => {
+                // a run of guessed characters counts as one segment
+                if i == n || best[i + 1].1.is_some() {
+                    segments += 1;
+                }
+                i -= 1;
+            }
+        }
+    }
+    // the patterns can come in any order
+    let order: f64 = (2..=segments).map(|k| (k as f64).log10()).sum();
+    (best[n].0 + order, used)
+}
+
+fn score_for(guesses_log10: f64) -> u8 {
+    match guesses_log10 {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => MAX_SCORE,
+    }
+}
+
+// the weakness of the longest pattern in the cover, zxcvbn's wording
+fn feedback(used: &[Match], whole_length: usize) -> (Option<&'static str>, Vec<&'static str>) {
+    let mut suggestions = vec!["Add another word or two. Uncommon words are better."];
+    let Some(longest) = used.iter().max_by_key(|m| m.end - m.start) else {
+        suggestions.push("Use a longer password made of several words.");
+        return (None, suggestions);
+    };
+    let warning = match longest.pattern {
+        Pattern::Dictionary { rank, common_password: true, .. } if rank <= 10 => "This is a top-10 common password.",
+        Pattern::Dictionary { rank, common_password: true, .. } if rank <= 100 => "This is a top-100 common password.",
+        Pattern::Dictionary { common_password: true, .. } => "This is similar to a commonly used password.",
+        Pattern::Dictionary { .. } if longest.end - longest.start == whole_length => "A word by itself is easy to guess.",
+        Pattern::Dictionary { .. } => "Common words are easy to guess.",
+        Pattern::UserInput => "Passwords containing your username are easy to guess.",
+        Pattern::Sequence => "Sequences like abc or 6543 are easy to guess.",
+        Pattern::Keyboard => "Straight rows of keys are easy to guess.",
+        Pattern::Repeat { single_char: true } => "Repeats like \"aaa\" are easy to guess.",
+        Pattern::Repeat { single_char: false } => "Repeats like \"abcabcabc\" are only slightly harder to guess than \"abc\".",
+        Pattern::Date => "Dates and years are often easy to guess.",
+    };
+    match longest.pattern {
+        Pattern::Dictionary { l33t, reversed, .. } => {
+            if longest.capitalized {
+                suggestions.push("Capitalization doesn't help very much.");
+            }
+            if reversed {
+                suggestions.push("Reversed words aren't much harder to guess.");
+            }
+            if l33t {
+                suggestions.push("Predictable substitutions like '@' instead of 'a' don't help very much.");
+            }
+        }
+        Pattern::UserInput => suggestions.push("Do not use your username or name in the password."),
+        Pattern::Sequence => suggestions.push("Avoid sequences."),
+        Pattern::Keyboard => suggestions.push("Use a longer keyboard pattern with more turns."),
+        Pattern::Repeat { .. } => suggestions.push("Avoid repeated words and characters."),
+        Pattern::Date => suggestions.push("Avoid dates and years that are associated with you."),
+    }
+    (Some(warning), suggestions)
+}
+
+// `user_inputs` are words the password should not be built from, such as the username
+pub fn estimate(password: &str, user_inputs: &[&str]) -> Estimate {
+    let chars: Vec<char> = password.chars().collect();
+    let mut inputs: Vec<String> = Vec::new();
+    for input in user_inputs {
+        let lower = input.trim().to_lowercase();
+        // "dr.smith" also rules out "smith"
+        for part in std::iter::once(lower.as_str()).chain(lower.split(|c: char| !c.is_alphanumeric())) {
+            if part.chars().count() >= 3 && !inputs.iter().any(|known| known == part) {
+                inputs.push(part.to_string());
+            }
+        }
+    }
+    let (guesses_log10, used) = minimum_guesses_log10(&chars, &inputs);
+    let score = score_for(guesses_log10);
+    let (warning, suggestions) = if score >= MAX_SCORE { (None, Vec::new()) } else { feedback(&used, chars.len()) };
+    Estimate { score, warning, suggestions }
+}
+
+// the character rules, then at least `min_score`; the error says what to change
+pub fn check_with(password: &str, user_inputs: &[&str], min_score: u8) -> Result<Estimate, String> {
+    validate_password_strength(password)?;
+    let estimate = estimate(password, user_inputs);
+    if estimate.score >= min_score {
+        return Ok(estimate);
+    }
+    let mut message = format!("Password is too easy to guess (strength {} of {}, at least {} required).", estimate.score, MAX_SCORE, min_score);
+    for advice in estimate.warning.iter().chain(&estimate.suggestions) {
+        message.push(' ');
+        message.push_str(advice);
+    }
+    Err(message)
+}
+
+// check a new password against the character rules and passwords.min_score
+pub fn check_new_password(password: &str, user_inputs: &[&str]) -> Result<Estimate, String> {
+    check_with(password, user_inputs, config::get().passwords.min_score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn common_patterns_score_low_with_advice() {
+        let weak = estimate("password", &[]);
+        assert_eq!(weak.score, 0);
+        assert_eq!(weak.warning, Some("This is a top-10 common password."));
+
+        // passes every character rule, but is "password" with a capital and two substitutions
+        let leet = check_with("P@ssw0rd!", &[], 3).unwrap_err();
+        assert!(leet.contains("strength 1 of 4"), "{}", leet);
+        assert!(leet.contains("Predictable substitutions"));
+
+        assert_eq!(estimate("Qwertyuiop!", &[]).warning, Some("This is a top-100 common password."));
+        assert_eq!(estimate("Abcdefgh!", &[]).warning, Some("Sequences like abc or 6543 are easy to guess."));
+        assert_eq!(estimate("Zzzzzzzzzz!", &[]).warning, Some("Repeats like \"aaa\" are easy to guess."));
+        assert_eq!(estimate("Asdfghjk;!", &[]).warning, Some("Straight rows of keys are easy to guess."));
+        assert_eq!(estimate("12/05/1987", &[]).warning, Some("Dates and years are often easy to guess."));
+        assert!(estimate("Tiger!1990", &[]).score <= 2);
+        assert!(estimate("Glucoguard2024!", &[]).score <= 2);
+
+        let own_name = estimate("Dr.Fixture!!", &["dr.fixture"]);
+        assert_eq!(own_name.warning, Some("Passwords containing your username are easy to guess."));
+        assert!(estimate("Fixture#Fixture", &["dr.fixture"]).score <= 1);
+    }
+
+    #[test]
+    fn long_unpredictable_passwords_pass() {
+        let strong = check_with("Violet!Lantern#Quiz42", &["dr.fixture"], 3).unwrap();
+        assert_eq!((strong.score, strong.warning), (MAX_SCORE, None));
+        assert!(strong.suggestions.is_empty());
+        assert!(check_with("uX7#rQ2!vL9m", &[], 3).is_ok());
+        // ten guesses a character: eight random characters are 10^8, just enough for the default
+        assert_eq!(estimate("Xk9#mQ2p", &[]).score, 3);
+        assert!(check_with("Xk9#mQ2p", &[], MAX_SCORE).is_err());
+        // the character rules still apply first
+        assert_eq!(check_with("violetlantern#quiz", &[], 0).unwrap_err(), "Password must contain at least one uppercase letter.");
+        assert!(check_with("password", &[], 0).is_err());
+    }
+}
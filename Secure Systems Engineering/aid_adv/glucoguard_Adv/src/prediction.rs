@@ -0,0 +1,132 @@
+// Short-horizon glucose prediction by linear extrapolation of recent readings
+use crate::alerts;
+use crate::db::models::Patient;
+use rusqlite::{params, Connection};
+
+// readings older than this are not used for the trend
+pub const LOOKBACK_MINUTES: u32 = 60;
+// fewer readings than this give no usable trend
+pub const MIN_READINGS: usize = 3;
+// no prediction when the newest reading is older than this
+pub const MAX_READING_AGE_MINUTES: f64 = 15.0;
+// minutes ahead of the newest reading that are checked
+pub const HORIZONS_MINUTES: [f64; 2] = [30.0, 60.0];
+
+// least-squares line through (minutes, mg/dL) points
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trend {
+    // mg/dL per minute
+    pub slope: f64,
+    pub intercept: f64,
+}
+
+impl Trend {
+    pub fn fit(points: &[(f64, f64)]) -> Option<Trend> {
+        if points.len() < 2 {
+            return None;
+        }
+        let n = points.len() as f64;
+        let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let sxx: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+        if sxx == 0.0 {
+            return None;
+        }
+        let sxy: f64 = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+        let slope = sxy / sxx;
+        Some(Trend {
+            slope,
+            intercept: mean_y - slope * mean_x,
+        })
+    }
+
+    pub fn at(&self, minutes: f64) -> f64 {
+        self.intercept + self.slope * minutes
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PredictedKind {
+    Low,
+    High,
+}
+
+impl PredictedKind {
+    pub fn alert_type(&self) -> &'static str {
+        match self {
+            PredictedKind::Low => "PREDICTED_LOW",
+            PredictedKind::High => "PREDICTED_HIGH",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PredictedEvent {
+    pub kind: PredictedKind,
+    pub minutes_ahead: f64,
+    pub predicted_level: f64,
+}
+
+impl PredictedEvent {
+    pub fn message(&self) -> String {
+        let direction = match self.kind {
+            PredictedKind::Low => "low",
+            PredictedKind::High => "high",
+        };
+        format!(
+            "Glucose predicted {} ({:.0} mg/dL) in about {:.0} minutes",
+            direction, self.predicted_level, self.minutes_ahead
+        )
+    }
+}
+
+// first horizon at which the trend leaves [low, high], points are (minutes relative to now, mg/dL)
+pub fn predict_event(points: &[(f64, f64)], low: f64, high: f64) -> Option<PredictedEvent> {
+    if points.len() < MIN_READINGS {
+        return None;
+    }
+    let newest = points.iter().map(|(x, _)| *x).fold(f64::NEG_INFINITY, f64::max);
+    if newest < -MAX_READING_AGE_MINUTES {
+        return None;
+    }
+    let trend = Trend::fit(points)?;
+    HORIZONS_MINUTES.iter().find_map(|&ahead| {
+        let predicted_level = trend.at(newest + ahead);
+        let kind = if predicted_level < low {
+            PredictedKind::Low
+        } else if predicted_level > high {
+            PredictedKind::High
+        } else {
+            return None;
+        };
+        Some(PredictedEvent { kind, minutes_ahead: ahead, predicted_level })
+    })
+}
+
+// recent readings as (minutes relative to now, mg/dL), oldest first
+pub fn recent_points(conn: &Connection, patient_id: &str) -> rusqlite::Result<Vec<(f64, f64)>> {
+    let mut stmt = conn.prepare(
+        "SELECT (julianday(reading_time) - julianday('now')) * 1440.0, glucose_level
+         FROM glucose_readings
+         WHERE patient_id = ?1 AND julianday(reading_time) >= julianday('now', ?2)
+         ORDER BY reading_time ASC",
+    )?;
+    let points = stmt.query_map(params![patient_id, format!("-{} minutes", LOOKBACK_MINUTES)], |row| {
+        Ok((row.get(0)?, row.get(1)?))
+    })?;
+    points.collect()
+}
+
+// predict against the patient's own thresholds and raise an alert when a low/high is expected
+pub fn check_patient(conn: &Connection, patient: &Patient) -> rusqlite::Result<Option<PredictedEvent>> {
+    let points = recent_points(conn, &patient.patient_id)?;
+    let event = predict_event(
+        &points,
+        patient.low_glucose_threshold as f64,
+        patient.high_glucose_threshold as f64,
+    );
+    if let Some(event) = &event {
+        alerts::raise_alert(conn, &patient.patient_id, event.kind.alert_type(), &event.message())?;
+    }
+    Ok(event)
+}
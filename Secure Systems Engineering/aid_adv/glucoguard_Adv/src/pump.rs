@@ -0,0 +1,452 @@
+// Insulin pump command queue shared by the menus and the pump telemetry interface
+// Every command is addressed to the patient's paired pump and signed with devices.command_signing_key
+// when it is queued, so the pump can check that a command it pulls was issued by this server unchanged.
+// Reservoir and battery reports (gRPC ReportStatus or `import --pump-status`) raise low-level alerts.
+use crate::alerts;
+use crate::config;
+use crate::devices;
+use crate::dose_log::{self, DoseEntry};
+use crate::dose_safety;
+use chrono::Utc;
+use ed25519_dalek::{Signer, SigningKey};
+use rusqlite::{params, Connection, OptionalExtension};
+
+pub const LOW_RESERVOIR_ALERT: &str = "LOW_RESERVOIR";
+pub const LOW_BATTERY_ALERT: &str = "LOW_BATTERY";
+
+#[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+#[derive(Debug, Clone)]
+pub struct PumpCommand {
+    pub command_id: i64,
+    pub device_id: String,
+    pub patient_id: String,
+    pub command_type: String,
+    pub dosage_units: f64,
+    // how long a "temp_basal" rate or "extended_bolus" runs, 0 for other commands
+    pub duration_minutes: u32,
+    pub created_at: String,
+    pub issued_at_ms: i64,
+    // hex Ed25519 signature over command_message()
+    pub signature: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct PumpStatus {
+    pub device_id: String,
+    pub reservoir_units: f64,
+    pub battery_percent: u32,
+    pub reported_at: String,
+}
+
+// the fields the server signs, joined the same way as device requests
+pub fn command_message(command: &PumpCommand) -> Vec<u8> {
+    let command_id = command.command_id.to_string();
+    let units = format!("{:.3}", command.dosage_units);
+    let duration = command.duration_minutes.to_string();
+    let issued_at = command.issued_at_ms.to_string();
+    devices::signed_message(&[
+        "command",
+        &command_id,
+        &command.device_id,
+        &command.patient_id,
+        &command.command_type,
+        &units,
+        &duration,
+        &issued_at,
+    ])
+}
+
+fn command_signing_key() -> Result<SigningKey, String> {
+    let key = &config::get().devices.command_signing_key;
+    if key.is_empty() {
+        return Err("Pump commands are disabled: set devices.command_signing_key (GLUCOGUARD_DEVICE_COMMAND_SIGNING_KEY)".to_string());
+    }
+    let seed: [u8; 32] = hex::decode(key)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or("devices.command_signing_key must be 64 hex characters")?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+// hex public key pumps use to verify commands, None while command signing is not configured
+pub fn command_public_key() -> Option<String> {
+    command_signing_key().ok().map(|key| hex::encode(key.verifying_key().to_bytes()))
+}
+
+// queue a command for the patient's paired pump once it passes the dose safety checks, returns the command id
+// command_type is "bolus" or "basal" for doses, "suspend" or "resume" for emergency stops,
+// "suspend_basal" or "resume_basal" for automatic low glucose suspensions;
+// extended boluses go through queue_bolus_request
+pub fn queue_command(conn: &Connection, patient_id: &str, command_type: &str, dosage_units: f64, created_by: &str) -> Result<i64, String> {
+    queue_timed_command(conn, patient_id, command_type, dosage_units, 0, created_by)
+}
+
+// queue_command for commands that apply for a while, e.g. a "temp_basal" rate in units/hour for duration_minutes
+pub fn queue_timed_command(
+    conn: &Connection,
+    patient_id: &str,
+    command_type: &str,
+    dosage_units: f64,
+    duration_minutes: u32,
+    created_by: &str,
+) -> Result<i64, String> {
+    dose_safety::check_dose(conn, patient_id, command_type, dosage_units)?;
+    let key = command_signing_key()?;
+    queue_signed_command(conn, &key, patient_id, command_type, dosage_units, duration_minutes, created_by)
+}
+
+fn queue_signed_command(
+    conn: &Connection,
+    key: &SigningKey,
+    patient_id: &str,
+    command_type: &str,
+    dosage_units: f64,
+    duration_minutes: u32,
+    created_by: &str,
+) -> Result<i64, String> {
+    let pump = devices::paired_pump(conn, patient_id)
+        .map_err(|e| e.to_string())?
+        .ok_or("no pump is paired to this patient")?;
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let command_id = insert_signed_command(&tx, key, &pump.device_id, patient_id, command_type, dosage_units, duration_minutes, None, created_by)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(command_id)
+}
+
+/*
+Queue the commands of one bolus request, e.g. the immediate "bolus" and the "extended_bolus" of a
+dual-wave bolus, after checking their total as a bolus. The commands carry request_id and are queued
+together with whatever `record` writes, in one transaction, or not at all.
+*/
+pub fn queue_bolus_request(
+    conn: &Connection,
+    patient_id: &str,
+    request_id: &str,
+    commands: &[(&str, f64, u32)],
+    created_by: &str,
+    record: impl FnOnce(&Connection) -> rusqlite::Result<()>,
+) -> Result<Vec<i64>, String> {
+    let total_units: f64 = commands.iter().map(|(_, units, _)| units).sum();
+    dose_safety::check_dose(conn, patient_id, "bolus", total_units)?;
+    let key = command_signing_key()?;
+    let pump = devices::paired_pump(conn, patient_id)
+        .map_err(|e| e.to_string())?
+        .ok_or("no pump is paired to this patient")?;
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let mut command_ids = Vec::with_capacity(commands.len());
+    for (command_type, dosage_units, duration_minutes) in commands {
+        command_ids.push(insert_signed_command(
+            &tx,
+            &key,
+            &pump.device_id,
+            patient_id,
+            command_type,
+            *dosage_units,
+            *duration_minutes,
+            Some(request_id),
+            created_by,
+        )?);
+    }
+    record(&tx).map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(command_ids)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn insert_signed_command(
+    tx: &Connection,
+    key: &SigningKey,
+    device_id: &str,
+    patient_id: &str,
+    command_type: &str,
+    dosage_units: f64,
+    duration_minutes: u32,
+    request_id: Option<&str>,
+    created_by: &str,
+) -> Result<i64, String> {
+    tx.execute(
+        "INSERT INTO pump_commands (patient_id, device_id, command_type, dosage_units, duration_minutes, request_id, status, created_by, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, 'pending', ?7, datetime('now'))",
+        params![patient_id, device_id, command_type, dosage_units, duration_minutes, request_id, created_by],
+    )
+    .map_err(|e| e.to_string())?;
+    let mut command = PumpCommand {
+        command_id: tx.last_insert_rowid(),
+        device_id: device_id.to_string(),
+        patient_id: patient_id.to_string(),
+        command_type: command_type.to_string(),
+        dosage_units,
+        duration_minutes,
+        created_at: String::new(),
+        issued_at_ms: Utc::now().timestamp_millis(),
+        signature: String::new(),
+    };
+    command.signature = hex::encode(key.sign(&command_message(&command)).to_bytes());
+    tx.execute(
+        "UPDATE pump_commands SET issued_at_ms = ?2, signature = ?3 WHERE command_id = ?1",
+        params![command.command_id, command.issued_at_ms, command.signature],
+    )
+    .map_err(|e| e.to_string())?;
+    tracing::info!(command_id = command.command_id, patient_id = %patient_id, device_id = %device_id, command_type = %command_type, dosage_units, created_by = %created_by, "Pump command queued");
+    Ok(command.command_id)
+}
+
+// pending commands for one of the patient's pumps, oldest first, marked as delivered once handed out
+#[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+pub fn take_pending_commands(conn: &Connection, patient_id: &str, device_id: &str) -> rusqlite::Result<Vec<PumpCommand>> {
+    let tx = conn.unchecked_transaction()?;
+    let commands = {
+        let mut stmt = tx.prepare(
+            "SELECT command_id, device_id, patient_id, command_type, dosage_units, duration_minutes, created_at, issued_at_ms, signature
+             FROM pump_commands
+             WHERE patient_id = ?1 AND device_id = ?2 AND status = 'pending'
+             ORDER BY command_id ASC",
+        )?;
+        let rows = stmt.query_map([patient_id, device_id], |row| {
+            Ok(PumpCommand {
+                command_id: row.get(0)?,
+                device_id: row.get(1)?,
+                patient_id: row.get(2)?,
+                command_type: row.get(3)?,
+                dosage_units: row.get(4)?,
+                duration_minutes: row.get(5)?,
+                created_at: row.get(6)?,
+                issued_at_ms: row.get(7)?,
+                signature: row.get(8)?,
+            })
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+    tx.execute(
+        "UPDATE pump_commands SET status = 'delivered', delivered_at = datetime('now')
+         WHERE patient_id = ?1 AND device_id = ?2 AND status = 'pending'",
+        [patient_id, device_id],
+    )?;
+    tx.commit()?;
+    Ok(commands)
+}
+
+// record a dose the pump reports as given; a queued command must belong to the patient
+#[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+pub fn confirm_dose(
+    conn: &Connection,
+    patient_id: &str,
+    command_id: Option<i64>,
+    action_type: &str,
+    dosage_units: f64,
+    device_id: &str,
+) -> Result<(), String> {
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let mut already_logged = false;
+    if let Some(command_id) = command_id {
+        let request_id: Option<Option<String>> = tx
+            .query_row(
+                "UPDATE pump_commands SET status = 'confirmed', confirmed_at = datetime('now')
+                 WHERE command_id = ?1 AND patient_id = ?2 AND status = 'delivered'
+                 RETURNING request_id",
+                params![command_id, patient_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        let Some(request_id) = request_id else {
+            return Err(format!("command {} is not awaiting confirmation for this patient", command_id));
+        };
+        // bolus requests were logged as scheduled entries when they were queued
+        already_logged = request_id.is_some();
+    }
+    if !already_logged {
+        dose_log::append(
+            &tx,
+            &DoseEntry {
+                patient_id,
+                action_type,
+                dosage_units,
+                requested_by: &format!("pump:{}", device_id),
+                dosage_time: None,
+                request_id: None,
+            },
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    tracing::info!(patient_id = %patient_id, device_id = %device_id, ?command_id, dosage_units, "Pump dose confirmed");
+    Ok(())
+}
+
+// keep the latest reservoir/battery report per pump and alert the care team when either runs low
+// reported_at ("YYYY-MM-DD HH:MM:SS" UTC) is when the pump took the reading, now when None;
+// returns false when a newer report is already stored, which an older one never replaces
+pub fn record_status(
+    conn: &Connection,
+    device_id: &str,
+    patient_id: &str,
+    reservoir_units: f64,
+    battery_percent: u32,
+    reported_at: Option<&str>,
+) -> rusqlite::Result<bool> {
+    let stored = conn.prepare_cached(
+        "INSERT INTO pump_status (device_id, patient_id, reservoir_units, battery_percent, reported_at)
+         VALUES (?1, ?2, ?3, ?4, COALESCE(?5, datetime('now')))
+         ON CONFLICT(device_id) DO UPDATE SET
+            patient_id = excluded.patient_id,
+            reservoir_units = excluded.reservoir_units,
+            battery_percent = excluded.battery_percent,
+            reported_at = excluded.reported_at
+         WHERE julianday(excluded.reported_at) >= julianday(pump_status.reported_at)",
+    )?
+    .execute(params![device_id, patient_id, reservoir_units, battery_percent, reported_at])?;
+    if stored == 0 {
+        return Ok(false);
+    }
+
+    let settings = &config::get().devices;
+    let checks = [
+        (
+            LOW_RESERVOIR_ALERT,
+            reservoir_units <= f64::from(settings.low_reservoir_units),
+            format!("Pump {} reservoir low: {:.1} units left", device_id, reservoir_units),
+        ),
+        (
+            LOW_BATTERY_ALERT,
+            battery_percent <= settings.low_battery_percent,
+            format!("Pump {} battery low: {}%", device_id, battery_percent),
+        ),
+    ];
+    for (alert_type, low, message) in checks {
+        if low {
+            alerts::raise_alert(conn, patient_id, alert_type, &message)?;
+        } else {
+            // refilled or recharged: the earlier warning no longer applies
+            conn.execute(
+                "UPDATE alerts SET is_resolved = 1, resolved_by = ?3 WHERE patient_id = ?1 AND alert_type = ?2 AND is_resolved = 0",
+                params![patient_id, alert_type, format!("pump:{}", device_id)],
+            )?;
+        }
+    }
+    Ok(true)
+}
+
+// the most recent report from the patient's pumps, if any
+pub fn latest_status(conn: &Connection, patient_id: &str) -> rusqlite::Result<Option<PumpStatus>> {
+    conn.query_row(
+        "SELECT device_id, reservoir_units, battery_percent, reported_at FROM pump_status
+         WHERE patient_id = ?1
+         ORDER BY reported_at DESC
+         LIMIT 1",
+        [patient_id],
+        |row| {
+            Ok(PumpStatus {
+                device_id: row.get(0)?,
+                reservoir_units: row.get(1)?,
+                battery_percent: row.get(2)?,
+                reported_at: row.get(3)?,
+            })
+        },
+    )
+    .optional()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::access_control::Role;
+    use crate::devices::DeviceType;
+    use crate::emergency;
+    use crate::test_utils;
+    use ed25519_dalek::{Signature, Verifier};
+
+    fn pair_pump(conn: &Connection, fixtures: &test_utils::Fixtures, device_id: &str) {
+        let clinician = Role::new(conn, "clinician", &fixtures.clinician_id);
+        let code = devices::register_device(conn, &clinician, &fixtures.patient_id, device_id, DeviceType::Pump, "").unwrap();
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let public_key = hex::encode(key.verifying_key().to_bytes());
+        let signature = hex::encode(key.sign(&devices::signed_message(&["pair", device_id, &code])).to_bytes());
+        devices::pair_device(conn, device_id, &code, &public_key, &signature).unwrap();
+    }
+
+    #[test]
+    fn queued_commands_are_signed_for_the_paired_pump() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let server_key = SigningKey::from_bytes(&[42u8; 32]);
+        assert!(queue_signed_command(&conn, &server_key, &fixtures.patient_id, "bolus", 2.5, 0, &fixtures.patient_id).is_err());
+
+        pair_pump(&conn, &fixtures, "PUMP-001");
+        queue_signed_command(&conn, &server_key, &fixtures.patient_id, "bolus", 2.5, 0, &fixtures.patient_id).unwrap();
+        // another device of the same patient gets nothing
+        assert!(take_pending_commands(&conn, &fixtures.patient_id, "PUMP-002").unwrap().is_empty());
+
+        let commands = take_pending_commands(&conn, &fixtures.patient_id, "PUMP-001").unwrap();
+        assert_eq!(commands.len(), 1);
+        let mut command = commands[0].clone();
+        let signature_bytes: [u8; 64] = hex::decode(&command.signature).unwrap().try_into().unwrap();
+        let signature = Signature::from_bytes(&signature_bytes);
+        let server_public = server_key.verifying_key();
+        assert!(server_public.verify(&command_message(&command), &signature).is_ok());
+        // an altered dose no longer verifies
+        command.dosage_units = 25.0;
+        assert!(server_public.verify(&command_message(&command), &signature).is_err());
+        assert!(take_pending_commands(&conn, &fixtures.patient_id, "PUMP-001").unwrap().is_empty());
+    }
+
+    #[test]
+    fn revoking_the_pump_cancels_its_pending_commands() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let server_key = SigningKey::from_bytes(&[42u8; 32]);
+        pair_pump(&conn, &fixtures, "PUMP-001");
+        queue_signed_command(&conn, &server_key, &fixtures.patient_id, "bolus", 1.0, 0, &fixtures.patient_id).unwrap();
+
+        let clinician = Role::new(&conn, "clinician", &fixtures.clinician_id);
+        assert!(devices::revoke_device(&conn, &clinician, &fixtures.patient_id, "PUMP-001").unwrap());
+        let status: String = conn.query_row("SELECT status FROM pump_commands", [], |row| row.get(0)).unwrap();
+        assert_eq!(status, "cancelled");
+        assert!(take_pending_commands(&conn, &fixtures.patient_id, "PUMP-001").unwrap().is_empty());
+    }
+
+    #[test]
+    fn doses_are_refused_while_delivery_is_suspended() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let server_key = SigningKey::from_bytes(&[42u8; 32]);
+        pair_pump(&conn, &fixtures, "PUMP-001");
+        let patient = Role::new(&conn, "patient", &fixtures.patient_id);
+        emergency::emergency_stop(&conn, &patient, &fixtures.patient_id, "").unwrap();
+
+        let refused = queue_command(&conn, &fixtures.patient_id, "bolus", 1.0, &fixtures.patient_id).unwrap_err();
+        assert!(refused.contains("suspended"), "{}", refused);
+        queue_signed_command(&conn, &server_key, &fixtures.patient_id, "suspend", 0.0, 0, &fixtures.patient_id).unwrap();
+        let commands = take_pending_commands(&conn, &fixtures.patient_id, "PUMP-001").unwrap();
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].command_type, "suspend");
+    }
+
+    #[test]
+    fn low_reservoir_and_battery_reports_alert_until_refilled() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let patient_id = &fixtures.patient_id;
+        let open_alerts = |alert_type: &str| -> i64 {
+            conn.query_row(
+                "SELECT COUNT(*) FROM alerts WHERE patient_id = ?1 AND alert_type = ?2 AND is_resolved = 0",
+                params![patient_id, alert_type],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+
+        assert!(record_status(&conn, "PUMP-001", patient_id, 12.0, 80, Some("2026-03-01 10:00:00")).unwrap());
+        assert_eq!(open_alerts(LOW_RESERVOIR_ALERT), 1);
+        assert_eq!(open_alerts(LOW_BATTERY_ALERT), 0);
+        // an older report arriving late does not replace the newer one
+        assert!(!record_status(&conn, "PUMP-001", patient_id, 150.0, 10, Some("2026-03-01 09:00:00")).unwrap());
+        assert_eq!(open_alerts(LOW_BATTERY_ALERT), 0);
+
+        assert!(record_status(&conn, "PUMP-001", patient_id, 200.0, 15, Some("2026-03-01 11:00:00")).unwrap());
+        assert_eq!(open_alerts(LOW_RESERVOIR_ALERT), 0);
+        assert_eq!(open_alerts(LOW_BATTERY_ALERT), 1);
+        let status = latest_status(&conn, patient_id).unwrap().unwrap();
+        assert_eq!((status.reservoir_units, status.battery_percent), (200.0, 15));
+    }
+}
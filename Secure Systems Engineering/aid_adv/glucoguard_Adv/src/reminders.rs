@@ -0,0 +1,320 @@
+/*
+Care reminders: glucose checks, infusion site changes and clinic appointments on a per-patient schedule.
+A reminder is due at next_due_at. Repeating reminders are due again interval_hours after each completion,
+one-off reminders (appointments) end when they are done. The patient and their clinician schedule and
+cancel reminders with ManageReminders; the care team sees them and marks them done with ViewReminders.
+Reminders due within DUE_SOON_HOURS are shown when the user logs in. Every completion is kept with the
+time it was due, so late checks and site changes show up in the history.
+*/
+use crate::access_control::{self, Permission, Resource, Role};
+use crate::input_validation::validate_free_text;
+use crate::logger::SECURITY_TARGET;
+use crate::timestamps;
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+
+// reminders due this soon (or overdue) are shown at login
+pub const DUE_SOON_HOURS: u32 = 24;
+pub const MIN_INTERVAL_HOURS: u32 = 1;
+pub const MAX_INTERVAL_HOURS: u32 = 90 * 24;
+pub const MAX_NOTE_LENGTH: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReminderKind {
+    GlucoseCheck,
+    SiteChange,
+    Appointment,
+}
+
+impl ReminderKind {
+    pub const ALL: [ReminderKind; 3] = [ReminderKind::GlucoseCheck, ReminderKind::SiteChange, ReminderKind::Appointment];
+
+    // name stored in care_reminders.kind
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReminderKind::GlucoseCheck => "glucose_check",
+            ReminderKind::SiteChange => "site_change",
+            ReminderKind::Appointment => "appointment",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ReminderKind> {
+        Self::ALL.iter().find(|kind| kind.as_str() == name).copied()
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ReminderKind::GlucoseCheck => "Check glucose",
+            ReminderKind::SiteChange => "Change infusion site",
+            ReminderKind::Appointment => "Clinic appointment",
+        }
+    }
+
+    // suggested repeat interval in hours; appointments do not repeat
+    pub fn default_interval_hours(&self) -> Option<u32> {
+        match self {
+            ReminderKind::GlucoseCheck => Some(4),
+            ReminderKind::SiteChange => Some(72),
+            ReminderKind::Appointment => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Reminder {
+    pub reminder_id: i64,
+    pub patient_id: String,
+    pub patient_name: String,
+    pub kind: String,
+    pub note: String,
+    pub interval_hours: Option<u32>,
+    pub next_due_at: String,
+    // username of whoever scheduled it, or their user id if the account is gone
+    pub created_by: String,
+}
+
+impl Reminder {
+    pub fn label(&self) -> &str {
+        ReminderKind::from_name(&self.kind).map_or(self.kind.as_str(), |kind| kind.label())
+    }
+
+    pub fn is_overdue(&self) -> bool {
+        timestamps::parse(&self.next_due_at).is_some_and(|due| due <= Utc::now())
+    }
+}
+
+// a reminder marked done
+#[derive(Debug, Clone)]
+pub struct Completion {
+    pub kind: String,
+    pub note: String,
+    pub due_at: String,
+    pub completed_at: String,
+    pub completed_by: String,
+}
+
+impl Completion {
+    pub fn label(&self) -> &str {
+        ReminderKind::from_name(&self.kind).map_or(self.kind.as_str(), |kind| kind.label())
+    }
+
+    pub fn was_late(&self) -> bool {
+        match (timestamps::parse(&self.due_at), timestamps::parse(&self.completed_at)) {
+            (Some(due), Some(completed)) => completed > due,
+            _ => false,
+        }
+    }
+}
+
+fn check_access(conn: &Connection, role: &Role, patient_id: &str, action: Permission) -> Result<(), String> {
+    if !access_control::can_access(conn, role, &Resource::Patient(patient_id), &action) {
+        return Err(format!("Access denied: patient is not in your care or {} is missing", action.as_str()));
+    }
+    Ok(())
+}
+
+const REMINDER_COLUMNS: &str = "r.reminder_id, r.patient_id, COALESCE(p.first_name || ' ' || p.last_name, r.patient_id), r.kind, r.note,
+     r.interval_hours, r.next_due_at, COALESCE(u.user_name, r.created_by)
+     FROM care_reminders r
+     LEFT JOIN patients p ON p.patient_id = r.patient_id
+     LEFT JOIN users u ON u.id = r.created_by";
+
+fn reminder_from_row(row: &rusqlite::Row) -> rusqlite::Result<Reminder> {
+    Ok(Reminder {
+        reminder_id: row.get(0)?,
+        patient_id: row.get(1)?,
+        patient_name: row.get(2)?,
+        kind: row.get(3)?,
+        note: row.get(4)?,
+        interval_hours: row.get(5)?,
+        next_due_at: row.get(6)?,
+        created_by: row.get(7)?,
+    })
+}
+
+// a reminder that has not ended
+fn load_active(conn: &Connection, reminder_id: i64) -> rusqlite::Result<Option<Reminder>> {
+    conn.query_row(
+        &format!("SELECT {} WHERE r.reminder_id = ?1 AND r.ended_at IS NULL", REMINDER_COLUMNS),
+        [reminder_id],
+        reminder_from_row,
+    )
+    .optional()
+}
+
+/*
+Schedule a reminder for a patient, first due at `first_due` and then every `interval_hours` after each
+completion (None for a one-off reminder). Returns the reminder id.
+*/
+pub fn schedule_reminder(
+    conn: &Connection,
+    role: &Role,
+    patient_id: &str,
+    kind: ReminderKind,
+    note: &str,
+    first_due: DateTime<Utc>,
+    interval_hours: Option<u32>,
+) -> Result<i64, String> {
+    check_access(conn, role, patient_id, Permission::ManageReminders)?;
+    let note = note.trim();
+    validate_free_text(note, MAX_NOTE_LENGTH)?;
+    if let Some(hours) = interval_hours {
+        if !(MIN_INTERVAL_HOURS..=MAX_INTERVAL_HOURS).contains(&hours) {
+            return Err(format!("a reminder repeats every {} to {} hours", MIN_INTERVAL_HOURS, MAX_INTERVAL_HOURS));
+        }
+    }
+    conn.execute(
+        "INSERT INTO care_reminders (patient_id, kind, note, interval_hours, next_due_at, created_by, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![patient_id, kind.as_str(), note, interval_hours, timestamps::to_storage(first_due), role.id, timestamps::now()],
+    )
+    .map_err(|e| e.to_string())?;
+    let reminder_id = conn.last_insert_rowid();
+    tracing::info!(target: SECURITY_TARGET, user_id = %role.id, patient_id = %patient_id, reminder_id, kind = kind.as_str(), "Care reminder scheduled");
+    Ok(reminder_id)
+}
+
+// stop a reminder; its completions stay in the history
+pub fn cancel_reminder(conn: &Connection, role: &Role, reminder_id: i64) -> Result<(), String> {
+    let reminder = load_active(conn, reminder_id).map_err(|e| e.to_string())?.ok_or("no such reminder")?;
+    check_access(conn, role, &reminder.patient_id, Permission::ManageReminders)?;
+    conn.execute(
+        "UPDATE care_reminders SET ended_at = ?2, ended_by = ?3 WHERE reminder_id = ?1 AND ended_at IS NULL",
+        params![reminder_id, timestamps::now(), role.id],
+    )
+    .map_err(|e| e.to_string())?;
+    tracing::info!(target: SECURITY_TARGET, user_id = %role.id, patient_id = %reminder.patient_id, reminder_id, "Care reminder cancelled");
+    Ok(())
+}
+
+/*
+Mark a reminder done now. A repeating reminder is due again interval_hours from now and the new due time
+is returned; a one-off reminder ends and None is returned.
+*/
+pub fn complete_reminder(conn: &Connection, role: &Role, reminder_id: i64) -> Result<Option<String>, String> {
+    let reminder = load_active(conn, reminder_id).map_err(|e| e.to_string())?.ok_or("no such reminder")?;
+    check_access(conn, role, &reminder.patient_id, Permission::ViewReminders)?;
+    let now = Utc::now();
+    let completed_at = timestamps::to_storage(now);
+    let next_due = reminder.interval_hours.map(|hours| timestamps::to_storage(now + Duration::hours(i64::from(hours))));
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    tx.execute(
+        "INSERT INTO reminder_completions (reminder_id, patient_id, due_at, completed_at, completed_by) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![reminder_id, reminder.patient_id, reminder.next_due_at, completed_at, role.id],
+    )
+    .map_err(|e| e.to_string())?;
+    match &next_due {
+        Some(next_due) => tx.execute("UPDATE care_reminders SET next_due_at = ?2 WHERE reminder_id = ?1", params![reminder_id, next_due]),
+        None => tx.execute(
+            "UPDATE care_reminders SET ended_at = ?2, ended_by = ?3 WHERE reminder_id = ?1",
+            params![reminder_id, completed_at, role.id],
+        ),
+    }
+    .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    tracing::info!(target: SECURITY_TARGET, user_id = %role.id, patient_id = %reminder.patient_id, reminder_id, "Care reminder completed");
+    Ok(next_due)
+}
+
+// active reminders of a patient, soonest first
+pub fn reminders_for_patient(conn: &Connection, role: &Role, patient_id: &str) -> Result<Vec<Reminder>, String> {
+    check_access(conn, role, patient_id, Permission::ViewReminders)?;
+    active_reminders(conn, patient_id, None).map_err(|e| e.to_string())
+}
+
+// active reminders of a patient, optionally only those due before `until`
+fn active_reminders(conn: &Connection, patient_id: &str, until: Option<&str>) -> rusqlite::Result<Vec<Reminder>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {}
+         WHERE r.patient_id = ?1 AND r.ended_at IS NULL AND (?2 IS NULL OR julianday(r.next_due_at) <= julianday(?2))
+         ORDER BY julianday(r.next_due_at), r.reminder_id",
+        REMINDER_COLUMNS
+    ))?;
+    let reminders = stmt.query_map(params![patient_id, until], reminder_from_row)?.collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(reminders)
+}
+
+// reminders overdue or due within DUE_SOON_HOURS for every patient the role looks after, soonest first
+pub fn due_soon(conn: &Connection, role: &Role) -> rusqlite::Result<Vec<Reminder>> {
+    let until = timestamps::to_storage(Utc::now() + Duration::hours(i64::from(DUE_SOON_HOURS)));
+    let mut due = Vec::new();
+    for patient in access_control::accessible_patients(conn, role, &Permission::ViewReminders)? {
+        due.extend(active_reminders(conn, &patient.patient_id, Some(&until))?);
+    }
+    due.sort_by_key(|reminder| timestamps::parse(&reminder.next_due_at));
+    Ok(due)
+}
+
+// the patient's most recent completions, newest first
+pub fn completion_history(conn: &Connection, role: &Role, patient_id: &str, limit: u32) -> Result<Vec<Completion>, String> {
+    check_access(conn, role, patient_id, Permission::ViewReminders)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT r.kind, r.note, c.due_at, c.completed_at, COALESCE(u.user_name, c.completed_by)
+             FROM reminder_completions c
+             JOIN care_reminders r ON r.reminder_id = c.reminder_id
+             LEFT JOIN users u ON u.id = c.completed_by
+             WHERE c.patient_id = ?1
+             ORDER BY c.completed_at DESC, c.completion_id DESC
+             LIMIT ?2",
+        )
+        .map_err(|e| e.to_string())?;
+    let completions = stmt
+        .query_map(params![patient_id, limit], |row| {
+            Ok(Completion { kind: row.get(0)?, note: row.get(1)?, due_at: row.get(2)?, completed_at: row.get(3)?, completed_by: row.get(4)? })
+        })
+        .and_then(|rows| rows.collect())
+        .map_err(|e| e.to_string())?;
+    Ok(completions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    #[test]
+    fn repeating_reminders_come_back_and_one_off_reminders_end() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let clinician = Role::new(&conn, "clinician", &fixtures.clinician_id);
+        let other = Role::new(&conn, "clinician", &fixtures.other_clinician_id);
+        let caretaker = Role::new(&conn, "caretaker", &fixtures.caretaker_id);
+        let patient = Role::new(&conn, "patient", &fixtures.patient_id);
+
+        let overdue = Utc::now() - Duration::hours(2);
+        assert!(schedule_reminder(&conn, &other, &fixtures.patient_id, ReminderKind::SiteChange, "", overdue, Some(72)).is_err());
+        assert!(schedule_reminder(&conn, &caretaker, &fixtures.patient_id, ReminderKind::SiteChange, "", overdue, Some(72)).is_err());
+        assert!(schedule_reminder(&conn, &patient, &fixtures.patient_id, ReminderKind::SiteChange, "", overdue, Some(0)).is_err());
+        let site = schedule_reminder(&conn, &patient, &fixtures.patient_id, ReminderKind::SiteChange, "", overdue, Some(72)).unwrap();
+        let visit = schedule_reminder(&conn, &clinician, &fixtures.patient_id, ReminderKind::Appointment, "Room 4", Utc::now() + Duration::hours(6), None).unwrap();
+        schedule_reminder(&conn, &clinician, &fixtures.patient_id, ReminderKind::GlucoseCheck, "", Utc::now() + Duration::days(3), Some(4)).unwrap();
+
+        // the caretaker sees what is due in the next day, overdue first
+        let due = due_soon(&conn, &caretaker).unwrap();
+        assert_eq!(due.iter().map(|r| r.reminder_id).collect::<Vec<_>>(), vec![site, visit]);
+        assert!(due[0].is_overdue() && !due[1].is_overdue());
+        assert!(due_soon(&conn, &other).unwrap().is_empty());
+
+        let next_due = complete_reminder(&conn, &caretaker, site).unwrap().unwrap();
+        let hours_ahead = (timestamps::parse(&next_due).unwrap() - Utc::now()).num_hours();
+        assert!((71..=72).contains(&hours_ahead));
+        assert_eq!(complete_reminder(&conn, &patient, visit).unwrap(), None);
+        assert!(complete_reminder(&conn, &patient, visit).is_err());
+        assert!(complete_reminder(&conn, &other, site).is_err());
+        assert!(due_soon(&conn, &patient).unwrap().is_empty());
+        assert_eq!(reminders_for_patient(&conn, &clinician, &fixtures.patient_id).unwrap().len(), 2);
+
+        let history = completion_history(&conn, &clinician, &fixtures.patient_id, 10).unwrap();
+        assert_eq!(history.len(), 2);
+        let site_change = history.iter().find(|c| c.kind == "site_change").unwrap();
+        assert!(site_change.was_late());
+        assert_eq!(site_change.completed_by, "care.fixture");
+
+        assert!(cancel_reminder(&conn, &caretaker, site).is_err());
+        cancel_reminder(&conn, &patient, site).unwrap();
+        assert_eq!(reminders_for_patient(&conn, &patient, &fixtures.patient_id).unwrap().len(), 1);
+        assert!(completion_history(&conn, &other, &fixtures.patient_id, 10).is_err());
+    }
+}
@@ -0,0 +1,167 @@
+// Per-patient clinical summary rendered to PDF for clinic visits
+use crate::access_control::{self, Permission, Resource, Role};
+use crate::analytics::{self, GlucoseStats, GmiEstimate, Window, TARGET_HIGH, TARGET_LOW};
+use crate::config;
+use crate::db::models::Patient;
+use crate::db::queries;
+use crate::logger::SECURITY_TARGET;
+use crate::timestamps;
+use crate::units::{self, GlucoseUnit};
+use chrono::Utc;
+use chrono_tz::Tz;
+use printpdf::{BuiltinFont, IndirectFontRef, Mm, PdfDocument, PdfLayerReference};
+use rusqlite::{params, Connection};
+use std::error::Error;
+use std::fs::{create_dir_all, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+pub struct PatientSummary {
+    pub patient: Patient,
+    pub days: u32,
+    pub glucose: Option<GlucoseStats>,
+    // over 14, 30 and 90 days whatever the reporting period
+    pub gmi: Vec<GmiEstimate>,
+    pub total_insulin: f64,
+    pub average_daily_dose: f64,
+    pub alert_count: i64,
+    pub open_alert_count: i64,
+}
+
+// gather the summary figures from glucose_readings, insulin_logs and alerts
+pub fn build_patient_summary(conn: &Connection, patient: Patient, days: u32) -> rusqlite::Result<PatientSummary> {
+    let window = format!("-{} days", days);
+    let glucose = analytics::glucose_stats(conn, &patient.patient_id, Window::Days(days))?;
+    let gmi = analytics::gmi_estimates(conn, &patient.patient_id, &timestamps::now())?;
+
+    let total_insulin: f64 = conn.query_row(
+        "SELECT COALESCE(SUM(dosage_units), 0) FROM insulin_doses
+         WHERE patient_id = ?1 AND julianday(dosage_time) >= julianday('now', ?2)",
+        params![patient.patient_id, window],
+        |row| row.get(0),
+    )?;
+
+    let (alert_count, open_alert_count): (i64, i64) = conn.query_row(
+        "SELECT COUNT(*), COALESCE(SUM(CASE WHEN is_resolved THEN 0 ELSE 1 END), 0) FROM alerts
+         WHERE patient_id = ?1 AND julianday(alert_time) >= julianday('now', ?2)",
+        params![patient.patient_id, window],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    Ok(PatientSummary {
+        glucose,
+        gmi,
+        total_insulin,
+        average_daily_dose: total_insulin / days.max(1) as f64,
+        alert_count,
+        open_alert_count,
+        days,
+        patient,
+    })
+}
+
+// writes lines top to bottom on an A4 page
+struct PageWriter<'a> {
+    layer: PdfLayerReference,
+    font: &'a IndirectFontRef,
+    bold: &'a IndirectFontRef,
+    y: f32,
+}
+
+impl PageWriter<'_> {
+    fn heading(&mut self, text: &str) {
+        self.y -= 4.0;
+        self.layer.use_text(text, 14.0, Mm(20.0), Mm(self.y), self.bold);
+        self.y -= 8.0;
+    }
+
+    fn line(&mut self, text: &str) {
+        self.layer.use_text(text, 11.0, Mm(20.0), Mm(self.y), self.font);
+        self.y -= 6.0;
+    }
+}
+
+// glucose figures in the given unit, the generation time in the given zone
+pub fn render_summary_pdf(summary: &PatientSummary, unit: GlucoseUnit, zone: Tz, path: &Path) -> Result<(), Box<dyn Error>> {
+    let patient = &summary.patient;
+    let (doc, page, layer) = PdfDocument::new("GlucoGuard Clinical Summary", Mm(210.0), Mm(297.0), "Summary");
+    let font = doc.add_builtin_font(BuiltinFont::Helvetica)?;
+    let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold)?;
+    let mut page = PageWriter {
+        layer: doc.get_page(page).get_layer(layer),
+        font: &font,
+        bold: &bold,
+        y: 277.0,
+    };
+
+    page.layer.use_text("GlucoGuard Clinical Summary", 18.0, Mm(20.0), Mm(page.y), &bold);
+    page.y -= 10.0;
+    page.line(&format!("Generated: {}", timestamps::format_time(Utc::now(), zone)));
+    page.line(&format!("Reporting period: last {} days", summary.days));
+    if patient.is_simulated {
+        page.line("SIMULATED PATIENT - training data, not for clinical use");
+    }
+
+    page.heading("Patient");
+    page.line(&format!("Name: {} {}", patient.first_name, patient.last_name));
+    page.line(&format!("Date of birth: {}", patient.date_of_birth));
+    page.line(&format!("Basal rate: {:.2}   Bolus rate: {:.2}   Max dosage: {:.2}",
+        patient.basal_rate, patient.bolus_rate, patient.max_dosage));
+    page.line(&format!("Alert thresholds: low {}, high {}",
+        unit.format(f64::from(patient.low_glucose_threshold)), unit.format(f64::from(patient.high_glucose_threshold))));
+
+    page.heading("Glucose");
+    match &summary.glucose {
+        Some(stats) => {
+            page.line(&format!("Readings: {}", stats.count));
+            page.line(&format!("Average glucose: {:.1} {} (SD {:.1}, CV {:.1}%)",
+                unit.convert(stats.mean), unit.label(), unit.convert(stats.std_dev), stats.cv));
+            page.line(&format!("Time in range ({}-{} {}): {:.1}%",
+                unit.value(TARGET_LOW), unit.value(TARGET_HIGH), unit.label(), stats.time_in_range_pct));
+            page.line(&format!("Time below range: {:.1}%", stats.time_below_pct));
+            page.line(&format!("Time above range: {:.1}%", stats.time_above_pct));
+        }
+        None => page.line("No glucose readings in this period"),
+    }
+
+    page.heading("Glucose management indicator (estimated A1c)");
+    if summary.gmi.is_empty() {
+        page.line(&format!("No glucose readings in the last {} days", Window::Quarter.days()));
+    }
+    for estimate in &summary.gmi {
+        page.line(&analytics::describe_gmi(estimate));
+    }
+
+    page.heading("Insulin");
+    page.line(&format!("Total insulin delivered: {:.2} units", summary.total_insulin));
+    page.line(&format!("Average total daily dose: {:.2} units/day", summary.average_daily_dose));
+
+    page.heading("Alerts");
+    page.line(&format!("Alerts raised: {}", summary.alert_count));
+    page.line(&format!("Unresolved alerts: {}", summary.open_alert_count));
+
+    doc.save(&mut BufWriter::new(File::create(path)?))?;
+    Ok(())
+}
+
+// build and render a patient's summary into export.dir
+pub fn generate_patient_report(conn: &Connection, role: &Role, patient_id: &str, days: u32) -> Result<PathBuf, Box<dyn Error>> {
+    if !access_control::can_access(conn, role, &Resource::Patient(patient_id), &Permission::ViewGlucose) {
+        return Err("Access denied: patient is not in your care or ViewGlucose is missing".into());
+    }
+    let patient = queries::get_patient_by_id(conn, patient_id)?
+        .ok_or("Patient not found")?;
+    let summary = build_patient_summary(conn, patient, days)?;
+
+    let report_dir = &config::get().export.dir;
+    create_dir_all(report_dir)?;
+    let path = Path::new(report_dir).join(format!(
+        "{}_summary_{}.pdf",
+        patient_id,
+        Utc::now().format("%Y%m%d_%H%M%S")
+    ));
+    render_summary_pdf(&summary, units::display_unit(conn, &role.id), timestamps::display_zone(conn, &role.id), &path)?;
+
+    tracing::info!(target: SECURITY_TARGET, user_id = %role.id, patient_id = %patient_id, path = %path.display(), "Clinical summary report generated");
+    Ok(path)
+}
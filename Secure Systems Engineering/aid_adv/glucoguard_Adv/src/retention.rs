@@ -0,0 +1,319 @@
+// Data retention: remove or archive glucose readings, old sessions and audit/export files past their age
+// Runs hourly from the session cleanup task when retention.enabled, or by hand with `glucoguard retention [--dry-run]`
+use crate::audit_dump;
+use crate::config::{self, RetentionConfig};
+use crate::db::initialize;
+use crate::logger::SECURITY_TARGET;
+use chrono::{Duration as ChronoDuration, Utc};
+use rusqlite::{params_from_iter, types::Value, Connection};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// how often the cleanup task applies the policy
+pub const RUN_INTERVAL: Duration = Duration::from_secs(60 * 60);
+// subdirectory archived files are moved into
+const ARCHIVE_DIR: &str = "archive";
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Default)]
+pub struct RetentionReport {
+    pub glucose_readings: usize,
+    pub sessions: usize,
+    pub files: usize,
+    pub dry_run: bool,
+}
+
+// rows of one table matched by a fixed predicate; table and predicate never come from user input
+struct RowRule {
+    table: &'static str,
+    predicate: &'static str,
+    params: Vec<Value>,
+}
+
+fn glucose_rule(days: u32) -> RowRule {
+    let cutoff = Utc::now().naive_utc() - ChronoDuration::days(days as i64);
+    RowRule {
+        table: "glucose_readings",
+        predicate: "julianday(reading_time) < julianday(?1)",
+        params: vec![Value::Text(cutoff.format("%Y-%m-%d %H:%M:%S").to_string())],
+    }
+}
+
+// sessions created more than `days` ago that are logged out or expired; live sessions are never touched
+fn session_rule(days: u32) -> RowRule {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let cutoff = now.saturating_sub(days as u64 * SECONDS_PER_DAY);
+    RowRule {
+        table: "sessions",
+        predicate: "creation_time < ?1 AND (active = 0 OR creation_time + expiration_time < ?2)",
+        params: vec![Value::Integer(cutoff as i64), Value::Integer(now as i64)],
+    }
+}
+
+fn count_rows(conn: &Connection, rule: &RowRule) -> rusqlite::Result<usize> {
+    let sql = format!("SELECT COUNT(*) FROM main.{} WHERE {}", rule.table, rule.predicate);
+    conn.query_row(&sql, params_from_iter(rule.params.iter()), |row| row.get::<_, i64>(0))
+        .map(|count| count as usize)
+}
+
+fn delete_rows(conn: &Connection, rule: &RowRule) -> rusqlite::Result<usize> {
+    let sql = format!("DELETE FROM main.{} WHERE {}", rule.table, rule.predicate);
+    conn.execute(&sql, params_from_iter(rule.params.iter()))
+}
+
+// copy matching rows into the same table in archive_path, then delete them, in one transaction
+fn archive_rows(conn: &Connection, archive_path: &str, rule: &RowRule) -> rusqlite::Result<usize> {
+    conn.execute("ATTACH DATABASE ?1 AS retention_archive", [archive_path])?;
+    let moved = (|| {
+        let tx = conn.unchecked_transaction()?;
+        tx.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS retention_archive.{table} AS SELECT * FROM main.{table} WHERE 0",
+            table = rule.table
+        ))?;
+        tx.execute(
+            &format!("INSERT INTO retention_archive.{table} SELECT * FROM main.{table} WHERE {}", rule.predicate, table = rule.table),
+            params_from_iter(rule.params.iter()),
+        )?;
+        let deleted = delete_rows(&tx, rule)?;
+        tx.commit()?;
+        Ok(deleted)
+    })();
+    conn.execute("DETACH DATABASE retention_archive", [])?;
+    moved
+}
+
+fn apply_rule(conn: &Connection, settings: &RetentionConfig, rule: &RowRule, dry_run: bool) -> rusqlite::Result<usize> {
+    if dry_run {
+        count_rows(conn, rule)
+    } else if settings.action == "archive" {
+        archive_rows(conn, &settings.archive_path, rule)
+    } else {
+        delete_rows(conn, rule)
+    }
+}
+
+// regular files in `dir` accepted by `matches` and last modified more than `days` ago
+fn expired_files(dir: &Path, days: u32, matches: impl Fn(&str) -> bool) -> std::io::Result<Vec<PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let cutoff = SystemTime::now() - Duration::from_secs(days as u64 * SECONDS_PER_DAY);
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if metadata.is_file() && matches(&name) && metadata.modified()? < cutoff {
+            files.push(entry.path());
+        }
+    }
+    Ok(files)
+}
+
+fn apply_files(files: &[PathBuf], settings: &RetentionConfig, dry_run: bool) -> std::io::Result<usize> {
+    for path in files {
+        if dry_run {
+            tracing::info!(path = %path.display(), "Retention dry run: file would be removed");
+        } else if settings.action == "archive" {
+            let dir = path.parent().unwrap_or(Path::new(".")).join(ARCHIVE_DIR);
+            fs::create_dir_all(&dir)?;
+            fs::rename(path, dir.join(path.file_name().unwrap_or_default()))?;
+        } else {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(files.len())
+}
+
+// apply every configured rule once; dry_run only counts
+pub fn apply(conn: &Connection, settings: &RetentionConfig, dry_run: bool) -> Result<RetentionReport, Box<dyn Error>> {
+    let mut report = RetentionReport { dry_run, ..Default::default() };
+
+    if settings.glucose_readings_days > 0 {
+        report.glucose_readings = apply_rule(conn, settings, &glucose_rule(settings.glucose_readings_days), dry_run)?;
+    }
+    if settings.sessions_days > 0 {
+        report.sessions = apply_rule(conn, settings, &session_rule(settings.sessions_days), dry_run)?;
+    }
+    if settings.audit_exports_days > 0 {
+        let app = config::get();
+        let mut files = expired_files(Path::new(&app.logging.log_dir), settings.audit_exports_days, |name| {
+            name.starts_with(audit_dump::FILE_PREFIX)
+        })?;
+        files.extend(expired_files(Path::new(&app.export.dir), settings.audit_exports_days, |_| true)?);
+        report.files = apply_files(&files, settings, dry_run)?;
+    }
+
+    if report.glucose_readings + report.sessions + report.files > 0 {
+        tracing::info!(
+            target: SECURITY_TARGET,
+            action = %settings.action,
+            dry_run,
+            glucose_readings = report.glucose_readings,
+            sessions = report.sessions,
+            files = report.files,
+            "Retention policy applied"
+        );
+    }
+    Ok(report)
+}
+
+// `glucoguard retention [--dry-run]`, runs even when retention.enabled is false
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let settings = config::get().retention.clone();
+    let dry_run = match args {
+        [] => settings.dry_run,
+        [flag] if flag == "--dry-run" => true,
+        _ => return Err("usage: glucoguard retention [--dry-run]".into()),
+    };
+    let conn = initialize::establish_connection()?;
+    let report = apply(&conn, &settings, dry_run)?;
+    let verb = match (report.dry_run, settings.action.as_str()) {
+        (true, _) => "would be removed",
+        (false, "archive") => "archived",
+        (false, _) => "deleted",
+    };
+    println!(
+        "{} glucose readings, {} sessions and {} audit/export files {}",
+        report.glucose_readings, report.sessions, report.files, verb
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::{client_fingerprint, Session};
+    use crate::db::queries;
+    use crate::test_utils;
+    use rusqlite::params;
+
+    fn settings(action: &str) -> RetentionConfig {
+        RetentionConfig {
+            enabled: true,
+            action: action.to_string(),
+            glucose_readings_days: 30,
+            sessions_days: 30,
+            audit_exports_days: 0,
+            ..Default::default()
+        }
+    }
+
+    fn add_reading(conn: &Connection, patient_id: &str, days_ago: i64) {
+        conn.execute(
+            "INSERT INTO glucose_readings (patient_id, glucose_level, reading_time, status)
+             VALUES (?1, 120, datetime('now', ?2), 'normal')",
+            params![patient_id, format!("-{} days", days_ago)],
+        )
+        .unwrap();
+    }
+
+    fn add_session(conn: &Connection, session_id: &str, user_id: &str, days_ago: u64, active: bool) {
+        let session = Session {
+            session_id: session_id.to_string(),
+            user_id: user_id.to_string(),
+            role: "clinician".to_string(),
+            create_time: SystemTime::now() - Duration::from_secs(days_ago * SECONDS_PER_DAY),
+            exp_time: Duration::from_secs(3600),
+            active: true,
+            fingerprint: client_fingerprint().to_string(),
+        };
+        queries::add_session_to_db(conn, &session).unwrap();
+        if !active {
+            queries::deactivate_session(conn, session_id).unwrap();
+        }
+    }
+
+    fn count(conn: &Connection, table: &str) -> i64 {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn dry_run_counts_without_removing() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        add_reading(&conn, &fixtures.patient_id, 45);
+        add_reading(&conn, &fixtures.patient_id, 1);
+
+        let report = apply(&conn, &settings("delete"), true).unwrap();
+        assert_eq!(report.glucose_readings, 1);
+        assert_eq!(count(&conn, "glucose_readings"), 2);
+    }
+
+    #[test]
+    fn delete_removes_only_expired_rows() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        add_reading(&conn, &fixtures.patient_id, 45);
+        add_reading(&conn, &fixtures.patient_id, 29);
+        add_session(&conn, "old-logged-out", &fixtures.clinician_id, 40, false);
+        add_session(&conn, "old-expired", &fixtures.clinician_id, 40, true);
+        add_session(&conn, "recent", &fixtures.clinician_id, 2, false);
+
+        let report = apply(&conn, &settings("delete"), false).unwrap();
+        assert_eq!((report.glucose_readings, report.sessions), (1, 2));
+        assert_eq!(count(&conn, "glucose_readings"), 1);
+        assert!(queries::get_session_by_id(&conn, "recent").unwrap().is_some());
+        assert!(queries::get_session_by_id(&conn, "old-expired").unwrap().is_none());
+    }
+
+    #[test]
+    fn zero_days_keeps_everything() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        add_reading(&conn, &fixtures.patient_id, 4000);
+        let keep = RetentionConfig { glucose_readings_days: 0, sessions_days: 0, ..settings("delete") };
+        assert_eq!(apply(&conn, &keep, false).unwrap().glucose_readings, 0);
+        assert_eq!(count(&conn, "glucose_readings"), 1);
+    }
+
+    #[test]
+    fn archive_moves_rows_to_the_archive_database() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        add_reading(&conn, &fixtures.patient_id, 45);
+        add_reading(&conn, &fixtures.patient_id, 60);
+        add_reading(&conn, &fixtures.patient_id, 3);
+
+        let dir = std::env::temp_dir().join(format!("glucoguard-retention-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let archive_path = dir.join("archive.db");
+        let _ = fs::remove_file(&archive_path);
+        let archive = RetentionConfig { archive_path: archive_path.to_string_lossy().to_string(), ..settings("archive") };
+
+        assert_eq!(apply(&conn, &archive, false).unwrap().glucose_readings, 2);
+        assert_eq!(count(&conn, "glucose_readings"), 1);
+        let archived = Connection::open(&archive_path).unwrap();
+        assert_eq!(count(&archived, "glucose_readings"), 2);
+        // the archive database is detached again
+        assert!(conn.execute("DETACH DATABASE retention_archive", []).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn expired_files_are_removed_or_archived() {
+        let dir = std::env::temp_dir().join(format!("glucoguard-retention-files-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let old = dir.join("system_audit_old.txt");
+        let other = dir.join("notes_old.txt");
+        let recent = dir.join("system_audit_new.txt");
+        for path in [&old, &other, &recent] {
+            fs::write(path, "audit").unwrap();
+        }
+        let long_ago = SystemTime::now() - Duration::from_secs(90 * SECONDS_PER_DAY);
+        for path in [&old, &other] {
+            fs::File::options().write(true).open(path).unwrap().set_modified(long_ago).unwrap();
+        }
+
+        let files = expired_files(&dir, 30, |name| name.starts_with(audit_dump::FILE_PREFIX)).unwrap();
+        assert_eq!(files, vec![old.clone()]);
+        assert_eq!(apply_files(&files, &settings("archive"), false).unwrap(), 1);
+        assert!(!old.exists());
+        assert!(dir.join(ARCHIVE_DIR).join("system_audit_old.txt").exists());
+        assert!(other.exists() && recent.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
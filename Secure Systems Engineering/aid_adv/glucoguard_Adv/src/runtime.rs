@@ -0,0 +1,44 @@
+// Shared tokio runtime for background tasks and network services
+// The interactive menus stay on the main thread; session cleanup, uploads and servers run here
+use crate::db::pool::Db;
+use rusqlite::Connection;
+use std::future::Future;
+use std::sync::OnceLock;
+use tokio::runtime::{Builder, Runtime};
+use tokio::task::JoinHandle;
+
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+// started on first use and kept for the life of the process
+pub fn get() -> &'static Runtime {
+    RUNTIME.get_or_init(|| {
+        Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("glucoguard-worker")
+            .build()
+            .expect("failed to start the tokio runtime")
+    })
+}
+
+// run a background task, callable from plain (non-async) code such as the menus
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    get().spawn(future)
+}
+
+// run blocking rusqlite work on the blocking pool with a pooled connection,
+// so async tasks never stall a worker thread on SQLite
+pub async fn with_db<T, F>(db: &Db, work: F) -> Result<T, String>
+where
+    F: FnOnce(&Connection) -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let db = db.clone();
+    tokio::task::spawn_blocking(move || db.get().map(|conn| work(&conn)))
+        .await
+        .map_err(|e| format!("database task failed: {}", e))?
+        .map_err(|e| format!("no database connection available: {}", e))
+}
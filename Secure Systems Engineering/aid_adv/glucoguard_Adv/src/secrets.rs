@@ -0,0 +1,137 @@
+/*
+Secrets: signing keys, the research key, the legacy audit dump key, the metrics token and the SMTP, MQTT
+and Nightscout credentials.
+Each of these settings (in glucoguard.toml or its GLUCOGUARD_* variable) can name where the value is kept
+instead of holding it:
+  keyring:<entry>  the password stored in the OS keyring under service "glucoguard" and account <entry>
+                   (secret-tool / libsecret on Linux, the login keychain on macOS)
+  file:<path>      the contents of a key file that only its owner can read (mode 0600 or 0400)
+References are resolved once, when the configuration is loaded, so the rest of the program only ever
+sees the value. A setting without either prefix is used as written.
+*/
+use crate::config::Config;
+use std::error::Error;
+use std::fs;
+use std::process::Command;
+
+pub const KEYRING_SERVICE: &str = "glucoguard";
+const KEYRING_PREFIX: &str = "keyring:";
+const FILE_PREFIX: &str = "file:";
+
+// every setting holding key material or a credential
+fn secret_settings(config: &mut Config) -> [(&'static str, &mut String); 9] {
+    [
+        ("export.research_key", &mut config.export.research_key),
+        ("audit.signing_key", &mut config.audit.signing_key),
+        ("audit.legacy_dump_key", &mut config.audit.legacy_dump_key),
+        ("devices.command_signing_key", &mut config.devices.command_signing_key),
+        ("maintenance.signing_key", &mut config.maintenance.signing_key),
+        ("email.password", &mut config.email.password),
+        ("mqtt.password", &mut config.mqtt.password),
+        ("nightscout.api_secret", &mut config.nightscout.api_secret),
+        ("api.metrics_token", &mut config.api.metrics_token),
+    ]
+}
+
+// replace every keyring: or file: reference in the configuration with the secret it names
+pub fn resolve(config: &mut Config) -> Result<(), Box<dyn Error>> {
+    for (name, value) in secret_settings(config) {
+        *value = resolve_value(value).map_err(|e| format!("{}: {}", name, e))?;
+    }
+    Ok(())
+}
+
+// the secret a reference names, or the value itself when it is not a reference
+pub fn resolve_value(value: &str) -> Result<String, String> {
+    if let Some(entry) = value.strip_prefix(KEYRING_PREFIX) {
+        from_keyring(entry.trim())
+    } else if let Some(path) = value.strip_prefix(FILE_PREFIX) {
+        from_key_file(path.trim())
+    } else {
+        Ok(value.to_string())
+    }
+}
+
+fn keyring_lookup(entry: &str) -> Option<Command> {
+    if cfg!(target_os = "macos") {
+        let mut command = Command::new("security");
+        command.args(["find-generic-password", "-s", KEYRING_SERVICE, "-a", entry, "-w"]);
+        Some(command)
+    } else if cfg!(unix) {
+        let mut command = Command::new("secret-tool");
+        command.args(["lookup", "service", KEYRING_SERVICE, "account", entry]);
+        Some(command)
+    } else {
+        None
+    }
+}
+
+fn from_keyring(entry: &str) -> Result<String, String> {
+    if entry.is_empty() {
+        return Err("keyring: needs an entry name, e.g. keyring:audit-signing-key".to_string());
+    }
+    let mut lookup = keyring_lookup(entry).ok_or("the OS keyring is only supported on Linux and macOS, use a file: reference")?;
+    let output = lookup
+        .output()
+        .map_err(|e| format!("cannot run the keyring tool ({}), install secret-tool or use a file: reference", e))?;
+    let secret = String::from_utf8(output.stdout).map_err(|_| "keyring entry is not valid UTF-8")?;
+    let secret = secret.trim_end_matches(['\r', '\n']);
+    if !output.status.success() || secret.is_empty() {
+        return Err(format!("no entry '{}' for service '{}' in the OS keyring", entry, KEYRING_SERVICE));
+    }
+    Ok(secret.to_string())
+}
+
+fn from_key_file(path: &str) -> Result<String, String> {
+    let metadata = fs::metadata(path).map_err(|e| format!("cannot read key file '{}': {}", path, e))?;
+    if !metadata.is_file() {
+        return Err(format!("key file '{}' is not a regular file", path));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = metadata.permissions().mode() & 0o777;
+        if mode & 0o077 != 0 {
+            return Err(format!("key file '{}' has mode {:o}, it must be readable by its owner only (chmod 600)", path, mode));
+        }
+    }
+    let secret = fs::read_to_string(path).map_err(|e| format!("cannot read key file '{}': {}", path, e))?;
+    let secret = secret.trim_end_matches(['\r', '\n']);
+    if secret.is_empty() {
+        return Err(format!("key file '{}' is empty", path));
+    }
+    Ok(secret.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_files_must_be_private_and_plain_values_pass_through() {
+        let dir = std::env::temp_dir().join(format!("glucoguard-secrets-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let key_file = dir.join("audit.key");
+        fs::write(&key_file, "0123456789abcdef0123456789abcdef\n").unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&key_file, fs::Permissions::from_mode(0o644)).unwrap();
+            assert!(resolve_value(&format!("file:{}", key_file.display())).unwrap_err().contains("chmod 600"));
+            fs::set_permissions(&key_file, fs::Permissions::from_mode(0o600)).unwrap();
+        }
+
+        let mut config = Config::default();
+        config.audit.signing_key = format!("file:{}", key_file.display());
+        config.nightscout.api_secret = "plain-secret-value".to_string();
+        resolve(&mut config).unwrap();
+        assert_eq!(config.audit.signing_key, "0123456789abcdef0123456789abcdef");
+        assert_eq!(config.nightscout.api_secret, "plain-secret-value");
+
+        config.mqtt.password = format!("file:{}", dir.join("missing.key").display());
+        assert!(resolve(&mut config).unwrap_err().to_string().starts_with("mqtt.password: cannot read key file"));
+        assert!(resolve_value("keyring:").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,8 +1,28 @@
-use std::time::{SystemTime, Duration};
+use std::collections::HashMap;
+use std::time::{SystemTime, Duration, Instant};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use sha2::{Digest, Sha256};
 use crate::db::queries;
+use crate::db::pool::Db;
+use crate::runtime;
+use crate::retention;
+use crate::housekeeping;
+use crate::temp_basal;
+use crate::low_suspend;
+use crate::escalation;
+use crate::monitoring;
+use crate::health;
+use crate::shutdown;
+use crate::timestamps;
+use crate::daily_summary;
 use rusqlite::Connection;
 use rand::RngCore;
 use crate::access_control::{Role, Permission};
+use crate::config;
+use crate::error::GlucoError;
+use crate::logger::SECURITY_TARGET;
+use crate::maintenance;
+use tracing::{error, info, warn};
 
 /*
 Securely track logged-in users.
@@ -20,12 +40,149 @@ pub struct Session {
     pub create_time: SystemTime,
     pub exp_time: Duration,
     pub active: bool,
+    // hash of the client that created the session (see client_fingerprint)
+    pub fingerprint: String,
 }
 
 impl Session {
     pub fn is_expired(&self) -> bool {
         self.create_time.elapsed().unwrap_or_default() > self.exp_time
     }
+
+    // usable = not logged out/evicted, not expired and used from the client that created it
+    pub fn is_valid(&self) -> bool {
+        self.active && !self.is_expired() && self.matches_client()
+    }
+
+    // sessions without a fingerprint (created before binding existed) never match
+    pub fn matches_client(&self) -> bool {
+        self.matches(client_fingerprint())
+    }
+
+    pub fn matches(&self, fingerprint: &str) -> bool {
+        !self.fingerprint.is_empty() && self.fingerprint == fingerprint
+    }
+}
+
+/*
+Client fingerprint: hostname, terminal and OS user of the running process.
+Stored hashed with the session so a copied session_id is useless on another machine/terminal.
+*/
+pub fn client_fingerprint() -> &'static str {
+    static FINGERPRINT: OnceLock<String> = OnceLock::new();
+    FINGERPRINT.get_or_init(|| {
+        let raw = format!("{}|{}|{}", host_name(), terminal_name(), process_user());
+        hex::encode(Sha256::digest(raw.as_bytes()))
+    })
+}
+
+/*
+REST API sessions are bound to the HTTP client that logged in (its IP address and User-Agent) instead,
+since the server process is the same for every client. The "api" prefix keeps the two kinds apart:
+a terminal session is never accepted over REST and a REST session never in a terminal.
+*/
+pub fn api_client_fingerprint(client_ip: &str, user_agent: &str) -> String {
+    let raw = format!("api|{}|{}", client_ip, user_agent);
+    hex::encode(Sha256::digest(raw.as_bytes()))
+}
+
+fn host_name() -> String {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .unwrap_or_else(|| "unknown-host".to_string())
+}
+
+fn terminal_name() -> String {
+    // controlling terminal of stdin on unix, e.g. /dev/pts/3
+    #[cfg(unix)]
+    if let Ok(path) = std::fs::read_link("/proc/self/fd/0") {
+        return path.to_string_lossy().to_string();
+    }
+    std::env::var("TERM_SESSION_ID")
+        .or_else(|_| std::env::var("SESSIONNAME"))
+        .unwrap_or_else(|_| "unknown-tty".to_string())
+}
+
+fn process_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown-user".to_string())
+}
+
+/*
+Session cache: the menus check the session on every action, so valid sessions are kept in memory for
+session.cache_ttl_secs after they are created or read. Every UPDATE of the sessions table in queries.rs
+(logout, revocation, eviction, the expiry cleanup) drops the entries it touches, so those take effect at
+once in this process; a session ended by another process is honoured here for at most cache_ttl_secs.
+*/
+const CACHE_CAPACITY: usize = 1024;
+
+struct CachedSession {
+    session: Session,
+    cached_at: Instant,
+}
+
+fn cache() -> MutexGuard<'static, HashMap<String, CachedSession>> {
+    static CACHE: OnceLock<Mutex<HashMap<String, CachedSession>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new())).lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn cache_ttl() -> Duration {
+    Duration::from_secs(config::get().session.cache_ttl_secs)
+}
+
+// remember a valid session; invalid ones are always read from the database
+fn cache_session(session: &Session) {
+    let ttl = cache_ttl();
+    if ttl.is_zero() || !session.is_valid() {
+        return;
+    }
+    let mut cache = cache();
+    if cache.len() >= CACHE_CAPACITY {
+        cache.retain(|_, entry| entry.cached_at.elapsed() < ttl && !entry.session.is_expired());
+        if cache.len() >= CACHE_CAPACITY {
+            cache.clear();
+        }
+    }
+    cache.insert(session.session_id.clone(), CachedSession { session: session.clone(), cached_at: Instant::now() });
+}
+
+fn cached_session(session_id: &str) -> Option<Session> {
+    let ttl = cache_ttl();
+    let mut cache = cache();
+    let entry = cache.get(session_id)?;
+    if ttl.is_zero() || entry.cached_at.elapsed() >= ttl || entry.session.is_expired() {
+        cache.remove(session_id);
+        return None;
+    }
+    Some(entry.session.clone())
+}
+
+pub fn forget_session(session_id: &str) {
+    cache().remove(session_id);
+}
+
+pub fn forget_user_sessions(user_id: &str) {
+    cache().retain(|_, entry| entry.session.user_id != user_id);
+}
+
+pub fn forget_expired_sessions() {
+    cache().retain(|_, entry| !entry.session.is_expired());
+}
+
+// the session row, from the cache while the entry is fresh
+fn load_session(conn: &Connection, session_id: &str) -> rusqlite::Result<Option<Session>> {
+    if let Some(session) = cached_session(session_id) {
+        return Ok(Some(session));
+    }
+    let session = queries::get_session_by_id(conn, session_id)?;
+    if let Some(session) = &session {
+        cache_session(session);
+    }
+    Ok(session)
 }
 
 //session manager to manage session creation and cleanup
@@ -37,8 +194,22 @@ impl SessionManager {
         Self
     }
 
-    // Create a new session and persist it in the DB
-    pub fn create_session(&self, conn: &Connection, user_id: String, role: String) -> rusqlite::Result<String> {
+    // Create a new session for this terminal and persist it in the DB
+    pub fn create_session(&self, conn: &Connection, user_id: String, role: String) -> Result<String, GlucoError> {
+        self.create_session_for_client(conn, user_id, role, client_fingerprint())
+    }
+
+    // Create a new session only usable by the client with this fingerprint
+    pub fn create_session_for_client(&self, conn: &Connection, user_id: String, role: String, fingerprint: &str) -> Result<String, GlucoError> {
+        // Suspended accounts get no new sessions
+        if queries::is_user_suspended(conn, &user_id)? {
+            warn!(target: SECURITY_TARGET, user_id = %user_id, "Session refused for suspended account");
+            return Err(GlucoError::Auth("Account is suspended. Contact an administrator.".to_string()));
+        }
+
+        // Enforce the concurrent session limit before issuing a new token
+        self.enforce_session_limit(conn, &user_id, &role)?;
+
         // Generate a random session token
         let mut bytes = [0u8; 32];
         rand::thread_rng().fill_bytes(&mut bytes);
@@ -50,34 +221,85 @@ impl SessionManager {
             user_id,
             role,
             create_time: SystemTime::now(),
-            exp_time: Duration::from_secs(60 * 60), // 1 hour
+            exp_time: Duration::from_secs(config::get().session.ttl_secs),
             active: true,
+            fingerprint: fingerprint.to_string(),
         };
 
         // Store directly in DB (no async)
         queries::add_session_to_db(conn, &session)?;
+        queries::record_login(conn, &session.user_id)?;
+        cache_session(&session);
+        info!(target: SECURITY_TARGET, user_id = %session.user_id, role = %session.role, "Session created");
 
         Ok(session_id)
     }
+    // Make room for a new session according to session.limit_policy
+    fn enforce_session_limit(&self, conn: &Connection, user_id: &str, role: &str) -> Result<(), GlucoError> {
+        let session_config = &config::get().session;
+        let limit = session_config.max_sessions_for(role) as usize;
+        if limit == 0 {
+            return Ok(());
+        }
+
+        let active_ids = queries::get_active_session_ids(conn, user_id)?;
+        if active_ids.len() < limit {
+            return Ok(());
+        }
+
+        if session_config.limit_policy == "reject" {
+            warn!(target: SECURITY_TARGET, user_id = %user_id, limit, "Session limit reached, login rejected");
+            return Err(GlucoError::Session(format!("Maximum of {} active session(s) reached. Log out elsewhere first.", limit)));
+        }
+
+        // evict_oldest: keep the newest (limit - 1) sessions
+        let evict_count = active_ids.len() + 1 - limit;
+        for session_id in active_ids.iter().take(evict_count) {
+            queries::deactivate_session(conn, session_id)?;
+        }
+        warn!(target: SECURITY_TARGET, user_id = %user_id, evicted = evict_count, "Session limit reached, oldest session(s) deactivated");
+        Ok(())
+    }
+
     // Retrieve a session by username
     pub fn get_session_by_username(&self, conn: &Connection, user_id: &str) -> Option<Session> {
         match queries::get_session(conn, user_id) {
-            Ok(Some(session)) if !session.is_expired() => Some(session),
+            Ok(Some(session)) if session.is_valid() => Some(session),
             _ => None,
         }
     }
 
     // Retrieve a session by ID
     pub fn get_session_by_id(&self, conn: &Connection, session_id: &str) -> Option<Session> {
-        match queries::get_session_by_id(conn, session_id) {
-            Ok(Some(session)) if !session.is_expired() => Some(session),
+        self.get_session_for_client(conn, session_id, client_fingerprint())
+    }
+
+    // Retrieve a session by ID if it belongs to the client with this fingerprint
+    pub fn get_session_for_client(&self, conn: &Connection, session_id: &str, fingerprint: &str) -> Option<Session> {
+        match load_session(conn, session_id) {
+            Ok(Some(session)) if session.active && !session.is_expired() && session.matches(fingerprint) => Some(session),
+            Ok(Some(session)) => {
+                if session.active && !session.is_expired() {
+                    warn!(target: SECURITY_TARGET, user_id = %session.user_id, "Session used from a different client");
+                }
+                None
+            }
             _ => None,
         }
     }
 
     // deactivate a session manually
     pub fn deactivate_session(&self, conn: &Connection, session_id: &str) -> rusqlite::Result<()> {
-        queries::deactivate_session(conn, session_id)
+        queries::deactivate_session(conn, session_id)?;
+        info!(target: SECURITY_TARGET, "Session deactivated");
+        Ok(())
+    }
+
+    // revoke all sessions of a user, e.g. when a credential is suspected compromised
+    pub fn revoke_user_sessions(&self, conn: &Connection, user_id: &str) -> rusqlite::Result<usize> {
+        let revoked = queries::deactivate_sessions_for_user(conn, user_id)?;
+        warn!(target: SECURITY_TARGET, user_id = %user_id, revoked, "All sessions revoked for user");
+        Ok(revoked)
     }
 
     // Periodic cleanup task (removes expired sessions)
@@ -85,22 +307,149 @@ impl SessionManager {
         queries::deactivate_expired_sessions(conn)
     }
 
-    // Run cleanup in a background thread every 60 seconds
-    pub fn run_cleanup(&self, db_path: &str) {
-        let db_path = db_path.to_string();
-        //create a new thread to rmove expired sessions
-        std::thread::spawn(move || loop {
-            match Connection::open(&db_path) {
-                Ok(conn) => {
-                    //remove expired sessions by calling remove_expired_sessions
-                    if let Err(e) = queries::deactivate_expired_sessions(&conn) {
-                        eprintln!("Failed to cleanup expired sessions: {:?}", e);
+    // Run cleanup as a background task every session.cleanup_interval_secs
+    // only the first call starts the task, later logins reuse it
+    pub fn run_cleanup(&self, db: &Db) {
+        static STARTED: OnceLock<()> = OnceLock::new();
+        if STARTED.set(()).is_err() {
+            return;
+        }
+        let db = db.clone();
+        let period = Duration::from_secs(config::get().session.cleanup_interval_secs);
+        let task = runtime::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            let mut last_retention: Option<std::time::Instant> = None;
+            loop {
+                // on shutdown the run in progress finishes and no new one starts
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = shutdown::stopped() => break,
+                }
+                // steps that fail are named in the job's health record
+                let mut failed = Vec::new();
+                //remove expired sessions by calling remove_expired_sessions
+                match runtime::with_db(&db, queries::deactivate_expired_sessions).await {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => {
+                        error!("Failed to cleanup expired sessions: {:?}", e);
+                        failed.push("sessions");
+                    }
+                    Err(e) => {
+                        error!("Failed to open DB connection for cleanup: {}", e);
+                        failed.push("database");
+                    }
+                }
+                match runtime::with_db(&db, queries::purge_expired_activation_codes).await {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => {
+                        error!("Failed to purge expired activation codes: {:?}", e);
+                        failed.push("activation_codes");
+                    }
+                    Err(e) => {
+                        error!("Failed to open DB connection for cleanup: {}", e);
+                        failed.push("database");
+                    }
+                }
+                match runtime::with_db(&db, temp_basal::end_expired).await {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => {
+                        error!("Failed to end expired temporary basal rates: {:?}", e);
+                        failed.push("temp_basal");
+                    }
+                    Err(e) => {
+                        error!("Failed to open DB connection for cleanup: {}", e);
+                        failed.push("database");
+                    }
+                }
+                let dosing = config::get().dosing.clone();
+                match runtime::with_db(&db, move |conn| low_suspend::run(conn, &dosing)).await {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => {
+                        error!("Failed to run predictive low glucose suspend: {:?}", e);
+                        failed.push("low_suspend");
+                    }
+                    Err(e) => {
+                        error!("Failed to open DB connection for cleanup: {}", e);
+                        failed.push("database");
+                    }
+                }
+                match runtime::with_db(&db, escalation::escalate_overdue).await {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => {
+                        error!("Failed to escalate unacknowledged alerts: {:?}", e);
+                        failed.push("escalation");
+                    }
+                    Err(e) => {
+                        error!("Failed to open DB connection for cleanup: {}", e);
+                        failed.push("database");
+                    }
+                }
+                let rules = config::get().monitoring.clone();
+                if rules.enabled {
+                    match runtime::with_db(&db, move |conn| monitoring::scan(conn, &rules, timestamps::default_zone())).await {
+                        Ok(Ok(_)) => {}
+                        Ok(Err(e)) => {
+                            error!("Failed to scan the audit trail for security alerts: {:?}", e);
+                            failed.push("monitoring");
+                        }
+                        Err(e) => {
+                            error!("Failed to open DB connection for cleanup: {}", e);
+                            failed.push("database");
+                        }
+                    }
+                }
+                let reports = config::get().reports.clone();
+                match runtime::with_db(&db, move |conn| daily_summary::generate_due(conn, &reports, chrono::Utc::now())).await {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => {
+                        error!("Failed to generate daily patient summaries: {:?}", e);
+                        failed.push("daily_summary");
+                    }
+                    Err(e) => {
+                        error!("Failed to open DB connection for cleanup: {}", e);
+                        failed.push("database");
+                    }
+                }
+
+                let housekeeping = config::get().housekeeping.clone();
+                if housekeeping.enabled {
+                    match runtime::with_db(&db, move |conn| housekeeping::run_due(conn, &housekeeping, chrono::Utc::now())).await {
+                        Ok(Ok(_)) => {}
+                        Ok(Err(e)) => {
+                            error!("Failed to run database housekeeping: {:?}", e);
+                            failed.push("housekeeping");
+                        }
+                        Err(e) => {
+                            error!("Failed to open DB connection for cleanup: {}", e);
+                            failed.push("database");
+                        }
                     }
                 }
-                Err(e) => eprintln!("Failed to open DB connection for cleanup: {:?}", e),
+
+                match runtime::with_db(&db, move |conn| health::record_run(conn, health::CLEANUP_JOB, &failed)).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => error!("Failed to record the cleanup run: {:?}", e),
+                    Err(e) => error!("Failed to open DB connection for cleanup: {}", e),
+                }
+
+                // the retention policy runs far less often than session cleanup
+                let settings = config::get().retention.clone();
+                if !settings.enabled || last_retention.is_some_and(|t| t.elapsed() < retention::RUN_INTERVAL) {
+                    continue;
+                }
+                last_retention = Some(std::time::Instant::now());
+                let result = runtime::with_db(&db, move |conn| {
+                    retention::apply(conn, &settings, settings.dry_run).map_err(|e| e.to_string())
+                })
+                .await;
+                match result {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => error!("Failed to apply retention policy: {}", e),
+                    Err(e) => error!("Failed to open DB connection for retention: {}", e),
+                }
             }
-            std::thread::sleep(Duration::from_secs(60));
         });
+        shutdown::track(task);
     }
 
     /* Access managed 
@@ -115,30 +464,181 @@ impl SessionManager {
         role: &Role,
         req_permission: Permission,
     ) -> bool {
-        // System session bypass for database maintenance operations
-        if session_id.len() == 60 && session_id.ends_with("00") {
-            return true;
+        // maintenance work without a login carries a signed token, checked against its own grant
+        if session_id.starts_with(maintenance::TOKEN_PREFIX) {
+            return maintenance::authorize(conn, session_id, &req_permission);
         }
-        
-        match queries::get_session_by_id(conn, session_id) {
+
+
+        match load_session(conn, session_id) {
             Ok(Some(session)) => {
                 // Ensure session hasn't expired
                 if session.is_expired() {
-                    println!("Session expired");
+                    warn!(target: SECURITY_TARGET, user_id = %session.user_id, "Permission check on expired session");
+                    return false;
+                }
+                if !session.active {
+                    warn!(target: SECURITY_TARGET, user_id = %session.user_id, "Permission check on deactivated session");
+                    return false;
+                }
+                if !session.matches_client() {
+                    warn!(target: SECURITY_TARGET, user_id = %session.user_id, "Session used from a different client");
                     return false;
                 }
 
                 // Verify if role has the requested permission
-                role.has_permission(&req_permission)
+                let allowed = role.has_permission(&req_permission);
+                if !allowed {
+                    warn!(target: SECURITY_TARGET, user_id = %session.user_id, role = %role.name, permission = ?req_permission, "Permission denied");
+                }
+                allowed
             }
             Ok(None) => {
-                println!("Invalid or missing session");
+                warn!(target: SECURITY_TARGET, "Permission check with invalid or missing session");
                 false
             }
             Err(e) => {
-                eprintln!("Database error checking session: {}", e);
+                error!("Database error checking session: {}", e);
                 false
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    #[test]
+    fn session_lifecycle() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let sessions = SessionManager::new();
+
+        let session_id = sessions.create_session(&conn, fixtures.clinician_id.clone(), "clinician".to_string()).unwrap();
+        assert_eq!(session_id.len(), 64);
+        let session = sessions.get_session_by_id(&conn, &session_id).unwrap();
+        assert_eq!(session.user_id, fixtures.clinician_id);
+        assert_eq!(session.role, "clinician");
+        assert!(session.is_valid());
+
+        let role = Role::new(&conn, "clinician", &fixtures.clinician_id);
+        assert!(sessions.check_permissions(&conn, &session_id, &role, Permission::ViewPatient));
+        assert!(!sessions.check_permissions(&conn, &session_id, &role, Permission::ManagePermissions));
+
+        sessions.deactivate_session(&conn, &session_id).unwrap();
+        assert!(sessions.get_session_by_id(&conn, &session_id).is_none());
+        assert!(!sessions.check_permissions(&conn, &session_id, &role, Permission::ViewPatient));
+    }
+
+    #[test]
+    fn unknown_sessions_are_rejected() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let sessions = SessionManager::new();
+        let role = Role::new(&conn, "admin", &fixtures.admin_id);
+
+        assert!(sessions.get_session_by_id(&conn, "deadbeef").is_none());
+        assert!(!sessions.check_permissions(&conn, "deadbeef", &role, Permission::ManageSessions));
+        // session ids have no special formats, and an unsigned maintenance token grants nothing
+        assert!(!sessions.check_permissions(&conn, &format!("{}00", "0".repeat(58)), &role, Permission::ManageSessions));
+        assert!(!sessions.check_permissions(&conn, "ggm_x.y.ManageSessions.9999999999.00", &role, Permission::ManageSessions));
+    }
+
+    #[test]
+    fn expired_sessions_are_invalid_and_cleaned_up() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let sessions = SessionManager::new();
+        let expired = Session {
+            session_id: "expired-session".to_string(),
+            user_id: fixtures.caretaker_id.clone(),
+            role: "caretaker".to_string(),
+            create_time: SystemTime::now() - Duration::from_secs(7200),
+            exp_time: Duration::from_secs(3600),
+            active: true,
+            fingerprint: client_fingerprint().to_string(),
+        };
+        queries::add_session_to_db(&conn, &expired).unwrap();
+        let live = sessions.create_session(&conn, fixtures.caretaker_id.clone(), "caretaker".to_string()).unwrap();
+
+        assert!(sessions.get_session_by_id(&conn, "expired-session").is_none());
+        sessions.cleanup_expired_sessions(&conn).unwrap();
+        assert!(!queries::get_session_by_id(&conn, "expired-session").unwrap().unwrap().active);
+        assert!(queries::get_session_by_id(&conn, &live).unwrap().unwrap().active);
+    }
+
+    #[test]
+    fn sessions_from_another_client_are_invalid() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let copied = Session {
+            session_id: "copied-session".to_string(),
+            user_id: fixtures.patient_id.clone(),
+            role: "patient".to_string(),
+            create_time: SystemTime::now(),
+            exp_time: Duration::from_secs(3600),
+            active: true,
+            fingerprint: "fingerprint-of-another-machine".to_string(),
+        };
+        queries::add_session_to_db(&conn, &copied).unwrap();
+        assert!(SessionManager::new().get_session_by_id(&conn, "copied-session").is_none());
+    }
+
+    #[test]
+    fn session_limit_evicts_the_oldest() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let sessions = SessionManager::new();
+        let limit = config::get().session.max_sessions_for("clinician") as usize;
+
+        let ids: Vec<String> = (0..=limit)
+            .map(|_| sessions.create_session(&conn, fixtures.clinician_id.clone(), "clinician".to_string()).unwrap())
+            .collect();
+        let active = queries::get_active_session_ids(&conn, &fixtures.clinician_id).unwrap();
+        assert_eq!(active.len(), limit);
+        assert!(active.contains(ids.last().unwrap()));
+    }
+
+    #[test]
+    fn revoking_a_user_ends_all_their_sessions() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let sessions = SessionManager::new();
+
+        let first = sessions.create_session(&conn, fixtures.caretaker_id.clone(), "caretaker".to_string()).unwrap();
+        let second = sessions.create_session(&conn, fixtures.caretaker_id.clone(), "caretaker".to_string()).unwrap();
+        let other = sessions.create_session(&conn, fixtures.admin_id.clone(), "admin".to_string()).unwrap();
+
+        assert_eq!(sessions.revoke_user_sessions(&conn, &fixtures.caretaker_id).unwrap(), 2);
+        assert!(sessions.get_session_by_id(&conn, &first).is_none());
+        assert!(sessions.get_session_by_id(&conn, &second).is_none());
+        assert!(sessions.get_session_by_id(&conn, &other).is_some());
+    }
+
+    #[test]
+    fn cached_sessions_are_dropped_on_logout_and_revocation() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let sessions = SessionManager::new();
+
+        // another process ending the session is only seen once the cache entry is stale
+        let cached = sessions.create_session(&conn, fixtures.clinician_id.clone(), "clinician".to_string()).unwrap();
+        conn.execute("UPDATE sessions SET active = 0 WHERE session_id = ?1", [&cached]).unwrap();
+        assert!(sessions.get_session_by_id(&conn, &cached).is_some());
+        forget_session(&cached);
+        assert!(sessions.get_session_by_id(&conn, &cached).is_none());
+
+        let logged_out = sessions.create_session(&conn, fixtures.clinician_id.clone(), "clinician".to_string()).unwrap();
+        assert!(sessions.get_session_by_id(&conn, &logged_out).is_some());
+        sessions.deactivate_session(&conn, &logged_out).unwrap();
+        assert!(sessions.get_session_by_id(&conn, &logged_out).is_none());
+
+        let revoked = sessions.create_session(&conn, fixtures.caretaker_id.clone(), "caretaker".to_string()).unwrap();
+        let role = Role::new(&conn, "caretaker", &fixtures.caretaker_id);
+        assert!(sessions.check_permissions(&conn, &revoked, &role, Permission::ViewPatient));
+        sessions.revoke_user_sessions(&conn, &fixtures.caretaker_id).unwrap();
+        assert!(!sessions.check_permissions(&conn, &revoked, &role, Permission::ViewPatient));
+    }
+}
@@ -0,0 +1,179 @@
+/*
+Graceful shutdown on SIGINT (Ctrl+C) and SIGTERM for the interactive menus and `serve`.
+Instead of dying wherever the signal lands, the handler asks the background tasks to stop and waits for
+the run in progress, waits for a write transaction open on another connection to commit, ends the
+logged-in session, stores a shutdown record in the audit trail and exits with 128 + the signal number.
+*/
+use crate::audit::{self, AuditEvent};
+use crate::db::pool::Db;
+use crate::db::queries;
+use crate::i18n;
+use crate::runtime;
+use crate::timestamps;
+use rusqlite::{Connection, Transaction, TransactionBehavior};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+// how long a background task may take to finish its current run
+const TASK_STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub const SHUTDOWN_ACTION: &str = "GlucoGuard shut down by signal";
+
+static STOP: OnceLock<watch::Sender<bool>> = OnceLock::new();
+static TASKS: Mutex<Vec<JoinHandle<()>>> = Mutex::new(Vec::new());
+// (session_id, user_id) of the interactive login, ended on shutdown
+static SESSION: Mutex<Option<(String, String)>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy)]
+enum Signal {
+    Interrupt,
+    Terminate,
+}
+
+impl Signal {
+    fn name(self) -> &'static str {
+        match self {
+            Signal::Interrupt => "SIGINT",
+            Signal::Terminate => "SIGTERM",
+        }
+    }
+
+    fn exit_code(self) -> i32 {
+        match self {
+            Signal::Interrupt => 130,
+            Signal::Terminate => 143,
+        }
+    }
+}
+
+fn stop_sender() -> &'static watch::Sender<bool> {
+    STOP.get_or_init(|| watch::channel(false).0)
+}
+
+// resolves once shutdown has begun; background loops select on it between runs
+pub async fn stopped() {
+    let mut stop = stop_sender().subscribe();
+    let _ = stop.wait_for(|stopped| *stopped).await;
+}
+
+// a background task the handler waits for after asking it to stop
+pub fn track(task: JoinHandle<()>) {
+    TASKS.lock().unwrap().push(task);
+}
+
+// the interactive session to end if the process is stopped before logout
+pub fn set_session(session_id: &str, user_id: &str) {
+    *SESSION.lock().unwrap() = Some((session_id.to_string(), user_id.to_string()));
+}
+
+pub fn clear_session() {
+    *SESSION.lock().unwrap() = None;
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() -> Signal {
+    use tokio::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminate) => tokio::select! {
+            _ = tokio::signal::ctrl_c() => Signal::Interrupt,
+            _ = terminate.recv() => Signal::Terminate,
+        },
+        Err(e) => {
+            tracing::warn!(error = %e, "Cannot listen for SIGTERM, only Ctrl+C shuts down gracefully");
+            let _ = tokio::signal::ctrl_c().await;
+            Signal::Interrupt
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() -> Signal {
+    let _ = tokio::signal::ctrl_c().await;
+    Signal::Interrupt
+}
+
+// handle SIGINT and SIGTERM from now on; only the first call installs the handler
+pub fn install(db: &Db) {
+    static INSTALLED: OnceLock<()> = OnceLock::new();
+    if INSTALLED.set(()).is_err() {
+        return;
+    }
+    let db = db.clone();
+    runtime::spawn(async move {
+        let signal = wait_for_signal().await;
+        tracing::warn!(signal = signal.name(), "Shutdown requested");
+
+        stop_sender().send_replace(true);
+        let tasks = std::mem::take(&mut *TASKS.lock().unwrap());
+        for task in tasks {
+            if tokio::time::timeout(TASK_STOP_TIMEOUT, task).await.is_err() {
+                tracing::warn!("A background task did not stop in time");
+            }
+        }
+
+        let session = SESSION.lock().unwrap().take();
+        let ended = session.is_some();
+        match runtime::with_db(&db, move |conn| finish(conn, signal.name(), session.as_ref())).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::error!("Failed to record the shutdown: {}", e),
+            Err(e) => tracing::error!("Failed to open DB connection for shutdown: {}", e),
+        }
+        let key = if ended { "home.shutdown_session" } else { "home.shutdown" };
+        println!("\n{}", i18n::tf(key, &[&signal.name()]));
+        std::process::exit(signal.exit_code());
+    });
+}
+
+/*
+Store the events queued so far, then end the session and write the shutdown record in one
+transaction. BEGIN IMMEDIATE waits (up to the busy timeout) for a write transaction in progress on
+another connection, such as the menus', to commit first.
+*/
+fn finish(conn: &Connection, signal: &str, session: Option<&(String, String)>) -> rusqlite::Result<()> {
+    audit::flush();
+    let tx = Transaction::new_unchecked(conn, TransactionBehavior::Immediate)?;
+    if let Some((session_id, _)) = session {
+        queries::deactivate_session(&tx, session_id)?;
+    }
+    let details = serde_json::json!({ "signal": signal, "session_ended": session.is_some() });
+    audit::insert_event(
+        &tx,
+        &AuditEvent {
+            occurred_at: timestamps::now(),
+            level: "WARN".to_string(),
+            action: SHUTDOWN_ACTION.to_string(),
+            user_id: session.map(|(_, user_id)| user_id.clone()),
+            username: None,
+            details: details.to_string(),
+        },
+    )?;
+    tx.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::SessionManager;
+    use crate::test_utils;
+
+    #[test]
+    fn finish_ends_the_session_and_records_the_signal() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let manager = SessionManager::new();
+        let session_id = manager.create_session(&conn, fixtures.clinician_id.clone(), "clinician".to_string()).unwrap();
+        assert!(manager.get_session_by_id(&conn, &session_id).is_some());
+
+        finish(&conn, "SIGTERM", Some(&(session_id.clone(), fixtures.clinician_id.clone()))).unwrap();
+        assert!(manager.get_session_by_id(&conn, &session_id).is_none());
+        let (user_id, details): (Option<String>, String) = conn
+            .query_row("SELECT user_id, details FROM audit_events WHERE action = ?1", [SHUTDOWN_ACTION], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(user_id.as_deref(), Some(fixtures.clinician_id.as_str()));
+        assert!(details.contains("\"signal\":\"SIGTERM\""), "{}", details);
+    }
+}
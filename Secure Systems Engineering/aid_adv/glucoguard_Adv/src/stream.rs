@@ -0,0 +1,249 @@
+// Live glucose and alert streaming over WebSocket (GET /api/stream)
+// Clients send {"subscribe": "<patient_id>"} / {"unsubscribe": "<patient_id>"} and receive
+// {"type": "glucose", ...} and {"type": "alert", ...} events for patients they may view
+use crate::access_control::{self, Permission, Resource};
+use crate::alerts::AlertRecord;
+use crate::api::{self, ApiClient, ApiError, AppState};
+use crate::db::pool::Db;
+use crate::insulin::GlucoseReading;
+use crate::runtime;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State};
+use axum::http::HeaderMap;
+use axum::response::Response;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+// how often the database is checked for new readings and alerts
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+// events buffered per subscriber before a slow client starts missing some
+pub const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveEvent {
+    Glucose { reading: GlucoseReading },
+    Alert { alert: AlertRecord },
+}
+
+impl LiveEvent {
+    fn patient_id(&self) -> &str {
+        match self {
+            LiveEvent::Glucose { reading } => &reading.patient_id,
+            LiveEvent::Alert { alert } => &alert.patient_id,
+        }
+    }
+
+    fn permission(&self) -> Permission {
+        match self {
+            LiveEvent::Glucose { .. } => Permission::ViewGlucose,
+            LiveEvent::Alert { .. } => Permission::ViewAlerts,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe(String),
+    Unsubscribe(String),
+}
+
+#[derive(Deserialize)]
+pub struct StreamQuery {
+    // browsers cannot set headers on WebSocket requests, so the credential may come as ?token=
+    token: Option<String>,
+}
+
+fn max_id(conn: &Connection, sql: &str) -> rusqlite::Result<i64> {
+    conn.query_row(sql, [], |row| row.get(0))
+}
+
+// rows added since the last poll, advancing the high-water marks
+fn poll_new_events(conn: &Connection, last_reading: &mut i64, last_alert: &mut i64) -> rusqlite::Result<Vec<LiveEvent>> {
+    let mut events = Vec::new();
+
+    let mut stmt = conn.prepare(
+        "SELECT reading_id, patient_id, glucose_level, reading_time, status
+         FROM glucose_readings WHERE reading_id > ?1 ORDER BY reading_id ASC",
+    )?;
+    let readings = stmt.query_map(params![*last_reading], |row| {
+        Ok(GlucoseReading {
+            reading_id: row.get(0)?,
+            patient_id: row.get(1)?,
+            glucose_level: row.get(2)?,
+            reading_time: row.get(3)?,
+            status: row.get(4)?,
+        })
+    })?;
+    for reading in readings {
+        let reading = reading?;
+        *last_reading = reading.reading_id;
+        events.push(LiveEvent::Glucose { reading });
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT alert_id, patient_id, alert_type, alert_message, alert_time, is_resolved, resolved_by
+         FROM alerts WHERE alert_id > ?1 ORDER BY alert_id ASC",
+    )?;
+    let alerts = stmt.query_map(params![*last_alert], |row| {
+        Ok(AlertRecord {
+            alert_id: row.get(0)?,
+            patient_id: row.get(1)?,
+            alert_type: row.get(2)?,
+            alert_message: row.get(3)?,
+            alert_time: row.get(4)?,
+            is_resolved: row.get(5)?,
+            resolved_by: row.get(6)?,
+        })
+    })?;
+    for alert in alerts {
+        let alert = alert?;
+        *last_alert = alert.alert_id;
+        events.push(LiveEvent::Alert { alert });
+    }
+    Ok(events)
+}
+
+// background task publishing rows written by any process (cgm, import, pumps) to subscribers
+pub fn spawn_poller(db: Db, events: broadcast::Sender<LiveEvent>) {
+    tokio::spawn(async move {
+        let start = runtime::with_db(&db, |conn| -> rusqlite::Result<(i64, i64)> {
+            let readings = max_id(conn, "SELECT COALESCE(MAX(reading_id), 0) FROM glucose_readings")?;
+            let alerts = max_id(conn, "SELECT COALESCE(MAX(alert_id), 0) FROM alerts")?;
+            Ok((readings, alerts))
+        })
+        .await;
+        let Ok(Ok((mut last_reading, mut last_alert))) = start else {
+            tracing::error!("Live stream poller could not read starting positions");
+            return;
+        };
+
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            let (from_reading, from_alert) = (last_reading, last_alert);
+            let polled = runtime::with_db(&db, move |conn| {
+                let (mut reading, mut alert) = (from_reading, from_alert);
+                poll_new_events(conn, &mut reading, &mut alert).map(|events| (events, reading, alert))
+            })
+            .await;
+            match polled {
+                Ok(Ok((new_events, reading, alert))) => {
+                    (last_reading, last_alert) = (reading, alert);
+                    // send only fails when nobody is subscribed
+                    for event in new_events {
+                        let _ = events.send(event);
+                    }
+                }
+                Ok(Err(e)) => tracing::error!("Live stream poll failed: {}", e),
+                Err(e) => tracing::error!("Live stream poller could not reach the database: {}", e),
+            }
+        }
+    });
+}
+
+pub async fn stream_handler(
+    State(state): State<AppState>,
+    client: ApiClient,
+    headers: HeaderMap,
+    Query(query): Query<StreamQuery>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    let credential = api::bearer_token(&headers)
+        .map(str::to_string)
+        .or(query.token)
+        .ok_or_else(ApiError::unauthorized)?;
+    let checked = credential.clone();
+    let fingerprint = client.fingerprint.clone();
+    state
+        .with_db(move |conn| api::authenticate_credential(conn, &checked, &fingerprint))
+        .await?;
+    Ok(ws.on_upgrade(move |socket| run_stream(socket, state, credential, client)))
+}
+
+fn reply(kind: &str, field: &str, value: &str) -> Message {
+    Message::Text(serde_json::json!({ "type": kind, field: value }).to_string().into())
+}
+
+// the credential is resolved again on every check so logout, expiry and revocation end the stream
+// None = the credential is no longer valid
+async fn may_view(state: &AppState, credential: &str, client: &ApiClient, patient_id: &str, permission: Permission) -> Option<bool> {
+    let credential = credential.to_string();
+    let fingerprint = client.fingerprint.clone();
+    let patient_id = patient_id.to_string();
+    state
+        .with_db(move |conn| {
+            let caller = api::authenticate_credential(conn, &credential, &fingerprint)?;
+            Ok(caller.allows_patient(&patient_id)
+                && access_control::can_access(conn, &caller.role, &Resource::Patient(&patient_id), &permission))
+        })
+        .await
+        .ok()
+}
+
+async fn run_stream(mut socket: WebSocket, state: AppState, credential: String, client: ApiClient) {
+    let mut events = state.events.subscribe();
+    let mut subscriptions: HashSet<String> = HashSet::new();
+
+    loop {
+        tokio::select! {
+            incoming = socket.recv() => {
+                let text = match incoming {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                    Some(Ok(_)) => continue,
+                };
+                let response = match serde_json::from_str::<ClientMessage>(&text) {
+                    Ok(ClientMessage::Subscribe(patient_id)) => {
+                        let Some(allowed) = may_view(&state, &credential, &client, &patient_id, Permission::ViewGlucose).await else {
+                            break;
+                        };
+                        if allowed {
+                            subscriptions.insert(patient_id.clone());
+                            reply("subscribed", "patient_id", &patient_id)
+                        } else {
+                            reply("error", "message", "access denied")
+                        }
+                    }
+                    Ok(ClientMessage::Unsubscribe(patient_id)) => {
+                        subscriptions.remove(&patient_id);
+                        reply("unsubscribed", "patient_id", &patient_id)
+                    }
+                    Err(_) => reply("error", "message", "expected {\"subscribe\": id} or {\"unsubscribe\": id}"),
+                };
+                if socket.send(response).await.is_err() {
+                    break;
+                }
+            }
+            event = events.recv() => {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!(skipped, "Live stream subscriber lagging, events dropped");
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                if !subscriptions.contains(event.patient_id()) {
+                    continue;
+                }
+                match may_view(&state, &credential, &client, event.patient_id(), event.permission()).await {
+                    Some(true) => {}
+                    Some(false) => continue,
+                    None => break,
+                }
+                let Ok(payload) = serde_json::to_string(&event) else {
+                    continue;
+                };
+                if socket.send(Message::Text(payload.into())).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    let _ = socket.send(Message::Close(None)).await;
+}
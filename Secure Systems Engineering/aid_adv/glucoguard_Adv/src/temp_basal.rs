@@ -0,0 +1,193 @@
+// Temporary basal rates
+// A temporary rate is a percentage of the patient's basal_rate for a bounded time, e.g. 150% for 2 hours.
+// The pump gets a signed "temp_basal" command with the rate and duration and reverts by itself when it ends;
+// the session cleanup task records the reversion. Clinicians (EditPatientData) may use the full range,
+// other roles with SetTempBasal only the dosing.temp_basal_patient_* limits.
+use crate::access_control::{self, Permission, Resource, Role};
+use crate::config;
+use crate::db::queries;
+use crate::dose_safety;
+use crate::logger::SECURITY_TARGET;
+use crate::pump;
+use rusqlite::{params, Connection, OptionalExtension};
+
+pub const MAX_PERCENT: u32 = 200;
+pub const MIN_MINUTES: u32 = 30;
+pub const MAX_MINUTES: u32 = 24 * 60;
+
+#[derive(Debug, Clone)]
+pub struct TempBasal {
+    pub percent: u32,
+    pub base_rate: f64,
+    // units/hour while the temporary rate runs
+    pub rate: f64,
+    pub starts_at: String,
+    pub ends_at: String,
+    // username of whoever set it, or their user id if the account is gone
+    pub set_by: String,
+}
+
+// percent and duration range the role may choose from
+pub fn limits_for(role: &Role) -> (u32, u32, u32) {
+    if role.has_permission(&Permission::EditPatientData) {
+        return (0, MAX_PERCENT, MAX_MINUTES);
+    }
+    let dosing = &config::get().dosing;
+    (dosing.temp_basal_patient_min_percent, dosing.temp_basal_patient_max_percent, dosing.temp_basal_patient_max_minutes)
+}
+
+// the temporary rate running now, if any
+pub fn active_temp_basal(conn: &Connection, patient_id: &str) -> rusqlite::Result<Option<TempBasal>> {
+    conn.query_row(
+        "SELECT t.percent, t.base_rate, t.rate, t.starts_at, t.ends_at, COALESCE(u.user_name, t.set_by)
+         FROM temp_basal_rates t LEFT JOIN users u ON u.id = t.set_by
+         WHERE t.patient_id = ?1 AND t.ended_at IS NULL AND julianday(t.ends_at) > julianday('now')
+         ORDER BY t.temp_basal_id DESC
+         LIMIT 1",
+        [patient_id],
+        |row| {
+            Ok(TempBasal {
+                percent: row.get(0)?,
+                base_rate: row.get(1)?,
+                rate: row.get(2)?,
+                starts_at: row.get(3)?,
+                ends_at: row.get(4)?,
+                set_by: row.get(5)?,
+            })
+        },
+    )
+    .optional()
+}
+
+/*
+Start a temporary basal rate for a patient in the role's scope, replacing one that is running.
+The extra insulin must fit the patient's daily allowance and delivery must not be suspended.
+Returns the new rate and the queued pump command (or why it could not be sent).
+*/
+pub fn set_temp_basal(
+    conn: &Connection,
+    role: &Role,
+    patient_id: &str,
+    percent: u32,
+    duration_minutes: u32,
+) -> Result<(TempBasal, Result<i64, String>), String> {
+    if !access_control::can_access(conn, role, &Resource::Patient(patient_id), &Permission::SetTempBasal) {
+        return Err("Access denied: patient is not in your care or SetTempBasal is missing".to_string());
+    }
+    let (min_percent, max_percent, max_minutes) = limits_for(role);
+    if percent < min_percent || percent > max_percent {
+        return Err(format!("temporary basal must be between {}% and {}%", min_percent, max_percent));
+    }
+    if !(MIN_MINUTES..=max_minutes).contains(&duration_minutes) {
+        return Err(format!("duration must be between {} and {} minutes", MIN_MINUTES, max_minutes));
+    }
+    let patient = queries::get_patient_by_id(conn, patient_id)
+        .map_err(|e| e.to_string())?
+        .ok_or("no patient record found")?;
+    let base_rate = f64::from(patient.basal_rate);
+    let rate = base_rate * f64::from(percent) / 100.0;
+    dose_safety::check_dose(conn, patient_id, "basal", rate * f64::from(duration_minutes) / 60.0)?;
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    end_running(&tx, patient_id, &role.id, "replaced").map_err(|e| e.to_string())?;
+    tx.execute(
+        "INSERT INTO temp_basal_rates (patient_id, percent, base_rate, rate, duration_minutes, starts_at, ends_at, set_by)
+         VALUES (?1, ?2, ?3, ?4, ?5, datetime('now'), datetime('now', ?6), ?7)",
+        params![patient_id, percent, base_rate, rate, duration_minutes, format!("+{} minutes", duration_minutes), role.id],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    tracing::warn!(target: SECURITY_TARGET, user_id = %role.id, patient_id = %patient_id, percent, rate, duration_minutes, "Temporary basal rate set");
+
+    let command = pump::queue_timed_command(conn, patient_id, "temp_basal", rate, duration_minutes, &role.id);
+    if let Err(e) = &command {
+        tracing::error!(patient_id = %patient_id, "Temporary basal not sent to the pump: {}", e);
+    }
+    let temp_basal = active_temp_basal(conn, patient_id)
+        .map_err(|e| e.to_string())?
+        .ok_or("temporary basal rate disappeared while it was set")?;
+    Ok((temp_basal, command))
+}
+
+// stop the running temporary rate early; None when none is running
+pub fn cancel_temp_basal(conn: &Connection, role: &Role, patient_id: &str) -> Result<Option<Result<i64, String>>, String> {
+    if !access_control::can_access(conn, role, &Resource::Patient(patient_id), &Permission::SetTempBasal) {
+        return Err("Access denied: patient is not in your care or SetTempBasal is missing".to_string());
+    }
+    if end_running(conn, patient_id, &role.id, "cancelled").map_err(|e| e.to_string())? == 0 {
+        return Ok(None);
+    }
+    tracing::warn!(target: SECURITY_TARGET, user_id = %role.id, patient_id = %patient_id, "Temporary basal rate cancelled");
+    let command = pump::queue_command(conn, patient_id, "cancel_temp_basal", 0.0, &role.id);
+    if let Err(e) = &command {
+        tracing::error!(patient_id = %patient_id, "Temporary basal cancel not sent to the pump: {}", e);
+    }
+    Ok(Some(command))
+}
+
+// mark the patient's running temporary rate as ended now
+pub fn end_running(conn: &Connection, patient_id: &str, ended_by: &str, reason: &str) -> rusqlite::Result<usize> {
+    conn.execute(
+        "UPDATE temp_basal_rates SET ended_at = datetime('now'), ended_by = ?2, end_reason = ?3
+         WHERE patient_id = ?1 AND ended_at IS NULL AND julianday(ends_at) > julianday('now')",
+        params![patient_id, ended_by, reason],
+    )
+}
+
+// record the automatic reversion of temporary rates whose time is up; run by the session cleanup task
+pub fn end_expired(conn: &Connection) -> rusqlite::Result<usize> {
+    let expired = {
+        let mut stmt = conn.prepare(
+            "SELECT temp_basal_id, patient_id, percent, base_rate FROM temp_basal_rates
+             WHERE ended_at IS NULL AND julianday(ends_at) <= julianday('now')",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, u32>(2)?, row.get::<_, f64>(3)?)))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+    for (temp_basal_id, patient_id, percent, base_rate) in &expired {
+        conn.execute(
+            "UPDATE temp_basal_rates SET ended_at = ends_at, ended_by = 'system', end_reason = 'expired' WHERE temp_basal_id = ?1",
+            [temp_basal_id],
+        )?;
+        tracing::info!(patient_id = %patient_id, percent, base_rate, "Temporary basal rate ended, back to the programmed basal rate");
+    }
+    Ok(expired.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    #[test]
+    fn patients_stay_within_limits_and_rates_revert() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let patient = Role::new(&conn, "patient", &fixtures.patient_id);
+        let clinician = Role::new(&conn, "clinician", &fixtures.clinician_id);
+        let caretaker = Role::new(&conn, "caretaker", &fixtures.caretaker_id);
+
+        assert!(set_temp_basal(&conn, &patient, &fixtures.patient_id, 180, 60).is_err());
+        assert!(set_temp_basal(&conn, &patient, &fixtures.patient_id, 150, 8 * 60).is_err());
+        assert!(set_temp_basal(&conn, &caretaker, &fixtures.patient_id, 120, 60).is_err());
+        assert!(set_temp_basal(&conn, &patient, &fixtures.other_patient_id, 120, 60).is_err());
+
+        // basal_rate 0.8 u/h in the fixture; no pump is paired so the command is not sent
+        let (temp_basal, command) = set_temp_basal(&conn, &patient, &fixtures.patient_id, 150, 120).unwrap();
+        assert!((temp_basal.rate - 1.2).abs() < 1e-6);
+        assert!(command.is_err());
+        let (temp_basal, _) = set_temp_basal(&conn, &clinician, &fixtures.patient_id, 180, 60).unwrap();
+        assert_eq!(temp_basal.percent, 180);
+        let replaced: String = conn
+            .query_row("SELECT end_reason FROM temp_basal_rates ORDER BY temp_basal_id LIMIT 1", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(replaced, "replaced");
+
+        // once its time is up the rate no longer applies and the reversion is recorded
+        conn.execute("UPDATE temp_basal_rates SET ends_at = datetime('now', '-1 minute') WHERE ended_at IS NULL", []).unwrap();
+        assert!(active_temp_basal(&conn, &fixtures.patient_id).unwrap().is_none());
+        assert_eq!(end_expired(&conn).unwrap(), 1);
+        assert_eq!(end_expired(&conn).unwrap(), 0);
+        assert_eq!(cancel_temp_basal(&conn, &patient, &fixtures.patient_id).unwrap().map(|_| ()), None);
+    }
+}
@@ -0,0 +1,111 @@
+// Test harness: fresh in-memory databases with the full schema and a small set of fixtures
+// Every test gets its own connection, so tests never share state or touch data/database.db
+use crate::auth;
+use crate::consent::{self, ConsentScope};
+use crate::db::initialize;
+use crate::db::queries;
+use crate::db::models::Patient;
+use rusqlite::{params, Connection};
+use std::sync::OnceLock;
+use uuid::Uuid;
+
+pub const TEST_PASSWORD: &str = "Correct-Horse-42";
+
+// migrated and seeded like a real database, but only lives as long as the connection
+pub fn test_db() -> Connection {
+    let conn = Connection::open_in_memory().expect("in-memory database");
+    initialize::initialize_database(&conn).expect("schema migrations");
+    conn
+}
+
+// argon2 is slow in debug builds, so fixtures share one hash of TEST_PASSWORD
+fn test_password_hash() -> &'static str {
+    static HASH: OnceLock<String> = OnceLock::new();
+    HASH.get_or_init(|| auth::hash_password(TEST_PASSWORD).expect("hash test password"))
+}
+
+// insert an account that logs in with TEST_PASSWORD and return its id
+// tests of account creation itself go through queries::create_user
+pub fn add_user(conn: &Connection, username: &str, role: &str) -> String {
+    let user_id = Uuid::new_v4().to_string();
+    conn.execute(
+        "INSERT INTO users (id, user_name, password_hash, role, created_at, last_login) VALUES (?1, ?2, ?3, ?4, datetime('now'), NULL)",
+        params![user_id, username, test_password_hash(), role],
+    )
+    .expect("insert user");
+    user_id
+}
+
+pub fn patient(patient_id: &str, first_name: &str, last_name: &str, clinician_id: &str) -> Patient {
+    Patient {
+        patient_id: patient_id.to_string(),
+        first_name: first_name.to_string(),
+        last_name: last_name.to_string(),
+        date_of_birth: "1990-04-12".to_string(),
+        basal_rate: 0.8,
+        bolus_rate: 1.2,
+        max_dosage: 10.0,
+        low_glucose_threshold: 70.0,
+        high_glucose_threshold: 180.0,
+        clinician_id: clinician_id.to_string(),
+        is_simulated: false,
+    }
+}
+
+// insert a patient row directly, bypassing the session checks of insert_patient_account_details_in_db
+pub fn add_patient(conn: &Connection, patient: &Patient) {
+    conn.execute(
+        "INSERT INTO patients (patient_id, first_name, last_name, date_of_birth, basal_rate, bolus_rate, max_dosage,
+             low_glucose_threshold, high_glucose_threshold, clinician_id, is_simulated)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            patient.patient_id,
+            patient.first_name,
+            patient.last_name,
+            patient.date_of_birth,
+            patient.basal_rate,
+            patient.bolus_rate,
+            patient.max_dosage,
+            patient.low_glucose_threshold,
+            patient.high_glucose_threshold,
+            patient.clinician_id,
+            patient.is_simulated
+        ],
+    )
+    .expect("insert patient");
+}
+
+// one account per built-in role and two patients with different clinicians
+pub struct Fixtures {
+    pub admin_id: String,
+    pub clinician_id: String,
+    pub other_clinician_id: String,
+    pub caretaker_id: String,
+    // patient account whose id is also its patients.patient_id
+    pub patient_id: String,
+    // patient of other_clinician_id, without caretakers
+    pub other_patient_id: String,
+}
+
+pub fn seed(conn: &Connection) -> Fixtures {
+    let admin_id = add_user(conn, "test.admin", "admin");
+    let clinician_id = add_user(conn, "dr.fixture", "clinician");
+    let other_clinician_id = add_user(conn, "dr.other", "clinician");
+    let caretaker_id = add_user(conn, "care.fixture", "caretaker");
+    let patient_id = add_user(conn, "pat.fixture", "patient");
+    let other_patient_id = "patient-without-account".to_string();
+
+    add_patient(conn, &patient(&patient_id, "Ada", "Fixture", &clinician_id));
+    add_patient(conn, &patient(&other_patient_id, "Bob", "Other", &other_clinician_id));
+    queries::add_patient_caretaker(conn, &patient_id, &caretaker_id, &patient_id).expect("link caretaker");
+    consent::record_consent(conn, &patient_id, &patient_id, ConsentScope::Caretakers, true).expect("caretaker consent");
+
+    Fixtures {
+        admin_id,
+        clinician_id,
+        other_clinician_id,
+        caretaker_id,
+        patient_id,
+        other_patient_id,
+    }
+}
@@ -0,0 +1,143 @@
+// Timestamps: stored as UTC text in SQLite's datetime('now') format, shown in the user's time zone
+// users.timezone holds an IANA zone name (NULL = ui.timezone). Menus, the terminal UI, reports and the
+// text output of the scripted commands show local times with the zone abbreviation; exports write
+// RFC 3339 with the offset. Rows written before migration 25 may still carry RFC 3339 text and are
+// read the same way.
+use crate::config;
+use crate::logger::SECURITY_TARGET;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use rusqlite::{params, Connection, OptionalExtension};
+
+// how every timestamp is stored, always UTC
+pub const STORAGE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+const DISPLAY_FORMAT: &str = "%Y-%m-%d %H:%M %Z";
+
+pub fn parse_zone(name: &str) -> Option<Tz> {
+    name.trim().parse().ok()
+}
+
+pub fn to_storage(time: DateTime<Utc>) -> String {
+    time.format(STORAGE_FORMAT).to_string()
+}
+
+// current time as stored
+pub fn now() -> String {
+    to_storage(Utc::now())
+}
+
+// stored text (datetime('now') format with optional fractions, or RFC 3339) as a UTC instant
+pub fn parse(stamp: &str) -> Option<DateTime<Utc>> {
+    let stamp = stamp.trim();
+    DateTime::parse_from_rfc3339(stamp)
+        .map(|time| time.with_timezone(&Utc))
+        .ok()
+        .or_else(|| NaiveDateTime::parse_from_str(stamp, "%Y-%m-%d %H:%M:%S%.f").ok().map(|time| time.and_utc()))
+        .or_else(|| NaiveDateTime::parse_from_str(stamp, "%Y-%m-%d %H:%M").ok().map(|time| time.and_utc()))
+}
+
+// typed time: RFC 3339, or "YYYY-MM-DD HH:MM[:SS]" read as local time in the zone
+pub fn parse_local(text: &str, zone: Tz) -> Option<DateTime<Utc>> {
+    let text = text.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some(time.with_timezone(&Utc));
+    }
+    let naive = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M"))
+        .ok()?;
+    zone.from_local_datetime(&naive).earliest().map(|time| time.with_timezone(&Utc))
+}
+
+// "2024-05-01 09:30 CEST"; text that is not a timestamp is shown as it is
+pub fn format(stamp: &str, zone: Tz) -> String {
+    parse(stamp).map_or_else(|| stamp.to_string(), |time| format_time(time, zone))
+}
+
+pub fn format_time(time: DateTime<Utc>, zone: Tz) -> String {
+    time.with_timezone(&zone).format(DISPLAY_FORMAT).to_string()
+}
+
+// format for optional columns, with the text to show when there is no value
+pub fn format_or(stamp: Option<&str>, zone: Tz, missing: &str) -> String {
+    stamp.map_or_else(|| missing.to_string(), |stamp| format(stamp, zone))
+}
+
+// "2024-05-01T09:30:00+02:00" for exports
+pub fn to_rfc3339(stamp: &str, zone: Tz) -> String {
+    parse(stamp).map_or_else(|| stamp.to_string(), |time| time.with_timezone(&zone).to_rfc3339())
+}
+
+// first instant of a local day as stored UTC text; a midnight skipped by a DST change starts at the next valid time
+pub fn start_of_day(date: NaiveDate, zone: Tz) -> String {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+    let start = zone
+        .from_local_datetime(&midnight)
+        .earliest()
+        .or_else(|| zone.from_local_datetime(&(midnight + chrono::Duration::hours(1))).earliest())
+        .map_or_else(|| midnight.and_utc(), |time| time.with_timezone(&Utc));
+    to_storage(start)
+}
+
+// the zone the user chose, or ui.timezone
+pub fn user_zone(conn: &Connection, user_id: &str) -> rusqlite::Result<Tz> {
+    let chosen: Option<String> = conn
+        .query_row("SELECT timezone FROM users WHERE id = ?1", [user_id], |row| row.get(0))
+        .optional()?
+        .flatten();
+    Ok(chosen.as_deref().and_then(parse_zone).unwrap_or_else(default_zone))
+}
+
+pub fn default_zone() -> Tz {
+    parse_zone(&config::get().ui.timezone).unwrap_or(Tz::UTC)
+}
+
+// user_zone for display, logging a lookup error instead of failing the screen
+pub fn display_zone(conn: &Connection, user_id: &str) -> Tz {
+    user_zone(conn, user_id).unwrap_or_else(|e| {
+        tracing::error!(user_id = %user_id, "Failed to load time zone preference: {}", e);
+        default_zone()
+    })
+}
+
+// set the user's zone, None goes back to ui.timezone
+pub fn set_user_zone(conn: &Connection, user_id: &str, zone: Option<Tz>) -> rusqlite::Result<()> {
+    conn.execute("UPDATE users SET timezone = ?2 WHERE id = ?1", params![user_id, zone.map(|zone| zone.name())])?;
+    tracing::info!(target: SECURITY_TARGET, user_id = %user_id, timezone = zone.map_or("default", |zone| zone.name()), "Time zone preference changed");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    #[test]
+    fn stored_times_are_shown_and_exported_in_the_users_zone() {
+        let madrid = parse_zone("Europe/Madrid").unwrap();
+        assert!(parse_zone("Mars/Olympus").is_none());
+        // both storage formats are the same instant
+        assert_eq!(parse("2024-07-01 07:30:00"), parse("2024-07-01T09:30:00.123+02:00").map(|t| t - chrono::Duration::milliseconds(123)));
+        assert_eq!(format("2024-07-01 07:30:00", madrid), "2024-07-01 09:30 CEST");
+        assert_eq!(format("2024-01-15 07:30:00", madrid), "2024-01-15 08:30 CET");
+        assert_eq!(format("2024-01-15 07:30:00", Tz::UTC), "2024-01-15 07:30 UTC");
+        assert_eq!(format("pending", madrid), "pending");
+        assert_eq!(to_rfc3339("2024-07-01 07:30:00", madrid), "2024-07-01T09:30:00+02:00");
+        assert_eq!(start_of_day(NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(), madrid), "2024-06-30 22:00:00");
+        // typed times are local unless they carry an offset
+        assert_eq!(parse_local("2024-07-01 09:30", madrid), parse("2024-07-01 07:30:00"));
+        assert_eq!(parse_local("2024-07-01T09:30:00Z", madrid), parse("2024-07-01 09:30:00"));
+        assert!(parse_local("01/07/2024", madrid).is_none());
+    }
+
+    #[test]
+    fn users_pick_a_zone_or_get_the_default() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        assert_eq!(user_zone(&conn, &fixtures.clinician_id).unwrap(), default_zone());
+        let zone = parse_zone("America/New_York").unwrap();
+        set_user_zone(&conn, &fixtures.clinician_id, Some(zone)).unwrap();
+        assert_eq!(user_zone(&conn, &fixtures.clinician_id).unwrap(), zone);
+        set_user_zone(&conn, &fixtures.clinician_id, None).unwrap();
+        assert_eq!(user_zone(&conn, &fixtures.clinician_id).unwrap(), default_zone());
+    }
+}
@@ -0,0 +1,203 @@
+// Transfer of a patient to another clinician
+// Clinicians with TransferPatient hand over patients in their care; admins (no patient scope) any patient.
+// The transfer updates patients.clinician_id, hands the old clinician's unread escalation notices for the
+// patient to the new one, and is recorded in patient_transfers and the audit log. Both clinicians, other than
+// the one who made the transfer, see it at the top of their menu once and are emailed when they set an address.
+use crate::access_control::{self, Permission, Resource, Role};
+use crate::daily_summary;
+use crate::db::queries;
+use crate::email;
+use crate::logger::SECURITY_TARGET;
+use rusqlite::{params, Connection, OptionalExtension};
+
+pub const MAX_REASON_LENGTH: usize = 500;
+
+// a transfer as shown to one of the two clinicians
+#[derive(Debug, Clone)]
+pub struct TransferNotice {
+    pub patient_name: String,
+    // usernames, or user ids of accounts that are gone
+    pub from_clinician: String,
+    pub to_clinician: String,
+    pub transferred_by: String,
+    pub reason: String,
+    pub transferred_at: String,
+    // true for the clinician who received the patient
+    pub incoming: bool,
+}
+
+// id of an active account whose role is (or is built on) clinician
+fn active_clinician_id(conn: &Connection, username: &str) -> Result<String, String> {
+    let account: Option<(String, String, bool)> = conn
+        .query_row("SELECT id, role, is_active FROM users WHERE user_name = ?1", [username], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })
+        .optional()
+        .map_err(|e| e.to_string())?;
+    match account {
+        Some((id, role, true)) if Role::resolve_chain(conn, &role).last().map(String::as_str) == Some("clinician") => Ok(id),
+        Some((_, _, false)) => Err(format!("{} is suspended", username)),
+        _ => Err(format!("{} is not a clinician account", username)),
+    }
+}
+
+/*
+Transfer a patient to the clinician with the given username. Returns the transfer id.
+Email notices are best effort; failures are logged and do not undo the transfer.
+*/
+pub fn transfer_patient(conn: &Connection, role: &Role, patient_id: &str, to_username: &str, reason: &str) -> Result<i64, String> {
+    let allowed = if access_control::has_patient_scope(role) {
+        access_control::can_access(conn, role, &Resource::Patient(patient_id), &Permission::TransferPatient)
+    } else {
+        role.has_permission(&Permission::TransferPatient)
+    };
+    if !allowed {
+        return Err("Access denied: patient is not in your care or TransferPatient is missing".to_string());
+    }
+    let reason = reason.trim();
+    if reason.is_empty() || reason.chars().count() > MAX_REASON_LENGTH {
+        return Err(format!("a reason of 1 to {} characters is required", MAX_REASON_LENGTH));
+    }
+    let patient = queries::get_patient_by_id(conn, patient_id)
+        .map_err(|e| e.to_string())?
+        .ok_or("no patient record found")?;
+    let to_clinician_id = active_clinician_id(conn, to_username)?;
+    if to_clinician_id == patient.clinician_id {
+        return Err(format!("{} is already the patient's clinician", to_username));
+    }
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    tx.execute("UPDATE patients SET clinician_id = ?2 WHERE patient_id = ?1", params![patient_id, to_clinician_id])
+        .map_err(|e| e.to_string())?;
+    // the old clinician can no longer acknowledge the patient's alerts
+    tx.execute(
+        "UPDATE alert_notifications SET user_id = ?3
+         WHERE user_id = ?2 AND read_at IS NULL AND alert_id IN (SELECT alert_id FROM alerts WHERE patient_id = ?1)",
+        params![patient_id, patient.clinician_id, to_clinician_id],
+    )
+    .map_err(|e| e.to_string())?;
+    // a clinician handing over their own patient needs no notice about it
+    tx.execute(
+        "INSERT INTO patient_transfers (patient_id, from_clinician_id, to_clinician_id, transferred_by, reason, transferred_at, from_seen_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, datetime('now'), CASE WHEN ?4 = ?2 THEN datetime('now') END)",
+        params![patient_id, patient.clinician_id, to_clinician_id, role.id, reason],
+    )
+    .map_err(|e| e.to_string())?;
+    let transfer_id = tx.last_insert_rowid();
+    tx.commit().map_err(|e| e.to_string())?;
+    tracing::warn!(target: SECURITY_TARGET, user_id = %role.id, patient_id = %patient_id, from_clinician_id = %patient.clinician_id, to_clinician_id = %to_clinician_id, transfer_id, "Patient transferred to another clinician");
+
+    if email::enabled() {
+        let patient_name = format!("{} {}", patient.first_name, patient.last_name);
+        for (clinician_id, body) in [
+            (&patient.clinician_id, format!("{} has been transferred from your care to {}.\n\nReason: {}", patient_name, to_username, reason)),
+            (&to_clinician_id, format!("{} has been transferred to your care.\n\nReason: {}", patient_name, reason)),
+        ] {
+            if *clinician_id == role.id {
+                continue;
+            }
+            let result = match daily_summary::summary_email(conn, clinician_id) {
+                Ok(Some(recipient)) => email::send_report(&recipient, "GlucoGuard patient transfer", body).map_err(|e| e.to_string()),
+                Ok(None) => Ok(()),
+                Err(e) => Err(e.to_string()),
+            };
+            if let Err(e) = result {
+                tracing::warn!(clinician_id = %clinician_id, transfer_id, error = %e, "Patient transfer email failed");
+            }
+        }
+    }
+    Ok(transfer_id)
+}
+
+// transfers to or from the clinician they have not seen yet, oldest first
+pub fn unseen_transfers(conn: &Connection, user_id: &str) -> rusqlite::Result<Vec<TransferNotice>> {
+    let mut stmt = conn.prepare(
+        "SELECT COALESCE(p.first_name || ' ' || p.last_name, t.patient_id),
+                COALESCE(f.user_name, t.from_clinician_id), COALESCE(n.user_name, t.to_clinician_id),
+                COALESCE(b.user_name, t.transferred_by), t.reason, t.transferred_at, t.to_clinician_id = ?1
+         FROM patient_transfers t
+         LEFT JOIN patients p ON p.patient_id = t.patient_id
+         LEFT JOIN users f ON f.id = t.from_clinician_id
+         LEFT JOIN users n ON n.id = t.to_clinician_id
+         LEFT JOIN users b ON b.id = t.transferred_by
+         WHERE (t.from_clinician_id = ?1 AND t.from_seen_at IS NULL) OR (t.to_clinician_id = ?1 AND t.to_seen_at IS NULL)
+         ORDER BY t.transfer_id",
+    )?;
+    let rows = stmt.query_map([user_id], |row| {
+        Ok(TransferNotice {
+            patient_name: row.get(0)?,
+            from_clinician: row.get(1)?,
+            to_clinician: row.get(2)?,
+            transferred_by: row.get(3)?,
+            reason: row.get(4)?,
+            transferred_at: row.get(5)?,
+            incoming: row.get(6)?,
+        })
+    })?;
+    rows.collect()
+}
+
+// mark every transfer notice of the clinician as seen
+pub fn mark_transfers_seen(conn: &Connection, user_id: &str) -> rusqlite::Result<()> {
+    conn.execute("UPDATE patient_transfers SET from_seen_at = datetime('now') WHERE from_clinician_id = ?1 AND from_seen_at IS NULL", [user_id])?;
+    conn.execute("UPDATE patient_transfers SET to_seen_at = datetime('now') WHERE to_clinician_id = ?1 AND to_seen_at IS NULL", [user_id])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::escalation;
+    use crate::test_utils;
+
+    #[test]
+    fn clinicians_hand_over_their_own_patients_and_both_are_told() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        let clinician = Role::new(&conn, "clinician", &fixtures.clinician_id);
+        let other = Role::new(&conn, "clinician", &fixtures.other_clinician_id);
+        let admin = Role::new(&conn, "admin", &fixtures.admin_id);
+        conn.execute(
+            "INSERT INTO alerts (patient_id, alert_type, alert_message, alert_time, is_resolved) VALUES (?1, 'LOW', 'low', datetime('now'), 0)",
+            [&fixtures.patient_id],
+        )
+        .unwrap();
+        let alert_id = conn.last_insert_rowid();
+        conn.execute(
+            "INSERT INTO alert_notifications (alert_id, user_id, message, created_at) VALUES (?1, ?2, 'LOW', datetime('now'))",
+            params![alert_id, fixtures.clinician_id],
+        )
+        .unwrap();
+
+        assert!(transfer_patient(&conn, &other, &fixtures.patient_id, "dr.other", "moving").is_err());
+        assert!(transfer_patient(&conn, &clinician, &fixtures.patient_id, "care.fixture", "moving").is_err());
+        assert!(transfer_patient(&conn, &clinician, &fixtures.patient_id, "dr.fixture", "moving").is_err());
+        assert!(transfer_patient(&conn, &clinician, &fixtures.patient_id, "dr.other", " ").is_err());
+        transfer_patient(&conn, &clinician, &fixtures.patient_id, "dr.other", "patient moved to the north clinic").unwrap();
+
+        let patient = queries::get_patient_by_id(&conn, &fixtures.patient_id).unwrap().unwrap();
+        assert_eq!(patient.clinician_id, fixtures.other_clinician_id);
+        assert!(escalation::unread_notifications(&conn, &fixtures.clinician_id).unwrap().is_empty());
+        assert_eq!(escalation::unread_notifications(&conn, &fixtures.other_clinician_id).unwrap().len(), 1);
+
+        // the clinician who handed the patient over is not told about it again
+        assert!(unseen_transfers(&conn, &fixtures.clinician_id).unwrap().is_empty());
+        let incoming = unseen_transfers(&conn, &fixtures.other_clinician_id).unwrap();
+        assert_eq!(incoming.len(), 1);
+        assert!(incoming[0].incoming);
+        assert_eq!(incoming[0].from_clinician, "dr.fixture");
+        mark_transfers_seen(&conn, &fixtures.other_clinician_id).unwrap();
+        assert!(unseen_transfers(&conn, &fixtures.other_clinician_id).unwrap().is_empty());
+
+        // the former clinician has lost access; an admin can move the patient back and both are told
+        assert!(transfer_patient(&conn, &clinician, &fixtures.patient_id, "dr.fixture", "back").is_err());
+        transfer_patient(&conn, &admin, &fixtures.patient_id, "dr.fixture", "back").unwrap();
+        let patient = queries::get_patient_by_id(&conn, &fixtures.patient_id).unwrap().unwrap();
+        assert_eq!(patient.clinician_id, fixtures.clinician_id);
+        let outgoing = unseen_transfers(&conn, &fixtures.other_clinician_id).unwrap();
+        assert_eq!(outgoing.len(), 1);
+        assert!(!outgoing[0].incoming);
+        assert_eq!(outgoing[0].transferred_by, "test.admin");
+        assert_eq!(unseen_transfers(&conn, &fixtures.clinician_id).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,627 @@
+// Terminal UI started with `glucoguard --tui` (built with the `tui` feature)
+// A login form, then tabs with the patient dashboard, a patient's readings, insulin doses and alerts,
+// and the audit log for roles that may read it. Data comes from the same queries and access checks
+// as the menus, and the session is created and checked like a menu login.
+use crate::access_control::{self, Permission, Resource, Role};
+use crate::alerts::{self, AlertRecord};
+use crate::audit::{self, AuditEvent, AuditFilter};
+use crate::auth;
+use crate::dashboard::{self, DashboardRow, Priority};
+use crate::db::models::User;
+use crate::db::pool::Db;
+use crate::db::queries;
+use crate::insulin::{self, GlucoseReading, InsulinLog};
+use crate::logger::SECURITY_TARGET;
+use crate::session::SessionManager;
+use crate::timestamps;
+use crate::units::{self, GlucoseUnit};
+use chrono_tz::Tz;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Clear, Paragraph, Row, Table, TableState, Tabs};
+use ratatui::{DefaultTerminal, Frame};
+use rusqlite::Connection;
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+// the open tab is reloaded this often, so new readings and alerts show up without a key press
+const REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+// rows loaded for the readings, insulin and alerts tabs
+const HISTORY_LIMIT: u32 = 500;
+const AUDIT_LIMIT: usize = 500;
+const PAGE_ROWS: usize = 10;
+
+pub fn run(db: &Db) -> Result<(), Box<dyn Error>> {
+    let conn = db.get()?;
+    let mut terminal = ratatui::init();
+    let result = App::default().run(&mut terminal, &conn, db);
+    ratatui::restore();
+    result
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    #[default]
+    Username,
+    Password,
+}
+
+#[derive(Default)]
+struct LoginForm {
+    username: String,
+    password: String,
+    focus: Field,
+    message: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Tab {
+    Patients,
+    Readings,
+    Insulin,
+    Alerts,
+    Audit,
+}
+
+impl Tab {
+    fn title(&self) -> &'static str {
+        match self {
+            Tab::Patients => "Patients",
+            Tab::Readings => "Readings",
+            Tab::Insulin => "Insulin",
+            Tab::Alerts => "Alerts",
+            Tab::Audit => "Audit log",
+        }
+    }
+}
+
+// the patient whose history the detail tabs show
+struct OpenPatient {
+    patient_id: String,
+    name: String,
+}
+
+struct MainView {
+    role: Role,
+    username: String,
+    // the user's glucose unit, read at login
+    unit: GlucoseUnit,
+    // the user's display time zone, read at login
+    zone: Tz,
+    session_id: String,
+    tabs: Vec<Tab>,
+    tab: usize,
+    rows: Vec<DashboardRow>,
+    // case-insensitive part of the patient name or id
+    filter: String,
+    editing_filter: bool,
+    patients: TableState,
+    open: Option<OpenPatient>,
+    readings: Vec<GlucoseReading>,
+    insulin: Vec<InsulinLog>,
+    alerts: Option<Vec<AlertRecord>>,
+    audit: Vec<AuditEvent>,
+    detail: TableState,
+    status: String,
+    loaded_at: Instant,
+}
+
+#[derive(Default)]
+enum Screen {
+    #[default]
+    Login,
+    Main(Box<MainView>),
+}
+
+#[derive(Default)]
+struct App {
+    screen: Screen,
+    login: LoginForm,
+    cleanup_started: bool,
+    quit: bool,
+}
+
+impl App {
+    fn run(mut self, terminal: &mut DefaultTerminal, conn: &Connection, db: &Db) -> Result<(), Box<dyn Error>> {
+        while !self.quit {
+            if let Screen::Main(view) = &mut self.screen {
+                // a session that was revoked or ran out sends the user back to the login form
+                let valid = SessionManager::new()
+                    .get_session_by_id(conn, &view.session_id)
+                    .is_some_and(|session| session.is_valid());
+                if !valid {
+                    self.login = LoginForm { message: Some("Session has expired. Please log in again.".to_string()), ..LoginForm::default() };
+                    self.screen = Screen::Login;
+                    continue;
+                }
+                if view.loaded_at.elapsed() >= REFRESH_INTERVAL {
+                    view.reload(conn);
+                }
+            }
+            terminal.draw(|frame| self.draw(frame))?;
+            if event::poll(Duration::from_millis(250))? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press {
+                        self.handle_key(key, conn, db);
+                    }
+                }
+            }
+        }
+        if let Screen::Main(view) = &self.screen {
+            let _ = SessionManager::new().deactivate_session(conn, &view.session_id);
+        }
+        Ok(())
+    }
+
+    fn handle_key(&mut self, key: KeyEvent, conn: &Connection, db: &Db) {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            self.quit = true;
+            return;
+        }
+        match &mut self.screen {
+            Screen::Login => {
+                if let Some(view) = self.login.handle_key(key, conn, &mut self.quit) {
+                    if !self.cleanup_started {
+                        SessionManager::new().run_cleanup(db);
+                        self.cleanup_started = true;
+                    }
+                    self.login = LoginForm::default();
+                    self.screen = Screen::Main(view);
+                }
+            }
+            Screen::Main(view) => {
+                if view.handle_key(key, conn) {
+                    let _ = SessionManager::new().deactivate_session(conn, &view.session_id);
+                    self.login = LoginForm { message: Some("Logged out.".to_string()), ..LoginForm::default() };
+                    self.screen = Screen::Login;
+                }
+            }
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        match &mut self.screen {
+            Screen::Login => self.login.draw(frame),
+            Screen::Main(view) => view.draw(frame),
+        }
+    }
+}
+
+impl LoginForm {
+    // returns the main view once the login succeeds
+    fn handle_key(&mut self, key: KeyEvent, conn: &Connection, quit: &mut bool) -> Option<Box<MainView>> {
+        let field = match self.focus {
+            Field::Username => &mut self.username,
+            Field::Password => &mut self.password,
+        };
+        match key.code {
+            KeyCode::Esc => *quit = true,
+            KeyCode::Tab | KeyCode::BackTab | KeyCode::Up | KeyCode::Down => {
+                self.focus = if self.focus == Field::Username { Field::Password } else { Field::Username };
+            }
+            KeyCode::Enter if self.focus == Field::Username => self.focus = Field::Password,
+            KeyCode::Enter => {
+                let result = login(conn, self.username.trim(), &self.password);
+                self.password.clear();
+                match result {
+                    Ok((role, session_id)) => return Some(Box::new(MainView::new(conn, role, self.username.trim().to_string(), session_id))),
+                    Err(e) => self.message = Some(e),
+                }
+            }
+            KeyCode::Backspace => {
+                field.pop();
+            }
+            KeyCode::Char(c) => field.push(c),
+            _ => {}
+        }
+        None
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let area = centered(frame.area(), 50, 9);
+        let style = |field: Field| {
+            if self.focus == field {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            }
+        };
+        let mut lines = vec![
+            Line::from(vec![Span::raw("Username: "), Span::styled(self.username.clone(), style(Field::Username))]),
+            Line::from(vec![Span::raw("Password: "), Span::styled("*".repeat(self.password.chars().count()), style(Field::Password))]),
+            Line::raw(""),
+        ];
+        if let Some(message) = &self.message {
+            lines.push(Line::styled(message.clone(), Style::default().fg(Color::Yellow)));
+        }
+        lines.push(Line::styled("Tab: next field  Enter: log in  Esc: quit", Style::default().fg(Color::DarkGray)));
+        frame.render_widget(Clear, area);
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" GlucoGuard login ")), area);
+    }
+}
+
+/*
+Check the credentials, refuse suspended accounts and create a session like the menu login.
+Returns the role and the session id, or a message for the form that does not say which part was wrong.
+*/
+fn login(conn: &Connection, username: &str, password: &str) -> Result<(Role, String), String> {
+    let account = queries::get_user_by_username(conn, username).map_err(|e| e.to_string())?;
+    let verified = account
+        .as_ref()
+        .is_some_and(|user| auth::verify_password(password, &user.password_hash).unwrap_or(false));
+    let Some(User { id: user_id, role, .. }) = account.filter(|_| verified) else {
+        tracing::warn!(target: SECURITY_TARGET, username = %username, "TUI login failed");
+        return Err("Invalid username or password.".to_string());
+    };
+    if queries::is_user_suspended(conn, &user_id).map_err(|e| e.to_string())? {
+        tracing::warn!(target: SECURITY_TARGET, username = %username, user_id = %user_id, "TUI login for suspended account");
+        return Err("Account is suspended. Contact an administrator.".to_string());
+    }
+    let session_id = SessionManager::new()
+        .create_session(conn, user_id.clone(), role.clone())
+        .map_err(|e| format!("Failed to create session: {}", e))?;
+    tracing::info!(target: SECURITY_TARGET, username = %username, user_id = %user_id, role = %role, "TUI login succeeded");
+    Ok((Role::new(conn, &role, &user_id), session_id))
+}
+
+impl MainView {
+    fn new(conn: &Connection, role: Role, username: String, session_id: String) -> MainView {
+        let mut tabs = vec![Tab::Patients, Tab::Readings, Tab::Insulin, Tab::Alerts];
+        if role.has_permission(&Permission::ViewAuditLog) {
+            tabs.push(Tab::Audit);
+        }
+        let mut view = MainView {
+            unit: units::display_unit(conn, &role.id),
+            zone: timestamps::display_zone(conn, &role.id),
+            role,
+            username,
+            session_id,
+            tabs,
+            tab: 0,
+            rows: Vec::new(),
+            filter: String::new(),
+            editing_filter: false,
+            patients: TableState::default(),
+            open: None,
+            readings: Vec::new(),
+            insulin: Vec::new(),
+            alerts: None,
+            audit: Vec::new(),
+            detail: TableState::default(),
+            status: String::new(),
+            loaded_at: Instant::now(),
+        };
+        view.reload(conn);
+        view.patients.select(if view.rows.is_empty() { None } else { Some(0) });
+        view
+    }
+
+    fn current(&self) -> Tab {
+        self.tabs[self.tab]
+    }
+
+    // dashboard rows matching the filter
+    fn visible_rows(&self) -> Vec<&DashboardRow> {
+        let filter = self.filter.trim().to_lowercase();
+        self.rows
+            .iter()
+            .filter(|row| {
+                filter.is_empty()
+                    || format!("{} {} {}", row.patient.first_name, row.patient.last_name, row.patient.patient_id)
+                        .to_lowercase()
+                        .contains(&filter)
+            })
+            .collect()
+    }
+
+    // reload the open tab
+    fn reload(&mut self, conn: &Connection) {
+        self.loaded_at = Instant::now();
+        let result = match self.current() {
+            Tab::Patients => dashboard::clinician_dashboard(conn, &self.role).map(|rows| self.rows = rows),
+            Tab::Readings | Tab::Insulin | Tab::Alerts => match &self.open {
+                Some(open) => self.load_patient(conn, &open.patient_id.clone()),
+                None => Ok(()),
+            },
+            Tab::Audit => audit::get_events_page(conn, &AuditFilter::default(), AUDIT_LIMIT, 0).map(|events| self.audit = events),
+        };
+        if let Err(e) = result {
+            self.status = format!("Error loading data: {}", e);
+        }
+    }
+
+    fn load_patient(&mut self, conn: &Connection, patient_id: &str) -> rusqlite::Result<()> {
+        self.readings = insulin::get_recent_glucose_readings(conn, patient_id, HISTORY_LIMIT)?;
+        self.insulin = insulin::get_recent_insulin_logs(conn, patient_id, HISTORY_LIMIT)?;
+        self.alerts = if self.role.has_permission(&Permission::ViewAlerts) {
+            Some(alerts::get_recent_alerts(conn, patient_id, HISTORY_LIMIT)?)
+        } else {
+            None
+        };
+        Ok(())
+    }
+
+    // show the history of the selected patient, after the same access check as the menus
+    fn open_selected(&mut self, conn: &Connection) {
+        let Some((patient_id, name)) = self
+            .patients
+            .selected()
+            .and_then(|index| self.visible_rows().get(index).map(|row| (row.patient.patient_id.clone(), format!("{} {}", row.patient.first_name, row.patient.last_name))))
+        else {
+            return;
+        };
+        if !access_control::can_access(conn, &self.role, &Resource::Patient(&patient_id), &Permission::ViewGlucose) {
+            self.status = "Access denied for this patient.".to_string();
+            return;
+        }
+        if let Err(e) = self.load_patient(conn, &patient_id) {
+            self.status = format!("Error loading patient history: {}", e);
+            return;
+        }
+        self.open = Some(OpenPatient { patient_id, name });
+        self.tab = 1;
+        self.detail.select(Some(0));
+        self.status.clear();
+    }
+
+    fn row_count(&self) -> usize {
+        match self.current() {
+            Tab::Patients => self.visible_rows().len(),
+            Tab::Readings => self.readings.len(),
+            Tab::Insulin => self.insulin.len(),
+            Tab::Alerts => self.alerts.as_ref().map_or(0, Vec::len),
+            Tab::Audit => self.audit.len(),
+        }
+    }
+
+    fn scroll(&mut self, delta: isize) {
+        let count = self.row_count();
+        let state = if self.current() == Tab::Patients { &mut self.patients } else { &mut self.detail };
+        if count == 0 {
+            state.select(None);
+            return;
+        }
+        let current = state.selected().unwrap_or(0) as isize;
+        state.select(Some((current + delta).clamp(0, count as isize - 1) as usize));
+    }
+
+    fn switch_tab(&mut self, conn: &Connection, index: usize) {
+        self.tab = index;
+        self.detail.select(Some(0));
+        self.reload(conn);
+    }
+
+    // returns true when the user logs out
+    fn handle_key(&mut self, key: KeyEvent, conn: &Connection) -> bool {
+        if self.editing_filter {
+            match key.code {
+                KeyCode::Enter | KeyCode::Esc => self.editing_filter = false,
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                }
+                KeyCode::Char(c) => self.filter.push(c),
+                _ => {}
+            }
+            self.patients.select(if self.visible_rows().is_empty() { None } else { Some(0) });
+            return false;
+        }
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return true,
+            KeyCode::Tab | KeyCode::Right => self.switch_tab(conn, (self.tab + 1) % self.tabs.len()),
+            KeyCode::BackTab | KeyCode::Left => self.switch_tab(conn, (self.tab + self.tabs.len() - 1) % self.tabs.len()),
+            KeyCode::Down | KeyCode::Char('j') => self.scroll(1),
+            KeyCode::Up | KeyCode::Char('k') => self.scroll(-1),
+            KeyCode::PageDown => self.scroll(PAGE_ROWS as isize),
+            KeyCode::PageUp => self.scroll(-(PAGE_ROWS as isize)),
+            KeyCode::Char('r') => self.reload(conn),
+            KeyCode::Char('/') if self.current() == Tab::Patients => self.editing_filter = true,
+            KeyCode::Enter if self.current() == Tab::Patients => self.open_selected(conn),
+            _ => {}
+        }
+        false
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [header, tabs, body, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(3),
+            Constraint::Min(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let patient = self.open.as_ref().map(|open| format!("  |  patient: {}", open.name)).unwrap_or_default();
+        frame.render_widget(
+            Paragraph::new(format!("GlucoGuard  |  {} ({}){}", self.username, self.role.name, patient)).style(Style::default().add_modifier(Modifier::BOLD)),
+            header,
+        );
+        frame.render_widget(
+            Tabs::new(self.tabs.iter().map(Tab::title))
+                .select(self.tab)
+                .block(Block::bordered())
+                .highlight_style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
+            tabs,
+        );
+        match self.current() {
+            Tab::Patients => self.draw_patients(frame, body),
+            tab => self.draw_detail(frame, body, tab),
+        }
+
+        let help = if self.editing_filter {
+            format!("Filter: {}_  (Enter: done)", self.filter)
+        } else if !self.status.is_empty() {
+            self.status.clone()
+        } else {
+            match self.current() {
+                Tab::Patients => "Enter: open patient  /: filter  Tab: next tab  r: refresh  q: log out".to_string(),
+                _ => "Up/Down/PgUp/PgDn: scroll  Tab: next tab  r: refresh  q: log out".to_string(),
+            }
+        };
+        frame.render_widget(Paragraph::new(help).style(Style::default().fg(Color::DarkGray)), footer);
+    }
+
+    fn draw_patients(&mut self, frame: &mut Frame, area: Rect) {
+        let rows: Vec<Row> = self
+            .visible_rows()
+            .into_iter()
+            .map(|row| {
+                let (flag, style) = match row.priority() {
+                    Priority::Urgent => ("!!", Style::default().fg(Color::Red)),
+                    Priority::Attention => ("!", Style::default().fg(Color::Yellow)),
+                    Priority::Stable => ("", Style::default()),
+                };
+                let latest = row
+                    .latest
+                    .as_ref()
+                    .map(|reading| format!("{} ({})", self.unit.value(reading.level), dashboard::format_age(reading.minutes_ago)))
+                    .unwrap_or_else(|| "no readings".to_string());
+                let alerts = match (row.open_alerts, row.open_critical_alerts) {
+                    (0, _) => "none".to_string(),
+                    (open, 0) => open.to_string(),
+                    (open, critical) => format!("{} ({} critical)", open, critical),
+                };
+                let last_dose = row
+                    .last_dose
+                    .as_ref()
+                    .map(|dose| format!("{} {:.2} u ({})", dose.action_type, dose.units, dashboard::format_age(dose.minutes_ago)))
+                    .unwrap_or_else(|| "none".to_string());
+                Row::new(vec![
+                    flag.to_string(),
+                    format!("{} {}", row.patient.first_name, row.patient.last_name),
+                    latest,
+                    row.trend.map(|arrow| arrow.symbol()).unwrap_or("-").to_string(),
+                    alerts,
+                    last_dose,
+                ])
+                .style(style)
+            })
+            .collect();
+        let title = if self.filter.is_empty() {
+            format!(" Patients ({}) ", rows.len())
+        } else {
+            format!(" Patients matching '{}' ({}) ", self.filter, rows.len())
+        };
+        let latest_header = format!("Latest ({})", self.unit.label());
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(2),
+                Constraint::Percentage(22),
+                Constraint::Percentage(20),
+                Constraint::Length(5),
+                Constraint::Percentage(16),
+                Constraint::Percentage(30),
+            ],
+        )
+        .header(header_row(&["", "Patient", &latest_header, "Trend", "Open alerts", "Last dose"]))
+        .block(Block::bordered().title(title))
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, area, &mut self.patients);
+    }
+
+    fn draw_detail(&mut self, frame: &mut Frame, area: Rect, tab: Tab) {
+        if tab != Tab::Audit && self.open.is_none() {
+            frame.render_widget(
+                Paragraph::new("Select a patient on the Patients tab and press Enter.").block(Block::bordered()),
+                area,
+            );
+            return;
+        }
+        let glucose_header = format!("Glucose ({})", self.unit.label());
+        let (header, widths, rows): (&[&str], Vec<Constraint>, Vec<Row>) = match tab {
+            Tab::Readings => (
+                &["Time", &glucose_header, "Status"],
+                vec![Constraint::Length(22), Constraint::Length(16), Constraint::Min(10)],
+                self.readings
+                    .iter()
+                    .map(|reading| Row::new(vec![timestamps::format(&reading.reading_time, self.zone), self.unit.value(reading.glucose_level), reading.status.clone()]))
+                    .collect(),
+            ),
+            Tab::Insulin => (
+                &["Time", "Type", "Units", "Requested by"],
+                vec![Constraint::Length(22), Constraint::Length(16), Constraint::Length(8), Constraint::Min(10)],
+                self.insulin
+                    .iter()
+                    .map(|log| Row::new(vec![timestamps::format(&log.dosage_time, self.zone), log.action_type.clone(), format!("{:.2}", log.dosage_units), log.requested_by.clone()]))
+                    .collect(),
+            ),
+            Tab::Alerts => {
+                let Some(alerts) = &self.alerts else {
+                    frame.render_widget(Paragraph::new("ViewAlerts permission required.").block(Block::bordered()), area);
+                    return;
+                };
+                (
+                    &["Time", "Type", "Resolved", "Message"],
+                    vec![Constraint::Length(22), Constraint::Length(12), Constraint::Length(9), Constraint::Min(20)],
+                    alerts
+                        .iter()
+                        .map(|alert| {
+                            Row::new(vec![
+                                timestamps::format(&alert.alert_time, self.zone),
+                                alert.alert_type.clone(),
+                                if alert.is_resolved { "yes" } else { "no" }.to_string(),
+                                self.unit.localize(&alert.alert_message),
+                            ])
+                        })
+                        .collect(),
+                )
+            }
+            Tab::Audit | Tab::Patients => (
+                &["Time", "Level", "User", "Action", "Details"],
+                vec![Constraint::Length(22), Constraint::Length(6), Constraint::Length(16), Constraint::Percentage(30), Constraint::Min(20)],
+                self.audit
+                    .iter()
+                    .map(|event| {
+                        Row::new(vec![
+                            timestamps::format(&event.occurred_at, self.zone),
+                            event.level.clone(),
+                            event.username.clone().or_else(|| event.user_id.clone()).unwrap_or_else(|| "-".to_string()),
+                            event.action.clone(),
+                            audit::format_details(&event.details),
+                        ])
+                    })
+                    .collect(),
+            ),
+        };
+        let title = format!(" {} ({}) ", tab.title(), rows.len());
+        let table = Table::new(rows, widths)
+            .header(header_row(header))
+            .block(Block::bordered().title(title))
+            .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, area, &mut self.detail);
+    }
+}
+
+fn header_row<'a>(titles: &[&'a str]) -> Row<'a> {
+    Row::new(titles.to_vec()).style(Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED))
+}
+
+// a width x height box in the middle of the area
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(area.x + (area.width - width) / 2, area.y + (area.height - height) / 2, width, height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    #[test]
+    fn login_checks_the_password_and_suspension() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        assert!(login(&conn, "dr.fixture", "wrong password").is_err());
+        assert!(login(&conn, "nobody", test_utils::TEST_PASSWORD).is_err());
+
+        let (role, session_id) = login(&conn, "dr.fixture", test_utils::TEST_PASSWORD).unwrap();
+        assert_eq!(role.id, fixtures.clinician_id);
+        assert!(SessionManager::new().get_session_by_id(&conn, &session_id).is_some());
+
+        conn.execute("UPDATE users SET is_active = 0 WHERE id = ?1", [&fixtures.clinician_id]).unwrap();
+        assert!(login(&conn, "dr.fixture", test_utils::TEST_PASSWORD).is_err());
+    }
+}
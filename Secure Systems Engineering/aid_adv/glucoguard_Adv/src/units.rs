@@ -0,0 +1,144 @@
+// Glucose units: every value is stored in mg/dL, each user picks the unit they read and enter values in
+// The preference is users.glucose_unit; conversion happens only where values are shown or typed in.
+// Exports, FHIR, the REST/gRPC APIs and device feeds stay in mg/dL.
+use crate::logger::SECURITY_TARGET;
+use regex::{Captures, Regex};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::sync::OnceLock;
+
+// glucose molar mass conversion used by meters and CGMs
+pub const MG_DL_PER_MMOL_L: f64 = 18.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GlucoseUnit {
+    #[default]
+    MgDl,
+    MmolL,
+}
+
+impl GlucoseUnit {
+    pub const ALL: [GlucoseUnit; 2] = [GlucoseUnit::MgDl, GlucoseUnit::MmolL];
+
+    // "mg/dL" or "mmol/L", case-insensitive and with or without the slash
+    pub fn parse(value: &str) -> Option<GlucoseUnit> {
+        match value.trim().to_ascii_lowercase().replace('/', "").as_str() {
+            "mgdl" => Some(GlucoseUnit::MgDl),
+            "mmoll" | "mmol" => Some(GlucoseUnit::MmolL),
+            _ => None,
+        }
+    }
+
+    // as stored in users.glucose_unit and shown after values
+    pub fn label(self) -> &'static str {
+        match self {
+            GlucoseUnit::MgDl => "mg/dL",
+            GlucoseUnit::MmolL => "mmol/L",
+        }
+    }
+
+    // stored mg/dL value in this unit
+    pub fn convert(self, mg_dl: f64) -> f64 {
+        match self {
+            GlucoseUnit::MgDl => mg_dl,
+            GlucoseUnit::MmolL => mg_dl / MG_DL_PER_MMOL_L,
+        }
+    }
+
+    // value typed in this unit as mg/dL for storage
+    pub fn to_mg_dl(self, value: f64) -> f64 {
+        match self {
+            GlucoseUnit::MgDl => value,
+            GlucoseUnit::MmolL => value * MG_DL_PER_MMOL_L,
+        }
+    }
+
+    // the number alone: whole mg/dL, mmol/L to one decimal
+    pub fn value(self, mg_dl: f64) -> String {
+        match self {
+            GlucoseUnit::MgDl => format!("{:.0}", mg_dl),
+            GlucoseUnit::MmolL => format!("{:.1}", self.convert(mg_dl)),
+        }
+    }
+
+    // "120 mg/dL" or "6.7 mmol/L"
+    pub fn format(self, mg_dl: f64) -> String {
+        format!("{} {}", self.value(mg_dl), self.label())
+    }
+
+    /*
+    Rewrite the "<value> mg/dL" and "<low>-<high> mg/dL" figures of a stored text (alert messages,
+    daily summaries, bolus refusals) into this unit. Text without mg/dL figures is returned unchanged.
+    */
+    pub fn localize(self, text: &str) -> String {
+        if self == GlucoseUnit::MgDl {
+            return text.to_string();
+        }
+        static FIGURE: OnceLock<Regex> = OnceLock::new();
+        let figure = FIGURE.get_or_init(|| Regex::new(r"(\d+(?:\.\d+)?)(?:-(\d+(?:\.\d+)?))? mg/dL").unwrap());
+        figure
+            .replace_all(text, |caps: &Captures| {
+                let shown = |value: &str| value.parse::<f64>().map(|mg_dl| self.value(mg_dl)).unwrap_or_else(|_| value.to_string());
+                match caps.get(2) {
+                    Some(high) => format!("{}-{} {}", shown(&caps[1]), shown(high.as_str()), self.label()),
+                    None => format!("{} {}", shown(&caps[1]), self.label()),
+                }
+            })
+            .into_owned()
+    }
+}
+
+// the user's unit; unknown users and unreadable values fall back to mg/dL
+pub fn user_unit(conn: &Connection, user_id: &str) -> rusqlite::Result<GlucoseUnit> {
+    let stored: Option<String> = conn
+        .query_row("SELECT glucose_unit FROM users WHERE id = ?1", [user_id], |row| row.get(0))
+        .optional()?;
+    Ok(stored.as_deref().and_then(GlucoseUnit::parse).unwrap_or_default())
+}
+
+// user_unit for display, logging a lookup error instead of failing the screen
+pub fn display_unit(conn: &Connection, user_id: &str) -> GlucoseUnit {
+    user_unit(conn, user_id).unwrap_or_else(|e| {
+        tracing::error!(user_id = %user_id, "Failed to load glucose unit preference: {}", e);
+        GlucoseUnit::default()
+    })
+}
+
+pub fn set_user_unit(conn: &Connection, user_id: &str, unit: GlucoseUnit) -> rusqlite::Result<()> {
+    conn.execute("UPDATE users SET glucose_unit = ?2 WHERE id = ?1", params![user_id, unit.label()])?;
+    tracing::info!(target: SECURITY_TARGET, user_id = %user_id, unit = unit.label(), "Glucose unit preference changed");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    #[test]
+    fn values_convert_both_ways_and_stored_texts_are_localized() {
+        assert_eq!(GlucoseUnit::parse("MMOL/L"), Some(GlucoseUnit::MmolL));
+        assert_eq!(GlucoseUnit::parse("mgdl"), Some(GlucoseUnit::MgDl));
+        assert_eq!(GlucoseUnit::parse("g/L"), None);
+        assert_eq!(GlucoseUnit::MmolL.format(126.0), "7.0 mmol/L");
+        assert_eq!(GlucoseUnit::MgDl.format(126.4), "126 mg/dL");
+        assert!((GlucoseUnit::MmolL.to_mg_dl(3.9) - 70.2).abs() < 1e-9);
+
+        let message = "Bolus refused: latest glucose 54 mg/dL is below the low threshold of 70 mg/dL";
+        assert_eq!(GlucoseUnit::MgDl.localize(message), message);
+        assert_eq!(
+            GlucoseUnit::MmolL.localize(message),
+            "Bolus refused: latest glucose 3.0 mmol/L is below the low threshold of 3.9 mmol/L"
+        );
+        assert_eq!(GlucoseUnit::MmolL.localize("Time in range (70-180 mg/dL): 80.0%"), "Time in range (3.9-10.0 mmol/L): 80.0%");
+    }
+
+    #[test]
+    fn preference_defaults_to_mg_dl_and_can_be_changed() {
+        let conn = test_utils::test_db();
+        let fixtures = test_utils::seed(&conn);
+        assert_eq!(user_unit(&conn, &fixtures.clinician_id).unwrap(), GlucoseUnit::MgDl);
+        set_user_unit(&conn, &fixtures.clinician_id, GlucoseUnit::MmolL).unwrap();
+        assert_eq!(user_unit(&conn, &fixtures.clinician_id).unwrap(), GlucoseUnit::MmolL);
+        assert_eq!(user_unit(&conn, "no-such-user").unwrap(), GlucoseUnit::MgDl);
+    }
+}
@@ -1,119 +1,471 @@
-use std::io::{self, Write};
-use crate::database::Database;
-use chrono::{NaiveDate, Utc, Datelike}; // Used for date handling voter birthday etc
-
-
-
-
-/// Admin menu which alows admins to create elections, register voters, or log out.
-pub fn handle_menu() -> bool {
-    let db = Database::new("e_voting.db").expect("Failed to initialize database");
-
-
-    loop {
-        println!("\n--- Election Admin Menu ---");
-        println!("1. Create New Election");
-        println!("2. Register New Voter");
-        println!("3. Logout");
-
-
-        let choice = get_input("Select an option: ");
-
-
-        match choice.trim() {
-            "1" => create_election(&db),
-            "2" => register_voter(&db),
-            "3" => return false,
-            _ => println!("Invalid option"),
-        }
-    }
-}
-
-
-/// Create a new election with positions and candidates + party
-fn create_election(db: &Database) {
-    let election_name = get_input("Enter election name: ");
-    let election_id = db.create_election(&election_name).expect("Failed to create election");
-
-
-    println!("Enter 3 positions for this election:");
-    let mut position_ids = Vec::new();
-
-
-    // Collect position names
-    for i in 1..=3 {
-        let pos_name = get_input(&format!("Position {} name: ", i));
-        let pos_id = db.add_position(election_id, &pos_name).expect("Failed to add position");
-        position_ids.push(pos_id);
-    }
-
-
-    // Collect candidates and party names for each position
-    for (i, &pos_id) in position_ids.iter().enumerate() {
-        println!("Enter 2 candidates for position {}:", i + 1);
-        for j in 1..=2 {
-            let cand_name = get_input(&format!("Candidate {} name: ", j));
-            let party_name = get_input(&format!("Candidate {} party: ", j));
-            db.add_candidate_with_party(pos_id, &cand_name, &party_name).expect("Failed to add candidate");
-            println!("✅ Candidate '{}' from party '{}' added.", cand_name, party_name);
-        }
-    }
-
-
-    println!("✅ Election created successfully!");
-}
-
-
-
-
-/// Register a new voter
-fn register_voter(db: &Database) {
-    let full_name = get_input("Enter full name: ");
-    let dob_input = get_input("Enter date of birth (YYYY-MM-DD): ");
-
-
-    // Validate DOB and age
-    let dob = match validate_dob(&dob_input) {
-        Some(date) => date.format("%Y-%m-%d").to_string(),
-        None => return, // invalid DOB
-    };
-
-
-    match db.register_voter(&full_name, &dob) {
-        Ok(true) => println!("✅ Voter registered successfully."),
-        Ok(false) => println!("Registration failed. Please recheck credentials."),
-        Err(e) => println!("❌ Failed to register voter: {}", e),
-    }
-}
-
-
-/// Validate DOB is in YYYY-MM-DD format and age >= 18
-fn validate_dob(dob_input: &str) -> Option<NaiveDate> {
-    match NaiveDate::parse_from_str(dob_input, "%Y-%m-%d") {
-        Ok(date) => {
-            let today = Utc::now().date_naive();
-            let age = today.year() - date.year()
-                - if (today.month(), today.day()) < (date.month(), date.day()) { 1 } else { 0 };
-            if age >= 18 {
-                Some(date)
-            } else {
-                println!("❌ Voter must be at least 18 years old.");
-                None
-            }
-        }
-        Err(_) => {
-            println!("❌ Invalid date format. Please use YYYY-MM-DD.");
-            None
-        }
-    }
-}
-
-
-/// Helper function to get input from user
-fn get_input(prompt: &str) -> String {
-    print!("{}", prompt);
-    io::stdout().flush().unwrap();
-    let mut input = String::new();
-    io::stdin().read_line(&mut input).unwrap();
-    input.trim().to_string()
+use std::io::{self, Write};
+use crate::database::{self, Database};
+use crate::voter::{print_registration_receipt, read_new_pin};
+use crate::voter_roll;
+use crate::auth::{self, ROLES};
+use chrono::{NaiveDate, Utc, Datelike}; // Used for date handling voter birthday etc
+
+
+
+
+/// Admin menu which alows admins to create elections, register voters, manage official accounts, or log out.
+/// `username` is the logged in admin, who cannot disable their own account.
+pub fn handle_menu(username: &str) -> bool {
+    let db = Database::new("e_voting.db").expect("Failed to initialize database");
+
+
+    loop {
+        println!("\n--- Election Admin Menu ---");
+        println!("1. Create New Election");
+        println!("2. Register New Voter");
+        println!("3. Create Official Account");
+        println!("4. List Official Accounts");
+        println!("5. Disable / Enable Official Account");
+        println!("6. Edit Election Ballot");
+        println!("7. Manage Districts");
+        println!("8. Look Up Voter Registration Number");
+        println!("9. Import Voters from CSV");
+        println!("10. Export Voter Roll");
+        println!("11. Logout");
+
+
+        let choice = get_input("Select an option: ");
+
+
+        match choice.trim() {
+            "1" => create_election(&db),
+            "2" => register_voter(&db),
+            "3" => create_official(&db),
+            "4" => list_officials(&db),
+            "5" => toggle_official(&db, username),
+            "6" => edit_election(&db),
+            "7" => manage_districts(&db),
+            "8" => look_up_registration(&db),
+            "9" => import_voters(&db),
+            "10" => voter_roll::handle_export(&db),
+            "11" => return false,
+            _ => println!("Invalid option"),
+        }
+    }
+}
+
+
+/// Create a new election with positions and candidates + party.
+/// Plurality elections elect the candidate with the most votes; in ranked-choice elections voters
+/// order the candidates and the count is by instant runoff.
+fn create_election(db: &Database) {
+    let election_name = get_input("Enter election name: ");
+    println!("Election type:");
+    println!("1. Plurality (one choice per position)");
+    println!("2. Ranked choice (instant runoff)");
+    let election_type = loop {
+        match get_input("Select a type: ").as_str() {
+            "1" => break "plurality",
+            "2" => break "ranked",
+            _ => println!("Invalid option"),
+        }
+    };
+    let district_id = get_district(db, "District holding this election (leave blank for all districts): ");
+    let position_count = get_count("How many positions does this election have? ");
+    let election_id = db.create_election(&election_name, election_type, district_id).expect("Failed to create election");
+
+
+    println!("Enter {} positions for this election:", position_count);
+    let mut position_ids = Vec::new();
+
+
+    // Collect position names
+    for i in 1..=position_count {
+        let pos_name = get_input(&format!("Position {} name: ", i));
+        // A position of an election held in one district belongs to that district
+        let pos_district = match district_id {
+            Some(_) => district_id,
+            None => get_district(db, "District voting for this position (leave blank for every district): "),
+        };
+        let pos_id = db.add_position(election_id, &pos_name, pos_district).expect("Failed to add position");
+        position_ids.push(pos_id);
+    }
+
+
+    // Collect candidates and party names for each position
+    for (i, &pos_id) in position_ids.iter().enumerate() {
+        let candidate_count = get_count(&format!("How many candidates for position {}? ", i + 1));
+        println!("Enter {} candidates for position {}:", candidate_count, i + 1);
+        for j in 1..=candidate_count {
+            let cand_name = get_input(&format!("Candidate {} name: ", j));
+            let party_name = get_input(&format!("Candidate {} party: ", j));
+            db.add_candidate_with_party(pos_id, &cand_name, &party_name).expect("Failed to add candidate");
+            println!("✅ Candidate '{}' from party '{}' added.", cand_name, party_name);
+        }
+    }
+
+
+    println!("✅ Election created successfully!");
+}
+
+
+
+
+/// Rename or remove positions and candidates of an election before it opens.
+/// Once the election is open or has votes, its ballot is locked.
+fn edit_election(db: &Database) {
+    match db.list_elections() {
+        Ok(elections) => {
+            println!("ID | Name | Status");
+            for (id, name, status) in elections {
+                println!("{} | {} | {}", id, name, status);
+            }
+        }
+        Err(e) => {
+            println!("❌ Failed to list elections: {}", e);
+            return;
+        }
+    }
+    let election_id = match get_input("Enter election ID to edit: ").parse::<i64>() {
+        Ok(id) => id,
+        Err(_) => {
+            println!("❌ Invalid election ID.");
+            return;
+        }
+    };
+
+    loop {
+        match db.is_ballot_locked(election_id) {
+            Ok(false) => {}
+            Ok(true) => {
+                println!("❌ Election {} is open or has votes; its ballot can no longer be changed.", election_id);
+                return;
+            }
+            Err(_) => {
+                println!("❌ No election with ID {}.", election_id);
+                return;
+            }
+        }
+        if !show_ballot(db, election_id) {
+            return;
+        }
+
+        println!("\n--- Edit Ballot ---");
+        println!("1. Rename Position");
+        println!("2. Remove Position");
+        println!("3. Edit Candidate");
+        println!("4. Remove Candidate");
+        println!("5. Back");
+
+        let result = match get_input("Select an option: ").as_str() {
+            "1" => {
+                let position_id = get_input("Enter position ID: ").parse::<i64>().unwrap_or(-1);
+                let name = get_input("New position name: ");
+                db.rename_position(election_id, position_id, &name)
+            }
+            "2" => {
+                let position_id = get_input("Enter position ID: ").parse::<i64>().unwrap_or(-1);
+                db.delete_position(election_id, position_id)
+            }
+            "3" => {
+                let candidate_id = get_input("Enter candidate ID: ").parse::<i64>().unwrap_or(-1);
+                let name = get_input("New candidate name: ");
+                let party = get_input("New candidate party: ");
+                db.update_candidate(election_id, candidate_id, &name, &party)
+            }
+            "4" => {
+                let candidate_id = get_input("Enter candidate ID: ").parse::<i64>().unwrap_or(-1);
+                db.delete_candidate(election_id, candidate_id)
+            }
+            "5" => return,
+            _ => {
+                println!("Invalid option");
+                continue;
+            }
+        };
+        match result {
+            Ok(true) => println!("✅ Ballot updated."),
+            Ok(false) => println!("❌ This election has no position or candidate with that ID."),
+            Err(e) => println!("❌ Failed to update ballot: {}", e),
+        }
+    }
+}
+
+
+/// Print the positions and candidates of an election with their IDs; returns false if they cannot be read
+fn show_ballot(db: &Database, election_id: i64) -> bool {
+    let positions = match db.list_positions(election_id) {
+        Ok(positions) => positions,
+        Err(e) => {
+            println!("❌ Failed to list positions: {}", e);
+            return false;
+        }
+    };
+    println!("\nBallot of election {}:", election_id);
+    for (position_id, position) in positions {
+        println!("Position {}: {}", position_id, position);
+        match db.list_candidates(position_id) {
+            Ok(candidates) => {
+                for (candidate_id, name, party) in candidates {
+                    println!("  Candidate {}: {} (party: {})", candidate_id, name, party);
+                }
+            }
+            Err(e) => {
+                println!("❌ Failed to list candidates: {}", e);
+                return false;
+            }
+        }
+    }
+    true
+}
+
+
+/// Register a new voter
+fn register_voter(db: &Database) {
+    let full_name = get_input("Enter full name: ");
+    let dob_input = get_input("Enter date of birth (YYYY-MM-DD): ");
+
+
+    // Validate DOB and age
+    let dob = match validate_dob(&dob_input) {
+        Some(date) => date.format("%Y-%m-%d").to_string(),
+        None => return, // invalid DOB
+    };
+    let district_id = get_district(db, "Voter's district (leave blank if none): ");
+    let pin_hash = match read_new_pin() {
+        Ok(pin_hash) => pin_hash,
+        Err(e) => {
+            println!("❌ {}", e);
+            return;
+        }
+    };
+
+
+    match db.register_voter(&full_name, &dob, district_id, pin_hash.as_deref(), None) {
+        Ok(Some(registration_number)) => {
+            println!("✅ Voter registered successfully.");
+            print_registration_receipt(&full_name, &registration_number);
+        }
+        Ok(None) => println!("❌ A voter with this name and date of birth is already registered."),
+        Err(e) => println!("❌ Failed to register voter: {}", e),
+    }
+}
+
+
+/// Re-issue the registration receipt of a voter who lost their number, found by name and date of birth.
+/// Voters registered before registration numbers existed get theirs this way too.
+fn look_up_registration(db: &Database) {
+    let full_name = get_input("Voter's full name: ");
+    let dob = get_input("Voter's date of birth (YYYY-MM-DD): ");
+    match db.find_registration_number(&full_name, &dob) {
+        Ok(Some(registration_number)) => print_registration_receipt(&full_name, &registration_number),
+        Ok(None) => println!("❌ No registered voter with that name and date of birth."),
+        Err(e) => println!("❌ Failed to look up voter: {}", e),
+    }
+}
+
+
+/// Import a voter roll from CSV and report what was imported, what was skipped and why.
+/// The registration numbers issued are written to a CSV file next to the roll.
+fn import_voters(db: &Database) {
+    let path = get_input("Path of the CSV file (columns name, dob, district, id): ");
+    let summary = match voter_roll::import_csv(db, &path) {
+        Ok(summary) => summary,
+        Err(e) => {
+            println!("❌ {}", e);
+            return;
+        }
+    };
+
+    println!("\n--- Import Summary ---");
+    println!("Rows read: {}", summary.rows);
+    println!("Imported: {}", summary.imported.len());
+    println!("Duplicates skipped: {}", summary.duplicates.len());
+    for (row, reason) in &summary.duplicates {
+        println!("  Row {}: {}", row, reason);
+    }
+    println!("Invalid rows skipped: {}", summary.invalid.len());
+    for (row, reason) in &summary.invalid {
+        println!("  Row {}: {}", row, reason);
+    }
+
+    if summary.imported.is_empty() {
+        return;
+    }
+    match voter_roll::write_registration_numbers(&path, &summary) {
+        Ok(out) => println!("✅ Registration numbers of imported voters written to {}. Keep it private: with a date of birth, a number lets a voter log in.", out),
+        Err(e) => println!("❌ {}", e),
+    }
+}
+
+
+/// Create and list districts, and assign registered voters to them
+fn manage_districts(db: &Database) {
+    loop {
+        println!("\n--- Districts ---");
+        println!("1. Create District");
+        println!("2. List Districts");
+        println!("3. Assign Voter to District");
+        println!("4. Back");
+
+        match get_input("Select an option: ").as_str() {
+            "1" => {
+                let name = get_input("District name: ");
+                if name.is_empty() {
+                    println!("❌ District name cannot be empty.");
+                    continue;
+                }
+                match db.create_district(&name) {
+                    Ok(true) => println!("✅ District '{}' created.", name),
+                    Ok(false) => println!("❌ District '{}' already exists.", name),
+                    Err(e) => println!("❌ Failed to create district: {}", e),
+                }
+            }
+            "2" => match db.list_districts() {
+                Ok(districts) => {
+                    println!("ID | Name");
+                    for (id, name) in districts {
+                        println!("{} | {}", id, name);
+                    }
+                }
+                Err(e) => println!("❌ Failed to list districts: {}", e),
+            },
+            "3" => {
+                let registration_number = database::normalize_registration_number(&get_input("Voter's registration number: "));
+                let dob = get_input("Voter's date of birth (YYYY-MM-DD): ");
+                let voter_id = match db.get_voter_id(&registration_number, &dob) {
+                    Ok(Some(id)) => id,
+                    Ok(None) => {
+                        println!("❌ No registered voter with that registration number and date of birth.");
+                        continue;
+                    }
+                    Err(e) => {
+                        println!("❌ Failed to look up voter: {}", e);
+                        continue;
+                    }
+                };
+                match get_district(db, "New district: ") {
+                    Some(district_id) => match db.set_voter_district(voter_id, district_id) {
+                        Ok(_) => println!("✅ Voter assigned to the district."),
+                        Err(e) => println!("❌ Failed to assign voter: {}", e),
+                    },
+                    None => println!("No district chosen; the voter was not changed."),
+                }
+            }
+            "4" => return,
+            _ => println!("Invalid option"),
+        }
+    }
+}
+
+
+/// Ask for a district by name until an existing one or a blank answer (no district) is given.
+/// Without any districts there is nothing to choose and nobody is asked.
+fn get_district(db: &Database, prompt: &str) -> Option<i64> {
+    let districts = db.list_districts().unwrap_or_default();
+    if districts.is_empty() {
+        return None;
+    }
+    let names: Vec<&str> = districts.iter().map(|(_, name)| name.as_str()).collect();
+    println!("Districts: {}", names.join(", "));
+    loop {
+        let name = get_input(prompt);
+        if name.is_empty() {
+            return None;
+        }
+        match districts.iter().find(|(_, n)| *n == name) {
+            Some((id, _)) => return Some(*id),
+            None => println!("❌ Unknown district '{}'.", name),
+        }
+    }
+}
+
+
+/// Create an admin, district official or audit account
+fn create_official(db: &Database) {
+    let username = get_input("Enter username: ");
+    let role = get_input(&format!("Enter role ({}): ", ROLES.join(", ")));
+    match auth::create_account(db, &username, &role) {
+        Ok(()) => println!("✅ {} account '{}' created.", role, username),
+        Err(e) => println!("❌ {}", e),
+    }
+}
+
+
+/// List official accounts with their role and whether they can log in
+fn list_officials(db: &Database) {
+    match db.list_users() {
+        Ok(users) => {
+            println!("Username | Role | Status");
+            for (username, role, active) in users {
+                println!("{} | {} | {}", username, role, if active { "active" } else { "disabled" });
+            }
+        }
+        Err(e) => println!("❌ Failed to list accounts: {}", e),
+    }
+}
+
+
+/// Disable an active account or enable a disabled one.
+/// Admins cannot disable themselves, so there is always an admin left to log in.
+fn toggle_official(db: &Database, current_admin: &str) {
+    let username = get_input("Enter username: ");
+    if username == current_admin {
+        println!("❌ You cannot disable your own account.");
+        return;
+    }
+    let active = match db.get_user(&username) {
+        Ok(Some((_, _, active))) => active,
+        Ok(None) => {
+            println!("❌ No account named '{}'.", username);
+            return;
+        }
+        Err(e) => {
+            println!("❌ Failed to load account: {}", e);
+            return;
+        }
+    };
+    match db.set_user_active(&username, !active) {
+        Ok(_) if active => println!("✅ Account '{}' disabled.", username),
+        Ok(_) => println!("✅ Account '{}' enabled.", username),
+        Err(e) => println!("❌ Failed to update account: {}", e),
+    }
+}
+
+
+/// Validate DOB is in YYYY-MM-DD format and age >= 18
+fn validate_dob(dob_input: &str) -> Option<NaiveDate> {
+    match NaiveDate::parse_from_str(dob_input, "%Y-%m-%d") {
+        Ok(date) => {
+            let today = Utc::now().date_naive();
+            let age = today.year() - date.year()
+                - if (today.month(), today.day()) < (date.month(), date.day()) { 1 } else { 0 };
+            if age >= 18 {
+                Some(date)
+            } else {
+                println!("❌ Voter must be at least 18 years old.");
+                None
+            }
+        }
+        Err(_) => {
+            println!("❌ Invalid date format. Please use YYYY-MM-DD.");
+            None
+        }
+    }
+}
+
+
+/// Ask for a number of positions or candidates until a whole number of at least 1 is given
+fn get_count(prompt: &str) -> usize {
+    loop {
+        match get_input(prompt).parse::<usize>() {
+            Ok(count) if count >= 1 => return count,
+            _ => println!("❌ Please enter a whole number of at least 1."),
+        }
+    }
+}
+
+
+/// Helper function to get input from user
+fn get_input(prompt: &str) -> String {
+    print!("{}", prompt);
+    io::stdout().flush().unwrap();
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).unwrap();
+    input.trim().to_string()
 }
\ No newline at end of file
@@ -1,5 +1,11 @@
-use rusqlite::{params, Connection};
+use rusqlite::{params, Connection, OptionalExtension};
 use chrono::Local;
+use sha2::{Digest, Sha256};
+use ed25519_dalek::VerifyingKey;
+use crate::signing;
+
+// prev_hash of the first record in the chain
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
 
 // Function to create the audit_log table if it doesn't already exist
 pub fn setup_audit_table(conn: &Connection) {
@@ -7,40 +13,220 @@ pub fn setup_audit_table(conn: &Connection) {
         "CREATE TABLE IF NOT EXISTS audit_log (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
             voter_name TEXT,
-            candidate_name TEXT,
+            detail TEXT,
             action TEXT,
-            timestamp TEXT
+            timestamp TEXT,
+            prev_hash TEXT,
+            entry_hash TEXT,
+            signature TEXT
         )",
         [], // No parameters needed for table creation
     ).unwrap();
+
+    // Older logs named the candidate each voter chose, which would undo ballot secrecy.
+    // Rename the column and keep only that a vote was cast.
+    let has_candidate_column: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM pragma_table_info('audit_log') WHERE name = 'candidate_name')",
+        [],
+        |r| r.get(0),
+    ).unwrap();
+    if has_candidate_column {
+        conn.execute_batch(
+            "ALTER TABLE audit_log RENAME COLUMN candidate_name TO detail;
+             UPDATE audit_log SET detail = '(selection not recorded)' WHERE action = 'vote_cast';"
+        ).unwrap();
+    }
+
+    // Records written before signing was added are signed once, here, with the authority key.
+    // The column is added first because the hash chain backfill below reads it.
+    let has_signature: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM pragma_table_info('audit_log') WHERE name = 'signature')",
+        [],
+        |r| r.get(0),
+    ).unwrap();
+    if !has_signature {
+        conn.execute("ALTER TABLE audit_log ADD COLUMN signature TEXT", []).unwrap();
+    }
+
+    // Logs written before the hash chain get one now, starting from their first record.
+    // Changes made to those records before this point cannot be detected.
+    let has_chain: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM pragma_table_info('audit_log') WHERE name = 'entry_hash')",
+        [],
+        |r| r.get(0),
+    ).unwrap();
+    if !has_chain {
+        conn.execute_batch(
+            "ALTER TABLE audit_log ADD COLUMN prev_hash TEXT;
+             ALTER TABLE audit_log ADD COLUMN entry_hash TEXT;"
+        ).unwrap();
+        let mut prev = GENESIS_HASH.to_string();
+        for record in read_records(conn) {
+            let hash = entry_hash(&prev, &record);
+            conn.execute(
+                "UPDATE audit_log SET prev_hash = ?1, entry_hash = ?2 WHERE id = ?3",
+                params![prev, hash, record.id],
+            ).unwrap();
+            prev = hash;
+        }
+    }
+
+    // Create the authority key pair with the log, so its public key can be published before the first record
+    signing::authority_public_key().unwrap();
+
+    if !has_signature {
+        for record in read_records(conn) {
+            let signature = signing::sign(record.entry_hash.unwrap_or_default().as_bytes()).unwrap();
+            conn.execute(
+                "UPDATE audit_log SET signature = ?1 WHERE id = ?2",
+                params![signature, record.id],
+            ).unwrap();
+        }
+    }
+}
+
+// One row of the audit log
+struct AuditRecord {
+    id: i64,
+    voter_name: Option<String>,
+    detail: Option<String>,
+    action: Option<String>,
+    timestamp: Option<String>,
+    prev_hash: Option<String>,
+    entry_hash: Option<String>,
+    signature: Option<String>,
+}
+
+// All records in the order they were written
+fn read_records(conn: &Connection) -> Vec<AuditRecord> {
+    let mut stmt = conn.prepare(
+        "SELECT id, voter_name, detail, action, timestamp, prev_hash, entry_hash, signature FROM audit_log ORDER BY id"
+    ).unwrap();
+    let rows = stmt.query_map([], |r| {
+        Ok(AuditRecord {
+            id: r.get(0)?,
+            voter_name: r.get(1)?,
+            detail: r.get(2)?,
+            action: r.get(3)?,
+            timestamp: r.get(4)?,
+            prev_hash: r.get(5)?,
+            entry_hash: r.get(6)?,
+            signature: r.get(7)?,
+        })
+    }).unwrap();
+    rows.map(|row| row.unwrap()).collect()
 }
 
-// Function to log a vote into the audit_log table
-pub fn log_vote(conn: &Connection, voter: &str, candidate: &str) {
+// SHA-256 over the previous record's hash and this record's fields.
+// Each field is length-prefixed so moving text between fields changes the hash.
+fn entry_hash(prev_hash: &str, record: &AuditRecord) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(prev_hash.as_bytes());
+    for field in [&record.voter_name, &record.detail, &record.action, &record.timestamp] {
+        match field {
+            Some(text) => hasher.update(format!("|{}:{}", text.len(), text).as_bytes()),
+            None => hasher.update(b"|-"),
+        }
+    }
+    hex::encode(hasher.finalize())
+}
+
+// Append a record to the chain: it stores the hash of the record before it, its own hash,
+// and the authority's signature over its hash
+fn append(conn: &Connection, voter: &str, detail: &str, action: &str) {
     // Get current timestamp in "YYYY-MM-DD HH:MM:SS" format
     let ts = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
 
+    // The previous hash is read and the record written in one transaction, so two writers cannot fork the chain
+    let tx = conn.unchecked_transaction().unwrap();
+    let prev: String = tx.query_row(
+        "SELECT entry_hash FROM audit_log ORDER BY id DESC LIMIT 1",
+        [],
+        |r| r.get::<_, Option<String>>(0),
+    ).optional().unwrap().flatten().unwrap_or_else(|| GENESIS_HASH.to_string());
+    let record = AuditRecord {
+        id: 0,
+        voter_name: Some(voter.to_string()),
+        detail: Some(detail.to_string()),
+        action: Some(action.to_string()),
+        timestamp: Some(ts),
+        prev_hash: None,
+        entry_hash: None,
+        signature: None,
+    };
+    let hash = entry_hash(&prev, &record);
+    let signature = signing::sign(hash.as_bytes()).unwrap();
+
     // Insert a new record into audit_log
-    conn.execute(
-        "INSERT INTO audit_log (voter_name, candidate_name, action, timestamp)
-         VALUES (?1, ?2, 'vote_cast', ?3)",
-        params![voter, candidate, ts], // Bind parameters to prevent SQL injection
+    tx.execute(
+        "INSERT INTO audit_log (voter_name, detail, action, timestamp, prev_hash, entry_hash, signature)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![record.voter_name, record.detail, record.action, record.timestamp, prev, hash, signature], // Bind parameters to prevent SQL injection
     ).unwrap();
+    tx.commit().unwrap();
+}
+
+// Function to log a vote into the audit_log table.
+// Only the position is recorded, never the candidate, so the log cannot reveal how anyone voted.
+pub fn log_vote(conn: &Connection, voter: &str, position: &str) {
+    append(conn, voter, position, "vote_cast");
+}
+
+// Walk the hash chain from the first record and check each signature against the authority's public key.
+// Returns the number of records checked, or the id of the first record that does not fit the chain and why.
+pub fn verify_chain(conn: &Connection, public_key: &VerifyingKey) -> Result<usize, (i64, String)> {
+    let records = read_records(conn);
+    let mut prev = GENESIS_HASH.to_string();
+    for record in &records {
+        if record.prev_hash.as_deref() != Some(prev.as_str()) {
+            return Err((record.id, "does not follow the record before it (a record was removed, inserted or reordered)".to_string()));
+        }
+        let hash = entry_hash(&prev, record);
+        if record.entry_hash.as_deref() != Some(hash.as_str()) {
+            return Err((record.id, "its contents were changed after it was written".to_string()));
+        }
+        if !signing::verify(public_key, hash.as_bytes(), record.signature.as_deref().unwrap_or_default()) {
+            return Err((record.id, "is not signed by the election authority".to_string()));
+        }
+        prev = hash;
+    }
+    Ok(records.len())
+}
+
+// Print the result of verify_chain, using the public key stored at public_key_path
+pub fn report_chain(conn: &Connection, public_key_path: &str) -> bool {
+    let public_key = match signing::read_public_key(public_key_path) {
+        Ok(key) => key,
+        Err(e) => {
+            println!("❌ Cannot verify audit log: {}", e);
+            return false;
+        }
+    };
+    match verify_chain(conn, &public_key) {
+        Ok(count) => {
+            println!("✅ Audit log intact: {} records, hash chain and signatures verified.", count);
+            true
+        }
+        Err((id, reason)) => {
+            println!("❌ Audit log tampered: record {} {}.", id, reason);
+            false
+        }
+    }
 }
 
 // Function to display all records from audit_log
 pub fn show_audit_log(conn: &Connection) {
     // Prepare a SELECT statement to fetch all audit logs in descending order
     let mut stmt = conn.prepare(
-        "SELECT voter_name, candidate_name, action, timestamp FROM audit_log ORDER BY id DESC"
+        "SELECT voter_name, detail, action, timestamp FROM audit_log ORDER BY id DESC"
     ).unwrap();
 
     // Execute the query and map each row to a tuple
     let rows = stmt.query_map([], |r| {
         Ok((
-            r.get::<_, String>(0)?, 
-            r.get::<_, String>(1)?, 
-            r.get::<_, String>(2)?, 
+            r.get::<_, String>(0)?,
+            r.get::<_, String>(1)?,
+            r.get::<_, String>(2)?,
             r.get::<_, String>(3)?
         ))
     }).unwrap();
@@ -49,7 +235,10 @@ pub fn show_audit_log(conn: &Connection) {
 
     // Iterate over the results and print them
     for row in rows {
-        let (voter, cand, action, ts) = row.unwrap();
-        println!("{ts}: {voter} -> {cand} [{action}]");
+        let (voter, detail, action, ts) = row.unwrap();
+        println!("{ts}: {voter} -> {detail} [{action}]");
     }
-}
\ No newline at end of file
+
+    // Tell the reader whether what they just saw can be trusted
+    report_chain(conn, signing::PUBLIC_KEY_PATH);
+}
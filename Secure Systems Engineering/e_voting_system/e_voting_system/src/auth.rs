@@ -1,45 +1,154 @@
-use sha2::{Sha256, Digest};
-use std::collections::HashMap;
-use rpassword::read_password;
-
-
-/// Simple auth module with hashed credentials
-pub struct Auth {
-    users: HashMap<String, String>, // username -> hashed password
-}
-
-
-impl Auth {
-    pub fn new() -> Self {
-        let mut users = HashMap::new();
-
-
-        // Add Admin, District and audit log with hashed passwords
-        users.insert("admin".to_string(), hash_password("pwd123"));
-        users.insert("district".to_string(), hash_password("pwd123"));
-        users.insert("audit".to_string(), hash_password("pwd123"));
-
-        Auth { users }
-    }
-
-
-    /// Login with username and password (returns true if correct)
-    pub fn login(&self, username: &str) -> bool {
-        if let Some(stored_hash) = self.users.get(username) {
-            println!("Password: ");
-            let password = read_password().unwrap_or_default();
-            let password_hash = hash_password(&password);
-            return &password_hash == stored_hash;
-        }
-        false
-    }
-}
-
-
-/// Hash a password using SHA-256
-fn hash_password(password: &str) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(password.as_bytes());
-    let result = hasher.finalize();
-    hex::encode(result)
-}
\ No newline at end of file
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use rpassword::read_password;
+use std::io::{self, Write};
+use crate::database::Database;
+
+
+/// Roles an election official account can have
+pub const ROLES: [&str; 3] = ["admin", "district", "audit"];
+
+/// Shortest password accepted for an official account
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Argon2 hash with the default parameters, checked when a username does not exist
+/// so that rejecting an unknown account takes as long as rejecting a wrong password
+const DUMMY_PASSWORD_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$zGZtdQcWoVIAP8TezRy/kQ$g13jAx4ih+Qrx7Dd+SYTVKXkIzOkublnZX2uuzmWq0o";
+
+
+/// Auth module backed by the `users` table, passwords stored as salted Argon2 hashes
+pub struct Auth {
+    db: Database,
+}
+
+
+impl Auth {
+    pub fn new() -> Self {
+        let db = Database::new("e_voting.db").expect("Failed to initialize database");
+        Auth { db }
+    }
+
+
+    /// On a fresh database there is nobody to log in as, so the first admin account is created here
+    pub fn ensure_admin(&self) {
+        match self.db.count_active_users("admin") {
+            Ok(0) => {}
+            Ok(_) => return,
+            Err(e) => {
+                println!("❌ Failed to check official accounts: {}", e);
+                return;
+            }
+        }
+
+        println!("\nNo active election admin account exists. Create one now.");
+        loop {
+            let username = get_input("Admin username: ");
+            if let Err(e) = create_account(&self.db, &username, "admin") {
+                println!("❌ {}", e);
+                continue;
+            }
+            println!("✅ Admin account '{}' created.", username);
+            return;
+        }
+    }
+
+
+    /// Login with username and password for the given role.
+    /// Returns the username when the account exists, is active, has the role and the password is correct.
+    pub fn login(&self, role: &str) -> Option<String> {
+        let username = get_input("Username: ");
+        println!("Password: ");
+        let password = read_password().unwrap_or_default();
+
+        let (stored_hash, user_role, active) = match self.db.get_user(&username) {
+            Ok(Some(user)) => user,
+            Ok(None) => {
+                verify_password(&password, DUMMY_PASSWORD_HASH);
+                return None;
+            }
+            Err(e) => {
+                println!("❌ Failed to check credentials: {}", e);
+                return None;
+            }
+        };
+        if !verify_password(&password, &stored_hash) || user_role != role || !active {
+            return None;
+        }
+        Some(username)
+    }
+}
+
+
+/// Ask for a password twice and store a new official account with it
+pub fn create_account(db: &Database, username: &str, role: &str) -> Result<(), String> {
+    if username.is_empty() || username.len() > 32 || !username.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-') {
+        return Err("Username must be 1-32 letters, digits, '.', '_' or '-'.".to_string());
+    }
+    if !ROLES.contains(&role) {
+        return Err(format!("Unknown role '{}'.", role));
+    }
+
+    println!("Password (at least {} characters): ", MIN_PASSWORD_LENGTH);
+    let password = read_password().unwrap_or_default();
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(format!("Password must be at least {} characters.", MIN_PASSWORD_LENGTH));
+    }
+    println!("Repeat password: ");
+    if read_password().unwrap_or_default() != password {
+        return Err("Passwords do not match.".to_string());
+    }
+
+    let password_hash = hash_password(&password)?;
+    match db.create_user(username, &password_hash, role) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(format!("Username '{}' is already taken.", username)),
+        Err(e) => Err(format!("Failed to create account: {}", e)),
+    }
+}
+
+
+/// Hash a password with Argon2 and a random salt, in PHC string format
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| format!("Failed to hash password: {}", e))
+}
+
+
+/// Check a password against a stored Argon2 hash
+pub fn verify_password(password: &str, stored_hash: &str) -> bool {
+    match PasswordHash::new(stored_hash) {
+        Ok(parsed) => Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok(),
+        Err(_) => false,
+    }
+}
+
+
+/// Helper function to get trimmed input from user
+fn get_input(prompt: &str) -> String {
+    print!("{}", prompt);
+    io::stdout().flush().unwrap();
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).unwrap();
+    input.trim().to_string()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dummy_hash_costs_as_much_as_a_real_one() {
+        let real_hash = hash_password("correct horse battery").unwrap();
+        let real = PasswordHash::new(&real_hash).unwrap();
+        let dummy = PasswordHash::new(DUMMY_PASSWORD_HASH).unwrap();
+        assert_eq!(dummy.algorithm, real.algorithm);
+        assert_eq!(dummy.version, real.version);
+        assert_eq!(dummy.params, real.params);
+        assert!(!verify_password("correct horse battery", DUMMY_PASSWORD_HASH));
+    }
+}
@@ -0,0 +1,124 @@
+use aes_gcm::aead::{Aead, KeyInit, OsRng, Payload};
+use aes_gcm::{AeadCore, Aes256Gcm, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use rand::RngCore;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+
+/// File holding the master key that wraps every election key.
+/// It is kept outside the database, so a database dump alone cannot be decrypted.
+pub const MASTER_KEY_PATH: &str = "ballot_master.key";
+
+const NONCE_LEN: usize = 12;
+
+
+/// Fresh random AES-256 key for one election
+pub fn new_election_key() -> [u8; 32] {
+    let mut key = [0u8; 32];
+    OsRng.fill_bytes(&mut key);
+    key
+}
+
+
+/// Encrypt an election key under the master key for storage in the database
+pub fn wrap_key(election_key: &[u8; 32]) -> Result<String, String> {
+    seal(&master_key()?, election_key, b"election-key")
+}
+
+
+/// Decrypt an election key stored with `wrap_key`
+pub fn unwrap_key(wrapped: &str) -> Result<[u8; 32], String> {
+    let key = open(&master_key()?, wrapped, b"election-key")?;
+    key.try_into().map_err(|_| "Stored election key has the wrong length.".to_string())
+}
+
+
+/// Encrypt the choices of a ballot with the election key: one candidate for a plurality ballot,
+/// candidates in order of preference for a ranked ballot.
+/// The ballot id, election and position are authenticated too, so a sealed choice cannot be moved to another ballot.
+pub fn seal_choice(election_key: &[u8; 32], ballot_id: &str, election_id: i64, position_id: i64, candidate_ids: &[i64]) -> Result<String, String> {
+    let aad = ballot_aad(ballot_id, election_id, position_id);
+    let plain: Vec<u8> = candidate_ids.iter().flat_map(|id| id.to_be_bytes()).collect();
+    seal(election_key, &plain, aad.as_bytes())
+}
+
+
+/// Decrypt the candidates of a ballot sealed with `seal_choice`, in the order they were sealed
+pub fn open_choice(election_key: &[u8; 32], ballot_id: &str, election_id: i64, position_id: i64, sealed: &str) -> Result<Vec<i64>, String> {
+    let aad = ballot_aad(ballot_id, election_id, position_id);
+    let plain = open(election_key, sealed, aad.as_bytes())?;
+    if plain.is_empty() || plain.len() % 8 != 0 {
+        return Err(format!("Ballot {} has a malformed choice.", ballot_id));
+    }
+    Ok(plain.chunks(8).map(|bytes| i64::from_be_bytes(bytes.try_into().unwrap())).collect())
+}
+
+
+/// Turn an encryption failure into an error the database layer can return
+pub fn to_db_error(message: String) -> rusqlite::Error {
+    rusqlite::Error::ToSqlConversionFailure(message.into())
+}
+
+
+fn ballot_aad(ballot_id: &str, election_id: i64, position_id: i64) -> String {
+    format!("ballot:{}:{}:{}", ballot_id, election_id, position_id)
+}
+
+
+/// AES-256-GCM with a random nonce, stored as base64 of nonce || ciphertext
+fn seal(key: &[u8; 32], plain: &[u8], aad: &[u8]) -> Result<String, String> {
+    let cipher = Aes256Gcm::new(&(*key).into());
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: plain, aad })
+        .map_err(|_| "Encryption failed.".to_string())?;
+    let mut out = nonce.to_vec();
+    out.extend_from_slice(&ciphertext);
+    Ok(BASE64.encode(out))
+}
+
+
+fn open(key: &[u8; 32], sealed: &str, aad: &[u8]) -> Result<Vec<u8>, String> {
+    let data = BASE64.decode(sealed).map_err(|_| "Sealed value is not valid base64.".to_string())?;
+    if data.len() <= NONCE_LEN {
+        return Err("Sealed value is too short.".to_string());
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let nonce: [u8; NONCE_LEN] = nonce.try_into().map_err(|_| "Sealed value has a malformed nonce.".to_string())?;
+    let cipher = Aes256Gcm::new(&(*key).into());
+    cipher
+        .decrypt(&Nonce::from(nonce), Payload { msg: ciphertext, aad })
+        .map_err(|_| "Decryption failed: wrong key or tampered data.".to_string())
+}
+
+
+/// Load the master key, creating it on first use
+fn master_key() -> Result<[u8; 32], String> {
+    load_or_create_key(MASTER_KEY_PATH)
+}
+
+
+/// Read a 32-byte hex key from a file, or create the file (readable by the owner only) with a random key
+pub fn load_or_create_key(path_name: &str) -> Result<[u8; 32], String> {
+    let path = Path::new(path_name);
+    if path.exists() {
+        let text = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path_name, e))?;
+        let bytes = hex::decode(text.trim()).map_err(|_| format!("{} is not a hex key.", path_name))?;
+        return bytes.try_into().map_err(|_| format!("{} must hold a 32-byte key.", path_name));
+    }
+
+    let key = new_election_key();
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path).map_err(|e| format!("Failed to create {}: {}", path_name, e))?;
+    file.write_all(hex::encode(key).as_bytes()).map_err(|e| format!("Failed to write {}: {}", path_name, e))?;
+    Ok(key)
+}
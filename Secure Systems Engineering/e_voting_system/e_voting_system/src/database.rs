@@ -1,285 +1,1234 @@
-use rusqlite::{params, Connection, Result, OptionalExtension}; // Here we import rusqlite for SQLite database handling
-
-
-pub struct Database {
-    conn: Connection,
-}
-
-
-impl Database {
-    pub fn new(db_path: &str) -> Result<Self> {
-        let conn = Connection::open(db_path)?;
-        let db = Database { conn };
-        db.initialize_tables()?; // will create/update tables
-        Ok(db)
-    }
-
-/// Initializes all necessary tables for the e-voting system and make sure it won't overwrite existing data
-    fn initialize_tables(&self) -> Result<()> {
-        self.conn.execute_batch(
-            "
-            CREATE TABLE IF NOT EXISTS elections (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                name TEXT NOT NULL,
-                status TEXT NOT NULL DEFAULT 'closed'
-            );
-            CREATE TABLE IF NOT EXISTS positions (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                election_id INTEGER NOT NULL,
-                name TEXT NOT NULL,
-                FOREIGN KEY(election_id) REFERENCES elections(id)
-            );
-            CREATE TABLE IF NOT EXISTS candidates (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                position_id INTEGER NOT NULL,
-                name TEXT NOT NULL,
-                party TEXT NOT NULL DEFAULT '',
-                FOREIGN KEY(position_id) REFERENCES positions(id)
-            );
-            CREATE TABLE IF NOT EXISTS voters (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                full_name TEXT NOT NULL,
-                date_of_birth TEXT NOT NULL
-            );
-            CREATE TABLE IF NOT EXISTS votes (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                election_id INTEGER NOT NULL,
-                position_id INTEGER NOT NULL,
-                candidate_id INTEGER NOT NULL,
-                voter_id INTEGER NOT NULL,
-                FOREIGN KEY(election_id) REFERENCES elections(id),
-                FOREIGN KEY(position_id) REFERENCES positions(id),
-                FOREIGN KEY(candidate_id) REFERENCES candidates(id),
-                FOREIGN KEY(voter_id) REFERENCES voters(id)
-            );
-            "
-        )?;
-        crate::audit::setup_audit_table(&self.conn);
-        Ok(())
-    }
-
-
-    // ------------------- ADMIN METHODS -------------------
-
-
-    pub fn create_election(&self, name: &str) -> Result<i64> {
-        self.conn.execute(
-            "INSERT INTO elections (name) VALUES (?1)",
-            params![name],
-        )?;
-        Ok(self.conn.last_insert_rowid())
-    }
-
-
-    pub fn add_position(&self, election_id: i64, name: &str) -> Result<i64> {
-        self.conn.execute(
-            "INSERT INTO positions (election_id, name) VALUES (?1, ?2)",
-            params![election_id, name],
-        )?;
-        Ok(self.conn.last_insert_rowid())
-    }
-
-
-    /// Add candidate along with party
-    pub fn add_candidate_with_party(&self, position_id: i64, name: &str, party: &str) -> Result<i64> {
-        self.conn.execute(
-            "INSERT INTO candidates (position_id, name, party) VALUES (?1, ?2, ?3)",
-            params![position_id, name, party],
-        )?;
-        Ok(self.conn.last_insert_rowid())
-    }
-
-
-    /// Register a new voter
-pub fn register_voter(&self, full_name: &str, date_of_birth: &str) -> Result<bool> {
-    // Check if voter already exists
-    let mut stmt = self.conn.prepare(
-        "SELECT id FROM voters WHERE full_name = ?1 AND date_of_birth = ?2"
-    )?;
-    let exists: Option<i64> = stmt.query_row(params![full_name, date_of_birth], |row| row.get(0)).optional()?;
-
-
-    if exists.is_some() {
-        return Ok(false); // already exists
-    }
-
-
-    // Insert new voter
-    self.conn.execute(
-        "INSERT INTO voters (full_name, date_of_birth) VALUES (?1, ?2)",
-        params![full_name, date_of_birth],
-    )?;
-
-
-    Ok(true)
-}
-
-
-
-
-    // ------------------- ELECTION METHODS -------------------
-
-
-    pub fn list_elections(&self) -> Result<Vec<(i64, String, String)>> {
-        let mut stmt = self.conn.prepare("SELECT id, name, status FROM elections")?;
-        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
-        let mut elections = Vec::new();
-        for e in rows {
-            elections.push(e?);
-        }
-        Ok(elections)
-    }
-
-
-    pub fn open_election(&self, election_id: i64) -> Result<()> {
-        self.conn.execute(
-            "UPDATE elections SET status = 'open' WHERE id = ?1",
-            params![election_id],
-        )?;
-        Ok(())
-    }
-
-
-    pub fn close_election(&self, election_id: i64) -> Result<()> {
-        self.conn.execute(
-            "UPDATE elections SET status = 'closed' WHERE id = ?1",
-            params![election_id],
-        )?;
-        Ok(())
-    }
-
-
-    pub fn get_election_status(&self, election_id: i64) -> Result<String> {
-        self.conn.query_row(
-            "SELECT status FROM elections WHERE id = ?1",
-            params![election_id],
-            |row| row.get(0),
-        )
-    }
-
-
-    pub fn tally_results(&self, election_id: i64) -> Result<Vec<(String, String, i64)>> {
-        let mut stmt = self.conn.prepare(
-            "
-            SELECT positions.name, candidates.name, COUNT(votes.id) as vote_count
-            FROM positions
-            JOIN candidates ON candidates.position_id = positions.id
-            LEFT JOIN votes ON votes.candidate_id = candidates.id AND votes.election_id = ?1
-            WHERE positions.election_id = ?1
-            GROUP BY positions.name, candidates.name
-            "
-        )?;
-        let rows = stmt.query_map(params![election_id], |row| {
-            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
-        })?;
-        let mut results = Vec::new();
-        for r in rows {
-            results.push(r?);
-        }
-        Ok(results)
-    }
-
-
-    // ------------------- VOTER METHODS -------------------
-
-
-    pub fn list_positions(&self, election_id: i64) -> Result<Vec<(i64, String)>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, name FROM positions WHERE election_id = ?1"
-        )?;
-        let rows = stmt.query_map(params![election_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
-        let mut positions = Vec::new();
-        for r in rows {
-            positions.push(r?);
-        }
-        Ok(positions)
-    }
-
-
-    pub fn list_candidates(&self, position_id: i64) -> Result<Vec<(i64, String, String)>> {
-    let mut stmt = self.conn.prepare(
-        "SELECT id, name, party FROM candidates WHERE position_id = ?1"
-    )?;
-    let rows = stmt.query_map(params![position_id], |row| {
-        Ok((row.get(0)?, row.get(1)?, row.get(2)?))
-    })?;
-    let mut candidates = Vec::new();
-    for r in rows {
-        candidates.push(r?);
-    }
-    Ok(candidates)
-}
-
-
-
-
-    pub fn cast_vote(&self, election_id: i64, position_id: i64, candidate_id: i64, voter_id: i64) -> Result<()> {
-        self.conn.execute(
-            "INSERT INTO votes (election_id, position_id, candidate_id, voter_id) VALUES (?1, ?2, ?3, ?4)",
-            params![election_id, position_id, candidate_id, voter_id],
-        )?;
-        Ok(())
-    }
-
-
-    pub fn has_voted(&self, election_id: i64, position_id: i64, voter_id: i64) -> Result<bool> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id FROM votes WHERE election_id = ?1 AND position_id = ?2 AND voter_id = ?3"
-        )?;
-        let exists: Option<i64> = stmt.query_row(params![election_id, position_id, voter_id], |row| row.get(0)).optional()?;
-        Ok(exists.is_some())
-    }
-
-
-    pub fn list_open_elections(&self) -> Result<Vec<(i64, String)>> {
-        let mut stmt = self.conn.prepare("SELECT id, name FROM elections WHERE status = 'open'")?;
-        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
-        let mut elections = Vec::new();
-        for r in rows {
-            elections.push(r?);
-        }
-        Ok(elections)
-    }
-
-
-    pub fn get_voter_id(&self, full_name: &str, dob: &str) -> Result<Option<i64>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id FROM voters WHERE full_name = ?1 AND date_of_birth = ?2"
-        )?;
-        let result: Option<i64> = stmt.query_row(params![full_name, dob], |row| row.get(0)).optional()?;
-        Ok(result)
-    }
-
-
-    pub fn get_votes_by_voter(&self, voter_id: i64) -> Result<Vec<(String, String, String, String)>> {
-    let mut stmt = self.conn.prepare(
-        "
-        SELECT e.name, p.name, c.name, c.party
-        FROM votes v
-        JOIN elections e ON e.id = v.election_id
-        JOIN positions p ON p.id = v.position_id
-        JOIN candidates c ON c.id = v.candidate_id
-        WHERE v.voter_id = ?1
-        "
-    )?;
-    let rows = stmt.query_map([voter_id], |row| {
-        Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
-    })?;
-    let mut results = Vec::new();
-    for r in rows {
-        results.push(r?);
-    }
-    Ok(results)
-    }
-
-    pub fn get_voter_name(&self, voter_id: i64) -> Result<Option<String>> {
-        let mut stmt = self.conn.prepare("SELECT full_name FROM voters WHERE id = ?1")?;
-        let result: Option<String> = stmt.query_row(params![voter_id], |row| row.get(0)).optional()?;
-        Ok(result)
-    }
-
-    pub fn connection(&self) -> &Connection {
-        &self.conn
-    }
-
-}
\ No newline at end of file
+use rusqlite::{params, Connection, Result, OptionalExtension}; // Here we import rusqlite for SQLite database handling
+use rand::RngCore;
+use std::collections::{HashMap, HashSet};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+use crate::ballot_crypto::{self, to_db_error};
+use crate::{irv, merkle, signing};
+
+
+pub struct Database {
+    conn: Connection,
+}
+
+
+impl Database {
+    pub fn new(db_path: &str) -> Result<Self> {
+        let conn = Connection::open(db_path)?;
+        let db = Database { conn };
+        db.initialize_tables()?; // will create/update tables
+        Ok(db)
+    }
+
+/// Initializes all necessary tables for the e-voting system and make sure it won't overwrite existing data
+    fn initialize_tables(&self) -> Result<()> {
+        self.conn.execute_batch(
+            "
+            CREATE TABLE IF NOT EXISTS districts (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE
+            );
+            CREATE TABLE IF NOT EXISTS elections (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                status TEXT NOT NULL DEFAULT 'closed',
+                election_type TEXT NOT NULL DEFAULT 'plurality' CHECK (election_type IN ('plurality', 'ranked'))
+            );
+            CREATE TABLE IF NOT EXISTS positions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                election_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                FOREIGN KEY(election_id) REFERENCES elections(id)
+            );
+            CREATE TABLE IF NOT EXISTS candidates (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                position_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                party TEXT NOT NULL DEFAULT '',
+                FOREIGN KEY(position_id) REFERENCES positions(id)
+            );
+            CREATE TABLE IF NOT EXISTS voters (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                full_name TEXT NOT NULL,
+                date_of_birth TEXT NOT NULL
+            );
+            -- per-election ballot key, encrypted under the master key kept outside the database
+            CREATE TABLE IF NOT EXISTS election_keys (
+                election_id INTEGER PRIMARY KEY,
+                wrapped_key TEXT NOT NULL,
+                FOREIGN KEY(election_id) REFERENCES elections(id)
+            );
+            -- who has voted for which position, without their selection
+            CREATE TABLE IF NOT EXISTS voted (
+                election_id INTEGER NOT NULL,
+                position_id INTEGER NOT NULL,
+                voter_id INTEGER NOT NULL,
+                PRIMARY KEY(election_id, position_id, voter_id),
+                FOREIGN KEY(election_id) REFERENCES elections(id),
+                FOREIGN KEY(position_id) REFERENCES positions(id),
+                FOREIGN KEY(voter_id) REFERENCES voters(id)
+            ) WITHOUT ROWID;
+            CREATE TABLE IF NOT EXISTS users (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                username TEXT NOT NULL UNIQUE,
+                password_hash TEXT NOT NULL,
+                role TEXT NOT NULL CHECK (role IN ('admin', 'district', 'audit')),
+                active INTEGER NOT NULL DEFAULT 1,
+                created_at TEXT NOT NULL DEFAULT (datetime('now'))
+            );
+            -- Merkle root over the ballot hashes of a closed election, signed by the election authority
+            CREATE TABLE IF NOT EXISTS ballot_roots (
+                election_id INTEGER PRIMARY KEY,
+                merkle_root TEXT NOT NULL,
+                ballot_count INTEGER NOT NULL,
+                signature TEXT NOT NULL,
+                published_at TEXT NOT NULL DEFAULT (datetime('now')),
+                FOREIGN KEY(election_id) REFERENCES elections(id)
+            );
+            "
+        )?;
+        self.conn.execute_batch(&format!("CREATE TABLE IF NOT EXISTS ballots {};", BALLOTS_COLUMNS))?;
+        crate::audit::setup_audit_table(&self.conn);
+        self.add_election_type()?;
+        self.migrate_votes()?;
+        self.migrate_plain_ballots()?;
+        self.add_tracking_codes()?;
+        self.add_ballot_hashes()?;
+        self.add_district_columns()?;
+        self.add_registration_numbers()?;
+        self.add_voter_pins()?;
+        self.add_external_ids()?;
+        self.add_normalized_names()?;
+        Ok(())
+    }
+
+
+    /// Elections created before ranked-choice voting are all plurality elections
+    fn add_election_type(&self) -> Result<()> {
+        let has_type: bool = self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM pragma_table_info('elections') WHERE name = 'election_type')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_type {
+            self.conn.execute(
+                "ALTER TABLE elections ADD COLUMN election_type TEXT NOT NULL DEFAULT 'plurality' CHECK (election_type IN ('plurality', 'ranked'))",
+                [],
+            )?;
+        }
+        Ok(())
+    }
+
+
+    /// Older databases kept every vote in `votes` next to the voter_id. Move them into anonymous
+    /// ballots plus voted markers and drop the old table, so the link is gone for good.
+    fn migrate_votes(&self) -> Result<()> {
+        let has_votes: bool = self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'votes')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_votes {
+            return Ok(());
+        }
+
+        let tx = self.conn.unchecked_transaction()?;
+        let old_votes = {
+            let mut stmt = tx.prepare("SELECT election_id, position_id, candidate_id, voter_id FROM votes")?;
+            let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?, row.get::<_, i64>(3)?)))?;
+            rows.collect::<Result<Vec<_>>>()?
+        };
+        for (election_id, position_id, candidate_id, voter_id) in old_votes {
+            self.insert_ballot(election_id, position_id, &[candidate_id], None)?;
+            tx.execute(
+                "INSERT OR IGNORE INTO voted (election_id, position_id, voter_id) VALUES (?1, ?2, ?3)",
+                params![election_id, position_id, voter_id],
+            )?;
+        }
+        tx.execute("DROP TABLE votes", [])?;
+        tx.commit()
+    }
+
+
+    /// Ballots written before encryption hold the candidate_id in clear. Seal each one with its
+    /// election key and rebuild the table without the plain column.
+    fn migrate_plain_ballots(&self) -> Result<()> {
+        let has_plain: bool = self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM pragma_table_info('ballots') WHERE name = 'candidate_id')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_plain {
+            return Ok(());
+        }
+
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute_batch(&format!(
+            "ALTER TABLE ballots RENAME TO plain_ballots;
+             CREATE TABLE ballots {};",
+            BALLOTS_COLUMNS
+        ))?;
+        let plain = {
+            let mut stmt = tx.prepare("SELECT election_id, position_id, candidate_id FROM plain_ballots")?;
+            let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?)))?;
+            rows.collect::<Result<Vec<_>>>()?
+        };
+        for (election_id, position_id, candidate_id) in plain {
+            self.insert_ballot(election_id, position_id, &[candidate_id], None)?;
+        }
+        tx.execute("DROP TABLE plain_ballots", [])?;
+        tx.commit()
+    }
+
+
+    /// Ballots written before tracking codes have no code column; they keep a NULL code
+    fn add_tracking_codes(&self) -> Result<()> {
+        let has_code: bool = self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM pragma_table_info('ballots') WHERE name = 'tracking_code')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_code {
+            self.conn.execute("ALTER TABLE ballots ADD COLUMN tracking_code TEXT", [])?;
+        }
+        self.conn.execute(
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_ballots_tracking_code ON ballots (tracking_code)",
+            [],
+        )?;
+        Ok(())
+    }
+
+
+    /// Ballots written before the Merkle tree get their hash now
+    fn add_ballot_hashes(&self) -> Result<()> {
+        let has_hash: bool = self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM pragma_table_info('ballots') WHERE name = 'ballot_hash')",
+            [],
+            |row| row.get(0),
+        )?;
+        if has_hash {
+            return Ok(());
+        }
+
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("ALTER TABLE ballots ADD COLUMN ballot_hash TEXT", [])?;
+        let ballots = {
+            let mut stmt = tx.prepare("SELECT ballot_id, election_id, position_id, sealed_choice, tracking_code FROM ballots")?;
+            let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?, row.get::<_, String>(3)?, row.get::<_, Option<String>>(4)?)))?;
+            rows.collect::<Result<Vec<_>>>()?
+        };
+        for (ballot_id, election_id, position_id, sealed, tracking_code) in ballots {
+            let hash = merkle::ballot_hash(&ballot_id, election_id, position_id, &sealed, tracking_code.as_deref(), None);
+            tx.execute("UPDATE ballots SET ballot_hash = ?1 WHERE ballot_id = ?2", params![hash, ballot_id])?;
+        }
+        tx.commit()
+    }
+
+
+    /// Voters, elections, positions and ballots belong to a district, or to none (district_id NULL).
+    /// An election or position without a district is open to every voter; older rows keep NULL.
+    fn add_district_columns(&self) -> Result<()> {
+        for table in ["voters", "elections", "positions", "ballots"] {
+            let has_district: bool = self.conn.query_row(
+                "SELECT EXISTS (SELECT 1 FROM pragma_table_info(?1) WHERE name = 'district_id')",
+                params![table],
+                |row| row.get(0),
+            )?;
+            if !has_district {
+                self.conn.execute(
+                    &format!("ALTER TABLE {} ADD COLUMN district_id INTEGER REFERENCES districts(id)", table),
+                    [],
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+
+    /// Voters registered before registration numbers get one now; an admin can look it up for them
+    fn add_registration_numbers(&self) -> Result<()> {
+        let has_number: bool = self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM pragma_table_info('voters') WHERE name = 'registration_number')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_number {
+            let tx = self.conn.unchecked_transaction()?;
+            tx.execute("ALTER TABLE voters ADD COLUMN registration_number TEXT", [])?;
+            let voter_ids = {
+                let mut stmt = tx.prepare("SELECT id FROM voters")?;
+                let rows = stmt.query_map([], |row| row.get::<_, i64>(0))?;
+                rows.collect::<Result<Vec<_>>>()?
+            };
+            for voter_id in voter_ids {
+                tx.execute(
+                    "UPDATE voters SET registration_number = ?1 WHERE id = ?2",
+                    params![new_registration_number(), voter_id],
+                )?;
+            }
+            tx.commit()?;
+        }
+        self.conn.execute(
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_voters_registration_number ON voters (registration_number)",
+            [],
+        )?;
+        Ok(())
+    }
+
+
+    /// Optional voter PIN (Argon2 hash) with a failed attempt counter and lockout time.
+    /// Voters registered before PINs have none.
+    fn add_voter_pins(&self) -> Result<()> {
+        let has_pin: bool = self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM pragma_table_info('voters') WHERE name = 'pin_hash')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_pin {
+            self.conn.execute_batch(
+                "ALTER TABLE voters ADD COLUMN pin_hash TEXT;
+                 ALTER TABLE voters ADD COLUMN failed_pin_attempts INTEGER NOT NULL DEFAULT 0;
+                 ALTER TABLE voters ADD COLUMN pin_locked_until TEXT;"
+            )?;
+        }
+        Ok(())
+    }
+
+
+    /// Id of the voter in the county's records, set by a voter roll import; unique when present
+    fn add_external_ids(&self) -> Result<()> {
+        let has_external_id: bool = self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM pragma_table_info('voters') WHERE name = 'external_id')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_external_id {
+            self.conn.execute("ALTER TABLE voters ADD COLUMN external_id TEXT", [])?;
+        }
+        self.conn.execute(
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_voters_external_id ON voters (external_id)",
+            [],
+        )?;
+        Ok(())
+    }
+
+
+    /// Name as compared for duplicate registrations, unique together with the date of birth.
+    /// If voters registered earlier already collide, the first keeps the name and the others are left
+    /// without one (NULL never collides) so the index can still be created; they are reported for review.
+    fn add_normalized_names(&self) -> Result<()> {
+        let has_normalized: bool = self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM pragma_table_info('voters') WHERE name = 'normalized_name')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_normalized {
+            let tx = self.conn.unchecked_transaction()?;
+            tx.execute("ALTER TABLE voters ADD COLUMN normalized_name TEXT", [])?;
+            let voters = {
+                let mut stmt = tx.prepare("SELECT id, full_name, date_of_birth FROM voters ORDER BY id")?;
+                let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?)))?;
+                rows.collect::<Result<Vec<_>>>()?
+            };
+            let mut seen = HashSet::new();
+            for (voter_id, full_name, date_of_birth) in voters {
+                let normalized = normalize_name(&full_name);
+                if seen.insert((normalized.clone(), date_of_birth.clone())) {
+                    tx.execute("UPDATE voters SET normalized_name = ?1 WHERE id = ?2", params![normalized, voter_id])?;
+                } else {
+                    eprintln!("⚠️ Voter {} ({}, {}) looks like a duplicate registration; please review.", voter_id, full_name, date_of_birth);
+                }
+            }
+            tx.commit()?;
+        }
+        self.conn.execute(
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_voters_normalized_name ON voters (normalized_name, date_of_birth)",
+            [],
+        )?;
+        Ok(())
+    }
+
+
+    /// Key that seals the ballots of an election, created on first use
+    fn election_key(&self, election_id: i64) -> Result<[u8; 32]> {
+        let wrapped: Option<String> = self.conn.query_row(
+            "SELECT wrapped_key FROM election_keys WHERE election_id = ?1",
+            params![election_id],
+            |row| row.get(0),
+        ).optional()?;
+        if let Some(wrapped) = wrapped {
+            return ballot_crypto::unwrap_key(&wrapped).map_err(to_db_error);
+        }
+
+        let key = ballot_crypto::new_election_key();
+        let wrapped = ballot_crypto::wrap_key(&key).map_err(to_db_error)?;
+        self.conn.execute(
+            "INSERT INTO election_keys (election_id, wrapped_key) VALUES (?1, ?2)",
+            params![election_id, wrapped],
+        )?;
+        Ok(key)
+    }
+
+
+    /// Store one encrypted ballot under a new random ballot id; returns its tracking code.
+    /// `district_id` is the voter's district, kept for per-district results.
+    fn insert_ballot(&self, election_id: i64, position_id: i64, candidate_ids: &[i64], district_id: Option<i64>) -> Result<String> {
+        let key = self.election_key(election_id)?;
+        let ballot_id = new_ballot_id();
+        let tracking_code = new_tracking_code();
+        let sealed = ballot_crypto::seal_choice(&key, &ballot_id, election_id, position_id, candidate_ids).map_err(to_db_error)?;
+        let ballot_hash = merkle::ballot_hash(&ballot_id, election_id, position_id, &sealed, Some(&tracking_code), district_id);
+        self.conn.execute(
+            "INSERT INTO ballots (ballot_id, election_id, position_id, sealed_choice, tracking_code, ballot_hash, district_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![ballot_id, election_id, position_id, sealed, tracking_code, ballot_hash, district_id],
+        )?;
+        Ok(tracking_code)
+    }
+
+
+    // ------------------- ADMIN METHODS -------------------
+
+
+    /// Create an election; `election_type` is 'plurality' or 'ranked'.
+    /// An election with a district is only open to that district's voters.
+    pub fn create_election(&self, name: &str, election_type: &str, district_id: Option<i64>) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO elections (name, election_type, district_id) VALUES (?1, ?2, ?3)",
+            params![name, election_type, district_id],
+        )?;
+        let election_id = self.conn.last_insert_rowid();
+        self.election_key(election_id)?;
+        Ok(election_id)
+    }
+
+
+    /// Add a position; one with a district is only on the ballot of that district's voters
+    pub fn add_position(&self, election_id: i64, name: &str, district_id: Option<i64>) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO positions (election_id, name, district_id) VALUES (?1, ?2, ?3)",
+            params![election_id, name, district_id],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+
+    /// Add candidate along with party
+    pub fn add_candidate_with_party(&self, position_id: i64, name: &str, party: &str) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO candidates (position_id, name, party) VALUES (?1, ?2, ?3)",
+            params![position_id, name, party],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+
+    /// A ballot can no longer be edited once its election is open or anyone has voted in it,
+    /// since a change would alter what voters already saw or chose.
+    pub fn is_ballot_locked(&self, election_id: i64) -> Result<bool> {
+        self.conn.query_row(
+            "
+            SELECT status = 'open'
+                OR EXISTS (SELECT 1 FROM ballots WHERE election_id = ?1)
+                OR EXISTS (SELECT 1 FROM voted WHERE election_id = ?1)
+            FROM elections WHERE id = ?1
+            ",
+            params![election_id],
+            |row| row.get(0),
+        )
+    }
+
+
+    fn ensure_ballot_editable(&self, election_id: i64) -> Result<()> {
+        if self.is_ballot_locked(election_id)? {
+            return Err(to_db_error("The election is open or has votes; its positions and candidates can no longer be changed.".to_string()));
+        }
+        Ok(())
+    }
+
+
+    /// Rename a position of an election that has not opened yet; returns false if the election has no such position
+    pub fn rename_position(&self, election_id: i64, position_id: i64, name: &str) -> Result<bool> {
+        self.ensure_ballot_editable(election_id)?;
+        let updated = self.conn.execute(
+            "UPDATE positions SET name = ?3 WHERE id = ?2 AND election_id = ?1",
+            params![election_id, position_id, name],
+        )?;
+        Ok(updated == 1)
+    }
+
+
+    /// Remove a position and its candidates from an election that has not opened yet;
+    /// returns false if the election has no such position
+    pub fn delete_position(&self, election_id: i64, position_id: i64) -> Result<bool> {
+        self.ensure_ballot_editable(election_id)?;
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "DELETE FROM candidates WHERE position_id = (SELECT id FROM positions WHERE id = ?2 AND election_id = ?1)",
+            params![election_id, position_id],
+        )?;
+        let deleted = tx.execute(
+            "DELETE FROM positions WHERE id = ?2 AND election_id = ?1",
+            params![election_id, position_id],
+        )?;
+        tx.commit()?;
+        Ok(deleted == 1)
+    }
+
+
+    /// Change the name and party of a candidate in an election that has not opened yet;
+    /// returns false if the election has no such candidate
+    pub fn update_candidate(&self, election_id: i64, candidate_id: i64, name: &str, party: &str) -> Result<bool> {
+        self.ensure_ballot_editable(election_id)?;
+        let updated = self.conn.execute(
+            "UPDATE candidates SET name = ?3, party = ?4
+             WHERE id = ?2 AND position_id IN (SELECT id FROM positions WHERE election_id = ?1)",
+            params![election_id, candidate_id, name, party],
+        )?;
+        Ok(updated == 1)
+    }
+
+
+    /// Remove a candidate from an election that has not opened yet; returns false if the election has no such candidate
+    pub fn delete_candidate(&self, election_id: i64, candidate_id: i64) -> Result<bool> {
+        self.ensure_ballot_editable(election_id)?;
+        let deleted = self.conn.execute(
+            "DELETE FROM candidates WHERE id = ?2 AND position_id IN (SELECT id FROM positions WHERE election_id = ?1)",
+            params![election_id, candidate_id],
+        )?;
+        Ok(deleted == 1)
+    }
+
+
+    // ------------------- DISTRICT METHODS -------------------
+
+
+    /// Create a district; returns false if the name is taken
+    pub fn create_district(&self, name: &str) -> Result<bool> {
+        let inserted = self.conn.execute(
+            "INSERT OR IGNORE INTO districts (name) VALUES (?1)",
+            params![name],
+        )?;
+        Ok(inserted == 1)
+    }
+
+
+    pub fn list_districts(&self) -> Result<Vec<(i64, String)>> {
+        let mut stmt = self.conn.prepare("SELECT id, name FROM districts ORDER BY name")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        let mut districts = Vec::new();
+        for d in rows {
+            districts.push(d?);
+        }
+        Ok(districts)
+    }
+
+
+    pub fn get_district_id(&self, name: &str) -> Result<Option<i64>> {
+        self.conn.query_row(
+            "SELECT id FROM districts WHERE name = ?1",
+            params![name],
+            |row| row.get(0),
+        ).optional()
+    }
+
+
+    /// Move a voter to a district; returns false if there is no such voter
+    pub fn set_voter_district(&self, voter_id: i64, district_id: i64) -> Result<bool> {
+        let updated = self.conn.execute(
+            "UPDATE voters SET district_id = ?2 WHERE id = ?1",
+            params![voter_id, district_id],
+        )?;
+        Ok(updated == 1)
+    }
+
+
+    /// Register a new voter and issue their registration number; returns None if they are already registered.
+    /// `pin_hash` is the Argon2 hash of the voter's PIN, if they chose one; `external_id` is their id in county records.
+pub fn register_voter(&self, full_name: &str, date_of_birth: &str, district_id: Option<i64>, pin_hash: Option<&str>, external_id: Option<&str>) -> Result<Option<String>> {
+    // Check if voter already exists, under any spelling of the name that normalizes the same
+    let normalized_name = normalize_name(full_name);
+    let mut stmt = self.conn.prepare(
+        "SELECT id FROM voters WHERE normalized_name = ?1 AND date_of_birth = ?2"
+    )?;
+    let exists: Option<i64> = stmt.query_row(params![normalized_name, date_of_birth], |row| row.get(0)).optional()?;
+
+
+    if exists.is_some() {
+        return Ok(None); // already exists
+    }
+
+
+    // Insert new voter
+    let registration_number = new_registration_number();
+    self.conn.execute(
+        "INSERT INTO voters (full_name, normalized_name, date_of_birth, district_id, registration_number, pin_hash, external_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![full_name.trim(), normalized_name, date_of_birth, district_id, registration_number, pin_hash, external_id],
+    )?;
+
+
+    Ok(Some(registration_number))
+}
+
+
+    /// Registered voters for the voter roll: name, date of birth, district name and county id, ordered by name.
+    /// With a district only its voters are listed. Registration numbers and PINs are never part of the roll.
+    pub fn list_voters(&self, district_id: Option<i64>) -> Result<Vec<RollVoter>> {
+        let mut stmt = self.conn.prepare(
+            "
+            SELECT v.full_name, v.date_of_birth, d.name, v.external_id
+            FROM voters v
+            LEFT JOIN districts d ON d.id = v.district_id
+            WHERE ?1 IS NULL OR v.district_id = ?1
+            ORDER BY v.full_name, v.date_of_birth
+            "
+        )?;
+        let rows = stmt.query_map(params![district_id], |row| {
+            Ok(RollVoter { full_name: row.get(0)?, date_of_birth: row.get(1)?, district: row.get(2)?, external_id: row.get(3)? })
+        })?;
+        let mut voters = Vec::new();
+        for r in rows {
+            voters.push(r?);
+        }
+        Ok(voters)
+    }
+
+
+    pub fn external_id_exists(&self, external_id: &str) -> Result<bool> {
+        self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM voters WHERE external_id = ?1)",
+            params![external_id],
+            |row| row.get(0),
+        )
+    }
+
+
+    /// Registration number of a voter, for an admin re-issuing it to a voter who lost theirs
+    pub fn find_registration_number(&self, full_name: &str, date_of_birth: &str) -> Result<Option<String>> {
+        self.conn.query_row(
+            "SELECT registration_number FROM voters WHERE normalized_name = ?1 AND date_of_birth = ?2",
+            params![normalize_name(full_name), date_of_birth],
+            |row| row.get(0),
+        ).optional()
+    }
+
+
+
+
+    // ------------------- ACCOUNT METHODS -------------------
+
+
+    /// Create an election official account; returns false if the username is taken
+    pub fn create_user(&self, username: &str, password_hash: &str, role: &str) -> Result<bool> {
+        let inserted = self.conn.execute(
+            "INSERT OR IGNORE INTO users (username, password_hash, role) VALUES (?1, ?2, ?3)",
+            params![username, password_hash, role],
+        )?;
+        Ok(inserted == 1)
+    }
+
+
+    /// Password hash, role and active flag of an account
+    pub fn get_user(&self, username: &str) -> Result<Option<(String, String, bool)>> {
+        self.conn.query_row(
+            "SELECT password_hash, role, active FROM users WHERE username = ?1",
+            params![username],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        ).optional()
+    }
+
+
+    pub fn list_users(&self) -> Result<Vec<(String, String, bool)>> {
+        let mut stmt = self.conn.prepare("SELECT username, role, active FROM users ORDER BY role, username")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        let mut users = Vec::new();
+        for u in rows {
+            users.push(u?);
+        }
+        Ok(users)
+    }
+
+
+    /// Enable or disable an account; returns false if there is no such account
+    pub fn set_user_active(&self, username: &str, active: bool) -> Result<bool> {
+        let updated = self.conn.execute(
+            "UPDATE users SET active = ?2 WHERE username = ?1",
+            params![username, active],
+        )?;
+        Ok(updated == 1)
+    }
+
+
+    pub fn count_active_users(&self, role: &str) -> Result<i64> {
+        self.conn.query_row(
+            "SELECT COUNT(*) FROM users WHERE role = ?1 AND active = 1",
+            params![role],
+            |row| row.get(0),
+        )
+    }
+
+
+    // ------------------- ELECTION METHODS -------------------
+
+
+    pub fn list_elections(&self) -> Result<Vec<(i64, String, String)>> {
+        let mut stmt = self.conn.prepare("SELECT id, name, status FROM elections")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        let mut elections = Vec::new();
+        for e in rows {
+            elections.push(e?);
+        }
+        Ok(elections)
+    }
+
+
+    pub fn open_election(&self, election_id: i64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE elections SET status = 'open' WHERE id = ?1",
+            params![election_id],
+        )?;
+        Ok(())
+    }
+
+
+    pub fn close_election(&self, election_id: i64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE elections SET status = 'closed' WHERE id = ?1",
+            params![election_id],
+        )?;
+        Ok(())
+    }
+
+
+    pub fn get_election_status(&self, election_id: i64) -> Result<String> {
+        self.conn.query_row(
+            "SELECT status FROM elections WHERE id = ?1",
+            params![election_id],
+            |row| row.get(0),
+        )
+    }
+
+
+    pub fn get_election_name(&self, election_id: i64) -> Result<String> {
+        self.conn.query_row(
+            "SELECT name FROM elections WHERE id = ?1",
+            params![election_id],
+            |row| row.get(0),
+        )
+    }
+
+
+    pub fn get_election_type(&self, election_id: i64) -> Result<String> {
+        self.conn.query_row(
+            "SELECT election_type FROM elections WHERE id = ?1",
+            params![election_id],
+            |row| row.get(0),
+        )
+    }
+
+
+    pub fn count_ballots(&self, election_id: i64) -> Result<i64> {
+        self.conn.query_row(
+            "SELECT COUNT(*) FROM ballots WHERE election_id = ?1",
+            params![election_id],
+            |row| row.get(0),
+        )
+    }
+
+
+    /// Hashes of an election's ballots, sorted so the leaf order says nothing about when a ballot was cast
+    pub fn ballot_hashes(&self, election_id: i64) -> Result<Vec<[u8; 32]>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT ballot_hash FROM ballots WHERE election_id = ?1 ORDER BY ballot_hash"
+        )?;
+        let rows = stmt.query_map(params![election_id], |row| row.get::<_, String>(0))?;
+        let mut hashes = Vec::new();
+        for r in rows {
+            let hash = r?;
+            hashes.push(merkle::decode_hash(&hash).ok_or_else(|| to_db_error(format!("Malformed ballot hash {}.", hash)))?);
+        }
+        Ok(hashes)
+    }
+
+
+    /// Compute the Merkle root over a closed election's ballots, sign it and store it for publication.
+    /// Returns the root and the number of ballots under it.
+    pub fn publish_ballot_root(&self, election_id: i64) -> Result<(String, i64)> {
+        if self.get_election_status(election_id)? != "closed" {
+            return Err(to_db_error("The ballot root is published once the election is closed.".to_string()));
+        }
+        let hashes = self.ballot_hashes(election_id)?;
+        let root = hex::encode(merkle::root(&hashes));
+        let count = hashes.len() as i64;
+        let signature = signing::sign(merkle::root_statement(election_id, count, &root).as_bytes()).map_err(to_db_error)?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO ballot_roots (election_id, merkle_root, ballot_count, signature) VALUES (?1, ?2, ?3, ?4)",
+            params![election_id, root, count, signature],
+        )?;
+        Ok((root, count))
+    }
+
+
+    /// Published root, ballot count and signature of an election, if it has been closed
+    pub fn get_ballot_root(&self, election_id: i64) -> Result<Option<(String, i64, String)>> {
+        self.conn.query_row(
+            "SELECT merkle_root, ballot_count, signature FROM ballot_roots WHERE election_id = ?1",
+            params![election_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        ).optional()
+    }
+
+
+    /// Decrypt every ballot of a closed election: position, district and the candidates it holds.
+    /// Ballots stay sealed while the election is open, so there are no interim results.
+    fn open_ballots(&self, election_id: i64) -> Result<Vec<OpenedBallot>> {
+        if self.get_election_status(election_id)? != "closed" {
+            return Err(to_db_error("Results are only available once the election is closed.".to_string()));
+        }
+        let key = self.election_key(election_id)?;
+
+        let mut stmt = self.conn.prepare(
+            "SELECT ballot_id, position_id, sealed_choice, district_id FROM ballots WHERE election_id = ?1"
+        )?;
+        let rows = stmt.query_map(params![election_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?, row.get::<_, String>(2)?, row.get::<_, Option<i64>>(3)?))
+        })?;
+        let mut ballots = Vec::new();
+        for r in rows {
+            let (ballot_id, position_id, sealed, district_id) = r?;
+            let candidate_ids = ballot_crypto::open_choice(&key, &ballot_id, election_id, position_id, &sealed).map_err(to_db_error)?;
+            ballots.push(OpenedBallot { position_id, district_id, candidate_ids });
+        }
+        Ok(ballots)
+    }
+
+
+    /// Decrypt the ballots of a closed plurality election and count them per candidate.
+    pub fn tally_results(&self, election_id: i64) -> Result<Vec<(String, String, i64)>> {
+        if self.get_election_type(election_id)? != "plurality" {
+            return Err(to_db_error("This is a ranked-choice election; it is counted by instant runoff.".to_string()));
+        }
+        let mut counts: HashMap<(i64, i64), i64> = HashMap::new();
+        for ballot in self.open_ballots(election_id)? {
+            *counts.entry((ballot.position_id, ballot.candidate_ids[0])).or_insert(0) += 1;
+        }
+
+        let mut stmt = self.conn.prepare(
+            "
+            SELECT positions.id, positions.name, candidates.id, candidates.name
+            FROM positions
+            JOIN candidates ON candidates.position_id = positions.id
+            WHERE positions.election_id = ?1
+            ORDER BY positions.id, candidates.id
+            "
+        )?;
+        let rows = stmt.query_map(params![election_id], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?, row.get::<_, String>(3)?))
+        })?;
+        let mut results = Vec::new();
+        for r in rows {
+            let (position_id, position, candidate_id, candidate) = r?;
+            let count = counts.get(&(position_id, candidate_id)).copied().unwrap_or(0);
+            results.push((position, candidate, count));
+        }
+        Ok(results)
+    }
+
+
+    /// Votes per district, position and candidate of a closed election; for a ranked-choice election these are first preferences.
+    /// Ballots cast by voters without a district are counted under "(no district)".
+    /// A district is listed only if it cast ballots, and only with the positions on its voters' ballots.
+    pub fn tally_by_district(&self, election_id: i64) -> Result<Vec<(String, String, String, i64)>> {
+        let mut stmt = self.conn.prepare(
+            "
+            SELECT positions.id, positions.name, positions.district_id, candidates.id, candidates.name
+            FROM positions
+            JOIN candidates ON candidates.position_id = positions.id
+            WHERE positions.election_id = ?1
+            ORDER BY positions.id, candidates.id
+            "
+        )?;
+        let rows = stmt.query_map(params![election_id], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<i64>>(2)?, row.get::<_, i64>(3)?, row.get::<_, String>(4)?))
+        })?;
+        let candidate_lines = rows.collect::<Result<Vec<_>>>()?;
+
+        // A ballot counts for its highest ranked candidate standing for the position, which skips ranking padding
+        let mut counts: HashMap<(Option<i64>, i64, i64), i64> = HashMap::new();
+        for ballot in self.open_ballots(election_id)? {
+            let first = ballot.candidate_ids.iter().find(|id| {
+                candidate_lines.iter().any(|(p, _, _, c, _)| *p == ballot.position_id && c == *id)
+            });
+            if let Some(&candidate_id) = first {
+                *counts.entry((ballot.district_id, ballot.position_id, candidate_id)).or_insert(0) += 1;
+            }
+        }
+
+        let mut districts: Vec<(Option<i64>, String)> = self.list_districts()?.into_iter().map(|(id, name)| (Some(id), name)).collect();
+        districts.push((None, "(no district)".to_string()));
+        let mut results = Vec::new();
+        for (district_id, district) in districts {
+            if !counts.keys().any(|(d, _, _)| *d == district_id) {
+                continue;
+            }
+            for (position_id, position, position_district, candidate_id, candidate) in &candidate_lines {
+                if position_district.is_some() && *position_district != district_id {
+                    continue;
+                }
+                let count = counts.get(&(district_id, *position_id, *candidate_id)).copied().unwrap_or(0);
+                results.push((district.clone(), position.clone(), candidate.clone(), count));
+            }
+        }
+        Ok(results)
+    }
+
+
+    /// Decrypt the ballots of a closed ranked-choice election and count each position by instant runoff.
+    pub fn tally_ranked(&self, election_id: i64) -> Result<Vec<irv::PositionCount>> {
+        if self.get_election_type(election_id)? != "ranked" {
+            return Err(to_db_error("This is a plurality election; it is not counted by instant runoff.".to_string()));
+        }
+        let mut ballots_by_position: HashMap<i64, Vec<Vec<i64>>> = HashMap::new();
+        for ballot in self.open_ballots(election_id)? {
+            ballots_by_position.entry(ballot.position_id).or_default().push(ballot.candidate_ids);
+        }
+
+        let mut results = Vec::new();
+        for (position_id, position) in self.list_positions(election_id)? {
+            let candidates: Vec<(i64, String)> = self.list_candidates(position_id)?
+                .into_iter()
+                .map(|(id, name, _)| (id, name))
+                .collect();
+            let candidate_ids: Vec<i64> = candidates.iter().map(|(id, _)| *id).collect();
+            let ballots = ballots_by_position.remove(&position_id).unwrap_or_default();
+            let rounds = irv::count(&candidate_ids, &ballots);
+            results.push(irv::PositionCount { position, candidates, rounds });
+        }
+        Ok(results)
+    }
+
+
+    // ------------------- VOTER METHODS -------------------
+
+
+    pub fn list_positions(&self, election_id: i64) -> Result<Vec<(i64, String)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, name FROM positions WHERE election_id = ?1 ORDER BY id"
+        )?;
+        let rows = stmt.query_map(params![election_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        let mut positions = Vec::new();
+        for r in rows {
+            positions.push(r?);
+        }
+        Ok(positions)
+    }
+
+
+    /// Positions of an election on the voter's ballot: those without a district and those of the voter's district
+    pub fn list_eligible_positions(&self, election_id: i64, voter_id: i64) -> Result<Vec<(i64, String)>> {
+        let mut stmt = self.conn.prepare(
+            "
+            SELECT p.id, p.name
+            FROM positions p
+            JOIN voters v ON v.id = ?2
+            WHERE p.election_id = ?1 AND (p.district_id IS NULL OR p.district_id = v.district_id)
+            ORDER BY p.id
+            "
+        )?;
+        let rows = stmt.query_map(params![election_id, voter_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        let mut positions = Vec::new();
+        for r in rows {
+            positions.push(r?);
+        }
+        Ok(positions)
+    }
+
+
+    pub fn list_candidates(&self, position_id: i64) -> Result<Vec<(i64, String, String)>> {
+    let mut stmt = self.conn.prepare(
+        "SELECT id, name, party FROM candidates WHERE position_id = ?1 ORDER BY id"
+    )?;
+    let rows = stmt.query_map(params![position_id], |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+    })?;
+    let mut candidates = Vec::new();
+    for r in rows {
+        candidates.push(r?);
+    }
+    Ok(candidates)
+}
+
+
+
+
+    /// Record the ballot and, separately, that the voter has voted for the position.
+    /// `candidate_ids` is the chosen candidate, or for a ranked election the candidates in order of preference.
+    /// Rankings are padded to the number of candidates, so the size of a sealed ballot does not show how many were ranked.
+    /// Both are written in one transaction; the voted marker's primary key stops a second ballot.
+    /// Returns the ballot's tracking code, which is shown to the voter and never stored next to them.
+    /// The voter's district is stored on the ballot; a voter can only vote on positions of their districts.
+    pub fn cast_vote(&self, election_id: i64, position_id: i64, candidate_ids: &[i64], voter_id: i64) -> Result<String> {
+        if !self.is_eligible(election_id, position_id, voter_id)? {
+            return Err(to_db_error("This position is not on the voter's ballot.".to_string()));
+        }
+        let district_id: Option<i64> = self.conn.query_row(
+            "SELECT district_id FROM voters WHERE id = ?1",
+            params![voter_id],
+            |row| row.get(0),
+        )?;
+        let mut sealed_ids = candidate_ids.to_vec();
+        if self.get_election_type(election_id)? == "ranked" {
+            let candidate_count = self.list_candidates(position_id)?.len();
+            sealed_ids.resize(candidate_count.max(sealed_ids.len()), 0);
+        }
+
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO voted (election_id, position_id, voter_id) VALUES (?1, ?2, ?3)",
+            params![election_id, position_id, voter_id],
+        )?;
+        let tracking_code = self.insert_ballot(election_id, position_id, &sealed_ids, district_id)?;
+        tx.commit()?;
+        Ok(tracking_code)
+    }
+
+
+    /// Election name, position name and election status of the ballot with a tracking code.
+    /// Nothing about the selection is returned.
+    pub fn find_ballot_by_tracking_code(&self, tracking_code: &str) -> Result<Option<(String, String, String)>> {
+        self.conn.query_row(
+            "
+            SELECT e.name, p.name, e.status
+            FROM ballots b
+            JOIN elections e ON e.id = b.election_id
+            JOIN positions p ON p.id = b.position_id
+            WHERE b.tracking_code = ?1
+            ",
+            params![tracking_code],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        ).optional()
+    }
+
+
+    /// Election id and ballot hash of the ballot with a tracking code
+    pub fn find_ballot_hash_by_tracking_code(&self, tracking_code: &str) -> Result<Option<(i64, String)>> {
+        self.conn.query_row(
+            "SELECT election_id, ballot_hash FROM ballots WHERE tracking_code = ?1",
+            params![tracking_code],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).optional()
+    }
+
+
+    pub fn has_voted(&self, election_id: i64, position_id: i64, voter_id: i64) -> Result<bool> {
+        let mut stmt = self.conn.prepare(
+            "SELECT 1 FROM voted WHERE election_id = ?1 AND position_id = ?2 AND voter_id = ?3"
+        )?;
+        let exists: Option<i64> = stmt.query_row(params![election_id, position_id, voter_id], |row| row.get(0)).optional()?;
+        Ok(exists.is_some())
+    }
+
+
+    /// Whether the position belongs to the election and both are open to the voter's district.
+    /// Elections and positions without a district are open to every voter.
+    pub fn is_eligible(&self, election_id: i64, position_id: i64, voter_id: i64) -> Result<bool> {
+        self.conn.query_row(
+            "
+            SELECT EXISTS (
+                SELECT 1
+                FROM positions p
+                JOIN elections e ON e.id = p.election_id
+                JOIN voters v ON v.id = ?3
+                WHERE p.id = ?2 AND e.id = ?1
+                  AND (e.district_id IS NULL OR e.district_id = v.district_id)
+                  AND (p.district_id IS NULL OR p.district_id = v.district_id)
+            )
+            ",
+            params![election_id, position_id, voter_id],
+            |row| row.get(0),
+        )
+    }
+
+
+    /// Open elections the voter's district takes part in
+    pub fn list_open_elections(&self, voter_id: i64) -> Result<Vec<(i64, String)>> {
+        let mut stmt = self.conn.prepare(
+            "
+            SELECT e.id, e.name
+            FROM elections e
+            JOIN voters v ON v.id = ?1
+            WHERE e.status = 'open' AND (e.district_id IS NULL OR e.district_id = v.district_id)
+            "
+        )?;
+        let rows = stmt.query_map(params![voter_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        let mut elections = Vec::new();
+        for r in rows {
+            elections.push(r?);
+        }
+        Ok(elections)
+    }
+
+
+    /// Voter with this registration number and date of birth
+    pub fn get_voter_id(&self, registration_number: &str, dob: &str) -> Result<Option<i64>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id FROM voters WHERE registration_number = ?1 AND date_of_birth = ?2"
+        )?;
+        let result: Option<i64> = stmt.query_row(params![registration_number, dob], |row| row.get(0)).optional()?;
+        Ok(result)
+    }
+
+
+    /// PIN hash of a voter who set one, and whether PIN entry is locked after too many failures
+    pub fn get_voter_pin(&self, voter_id: i64) -> Result<Option<(String, bool)>> {
+        self.conn.query_row(
+            "SELECT pin_hash, COALESCE(pin_locked_until > datetime('now'), 0) FROM voters WHERE id = ?1 AND pin_hash IS NOT NULL",
+            params![voter_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).optional()
+    }
+
+
+    /// Count a wrong PIN. The `max_attempts`th failure in a row locks PIN entry for `lockout_minutes`
+    /// and starts the count again. Returns true if PIN entry is now locked.
+    pub fn record_pin_failure(&self, voter_id: i64, max_attempts: i64, lockout_minutes: i64) -> Result<bool> {
+        self.conn.execute(
+            "
+            UPDATE voters SET
+                pin_locked_until = CASE WHEN failed_pin_attempts + 1 >= ?2
+                    THEN datetime('now', ?3) ELSE pin_locked_until END,
+                failed_pin_attempts = CASE WHEN failed_pin_attempts + 1 >= ?2
+                    THEN 0 ELSE failed_pin_attempts + 1 END
+            WHERE id = ?1
+            ",
+            params![voter_id, max_attempts, format!("+{} minutes", lockout_minutes)],
+        )?;
+        Ok(self.get_voter_pin(voter_id)?.is_some_and(|(_, locked)| locked))
+    }
+
+
+    /// A correct PIN clears the failure count
+    pub fn reset_pin_failures(&self, voter_id: i64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE voters SET failed_pin_attempts = 0 WHERE id = ?1",
+            params![voter_id],
+        )?;
+        Ok(())
+    }
+
+
+    /// Elections and positions the voter has voted for; the selections themselves are not linked to voters
+    pub fn get_voted_positions(&self, voter_id: i64) -> Result<Vec<(String, String)>> {
+    let mut stmt = self.conn.prepare(
+        "
+        SELECT e.name, p.name
+        FROM voted v
+        JOIN elections e ON e.id = v.election_id
+        JOIN positions p ON p.id = v.position_id
+        WHERE v.voter_id = ?1
+        "
+    )?;
+    let rows = stmt.query_map([voter_id], |row| {
+        Ok((row.get(0)?, row.get(1)?))
+    })?;
+    let mut results = Vec::new();
+    for r in rows {
+        results.push(r?);
+    }
+    Ok(results)
+    }
+
+    pub fn get_voter_name(&self, voter_id: i64) -> Result<Option<String>> {
+        let mut stmt = self.conn.prepare("SELECT full_name FROM voters WHERE id = ?1")?;
+        let result: Option<String> = stmt.query_row(params![voter_id], |row| row.get(0)).optional()?;
+        Ok(result)
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+}
+
+
+/// Columns of the ballots table: ballot content with nothing that points back to the voter.
+/// The chosen candidate is sealed with the election key (see ballot_crypto). WITHOUT ROWID stores
+/// ballots in random ballot_id order, so insertion order cannot be matched against `voted`.
+/// ballot_hash is the ballot's leaf in the election's Merkle tree (see merkle).
+const BALLOTS_COLUMNS: &str = "(
+                ballot_id TEXT PRIMARY KEY,
+                election_id INTEGER NOT NULL,
+                position_id INTEGER NOT NULL,
+                sealed_choice TEXT NOT NULL,
+                tracking_code TEXT,
+                ballot_hash TEXT,
+                district_id INTEGER REFERENCES districts(id),
+                FOREIGN KEY(election_id) REFERENCES elections(id),
+                FOREIGN KEY(position_id) REFERENCES positions(id)
+            ) WITHOUT ROWID";
+
+
+/// A decrypted ballot, only ever held in memory while a closed election is tallied
+struct OpenedBallot {
+    position_id: i64,
+    district_id: Option<i64>,
+    candidate_ids: Vec<i64>,
+}
+
+
+/// A registered voter as listed on the voter roll
+pub struct RollVoter {
+    pub full_name: String,
+    pub date_of_birth: String,
+    pub district: Option<String>,
+    pub external_id: Option<String>,
+}
+
+
+/// Letters and digits of tracking codes, without the look-alikes 0/O and 1/I
+const TRACKING_ALPHABET: &[u8; 32] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+
+/// Random tracking code such as K7QM-2XHD-9PTA (60 bits)
+fn new_tracking_code() -> String {
+    let mut bytes = [0u8; 12];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let chars: Vec<char> = bytes.iter().map(|b| TRACKING_ALPHABET[(b % 32) as usize] as char).collect();
+    chars.chunks(4).map(|group| group.iter().collect::<String>()).collect::<Vec<_>>().join("-")
+}
+
+
+/// Tracking code as typed by a voter in the stored form: upper case, grouped by dashes
+pub fn normalize_tracking_code(input: &str) -> String {
+    let chars: Vec<char> = input.chars().filter(|c| c.is_ascii_alphanumeric()).map(|c| c.to_ascii_uppercase()).collect();
+    chars.chunks(4).map(|group| group.iter().collect::<String>()).collect::<Vec<_>>().join("-")
+}
+
+
+/// Random voter registration number such as VR-K7QM-2XHD-9PTA (60 bits), issued at registration
+fn new_registration_number() -> String {
+    format!("VR-{}", new_tracking_code())
+}
+
+
+/// Registration number as typed by a voter in the stored form, with or without the VR prefix
+pub fn normalize_registration_number(input: &str) -> String {
+    let chars: String = input.chars().filter(|c| c.is_ascii_alphanumeric()).map(|c| c.to_ascii_uppercase()).collect();
+    let code = if chars.len() == 14 && chars.starts_with("VR") { &chars[2..] } else { &chars };
+    format!("VR-{}", normalize_tracking_code(code))
+}
+
+
+/// Name in the form compared for duplicate registrations: accents removed, lower case,
+/// and whitespace collapsed, so "José  García" and "jose garcia" are the same person
+pub fn normalize_name(name: &str) -> String {
+    let plain: String = name.nfkd().filter(|c| !is_combining_mark(*c)).collect();
+    plain.to_lowercase().split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+
+/// Random identifier of an anonymous ballot
+fn new_ballot_id() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
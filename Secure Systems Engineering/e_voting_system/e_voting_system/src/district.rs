@@ -1,96 +1,232 @@
-use crate::database::Database;       // Import the Database helper for SQLite access
-use std::io::{self, Write};          // Used for input/output operations
-
-/// The main menu handler for district officials.
-/// Displays options to manage elections and performs operations on the database.
-/// Returns `false` when the user selects "Logout".
-pub fn handle_menu() -> bool {
-    // Connect to the database (creates it if it doesn’t exist)
-    let db = Database::new("e_voting.db").expect("Failed to initialize database");
-
-    // Menu loop continues until user logs out
-    loop {
-        println!("\n--- District Official Menu ---");
-        println!("1. List Elections");
-        println!("2. Open Election");
-        println!("3. Close Election");
-        println!("4. View Election Status");
-        println!("5. Tally Results");
-        println!("6. Logout");
-
-        // Get user’s menu choice
-        let choice = get_input("Select an option: ");
-
-        // Match user input to action
-        match choice.trim() {
-            "1" => list_elections(&db),
-            "2" => open_election(&db),
-            "3" => close_election(&db),
-            "4" => view_status(&db),
-            "5" => tally_results(&db),
-            "6" => return false, // Exit back to main menu
-            _ => println!("Invalid option"),
-        }
-    }
-}
-
-/// Lists all the ewlections from the database.
-/// Displays ID, name, and status of each election.
-fn list_elections(db: &Database) {
-    let elections = db.list_elections().unwrap();
-    println!("ID | Name | Status");
-    for (id, name, status) in elections {
-        println!("{} | {} | {}", id, name, status);
-    }
-}
-
-/// Opens an election by its ID.
-/// Changes its status to open in db here
-fn open_election(db: &Database) {
-    let id = get_input("Enter election ID to open: ").parse::<i64>().unwrap();
-    db.open_election(id).unwrap();
-    println!("Election {} is now open.", id);
-}
-
-/// Closes an election by it's ID here
-/// Updates its status to "closed" in the database.
-fn close_election(db: &Database) {
-    let id = get_input("Enter election ID to close: ").parse::<i64>().unwrap();
-    db.close_election(id).unwrap();
-    println!("Election {} is now closed.", id);
-}
-
-/// Displays the currentt status (open/closed) of a specific election.
-fn view_status(db: &Database) {
-    let id = get_input("Enter election ID to view status: ").parse::<i64>().unwrap();
-    let status = db.get_election_status(id).unwrap();
-    println!("Election {} status: {}", id, status);
-}
-
-/// Tallies all votes for a given election.
-/// Displays the count of votes per candidate and position.
-fn tally_results(db: &Database) {
-    let id = get_input("Enter election ID to tally: ").parse::<i64>().unwrap();
-    let results = db.tally_results(id).unwrap();
-
-    println!("\n--- Tally Results ---");
-
-    // Tracks position changes to group results neatly
-    let mut current_position = String::new();
-    for (position, candidate, count) in results {
-        if position != current_position {
-            current_position = position.clone();
-            println!("\nPosition: {}", current_position);
-        }
-        println!("{} - {} votes", candidate, count);
-    }
-}
-
-/// Helper function for getting trimmed input from user.
-fn get_input(prompt: &str) -> String {
-    print!("{}", prompt);
-    io::stdout().flush().unwrap(); // Ensure the prompt appears for the user
-    let mut input = String::new();
-    io::stdin().read_line(&mut input).unwrap();
-    input.trim().to_string()
-}
+use crate::database::Database;       // Import the Database helper for SQLite access
+use crate::signing;                  // Authority signature over exported results
+use crate::irv::{self, RoundOutcome}; // Instant-runoff count and round outcomes
+use crate::voter_roll;               // Voter roll export
+use std::io::{self, Write};          // Used for input/output operations
+
+/// The main menu handler for district officials.
+/// Displays options to manage elections and performs operations on the database.
+/// Returns `false` when the user selects "Logout".
+pub fn handle_menu() -> bool {
+    // Connect to the database (creates it if it doesn’t exist)
+    let db = Database::new("e_voting.db").expect("Failed to initialize database");
+
+    // Menu loop continues until user logs out
+    loop {
+        println!("\n--- District Official Menu ---");
+        println!("1. List Elections");
+        println!("2. Open Election");
+        println!("3. Close Election");
+        println!("4. View Election Status");
+        println!("5. Tally Results");
+        println!("6. Tally Results by District");
+        println!("7. Export Voter Roll");
+        println!("8. Logout");
+
+        // Get user’s menu choice
+        let choice = get_input("Select an option: ");
+
+        // Match user input to action
+        match choice.trim() {
+            "1" => list_elections(&db),
+            "2" => open_election(&db),
+            "3" => close_election(&db),
+            "4" => view_status(&db),
+            "5" => tally_results(&db),
+            "6" => tally_by_district(&db),
+            "7" => voter_roll::handle_export(&db),
+            "8" => return false, // Exit back to main menu
+            _ => println!("Invalid option"),
+        }
+    }
+}
+
+/// Lists all the ewlections from the database.
+/// Displays ID, name, and status of each election.
+fn list_elections(db: &Database) {
+    let elections = db.list_elections().unwrap();
+    println!("ID | Name | Status");
+    for (id, name, status) in elections {
+        println!("{} | {} | {}", id, name, status);
+    }
+}
+
+/// Opens an election by its ID.
+/// Changes its status to open in db here. A closed election that already has ballots is final:
+/// its results can be tallied, so reopening it would allow voting after seeing them.
+fn open_election(db: &Database) {
+    let id = get_input("Enter election ID to open: ").parse::<i64>().unwrap();
+    if db.count_ballots(id).unwrap() > 0 {
+        println!("Election {} already has ballots and was closed; it cannot be reopened.", id);
+        return;
+    }
+    db.open_election(id).unwrap();
+    println!("Election {} is now open.", id);
+}
+
+/// Closes an election by it's ID here
+/// Updates its status to "closed" in the database and publishes the signed Merkle root of its ballots,
+/// which voters check their inclusion proofs against.
+fn close_election(db: &Database) {
+    let id = get_input("Enter election ID to close: ").parse::<i64>().unwrap();
+    db.close_election(id).unwrap();
+    println!("Election {} is now closed.", id);
+    match db.publish_ballot_root(id) {
+        Ok((root, count)) => println!("✅ Published ballot Merkle root over {} ballots: {}", count, root),
+        Err(e) => println!("❌ Failed to publish the ballot Merkle root: {}", e),
+    }
+}
+
+/// Displays the currentt status (open/closed) of a specific election.
+fn view_status(db: &Database) {
+    let id = get_input("Enter election ID to view status: ").parse::<i64>().unwrap();
+    let status = db.get_election_status(id).unwrap();
+    println!("Election {} status: {}", id, status);
+}
+
+/// Tallies all votes for a given election.
+/// Displays the count of votes per candidate and position, or the instant-runoff rounds of a ranked-choice election.
+/// Ballots are decrypted only once the election is closed.
+/// The results are also written to a file signed by the election authority, which the `verify` command can check.
+fn tally_results(db: &Database) {
+    let id = get_input("Enter election ID to tally: ").parse::<i64>().unwrap();
+    let ranked = match db.get_election_type(id) {
+        Ok(election_type) => election_type == "ranked",
+        Err(e) => {
+            println!("Cannot tally election {}: {}", id, e);
+            return;
+        }
+    };
+    let body = if ranked { tally_ranked(db, id) } else { tally_plurality(db, id) };
+    let Some(body) = body else { return };
+
+    let path = format!("election_{}_results.txt", id);
+    match write_signed_results(&path, &body) {
+        Ok(()) => println!("\n✅ Signed results written to {}", path),
+        Err(e) => println!("\n❌ Failed to write signed results: {}", e),
+    }
+}
+
+/// Prints the votes per candidate of a plurality election and returns them as lines for the results file
+fn tally_plurality(db: &Database, id: i64) -> Option<String> {
+    let results = match db.tally_results(id) {
+        Ok(results) => results,
+        Err(e) => {
+            println!("Cannot tally election {}: {}", id, e);
+            return None;
+        }
+    };
+
+    println!("\n--- Tally Results ---");
+
+    // Tracks position changes to group results neatly
+    let mut current_position = String::new();
+    let mut body = format!("election: {}\n", id);
+    for (position, candidate, count) in &results {
+        if *position != current_position {
+            current_position = position.clone();
+            println!("\nPosition: {}", current_position);
+        }
+        println!("{} - {} votes", candidate, count);
+        body.push_str(&format!("position: {} | candidate: {} | votes: {}\n", position, candidate, count));
+    }
+    Some(body)
+}
+
+/// Prints the instant-runoff count of a ranked-choice election round by round
+/// and returns it as lines for the results file
+fn tally_ranked(db: &Database, id: i64) -> Option<String> {
+    let results = match db.tally_ranked(id) {
+        Ok(results) => results,
+        Err(e) => {
+            println!("Cannot tally election {}: {}", id, e);
+            return None;
+        }
+    };
+
+    println!("\n--- Tally Results (instant runoff) ---");
+
+    let mut body = format!("election: {}\n", id);
+    for irv::PositionCount { position, candidates, rounds } in &results {
+        let name = |candidate_id: &i64| {
+            candidates.iter().find(|(c, _)| c == candidate_id).map_or("?", |(_, name)| name.as_str())
+        };
+        let names = |candidate_ids: &[i64]| candidate_ids.iter().map(name).collect::<Vec<_>>().join(", ");
+        println!("\nPosition: {}", position);
+
+        for (i, round) in rounds.iter().enumerate() {
+            let number = i + 1;
+            println!("Round {}:", number);
+            for (candidate_id, count) in &round.counts {
+                println!("  {} - {} votes", name(candidate_id), count);
+                body.push_str(&format!("position: {} | round: {} | candidate: {} | votes: {}\n", position, number, name(candidate_id), count));
+            }
+            println!("  Exhausted ballots: {}", round.exhausted);
+            body.push_str(&format!("position: {} | round: {} | exhausted: {}\n", position, number, round.exhausted));
+
+            let outcome = match &round.outcome {
+                RoundOutcome::Eliminated(out) => {
+                    println!("  Eliminated: {}", name(out));
+                    format!("eliminated: {}", name(out))
+                }
+                RoundOutcome::Winner(winner) => {
+                    println!("Winner: {}", name(winner));
+                    format!("winner: {}", name(winner))
+                }
+                RoundOutcome::Tie(tied) => {
+                    println!("No winner yet: {} are tied and the tie must be broken as election rules provide.", names(tied));
+                    format!("tie: {}", names(tied))
+                }
+            };
+            body.push_str(&format!("position: {} | round: {} | {}\n", position, number, outcome));
+        }
+    }
+    Some(body)
+}
+
+/// Displays the votes per candidate separately for each district that cast ballots.
+/// For a ranked-choice election these are first preferences; the winner comes from the full count.
+fn tally_by_district(db: &Database) {
+    let id = get_input("Enter election ID to tally: ").parse::<i64>().unwrap();
+    let results = match db.tally_by_district(id) {
+        Ok(results) => results,
+        Err(e) => {
+            println!("Cannot tally election {}: {}", id, e);
+            return;
+        }
+    };
+    if db.get_election_type(id).map(|t| t == "ranked").unwrap_or(false) {
+        println!("\n--- First Preferences by District ---");
+    } else {
+        println!("\n--- Tally Results by District ---");
+    }
+
+    // Tracks district and position changes to group results neatly
+    let mut current = (String::new(), String::new());
+    for (district, position, candidate, count) in results {
+        if district != current.0 {
+            println!("\nDistrict: {}", district);
+            current = (district, String::new());
+        }
+        if position != current.1 {
+            println!("  Position: {}", position);
+            current.1 = position;
+        }
+        println!("    {} - {} votes", candidate, count);
+    }
+}
+
+/// Write tally result lines followed by the authority's signature over those lines.
+fn write_signed_results(path: &str, body: &str) -> Result<(), String> {
+    let document = signing::sign_document(body)?;
+    std::fs::write(path, document).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// Helper function for getting trimmed input from user.
+fn get_input(prompt: &str) -> String {
+    print!("{}", prompt);
+    io::stdout().flush().unwrap(); // Ensure the prompt appears for the user
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).unwrap();
+    input.trim().to_string()
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+
+
+/// How a round of instant-runoff counting ended
+pub enum RoundOutcome {
+    /// The candidate holds a majority of the ballots still in play, or is the last one left
+    Winner(i64),
+    /// The candidate with the fewest votes
+    Eliminated(i64),
+    /// These candidates share the fewest votes, in this round and every earlier one,
+    /// so the count cannot continue until the tie is broken as election rules provide
+    Tie(Vec<i64>),
+}
+
+
+/// One round: votes per remaining candidate, ballots with no remaining candidate left, and the outcome
+pub struct Round {
+    pub counts: Vec<(i64, i64)>,
+    pub exhausted: i64,
+    pub outcome: RoundOutcome,
+}
+
+
+/// Instant-runoff count of one position, with its candidates' ids and names
+pub struct PositionCount {
+    pub position: String,
+    pub candidates: Vec<(i64, String)>,
+    pub rounds: Vec<Round>,
+}
+
+
+/// Instant-runoff count for one position. Each ballot counts for its highest ranked candidate still in the race;
+/// candidate ids a ballot holds that are not in `candidates` (such as padding) are skipped.
+/// Rounds are counted until a candidate has more than half of the ballots still in play.
+/// A tie for the fewest votes is broken by the most recent earlier round in which the tied candidates differ.
+pub fn count(candidates: &[i64], ballots: &[Vec<i64>]) -> Vec<Round> {
+    let mut remaining: Vec<i64> = candidates.to_vec();
+    let mut rounds = Vec::new();
+
+    loop {
+        let mut votes: HashMap<i64, i64> = remaining.iter().map(|&c| (c, 0)).collect();
+        let mut exhausted = 0;
+        for ballot in ballots {
+            match ballot.iter().find(|c| votes.contains_key(c)) {
+                Some(choice) => *votes.get_mut(choice).unwrap() += 1,
+                None => exhausted += 1,
+            }
+        }
+        let counts: Vec<(i64, i64)> = remaining.iter().map(|c| (*c, votes[c])).collect();
+        let in_play: i64 = counts.iter().map(|(_, n)| n).sum();
+
+        let leader = counts.iter().max_by_key(|(_, n)| *n).copied();
+        let outcome = match leader {
+            None => RoundOutcome::Tie(Vec::new()),
+            Some((c, n)) if remaining.len() == 1 || n * 2 > in_play => RoundOutcome::Winner(c),
+            Some(_) => match fewest_votes(&counts, &rounds) {
+                last if last.len() == 1 => RoundOutcome::Eliminated(last[0]),
+                last => RoundOutcome::Tie(last),
+            },
+        };
+        let done = !matches!(outcome, RoundOutcome::Eliminated(_));
+        if let RoundOutcome::Eliminated(out) = outcome {
+            remaining.retain(|c| *c != out);
+        }
+        rounds.push(Round { counts, exhausted, outcome });
+        if done {
+            return rounds;
+        }
+    }
+}
+
+
+/// Candidates with the fewest votes in this round, narrowed down by earlier rounds (latest first) while they are tied
+fn fewest_votes(counts: &[(i64, i64)], earlier: &[Round]) -> Vec<i64> {
+    let mut last: Vec<i64> = counts.iter().map(|(c, _)| *c).collect();
+    for round in std::iter::once(counts).chain(earlier.iter().rev().map(|r| r.counts.as_slice())) {
+        let votes = |c: i64| round.iter().find(|(candidate, _)| *candidate == c).map_or(0, |(_, n)| *n);
+        let fewest = last.iter().map(|c| votes(*c)).min().unwrap_or(0);
+        last.retain(|c| votes(*c) == fewest);
+        if last.len() == 1 {
+            break;
+        }
+    }
+    last
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn majority_in_the_first_round_wins() {
+        let ballots = vec![vec![1, 2], vec![1], vec![1, 3], vec![2, 1], vec![3]];
+        let rounds = count(&[1, 2, 3], &ballots);
+        assert_eq!(rounds.len(), 1);
+        assert_eq!(rounds[0].counts, vec![(1, 3), (2, 1), (3, 1)]);
+        assert!(matches!(rounds[0].outcome, RoundOutcome::Winner(1)));
+    }
+
+    #[test]
+    fn eliminated_candidate_votes_move_to_next_preferences() {
+        let ballots = vec![vec![1], vec![1], vec![2], vec![2], vec![3, 2]];
+        let rounds = count(&[1, 2, 3], &ballots);
+        assert_eq!(rounds.len(), 2);
+        assert!(matches!(rounds[0].outcome, RoundOutcome::Eliminated(3)));
+        assert_eq!(rounds[1].counts, vec![(1, 2), (2, 3)]);
+        assert!(matches!(rounds[1].outcome, RoundOutcome::Winner(2)));
+    }
+
+    #[test]
+    fn tie_for_fewest_votes_is_broken_by_earlier_rounds() {
+        let mut ballots = vec![vec![1]; 4];
+        ballots.extend(vec![vec![2]; 3]);
+        ballots.extend(vec![vec![3, 1]; 2]);
+        ballots.push(vec![4, 3, 1]);
+        let rounds = count(&[1, 2, 3, 4], &ballots);
+
+        assert!(matches!(rounds[0].outcome, RoundOutcome::Eliminated(4)));
+        // 2 and 3 both have 3 votes now, but 3 had fewer in round 1
+        assert_eq!(rounds[1].counts, vec![(1, 4), (2, 3), (3, 3)]);
+        assert!(matches!(rounds[1].outcome, RoundOutcome::Eliminated(3)));
+        assert_eq!(rounds[2].counts, vec![(1, 7), (2, 3)]);
+        assert!(matches!(rounds[2].outcome, RoundOutcome::Winner(1)));
+    }
+
+    #[test]
+    fn tie_in_every_round_stops_the_count() {
+        let ballots = vec![vec![1], vec![1], vec![2], vec![2]];
+        let rounds = count(&[1, 2, 3], &ballots);
+        assert!(matches!(rounds[0].outcome, RoundOutcome::Eliminated(3)));
+        match &rounds[1].outcome {
+            RoundOutcome::Tie(tied) => assert_eq!(tied, &vec![1, 2]),
+            _ => panic!("expected a tie"),
+        }
+    }
+
+    #[test]
+    fn ballots_without_remaining_preferences_are_exhausted() {
+        // 0 is ranking padding, not a candidate
+        let ballots = vec![vec![1, 0, 0], vec![1, 0, 0], vec![1, 0, 0], vec![2, 0, 0], vec![2, 0, 0], vec![3, 0, 0]];
+        let rounds = count(&[1, 2, 3], &ballots);
+        assert_eq!(rounds[0].exhausted, 0);
+        assert!(matches!(rounds[0].outcome, RoundOutcome::Eliminated(3)));
+        assert_eq!(rounds[1].exhausted, 1);
+        assert_eq!(rounds[1].counts, vec![(1, 3), (2, 2)]);
+        // 3 of the 5 ballots still in play is a majority
+        assert!(matches!(rounds[1].outcome, RoundOutcome::Winner(1)));
+    }
+}
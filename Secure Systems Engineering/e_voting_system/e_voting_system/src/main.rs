@@ -1,91 +1,213 @@
-// Import local modules that handle different roles and functionality
-mod admin;
-mod district;
-mod voter;
-mod auth;
-mod database;
-mod audit;
-
-// Bring key functions and structs into scope for easier use
-use crate::admin::handle_menu as admin_menu;        // Admin menu logic
-use crate::district::handle_menu as district_menu;  // District official menu
-use crate::voter::handle_menu as voter_menu;        // Voter menu
-use crate::auth::Auth;                              // Authentication handler
-use crate::database::Database;                      // Database wrapper
-
-// Standard I/O imports for user input and output
-use std::io::{self, Write};
-
-/// The entry point of the e-voting system.
-/// Displays a role selection menu and directs the user to the appropriate module.
-fn main() {
-    // Initialize the authentication system
-    let auth = Auth::new();
-
-    // Main program loop — runs until the user chooses to exit
-    loop {
-        println!("\nSelect your role:");
-        println!("1. Election Admin");
-        println!("2. District Official");
-        println!("3. Voter");
-        println!("4. View Audit Log");
-        println!("5. Exit");
-
-        // Ask for user input
-        let choice = get_input("Select an option: ");
-
-        // Match user selection to corresponding action
-        match choice.trim() {
-            // Admin: requires successful authentication
-            "1" => {
-                if auth.login("admin") {
-                    let _ = admin_menu();
-                } else {
-                    println!("Login failed!");
-                }
-            },
-
-            // District official: also requires authentication
-            "2" => {
-                if auth.login("district") {
-                    let _ = district_menu();
-                } else {
-                    println!("Login failed!");
-                }
-            },
-
-            // Voter: opens voter menu (no login required)
-            "3" => { 
-                let _ = voter_menu(); 
-            },
-
-            // Audit log viewer: connects to database and displays audit info
-            "4" => {
-                if auth.login("audit"){
-                  let db = Database::new("e_voting.db").expect("Failed to initialize database");
-                  audit::show_audit_log(db.connection());
-                  } else {
-                  println!("Login failed!");
-                  }
-            },
-
-            // Exit option: breaks out of main loop, ending the program
-            "5" => break,
-
-            // Catch invalid options
-            _ => println!("Invalid option"),
-        }
-    }
-
-    println!("Exiting system. Goodbye!");
-}
-
-/// Helper function to get trimmed user input from the console.
-/// Prints a prompt, reads user input, and returns it as a `String`.
-fn get_input(prompt: &str) -> String {
-    print!("{}", prompt);
-    io::stdout().flush().unwrap(); // Ensure the prompt is printed before input
-    let mut input = String::new();
-    io::stdin().read_line(&mut input).unwrap();
-    input.trim().to_string()
-}
+// Import local modules that handle different roles and functionality
+mod admin;
+mod district;
+mod voter;
+mod auth;
+mod database;
+mod audit;
+mod ballot_crypto;
+mod signing;
+mod merkle;
+mod irv;
+mod voter_roll;
+mod results_export;
+
+// Bring key functions and structs into scope for easier use
+use crate::admin::handle_menu as admin_menu;        // Admin menu logic
+use crate::district::handle_menu as district_menu;  // District official menu
+use crate::voter::handle_menu as voter_menu;        // Voter menu
+use crate::auth::Auth;                              // Authentication handler
+use crate::database::Database;                      // Database wrapper
+
+// Standard I/O imports for user input and output
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use clap::{Parser, Subcommand};
+
+
+/// Command line: without a subcommand the interactive menu starts
+#[derive(Parser)]
+#[command(name = "e_voting_system", about = "Electronic voting system")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Non-interactive commands
+#[derive(Subcommand)]
+enum Command {
+    /// Walk the audit log hash chain and report the first tampered or unsigned record
+    VerifyAudit {
+        /// Election authority public key (hex)
+        #[arg(long, default_value = signing::PUBLIC_KEY_PATH)]
+        public_key: String,
+    },
+    /// Check the authority's signature on an exported results file
+    Verify {
+        /// Results file written by the tally or by export-results
+        file: PathBuf,
+        /// Election authority public key (hex)
+        #[arg(long, default_value = signing::PUBLIC_KEY_PATH)]
+        public_key: String,
+        /// Detached signature of the file (the .sig written by export-results);
+        /// without it the signature is expected on the file's last line
+        #[arg(long)]
+        signature: Option<PathBuf>,
+    },
+    /// Export the results of a closed election per position, candidate and district, signed by the election authority.
+    /// Asks for an election admin login first
+    ExportResults {
+        /// Closed election to export
+        election_id: i64,
+        #[arg(long, value_enum, default_value = "json")]
+        format: results_export::Format,
+        /// Output file [default: election_<id>_results.json or .csv]
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Show the Merkle inclusion proof of a ballot in its closed election's published root
+    BallotProof {
+        /// Tracking code given when the ballot was cast
+        tracking_code: String,
+    },
+}
+
+/// The entry point of the e-voting system.
+/// Runs a subcommand if one is given, otherwise displays a role selection menu and directs the user to the appropriate module.
+fn main() -> ExitCode {
+    match Cli::parse().command {
+        Some(Command::VerifyAudit { public_key }) => {
+            let db = Database::new("e_voting.db").expect("Failed to initialize database");
+            return if audit::report_chain(db.connection(), &public_key) { ExitCode::SUCCESS } else { ExitCode::FAILURE };
+        }
+        Some(Command::Verify { file, public_key, signature }) => {
+            // Works without the database or the signing key: only the file and the published public key are needed
+            let result = signing::read_public_key(&public_key).and_then(|key| {
+                let document = std::fs::read_to_string(&file).map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+                match &signature {
+                    Some(signature) => {
+                        let signature = std::fs::read_to_string(signature)
+                            .map_err(|e| format!("Failed to read {}: {}", signature.display(), e))?;
+                        if signing::verify(&key, document.as_bytes(), signature.trim()) {
+                            Ok(())
+                        } else {
+                            Err("The signature does not match the contents or the public key.".to_string())
+                        }
+                    }
+                    None => signing::verify_document(&key, &document),
+                }
+            });
+            return match result {
+                Ok(()) => {
+                    println!("✅ {} is signed by the election authority.", file.display());
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    println!("❌ {} failed verification: {}", file.display(), e);
+                    ExitCode::FAILURE
+                }
+            };
+        }
+        Some(Command::ExportResults { election_id, format, output }) => {
+            // Unlike verify and ballot-proof this reads the database on the authority's behalf, so only an admin may run it
+            if Auth::new().login("admin").is_none() {
+                println!("Login failed!");
+                return ExitCode::FAILURE;
+            }
+            let db = Database::new("e_voting.db").expect("Failed to initialize database");
+            let extension = match format {
+                results_export::Format::Json => "json",
+                results_export::Format::Csv => "csv",
+            };
+            let output = output.unwrap_or_else(|| PathBuf::from(format!("election_{}_results.{}", election_id, extension)));
+            return match results_export::export(&db, election_id, format, &output) {
+                Ok(signature_path) => {
+                    println!("✅ Results written to {}, signature to {}.", output.display(), signature_path);
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    println!("❌ {}", e);
+                    ExitCode::FAILURE
+                }
+            };
+        }
+        Some(Command::BallotProof { tracking_code }) => {
+            let db = Database::new("e_voting.db").expect("Failed to initialize database");
+            return if voter::show_inclusion_proof(&db, &tracking_code) { ExitCode::SUCCESS } else { ExitCode::FAILURE };
+        }
+        None => {}
+    }
+
+    // Initialize the authentication system
+    let auth = Auth::new();
+    auth.ensure_admin();
+
+    // Main program loop — runs until the user chooses to exit
+    loop {
+        println!("\nSelect your role:");
+        println!("1. Election Admin");
+        println!("2. District Official");
+        println!("3. Voter");
+        println!("4. View Audit Log");
+        println!("5. Exit");
+
+        // Ask for user input
+        let choice = get_input("Select an option: ");
+
+        // Match user selection to corresponding action
+        match choice.trim() {
+            // Admin: requires successful authentication
+            "1" => {
+                if let Some(username) = auth.login("admin") {
+                    let _ = admin_menu(&username);
+                } else {
+                    println!("Login failed!");
+                }
+            },
+
+            // District official: also requires authentication
+            "2" => {
+                if auth.login("district").is_some() {
+                    let _ = district_menu();
+                } else {
+                    println!("Login failed!");
+                }
+            },
+
+            // Voter: opens voter menu (no login required)
+            "3" => { 
+                let _ = voter_menu(); 
+            },
+
+            // Audit log viewer: connects to database and displays audit info
+            "4" => {
+                if auth.login("audit").is_some() {
+                  let db = Database::new("e_voting.db").expect("Failed to initialize database");
+                  audit::show_audit_log(db.connection());
+                  } else {
+                  println!("Login failed!");
+                  }
+            },
+
+            // Exit option: breaks out of main loop, ending the program
+            "5" => break,
+
+            // Catch invalid options
+            _ => println!("Invalid option"),
+        }
+    }
+
+    println!("Exiting system. Goodbye!");
+    ExitCode::SUCCESS
+}
+
+/// Helper function to get trimmed user input from the console.
+/// Prints a prompt, reads user input, and returns it as a `String`.
+fn get_input(prompt: &str) -> String {
+    print!("{}", prompt);
+    io::stdout().flush().unwrap(); // Ensure the prompt is printed before input
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).unwrap();
+    input.trim().to_string()
+}
@@ -0,0 +1,175 @@
+use sha2::{Digest, Sha256};
+
+
+/// One step of an inclusion proof: the sibling hash and whether it sits left of the running hash
+pub struct ProofStep {
+    pub sibling: [u8; 32],
+    pub sibling_is_left: bool,
+}
+
+
+/// Hash of a stored ballot, computed when it is cast. The tracking code is part of it,
+/// so a voter's code picks out exactly one leaf. Fields are length-prefixed as in the audit chain.
+/// The district is only hashed when the ballot has one, so ballots cast before districts keep their hash.
+pub fn ballot_hash(ballot_id: &str, election_id: i64, position_id: i64, sealed_choice: &str, tracking_code: Option<&str>, district_id: Option<i64>) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(b"ballot");
+    for field in [ballot_id, &election_id.to_string(), &position_id.to_string(), sealed_choice] {
+        hasher.update(format!("|{}:{}", field.len(), field).as_bytes());
+    }
+    match tracking_code {
+        Some(code) => hasher.update(format!("|{}:{}", code.len(), code).as_bytes()),
+        None => hasher.update(b"|-"),
+    }
+    if let Some(district_id) = district_id {
+        let district = district_id.to_string();
+        hasher.update(format!("|district|{}:{}", district.len(), district).as_bytes());
+    }
+    hex::encode(hasher.finalize())
+}
+
+
+/// Leaves and inner nodes are hashed with different prefixes, so a node can never pass as a ballot
+fn leaf(ballot_hash: &[u8; 32]) -> [u8; 32] {
+    Sha256::new().chain_update([0u8]).chain_update(ballot_hash).finalize().into()
+}
+
+
+fn node(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    Sha256::new().chain_update([1u8]).chain_update(left).chain_update(right).finalize().into()
+}
+
+
+/// Decode a hex ballot hash as stored in the database
+pub fn decode_hash(hex_hash: &str) -> Option<[u8; 32]> {
+    hex::decode(hex_hash).ok().and_then(|bytes| bytes.try_into().ok())
+}
+
+
+/// Next level up: pairs are hashed together, an odd node at the end is carried up unchanged
+fn parent_level(level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    level
+        .chunks(2)
+        .map(|pair| if pair.len() == 2 { node(&pair[0], &pair[1]) } else { pair[0] })
+        .collect()
+}
+
+
+/// Merkle root over ballot hashes in the given order; an election without ballots has the hash of nothing
+pub fn root(ballot_hashes: &[[u8; 32]]) -> [u8; 32] {
+    if ballot_hashes.is_empty() {
+        return Sha256::digest(b"").into();
+    }
+    let mut level: Vec<[u8; 32]> = ballot_hashes.iter().map(leaf).collect();
+    while level.len() > 1 {
+        level = parent_level(&level);
+    }
+    level[0]
+}
+
+
+/// Sibling hashes from the leaf at `index` up to the root
+pub fn proof(ballot_hashes: &[[u8; 32]], index: usize) -> Vec<ProofStep> {
+    let mut steps = Vec::new();
+    let mut level: Vec<[u8; 32]> = ballot_hashes.iter().map(leaf).collect();
+    let mut index = index;
+    while level.len() > 1 {
+        let sibling = index ^ 1;
+        if sibling < level.len() {
+            steps.push(ProofStep { sibling: level[sibling], sibling_is_left: sibling < index });
+        }
+        level = parent_level(&level);
+        index /= 2;
+    }
+    steps
+}
+
+
+/// Recompute the root from a ballot hash and its proof
+pub fn verify_proof(ballot_hash: &[u8; 32], steps: &[ProofStep], expected_root: &[u8; 32]) -> bool {
+    let mut hash = leaf(ballot_hash);
+    for step in steps {
+        hash = if step.sibling_is_left { node(&step.sibling, &hash) } else { node(&hash, &step.sibling) };
+    }
+    hash == *expected_root
+}
+
+
+/// Text the election authority signs when it publishes a root
+pub fn root_statement(election_id: i64, ballot_count: i64, root_hex: &str) -> String {
+    format!("election: {}\nballots: {}\nmerkle_root: {}\n", election_id, ballot_count, root_hex)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hashes(n: usize) -> Vec<[u8; 32]> {
+        (0..n).map(|i| decode_hash(&ballot_hash(&format!("ballot-{}", i), 1, 1, "sealed", None, None)).unwrap()).collect()
+    }
+
+    /// Root as published (hex in ballot_roots) and read back by a voter
+    fn published_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+        decode_hash(&hex::encode(root(leaves))).unwrap()
+    }
+
+    #[test]
+    fn single_ballot_is_its_own_leaf() {
+        let leaves = hashes(1);
+        assert_eq!(root(&leaves), leaf(&leaves[0]));
+        assert!(proof(&leaves, 0).is_empty());
+        assert!(verify_proof(&leaves[0], &[], &published_root(&leaves)));
+    }
+
+    #[test]
+    fn two_ballots_are_hashed_in_order() {
+        let leaves = hashes(2);
+        assert_eq!(root(&leaves), node(&leaf(&leaves[0]), &leaf(&leaves[1])));
+        for i in 0..2 {
+            assert!(verify_proof(&leaves[i], &proof(&leaves, i), &published_root(&leaves)));
+        }
+    }
+
+    #[test]
+    fn odd_node_is_carried_up() {
+        let leaves = hashes(3);
+        let expected = node(&node(&leaf(&leaves[0]), &leaf(&leaves[1])), &leaf(&leaves[2]));
+        assert_eq!(root(&leaves), expected);
+        // the carried-up leaf only needs the sibling from the level it was paired at
+        assert_eq!(proof(&leaves, 2).len(), 1);
+    }
+
+    #[test]
+    fn every_proof_verifies_against_the_published_root() {
+        for n in [3, 5, 6, 7, 8] {
+            let leaves = hashes(n);
+            let published = published_root(&leaves);
+            for i in 0..n {
+                assert!(verify_proof(&leaves[i], &proof(&leaves, i), &published), "leaf {} of {}", i, n);
+            }
+        }
+    }
+
+    #[test]
+    fn changed_leaf_or_sibling_is_rejected() {
+        let leaves = hashes(5);
+        let published = published_root(&leaves);
+        let steps = proof(&leaves, 1);
+
+        let mut changed_leaf = leaves[1];
+        changed_leaf[0] ^= 1;
+        assert!(!verify_proof(&changed_leaf, &steps, &published));
+        assert!(!verify_proof(&leaves[0], &steps, &published));
+
+        for i in 0..steps.len() {
+            let mut changed = proof(&leaves, 1);
+            changed[i].sibling[31] ^= 1;
+            assert!(!verify_proof(&leaves[1], &changed, &published));
+
+            let mut swapped = proof(&leaves, 1);
+            swapped[i].sibling_is_left = !swapped[i].sibling_is_left;
+            assert!(!verify_proof(&leaves[1], &swapped, &published));
+        }
+    }
+}
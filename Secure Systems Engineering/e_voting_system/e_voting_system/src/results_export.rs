@@ -0,0 +1,224 @@
+use crate::database::Database;
+use crate::irv::{self, RoundOutcome};
+use crate::signing;
+use serde::Serialize;
+use std::path::Path;
+
+
+/// File formats of a results export
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum Format {
+    Json,
+    Csv,
+}
+
+
+/// Results of a closed election as published: totals per position and candidate, and votes per district
+#[derive(Serialize)]
+pub struct ElectionResults {
+    election_id: i64,
+    name: String,
+    election_type: String,
+    ballots: i64,
+    /// Signed Merkle root over the ballots, which voters check their inclusion proofs against
+    merkle_root: Option<String>,
+    positions: Vec<PositionResults>,
+    districts: Vec<DistrictVotes>,
+}
+
+
+#[derive(Serialize)]
+struct PositionResults {
+    position: String,
+    /// Votes per candidate; first preferences in a ranked-choice election
+    candidates: Vec<CandidateVotes>,
+    /// Sole candidate with the most votes, or the instant-runoff winner; none on a tie
+    winner: Option<String>,
+    /// Instant-runoff rounds, in ranked-choice elections only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rounds: Option<Vec<RoundResults>>,
+}
+
+
+#[derive(Serialize)]
+struct CandidateVotes {
+    candidate: String,
+    votes: i64,
+}
+
+
+#[derive(Serialize)]
+struct RoundResults {
+    round: usize,
+    candidates: Vec<CandidateVotes>,
+    exhausted: i64,
+    eliminated: Option<String>,
+    /// Candidates tied for the fewest votes when the count stopped on a tie
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tied: Vec<String>,
+}
+
+
+/// Votes for one candidate from the voters of one district; first preferences in a ranked-choice election
+#[derive(Serialize)]
+struct DistrictVotes {
+    district: String,
+    position: String,
+    candidate: String,
+    votes: i64,
+}
+
+
+/// Count a closed election the same way the district official's tally does
+pub fn collect(db: &Database, election_id: i64) -> Result<ElectionResults, String> {
+    let db_error = |e: rusqlite::Error| format!("Cannot export election {}: {}", election_id, e);
+    let name = db.get_election_name(election_id).map_err(db_error)?;
+    let election_type = db.get_election_type(election_id).map_err(db_error)?;
+    let positions = if election_type == "ranked" {
+        db.tally_ranked(election_id).map_err(db_error)?.iter().map(ranked_position).collect()
+    } else {
+        plurality_positions(db.tally_results(election_id).map_err(db_error)?)
+    };
+    let districts = db
+        .tally_by_district(election_id)
+        .map_err(db_error)?
+        .into_iter()
+        .map(|(district, position, candidate, votes)| DistrictVotes { district, position, candidate, votes })
+        .collect();
+
+    Ok(ElectionResults {
+        election_id,
+        name,
+        election_type,
+        ballots: db.count_ballots(election_id).map_err(db_error)?,
+        merkle_root: db.get_ballot_root(election_id).map_err(db_error)?.map(|(root, _, _)| root),
+        positions,
+        districts,
+    })
+}
+
+
+/// Group the plurality tally, which is ordered by position, into positions
+fn plurality_positions(results: Vec<(String, String, i64)>) -> Vec<PositionResults> {
+    let mut positions: Vec<PositionResults> = Vec::new();
+    for (position, candidate, votes) in results {
+        match positions.last_mut() {
+            Some(last) if last.position == position => last.candidates.push(CandidateVotes { candidate, votes }),
+            _ => positions.push(PositionResults {
+                position,
+                candidates: vec![CandidateVotes { candidate, votes }],
+                winner: None,
+                rounds: None,
+            }),
+        }
+    }
+    for position in &mut positions {
+        let most = position.candidates.iter().map(|c| c.votes).max().unwrap_or(0);
+        let leaders: Vec<&CandidateVotes> = position.candidates.iter().filter(|c| c.votes == most).collect();
+        if most > 0 && leaders.len() == 1 {
+            position.winner = Some(leaders[0].candidate.clone());
+        }
+    }
+    positions
+}
+
+
+fn ranked_position(count: &irv::PositionCount) -> PositionResults {
+    let name = |candidate_id: &i64| {
+        count.candidates.iter().find(|(c, _)| c == candidate_id).map_or("?".to_string(), |(_, name)| name.clone())
+    };
+    let votes = |counts: &[(i64, i64)]| -> Vec<CandidateVotes> {
+        counts.iter().map(|(c, votes)| CandidateVotes { candidate: name(c), votes: *votes }).collect()
+    };
+
+    let rounds: Vec<RoundResults> = count
+        .rounds
+        .iter()
+        .enumerate()
+        .map(|(i, round)| RoundResults {
+            round: i + 1,
+            candidates: votes(&round.counts),
+            exhausted: round.exhausted,
+            eliminated: match &round.outcome {
+                RoundOutcome::Eliminated(out) => Some(name(out)),
+                _ => None,
+            },
+            tied: match &round.outcome {
+                RoundOutcome::Tie(tied) => tied.iter().map(name).collect(),
+                _ => Vec::new(),
+            },
+        })
+        .collect();
+    let winner = match count.rounds.last().map(|round| &round.outcome) {
+        Some(RoundOutcome::Winner(winner)) => Some(name(winner)),
+        _ => None,
+    };
+
+    PositionResults {
+        position: count.position.clone(),
+        candidates: count.rounds.first().map(|round| votes(&round.counts)).unwrap_or_default(),
+        winner,
+        rounds: Some(rounds),
+    }
+}
+
+
+pub fn to_json(results: &ElectionResults) -> Result<String, String> {
+    serde_json::to_string_pretty(results).map(|json| json + "\n").map_err(|e| format!("Failed to encode results: {}", e))
+}
+
+
+/// One row per vote count: scope "election" for totals (one set per round in a ranked-choice election)
+/// and scope "district" for the votes of each district. Rounds, eliminations and winners are in the JSON export.
+pub fn to_csv(results: &ElectionResults) -> Result<String, String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let write_error = |e: csv::Error| format!("Failed to encode results: {}", e);
+    writer.write_record(["election_id", "scope", "district", "position", "round", "candidate", "votes"]).map_err(write_error)?;
+    let id = results.election_id.to_string();
+
+    for position in &results.positions {
+        match &position.rounds {
+            Some(rounds) => {
+                for round in rounds {
+                    for c in &round.candidates {
+                        let round_number = round.round.to_string();
+                        writer
+                            .write_record([&id, "election", "", &position.position, &round_number, &c.candidate, &c.votes.to_string()])
+                            .map_err(write_error)?;
+                    }
+                }
+            }
+            None => {
+                for c in &position.candidates {
+                    writer
+                        .write_record([&id, "election", "", &position.position, "", &c.candidate, &c.votes.to_string()])
+                        .map_err(write_error)?;
+                }
+            }
+        }
+    }
+    for d in &results.districts {
+        writer
+            .write_record([&id, "district", &d.district, &d.position, "", &d.candidate, &d.votes.to_string()])
+            .map_err(write_error)?;
+    }
+
+    let bytes = writer.into_inner().map_err(|e| format!("Failed to encode results: {}", e))?;
+    String::from_utf8(bytes).map_err(|e| format!("Failed to encode results: {}", e))
+}
+
+
+/// Write the results of a closed election to `path`, with the authority's signature over the file's bytes
+/// in `<path>.sig`; `verify --signature` checks it. Returns the path of the signature.
+pub fn export(db: &Database, election_id: i64, format: Format, path: &Path) -> Result<String, String> {
+    let results = collect(db, election_id)?;
+    let document = match format {
+        Format::Json => to_json(&results)?,
+        Format::Csv => to_csv(&results)?,
+    };
+    let signature_path = format!("{}.sig", path.display());
+    std::fs::write(path, &document).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    let signature = signing::sign(document.as_bytes())?;
+    std::fs::write(&signature_path, signature + "\n").map_err(|e| format!("Failed to write {}: {}", signature_path, e))?;
+    Ok(signature_path)
+}
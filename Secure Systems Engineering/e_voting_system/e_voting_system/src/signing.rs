@@ -0,0 +1,79 @@
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use std::fs;
+use crate::ballot_crypto;
+
+
+/// Secret signing key of the election authority, created on first use and readable by the owner only
+pub const SIGNING_KEY_PATH: &str = "authority_signing.key";
+
+/// Public key matching the signing key, written next to it for publication.
+/// Anyone holding it can check signed results and audit records without access to the system.
+pub const PUBLIC_KEY_PATH: &str = "authority_public.key";
+
+/// Last line of a signed results file
+const SIGNATURE_PREFIX: &str = "signature: ";
+
+
+/// The authority's signing key; the public key file is (re)written if it is missing
+fn signing_key() -> Result<SigningKey, String> {
+    let key = SigningKey::from_bytes(&ballot_crypto::load_or_create_key(SIGNING_KEY_PATH)?);
+    if !std::path::Path::new(PUBLIC_KEY_PATH).exists() {
+        fs::write(PUBLIC_KEY_PATH, hex::encode(key.verifying_key().to_bytes()))
+            .map_err(|e| format!("Failed to write {}: {}", PUBLIC_KEY_PATH, e))?;
+    }
+    Ok(key)
+}
+
+
+/// Sign a message with the authority key; returns the signature as hex
+pub fn sign(message: &[u8]) -> Result<String, String> {
+    Ok(hex::encode(signing_key()?.sign(message).to_bytes()))
+}
+
+
+/// The authority's public key, as written to PUBLIC_KEY_PATH
+pub fn authority_public_key() -> Result<VerifyingKey, String> {
+    Ok(signing_key()?.verifying_key())
+}
+
+
+/// Read a hex public key from a file, e.g. a published authority_public.key
+pub fn read_public_key(path: &str) -> Result<VerifyingKey, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let bytes: [u8; 32] = hex::decode(text.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| format!("{} does not hold a hex Ed25519 public key.", path))?;
+    VerifyingKey::from_bytes(&bytes).map_err(|_| format!("{} does not hold a valid Ed25519 public key.", path))
+}
+
+
+/// Check a hex signature over a message
+pub fn verify(public_key: &VerifyingKey, message: &[u8], signature_hex: &str) -> bool {
+    let bytes: Option<[u8; 64]> = hex::decode(signature_hex).ok().and_then(|bytes| bytes.try_into().ok());
+    match bytes {
+        Some(bytes) => public_key.verify(message, &Signature::from_bytes(&bytes)).is_ok(),
+        None => false,
+    }
+}
+
+
+/// Append a signature line to a text document
+pub fn sign_document(body: &str) -> Result<String, String> {
+    Ok(format!("{}{}{}\n", body, SIGNATURE_PREFIX, sign(body.as_bytes())?))
+}
+
+
+/// Check a document written by `sign_document`: the last line signs every byte before it
+pub fn verify_document(public_key: &VerifyingKey, document: &str) -> Result<(), String> {
+    let trimmed = document.strip_suffix('\n').unwrap_or(document);
+    let start = trimmed.rfind('\n').map_or(0, |i| i + 1);
+    let signature = trimmed[start..]
+        .strip_prefix(SIGNATURE_PREFIX)
+        .ok_or("The last line is not a signature line.")?;
+    if verify(public_key, &trimmed.as_bytes()[..start], signature) {
+        Ok(())
+    } else {
+        Err("The signature does not match the contents or the public key.".to_string())
+    }
+}
@@ -1,279 +1,523 @@
-use std::io::{self, Write};
-use crate::database::Database;
-use crate::audit;
-use chrono::{NaiveDate, Utc, Datelike};
-use std::collections::HashMap;
-
-
-/// Main Voter Menu
-pub fn handle_menu() -> bool {
-    let db = Database::new("e_voting.db").expect("Failed to initialize database");
-
-
-    // First, ask if existing or new voter
-    let voter_id = match voter_login_or_register(&db) {
-        Some(id) => id,
-        None => return true, // failed login/registration, return to main menu
-    };
-// Show voter menu
-    loop {
-        println!("\n--- VOTER MENU ---");
-        println!("1. View Open Elections");
-        println!("2. Cast Ballot");
-        println!("3. Verify My Ballot");
-        println!("4. Logout");
-
-
-        let choice = get_input("Select an option: ");
-
-
-        match choice.trim() {
-            "1" => handle_view_open_elections(&db),
-            "2" => handle_cast_ballot(&db, voter_id),
-            "3" => handle_verify_ballot(&db, voter_id),
-            "4" => break,
-            _ => println!("Invalid option"),
-        }
-    }
-
-
-    true
-}
-
-
-/// Ask if voter is existing or new, handle login/registration
-fn voter_login_or_register(db: &Database) -> Option<i64> {
-    println!("\nAre you an existing voter or a new voter?");
-    println!("1. Existing Voter");
-    println!("2. New Voter");
-
-
-    let choice = get_input("Choice: ");
-  match choice.trim() {
-        "1" => {
-            let full_name = get_input("Enter full name: ");
-            let dob = get_input("Enter date of birth (YYYY-MM-DD): ");
-            match db.get_voter_id(&full_name, &dob) {
-                Ok(Some(id)) => {
-                    println!("Welcome back, {}!", full_name);
-                    Some(id)
-                }
-                Ok(None) => {
-                    println!("Authentication failed. Please check your credentials.");
-                    None
-                }
-                Err(e) => {
-                    println!("Error checking voter: {}", e);
-                    None
-                }
-            }
-        }
-        "2" => {
-            let full_name = get_input("Enter full name: ");
-            let dob_input = get_input("Enter date of birth (YYYY-MM-DD): ");
-
-
-            // Validate DOB format and age
-          let dob = match validate_dob(&dob_input) {
-                Some(date) => date.format("%Y-%m-%d").to_string(),
-                None => return None, // invalid DOB
-            };
-
-
-            match db.register_voter(&full_name, &dob) {
-                Ok(true) => {
-                    println!("✅ Registration successful! Welcome, {}!", full_name);
-                    db.get_voter_id(&full_name, &dob).ok().flatten()
-                }
-                Ok(false) => {
-                    println!("Welcome back, {}!", full_name);
-                    db.get_voter_id(&full_name, &dob).ok().flatten()
-                }
-                Err(e) => {
-                    println!("❌ Failed to register voter: {}", e);
-                    None
-                }
-            }
-        }
-        _ => {
-            println!("Invalid option.");
-            None
-        }
-   }
-}
-
-
-/// List open elections
-fn handle_view_open_elections(db: &Database) {
-    match db.list_open_elections() {
-        Ok(elections) => {
-            println!("\nOpen Elections:");
-            if elections.is_empty() {
-                println!("No open elections at the moment.");
-            }
-            for (id, name) in elections {
-                println!("{}: {}", id, name);
-            }
-        }
-        Err(e) => println!("Failed to list elections: {}", e),
-    }
-}
-
-
-/// Cast ballot
-fn handle_cast_ballot(db: &Database, voter_id: i64) {
-    // List open elections
-    let elections = match db.list_open_elections() {
-        Ok(e) => e,
-        Err(e) => {
-            println!("Failed to get open elections: {}", e);
-            return;
-        }
-    };
-
-
-    if elections.is_empty() {
- println!("No open elections available.");
-        return;
-    }
-
-
-    println!("\nOpen Elections:");
-    for (id, name) in &elections {
-        println!("{}: {}", id, name);
-    }
-
-
-    let election_id: i64 = get_input("Enter the ID of the election you want to vote in: ")
-        .parse().unwrap_or(-1);
-
-
-    let positions = match db.list_positions(election_id) {
-        Ok(p) => p,
-        Err(e) => {
-            println!("Failed to list positions: {}", e);
-            return;
-        }
-    };
-
-
-    for (pos_id, pos_name) in &positions {
-        println!("\nPosition: {} - {}", pos_id, pos_name);
-
-
-        let candidates = match db.list_candidates(*pos_id) {
-            Ok(c) => c,
-            Err(e) => {
-                println!("Failed to listcandidates: {}", e);
-                continue;
-            }
-        };
-
-
-        // Check if voter already voted for this position
-        match db.has_voted(election_id, *pos_id, voter_id) {
-            Ok(true) => {
-                println!("You have already voted for this position.");
-                continue;
-            }
-            Ok(false) => {}
-            Err(e) => {
-                println!("Error checking votes: {}", e);
-                continue;
-            }
-        }
-
-
-        // Map candidates to local options 1 or 2
-        let mut candidate_map: HashMap<usize, i64> = HashMap::new();
-        for (i, (cand_id, cand_name, cand_party)) in candidates.iter().enumerate() {
-            let option_num = i + 1; // 1 or 2
-            println!("{}: {} (party: {})", option_num, cand_name, cand_party);
-            candidate_map.insert(option_num, *cand_id);
-        }
-
-
-
-
-        // Prompt until valid choice
-        let candidate_id = loop {
-            let input: usize = get_input("Enter the candidate number to vote for: ")
-                .parse().unwrap_or(0);
-            if let Some(&cid) = candidate_map.get(&input) {
-                break cid;
-            } else {
-                println!("❌ Invalid option, please choose from the numbers shown above.");
-            }
-        };
-
-        // Get candidate name for audit logging
-        let candidate_name = candidates.iter()
-            .find(|(id, _, _)| *id == candidate_id)
-            .map(|(_, name, _)| name.clone())
-            .unwrap_or_else(|| "Unknown".to_string());
-
-        match db.cast_vote(election_id, *pos_id, candidate_id, voter_id) {
-            Ok(_) => {
-                println!("✅ Vote cast successfully!");
-                // Log vote to audit trail
-                if let Ok(Some(voter_name)) = db.get_voter_name(voter_id) {
-                    audit::log_vote(db.connection(), &voter_name, &candidate_name);
-                }
-            },
-            Err(e) => println!("❌ Failed to cast vote: {}", e),
-        }
-    }
-
-
-    println!("\nThank you for voting!");
-}
-
-
-/// Verify votes cast by the voter
-fn handle_verify_ballot(db: &Database, voter_id: i64) {
-    println!("\nYour votes:");
-    match db.get_votes_by_voter(voter_id) {
-        Ok(votes) => {
-            if votes.is_empty() {
-                println!("No votes cast yet.");
-                return;
-            }
-            for (election, position, candidate, party) in votes {
-                println!("Election: {}, Position: {}, Voted for: {} (party: {})", election, position, candidate, party);
-            }
-        }
-        Err(e) => println!("Failed to retrieve votes: {}", e),
-    }
-}
-
-
-/// Validate DOB is in YYYY-MM-DD format and age >= 18
-fn validate_dob(dob_input: &str) -> Option<NaiveDate> {
-    match NaiveDate::parse_from_str(dob_input, "%Y-%m-%d") {
-        Ok(date) => {
-            let today = Utc::now().date_naive();
-            let age = today.year() - date.year()
-                - if (today.month(), today.day()) < (date.month(), date.day()) { 1 } else { 0 };
-            if age >= 18 {
-                Some(date)
-            } else {
-               println!("❌ Voter must be at least 18 years old.");
-                None
-            }
-        }
-        Err(_) => {
-            println!("❌ Invalid date format. Please use YYYY-MM-DD.");
-            None
-        }
-    }
-}
-
-
-/// Helper: Get user input
-fn get_input(prompt: &str) -> String {
-    print!("{}", prompt);
-    io::stdout().flush().unwrap();
-    let mut input = String::new();
-    io::stdin().read_line(&mut input).unwrap();
-    input.trim().to_string()
+use std::io::{self, Write};
+use crate::database::{self, Database};
+use crate::audit;
+use crate::auth;
+use rpassword::read_password;
+use crate::{merkle, signing};
+use chrono::{NaiveDate, Utc, Datelike};
+use std::collections::HashMap;
+
+
+/// Shortest PIN a voter can choose
+pub const MIN_PIN_LENGTH: usize = 4;
+
+/// Wrong PINs in a row before PIN entry is locked
+const MAX_PIN_ATTEMPTS: i64 = 5;
+
+/// How long PIN entry stays locked after too many wrong PINs
+const PIN_LOCKOUT_MINUTES: i64 = 15;
+
+
+/// Main Voter Menu
+pub fn handle_menu() -> bool {
+    let db = Database::new("e_voting.db").expect("Failed to initialize database");
+
+
+    // First, ask if existing or new voter
+    let voter_id = match voter_login_or_register(&db) {
+        Some(id) => id,
+        None => return true, // failed login/registration, return to main menu
+    };
+// Show voter menu
+    loop {
+        println!("\n--- VOTER MENU ---");
+        println!("1. View Open Elections");
+        println!("2. Cast Ballot");
+        println!("3. Verify My Ballot");
+        println!("4. Check My Ballot Was Counted");
+        println!("5. Logout");
+
+
+        let choice = get_input("Select an option: ");
+
+
+        match choice.trim() {
+            "1" => handle_view_open_elections(&db, voter_id),
+            "2" => handle_cast_ballot(&db, voter_id),
+            "3" => handle_verify_ballot(&db, voter_id),
+            "4" => handle_check_tracking_code(&db),
+            "5" => break,
+            _ => println!("Invalid option"),
+        }
+    }
+
+
+    true
+}
+
+
+/// Ask if voter is existing or new, handle login/registration
+fn voter_login_or_register(db: &Database) -> Option<i64> {
+    println!("\nAre you an existing voter or a new voter?");
+    println!("1. Existing Voter");
+    println!("2. New Voter");
+
+
+    let choice = get_input("Choice: ");
+  match choice.trim() {
+        "1" => {
+            let registration_number = database::normalize_registration_number(&get_input("Enter registration number: "));
+            let dob = get_input("Enter date of birth (YYYY-MM-DD): ");
+            match db.get_voter_id(&registration_number, &dob) {
+                Ok(Some(id)) => {
+                    let name = db.get_voter_name(id).ok().flatten().unwrap_or_default();
+                    println!("Welcome back, {}!", name);
+                    Some(id)
+                }
+                Ok(None) => {
+                    println!("Authentication failed. Please check your credentials.");
+                    None
+                }
+                Err(e) => {
+                    println!("Error checking voter: {}", e);
+                    None
+                }
+            }
+        }
+        "2" => {
+            let full_name = get_input("Enter full name: ");
+            let dob_input = get_input("Enter date of birth (YYYY-MM-DD): ");
+
+
+            // Validate DOB format and age
+          let dob = match validate_dob(&dob_input) {
+                Some(date) => date.format("%Y-%m-%d").to_string(),
+                None => return None, // invalid DOB
+            };
+            let district_id = get_district(db);
+            let pin_hash = match read_new_pin() {
+                Ok(pin_hash) => pin_hash,
+                Err(e) => {
+                    println!("❌ {}", e);
+                    return None;
+                }
+            };
+
+
+            match db.register_voter(&full_name, &dob, district_id, pin_hash.as_deref(), None) {
+                Ok(Some(registration_number)) => {
+                    println!("✅ Registration successful! Welcome, {}!", full_name);
+                    print_registration_receipt(&full_name, &registration_number);
+                    db.get_voter_id(&registration_number, &dob).ok().flatten()
+                }
+                Ok(None) => {
+                    // Registering again must not log anyone in: name and date of birth are easy to find out
+                    println!("You are already registered. Log in as an existing voter with your registration number.");
+                    None
+                }
+                Err(e) => {
+                    println!("❌ Failed to register voter: {}", e);
+                    None
+                }
+            }
+        }
+        _ => {
+            println!("Invalid option.");
+            None
+        }
+   }
+}
+
+
+/// Ask a registering voter for an optional PIN, twice. Returns its Argon2 hash, or None if they chose not to set one.
+pub fn read_new_pin() -> Result<Option<String>, String> {
+    println!("Choose a PIN to protect your ballot (at least {} characters), or leave blank for none: ", MIN_PIN_LENGTH);
+    let pin = read_password().unwrap_or_default();
+    if pin.is_empty() {
+        return Ok(None);
+    }
+    if pin.chars().count() < MIN_PIN_LENGTH {
+        return Err(format!("PIN must be at least {} characters.", MIN_PIN_LENGTH));
+    }
+    println!("Repeat PIN: ");
+    if read_password().unwrap_or_default() != pin {
+        return Err("PINs do not match.".to_string());
+    }
+    auth::hash_password(&pin).map(Some)
+}
+
+
+/// Voters who set a PIN must enter it before casting a ballot.
+/// After MAX_PIN_ATTEMPTS wrong PINs in a row, PIN entry is locked for PIN_LOCKOUT_MINUTES.
+fn check_pin(db: &Database, voter_id: i64) -> bool {
+    let (pin_hash, locked) = match db.get_voter_pin(voter_id) {
+        Ok(Some(pin)) => pin,
+        Ok(None) => return true, // no PIN set
+        Err(e) => {
+            println!("❌ Failed to check PIN: {}", e);
+            return false;
+        }
+    };
+    if locked {
+        println!("❌ Too many wrong PINs. Try again in {} minutes.", PIN_LOCKOUT_MINUTES);
+        return false;
+    }
+
+    println!("Enter your PIN: ");
+    let pin = read_password().unwrap_or_default();
+    if auth::verify_password(&pin, &pin_hash) {
+        if let Err(e) = db.reset_pin_failures(voter_id) {
+            println!("❌ Failed to check PIN: {}", e);
+            return false;
+        }
+        return true;
+    }
+    match db.record_pin_failure(voter_id, MAX_PIN_ATTEMPTS, PIN_LOCKOUT_MINUTES) {
+        Ok(true) => println!("❌ Wrong PIN. PIN entry is locked for {} minutes.", PIN_LOCKOUT_MINUTES),
+        Ok(false) => println!("❌ Wrong PIN."),
+        Err(e) => println!("❌ Failed to check PIN: {}", e),
+    }
+    false
+}
+
+
+/// Registration receipt: the number is needed with the date of birth at every login
+pub fn print_registration_receipt(full_name: &str, registration_number: &str) {
+    println!("\n--- Voter Registration Receipt ---");
+    println!("Name: {}", full_name);
+    println!("Registration number: {}", registration_number);
+    println!("Keep this number. You need it with your date of birth to log in and vote.");
+}
+
+
+/// List open elections the voter's district takes part in
+fn handle_view_open_elections(db: &Database, voter_id: i64) {
+    match db.list_open_elections(voter_id) {
+        Ok(elections) => {
+            println!("\nOpen Elections:");
+            if elections.is_empty() {
+                println!("No open elections at the moment.");
+            }
+            for (id, name) in elections {
+                println!("{}: {}", id, name);
+            }
+        }
+        Err(e) => println!("Failed to list elections: {}", e),
+    }
+}
+
+
+/// Cast ballot
+fn handle_cast_ballot(db: &Database, voter_id: i64) {
+    if !check_pin(db, voter_id) {
+        return;
+    }
+
+    // List open elections
+    let elections = match db.list_open_elections(voter_id) {
+        Ok(e) => e,
+        Err(e) => {
+            println!("Failed to get open elections: {}", e);
+            return;
+        }
+    };
+
+
+    if elections.is_empty() {
+ println!("No open elections available.");
+        return;
+    }
+
+
+    println!("\nOpen Elections:");
+    for (id, name) in &elections {
+        println!("{}: {}", id, name);
+    }
+
+
+    let election_id: i64 = get_input("Enter the ID of the election you want to vote in: ")
+        .parse().unwrap_or(-1);
+
+
+    if !elections.iter().any(|(id, _)| *id == election_id) {
+        println!("❌ You are not eligible to vote in this election.");
+        return;
+    }
+
+    // Only positions of the voter's district, and those open to every district, are on the ballot
+    let positions = match db.list_eligible_positions(election_id, voter_id) {
+        Ok(p) => p,
+        Err(e) => {
+            println!("Failed to list positions: {}", e);
+            return;
+        }
+    };
+    let ranked = db.get_election_type(election_id).map(|t| t == "ranked").unwrap_or(false);
+    if ranked {
+        println!("\nThis is a ranked-choice election: rank the candidates in order of preference.");
+    }
+
+
+    for (pos_id, pos_name) in &positions {
+        println!("\nPosition: {} - {}", pos_id, pos_name);
+
+
+        let candidates = match db.list_candidates(*pos_id) {
+            Ok(c) => c,
+            Err(e) => {
+                println!("Failed to listcandidates: {}", e);
+                continue;
+            }
+        };
+        if candidates.is_empty() {
+            println!("No candidates are standing for this position.");
+            continue;
+        }
+
+
+        // Check if voter already voted for this position
+        match db.has_voted(election_id, *pos_id, voter_id) {
+            Ok(true) => {
+                println!("You have already voted for this position.");
+                continue;
+            }
+            Ok(false) => {}
+            Err(e) => {
+                println!("Error checking votes: {}", e);
+                continue;
+            }
+        }
+
+
+        // Map candidates to local options 1..n
+        let mut candidate_map: HashMap<usize, i64> = HashMap::new();
+        for (i, (cand_id, cand_name, cand_party)) in candidates.iter().enumerate() {
+            let option_num = i + 1;
+            println!("{}: {} (party: {})", option_num, cand_name, cand_party);
+            candidate_map.insert(option_num, *cand_id);
+        }
+
+
+
+
+        // Prompt until valid choice
+        let candidate_ids = if ranked {
+            read_ranking(&candidates, &candidate_map)
+        } else {
+            loop {
+                let input: usize = get_input("Enter the candidate number to vote for: ")
+                    .parse().unwrap_or(0);
+                if let Some(&cid) = candidate_map.get(&input) {
+                    break vec![cid];
+                } else {
+                    println!("❌ Invalid option, please choose from the numbers shown above.");
+                }
+            }
+        };
+
+        match db.cast_vote(election_id, *pos_id, &candidate_ids, voter_id) {
+            Ok(tracking_code) => {
+                println!("✅ Vote cast successfully!");
+                println!("Your tracking code for {}: {}", pos_name, tracking_code);
+                println!("Keep it to check later that your ballot was counted. It cannot be shown again.");
+                // Log vote to audit trail, without the selection
+                if let Ok(Some(voter_name)) = db.get_voter_name(voter_id) {
+                    audit::log_vote(db.connection(), &voter_name, pos_name);
+                }
+            },
+            Err(e) => println!("❌ Failed to cast vote: {}", e),
+        }
+    }
+
+
+    println!("\nThank you for voting!");
+}
+
+
+/// Ask for the candidate numbers in order of preference until the voter enters a valid ranking and confirms it.
+/// Not every candidate has to be ranked; a candidate can appear only once.
+fn read_ranking(candidates: &[(i64, String, String)], candidate_map: &HashMap<usize, i64>) -> Vec<i64> {
+    loop {
+        let input = get_input("Enter candidate numbers in order of preference, separated by commas (e.g. 2,1): ");
+        let mut options: Vec<usize> = Vec::new();
+        let mut valid = true;
+        for part in input.split(',') {
+            match part.trim().parse::<usize>() {
+                Ok(option) if candidate_map.contains_key(&option) && !options.contains(&option) => options.push(option),
+                _ => valid = false,
+            }
+        }
+        if !valid || options.is_empty() {
+            println!("❌ Invalid ranking, please list each candidate number shown above at most once.");
+            continue;
+        }
+
+        println!("Your ranking:");
+        for (rank, option) in options.iter().enumerate() {
+            println!("  {}. {}", rank + 1, candidates[option - 1].1);
+        }
+        if get_input("Cast this ranking? (y/n): ").eq_ignore_ascii_case("y") {
+            return options.iter().map(|option| candidate_map[option]).collect();
+        }
+    }
+}
+
+
+/// Show which positions the voter has cast a ballot for.
+/// Ballots are stored apart from voters, so the selections cannot be shown here.
+fn handle_verify_ballot(db: &Database, voter_id: i64) {
+    println!("\nYour ballots:");
+    match db.get_voted_positions(voter_id) {
+        Ok(votes) => {
+            if votes.is_empty() {
+                println!("No votes cast yet.");
+                return;
+            }
+            for (election, position) in votes {
+                println!("Election: {}, Position: {} - ballot recorded", election, position);
+            }
+            println!("Your selections are not linked to you and cannot be shown.");
+        }
+        Err(e) => println!("Failed to retrieve votes: {}", e),
+    }
+}
+
+
+/// Look up a ballot by its tracking code and say whether it is counted, without showing the selection
+fn handle_check_tracking_code(db: &Database) {
+    let code = database::normalize_tracking_code(&get_input("Enter your tracking code: "));
+    match db.find_ballot_by_tracking_code(&code) {
+        Ok(Some((election, position, status))) => {
+            if status == "closed" {
+                println!("✅ Ballot {} for {} in {} was counted in the tally.", code, position, election);
+                println!("For a proof against the published ballot root, run: e_voting_system ballot-proof {}", code);
+            } else {
+                println!("✅ Ballot {} for {} in {} is recorded and will be counted when the election closes.", code, position, election);
+            }
+        }
+        Ok(None) => println!("❌ No ballot has this tracking code. Please check the code and try again."),
+        Err(e) => println!("Failed to look up the tracking code: {}", e),
+    }
+}
+
+
+/// Print the Merkle inclusion proof of the ballot with a tracking code and check it against the
+/// election's published root and the authority's signature on that root. Returns true if both hold.
+pub fn show_inclusion_proof(db: &Database, tracking_code: &str) -> bool {
+    let code = database::normalize_tracking_code(tracking_code);
+    let (election_id, ballot_hash) = match db.find_ballot_hash_by_tracking_code(&code) {
+        Ok(Some(found)) => found,
+        Ok(None) => {
+            println!("❌ No ballot has tracking code {}.", code);
+            return false;
+        }
+        Err(e) => {
+            println!("❌ Failed to look up the tracking code: {}", e);
+            return false;
+        }
+    };
+    let (root, count, signature) = match db.get_ballot_root(election_id) {
+        Ok(Some(published)) => published,
+        Ok(None) => {
+            println!("❌ Election {} has no published ballot root yet; it is published when the election closes.", election_id);
+            return false;
+        }
+        Err(e) => {
+            println!("❌ Failed to read the published ballot root: {}", e);
+            return false;
+        }
+    };
+    let hashes = match db.ballot_hashes(election_id) {
+        Ok(hashes) => hashes,
+        Err(e) => {
+            println!("❌ Failed to read the ballots of election {}: {}", election_id, e);
+            return false;
+        }
+    };
+    let leaf = merkle::decode_hash(&ballot_hash).unwrap_or_default();
+    let index = match hashes.iter().position(|h| *h == leaf) {
+        Some(index) => index,
+        None => {
+            println!("❌ Ballot {} is not among the ballots of election {}.", code, election_id);
+            return false;
+        }
+    };
+    let steps = merkle::proof(&hashes, index);
+
+    println!("\nElection: {}", election_id);
+    println!("Ballot hash: {}", ballot_hash);
+    println!("Leaf {} of {}", index + 1, hashes.len());
+    for step in &steps {
+        println!("  {} {}", if step.sibling_is_left { "left: " } else { "right:" }, hex::encode(step.sibling));
+    }
+    println!("Published root: {} ({} ballots)", root, count);
+
+    let included = merkle::decode_hash(&root).is_some_and(|root| merkle::verify_proof(&leaf, &steps, &root));
+    let signed = signing::read_public_key(signing::PUBLIC_KEY_PATH)
+        .is_ok_and(|key| signing::verify(&key, merkle::root_statement(election_id, count, &root).as_bytes(), &signature));
+    if included {
+        println!("✅ The proof leads to the published root: your ballot is included.");
+    } else {
+        println!("❌ The proof does not lead to the published root.");
+    }
+    if signed {
+        println!("✅ The published root is signed by the election authority.");
+    } else {
+        println!("❌ The published root is not signed by the election authority.");
+    }
+    included && signed
+}
+
+
+/// Ask a new voter for their district by name; blank means none.
+/// A voter without a district can only vote in elections and on positions open to every district.
+fn get_district(db: &Database) -> Option<i64> {
+    let districts = db.list_districts().unwrap_or_default();
+    if districts.is_empty() {
+        return None;
+    }
+    let names: Vec<&str> = districts.iter().map(|(_, name)| name.as_str()).collect();
+    println!("Districts: {}", names.join(", "));
+    loop {
+        let name = get_input("Enter your district (leave blank if none): ");
+        if name.is_empty() {
+            return None;
+        }
+        match districts.iter().find(|(_, n)| *n == name) {
+            Some((id, _)) => return Some(*id),
+            None => println!("❌ Unknown district '{}'.", name),
+        }
+    }
+}
+
+
+/// Validate DOB is in YYYY-MM-DD format and age >= 18
+fn validate_dob(dob_input: &str) -> Option<NaiveDate> {
+    match NaiveDate::parse_from_str(dob_input, "%Y-%m-%d") {
+        Ok(date) => {
+            let today = Utc::now().date_naive();
+            let age = today.year() - date.year()
+                - if (today.month(), today.day()) < (date.month(), date.day()) { 1 } else { 0 };
+            if age >= 18 {
+                Some(date)
+            } else {
+               println!("❌ Voter must be at least 18 years old.");
+                None
+            }
+        }
+        Err(_) => {
+            println!("❌ Invalid date format. Please use YYYY-MM-DD.");
+            None
+        }
+    }
+}
+
+
+/// Helper: Get user input
+fn get_input(prompt: &str) -> String {
+    print!("{}", prompt);
+    io::stdout().flush().unwrap();
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).unwrap();
+    input.trim().to_string()
 }
\ No newline at end of file
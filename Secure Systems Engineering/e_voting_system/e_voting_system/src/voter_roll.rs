@@ -0,0 +1,240 @@
+use crate::database::{self, Database};
+use chrono::{Datelike, NaiveDate, Utc};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+
+/// Outcome of a voter roll import. Rows are numbered as in a spreadsheet, the header being row 1.
+pub struct ImportSummary {
+    pub rows: usize,
+    /// Name, county id and issued registration number of each imported voter
+    pub imported: Vec<(String, Option<String>, String)>,
+    pub duplicates: Vec<(u64, String)>,
+    pub invalid: Vec<(u64, String)>,
+}
+
+
+/// One voter read from the roll, after validation
+struct RollEntry {
+    name: String,
+    dob: String,
+    district_id: Option<i64>,
+    external_id: Option<String>,
+}
+
+
+/// Column of a CSV header, accepting a few common spellings
+fn column(headers: &csv::StringRecord, names: &[&str]) -> Result<usize, String> {
+    headers
+        .iter()
+        .position(|h| names.iter().any(|n| h.trim().eq_ignore_ascii_case(n)))
+        .ok_or_else(|| format!("The header has no '{}' column.", names[0]))
+}
+
+
+/// Import voters from a CSV file with a header naming the columns name, dob, district and id.
+/// Each row is validated on its own: bad rows and duplicates (within the file or already registered)
+/// are skipped and reported, the other rows are registered. Imported voters get no PIN.
+pub fn import_csv(db: &Database, path: &str) -> Result<ImportSummary, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_path(path)
+        .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let headers = reader.headers().map_err(|e| format!("Failed to read the header of {}: {}", path, e))?.clone();
+    let name_col = column(&headers, &["name", "full_name"])?;
+    let dob_col = column(&headers, &["dob", "date_of_birth"])?;
+    let district_col = column(&headers, &["district"])?;
+    let id_col = column(&headers, &["id", "voter_id"])?;
+
+    let mut summary = ImportSummary { rows: 0, imported: Vec::new(), duplicates: Vec::new(), invalid: Vec::new() };
+    let mut seen_ids: HashSet<String> = HashSet::new();
+    let mut seen_people: HashSet<(String, String)> = HashSet::new();
+
+    for (i, record) in reader.records().enumerate() {
+        let row = i as u64 + 2;
+        summary.rows += 1;
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                summary.invalid.push((row, format!("unreadable row: {}", e)));
+                continue;
+            }
+        };
+        let field = |col: usize| record.get(col).unwrap_or("").to_string();
+        let entry = match validate_row(db, field(name_col), field(dob_col), field(district_col), field(id_col)) {
+            Ok(entry) => entry,
+            Err(reason) => {
+                summary.invalid.push((row, reason));
+                continue;
+            }
+        };
+
+        // Duplicates within the file
+        if let Some(id) = &entry.external_id {
+            if !seen_ids.insert(id.clone()) {
+                summary.duplicates.push((row, format!("id {} appears earlier in the file", id)));
+                continue;
+            }
+        }
+        if !seen_people.insert((database::normalize_name(&entry.name), entry.dob.clone())) {
+            summary.duplicates.push((row, format!("{} ({}) appears earlier in the file", entry.name, entry.dob)));
+            continue;
+        }
+
+        // Duplicates of voters already registered
+        if let Some(id) = &entry.external_id {
+            match db.external_id_exists(id) {
+                Ok(false) => {}
+                Ok(true) => {
+                    summary.duplicates.push((row, format!("a voter with id {} is already registered", id)));
+                    continue;
+                }
+                Err(e) => return Err(format!("Failed to check row {}: {}", row, e)),
+            }
+        }
+        match db.register_voter(&entry.name, &entry.dob, entry.district_id, None, entry.external_id.as_deref()) {
+            Ok(Some(registration_number)) => summary.imported.push((entry.name, entry.external_id, registration_number)),
+            Ok(None) => summary.duplicates.push((row, format!("{} ({}) is already registered", entry.name, entry.dob))),
+            Err(e) => return Err(format!("Failed to register row {}: {}", row, e)),
+        }
+    }
+    Ok(summary)
+}
+
+
+/// Check one row: a name, a YYYY-MM-DD date of birth of someone at least 18, a known district or none, and an optional id
+fn validate_row(db: &Database, name: String, dob: String, district: String, id: String) -> Result<RollEntry, String> {
+    if name.is_empty() {
+        return Err("name is empty".to_string());
+    }
+    let date = NaiveDate::parse_from_str(&dob, "%Y-%m-%d").map_err(|_| format!("date of birth '{}' is not YYYY-MM-DD", dob))?;
+    let today = Utc::now().date_naive();
+    let age = today.year() - date.year()
+        - if (today.month(), today.day()) < (date.month(), date.day()) { 1 } else { 0 };
+    if age < 18 {
+        return Err(format!("voter born {} is under 18", dob));
+    }
+    let district_id = if district.is_empty() {
+        None
+    } else {
+        match db.get_district_id(&district) {
+            Ok(Some(id)) => Some(id),
+            Ok(None) => return Err(format!("unknown district '{}'", district)),
+            Err(e) => return Err(format!("failed to look up district '{}': {}", district, e)),
+        }
+    };
+    Ok(RollEntry {
+        name,
+        dob: date.format("%Y-%m-%d").to_string(),
+        district_id,
+        external_id: if id.is_empty() { None } else { Some(id) },
+    })
+}
+
+
+/// Write the registration numbers issued by an import next to the imported file, for mailing to the voters.
+/// A registration number and date of birth are a voter's login, so the file is readable by the owner only.
+/// Returns the path written.
+pub fn write_registration_numbers(path: &str, summary: &ImportSummary) -> Result<String, String> {
+    let source = Path::new(path);
+    let stem = source.file_stem().and_then(|s| s.to_str()).unwrap_or("voters");
+    let out = source.with_file_name(format!("{}_registration_numbers.csv", stem));
+    let out_name = out.display().to_string();
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let file = options.open(&out).map_err(|e| format!("Failed to create {}: {}", out_name, e))?;
+    // the mode only applies to a new file; one left by an earlier import is tightened too
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600)).map_err(|e| format!("Failed to restrict {}: {}", out_name, e))?;
+    }
+    let mut writer = csv::Writer::from_writer(file);
+    let write_error = |e: csv::Error| format!("Failed to write {}: {}", out_name, e);
+    writer.write_record(["name", "id", "registration_number"]).map_err(write_error)?;
+    for (name, id, registration_number) in &summary.imported {
+        writer.write_record([name.as_str(), id.as_deref().unwrap_or(""), registration_number.as_str()]).map_err(write_error)?;
+    }
+    writer.flush().map_err(|e| format!("Failed to write {}: {}", out_name, e))?;
+    Ok(out_name)
+}
+
+
+/// Write the voter roll to CSV with the columns of an import (name, dob, district, id), for reconciliation with county records.
+/// With `district_id` only that district's voters are written; with `redact_dob` only the year of birth is kept.
+/// Returns the number of voters written.
+pub fn export_csv(db: &Database, path: &str, district_id: Option<i64>, redact_dob: bool) -> Result<usize, String> {
+    let voters = db.list_voters(district_id).map_err(|e| format!("Failed to list voters: {}", e))?;
+    let mut writer = csv::Writer::from_path(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+    let write_error = |e: csv::Error| format!("Failed to write {}: {}", path, e);
+    writer.write_record(["name", "dob", "district", "id"]).map_err(write_error)?;
+    for voter in &voters {
+        let dob = if redact_dob {
+            format!("{}-XX-XX", voter.date_of_birth.get(..4).unwrap_or("XXXX"))
+        } else {
+            voter.date_of_birth.clone()
+        };
+        let district = voter.district.as_deref().unwrap_or("");
+        writer
+            .write_record([voter.full_name.as_str(), dob.as_str(), district, voter.external_id.as_deref().unwrap_or("")])
+            .map_err(write_error)?;
+    }
+    writer.flush().map_err(|e| format!("Failed to write {}: {}", path, e))?;
+    Ok(voters.len())
+}
+
+
+/// Ask where to write the voter roll, for which district and whether to redact dates of birth, then export it
+pub fn handle_export(db: &Database) {
+    let districts = match db.list_districts() {
+        Ok(districts) => districts,
+        Err(e) => {
+            println!("❌ Failed to list districts: {}", e);
+            return;
+        }
+    };
+    let mut district_id = None;
+    if !districts.is_empty() {
+        let names: Vec<&str> = districts.iter().map(|(_, name)| name.as_str()).collect();
+        println!("Districts: {}", names.join(", "));
+        loop {
+            let name = get_input("District to export (leave blank for all voters): ");
+            if name.is_empty() {
+                break;
+            }
+            match districts.iter().find(|(_, n)| *n == name) {
+                Some((id, _)) => {
+                    district_id = Some(*id);
+                    break;
+                }
+                None => println!("❌ Unknown district '{}'.", name),
+            }
+        }
+    }
+    let redact_dob = !get_input("Include full dates of birth? (y/n): ").eq_ignore_ascii_case("y");
+    let path = get_input("Path of the CSV file to write: ");
+
+    match export_csv(db, &path, district_id, redact_dob) {
+        Ok(count) => println!("✅ {} voters written to {}.", count, path),
+        Err(e) => println!("❌ {}", e),
+    }
+}
+
+
+/// Helper function to get trimmed input from user
+fn get_input(prompt: &str) -> String {
+    print!("{}", prompt);
+    io::stdout().flush().unwrap();
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).unwrap();
+    input.trim().to_string()
+}